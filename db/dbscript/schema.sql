@@ -16,10 +16,18 @@ SET xmloption = content;
 SET client_min_messages = warning;
 SET row_security = off;
 
+CREATE EXTENSION IF NOT EXISTS pg_trgm WITH SCHEMA public;
+
 SET default_tablespace = '';
 
 SET default_table_access_method = heap;
 
+--
+-- Name: changelog_source; Type: TYPE;
+--
+
+CREATE TYPE changelog_source AS ENUM ('manual', 'steam_auto', 'import');
+
 --
 -- Name: evidence_requirements; Type: TABLE;
 --
@@ -62,7 +70,8 @@ CREATE TABLE categories (
     name character varying(100) DEFAULT ''::character varying NOT NULL,
     map_id character varying(6) DEFAULT ''::character varying NOT NULL,
     rules_id integer,
-    updated timestamp(6) without time zone
+    updated timestamp(6) without time zone,
+    min_score integer
 );
 
 -- Trigger to update `updated` when a row is updated.
@@ -156,7 +165,8 @@ CREATE TABLE changelog (
     score_delta integer,
     verified boolean,
     admin_note character varying(200),
-    updated timestamp(6) without time zone
+    updated timestamp(6) without time zone,
+    source changelog_source DEFAULT 'steam_auto'::changelog_source NOT NULL
 );
 
 -- Trigger to update `updated` when a row is updated.
@@ -266,7 +276,13 @@ CREATE TABLE demos (
     parsed_successfully boolean DEFAULT false NOT NULL,
     sar_version character varying(50),
     cl_id bigint NOT NULL,
-    updated timestamp(6) without time zone
+    updated timestamp(6) without time zone,
+    sha1 character varying(40),
+    bucket_id character varying(50),
+    map_name character varying(64),
+    tick_count integer,
+    playback_time real,
+    protocol integer
 );
 
 
@@ -476,7 +492,9 @@ CREATE TABLE users (
     donation_amount character varying(11),
     discord_id character varying(40),
     auth_hash character varying(64),
-    country_id integer
+    country_id integer,
+    needs_review boolean DEFAULT false NOT NULL,
+    banned_at timestamp without time zone
 );
 
 --
@@ -763,6 +781,12 @@ ALTER TABLE ONLY mtrigger_entries
 ALTER TABLE ONLY users
     ADD CONSTRAINT pk_users_profile_number PRIMARY KEY (profile_number);
 
+--
+-- Name: users_name_trgm_idx; Type: INDEX;
+--
+
+CREATE INDEX users_name_trgm_idx ON users USING gin (lower((COALESCE(board_name, steam_name))::text) gin_trgm_ops);
+
 
 --
 -- Name: demos unq_demos_id; Type: CONSTRAINT;
@@ -0,0 +1,6 @@
+-- Trigram index backing `Users::check_board_name`, which matches `%name%` against
+-- the user's board name, falling back to their steam name.
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+
+CREATE INDEX IF NOT EXISTS users_name_trgm_idx
+    ON users USING gin (LOWER(COALESCE(board_name, steam_name)) gin_trgm_ops);
@@ -0,0 +1,3 @@
+-- SHA1 of the demo file contents, recorded on upload and used to verify the file
+-- served back from BackBlaze. NULL for demos uploaded before this was tracked.
+ALTER TABLE demos ADD COLUMN IF NOT EXISTS sha1 character varying(40);
@@ -0,0 +1,3 @@
+-- Users whose name was caught by the name filter, kept for an admin to review
+-- rather than rejecting the user outright.
+ALTER TABLE users ADD COLUMN IF NOT EXISTS needs_review boolean DEFAULT false NOT NULL;
@@ -0,0 +1,8 @@
+-- BackBlaze bucket each demo was uploaded to, so demos can still be downloaded and
+-- deleted after the active bucket (`BACKBLAZE.BUCKET`) changes.
+-- Existing demos are given the bucket of the newest demo that has one recorded. Demos that
+-- are still NULL (no demo had a bucket yet) are read from the active bucket by the server.
+ALTER TABLE demos ADD COLUMN IF NOT EXISTS bucket_id character varying(50);
+UPDATE demos SET bucket_id = (
+    SELECT bucket_id FROM demos WHERE bucket_id IS NOT NULL ORDER BY id DESC LIMIT 1
+) WHERE bucket_id IS NULL;
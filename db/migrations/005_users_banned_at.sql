@@ -0,0 +1,3 @@
+-- When a user was last banned, used to list the most recently banned users first.
+-- Users banned before this column existed are left without a date and are listed last.
+ALTER TABLE users ADD COLUMN IF NOT EXISTS banned_at timestamp without time zone;
@@ -0,0 +1,6 @@
+-- Fields parsed from each demo's header, so clients can show demo info without downloading the file.
+-- Existing demos are left without them, they are parsed and stored the first time their metadata is requested.
+ALTER TABLE demos ADD COLUMN IF NOT EXISTS map_name character varying(64);
+ALTER TABLE demos ADD COLUMN IF NOT EXISTS tick_count integer;
+ALTER TABLE demos ADD COLUMN IF NOT EXISTS playback_time real;
+ALTER TABLE demos ADD COLUMN IF NOT EXISTS protocol integer;
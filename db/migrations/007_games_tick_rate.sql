@@ -0,0 +1,4 @@
+-- The number of score units in a second for each game's boards, used to format scores as times.
+-- Scores from the Steam leaderboards are in centiseconds, so existing games default to 100. Mods that count scores in ticks
+-- should be updated to their tick rate, e.g. `UPDATE games SET tick_rate = 60 WHERE id = <game_id>;`.
+ALTER TABLE games ADD COLUMN IF NOT EXISTS tick_rate integer NOT NULL DEFAULT 100;
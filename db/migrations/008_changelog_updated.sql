@@ -0,0 +1,15 @@
+-- The time each changelog entry was last changed, used as its version so an update made from an out of date copy of the
+-- entry is rejected (409) instead of overwriting the newer changes. Entries that have never been updated are left NULL.
+ALTER TABLE changelog ADD COLUMN IF NOT EXISTS updated timestamp(6) without time zone;
+
+CREATE OR REPLACE FUNCTION update_updated_column() RETURNS trigger
+    LANGUAGE plpgsql
+    AS $$
+BEGIN
+   NEW.updated = now();
+   RETURN NEW;
+END;
+$$;
+
+DROP TRIGGER IF EXISTS update_changelog_updated ON changelog;
+CREATE TRIGGER update_changelog_updated BEFORE UPDATE ON changelog FOR EACH ROW EXECUTE FUNCTION update_updated_column();
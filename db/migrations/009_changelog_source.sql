@@ -0,0 +1,13 @@
+-- Where each changelog entry came from: submitted by a player (`manual`), pulled from the Steam leaderboards
+-- (`steam_auto`) or added through the admin import (`import`). Existing entries are set from `submission`, which is
+-- non-zero for entries that were submitted to the boards.
+DO $$
+BEGIN
+    CREATE TYPE changelog_source AS ENUM ('manual', 'steam_auto', 'import');
+EXCEPTION
+    WHEN duplicate_object THEN NULL;
+END
+$$;
+
+ALTER TABLE changelog ADD COLUMN IF NOT EXISTS source changelog_source NOT NULL DEFAULT 'steam_auto';
+UPDATE changelog SET source = 'manual' WHERE submission <> 0 AND source = 'steam_auto';
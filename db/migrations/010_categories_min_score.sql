@@ -0,0 +1,4 @@
+-- The lowest score that is possible on each category, submissions below it are banned and sent to the verification
+-- queue for review. Categories without a minimum are left NULL, e.g.
+-- `UPDATE categories SET min_score = 1234 WHERE id = <cat_id>;`.
+ALTER TABLE categories ADD COLUMN IF NOT EXISTS min_score integer;
@@ -1,9 +1,30 @@
 use crate::{
-    models::{admin::*, changelog::ChangelogQueryParams, users::Users},
-    tools::error::Result,
+    api::v1::handlers::demos::spawn_demo_reparse,
+    models::{
+        admin::*,
+        changelog::{BannedTimeDetails, Changelog, ChangelogInsert, ChangelogPage, ChangelogQueryParams, ChangelogSource},
+        chapters::GameID,
+        demos::{Demos, ReparseParams, ReparseProgress},
+        users::{FlaggedUser, Users, UsersDisplay},
+    },
+    tools::{
+        auth::AuthUser,
+        cache::{CacheState, COOP_PREVIEWS, SP_PREVIEWS},
+        error::{ErrorType, Result, ServerError},
+        maintenance::Maintenance,
+        storage::DemoStorage,
+    },
 };
-use actix_web::{get, web, Responder};
+use actix_web::{get, post, put, web, Responder};
 use sqlx::PgPool;
+use std::time::Instant;
+
+/// Number of demos a re-parse job processes when no `limit` is given.
+const DEFAULT_REPARSE_LIMIT: i64 = 500;
+/// The most demos a single re-parse job can process.
+const MAX_REPARSE_LIMIT: i64 = 10000;
+/// The most rows a single changelog import can insert, each row takes 17 of the 65535 parameters Postgres allows.
+const MAX_IMPORT_ROWS: usize = 1000;
 
 /// **GET** method for admin-relevant entiries. Utilizes [ChangelogQueryParams] as an optional addition to the query
 ///
@@ -69,6 +90,7 @@ use sqlx::PgPool;
 ///         "avatar": "https://steamcdn-a.akamaihd.net/steamcommunity/public/images/avatars/79/79d3fe5839617eb83a9661071ed021dd56ac8a5b_full.jpg"
 ///     },...]
 /// ```
+#[utoipa::path(tag = "admin", params(ChangelogQueryParams), responses((status = 200, body = Vec<ChangelogPage>)))]
 #[get("/admin/changelog")]
 pub async fn admin_changelog(
     pool: web::Data<PgPool>,
@@ -101,6 +123,7 @@ pub async fn admin_changelog(
 ///         "non_verified_runs": 1
 ///     },...]
 /// ```
+#[utoipa::path(tag = "admin", responses((status = 200, body = Vec<BannedTimeDetails>)))]
 #[get("/admin/banned_stats")]
 pub async fn admin_banned_stats(pool: web::Data<PgPool>) -> Result<impl Responder> {
     Ok(web::Json(
@@ -108,6 +131,36 @@ pub async fn admin_banned_stats(pool: web::Data<PgPool>) -> Result<impl Responde
     ))
 }
 
+/// **GET** method for users whose names were flagged by the name filter and need to be reviewed by an admin.
+///
+/// Users are only flagged when `MODERATION.FILTER_NAMES` is enabled. Clear the flag by updating the user with
+/// `needs_review` set to `false`. Requires an admin's auth token, see [AuthUser].
+///
+/// ## Example endpoints:
+///  - **Default**
+///     - `/api/v1/users/flagged`
+///
+/// Makes a call to the underlying [Users::get_flagged]
+///
+/// ## Example JSON output
+/// ```json
+/// [
+///     {
+///         "profile_number": "76561198040982247",
+///         "board_name": null,
+///         "steam_name": "BigDaniel",
+///         "banned": false,
+///         "avatar": "https://steamcdn-a.akamaihd.net/steamcommunity/public/images/avatars/92/921d9d7402a6e766759bcc0b2ac7b91f1dcf0ad2_full.jpg",
+///         "needs_review": true
+///     },...]
+/// ```
+#[utoipa::path(tag = "admin", responses((status = 200, body = Vec<FlaggedUser>)))]
+#[get("/users/flagged")]
+pub async fn users_flagged(auth: AuthUser, pool: web::Data<PgPool>) -> Result<impl Responder> {
+    auth.require_admin()?;
+    Ok(web::Json(Users::get_flagged(pool.get_ref()).await?))
+}
+
 /// **GET** method that returns lists of admins
 ///
 /// ## Parameters:
@@ -146,6 +199,7 @@ pub async fn admin_banned_stats(pool: web::Data<PgPool>) -> Result<impl Responde
 ///         "avatar": "https://steamcdn-a.akamaihd.net/steamcommunity/public/images/avatars/9f/9f6de098624a8f81ba6d2318e1923bdda1c710d6_full.jpg"
 ///     },...]
 /// ```
+#[utoipa::path(tag = "admin", params(AdminLevel), responses((status = 200, body = Vec<UsersDisplay>)))]
 #[get("/admins")]
 pub async fn admins_list(
     pool: web::Data<PgPool>,
@@ -159,3 +213,427 @@ pub async fn admins_list(
         .await?,
     ))
 }
+
+/// **GET** method for the verification queue, submissions that have not been verified or banned yet, oldest first.
+///
+/// Submissions below their category's `min_score` are banned when they are submitted, they are in the queue with an
+/// `admin_note` of `auto-flagged: below min_score` until they are approved or rejected.
+///
+/// Requires an admin's auth token, see [AuthUser].
+///
+/// ## Parameters:
+/// - `game_id`
+///     - **Optional** - `i32` : ID of the game. Defaults to all.
+///
+/// ## Example endpoints:
+///  - **Default**
+///     - `/api/v1/admin/verify/queue`
+///  - **With parameters**
+///     - `/api/v1/admin/verify/queue?game_id=1`
+///
+/// Makes a call to the underlying [Changelog::get_unverified]
+///
+/// ## Example JSON output
+/// ```json
+/// [
+///     {
+///         "id": 157811,
+///         "timestamp": "2021-08-26T14:02:45",
+///         "profile_number": "76561198040982247",
+///         "score": 1763,
+///         "map_id": "47763",
+///         "demo_id": 24530,
+///         "banned": false,
+///         "youtube_id": null,
+///         "previous_id": 141996,
+///         "coop_id": null,
+///         "post_rank": 3,
+///         "pre_rank": 5,
+///         "submission": 1,
+///         "note": null,
+///         "category_id": 19,
+///         "score_delta": -12,
+///         "verified": false,
+///         "admin_note": null,
+///         "map_name": "Laser vs Turret",
+///         "user_name": "Daniel",
+///         "avatar": "https://steamcdn-a.akamaihd.net/steamcommunity/public/images/avatars/92/921d9d7402a6e766759bcc0b2ac7b91f1dcf0ad2_full.jpg",
+///         "blue_name": null,
+///         "orange_name": null,
+///         "blue_avatar": null,
+///         "orange_avatar": null
+///     },...]
+/// ```
+#[utoipa::path(tag = "admin", params(GameID), responses((status = 200, body = Vec<ChangelogPage>)))]
+#[get("/admin/verify/queue")]
+pub async fn admin_verify_queue(
+    pool: web::Data<PgPool>,
+    params: web::Query<GameID>,
+    auth: AuthUser,
+) -> Result<impl Responder> {
+    auth.require_admin()?;
+    Ok(web::Json(
+        Changelog::get_unverified(pool.get_ref(), params.game_id).await?,
+    ))
+}
+
+/// **POST** method to approve or reject a submission in the verification queue.
+///
+/// Approving verifies the score, rejecting bans it. The cached previews and aggregate points are invalidated either way.
+/// Requires an admin's auth token, see [AuthUser].
+///
+/// ## Parameters (expects valid JSON Object):
+/// - `approved`
+///     - **Required** - `bool` : `true` to verify the score, `false` to ban it.
+/// - `admin_note`
+///     - **Optional** - `String` : Reason for the decision, keeps the current note if left out.
+///
+/// ## Example endpoints:
+/// - `/api/v1/admin/verify/157811`
+///
+/// Makes a call to the underlying [Changelog::set_verification]
+///
+/// ## Example JSON String
+/// ```json
+/// {
+///     "approved": false,
+///     "admin_note": "Demo does not match the submitted time"
+/// }
+/// ```
+///
+/// ## Example JSON output
+///
+/// The updated [Changelog].
+#[utoipa::path(tag = "admin", responses((status = 200, body = Changelog)))]
+#[post("/admin/verify/{cl_id}")]
+pub async fn admin_verify(
+    cl_id: web::Path<i64>,
+    params: web::Json<VerificationDecision>,
+    pool: web::Data<PgPool>,
+    cache: web::Data<CacheState>,
+    auth: AuthUser,
+) -> Result<impl Responder> {
+    auth.require_admin()?;
+    let cl_id = cl_id.into_inner();
+    let decision = params.into_inner();
+    match Changelog::set_verification(pool.get_ref(), cl_id, decision.approved, decision.admin_note).await? {
+        Some(changelog_entry) => {
+            cache.invalidate_map_preview(pool.get_ref(), &changelog_entry.map_id).await;
+            cache.invalidate_aggregate_points().await;
+            Ok(web::Json(changelog_entry))
+        }
+        None => Err(ServerError::new(
+            ErrorType::NotFound,
+            format!("No changelog entry with id {}", cl_id),
+        )),
+    }
+}
+
+/// **POST** method to rebuild a player's PB chain on a map, after manual edits or deletes have broken it.
+///
+/// Rewrites `previous_id`, `score_delta`, `pre_rank` and `post_rank` for the player's unbanned times, in the order they
+/// were set. Requires an admin's auth token, see [AuthUser].
+///
+/// ## Parameters (expects valid JSON Object):
+/// - `profile_number`
+///     - **Required** - `String` : ID of the player.
+/// - `map_id`
+///     - **Required** - `String` : Steam ID for the map.
+/// - `cat_id`
+///     - **Optional** - `i32` : ID of the category, defaults to the map's default category.
+/// - `game_id`
+///     - **Optional** - `i32` : ID of the game, defaults to the base game.
+///
+/// ## Example endpoints:
+/// - `/api/v1/admin/repair/chain`
+///
+/// Makes a call to the underlying [Changelog::rebuild_pb_chain]
+///
+/// ## Example JSON String
+/// ```json
+/// {
+///     "profile_number": "76561198040982247",
+///     "map_id": "47763"
+/// }
+/// ```
+///
+/// ## Example JSON output
+///
+/// The rebuilt chain as a list of [Changelog], oldest first.
+#[utoipa::path(tag = "admin", responses((status = 200, body = Vec<Changelog>)))]
+#[post("/admin/repair/chain")]
+pub async fn admin_repair_chain(
+    params: web::Json<ChainRepairParams>,
+    pool: web::Data<PgPool>,
+    cache: web::Data<CacheState>,
+    auth: AuthUser,
+) -> Result<impl Responder> {
+    auth.require_admin()?;
+    let params = params.into_inner();
+    let cat_id = match params.cat_id {
+        Some(cat_id) => cat_id,
+        None => cache
+            .default_cat_ids
+            .get(&params.map_id, params.game_id.unwrap_or(1))
+            .ok_or_else(|| ServerError::new(ErrorType::NotFound, format!("No map with id {}", params.map_id)))?,
+    };
+    Ok(web::Json(
+        Changelog::rebuild_pb_chain(pool.get_ref(), &params.profile_number, &params.map_id, cat_id).await?,
+    ))
+}
+
+/// **POST** method to parse stored demos that are missing their metadata (`sar_version`), such as demos uploaded before
+/// demos were parsed. Requires an admin's auth token, see [AuthUser].
+///
+/// The demos are downloaded from backblaze and parsed in batches by a background job, the request returns as soon as the
+/// job has started. Only demos that have not been parsed are processed, so the job can be run again to resume or retry
+/// failed demos. If a job is already running, its progress is returned without starting another.
+///
+/// ## Parameters:
+/// - `limit`
+///     - **Optional** - `i64` : The most demos to process, defaults to 500, at most 10000.
+///
+/// ## Example endpoints:
+/// - `/api/v1/admin/demos/reparse`
+/// - `/api/v1/admin/demos/reparse?limit=2000`
+///
+/// Makes a call to the underlying [spawn_demo_reparse]
+///
+/// ## Example JSON output
+///
+/// ```json
+/// {
+///     "running": true,
+///     "parsed": 0,
+///     "failed": 0,
+///     "last_id": 0,
+///     "remaining": 18342
+/// }
+/// ```
+#[utoipa::path(tag = "admin", params(ReparseParams), responses((status = 200, body = ReparseProgress)))]
+#[post("/admin/demos/reparse")]
+pub async fn admin_demos_reparse(
+    params: web::Query<ReparseParams>,
+    pool: web::Data<PgPool>,
+    storage: web::Data<dyn DemoStorage>,
+    cache: web::Data<CacheState>,
+    auth: AuthUser,
+) -> Result<impl Responder> {
+    auth.require_admin()?;
+    let limit = params.limit.unwrap_or(DEFAULT_REPARSE_LIMIT).clamp(1, MAX_REPARSE_LIMIT);
+    let mut progress = cache.demo_reparse.lock().await;
+    if progress.running {
+        return Ok(web::Json(progress.clone()));
+    }
+    *progress = ReparseProgress {
+        running: true,
+        remaining: Demos::count_unparsed(pool.get_ref()).await?,
+        ..Default::default()
+    };
+    let started = progress.clone();
+    drop(progress);
+    spawn_demo_reparse(pool.get_ref().clone(), storage.into_inner(), cache.demo_reparse.clone(), limit);
+    Ok(web::Json(started))
+}
+
+/// **GET** method for the progress of the current (or last) demo re-parse job, see [admin_demos_reparse].
+/// Requires an admin's auth token, see [AuthUser].
+///
+/// ## Example endpoints:
+/// - `/api/v1/admin/demos/reparse`
+///
+/// ## Example JSON output
+///
+/// ```json
+/// {
+///     "running": false,
+///     "parsed": 488,
+///     "failed": 12,
+///     "last_id": 9120,
+///     "remaining": 17854
+/// }
+/// ```
+#[utoipa::path(tag = "admin", responses((status = 200, body = ReparseProgress)))]
+#[get("/admin/demos/reparse")]
+pub async fn admin_demos_reparse_progress(cache: web::Data<CacheState>, auth: AuthUser) -> Result<impl Responder> {
+    auth.require_admin()?;
+    Ok(web::Json(cache.demo_reparse.lock().await.clone()))
+}
+
+/// **POST** method to insert many changelog entries at once, for migrating scores from the old boards.
+/// Requires an admin's auth token, see [AuthUser].
+///
+/// Every row is checked before anything is inserted, rows referencing a user, map, category, demo or coop bundle that
+/// does not exist are reported in `failed` and the valid rows are imported. With `all_or_nothing`, nothing is imported
+/// if any row fails. At most 1000 rows can be imported at a time. Imported rows are given the `import` source, see [ChangelogSource].
+///
+/// ## Parameters:
+/// - `all_or_nothing`
+///     - **Optional** - `bool` : Import nothing if any row is invalid, defaults to `false`.
+///
+/// ## Example endpoints:
+/// - `/api/v1/admin/changelog/import`
+/// - `/api/v1/admin/changelog/import?all_or_nothing=true`
+///
+/// Makes a call to the underlying [Admin::get_import_errors] & [Changelog::insert_changelog_batch]
+///
+/// ## Example JSON String
+///
+/// An array of [ChangelogInsert].
+/// ```json
+/// [
+///     {
+///         "timestamp": "2014-05-20T18:13:34",
+///         "profile_number": "76561198040982247",
+///         "score": 1763,
+///         "map_id": "47763",
+///         "demo_id": null,
+///         "banned": false,
+///         "youtube_id": null,
+///         "previous_id": null,
+///         "coop_id": null,
+///         "post_rank": null,
+///         "pre_rank": null,
+///         "submission": 0,
+///         "note": "Imported from the old boards",
+///         "category_id": 19,
+///         "score_delta": null,
+///         "verified": true,
+///         "admin_note": null
+///     },...]
+/// ```
+///
+/// ## Example JSON output
+///
+/// ```json
+/// {
+///     "imported": [{ "index": 0, "id": 157812 }],
+///     "failed": [{ "index": 1, "error": "No map with map_id 12345" }]
+/// }
+/// ```
+#[utoipa::path(tag = "admin", params(ImportParams), responses((status = 200, body = ChangelogImport)))]
+#[post("/admin/changelog/import")]
+pub async fn admin_changelog_import(
+    rows: web::Json<Vec<ChangelogInsert>>,
+    params: web::Query<ImportParams>,
+    pool: web::Data<PgPool>,
+    cache: web::Data<CacheState>,
+    auth: AuthUser,
+) -> Result<impl Responder> {
+    auth.require_admin()?;
+    let rows = rows.into_inner();
+    if rows.len() > MAX_IMPORT_ROWS {
+        return Err(ServerError::new(
+            ErrorType::BadRequest,
+            format!("Cannot import {} rows at once, the limit is {}", rows.len(), MAX_IMPORT_ROWS),
+        ));
+    }
+    let errors = Admin::get_import_errors(pool.get_ref(), &rows).await?;
+    let mut summary = ChangelogImport::default();
+    let mut valid = Vec::with_capacity(rows.len());
+    for (index, (mut row, error)) in rows.into_iter().zip(errors).enumerate() {
+        match error {
+            Some(error) => summary.failed.push(FailedRow { index, error }),
+            None => {
+                row.source = ChangelogSource::Import;
+                valid.push((index, row));
+            }
+        }
+    }
+    if valid.is_empty() || (params.all_or_nothing.unwrap_or(false) && !summary.failed.is_empty()) {
+        return Ok(web::Json(summary));
+    }
+    let (indices, rows): (Vec<usize>, Vec<ChangelogInsert>) = valid.into_iter().unzip();
+    let ids = Changelog::insert_changelog_batch(pool.get_ref(), rows).await?;
+    summary.imported = indices.into_iter().zip(ids).map(|(index, id)| ImportedRow { index, id }).collect();
+    cache.invalidate_all_previews().await;
+    cache.invalidate_aggregate_points().await;
+    Ok(web::Json(summary))
+}
+
+/// **POST** method to rebuild the cached previews right away, such as after fixing scores directly in the database.
+/// Requires an admin's auth token, see [AuthUser].
+///
+/// The previews are recomputed, written to the cache files and marked as cached, see [CacheState::rebuild_previews].
+///
+/// ## Parameters:
+/// - `target`
+///     - **Optional** - `String` : `sp_previews`, `coop_previews` or `all`, defaults to `all`.
+///
+/// ## Example endpoints:
+/// - `/api/v1/admin/cache/rebuild`
+/// - `/api/v1/admin/cache/rebuild?target=sp_previews`
+///
+/// ## Example JSON output
+///
+/// ```json
+/// [
+///     {
+///         "target": "sp_previews",
+///         "millis": 1843
+///     },
+///     {
+///         "target": "coop_previews",
+///         "millis": 1265
+///     }
+/// ]
+/// ```
+#[utoipa::path(tag = "admin", params(CacheRebuildParams), responses((status = 200, body = Vec<CacheRebuilt>)))]
+#[post("/admin/cache/rebuild")]
+pub async fn admin_cache_rebuild(
+    params: web::Query<CacheRebuildParams>,
+    pool: web::Data<PgPool>,
+    cache: web::Data<CacheState>,
+    auth: AuthUser,
+) -> Result<impl Responder> {
+    auth.require_admin()?;
+    let target = params.target.unwrap_or_default();
+    let mut rebuilt = Vec::with_capacity(2);
+    if matches!(target, CacheTarget::SpPreviews | CacheTarget::All) {
+        let start = Instant::now();
+        cache.rebuild_sp_previews(pool.get_ref()).await?;
+        rebuilt.push(CacheRebuilt { target: SP_PREVIEWS.to_string(), millis: start.elapsed().as_millis() as u64 });
+    }
+    if matches!(target, CacheTarget::CoopPreviews | CacheTarget::All) {
+        let start = Instant::now();
+        cache.rebuild_coop_previews(pool.get_ref()).await?;
+        rebuilt.push(CacheRebuilt { target: COOP_PREVIEWS.to_string(), millis: start.elapsed().as_millis() as u64 });
+    }
+    Ok(web::Json(rebuilt))
+}
+
+/// **PUT** method to turn maintenance mode on or off without restarting the server. Requires an admin's auth token, see
+/// [AuthUser].
+///
+/// While it is on, the boards are read-only: anything but a `GET` is rejected with a 503, except this endpoint.
+/// It starts as `MAINTENANCE.ENABLED`, a change made here lasts until the server restarts.
+///
+/// ## Example endpoints:
+/// - `/api/v1/admin/maintenance`
+///
+/// ## Example JSON String
+///
+/// ```json
+/// {
+///     "enabled": true
+/// }
+/// ```
+///
+/// ## Example JSON output
+///
+/// ```json
+/// {
+///     "enabled": true
+/// }
+/// ```
+#[utoipa::path(tag = "admin", responses((status = 200, body = MaintenanceState)))]
+#[put("/admin/maintenance")]
+pub async fn admin_maintenance(
+    state: web::Json<MaintenanceState>,
+    maintenance: web::Data<Maintenance>,
+    auth: AuthUser,
+) -> Result<impl Responder> {
+    auth.require_admin()?;
+    maintenance.set(state.enabled);
+    Ok(web::Json(MaintenanceState { enabled: maintenance.is_enabled() }))
+}
@@ -1,7 +1,7 @@
 use crate::{
-    models::{changelog::*, demos::DemoOptions},
+    models::{changelog::*, demos::DemoOptions, maps::Maps},
     tools::{
-        cache::{CacheState, COOP_PREVIEWS, SP_PREVIEWS},
+        cache::CacheState,
         config::Config,
         error::Result,
         helpers::get_valid_changelog_insert,
@@ -10,6 +10,11 @@ use crate::{
 use actix_web::{get, post, put, web, Responder};
 use sqlx::PgPool;
 
+/// Number of runs returned by the recent activity feed when no `limit` is given.
+const DEFAULT_RECENT_LIMIT: i64 = 20;
+/// The most runs that can be requested from the recent activity feed.
+const MAX_RECENT_LIMIT: i64 = 100;
+
 /// **GET** method for changelog entiries. Utilizes [ChangelogQueryParams] as an optional addition to the query
 ///
 /// ## Parameters:
@@ -35,6 +40,22 @@ use sqlx::PgPool;
 ///         - **Optional** - `i64` : Will only return scores with an ID higher than the given amount
 ///    - `last`            
 ///         - **Optional** - `i64` : Will only return scores with an ID lower than the given amount
+///    - `cat_id`
+///         - **Optional** - `i32` : Filters for only scores in a specific category
+///    - `banned`
+///         - **Optional** - `bool` : Filters for only banned (`true`) or unbanned (`false`) scores
+///    - `submission`
+///         - **Optional** - `i32` : Filters for only scores with the given submission type
+///    - `source`
+///         - **Optional** - `String` : Filters for only scores from `manual` submissions, `steam_auto` leaderboard pulls or admin `import`s
+///    - `start_date`
+///         - **Optional** - `String` : `%Y-%m-%d`, only returns scores on or after this date
+///    - `end_date`
+///         - **Optional** - `String` : `%Y-%m-%d`, only returns scores on or before this date
+///    - `offset`
+///         - **Optional** - `u32` : Skips the given # of results, for paging alongside `limit`
+///
+/// Results are ordered newest first, an empty list is returned when nothing matches.
 ///
 /// ## Example endpoints:
 ///  - **Default**           
@@ -45,6 +66,10 @@ use sqlx::PgPool;
 ///     - `/api/v1/changelog?limit=200&first=157804`
 ///  - **A scroll call**     
 ///     - `/api/v1/changelog?limit-200&last=157604`
+///  - **A user's history for a date range**
+///     - `/api/v1/changelog?profile_number=76561198040982247&start_date=2021-01-01&end_date=2021-12-31&limit=50&offset=50`
+///  - **Only scores submitted to the boards**
+///     - `/api/v1/changelog?source=manual`
 ///
 /// Makes a call to the underlying [ChangelogPage::get_changelog_page]
 ///
@@ -70,11 +95,13 @@ use sqlx::PgPool;
 ///         "score_delta": -7,
 ///         "verified": true,
 ///         "admin_note": null,
+///         "source": "steam_auto",
 ///         "map_name": "Laser Relays",
 ///         "user_name": "Zypeh",
 ///         "avatar": "https://steamcdn-a.akamaihd.net/steamcommunity/public/images/avatars/f9/f934276c99d0f970fdcb2d4e1229dde02d778d99_full.jpg"
 ///     },...]
 /// ```
+#[utoipa::path(tag = "changelog", params(ChangelogQueryParams), responses((status = 200, body = Vec<ChangelogPage>)))]
 #[get("/changelog")]
 async fn changelog(
     pool: web::Data<PgPool>,
@@ -91,7 +118,7 @@ async fn changelog(
 ///
 /// ## Parameters (expects valid JSON Object):
 /// - `timestamp`    
-///     - **Required** - `String` : `%Y-%m-%d %H:%M:%S` (use `%20` to denote a space)
+///     - **Required** - `String` : `%Y-%m-%d %H:%M:%S` (use `%20` to denote a space). An invalid or future timestamp returns a 422.
 /// - `profile_number`
 ///     - **Required** - `String` : Steam ID Number
 /// - `score`         
@@ -99,7 +126,7 @@ async fn changelog(
 /// - `map_id`       
 ///     - **Required** - `String` : Steam ID for the map
 /// - `youtube_id`
-///     - **Optional** - `String` : Youtube URL Extension.
+///     - **Optional** - `String` : YouTube link or video id, stored as `{id}?start={seconds}`. An invalid video returns a 422.
 /// - `note`          
 ///     - **Optional** - `String` : Note for the run
 /// - `category_id`   
@@ -113,7 +140,7 @@ async fn changelog(
 /// ## Example JSON Input String
 /// ```json
 /// {
-///    "timestamp" : "2020-08-18%2024:60:60",
+///     "timestamp" : "2020-08-18 14:32:10",
 ///     "profile_number" : "76561198040982247",
 ///     "score" : 1763,
 ///     "map_id" : "47763",
@@ -123,6 +150,7 @@ async fn changelog(
 ///     "game_id" : 1
 /// }
 /// ```
+#[utoipa::path(tag = "changelog", responses((status = 200, body = i64)))]
 #[post("/changelog")]
 pub async fn changelog_new(
     pool: web::Data<PgPool>,
@@ -138,13 +166,75 @@ pub async fn changelog_new(
         cl.into_inner(),
     )
     .await?;
+    let map_id = cl_i.map_id.clone();
     let id = Changelog::insert_changelog(pool.get_ref(), cl_i).await?;
-    cache
-        .update_current_states(&[SP_PREVIEWS, COOP_PREVIEWS], &[false, false])
-        .await;
+    cache.invalidate_map_preview(pool.get_ref(), &map_id).await;
+    cache.invalidate_aggregate_points().await;
     Ok(web::Json(id))
 }
 
+/// **GET** method for the recent activity feed, the newest verified runs across both singleplayer and coop.
+///
+/// Coop runs are returned once, with the second player as the partner. The feed is cached for
+/// [crate::tools::cache::RECENT_ACTIVITY_TTL].
+///
+/// ## Parameters:
+///    - `limit`
+///         - **Optional** - `i64` : The # of runs to return, defaults to 20 and is capped at 100.
+///    - `game_id`
+///         - **Optional** - `i32` : The game to return runs for, defaults to the base game.
+///
+/// ## Example endpoints:
+///  - **Default**
+///     - `/api/v1/recent`
+///  - **With parameters**
+///     - `/api/v1/recent?limit=50&game_id=1`
+///
+/// Makes a call to the underlying [Changelog::get_recent]
+///
+/// ## Example JSON output
+/// ```json
+/// [
+///     {
+///         "id": 158010,
+///         "timestamp": "2022-03-02T18:41:12",
+///         "profile_number": "76561198040982247",
+///         "user_name": "Zyntex",
+///         "avatar": "https://steamcdn-a.akamaihd.net/steamcommunity/public/images/avatars/a5/a5e5a3bbd0a2d1f9e6a8d2a34b8b2c0d6e4b3f1a_full.jpg",
+///         "map_id": "47763",
+///         "map_name": "Laser vs Turret",
+///         "is_coop": false,
+///         "score": 1698,
+///         "post_rank": 1,
+///         "category_id": 19,
+///         "partner_profile_number": null,
+///         "partner_name": null,
+///         "partner_avatar": null,
+///         "has_demo": true,
+///         "has_youtube": false
+///     },...]
+/// ```
+#[utoipa::path(tag = "changelog", params(RecentParams), responses((status = 200, body = Vec<RecentActivity>)))]
+#[get("/recent")]
+async fn recent(
+    pool: web::Data<PgPool>,
+    cache: web::Data<CacheState>,
+    params: web::Query<RecentParams>,
+) -> Result<impl Responder> {
+    let limit = params
+        .limit
+        .unwrap_or(DEFAULT_RECENT_LIMIT)
+        .clamp(1, MAX_RECENT_LIMIT);
+    let game_id = params.game_id.unwrap_or(1);
+    if let Some(recent) = cache.get_recent_activity(limit, game_id).await {
+        return Ok(web::Json(recent));
+    }
+    let recent = Changelog::get_recent(pool.get_ref(), limit, game_id).await?;
+    cache.set_recent_activity(limit, game_id, recent.clone()).await;
+    Ok(web::Json(recent))
+}
+
+#[utoipa::path(tag = "changelog", responses((status = 200, body = Vec<Graph>)))]
 #[get("/graph")]
 async fn graph(
     pool: web::Data<PgPool>
@@ -166,9 +256,10 @@ async fn graph(
 ///     "52738": 98,
 ///     "48287": 108,...}
 /// ```
+#[utoipa::path(tag = "changelog", responses((status = 200, body = HashMap<String, i32>)))]
 #[get("/default_categories_all")]
 pub async fn default_categories_all(pool: web::Data<PgPool>) -> impl Responder {
-    web::Json(crate::tools::helpers::get_default_cat_ids(pool.get_ref()).await)
+    web::Json(Maps::get_all_default_cats(pool.get_ref()).await.unwrap())
 }
 
 /// **PUT** method for updating the demo_id on a changelog entry.
@@ -195,6 +286,7 @@ pub async fn default_categories_all(pool: web::Data<PgPool>) -> impl Responder {
 ///     "demo_id" : 1251
 /// }
 /// ```
+#[utoipa::path(tag = "changelog", responses((status = 200, body = Changelog)))]
 #[put("/changelog/demo")]
 pub async fn changelog_demo_update(
     pool: web::Data<PgPool>,
@@ -208,8 +300,6 @@ pub async fn changelog_demo_update(
         ids.demo_id.unwrap(),
     )
     .await?;
-    cache
-        .update_current_states(&[SP_PREVIEWS, COOP_PREVIEWS], &[false, false])
-        .await;
+    cache.invalidate_all_previews().await;
     Ok(web::Json(return_changelog))
 }
@@ -1,5 +1,5 @@
 use crate::{
-    models::chapters::{ChapterQueryParams, Chapters},
+    models::chapters::{ChapterQueryParams, ChapterWithMaps, Chapters, GameID},
     tools::error::Result,
 };
 use actix_web::{get, web, Responder};
@@ -26,6 +26,7 @@ use sqlx::PgPool;
 ///     "46362"
 /// ]
 /// ```
+#[utoipa::path(tag = "chapters", responses((status = 200, body = Vec<String>)))]
 #[get("/chapter/{chapter_id}/maps")]
 async fn maps_from_chapter(
     chapter_id: web::Path<i32>,
@@ -55,6 +56,7 @@ async fn maps_from_chapter(
 ///     "game_id": 1
 /// }
 /// ```
+#[utoipa::path(tag = "chapters", responses((status = 200, body = Option<Chapters>)))]
 #[get("/chapter/{chapter_id}")]
 async fn chapter(id: web::Path<i32>, pool: web::Data<PgPool>) -> Result<impl Responder> {
     Ok(web::Json(
@@ -89,6 +91,7 @@ async fn chapter(id: web::Path<i32>, pool: web::Data<PgPool>) -> Result<impl Res
 ///     }
 /// ]
 /// ```
+#[utoipa::path(tag = "chapters", params(ChapterQueryParams), responses((status = 200, body = Vec<Chapters>)))]
 #[get("/chapters")]
 async fn chapters_filtered(
     params: web::Query<ChapterQueryParams>,
@@ -98,3 +101,45 @@ async fn chapters_filtered(
         Chapters::get_filtered_chapters(pool.get_ref(), params.into_inner()).await?,
     ))
 }
+
+/// **GET** method to return the chapters of a game with the maps in each chapter.
+///
+/// Singleplayer chapters are listed before multiplayer chapters, and maps are in the order they appear in the game.
+///
+/// ## Parameters:
+/// - `game_id`
+///     - **Optional** - `i32` : ID of the game. Defaults to all.
+///
+/// ## Example Endpoints
+/// - **Default**
+///     - `/api/v1/chapters/maps`
+/// - **With Params**
+///     - `/api/v1/chapters/maps?game_id=1`
+///
+/// Makes a call to the underlying [Chapters::get_chapters_with_maps]
+///
+/// ## Example JSON output
+/// ```json
+/// [
+///     {
+///         "chapter_id": 7,
+///         "name": "The Courtesy Call",
+///         "is_multiplayer": false,
+///         "maps": [
+///             {
+///                 "steam_id": "62761",
+///                 "name": "Container Ride",
+///                 "default_cat_id": 1
+///             },...]
+///     },...]
+/// ```
+#[utoipa::path(tag = "chapters", params(GameID), responses((status = 200, body = Vec<ChapterWithMaps>)))]
+#[get("/chapters/maps")]
+async fn chapters_with_maps(
+    params: web::Query<GameID>,
+    pool: web::Data<PgPool>,
+) -> Result<impl Responder> {
+    Ok(web::Json(
+        Chapters::get_chapters_with_maps(pool.get_ref(), params.game_id).await?,
+    ))
+}
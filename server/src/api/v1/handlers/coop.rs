@@ -1,28 +1,44 @@
 use crate::{
     models::{
-        changelog::{Changelog, ScoreLookup, ScoreParams},
-        chapters::OptIDs,
+        changelog::{Changelog, HistoryParams, ScoreLookup, ScoreParams},
+        chapters::{ExportParams, FormatParams, GameID, Games, LimitParams, OptIDs},
         coop::*,
+        users::Users,
     },
     tools::{
-        cache::{read_from_file, write_to_file, CacheState, COOP_PREVIEWS},
+        auth::AuthUser,
+        cache::{CacheState, COOP_PREVIEWS},
+        client::client_id,
+        live::LiveUpdate,
         config::Config,
-        error::Result,
-        helpers::filter_coop_entries,
+        error::{ErrorType, Result, ServerError},
+        export::{csv_response, wants_csv, CoopCsvRow},
+        helpers::{filter_coop_entries, format_score, results_limit},
+        metrics::Metrics,
+        ratelimit::RateLimiter,
     },
 };
-use actix_web::{get, post, put, web, HttpResponse, Responder};
+use actix_web::{get, post, put, web, HttpRequest, HttpResponse, Responder};
 use sqlx::PgPool;
 
-// TODO: Should use default cat_id
 /// **GET** Returns top 7 information for each map, used to generate the previews page for Coop.
 ///
 /// Inital load tends to be relatively slow, but the information cached, and
-/// remains in chache until a new singleplayer score is submitted
+/// remains in chache until a new score is submitted, or it is older than the cache TTL (`CACHE.TTL_SECS`).
+/// The previews are rebuilt in the background before the TTL expires.
+///
+/// Responses include an `ETag`, requests with a matching `If-None-Match` get a 304 while the cached previews are unchanged.
+/// Previews for games other than the base game are cached in memory and do not include an `ETag`.
+///
+/// ## Parameters:
+/// - `game_id`
+///     - **Optional** - `i32` : The ID of the game, defaults to the base game (id = 1).
 ///
 /// ## Example Endpoints:
 /// - **Default**
 ///     - `/api/v1/coop`
+/// - **Game**
+///     - `/api/v1/coop?game_id=2`
 ///
 /// Makes a call to the underlying [CoopPreview::get_coop_previews]
 ///
@@ -44,21 +60,47 @@ use sqlx::PgPool;
 ///                 "user_name2": "Rex"
 ///             },...]},...}
 /// ```
+#[utoipa::path(tag = "coop", params(GameID), responses((status = 200, body = Vec<Vec<CoopPreview>>)))]
 #[get("/coop")]
-async fn coop(pool: web::Data<PgPool>, cache: web::Data<CacheState>) -> Result<impl Responder> {
-    if !cache.get_current_state(COOP_PREVIEWS).await {
-        let previews = CoopPreview::get_coop_previews(pool.get_ref()).await?;
-        if write_to_file("coop_previews", &previews).await.is_ok() {
-            cache.update_current_state(COOP_PREVIEWS, true).await;
-        } else {
-            eprintln!("Could not write cache for coop previews");
+async fn coop(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    cache: web::Data<CacheState>,
+    metrics: web::Data<Metrics>,
+    query: web::Query<GameID>,
+) -> Result<impl Responder> {
+    let game_id = query.into_inner().game_id.unwrap_or(1);
+    if game_id != 1 {
+        let cached = cache.get_game_coop_previews(game_id).await;
+        metrics.record_cache_lookup(COOP_PREVIEWS, cached.is_some());
+        let previews = match cached {
+            Some(previews) => previews,
+            None => {
+                let previews =
+                    CoopPreview::get_coop_previews(pool.get_ref(), &cache.default_cat_ids.for_game(game_id), game_id)
+                        .await?;
+                cache.set_game_coop_previews(game_id, previews.clone()).await;
+                previews
+            }
+        };
+        return Ok(HttpResponse::Ok().json(previews));
+    }
+    let cached = cache.get_current_state(COOP_PREVIEWS).await;
+    metrics.record_cache_lookup(COOP_PREVIEWS, cached);
+    if let Some(not_modified) = cache.not_modified(&req, COOP_PREVIEWS).await {
+        return Ok(not_modified);
+    }
+    let previews = if !cached {
+        let generation = cache.preview_generation(COOP_PREVIEWS).await;
+        let previews = CoopPreview::get_coop_previews(pool.get_ref(), &cache.default_cat_ids.for_game(1), 1).await?;
+        if let Err(e) = cache.write_previews(COOP_PREVIEWS, generation, &previews).await {
+            eprintln!("Could not write cache for coop previews -> {}", e);
         }
-        Ok(web::Json(previews))
+        previews
     } else {
-        Ok(web::Json(
-            read_from_file::<Vec<Vec<CoopPreview>>>(COOP_PREVIEWS).await?,
-        ))
-    }
+        cache.read_previews::<Vec<Vec<CoopPreview>>>(COOP_PREVIEWS).await?
+    };
+    Ok(cache.preview_response(&req, COOP_PREVIEWS, &previews).await?)
 }
 
 /// **GET** method that returns all coop scores for a maps page on a specific category.
@@ -68,14 +110,30 @@ async fn coop(pool: web::Data<PgPool>, cache: web::Data<CacheState>) -> Result<i
 /// ## Parameters:
 /// - `cat_id`           
 ///     - **Optional** - `i32` : The ID of the category you want a Cooperative Ranked Page for.
-/// - `cat_id`
+/// - `game_id`
 ///     - **Optional** - `i32` : The ID of the game, defaults to the base game (id = 1).
+/// - `limit`
+///     - **Optional** - `i32` : The max number of entries returned, defaults to `PROOF.RESULTS` and is clamped to between 1 and
+///       `PROOF.MAX_RESULTS`, see [results_limit]. Ranks are the same as on the full leaderboard.
+/// - `formatted`
+///     - **Optional** - `bool` : If `true`, each entry includes a `formatted_score` (`m:ss.cc`).
+/// - `format`
+///     - **Optional** - `String` : `json` or `csv`, if left blank `csv` is returned to clients sending `Accept: text/csv`.
+///
+/// As CSV, each run is a row of `rank,profile_number1,user_name1,profile_number2,user_name2,score,points` after a
+/// header line.
 ///
 /// Example Endpoints:
 /// - **Default**
 ///     - `/api/v1/map/coop/47741` - Will assume default category ID
 /// - **Specific IDs**
 ///     - `/api/v1/map/coop/47741?cat_id=61&game_id=1`
+/// - **Top 10**
+///     - `/api/v1/map/coop/47741?limit=10`
+/// - **Formatted Scores**
+///     - `/api/v1/map/coop/47741?formatted=true`
+/// - **As CSV**
+///     - `/api/v1/map/coop/47741?format=csv`
 ///
 /// Makes a call to the underlying [CoopMap::get_coop_map_page]
 ///
@@ -108,18 +166,31 @@ async fn coop(pool: web::Data<PgPool>, cache: web::Data<CacheState>) -> Result<i
 ///         "points": 200.0
 ///     },...]
 /// ```
+#[utoipa::path(
+    tag = "coop",
+    params(OptIDs, LimitParams, FormatParams, ExportParams),
+    responses((status = 200, body = Vec<CoopRanked>)),
+)]
 #[get("/map/coop/{map_id}")]
+#[allow(clippy::too_many_arguments)]
 async fn coop_map(
+    req: HttpRequest,
     map_id: web::Path<String>,
     ids: web::Query<OptIDs>,
+    limit: web::Query<LimitParams>,
+    format: web::Query<FormatParams>,
+    export: web::Query<ExportParams>,
     config: web::Data<Config>,
     cache: web::Data<CacheState>,
     pool: web::Data<PgPool>,
 ) -> Result<impl Responder> {
     let map_id = map_id.into_inner();
-    let cat_id = ids
-        .cat_id
-        .unwrap_or_else(|| cache.into_inner().default_cat_ids[&map_id]);
+    let cat_id = match ids.cat_id {
+        Some(cat_id) => cat_id,
+        None => cache.default_cat_ids.get(&map_id, ids.game_id.unwrap_or(1)).ok_or_else(|| {
+            ServerError::new(ErrorType::NotFound, format!("No map with id {}", map_id))
+        })?,
+    };
     let coop_entries = CoopMap::get_coop_map_page(
         pool.get_ref(),
         &map_id,
@@ -127,9 +198,116 @@ async fn coop_map(
         ids.game_id.unwrap_or(1),
     )
     .await?;
-    Ok(web::Json(
-        filter_coop_entries(coop_entries, config.proof.results as usize).await,
-    ))
+    let limit = results_limit(limit.limit, &config.proof);
+    let mut ranked = filter_coop_entries(coop_entries, limit as usize).await;
+    if wants_csv(&req, export.format) {
+        return Ok(csv_response(ranked.into_iter().map(CoopCsvRow::from)));
+    }
+    if format.formatted.unwrap_or(false) {
+        let tick_rate = Games::get_tick_rate(pool.get_ref(), ids.game_id.unwrap_or(1)).await?;
+        for entry in ranked.iter_mut() {
+            entry.formatted_score = Some(format_score(entry.map_data.score, tick_rate));
+        }
+    }
+    Ok(HttpResponse::Ok().json(ranked))
+}
+
+/// **GET** method to return a player's bundled coop runs on a map, newest first, with the partner for each run.
+///
+/// A player can run with different partners over time, each run has the partner it was bundled with.
+///
+/// Query parameters represented as [HistoryParams]
+///
+/// ## Parameters:
+/// - `profile_number`
+///     - **Required** - `String` : ID for the player.
+/// - `map_id`
+///     - **Required** - `String` : ID for the map.
+/// - `cat_id`
+///     - **Optional** `i32` : ID for the category. Defaults to the default category for the map.
+/// - `game_id`
+///     - **Optional**  `i32` : ID for the game. Defaults to the base game, or ID = 1.
+///
+/// ## Example Endpoints:
+/// - **With Parametes**
+///     - `/api/v1/coop/history?map_id=47741&profile_number=76561198048179892`
+/// - **With cat_id**
+///     - `/api/v1/coop/history?map_id=47741&profile_number=76561198048179892&cat_id=61`
+///
+/// Makes a call to the underlying [Users::get_user_data] & [Changelog::get_coop_pb_history]
+///
+/// # Example JSON output
+///
+/// - For a user that exists
+///
+/// ```json
+/// {
+///     "user_name": "Betsruner",
+///     "avatar": "https://steamcdn-a.akamaihd.net/steamcommunity/public/images/avatars/2c/2c1253fe42835727f16e4625674a6079b963d16e_full.jpg",
+///     "pb_history": [
+///         {
+///             "id": 128470,
+///             "timestamp": "2021-02-21T02:12:42",
+///             "profile_number": "76561198048179892",
+///             "score": 750,
+///             "map_id": "47741",
+///             "demo_id": 18944,
+///             "banned": false,
+///             "youtube_id": null,
+///             "previous_id": 127901,
+///             "coop_id": 8473,
+///             "post_rank": 1,
+///             "pre_rank": 2,
+///             "submission": 0,
+///             "note": "Holy shit no reportal wtf",
+///             "category_id": 61,
+///             "score_delta": -12,
+///             "verified": true,
+///             "admin_note": null,
+///             "updated": null,
+///             "partner_profile_number": "76561198095730281",
+///             "partner_name": "Rex"
+///         },..]}
+/// ```
+///
+/// - For a user that does not exist.
+///
+/// ```json
+/// {
+///     "user_name": null,
+///     "avatar": null,
+///     "pb_history": null
+/// }
+/// ```
+#[utoipa::path(tag = "coop", params(HistoryParams), responses((status = 200, body = CoopPbHistory)))]
+#[get("/coop/history")]
+async fn coop_history(
+    query: web::Query<HistoryParams>,
+    pool: web::Data<PgPool>,
+    cache: web::Data<CacheState>,
+) -> Result<impl Responder> {
+    let query = query.into_inner();
+    let game_id = query.game_id.unwrap_or(1);
+    let cat_id = match query.cat_id {
+        Some(cat_id) => cat_id,
+        None => cache.default_cat_ids.get(&query.map_id, game_id).ok_or_else(|| {
+            ServerError::new(ErrorType::NotFound, format!("No map with id {}", query.map_id))
+        })?,
+    };
+    let Some(user_data) = Users::get_user_data(pool.get_ref(), &query.profile_number).await? else {
+        return Ok(web::Json(CoopPbHistory {
+            user_name: None,
+            avatar: None,
+            pb_history: None,
+        }));
+    };
+    let pb_history =
+        Changelog::get_coop_pb_history(pool.get_ref(), &query.profile_number, &query.map_id, cat_id, game_id).await?;
+    Ok(web::Json(CoopPbHistory {
+        user_name: Some(user_data.user_name),
+        avatar: Some(user_data.avatar),
+        pb_history: Some(pb_history),
+    }))
 }
 
 /// **GET** method to return all banned scores on a map for a specific category.
@@ -155,6 +333,7 @@ async fn coop_map(
 ///         "score": 986
 ///     },...]
 /// ```
+#[utoipa::path(tag = "coop", params(OptIDs), responses((status = 200, body = Vec<CoopBanned>)))]
 #[get("/coop/map_banned/{map_id}")]
 async fn coop_banned_all(
     map_id: web::Path<String>,
@@ -163,9 +342,12 @@ async fn coop_banned_all(
     params: web::Query<OptIDs>,
 ) -> Result<impl Responder> {
     let map_id = map_id.into_inner();
-    let cat_id = params
-        .cat_id
-        .unwrap_or_else(|| cache.into_inner().default_cat_ids[&map_id]);
+    let cat_id = match params.cat_id {
+        Some(cat_id) => cat_id,
+        None => cache.default_cat_ids.get(&map_id, params.game_id.unwrap_or(1)).ok_or_else(|| {
+            ServerError::new(ErrorType::NotFound, format!("No map with id {}", map_id))
+        })?,
+    };
     Ok(web::Json(
         CoopBanned::get_coop_banned(pool.get_ref(), &map_id, cat_id).await?,
     ))
@@ -198,6 +380,7 @@ async fn coop_banned_all(
 /// ```json
 /// true
 /// ```
+#[utoipa::path(tag = "coop", params(ScoreParams), responses((status = 200, body = bool)))]
 #[get("/coop/time_banned/{map_id}")]
 async fn coop_banned(
     map_id: web::Path<String>,
@@ -206,11 +389,12 @@ async fn coop_banned(
     pool: web::Data<PgPool>,
 ) -> Result<impl Responder> {
     let map_id = map_id.into_inner();
-    let cat_id = Some(
-        params
-            .cat_id
-            .unwrap_or(cache.into_inner().default_cat_ids[&map_id]),
-    );
+    let cat_id = Some(match params.cat_id {
+        Some(cat_id) => cat_id,
+        None => cache.default_cat_ids.get(&map_id, params.game_id.unwrap_or(1)).ok_or_else(|| {
+            ServerError::new(ErrorType::NotFound, format!("No map with id {}", map_id))
+        })?,
+    });
     let is_banned = Changelog::check_banned_scores(
         pool.get_ref(),
         ScoreLookup {
@@ -242,13 +426,14 @@ async fn coop_banned(
 ///    "profile_number": "N/A"
 /// }
 ///
+#[utoipa::path(tag = "coop", responses((status = 200, body = Option<CoopTempUser>)))]
 #[get("/coop/temp/{map_id}")]
-async fn coop_temp(pool: web::Data<PgPool>, map_id: web::Path<String>) -> impl Responder {
+async fn coop_temp(pool: web::Data<PgPool>, map_id: web::Path<String>) -> Result<impl Responder> {
     match CoopBundled::get_temp_coop_changelog(pool.get_ref(), &map_id).await {
-        Ok(res) => HttpResponse::Ok().json(res),
+        Ok(res) => Ok(web::Json(res)),
         Err(e) => {
             eprintln!("Error finding temp score -> {}", e);
-            HttpResponse::NotFound().body("Cannot find temp score on given map.")
+            Err(ServerError::new(ErrorType::NotFound, "Cannot find temp score on given map."))
         }
     }
 }
@@ -273,7 +458,10 @@ async fn coop_temp(pool: web::Data<PgPool>, map_id: web::Path<String>) -> impl R
 /// ## Example Endpoints
 /// - `/api/v1/coop/post_score`
 ///
-/// Makes a call to the underlying [CoopBundled::insert_coop_bundled]
+/// Makes a call to the underlying [CoopBundled::insert_coop_bundled], and returns the `id` of the new bundle.
+///
+/// Submissions are rate limited for each `p_id1` and each client address, see [RateLimiter] and [client_id].
+/// Live clients are sent a `coop_update` for the map, see [LiveUpdate].
 ///
 /// ## Example JSON string
 /// ```json
@@ -285,15 +473,198 @@ async fn coop_temp(pool: web::Data<PgPool>, map_id: web::Path<String>) -> impl R
 ///     "cl_id2" : 157753
 /// }
 /// ```
+#[utoipa::path(tag = "coop", responses((status = 200, body = i64)))]
 #[post("/coop/post_score")]
 async fn coop_add(
+    req: HttpRequest,
     params: web::Json<CoopBundledInsert>,
     pool: web::Data<PgPool>,
+    config: web::Data<Config>,
+    cache: web::Data<CacheState>,
+    limiter: web::Data<RateLimiter>,
+) -> Result<impl Responder> {
+    // The client is checked first, so a client over its limit cannot use up the player's submissions.
+    limiter.check(&client_id(&req, &config.trusted_proxies)).await?;
+    limiter.check(&params.p_id1).await?;
+    let map_id = Changelog::get_changelog(pool.get_ref(), params.cl_id1).await?.map(|cl| cl.map_id);
+    match CoopBundled::insert_coop_bundled(pool.get_ref(), params.0).await {
+        Ok(id) => {
+            cache.update_current_state(COOP_PREVIEWS, false).await;
+            cache.invalidate_aggregate_points().await;
+            if let Some(map_id) = map_id {
+                cache.live.publish(LiveUpdate::CoopUpdate { map_id });
+            }
+            Ok(web::Json(id))
+        }
+        Err(e) => {
+            eprintln!("Error inserting coop bundle -> {}", e);
+            Err(ServerError::new(ErrorType::Internal, "Could not insert coop bundle."))
+        }
+    }
+}
+
+/// **GET** method to return changelog entries on a coop map that have not been bundled with a partner.
+///
+/// Entries that are only bundled with the temporary changelog entry (see [coop_temp]) are included.
+///
+/// ## Parameters:
+/// - `map_id`
+///     - **Required** - `String` : **Not a query string, part of the endpoint**
+/// - `cat_id`
+///     - **Optional** - `i32` : The ID of the category, if left blank will use the default.
+///
+/// ## Example Endpoints
+/// - **Default**
+///     - `/api/v1/coop/unbundled/47741`
+/// - **Specific Category**
+///     - `/api/v1/coop/unbundled/47741?cat_id=61`
+///
+/// Makes a call to the underlying [CoopBundled::find_unbundled]
+///
+/// ## Example JSON output
+/// ```json
+/// [
+///     {
+///         "id": 185404,
+///         "timestamp": "2022-01-20T19:35:53",
+///         "profile_number": "76561198039230536",
+///         "score": 2396,
+///         "map_id": "47741",
+///         "demo_id": null,
+///         "banned": false,
+///         "youtube_id": null,
+///         "previous_id": null,
+///         "coop_id": null,
+///         "post_rank": null,
+///         "pre_rank": null,
+///         "submission": 1,
+///         "note": null,
+///         "category_id": 61,
+///         "score_delta": null,
+///         "verified": null,
+///         "admin_note": null
+///     }
+/// ]
+/// ```
+#[utoipa::path(tag = "coop", params(OptIDs), responses((status = 200, body = Vec<Changelog>)))]
+#[get("/coop/unbundled/{map_id}")]
+pub async fn coop_unbundled(
+    map_id: web::Path<String>,
+    ids: web::Query<OptIDs>,
+    cache: web::Data<CacheState>,
+    pool: web::Data<PgPool>,
+) -> Result<impl Responder> {
+    let map_id = map_id.into_inner();
+    let cat_id = match ids.cat_id {
+        Some(cat_id) => cat_id,
+        None => cache.default_cat_ids.get(&map_id, ids.game_id.unwrap_or(1)).ok_or_else(|| {
+            ServerError::new(ErrorType::NotFound, format!("No map with id {}", map_id))
+        })?,
+    };
+    Ok(web::Json(
+        CoopBundled::find_unbundled(pool.get_ref(), &map_id, cat_id).await?,
+    ))
+}
+
+/// **POST** method that bundles two existing changelog entries into a coop score.
+///
+/// Both entries must be on the same map and category, and neither can already be bundled with a partner, which is checked
+/// with both entries locked, see [CoopBundled::transaction_lock_unbundled].
+/// Any bundle pairing either entry with the temporary changelog entry is removed, all in one transaction.
+/// Live clients are sent a `coop_update` for the map, see [LiveUpdate].
+///
+/// Requires an auth token, see [AuthUser]. Users can only bundle runs where one of the entries is their own.
+///
+/// ## Parameters:
+/// - `cl_id1`
+///     - **Required** - `i64` : The `changelog_id` for player 1's run.
+/// - `cl_id2`
+///     - **Required** - `i64` : The `changelog_id` for player 2's run.
+/// - `p1_is_host`
+///     - **Optional** - `bool` : If player 1 was the host for the run.
+///
+/// ## Example Endpoints
+/// - `/api/v1/coop/bundle`
+///
+/// Makes a call to the underlying [CoopBundled::transaction_insert_coop_bundled], and returns the `id` of the new bundle.
+///
+/// ## Example JSON string
+/// ```json
+/// {
+///     "cl_id1" : 157752,
+///     "cl_id2" : 157753,
+///     "p1_is_host" : true
+/// }
+/// ```
+#[utoipa::path(tag = "coop", responses((status = 200, body = i64)))]
+#[post("/coop/bundle")]
+pub async fn coop_bundle(
+    auth: AuthUser,
+    params: web::Json<CoopBundleParams>,
+    pool: web::Data<PgPool>,
     cache: web::Data<CacheState>,
 ) -> Result<impl Responder> {
-    let id = CoopBundled::insert_coop_bundled(pool.get_ref(), params.0).await?;
+    let params = params.into_inner();
+    let pool = pool.get_ref();
+    if params.cl_id1 == params.cl_id2 {
+        return Err(ServerError::new(
+            ErrorType::BadRequest,
+            "Cannot bundle a changelog entry with itself.",
+        ));
+    }
+    let mut entries = Vec::with_capacity(2);
+    for cl_id in [params.cl_id1, params.cl_id2] {
+        match Changelog::get_changelog(pool, cl_id).await? {
+            Some(cl) => entries.push(cl),
+            None => {
+                return Err(ServerError::new(
+                    ErrorType::NotFound,
+                    format!("No changelog entry with id {}", cl_id),
+                ))
+            }
+        }
+    }
+    let (cl1, cl2) = (&entries[0], &entries[1]);
+    if !auth.is_admin() && !entries.iter().any(|cl| cl.profile_number == auth.profile_number) {
+        return Err(ServerError::new(
+            ErrorType::Forbidden,
+            "You can only bundle runs that include one of your own scores.",
+        ));
+    }
+    if cl1.map_id != cl2.map_id || cl1.category_id != cl2.category_id {
+        return Err(ServerError::new(
+            ErrorType::BadRequest,
+            "Both changelog entries must share the same map_id and category_id.",
+        ));
+    }
+    let mut transaction = pool.begin().await?;
+    // Checked with both entries locked, so two requests cannot bundle the same entry with different partners.
+    if !CoopBundled::transaction_lock_unbundled(&mut transaction, &[cl1.id, cl2.id]).await? {
+        return Err(ServerError::new(
+            ErrorType::BadRequest,
+            "Changelog entries must not already be bundled with a partner.",
+        ));
+    }
+    let coop_id = CoopBundled::transaction_insert_coop_bundled(
+        &mut transaction,
+        CoopBundledInsert {
+            p_id1: cl1.profile_number.clone(),
+            p_id2: Some(cl2.profile_number.clone()),
+            p1_is_host: params.p1_is_host,
+            cl_id1: cl1.id,
+            cl_id2: Some(cl2.id),
+        },
+    )
+    .await?;
+    for cl in entries.iter() {
+        CoopBundled::transaction_update_changelog_with_coop_id(&mut transaction, cl.id, coop_id).await?;
+    }
+    CoopBundled::transaction_clear_temp_bundles(&mut transaction, &[cl1.id, cl2.id]).await?;
+    transaction.commit().await?;
     cache.update_current_state(COOP_PREVIEWS, false).await;
-    Ok(web::Json(id))
+    cache.invalidate_aggregate_points().await;
+    cache.live.publish(LiveUpdate::CoopUpdate { map_id: cl1.map_id.clone() });
+    Ok(web::Json(coop_id))
 }
 
 /// **PUT** method that updates existing changelog entries with their parent coop_bundled entry ID.
@@ -307,7 +678,9 @@ async fn coop_add(
 /// ## Example Endpoints
 /// - `/api/v1/coop/update_changelog/12213/124`
 ///
-/// Makes a call to the underlying [CoopBundled::insert_coop_bundled]
+/// Requires an auth token, see [AuthUser]. Users can only update their own changelog entries.
+///
+/// Makes a call to the underlying [CoopBundled::update_changelog_with_coop_id]
 ///
 /// ## Example JSON output: Returns the changelog in it's updated state.
 /// ```json
@@ -335,13 +708,33 @@ async fn coop_add(
 ///     "avatar": "https://steamcdn-a.akamaihd.net/steamcommunity/public/images/avatars/dc/dc4c1cfa8f0c5b0c85354825c7711f60c3714a41_full.jpg"
 /// }
 /// ```
+#[utoipa::path(tag = "coop", responses((status = 200, body = Changelog)))]
 #[put("/coop/update_changelog/{cl_id}/{coop_id}")]
 async fn coop_update_changelog(
+    auth: AuthUser,
     pool: web::Data<PgPool>,
     path: web::Path<(i64, i64)>,
     cache: web::Data<CacheState>,
 ) -> Result<impl Responder> {
+    if !auth.is_admin() {
+        match Changelog::get_changelog(pool.get_ref(), path.0).await? {
+            Some(cl) if cl.profile_number == auth.profile_number => {}
+            Some(_) => {
+                return Err(ServerError::new(
+                    ErrorType::Forbidden,
+                    "You can only update your own changelog entries.",
+                ))
+            }
+            None => {
+                return Err(ServerError::new(
+                    ErrorType::NotFound,
+                    format!("No changelog entry with id {}", path.0),
+                ))
+            }
+        }
+    }
     let id = CoopBundled::update_changelog_with_coop_id(pool.get_ref(), path.0, path.1).await?;
     cache.update_current_state(COOP_PREVIEWS, false).await;
+    cache.invalidate_aggregate_points().await;
     Ok(web::Json(id))
 }
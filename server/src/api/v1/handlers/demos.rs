@@ -2,19 +2,55 @@ use crate::models::changelog::{Changelog, ChangelogInsert, SubmissionChangelog};
 use crate::models::demos::*;
 use crate::models::maps::Maps;
 use crate::tools::cache::CacheState;
+use crate::tools::auth::AuthUser;
+use crate::tools::backblaze::content_sha1;
+use crate::tools::client::client_id;
 use crate::tools::config::Config;
+use crate::tools::demo::{
+    demo_header, demo_matches_map, has_demo_magic, is_demo_file_name, parse_demo, parse_demo_bytes, sanitize_demo_file_name,
+    upload_file_name,
+};
+use crate::tools::discord::notify_if_wr;
+use crate::tools::error::{self, ErrorType, ServerError};
 use crate::tools::helpers::get_valid_changelog_insert;
+use crate::tools::metrics::Metrics;
+use crate::tools::ratelimit::RateLimiter;
+use crate::tools::storage::{is_missing_file, DemoStorage, StoredFile};
 use actix_multipart::Multipart;
-use actix_web::{delete, get, post, web, HttpResponse, Responder};
+use actix_web::{delete, get, http::header, post, web, web::Bytes, HttpRequest, HttpResponse, Responder};
 use anyhow::{bail, Result};
-use futures::{StreamExt, TryStreamExt};
-use raze::api::*;
-use raze::utils::*;
+use futures::{future, stream, Stream, StreamExt, TryStreamExt};
 use sqlx::PgPool;
 use std::fs::remove_file;
 use std::fs::OpenOptions;
-use std::io::Write;
+use std::future::Future;
+use std::io::{Read, Write};
 use std::str;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+/// Header clients can send the demo's SHA1 in to make uploads idempotent, see [demos_changelog].
+pub const DEMO_SHA1_HEADER: &str = "X-Demo-Sha1";
+/// Number of demos downloaded and parsed at a time by [spawn_demo_reparse].
+const DEMO_REPARSE_BATCH_SIZE: i64 = 25;
+
+/// What [add_to_database] needs to store a newly submitted demo.
+#[derive(Clone, Copy)]
+struct DemoUpload<'a> {
+    config: &'a Config,
+    storage: &'a dyn DemoStorage,
+    metrics: &'a Metrics,
+}
+
+/// Removes an upload written to `./demos` by [parse_and_write_multipart] when dropped, so it is cleaned up however the
+/// submission ends. Removing it is best-effort, a file that is already gone or cannot be removed is ignored.
+struct LocalUpload<'a>(&'a str);
+
+impl Drop for LocalUpload<'_> {
+    fn drop(&mut self) {
+        let _ = remove_file(format!("./demos/{}", self.0));
+    }
+}
 
 /// GET endpoint to return demo information.
 /// ## Expects **one** of following fields:
@@ -32,376 +68,1073 @@ use std::str;
 /// - `/api/v1/demos?cl_id=15625`
 /// - `/api/v1/demos?demo_id=12651`
 ///
-///[get("/demos")]
-///pub async fn demos(pool: web::Data<PgPool>, query: web::Query<DemoOptions>) -> impl Responder {
-///    let query = query.into_inner();
-///    let res_str = "Could not find demo.";
-///    if query.demo_id.is_some() & query.cl_id.is_none() {
-///        match Demos::get_demo(pool.get_ref(), query.demo_id.unwrap()).await {
-///            Ok(Some(demo)) => HttpResponse::Ok().json(demo),
-///            Err(e) => {
-///                eprintln!("{}", e);
-///                HttpResponse::NotFound().body(res_str)
-///            }
-///            _ => HttpResponse::NotFound().body(res_str),
-///        }
-///    } else if query.demo_id.is_none() & query.cl_id.is_some() {
-///        match Demos::get_demo_by_cl_id(pool.get_ref(), query.cl_id.unwrap()).await {
-///            Ok(Some(demo)) => HttpResponse::Ok().json(demo),
-///            Err(e) => {
-///                eprintln!("{}", e);
-///                HttpResponse::NotFound().body(res_str)
-///            }
-///            _ => HttpResponse::NotFound().body(res_str),
-///        }
-///    } else {
-///        HttpResponse::BadRequest()
-///            .body("Neither a `cl_id` nor a `demo_id` was provided to search on.")
-///    }
-///}
-///
-////// POST endpoint to upload a new demo changelog entry. Returns the new demo ID.
-//////
-////// ## Note: **DOES NOT HANDLE ACTUAL DEMO FILES**
-//////
-////// ## Parameters:
-////// - `file_id`           
-//////     - **Required** - `String` : ID for the player.
-////// - `cl_id`
-//////     - **Required** - `i64` : The associated changelog entry ID.
-////// - `parsed_successfully`
-//////     - **Required** - `bool` : If the demo was successfully parsed, outside posts should be false.
-////// - `partner_name`           
-//////     - **Optional** - `String` : Name of the partner (used for legacy demo reasons)
-////// - `sar_version`           
-//////     - **Optional** - `String` : Version of SAR used.
-//////
-////// ## Example endpoint:       
-////// - `/api/v1/demos`
-//////
-////// Makes a call to the underlying [Demos::insert_demo]
-//////
-////// ## Example JSON input string:
-////// ```json
-////// {
-//////     "file_id": "TripleLaser_1053_76561198003223063_1.dem",
-//////     "partner_name": null,
-//////     "parsed_successfully": true,
-//////     "sar_version": null,
-//////     "cl_id": 8513,
-//////     "updated": null
-////// }
-////// ```
-//////
-////// ## Example JSON input response:
-////// ```json
-////// 1252
-////// ```
-///#[post("/demos")]
-///pub async fn demos_add(pool: web::Data<PgPool>, demo: web::Json<DemoInsert>) -> impl Responder {
-///    match Demos::insert_demo(pool.get_ref(), demo.into_inner()).await {
-///        Ok(demo_id) => HttpResponse::Ok().json(demo_id),
-///        Err(e) => {
-///            eprintln!("Error uploading demo -> {e}");
-///            HttpResponse::InternalServerError().body("Could not add new demo")
-///        }
-///    }
-///}
-///
-/////  a. Handle renaming/db interactions (update demo table/specific time that is being uploaded)
-/////  b. Pass to backblaze
-/////  c. Look to see if there is anything special needed for auto-submit
-/////  d. Integrate Parsing
-///// Code Reference: https://github.com/Ujang360/actix-multipart-demo/blob/main/src/main.rs
-///// TODO: Allow for sar version or partner name?
-////// Accepts field values for both a changelog, and a demo file.
-////// ## Expects the following fields:
-//////
-////// **Required Parameters**: timestamp, profile_number, score, map_id
-//////
-////// **Optional Parameters**: youtube_id, note, cat_id
-//////
-////// ## Parameters:
-//////
-////// - **timestamp**    
-//////     - `String`: `%Y-%m-%d %H:%M:%S` (use `%20` to denote a space)
-////// - **profile_number**
-//////     - `String`: Steam ID Number
-////// - **score**         
-//////     - `i32`: Current board time format         
-////// - **map_id**       
-//////     - `String`: Steam ID for the map
-////// - **youtube_id**
-//////     - `String`: Youtube URL Extension.
-////// - **note**          
-//////     - `String`: Note for the run
-////// - **category_id**   
-//////     - `i32`: ID for the category being played  
-////// - `game_id`
-//////     - **Optional** - `i32` : The ID for the game, defaults to the base game (id = 1).
-//////
-////// ## Example endpoints:       
-////// - `/api/v1/demos/changelog?timestamp=2020-08-18%2024:60:60&profile_number=76561198040982247&score=1763&map_id=47763`
-//////
-///#[post("/demos/changelog")]
-///pub async fn demos_changelog(
-///    mut payload: Multipart,
-///    config: web::Data<Config>,
-///    query: web::Query<SubmissionChangelog>,
-///    cache: web::Data<CacheState>,
-///    pool: web::Data<PgPool>,
-///) -> impl Responder {
-///    // This function heavily utilizes helper functions to make error propagation easier, and reduce the # of match arms
-///    let config = config.into_inner();
-///    let mut file_name = String::default();
-///    let changelog_insert = match get_valid_changelog_insert(pool.get_ref(), &config, &cache.into_inner(), query.into_inner()).await {
-///        Ok(insert) => insert,
-///        Err(e) => {
-///            eprintln!("Error validating changelog -> {e}");
-///            return HttpResponse::UnprocessableEntity().body("Could not validate changelog entry.")
-///        }
-///    };
-///    match parse_and_write_multipart(&mut payload, &mut file_name).await {
-///        Ok(_) => (),
-///        Err(e) => {
-///            eprintln!("Error parsing or writing the file. -> {}", e);
-///            return HttpResponse::BadRequest().body("Error parsing or write the file.");
-///        }
-///    }
-///    // Add Changelog/Demo entries to database.
-///    match add_to_database(pool.get_ref(), changelog_insert, &config, &file_name, true).await {
-///        Ok((cl_id, demo_id)) => HttpResponse::Ok().json((cl_id, demo_id)),
-///        Err(e) => {
-///            eprintln!("Error with adding changelog/demo insert -> {}", e);
-///            HttpResponse::InternalServerError()
-///                .body("Failed updating demo/changelog entries to database.")
-///        }
-///    }
-///}
-///
-///// Different demo entries can have the same changelog ID, but a changelog entry should only have the most recent, valid demo_id.
-////// DELETE endpoint to remove a demo from both backbalze and the database.
-////// ## Expects **one** of the two parametes
-//////
-////// ***Note***: If both, or neither parameter is provided you will encounter errors.
-////// If you want to delete the demo associated with a changelog entry, use the changelog entry.
-//////
-////// Parameters: demo_id, cl_id
-//////
-////// ## Parameters:
-//////
-////// - **demo_id**    
-//////     - `i64`: ID for a demo entry in the db, use this if you want to delete a specifc demo.
-////// - **cl_id**
-//////     - `i64`: ID for a changelog entry, use this if you want to delete the demo associated with a changelog entry.
-//////
-////// ## Example endpoints:       
-////// - `/api/v1/demos?cl_id=15625`
-////// - `/api/v1/demos?demo_id=12651`
-///#[delete("/demos")]
-///pub async fn demos_delete(
-///    query: web::Query<DemoOptions>,
-///    config: web::Data<Config>,
-///    pool: web::Data<PgPool>,
-///) -> impl Responder {
-///    let query = query.into_inner();
-///    let (cl, demo_id) = match get_changelog_and_demo_id(query, pool.get_ref()).await {
-///        Ok((cl, demo_id)) => (cl, demo_id),
-///        Err(e) => {
-///            eprintln!("{}", e);
-///            return HttpResponse::NotFound()
-///                .body("Cannot find changelog and demo associated with provided information");
-///        }
-///    };
-///    match delete_demo_file(pool.get_ref(), &config.into_inner(), cl, demo_id).await {
-///        Ok(_) => match delete_demo_db(pool.get_ref(), demo_id).await {
-///            Ok(_) => HttpResponse::Ok().body("Demo file and entry succesfully removed."),
-///            Err(e) => {
-///                eprintln!("{}", e);
-///                HttpResponse::InternalServerError().body("Error deleting demo entry from database")
-///            }
-///        },
-///        Err(e) => {
-///            eprintln!("{}", e);
-///            HttpResponse::InternalServerError().body("Error deleting file from backblaze.")
-///        }
-///    }
-///}
-///
-////// Adds a demo and changelog insert to the database.
-//////
-////// The debug value passed will remove the added changelog/demo entries inserted, and skip uploading the file for quicker debugging.
-///async fn add_to_database(
-///    pool: &PgPool,
-///    changelog_insert: ChangelogInsert,
-///    config: &Config,
-///    file_name: &str,
-///    debug: bool,
-///) -> Result<(i64, i64)> {
-///    let mut demo_insert = DemoInsert::default();
-///    let cl_id = Changelog::insert_changelog(pool, changelog_insert).await?;
-///    demo_insert.cl_id = cl_id;
-///    // TODO: How do we want demo files named?
-///    let file_id = if !debug {
-///        upload_demo(config, file_name).await?
-///    } else {
-///        Some(format!("{}.dem", file_name))
-///    };
-///    // Delete Demo
-///    remove_file(format!("./demos/{}", file_name))?;
-///    if let Some(file_id) = file_id {
-///        demo_insert.file_id = file_id;
-///    }
-///    // Add demo entry to database.
-///    let demo_id = Demos::insert_demo(pool, demo_insert).await?;
-///    // Update changelog to have the new demo_id
-///    Changelog::update_demo_id_in_changelog(pool, cl_id, demo_id).await?;
-///    if debug {
-///        Changelog::delete_changelog(pool, cl_id).await?;
-///        Demos::delete_demo(pool, demo_id).await?;
-///    }
-///    Ok((cl_id, demo_id))
-///}
-///
-////// Helper function that handles parsing the multipart and writing the file out locally
-///async fn parse_and_write_multipart(payload: &mut Multipart, file_name: &mut String) -> Result<()> {
-///    while let Ok(Some(mut field)) = payload.try_next().await {
-///        let mut content_data = Vec::new();
-///        while let Some(Ok(chunk)) = field.next().await {
-///            content_data.extend(chunk);
-///        }
-///        let fname = field.content_disposition().get_filename();
-///
-///        if let Some(fname) = fname {
-///            use std::fs;
-///            fs::create_dir_all("./demos")?;
-///            let mut file = OpenOptions::new()
-///                .create(true)
-///                .write(true)
-///                .open(format!("./demos/{}", fname))?;
-///            file.write_all(&content_data)?;
-///            *file_name = fname.to_string();
-///            // TODO: Parse Demo
-///        }
-///    }
-///    Ok(())
-///}
-///
-////// Returns a client, and an authenticated session for use with backblaze.
-///async fn b2_client_and_auth(config: &Config) -> Result<(reqwest::Client, B2Auth)> {
-///    let client = reqwest::ClientBuilder::new().build()?;
-///    let auth = b2_authorize_account(
-///        &client,
-///        format!("{}:{}", config.backblaze.keyid, config.backblaze.key),
-///    )
-///    .await
-///    .unwrap();
-///    Ok((client, auth))
-///}
-///
-////// Handles uploading the demo file.
-///async fn upload_demo(config: &Config, file_name: &str) -> Result<Option<String>> {
-///    // Ref: https://docs.rs/raze/0.4.1/raze/api/fn.b2_authorize_account.html
-///    let (client, auth) = b2_client_and_auth(config).await.unwrap();
-///
-///    let upload_auth = b2_get_upload_url(&client, &auth, config.backblaze.bucket.clone())
-///        .await
-///        .unwrap();
-///    let file = tokio::fs::File::open(format!("./demos/{}", file_name))
-///        .await
-///        .unwrap();
-///    let metadata = file.metadata().await.unwrap();
-///    let size = metadata.len();
-///    let modf = metadata
-///        .modified()
-///        .unwrap()
-///        .duration_since(std::time::UNIX_EPOCH)
-///        .unwrap()
-///        .as_secs()
-///        * 1000;
-///
-///    let param = FileParameters {
-///        file_path: file_name,
-///        file_size: size,
-///        content_type: None,
-///        content_sha1: Sha1Variant::HexAtEnd,
-///        last_modified_millis: modf,
-///    };
-///
-///    let stream = reader_to_stream(file);
-///    let stream = BytesStreamHashAtEnd::wrap(stream);
-///    let stream = BytesStreamThrottled::wrap(stream, 500000000);
-///
-///    let body = reqwest::Body::wrap_stream(stream);
-///    let resp1 = b2_upload_file(&client, &upload_auth, body, param)
-///        .await
-///        .unwrap();
-///    Ok(resp1.file_id)
-///}
-///
-////// Takes in either a demo_id or a changelog_id, and returns a changelog entry and a demno_id.
-//////
-////// We return a demo_id because there is a chance that there are multiple demos uploaded for the same changelog entry,
-////// and we might want to delete an older demo.
-///async fn get_changelog_and_demo_id(query: DemoOptions, pool: &PgPool) -> Result<(Changelog, i64)> {
-///    if let Some(cl_id) = query.cl_id {
-///        // Find the demo_id currently associated with the changelog entry.
-///        let changelog = Changelog::get_changelog(pool, cl_id).await?;
-///        if let Some(cl) = changelog {
-///            match cl.demo_id {
-///                Some(demo_id) => Ok((cl, demo_id)),
-///                None => bail!("Changelog does not have a demo_id"),
-///            }
-///        } else {
-///            bail!("No changelog entry found to match changelog_id")
-///        }
-///    } else if let Some(d_id) = query.demo_id {
-///        let d = Demos::get_demo(pool, d_id).await?;
-///        if let Some(d) = d {
-///            let changelog = Changelog::get_changelog(pool, d.cl_id).await?;
-///            if let Some(cl) = changelog {
-///                Ok((cl, d_id))
-///            } else {
-///                bail!("Changelog entry referenced by demo does not exist")
-///            }
-///        } else {
-///            bail!("No demo found")
-///        }
-///    } else {
-///        bail!("Neither a demo or changelog ID was supplied")
-///    }
-///}
-///
-////// Deletes the demo from backblaze.
-///async fn delete_demo_file(
-///    pool: &PgPool,
-///    config: &Config,
-///    cl: Changelog,
-///    demo_id: i64,
-///) -> Result<()> {
-///    let (client, auth) = b2_client_and_auth(config).await.unwrap();
-///    let d = Demos::get_demo(pool, demo_id).await.unwrap().unwrap();
-///    let file_name = generate_file_name(pool, cl).await?;
-///    match b2_delete_file_version(&client, &auth, file_name, d.file_id).await {
-///        Ok(_) => Ok(()),
-///        Err(e) => {
-///            eprintln!("Failed to delete file -> {:#?}", e);
-///            bail!("Failed to delete file from BackBlaze");
-///        }
-///    }
-///}
-///
-////// Once the file has been removed, delete the demo entry.
-///async fn delete_demo_db(pool: &PgPool, demo_id: i64) -> std::result::Result<Demos, sqlx::Error> {
-///    // Delete references to the demo_id in the changelog table.
-///    Changelog::delete_references_to_demo(pool, demo_id).await?;
-///    // Delete the demo entry.
-///    Demos::delete_demo(pool, demo_id).await
-///}
-///
-////// Create file_name
-///async fn generate_file_name(pool: &PgPool, cl: Changelog) -> Result<String> {
-///    let mut map_name = Maps::get_map_name(pool, cl.map_id).await?.unwrap();
-///    map_name.retain(|c| !c.is_whitespace());
-///    Ok(format!("{}_{}_{}", map_name, cl.score, cl.profile_number))
-///}
+#[utoipa::path(
+    tag = "demos",
+    params(DemoOptions),
+    responses((status = 200, body = Demos), (status = 404, description = "The demo could not be found")),
+)]
+#[get("/demos")]
+pub async fn demos(pool: web::Data<PgPool>, query: web::Query<DemoOptions>) -> error::Result<impl Responder> {
+    let query = query.into_inner();
+    let res = match (query.demo_id, query.cl_id) {
+        (Some(demo_id), None) => Demos::get_demo(pool.get_ref(), demo_id).await,
+        (None, Some(cl_id)) => Demos::get_demo_by_cl_id(pool.get_ref(), cl_id).await,
+        _ => {
+            return Err(ServerError::new(
+                ErrorType::BadRequest,
+                "Neither a `cl_id` nor a `demo_id` was provided to search on.",
+            ))
+        }
+    };
+    match res {
+        Ok(Some(demo)) => Ok(web::Json(demo)),
+        Ok(None) => Err(ServerError::new(ErrorType::NotFound, "Could not find demo.")),
+        Err(e) => {
+            eprintln!("{}", e);
+            Err(ServerError::new(ErrorType::NotFound, "Could not find demo."))
+        }
+    }
+}
+
+/// GET endpoint to return every demo uploaded for a changelog entry, newest first.
+///
+/// The changelog's `demo_id` always points at the first (newest) entry.
+///
+/// **Required Parameters**: cl_id
+///
+/// ## Parameters:
+///
+/// - **cl_id**    
+///     - `i64`: ID for a changelog entry.
+///
+/// ## Example endpoints:       
+/// - `/api/v1/demos/all?cl_id=15625`
+///
+/// Makes a call to the underlying [Demos::get_all_demos_by_cl_id]
+///
+/// ## Example JSON output:
+/// ```json
+/// [
+///     {
+///         "id": 1253,
+///         "file_id": "TripleLaser_1053_76561198003223063_2.dem",
+///         "partner_name": null,
+///         "parsed_successfully": true,
+///         "sar_version": null,
+///         "cl_id": 8513,
+///         "updated": null
+///     },...]
+/// ```
+#[utoipa::path(tag = "demos", params(DemoOptions), responses((status = 200, body = Vec<Demos>)))]
+#[get("/demos/all")]
+pub async fn demos_all(pool: web::Data<PgPool>, query: web::Query<DemoOptions>) -> error::Result<impl Responder> {
+    match query.cl_id {
+        Some(cl_id) => Ok(web::Json(Demos::get_all_demos_by_cl_id(pool.get_ref(), cl_id).await?)),
+        None => Err(ServerError::new(ErrorType::BadRequest, "No `cl_id` was provided to search on.")),
+    }
+}
+
+/// POST endpoint to upload a new demo changelog entry. Returns the new demo ID.
+///
+/// The associated changelog entry is updated to point at the new demo.
+///
+/// Requires an auth token, see [AuthUser]. Users can only add demos to their own scores.
+///
+/// ## Note: **DOES NOT HANDLE ACTUAL DEMO FILES**
+///
+/// ## Parameters:
+/// - `file_id`           
+///     - **Required** - `String` : ID for the player.
+/// - `cl_id`
+///     - **Required** - `i64` : The associated changelog entry ID.
+/// - `parsed_successfully`
+///     - **Required** - `bool` : If the demo was successfully parsed, outside posts should be false.
+/// - `partner_name`           
+///     - **Optional** - `String` : Name of the partner (used for legacy demo reasons)
+/// - `sar_version`           
+///     - **Optional** - `String` : Version of SAR used.
+///
+/// ## Example endpoint:       
+/// - `/api/v1/demos`
+///
+/// Makes a call to the underlying [Demos::insert_demo]
+///
+/// ## Example JSON input string:
+/// ```json
+/// {
+///     "file_id": "TripleLaser_1053_76561198003223063_1.dem",
+///     "partner_name": null,
+///     "parsed_successfully": true,
+///     "sar_version": null,
+///     "cl_id": 8513,
+///     "updated": null
+/// }
+/// ```
+///
+/// ## Example JSON input response:
+/// ```json
+/// 1252
+/// ```
+#[utoipa::path(tag = "demos", responses((status = 200, body = i64)))]
+#[post("/demos")]
+pub async fn demos_add(
+    auth: AuthUser,
+    pool: web::Data<PgPool>,
+    demo: web::Json<DemoInsert>,
+) -> error::Result<impl Responder> {
+    let cl_id = demo.cl_id;
+    match Changelog::get_changelog(pool.get_ref(), cl_id).await? {
+        Some(cl) => auth.check_submitter(&cl.profile_number)?,
+        None => return Err(ServerError::new(ErrorType::NotFound, format!("No changelog entry with id {}", cl_id))),
+    }
+    let res = match Demos::insert_demo(pool.get_ref(), demo.into_inner()).await {
+        Ok(demo_id) => Changelog::update_demo_id_in_changelog(pool.get_ref(), cl_id, demo_id)
+            .await
+            .map(|_| demo_id),
+        Err(e) => Err(e),
+    };
+    match res {
+        Ok(demo_id) => Ok(web::Json(demo_id)),
+        Err(e) => {
+            eprintln!("Error uploading demo -> {e}");
+            Err(ServerError::new(ErrorType::Internal, "Could not add new demo"))
+        }
+    }
+}
+
+//  a. Handle renaming/db interactions (update demo table/specific time that is being uploaded)
+//  b. Pass to storage
+//  c. Look to see if there is anything special needed for auto-submit
+//  d. Integrate Parsing
+// Code Reference: https://github.com/Ujang360/actix-multipart-demo/blob/main/src/main.rs
+// TODO: Allow for sar version or partner name?
+/// Accepts field values for both a changelog, and a demo file.
+/// ## Expects the following fields:
+///
+/// **Required Parameters**: timestamp, profile_number, score, map_id
+///
+/// **Optional Parameters**: youtube_id, note, cat_id
+///
+/// ## Parameters:
+///
+/// - **timestamp**    
+///     - `String`: `%Y-%m-%d %H:%M:%S` (use `%20` to denote a space). An invalid or future timestamp returns a 422.
+/// - **profile_number**
+///     - `String`: Steam ID Number
+/// - **score**         
+///     - `i32`: Current board time format         
+/// - **map_id**       
+///     - `String`: Steam ID for the map
+/// - **youtube_id**
+///     - `String`: Youtube URL Extension.
+/// - **note**          
+///     - `String`: Note for the run
+/// - **category_id**   
+///     - `i32`: ID for the category being played  
+/// - `game_id`
+///     - **Optional** - `i32` : The ID for the game, defaults to the base game (id = 1).
+/// - `dry_run`
+///     - **Optional** - `bool` : Validate the score and demo without saving anything. The demo is not uploaded, and the entries
+///       that would have been created are returned instead, see [DemoSubmissionPreview]. Retried uploads are not detected in a dry run.
+///
+/// ## Headers:
+///
+/// - **X-Demo-Sha1**
+///     - **Optional** - `String`: Hex encoded SHA1 of the demo file. When sent, the upload is idempotent, if the player already has
+///       a demo with the same SHA1 on the map the existing `(cl_id, demo_id)` is returned instead of creating new entries.
+///       Returns a 400 if it does not match the uploaded file.
+///
+/// The demo must be a `.dem` file that starts with the demo header, anything else returns a 415. Uploads larger than
+/// `DEMOS.MAX_BYTES` return a 413 as soon as the limit is passed.
+///
+/// Requires an auth token, see [AuthUser]. Users can only submit their own scores.
+///
+/// Submissions are rate limited for each `profile_number` and each client address, see [RateLimiter] and [client_id].
+/// Retried uploads that are found by their SHA1 are not limited.
+///
+/// Makes a call to the underlying [Demos::get_demo_by_sha1]
+///
+/// ## Example endpoints:       
+/// - `/api/v1/demos/changelog?timestamp=2020-08-18%2014:32:10&profile_number=76561198040982247&score=1763&map_id=47763`
+/// - `/api/v1/demos/changelog?timestamp=2020-08-18%2014:32:10&profile_number=76561198040982247&score=1763&map_id=47763&dry_run=true`
+///
+#[utoipa::path(
+    tag = "demos",
+    params(SubmissionChangelog),
+    responses(
+        (status = 200, body = (i64, i64)),
+        (status = 413, description = "The demo is larger than `DEMOS.MAX_BYTES`"),
+        (status = 415, description = "The upload is not a demo file"),
+        (status = 422, description = "The score is not valid for the map"),
+    ),
+)]
+#[post("/demos/changelog")]
+#[allow(clippy::too_many_arguments)]
+pub async fn demos_changelog(
+    req: HttpRequest,
+    mut payload: Multipart,
+    config: web::Data<Config>,
+    query: web::Query<SubmissionChangelog>,
+    cache: web::Data<CacheState>,
+    metrics: web::Data<Metrics>,
+    limiter: web::Data<RateLimiter>,
+    storage: web::Data<dyn DemoStorage>,
+    pool: web::Data<PgPool>,
+    auth: AuthUser,
+) -> error::Result<impl Responder> {
+    // This function heavily utilizes helper functions to make error propagation easier, and reduce the # of match arms
+    let config = config.into_inner();
+    let query = query.into_inner();
+    auth.check_submitter(&query.profile_number)?;
+    let dry_run = query.dry_run.unwrap_or(false);
+    let mut file_name = String::default();
+    let client_sha1 = req
+        .headers()
+        .get(DEMO_SHA1_HEADER)
+        .and_then(|sha1| sha1.to_str().ok())
+        .map(|sha1| sha1.to_ascii_lowercase());
+    // A retried upload would fail validation against the score it already created, so check for it first.
+    if let Some(sha1) = client_sha1.as_ref().filter(|_| !dry_run) {
+        if let Some(existing) = Demos::get_demo_by_sha1(pool.get_ref(), sha1, &query.profile_number, &query.map_id).await? {
+            return Ok(HttpResponse::Ok().json((existing.cl_id, existing.id)));
+        }
+    }
+    // The client is checked first, so a client over its limit cannot use up the player's submissions.
+    limiter.check(&client_id(&req, &config.trusted_proxies)).await?;
+    limiter.check(&query.profile_number).await?;
+    let changelog_insert = match get_valid_changelog_insert(pool.get_ref(), &config, &cache.into_inner(), query).await {
+        Ok(insert) => insert,
+        Err(e) => {
+            eprintln!("Error validating changelog -> {e}");
+            // Keep the message for invalid fields (e.g. the timestamp), so the client knows what to fix.
+            return match e.downcast::<ServerError>() {
+                Ok(e) if e.error_type == ErrorType::Unprocessable => Err(e),
+                _ => Err(ServerError::new(ErrorType::Unprocessable, "Could not validate changelog entry.")),
+            };
+        }
+    };
+    let metadata = match parse_and_write_multipart(&mut payload, &mut file_name, config.demos.max_bytes).await {
+        Ok(metadata) => metadata,
+        Err(e) => {
+            eprintln!("Error parsing or writing the file. -> {}", e);
+            // Oversized, non-demo and multi-file uploads keep their status, so the client knows not to retry them as is.
+            return match e.downcast::<ServerError>() {
+                Ok(e) if matches!(e.error_type, ErrorType::PayloadTooLarge | ErrorType::UnsupportedMediaType | ErrorType::BadRequest) => {
+                    Err(e)
+                }
+                _ => Err(ServerError::new(ErrorType::BadRequest, "Error parsing or write the file.")),
+            };
+        }
+    };
+    if !demo_matches_map(&metadata, &changelog_insert.map_id) {
+        eprintln!("Demo was recorded on {}, but submitted for {}", metadata.map_name, changelog_insert.map_id);
+        let _ = remove_file(format!("./demos/{}", file_name));
+        return Err(ServerError::new(ErrorType::Unprocessable, "Demo was not recorded on the submitted map."));
+    }
+    let header = demo_header(&metadata);
+    let demo_insert = DemoInsert {
+        parsed_successfully: true,
+        sar_version: header.sar_version,
+        map_name: Some(header.map_name),
+        tick_count: Some(header.tick_count),
+        playback_time: Some(header.playback_time),
+        protocol: Some(header.protocol),
+        ..Default::default()
+    };
+    if dry_run {
+        return preview_submission(storage.get_ref(), changelog_insert, demo_insert, metadata, &file_name, client_sha1);
+    }
+    // Add Changelog/Demo entries to database.
+    let upload = DemoUpload { config: &config, storage: storage.get_ref(), metrics: &metrics };
+    match add_to_database(pool.get_ref(), changelog_insert, demo_insert, upload, &file_name, client_sha1, false).await {
+        Ok((cl_id, demo_id)) => Ok(HttpResponse::Ok().json((cl_id, demo_id))),
+        Err(e) if e.error_type == ErrorType::BadRequest => Err(e),
+        Err(e) => {
+            eprintln!("Error with adding changelog/demo insert -> {}", e);
+            Err(ServerError::new(
+                ErrorType::Internal,
+                "Failed updating demo/changelog entries to database.",
+            ))
+        }
+    }
+}
+
+/// GET endpoint to return the demos for both players of a bundled coop run.
+///
+/// Either side is `null` if that player has not uploaded a demo, each demo can then be downloaded with [demos_download].
+///
+/// **Required Parameters**: coop_id
+///
+/// ## Parameters:
+///
+/// - **coop_id**
+///     - `i64`: ID for the coop bundle, `coop_id` on the coop map page.
+///
+/// ## Example endpoints:
+/// - `/api/v1/demos/coop?coop_id=8473`
+///
+/// Makes a call to the underlying [Demos::get_coop_demos]
+///
+/// ## Example JSON output:
+/// ```json
+/// {
+///     "coop_id": 8473,
+///     "demo_id1": 24527,
+///     "demo_id2": null,
+///     "file_id1": "4_Zd3b1c8a6b5e5b12a7c5a0e18_f1171b0b7a7d6e5d1_d20211104_m021845_c002_v0001146_t0053",
+///     "file_id2": null
+/// }
+/// ```
+#[utoipa::path(tag = "demos", params(CoopDemoParams), responses((status = 200, body = CoopDemos)))]
+#[get("/demos/coop")]
+pub async fn demos_coop(pool: web::Data<PgPool>, query: web::Query<CoopDemoParams>) -> error::Result<impl Responder> {
+    match Demos::get_coop_demos(pool.get_ref(), query.coop_id).await? {
+        Some(coop_demos) => Ok(web::Json(coop_demos)),
+        None => Err(ServerError::new(
+            ErrorType::NotFound,
+            format!("No coop bundle with id {}", query.coop_id),
+        )),
+    }
+}
+
+/// GET endpoint to download a demo file from storage.
+/// ## Expects **one** of following fields:
+///
+/// **Required Parameters**: cl_id, demo_id
+///
+/// ## Parameters:
+///
+/// - **cl_id**    
+///     - `i64`: ID for a changelog entry, will download the demo currently associated with that changelog entry.
+/// - **demo_id**
+///     - `i64`: ID for a specific demo.
+///
+/// ## Example endpoints:       
+/// - `/api/v1/demos/download?cl_id=15625`
+/// - `/api/v1/demos/download?demo_id=12651`
+///
+/// The file is sent back as an attachment, named `{map_name}_{score}_{profile_number}.dem`.
+///
+/// If a SHA1 was recorded when the demo was uploaded the whole file is downloaded and hashed before anything is sent, and a
+/// `502` is returned if it does not match. Demos without a SHA1 are streamed from storage as they are sent, see
+/// [DemoStorage::download_stream].
+#[utoipa::path(
+    tag = "demos",
+    params(DemoOptions),
+    responses(
+        (status = 200, content_type = "application/octet-stream", description = "The demo file"),
+        (status = 502, description = "The stored file does not match the SHA1 recorded on upload"),
+    ),
+)]
+#[get("/demos/download")]
+pub async fn demos_download(
+    query: web::Query<DemoOptions>,
+    storage: web::Data<dyn DemoStorage>,
+    pool: web::Data<PgPool>,
+) -> error::Result<impl Responder> {
+    let not_found = || ServerError::new(ErrorType::NotFound, "Cannot find demo associated with provided information");
+    let (cl, demo) = match get_changelog_and_demo_id(query.into_inner(), pool.get_ref()).await {
+        Ok((cl, demo_id)) => match Demos::get_demo(pool.get_ref(), demo_id).await {
+            Ok(Some(demo)) => (cl, demo),
+            _ => return Err(not_found()),
+        },
+        Err(e) => {
+            eprintln!("{}", e);
+            return Err(not_found());
+        }
+    };
+    let file_name = match generate_file_name(pool.get_ref(), &cl.map_id, cl.score, &cl.profile_number).await {
+        Ok(file_name) => file_name,
+        Err(e) => {
+            eprintln!("Error generating demo file name -> {}", e);
+            return Err(ServerError::new(ErrorType::Internal, "Error generating demo file name."));
+        }
+    };
+    let download_error = |e: anyhow::Error| {
+        eprintln!("Error downloading demo from storage -> {}", e);
+        ServerError::new(ErrorType::Internal, "Error downloading demo from storage.")
+    };
+    let contents = match demo.sha1.as_deref() {
+        Some(expected) => {
+            let contents = storage.download(StoredFile::from(&demo)).await.map_err(download_error)?;
+            let actual = content_sha1(&contents);
+            if actual != expected {
+                eprintln!("Demo {} is corrupted, expected SHA1 {} but found {}", demo.id, expected, actual);
+                return Err(ServerError::new(
+                    ErrorType::BadGateway,
+                    "The stored demo file does not match the SHA1 recorded on upload, the file may be corrupted.",
+                ));
+            }
+            stream::once(future::ok(Bytes::from(contents))).boxed()
+        }
+        None => storage.download_stream(StoredFile::from(&demo)).await.map_err(download_error)?,
+    };
+    let demo_id = demo.id;
+    let contents = contents.inspect_err(move |e| eprintln!("Error sending demo {} -> {}", demo_id, e));
+    Ok(HttpResponse::Ok()
+        .content_type("application/octet-stream")
+        .insert_header((
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{}\"", file_name),
+        ))
+        .streaming(contents))
+}
+
+/// GET endpoint to check a stored demo file against the SHA1 recorded when it was uploaded.
+///
+/// The file is hashed on the server, nothing is sent to the client but the result.
+///
+/// **Required Parameters**: demo_id
+///
+/// ## Parameters:
+///
+/// - **demo_id**
+///     - `i64`: ID for a specific demo.
+///
+/// ## Example endpoints:       
+/// - `/api/v1/demos/verify?demo_id=12651`
+///
+/// Makes a call to the underlying [DemoStorage::sha1]
+///
+/// ## Example JSON output:
+/// ```json
+/// {
+///     "demo_id": 12651,
+///     "expected_sha1": "a9993e364706816aba3e25717850c26c9cd0d89d",
+///     "actual_sha1": "a9993e364706816aba3e25717850c26c9cd0d89d",
+///     "matches": true
+/// }
+/// ```
+#[utoipa::path(tag = "demos", params(DemoOptions), responses((status = 200, body = DemoVerification)))]
+#[get("/demos/verify")]
+pub async fn demos_verify(
+    query: web::Query<DemoOptions>,
+    storage: web::Data<dyn DemoStorage>,
+    pool: web::Data<PgPool>,
+) -> error::Result<impl Responder> {
+    let demo_id = match query.demo_id {
+        Some(demo_id) => demo_id,
+        None => return Err(ServerError::new(ErrorType::BadRequest, "No `demo_id` was provided to verify.")),
+    };
+    let demo = match Demos::get_demo(pool.get_ref(), demo_id).await? {
+        Some(demo) => demo,
+        None => return Err(ServerError::new(ErrorType::NotFound, "Could not find demo.")),
+    };
+    match storage.sha1(StoredFile::from(&demo)).await {
+        Ok(actual_sha1) => Ok(web::Json(DemoVerification {
+            demo_id,
+            matches: demo.sha1.as_ref().map(|expected| *expected == actual_sha1),
+            expected_sha1: demo.sha1,
+            actual_sha1,
+        })),
+        Err(e) => {
+            eprintln!("Error hashing demo from storage -> {}", e);
+            Err(ServerError::new(ErrorType::Internal, "Error downloading demo from storage."))
+        }
+    }
+}
+
+/// GET endpoint to return the map, length and SAR information parsed from a demo, without downloading the demo.
+///
+/// Older demos uploaded before the header was stored are downloaded and parsed on the first request, and the result is stored.
+///
+/// **Required Parameters**: demo_id
+///
+/// ## Parameters:
+///
+/// - **demo_id**
+///     - `i64`: ID for a specific demo.
+///
+/// ## Example endpoints:
+/// - `/api/v1/demos/metadata?demo_id=12651`
+///
+/// Makes a call to the underlying [Demos::get_demo_header]
+///
+/// ## Example JSON output:
+/// ```json
+/// {
+///     "map_name": "sp_a2_laser_vs_turret",
+///     "tick_count": 1060,
+///     "playback_time": 17.666666,
+///     "sar_version": "1234ABCD",
+///     "protocol": 4
+/// }
+/// ```
+#[utoipa::path(tag = "demos", params(DemoOptions), responses((status = 200, body = DemoHeader)))]
+#[get("/demos/metadata")]
+pub async fn demos_metadata(
+    query: web::Query<DemoOptions>,
+    storage: web::Data<dyn DemoStorage>,
+    pool: web::Data<PgPool>,
+) -> error::Result<impl Responder> {
+    let demo_id = match query.demo_id {
+        Some(demo_id) => demo_id,
+        None => return Err(ServerError::new(ErrorType::BadRequest, "No `demo_id` was provided.")),
+    };
+    let header =
+        load_demo_header(pool.get_ref(), demo_id, |demo| async move { storage.download(StoredFile::from(&demo)).await }).await?;
+    Ok(web::Json(header))
+}
+
+/// Returns the stored [DemoHeader] for a demo, parsing the demo returned by `fetch` and storing its header if it has not been stored yet.
+pub async fn load_demo_header<F, Fut>(pool: &PgPool, demo_id: i64, fetch: F) -> error::Result<DemoHeader>
+where
+    F: FnOnce(Demos) -> Fut,
+    Fut: Future<Output = Result<Vec<u8>>>,
+{
+    if let Some(header) = Demos::get_demo_header(pool, demo_id).await? {
+        return Ok(header);
+    }
+    let demo = match Demos::get_demo(pool, demo_id).await? {
+        Some(demo) => demo,
+        None => return Err(ServerError::new(ErrorType::NotFound, "Could not find demo.")),
+    };
+    let contents = match fetch(demo).await {
+        Ok(contents) => contents,
+        Err(e) => {
+            eprintln!("Error downloading demo {} from storage -> {}", demo_id, e);
+            return Err(ServerError::new(ErrorType::Internal, "Error downloading demo from storage."));
+        }
+    };
+    let header = match parse_demo_bytes(&contents) {
+        Ok(metadata) => demo_header(&metadata),
+        Err(e) => {
+            eprintln!("Error parsing demo {} -> {}", demo_id, e);
+            return Err(ServerError::new(ErrorType::Unprocessable, "Demo could not be parsed."));
+        }
+    };
+    Demos::set_parsed(pool, demo_id, &header).await?;
+    Ok(header)
+}
+
+// Different demo entries can have the same changelog ID, but a changelog entry should only have the most recent, valid demo_id.
+/// DELETE endpoint to remove a demo from both backbalze and the database.
+/// ## Expects **one** of the two parametes
+///
+/// ***Note***: If both, or neither parameter is provided you will encounter errors.
+/// If you want to delete the demo associated with a changelog entry, use the changelog entry.
+/// Older demos can be removed with their `demo_id`, and if the changelog's current demo is removed it falls back to the newest remaining demo.
+/// If the file is already missing from storage the entry is still removed, failing to reach storage returns a 500.
+///
+/// Requires an auth token, see [AuthUser]. Users can only delete demos for their own scores.
+///
+/// Parameters: demo_id, cl_id
+///
+/// ## Parameters:
+///
+/// - **demo_id**    
+///     - `i64`: ID for a demo entry in the db, use this if you want to delete a specifc demo.
+/// - **cl_id**
+///     - `i64`: ID for a changelog entry, use this if you want to delete the demo associated with a changelog entry.
+///
+/// ## Example endpoints:       
+/// - `/api/v1/demos?cl_id=15625`
+/// - `/api/v1/demos?demo_id=12651`
+#[utoipa::path(tag = "demos", params(DemoOptions), responses((status = 200, body = String)))]
+#[delete("/demos")]
+pub async fn demos_delete(
+    auth: AuthUser,
+    query: web::Query<DemoOptions>,
+    storage: web::Data<dyn DemoStorage>,
+    pool: web::Data<PgPool>,
+) -> error::Result<impl Responder> {
+    let query = query.into_inner();
+    let (cl, demo_id) = match get_changelog_and_demo_id(query, pool.get_ref()).await {
+        Ok((cl, demo_id)) => (cl, demo_id),
+        Err(e) => {
+            eprintln!("{}", e);
+            return Err(ServerError::new(
+                ErrorType::NotFound,
+                "Cannot find changelog and demo associated with provided information",
+            ));
+        }
+    };
+    if !auth.is_admin() && cl.profile_number != auth.profile_number {
+        return Err(ServerError::new(ErrorType::Forbidden, "You can only delete demos for your own scores."));
+    }
+    let cl_id = cl.id;
+    match delete_demo_file(pool.get_ref(), storage.get_ref(), cl, demo_id).await {
+        Ok(_) => match delete_demo_db(pool.get_ref(), cl_id, demo_id).await {
+            Ok(_) => Ok(HttpResponse::Ok().body("Demo file and entry succesfully removed.")),
+            Err(e) => {
+                eprintln!("{}", e);
+                Err(ServerError::new(ErrorType::Internal, "Error deleting demo entry from database"))
+            }
+        },
+        Err(e) => {
+            eprintln!("Error deleting demo file -> {}", e);
+            Err(ServerError::new(ErrorType::Internal, "Error deleting file from storage."))
+        }
+    }
+}
+
+/// DELETE endpoint to remove every demo uploaded by a player from both storage and the database, typically after a ban.
+///
+/// A demo that fails to be removed from storage does not stop the others from being removed, it is kept in the database
+/// and reported in `failed`. Demos whose file is already missing from storage are removed. Requires an admin's auth token, see [AuthUser].
+///
+/// ## Parameters:
+///
+/// - **profile_number**
+///     - `String`: The player whose demos should be removed.
+///
+/// ## Example endpoints:
+/// - `/api/v1/demos/by_player?profile_number=76561198040982247`
+///
+/// Makes a call to the underlying [Demos::get_demos_by_profile] & [Demos::delete_demos]
+///
+/// ## Example JSON output
+/// ```json
+/// {
+///     "deleted": [36899, 36412],
+///     "failed": [24527]
+/// }
+/// ```
+#[utoipa::path(tag = "demos", params(DemoPlayerParams), responses((status = 200, body = DemoBulkDeletion)))]
+#[delete("/demos/by_player")]
+pub async fn demos_delete_by_player(
+    query: web::Query<DemoPlayerParams>,
+    storage: web::Data<dyn DemoStorage>,
+    pool: web::Data<PgPool>,
+    auth: AuthUser,
+) -> error::Result<impl Responder> {
+    auth.require_admin()?;
+    let pool = pool.get_ref();
+    let player_demos = Demos::get_demos_by_profile(pool, &query.profile_number).await?;
+    let mut summary = DemoBulkDeletion::default();
+    if player_demos.is_empty() {
+        return Ok(web::Json(summary));
+    }
+    let mut files = Vec::with_capacity(player_demos.len());
+    let mut file_demo_ids = Vec::with_capacity(player_demos.len());
+    for demo in &player_demos {
+        match demo_file_name(pool, demo).await {
+            Ok(file_name) => {
+                files.push((StoredFile::from(demo), file_name));
+                file_demo_ids.push(demo.id);
+            }
+            Err(e) => {
+                eprintln!("Error finding the file name for demo {} -> {}", demo.id, e);
+                summary.failed.push(demo.id);
+            }
+        }
+    }
+    for (demo_id, result) in file_demo_ids.into_iter().zip(storage.delete_all(files).await) {
+        match ignore_missing_file(result, demo_id) {
+            Ok(_) => summary.deleted.push(demo_id),
+            Err(e) => {
+                eprintln!("Error deleting demo {} from storage -> {}", demo_id, e);
+                summary.failed.push(demo_id);
+            }
+        }
+    }
+    if !summary.deleted.is_empty() {
+        Demos::delete_demos(pool, &summary.deleted).await?;
+    }
+    Ok(web::Json(summary))
+}
+
+/// Adds a demo and changelog insert to the database, `demo_insert` is expected to already have the parsed demo information.
+///
+/// If the client sent the demo's SHA1 it is checked against the uploaded file before anything is inserted.
+/// The local file is removed on every path, see [LocalUpload]. The demo is uploaded before the database entries are inserted in a single transaction, see [insert_uploaded_demo].
+///
+/// The debug value passed will roll back the changelog/demo entries inserted, and skip uploading the file for quicker debugging.
+/// New world records are announced on Discord, except in debug.
+async fn add_to_database(
+    pool: &PgPool,
+    changelog_insert: ChangelogInsert,
+    mut demo_insert: DemoInsert,
+    upload: DemoUpload<'_>,
+    file_name: &str,
+    client_sha1: Option<String>,
+    debug: bool,
+) -> error::Result<(i64, i64)> {
+    let DemoUpload { config, storage, metrics } = upload;
+    let _local = LocalUpload(file_name);
+    demo_insert.sha1 = Some(check_client_sha1(file_name, client_sha1)?);
+    // New demos always go to the active bucket, the bucket is kept with the demo so it can still be found if the active bucket changes.
+    demo_insert.bucket_id = storage.bucket();
+    // Stored under the canonical name, so deleting the demo later finds the same file.
+    let stored_file_name = generate_file_name(
+        pool,
+        &changelog_insert.map_id,
+        changelog_insert.score,
+        &changelog_insert.profile_number,
+    )
+    .await?;
+    let file_id = if !debug {
+        let start = std::time::Instant::now();
+        let file_id = storage.upload(&format!("./demos/{}", file_name), &stored_file_name).await?;
+        metrics.observe_backblaze_upload(start.elapsed());
+        Some(file_id)
+    } else {
+        Some(format!("{}.dem", file_name))
+    };
+    if let Some(file_id) = file_id.clone() {
+        demo_insert.file_id = file_id;
+    }
+    let bucket_id = demo_insert.bucket_id.clone();
+    let ids = insert_uploaded_demo(pool, changelog_insert.clone(), demo_insert, !debug, || async {
+        match file_id {
+            Some(file_id) if !debug => {
+                let file = StoredFile { file_id: &file_id, bucket_id: bucket_id.as_deref() };
+                storage.delete(file, &stored_file_name).await
+            }
+            _ => Ok(()),
+        }
+    })
+    .await?;
+    if !debug {
+        notify_if_wr(pool, config, &changelog_insert);
+    }
+    Ok(ids)
+}
+
+/// Returns the entries a submission would create without uploading the demo or touching the database, the local file is removed.
+///
+/// The client's SHA1 is still checked against the file, as it would be by [add_to_database].
+fn preview_submission(
+    storage: &dyn DemoStorage,
+    changelog: ChangelogInsert,
+    mut demo: DemoInsert,
+    metadata: DemoMetadata,
+    file_name: &str,
+    client_sha1: Option<String>,
+) -> error::Result<HttpResponse> {
+    let _local = LocalUpload(file_name);
+    demo.sha1 = Some(check_client_sha1(file_name, client_sha1)?);
+    demo.bucket_id = storage.bucket();
+    demo.file_id = file_name.to_string();
+    Ok(HttpResponse::Ok().json(DemoSubmissionPreview { changelog, demo, metadata }))
+}
+
+/// Computes the SHA1 of a locally written demo, and checks it against the SHA1 the client sent if there is one.
+fn check_client_sha1(file_name: &str, client_sha1: Option<String>) -> error::Result<String> {
+    let contents = std::fs::read(format!("./demos/{}", file_name))?;
+    let sha1 = content_sha1(&contents);
+    if let Some(client_sha1) = client_sha1 {
+        if client_sha1 != sha1 {
+            return Err(ServerError::new(
+                ErrorType::BadRequest,
+                format!("{} does not match the uploaded demo.", DEMO_SHA1_HEADER),
+            ));
+        }
+    }
+    Ok(sha1)
+}
+
+/// Inserts the changelog and demo entries for an uploaded demo in a single transaction, and points the changelog entry at the demo.
+/// The transaction is rolled back rather than committed if `commit` is false.
+///
+/// If any of the inserts fail, `cleanup` is run to remove the already uploaded file so no orphaned files are left in storage.
+/// A failed cleanup is logged, and the original error is returned.
+pub async fn insert_uploaded_demo<F, Fut>(
+    pool: &PgPool,
+    changelog_insert: ChangelogInsert,
+    demo_insert: DemoInsert,
+    commit: bool,
+    cleanup: F,
+) -> error::Result<(i64, i64)>
+where
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = Result<()>>,
+{
+    match insert_changelog_and_demo(pool, changelog_insert, demo_insert, commit).await {
+        Ok(ids) => Ok(ids),
+        Err(e) => {
+            if let Err(cleanup_err) = cleanup().await {
+                eprintln!("Failed to remove uploaded demo after a failed insert -> {}", cleanup_err);
+            }
+            Err(e.into())
+        }
+    }
+}
+
+/// Transaction for [insert_uploaded_demo].
+async fn insert_changelog_and_demo(
+    pool: &PgPool,
+    changelog_insert: ChangelogInsert,
+    mut demo_insert: DemoInsert,
+    commit: bool,
+) -> std::result::Result<(i64, i64), sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    let cl_id = Changelog::transaction_insert_changelog(&mut transaction, changelog_insert).await?.id;
+    demo_insert.cl_id = cl_id;
+    let demo_id = Demos::transaction_insert_demo(&mut transaction, demo_insert).await?;
+    Changelog::transaction_update_demo_id_in_changelog(&mut transaction, cl_id, demo_id).await?;
+    if commit {
+        transaction.commit().await?;
+    } else {
+        transaction.rollback().await?;
+    }
+    Ok((cl_id, demo_id))
+}
+
+/// Helper function that handles parsing the multipart and writing the file out locally, then parses the written demo.
+///
+/// The file is written under a name generated by the server, see [upload_file_name], the uploaded file name is only
+/// sanitized and checked for the `.dem` extension, see [sanitize_demo_file_name]. The local file is removed if the demo
+/// cannot be parsed.
+///
+/// Files that are not named `.dem` or do not start with the demo header return an [ErrorType::UnsupportedMediaType] error,
+/// files over `max_bytes` return an [ErrorType::PayloadTooLarge] error, see [write_stream_to_file]. Only one file can be
+/// uploaded, any more return an [ErrorType::BadRequest] error and the file already written is removed.
+pub async fn parse_and_write_multipart(payload: &mut Multipart, file_name: &mut String, max_bytes: u64) -> Result<DemoMetadata> {
+    while let Ok(Some(mut field)) = payload.try_next().await {
+        let fname = field
+            .content_disposition()
+            .and_then(|cd| cd.get_filename())
+            .map(|fname| fname.to_string());
+
+        match fname {
+            Some(_) if !file_name.is_empty() => {
+                let _ = remove_file(format!("./demos/{}", file_name));
+                file_name.clear();
+                return Err(ServerError::new(ErrorType::BadRequest, "Only one demo file can be uploaded.").into());
+            }
+            Some(fname) => {
+                let fname = sanitize_demo_file_name(&fname)?;
+                if !is_demo_file_name(&fname) {
+                    return Err(ServerError::new(ErrorType::UnsupportedMediaType, "Only .dem files can be uploaded.").into());
+                }
+                std::fs::create_dir_all("./demos")?;
+                let local_name = upload_file_name();
+                let path = format!("./demos/{}", local_name);
+                write_stream_to_file(&mut field, &path, max_bytes).await?;
+                if !file_has_demo_magic(&path)? {
+                    let _ = remove_file(&path);
+                    return Err(ServerError::new(ErrorType::UnsupportedMediaType, "The uploaded file is not a demo.").into());
+                }
+                *file_name = local_name;
+            }
+            // Fields without a file are drained and ignored.
+            None => while let Some(Ok(_)) = field.next().await {},
+        }
+    }
+    if file_name.is_empty() {
+        bail!("No demo file was included in the upload");
+    }
+    let path = format!("./demos/{}", file_name);
+    match parse_demo(&path) {
+        Ok(metadata) => Ok(metadata),
+        Err(e) => {
+            let _ = remove_file(&path);
+            bail!("Could not parse demo -> {e}")
+        }
+    }
+}
+
+/// Checks the file at `path` starts with the demo header magic bytes, see [has_demo_magic].
+fn file_has_demo_magic(path: &str) -> Result<bool> {
+    let mut magic = Vec::with_capacity(8);
+    std::fs::File::open(path)?.take(8).read_to_end(&mut magic)?;
+    Ok(has_demo_magic(&magic))
+}
+
+/// Writes each chunk to the file at `path` as it arrives, so an upload is never held in memory all at once.
+///
+/// Stops as soon as more than `max_bytes` have been received, the partly written file is removed and an
+/// [ErrorType::PayloadTooLarge] error is returned.
+///
+/// Returns the number of bytes written.
+pub async fn write_stream_to_file<S, E>(stream: &mut S, path: &str, max_bytes: u64) -> Result<u64>
+where
+    S: Stream<Item = std::result::Result<web::Bytes, E>> + Unpin,
+    E: std::fmt::Display,
+{
+    let mut file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .open(path)?;
+    let mut written = 0;
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.map_err(|e| anyhow::anyhow!("Error reading the upload -> {e}"))?;
+        if written + chunk.len() as u64 > max_bytes {
+            drop(file);
+            let _ = remove_file(path);
+            return Err(ServerError::new(
+                ErrorType::PayloadTooLarge,
+                format!("Demos can be at most {max_bytes} bytes."),
+            )
+            .into());
+        }
+        file.write_all(&chunk)?;
+        written += chunk.len() as u64;
+    }
+    Ok(written)
+}
+
+/// Downloads and parses up to `limit` unparsed demos with an id greater than `after_id`, marking each demo that parses as parsed.
+///
+/// `fetch` returns the contents of a demo. Demos that fail to download or parse are left unparsed, so a later job retries them.
+pub async fn reparse_demo_batch<F, Fut>(
+    pool: &PgPool,
+    after_id: i64,
+    limit: i64,
+    fetch: F,
+) -> std::result::Result<ReparseBatch, sqlx::Error>
+where
+    F: Fn(Demos) -> Fut,
+    Fut: Future<Output = Result<Vec<u8>>>,
+{
+    let unparsed = Demos::get_unparsed(pool, after_id, limit).await?;
+    let mut batch = ReparseBatch {
+        last_id: unparsed.last().map(|demo| demo.id),
+        ..Default::default()
+    };
+    for demo in unparsed {
+        let demo_id = demo.id;
+        match fetch(demo).await.and_then(|contents| parse_demo_bytes(&contents)) {
+            Ok(metadata) => {
+                Demos::set_parsed(pool, demo_id, &demo_header(&metadata)).await?;
+                batch.parsed += 1;
+            }
+            Err(e) => {
+                eprintln!("Error re-parsing demo {} -> {}", demo_id, e);
+                batch.failed += 1;
+            }
+        }
+    }
+    Ok(batch)
+}
+
+/// Spawns a task that re-parses up to `limit` unparsed demos from storage, in batches of [DEMO_REPARSE_BATCH_SIZE].
+///
+/// `progress` is updated after every batch, and `running` is cleared once the job finishes or a batch fails.
+pub fn spawn_demo_reparse(pool: PgPool, storage: Arc<dyn DemoStorage>, progress: Arc<Mutex<ReparseProgress>>, limit: i64) {
+    actix_web::rt::spawn(async move {
+        let mut after_id = 0;
+        let mut left = limit;
+        while left > 0 {
+            let batch = reparse_demo_batch(&pool, after_id, left.min(DEMO_REPARSE_BATCH_SIZE), |demo| {
+                let storage = storage.clone();
+                async move { storage.download(StoredFile::from(&demo)).await }
+            })
+            .await;
+            let batch = match batch {
+                Ok(batch) => batch,
+                Err(e) => {
+                    eprintln!("Error re-parsing demos after {} -> {}", after_id, e);
+                    break;
+                }
+            };
+            let Some(last_id) = batch.last_id else {
+                break;
+            };
+            after_id = last_id;
+            left -= (batch.parsed + batch.failed) as i64;
+            let remaining = Demos::count_unparsed(&pool).await;
+            let mut progress = progress.lock().await;
+            progress.parsed += batch.parsed;
+            progress.failed += batch.failed;
+            progress.last_id = last_id;
+            if let Ok(remaining) = remaining {
+                progress.remaining = remaining;
+            }
+        }
+        progress.lock().await.running = false;
+    });
+}
+
+/// Takes in either a demo_id or a changelog_id, and returns a changelog entry and a demno_id.
+///
+/// We return a demo_id because there is a chance that there are multiple demos uploaded for the same changelog entry,
+/// and we might want to delete an older demo. A `cl_id` always resolves to the demo the changelog currently points at (the newest).
+async fn get_changelog_and_demo_id(query: DemoOptions, pool: &PgPool) -> Result<(Changelog, i64)> {
+    if let Some(cl_id) = query.cl_id {
+        // Find the demo_id currently associated with the changelog entry.
+        let changelog = Changelog::get_changelog(pool, cl_id).await?;
+        if let Some(cl) = changelog {
+            match cl.demo_id {
+                Some(demo_id) => Ok((cl, demo_id)),
+                None => bail!("Changelog does not have a demo_id"),
+            }
+        } else {
+            bail!("No changelog entry found to match changelog_id")
+        }
+    } else if let Some(d_id) = query.demo_id {
+        let d = Demos::get_demo(pool, d_id).await?;
+        if let Some(d) = d {
+            let changelog = Changelog::get_changelog(pool, d.cl_id).await?;
+            if let Some(cl) = changelog {
+                Ok((cl, d_id))
+            } else {
+                bail!("Changelog entry referenced by demo does not exist")
+            }
+        } else {
+            bail!("No demo found")
+        }
+    } else {
+        bail!("Neither a demo or changelog ID was supplied")
+    }
+}
+
+/// Deletes the demo's file from storage, a file that is already missing is not an error, see [ignore_missing_file].
+async fn delete_demo_file(
+    pool: &PgPool,
+    storage: &dyn DemoStorage,
+    cl: Changelog,
+    demo_id: i64,
+) -> Result<()> {
+    let d = match Demos::get_demo(pool, demo_id).await? {
+        Some(d) => d,
+        None => bail!("No demo found"),
+    };
+    let file_name = generate_file_name(pool, &cl.map_id, cl.score, &cl.profile_number).await?;
+    match ignore_missing_file(storage.delete(StoredFile::from(&d), &file_name).await, demo_id) {
+        Ok(_) => Ok(()),
+        Err(e) => bail!("Failed to delete file from storage -> {e}"),
+    }
+}
+
+/// Treats deleting a file that storage no longer has as done, so the demo's entry can still be removed.
+///
+/// The file may have been removed by hand, or stored under a name that no longer matches its changelog entry.
+fn ignore_missing_file(result: Result<()>, demo_id: i64) -> Result<()> {
+    match result {
+        Err(e) if is_missing_file(&e) => {
+            eprintln!("Warning: the file for demo {} is missing from storage, removing the entry anyway -> {}", demo_id, e);
+            Ok(())
+        }
+        result => result,
+    }
+}
+
+/// The canonical file name a demo was stored under, found from its changelog entry.
+async fn demo_file_name(pool: &PgPool, demo: &Demos) -> Result<String> {
+    let cl = match Changelog::get_changelog(pool, demo.cl_id).await? {
+        Some(cl) => cl,
+        None => bail!("No changelog entry found for demo"),
+    };
+    generate_file_name(pool, &cl.map_id, cl.score, &cl.profile_number).await
+}
+
+/// Once the file has been removed, delete the demo entry.
+///
+/// If the changelog entry pointed at the deleted demo, it is updated to point at the newest remaining demo (if any).
+async fn delete_demo_db(pool: &PgPool, cl_id: i64, demo_id: i64) -> std::result::Result<Demos, sqlx::Error> {
+    // Delete references to the demo_id in the changelog table.
+    let references = Changelog::delete_references_to_demo(pool, demo_id).await?;
+    // Delete the demo entry.
+    let demo = Demos::delete_demo(pool, demo_id).await?;
+    if references.contains(&cl_id) {
+        if let Some(latest) = Demos::get_demo_by_cl_id(pool, cl_id).await? {
+            Changelog::update_demo_id_in_changelog(pool, cl_id, latest.id).await?;
+        }
+    }
+    Ok(demo)
+}
+
+/// Creates the canonical file name for a demo, `{map_name}_{score}_{profile_number}.dem`.
+///
+/// Demos are stored under this name, and it is recomputed from the changelog entry to delete or download them.
+pub async fn generate_file_name(pool: &PgPool, map_id: &str, score: i32, profile_number: &str) -> Result<String> {
+    let mut map_name = match Maps::get_map_name(pool, map_id.to_string()).await? {
+        Some(map_name) => map_name,
+        None => bail!("Changelog entry references a map that does not exist"),
+    };
+    map_name.retain(|c| !c.is_whitespace());
+    Ok(format!("{}_{}_{}.dem", map_name, score, profile_number))
+}
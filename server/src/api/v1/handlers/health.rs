@@ -0,0 +1,134 @@
+use crate::{
+    models::health::ReadyStatus,
+    tools::{
+        backblaze::b2_authorize_account,
+        cache::CacheState,
+        config::{Config, StorageBackend},
+        error::{ErrorType, Result, ServerError},
+        metrics::Metrics,
+    },
+};
+use actix_web::{get, web, HttpResponse, Responder};
+use sqlx::PgPool;
+
+/// **GET** method to check that the server is up, always returns a 200.
+///
+/// ## Example endpoints:
+///  - **Default**
+///     - `/api/v1/health`
+///
+/// ## Example JSON output
+///
+/// ```json
+/// "ok"
+/// ```
+#[utoipa::path(tag = "health", responses((status = 200, body = String)))]
+#[get("/health")]
+pub async fn health() -> impl Responder {
+    web::Json("ok")
+}
+
+/// **GET** method to check that the server's dependencies are available, for use as a readiness probe.
+///
+/// Runs a `SELECT 1` against the database, and authorizes with BackBlaze. A successful BackBlaze authorization
+/// is trusted for a short window, so frequent polling does not re-authorize every time. BackBlaze is not checked
+/// when demos are stored locally (`STORAGE.BACKEND=local`).
+///
+/// Returns a 200 if all dependencies are available, otherwise a 503 naming the dependencies that failed.
+///
+/// ## Example endpoints:
+///  - **Default**
+///     - `/api/v1/ready`
+///
+/// ## Example JSON output
+///
+/// ```json
+/// {
+///     "database": true,
+///     "backblaze": false,
+///     "failed": [
+///         "backblaze"
+///     ]
+/// }
+/// ```
+#[utoipa::path(
+    tag = "health",
+    responses(
+        (status = 200, body = ReadyStatus),
+        (status = 503, body = ReadyStatus, description = "A dependency is unavailable"),
+    ),
+)]
+#[get("/ready")]
+pub async fn ready(
+    pool: web::Data<PgPool>,
+    config: web::Data<Config>,
+    cache: web::Data<CacheState>,
+) -> impl Responder {
+    let database = match sqlx::query("SELECT 1").execute(pool.get_ref()).await {
+        Ok(_) => true,
+        Err(e) => {
+            eprintln!("Readiness check failed for the database -> {e}");
+            false
+        }
+    };
+    let uses_backblaze = config.storage.backend == StorageBackend::Backblaze;
+    let backblaze = !uses_backblaze || cache.b2_recently_authorized().await || {
+        let key_string = format!("{}:{}", config.backblaze.keyid, config.backblaze.key);
+        match b2_authorize_account(&reqwest::Client::new(), key_string).await {
+            Ok(_) => {
+                cache.set_b2_authorized().await;
+                true
+            }
+            Err(e) => {
+                eprintln!("Readiness check failed for BackBlaze -> {e}");
+                false
+            }
+        }
+    };
+    let mut failed = Vec::new();
+    if !database {
+        failed.push("database".to_string());
+    }
+    if !backblaze {
+        failed.push("backblaze".to_string());
+    }
+    let status = ReadyStatus {
+        database,
+        backblaze,
+        failed,
+    };
+    if status.failed.is_empty() {
+        HttpResponse::Ok().json(status)
+    } else {
+        HttpResponse::ServiceUnavailable().json(status)
+    }
+}
+
+/// **GET** method to return the server's metrics in the Prometheus text format.
+///
+/// Includes request counts and latency for every endpoint, BackBlaze upload duration and preview cache hits/misses.
+///
+/// ## Example endpoints:
+///  - **Default**
+///     - `/api/v1/metrics`
+///
+/// ## Example output
+///
+/// ```text
+/// # HELP boards_http_requests_total Requests handled, by route, method and status.
+/// # TYPE boards_http_requests_total counter
+/// boards_http_requests_total{method="POST",route="/api/v1/sp/post_score",status="200"} 12
+/// ```
+#[utoipa::path(tag = "health", responses((status = 200, body = String)))]
+#[get("/metrics")]
+pub async fn metrics(metrics: web::Data<Metrics>) -> Result<impl Responder> {
+    match metrics.render() {
+        Ok(body) => Ok(HttpResponse::Ok()
+            .content_type("text/plain; version=0.0.4")
+            .body(body)),
+        Err(e) => {
+            eprintln!("Error rendering metrics -> {e}");
+            Err(ServerError::new(ErrorType::Internal, "Error rendering metrics."))
+        }
+    }
+}
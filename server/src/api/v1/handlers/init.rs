@@ -1,48 +1,90 @@
 use actix_web::web;
 
 use crate::api::v1::handlers::{
-    admin::*, changelog::*, chapters::*, coop::*, maps::*, points::*, sp::*, stats::*,
-    users::*,
+    admin::*, changelog::*, chapters::*, coop::*, demos::*, health::*, live::*, maps::*, openapi::*,
+    points::*, sp::*, stats::*, users::*,
 };
 
 /// Mounts the routes to /api/..
 pub fn init(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::scope("/api/v1")
+            .service(health)
+            .service(ready)
+            .service(metrics)
+            .service(openapi_spec)
+            .service(live)
             .service(changelog)
             .service(changelog_new)
             .service(graph)
+            .service(recent)
             .service(changelog_demo_update)
             .service(default_categories_all)
             .service(sp)
             .service(sp_map)
+            .service(sp_wr_history)
+            .service(sp_player_rank)
+            .service(sp_compare)
             .service(sp_banned)
             .service(sp_all_banned)
             .service(sp_history)
+            .service(sp_history_timeline)
             .service(sp_update)
+            .service(sp_patch)
             .service(sp_validate)
             .service(sp_post_score)
             .service(coop)
             .service(coop_map)
+            .service(coop_history)
             .service(coop_banned_all)
             .service(coop_banned)
             .service(coop_add)
             .service(coop_temp)
+            .service(coop_unbundled)
+            .service(coop_bundle)
             .service(coop_update_changelog)
+            .service(demos)
+            .service(demos_all)
+            .service(demos_add)
+            .service(demos_changelog)
+            .service(demos_download)
+            .service(demos_coop)
+            .service(demos_verify)
+            .service(demos_metadata)
+            .service(demos_delete)
+            .service(demos_delete_by_player)
             .service(maps)
+            .service(map_detail)
+            .service(map_summary)
+            .service(map_categories)
             .service(default_category)
+            .service(default_categories)
+            .service(set_default_category)
             .service(map_ids)
             .service(chapter)
             .service(chapters_filtered)
+            .service(chapters_with_maps)
             .service(maps_from_chapter)
             .service(user)
+            .service(user_socials)
             .service(user_add)
             .service(avatar_update)
+            .service(avatar_refresh)
             .service(banned_users_all)
             .service(banned_user)
+            .service(users_ban)
+            .service(search_users)
+            .service(search_maps)
+            .service(users_batch)
             .service(donators)
             .service(wall_of_shame)
+            .service(users_banned)
+            // Registered after the other `/users/...` routes, so they are not matched as a `profile_number`.
+            .service(user_profile)
             .service(profile)
+            .service(chapter_points)
+            .service(user_pbs)
+            .service(user_missing_maps)
             .service(points_sp)
             .service(points_sp_add)
             .service(points_coop)
@@ -51,11 +93,22 @@ pub fn init(cfg: &mut web::ServiceConfig) {
             .service(points_chapter_add)
             .service(points_overall)
             .service(points_overall_add)
+            .service(points_curve)
             .service(admin_changelog)
             .service(admin_banned_stats)
+            .service(admin_verify_queue)
+            .service(admin_verify)
+            .service(admin_repair_chain)
+            .service(admin_demos_reparse)
+            .service(admin_demos_reparse_progress)
+            .service(admin_changelog_import)
+            .service(admin_cache_rebuild)
+            .service(admin_maintenance)
             .service(admins_list)
+            .service(users_flagged)
             .service(count_scores)
             .service(count_scores_by_map)
+            .service(stats_overview)
             .service(recap)
             .service(badges)
             .service(users_badges),
@@ -0,0 +1,66 @@
+use crate::tools::{
+    cache::CacheState,
+    error::{ErrorType, Result, ServerError},
+    live::LiveUpdate,
+};
+use actix_web::{get, web, HttpRequest, Responder};
+use actix_ws::Message;
+use futures::StreamExt;
+use tokio::sync::broadcast::error::RecvError;
+
+/// **GET** method to open a WebSocket that pushes live leaderboard updates, so clients don't have to poll `/sp` and `/coop`.
+///
+/// Each message is a JSON [LiveUpdate] sent when a submission changes a map's leaderboard, refetch the map it names.
+/// A client that falls too far behind is sent a `resync` instead of the updates it missed, and should refetch everything
+/// it is showing. Messages sent by the client are ignored, pings are answered.
+///
+/// Returns a 400 if the request is not a WebSocket upgrade.
+///
+/// ## Example endpoints:
+/// - `ws://localhost:8080/api/v1/ws/live`
+///
+/// ## Example messages
+///
+/// ```json
+/// { "type": "sp_update", "map_id": "47763" }
+/// { "type": "coop_update", "map_id": "47741" }
+/// { "type": "resync" }
+/// ```
+#[utoipa::path(tag = "live", responses((status = 101, description = "Switching to the WebSocket protocol")))]
+#[get("/ws/live")]
+pub async fn live(req: HttpRequest, body: web::Payload, cache: web::Data<CacheState>) -> Result<impl Responder> {
+    let (response, mut session, mut messages) =
+        actix_ws::handle(&req, body).map_err(|e| ServerError::new(ErrorType::BadRequest, e.to_string()))?;
+    let mut updates = cache.live.subscribe();
+    actix_web::rt::spawn(async move {
+        let reason = loop {
+            tokio::select! {
+                update = updates.recv() => {
+                    let update = match update {
+                        Ok(update) => update,
+                        Err(RecvError::Lagged(_)) => LiveUpdate::Resync,
+                        Err(RecvError::Closed) => break None,
+                    };
+                    let text = serde_json::to_string(&update).expect("live updates serialize to JSON");
+                    // The client is gone, there is nothing left to close.
+                    if session.text(text).await.is_err() {
+                        return;
+                    }
+                }
+                message = messages.next() => match message {
+                    Some(Ok(Message::Ping(bytes))) => {
+                        if session.pong(&bytes).await.is_err() {
+                            return;
+                        }
+                    }
+                    Some(Ok(Message::Close(reason))) => break reason,
+                    Some(Ok(_)) => {}
+                    // The connection dropped or sent an invalid frame.
+                    Some(Err(_)) | None => break None,
+                },
+            }
+        };
+        let _ = session.close(reason).await;
+    });
+    Ok(response)
+}
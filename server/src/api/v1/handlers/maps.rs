@@ -1,11 +1,16 @@
 use crate::{
     models::{
-        chapters::GameID,
-        maps::{IsCoop, Maps},
+        categories::{Categories, Category},
+        chapters::{GameID, OptIDs},
+        maps::{DefaultCategoryUpdate, IsCoop, MapDetail, MapSearchParams, MapSearchResult, MapSummary, Maps},
+    },
+    tools::{
+        auth::AuthUser,
+        cache::CacheState,
+        error::{ErrorType, Result, ServerError},
     },
-    tools::error::Result,
 };
-use actix_web::{get, web, Responder};
+use actix_web::{get, put, web, Responder};
 use sqlx::PgPool;
 
 /// **GET** method to return all map information for a given game.
@@ -37,6 +42,7 @@ use sqlx::PgPool;
 ///         "is_public": true
 ///     },...]
 /// ```
+#[utoipa::path(tag = "maps", params(GameID), responses((status = 200, body = Vec<Maps>)))]
 #[get("/maps")]
 async fn maps(pool: web::Data<PgPool>, query: web::Query<GameID>) -> Result<impl Responder> {
     Ok(web::Json(
@@ -44,6 +50,161 @@ async fn maps(pool: web::Data<PgPool>, query: web::Query<GameID>) -> Result<impl
     ))
 }
 
+/// **GET** method to return a single map, along with its chapter and all valid categories for the map.
+///
+/// Returns a 404 if there is no map with the given `map_id`.
+///
+/// ## Example endpoints:
+///  - **Default**
+///     - `/api/v1/maps/47458`
+///
+/// Makes a call to the underlying [Maps::get_map_detail]
+///
+/// ## Example JSON output
+///
+/// ```json
+/// {
+///     "steam_id": "47458",
+///     "name": "Portal Gun",
+///     "chapter_id": 7,
+///     "chapter_name": "The Courtesy Call",
+///     "game_id": 1,
+///     "is_coop": false,
+///     "default_cat_id": 1,
+///     "is_public": true,
+///     "categories": [
+///         {
+///             "id": 1,
+///             "name": "any%",
+///             "map_id": "47458",
+///             "rules_id": 1,
+///             "updated": null
+///         }
+///     ]
+/// }
+/// ```
+#[utoipa::path(
+    tag = "maps",
+    responses((status = 200, body = MapDetail), (status = 404, description = "The map could not be found")),
+)]
+#[get("/maps/{map_id}")]
+pub async fn map_detail(
+    map_id: web::Path<String>,
+    pool: web::Data<PgPool>,
+) -> Result<impl Responder> {
+    let map_id = map_id.into_inner();
+    match Maps::get_map_detail(pool.get_ref(), &map_id).await? {
+        Some(detail) => Ok(web::Json(detail)),
+        None => Err(ServerError::new(
+            ErrorType::NotFound,
+            format!("No map with map_id {map_id}"),
+        )),
+    }
+}
+
+/// **GET** method to return a summary of a map's leaderboard, the current world record and the number of players with a valid time.
+///
+/// Summaries are cached, and invalidated alongside the previews.
+///
+/// ## Parameters:
+/// - `cat_id`
+///     - **Optional** - `i32` : The ID of the category, if left blank will use the default. A category that is not on the map returns a 404.
+///
+/// ## Example endpoints:
+///  - **Default**
+///     - `/api/v1/maps/47458/summary`
+///  - **Specific Category**
+///     - `/api/v1/maps/47458/summary?cat_id=49`
+///
+/// Makes a call to the underlying [Maps::get_map_summary]
+///
+/// ## Example JSON output
+///
+/// ```json
+/// {
+///     "map_id": "47458",
+///     "category_id": 49,
+///     "wr_score": 2326,
+///     "wr_profile_number": "76561198795823814",
+///     "wr_user_name": "Royal",
+///     "wr_avatar": "https://steamcdn-a.akamaihd.net/steamcommunity/public/images/avatars/d8/d84366b1be1f0439b0edc7fc8404fe2ea29a9c54_full.jpg",
+///     "player_count": 1843
+/// }
+/// ```
+#[utoipa::path(tag = "maps", params(OptIDs), responses((status = 200, body = MapSummary)))]
+#[get("/maps/{map_id}/summary")]
+pub async fn map_summary(
+    map_id: web::Path<String>,
+    ids: web::Query<OptIDs>,
+    cache: web::Data<CacheState>,
+    pool: web::Data<PgPool>,
+) -> Result<impl Responder> {
+    let map_id = map_id.into_inner();
+    let Some(default_cat_id) = cache.default_cat_ids.get(&map_id, ids.game_id.unwrap_or(1)) else {
+        return Err(ServerError::new(
+            ErrorType::NotFound,
+            format!("No map with map_id {map_id}"),
+        ));
+    };
+    let cat_id = ids.cat_id.unwrap_or(default_cat_id);
+    if let Some(summary) = cache.get_map_summary(&map_id, cat_id).await {
+        return Ok(web::Json(summary));
+    }
+    // Only categories on the map are cached, so the cache is bounded by the number of categories.
+    if cat_id != default_cat_id
+        && !Categories::get_for_map(pool.get_ref(), &map_id).await?.iter().any(|category| category.id == cat_id)
+    {
+        return Err(ServerError::new(
+            ErrorType::NotFound,
+            format!("Category {cat_id} is not a category on map {map_id}"),
+        ));
+    }
+    let summary = Maps::get_map_summary(pool.get_ref(), &map_id, cat_id).await?;
+    cache.set_map_summary(summary.clone()).await;
+    Ok(web::Json(summary))
+}
+
+/// **GET** method to return all categories on a map, with the rules for each category.
+///
+/// `is_default` marks the category clients should preselect for the map.
+///
+/// ## Example endpoints:
+///  - **Default**
+///     - `/api/v1/maps/47458/categories`
+///
+/// Makes a call to the underlying [Categories::get_for_map]
+///
+/// ## Example JSON output
+///
+/// ```json
+/// [
+///     {
+///         "id": 49,
+///         "name": "any%",
+///         "rules": null,
+///         "external_link": null,
+///         "is_default": true
+///     },...]
+/// ```
+#[utoipa::path(tag = "maps", responses((status = 200, body = Vec<Category>)))]
+#[get("/maps/{map_id}/categories")]
+pub async fn map_categories(
+    map_id: web::Path<String>,
+    cache: web::Data<CacheState>,
+    pool: web::Data<PgPool>,
+) -> Result<impl Responder> {
+    let map_id = map_id.into_inner();
+    if cache.default_cat_ids.get(&map_id, 1).is_none() {
+        return Err(ServerError::new(
+            ErrorType::NotFound,
+            format!("No map with map_id {map_id}"),
+        ));
+    }
+    Ok(web::Json(
+        Categories::get_for_map(pool.get_ref(), &map_id).await?,
+    ))
+}
+
 /// **GET** method to return the default category ID for a given map
 ///
 /// ## Example endpoints:
@@ -57,6 +218,7 @@ async fn maps(pool: web::Data<PgPool>, query: web::Query<GameID>) -> Result<impl
 /// ```json
 /// 49
 /// ```
+#[utoipa::path(tag = "maps", responses((status = 200, body = Option<i32>)))]
 #[get("/default_category/{map}")]
 async fn default_category(
     params: web::Path<u64>,
@@ -67,14 +229,114 @@ async fn default_category(
     ))
 }
 
-// TODO: Have this take an option<bool>? Somewhat more ergonomic in some places.
-/// **GET** method to return the all steam_ids for a given game. Filters by if the map is coop or not.
+/// **PUT** method to change the default category for a map. Requires an admin's auth token, see [AuthUser].
+///
+/// The cached default is updated right away, and the previews and aggregate points are refreshed to use the new category.
+/// Returns a 404 if there is no map with the given `map_id`, and a 400 if the category is not a category on the map.
+///
+/// ## Parameters (expects valid JSON Object):
+/// - `cat_id`
+///     - **Required** - `i32` : ID of the category, must be one of the map's categories.
+///
+/// ## Example endpoints:
+///  - **Default**
+///     - `/api/v1/maps/47458/default_category`
+///
+/// Makes a call to the underlying [Maps::set_default_cat]
+///
+/// ## Example JSON String
+///
+/// ```json
+/// {
+///     "cat_id": 49
+/// }
+/// ```
+///
+/// ## Example JSON output
+///
+/// The updated [MapDetail], see [map_detail].
+#[utoipa::path(
+    tag = "maps",
+    responses((status = 200, body = MapDetail), (status = 400, description = "The category is not on the map"))
+)]
+#[put("/maps/{map_id}/default_category")]
+pub async fn set_default_category(
+    map_id: web::Path<String>,
+    params: web::Json<DefaultCategoryUpdate>,
+    cache: web::Data<CacheState>,
+    pool: web::Data<PgPool>,
+    auth: AuthUser,
+) -> Result<impl Responder> {
+    auth.require_admin()?;
+    let map_id = map_id.into_inner();
+    let cat_id = params.into_inner().cat_id;
+    let Some(detail) = Maps::get_map_detail(pool.get_ref(), &map_id).await? else {
+        return Err(ServerError::new(
+            ErrorType::NotFound,
+            format!("No map with map_id {map_id}"),
+        ));
+    };
+    if !detail.categories.iter().any(|category| category.id == cat_id) {
+        return Err(ServerError::new(
+            ErrorType::BadRequest,
+            format!("Category {cat_id} is not a category on map {map_id}"),
+        ));
+    }
+    Maps::set_default_cat(pool.get_ref(), &map_id, cat_id).await?;
+    cache.default_cat_ids.set(&map_id, detail.game_id, cat_id);
+    cache.invalidate_map_preview(pool.get_ref(), &map_id).await;
+    cache.invalidate_aggregate_points().await;
+    Ok(web::Json(MapDetail { default_cat_id: cat_id, ..detail }))
+}
+
+/// **GET** method to return the default category ID for every map, as `map_id` -> `cat_id`.
+///
+/// Served from the default categories loaded into the cache on startup.
+///
+/// ## Parameters:
+/// - `game_id`
+///     - **Optional** - `i32` : If left empty, maps from every game are returned.
+///
+/// ## Example endpoints:
+///  - **Default**
+///     - `/api/v1/default_categories`
+///  - **Specific Game**
+///     - `/api/v1/default_categories?game_id=1`
+///
+/// ## Example JSON ouput
+///
+/// ```json
+/// {
+///     "47458": 49,
+///     "47455": 1,
+///     "47452": 52,...}
+/// ```
+#[utoipa::path(tag = "maps", params(GameID), responses((status = 200, body = HashMap<String, i32>)))]
+#[get("/default_categories")]
+async fn default_categories(query: web::Query<GameID>, cache: web::Data<CacheState>) -> Result<impl Responder> {
+    Ok(web::Json(match query.game_id {
+        Some(game_id) => cache.default_cat_ids.for_game(game_id),
+        None => cache.default_cat_ids.all(),
+    }))
+}
+
+/// **GET** method to return the all steam_ids, optionally filtered by if the map is coop or not and by game.
+///
+/// ## Parameters:
+/// - `is_coop`
+///     - **Optional** - `bool` : If left empty, both singleplayer and coop maps are returned.
+/// - `game_id`
+///     - **Optional** - `i32` : If left empty, maps from every game are returned.
 ///
 /// ## Example endpoints:
+///  - **All Maps**
+///     - `/api/v1/map_ids`
 ///  - **With Parameters**
 ///     - `/api/v1/map_ids?is_coop=true`
 ///  - **Specific Game**
 ///     - `/api/v1/map_ids?is_coop=true&game_id=1`
+///  - **Every Map For A Game**
+///     - `/api/v1/map_ids?game_id=2`
 ///
 /// Makes a call to the underlying [Maps::get_steam_ids]
 ///
@@ -87,9 +349,54 @@ async fn default_category(
 //     "47828",
 //     "47829",...]
 /// ```
+#[utoipa::path(tag = "maps", params(IsCoop), responses((status = 200, body = Vec<String>)))]
 #[get("/map_ids")]
 async fn map_ids(pool: web::Data<PgPool>, query: web::Query<IsCoop>) -> Result<impl Responder> {
+    let query = query.into_inner();
     Ok(web::Json(
-        Maps::get_steam_ids(pool.get_ref(), query.into_inner().is_coop).await?,
+        Maps::get_steam_ids(pool.get_ref(), query.is_coop, query.game_id).await?,
     ))
 }
+
+/// **GET** method to search for maps by name, for players who know a map's name but not its `steam_id`.
+///
+/// The search is case-insensitive and matches anywhere in the name. An exact name comes first, then names starting
+/// with the query. An empty query returns no maps.
+///
+/// ## Parameters:
+/// - `q`
+///     - **Required** - `String` : The (partial) map name to search for.
+/// - `game_id`
+///     - **Optional** - `i32` : If left empty, maps from every game are searched.
+///
+/// ## Example endpoints:
+///  - **With Parameters**
+///     - `/api/v1/search/maps?q=door`
+///  - **Specific Game**
+///     - `/api/v1/search/maps?q=door&game_id=1`
+///
+/// Makes a call to the underlying [Maps::search_by_name]
+///
+/// ## Example JSON output
+///
+/// ```json
+/// [
+///     {
+///         "steam_id": "47741",
+///         "name": "Doors",
+///         "chapter_id": 1,
+///         "chapter_name": "Team Building",
+///         "is_coop": true
+///     },...]
+/// ```
+#[utoipa::path(tag = "maps", params(MapSearchParams), responses((status = 200, body = Vec<MapSearchResult>)))]
+#[get("/search/maps")]
+async fn search_maps(pool: web::Data<PgPool>, query: web::Query<MapSearchParams>) -> Result<impl Responder> {
+    let query = query.into_inner();
+    let q = query.q.unwrap_or_default();
+    let q = q.trim();
+    if q.is_empty() {
+        return Ok(web::Json(Vec::new()));
+    }
+    Ok(web::Json(Maps::search_by_name(pool.get_ref(), q, query.game_id).await?))
+}
@@ -7,11 +7,17 @@ pub mod chapters;
 /// Cooperative-specific endpoints.
 pub mod coop;
 /// Demo endpoints
-///pub mod demos;
+pub mod demos;
+/// Health and readiness checks.
+pub mod health;
 /// Mounting of the endpoints.
 pub mod init;
+/// Live updates over WebSockets.
+pub mod live;
 /// Maps-based endpoints.
 pub mod maps;
+/// The OpenAPI spec for the endpoints.
+pub mod openapi;
 /// Point-based endpoints.
 pub mod points;
 /// Singleplayer-specific endpoints.
@@ -0,0 +1,48 @@
+use crate::api::v1::handlers::{admin, changelog, chapters, coop, demos, health, live, maps, points, sp, stats, users};
+use actix_web::{get, web, Responder};
+use utoipa::OpenApi;
+
+/// The OpenAPI description of every endpoint under `/api/v1`, generated from the `#[utoipa::path]` on each handler.
+///
+/// New handlers need to be added to `paths` as well as [crate::api::v1::handlers::init::init].
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "Portal 2 Challenge Mode Boards"),
+    servers((url = "/api/v1")),
+    paths(
+        health::health, health::ready, health::metrics, openapi_spec, live::live, changelog::changelog,
+        changelog::changelog_new, changelog::graph, changelog::recent, changelog::changelog_demo_update,
+        changelog::default_categories_all, sp::sp, sp::sp_map, sp::sp_wr_history, sp::sp_player_rank, sp::sp_compare,
+        sp::sp_banned, sp::sp_all_banned, sp::sp_history, sp::sp_history_timeline, sp::sp_update, sp::sp_patch,
+        sp::sp_validate, sp::sp_post_score, coop::coop, coop::coop_map, coop::coop_history, coop::coop_banned_all,
+        coop::coop_banned, coop::coop_add, coop::coop_temp, coop::coop_unbundled, coop::coop_bundle,
+        coop::coop_update_changelog, demos::demos, demos::demos_all, demos::demos_add, demos::demos_changelog,
+        demos::demos_download, demos::demos_coop, demos::demos_verify, demos::demos_metadata, demos::demos_delete,
+        demos::demos_delete_by_player, maps::maps, maps::map_detail, maps::map_summary, maps::map_categories,
+        maps::default_category, maps::default_categories, maps::set_default_category, maps::map_ids, maps::search_maps,
+        chapters::chapter, chapters::chapters_filtered, chapters::chapters_with_maps, chapters::maps_from_chapter,
+        users::user, users::user_socials, users::user_add, users::avatar_update, users::avatar_refresh,
+        users::banned_users_all, users::banned_user, users::users_ban, users::search_users, users::users_batch,
+        users::donators, users::wall_of_shame, users::users_banned, users::user_profile, users::profile,
+        users::chapter_points, users::user_pbs, users::user_missing_maps, points::points_sp, points::points_sp_add,
+        points::points_coop, points::points_coop_add, points::points_chapter, points::points_chapter_add,
+        points::points_overall, points::points_overall_add, points::points_curve, admin::admin_changelog,
+        admin::admin_banned_stats, admin::admin_verify_queue, admin::admin_verify, admin::admin_repair_chain,
+        admin::admin_demos_reparse, admin::admin_demos_reparse_progress, admin::admin_changelog_import,
+        admin::admin_cache_rebuild, admin::admin_maintenance, admin::admins_list, admin::users_flagged,
+        stats::count_scores, stats::count_scores_by_map, stats::stats_overview, stats::recap, stats::badges,
+        stats::users_badges
+    )
+)]
+pub struct ApiDoc;
+
+/// **GET** method for the OpenAPI spec of the API, the schemas are generated from the models at compile time.
+///
+/// ## Example endpoints:
+///  - **Default**
+///     - `/api/v1/openapi.json`
+#[utoipa::path(tag = "openapi", responses((status = 200, description = "The OpenAPI spec")))]
+#[get("/openapi.json")]
+pub async fn openapi_spec() -> impl Responder {
+    web::Json(ApiDoc::openapi())
+}
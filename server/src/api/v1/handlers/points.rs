@@ -1,5 +1,11 @@
-use crate::models::points::{PointsReadWrapper, PointsReceiveWrapper, PointsWriteWrapper};
-use crate::tools::cache::{write_to_file, CacheState};
+use crate::models::points::{
+    PointsAggregate, PointsCurve, PointsCurveParams, PointsReadWrapper, PointsReceiveWrapper, PointsWriteWrapper, RankPoints,
+};
+use crate::tools::cache::{read_from_file, write_to_file, CacheState, COOP_AGGREGATE, SP_AGGREGATE};
+use crate::tools::config::Config;
+use crate::tools::error;
+use crate::tools::helpers::{calc_coop_aggregate_points, calc_sp_aggregate_points, points_for_rank};
+use sqlx::PgPool;
 use actix_web::{get, post, web, HttpResponse, Responder};
 use anyhow::{Error, Result};
 use std::fs::File;
@@ -59,6 +65,7 @@ use std::path::Path;
 ///                 }
 ///             ],...]}
 /// ```
+#[utoipa::path(tag = "points", responses((status = 200, body = String)))]
 #[post("/points/sp")]
 async fn points_sp_add(
     data: web::Json<PointsReceiveWrapper>,
@@ -81,17 +88,50 @@ async fn points_sp_add(
     }
 }
 
-/// Gget single player points data.
+/// **GET** method for the aggregate single player points leaderboard.
+///
+/// Each player's best rank on every single player map is converted to points with [crate::tools::helpers::score],
+/// and summed. Banned players are excluded. The result is expensive to calculate, so it is cached until a score changes.
+///
+/// ## Example endpoints:
+/// - `/api/v1/points/sp`
+///
+/// Makes a call to the underlying [calc_sp_aggregate_points]
+/// **or** uses a cached value.
+///
+/// ## Example JSON output
+///
+/// ```json
+/// [
+///     {
+///         "profile_number": "76561198039230536",
+///         "user_name": "Zypeh",
+///         "total_points": 11734.67,
+///         "rank": 1
+///     },...]
+/// ```
+#[utoipa::path(tag = "points", path = "/points/sp", responses((status = 200, body = Vec<PointsAggregate>)))]
 #[get("points/sp")]
-async fn points_sp() -> impl Responder {
-    let res = read_points_from_file("sp").await;
-    match res {
-        Ok(sp_points) => HttpResponse::Ok().json(sp_points),
-        _ => HttpResponse::NotFound().body("No score entries for SP found."),
+async fn points_sp(
+    pool: web::Data<PgPool>,
+    config: web::Data<Config>,
+    cache: web::Data<CacheState>,
+) -> error::Result<impl Responder> {
+    if !cache.get_current_state(SP_AGGREGATE).await {
+        let points = calc_sp_aggregate_points(pool.get_ref(), &config, &cache.default_cat_ids.for_game(1)).await?;
+        if write_to_file(SP_AGGREGATE, &points).await.is_ok() {
+            cache.update_current_state(SP_AGGREGATE, true).await;
+        } else {
+            eprintln!("Could not write cache for sp aggregate points");
+        }
+        Ok(web::Json(points))
+    } else {
+        Ok(web::Json(read_from_file::<Vec<PointsAggregate>>(SP_AGGREGATE).await?))
     }
 }
 
 /// Update coop points data.
+#[utoipa::path(tag = "points", responses((status = 200, body = String)))]
 #[post("/points/coop")]
 async fn points_coop_add(
     data: web::Json<PointsReceiveWrapper>,
@@ -114,17 +154,50 @@ async fn points_coop_add(
     }
 }
 
-/// Get coop points data.
+/// **GET** method for the aggregate coop points leaderboard.
+///
+/// Each player's best rank on every coop map (with any partner) is converted to points with [crate::tools::helpers::score],
+/// and summed. Banned players are excluded. The result is cached until a score changes.
+///
+/// ## Example endpoints:
+/// - `/api/v1/points/coop`
+///
+/// Makes a call to the underlying [calc_coop_aggregate_points]
+/// **or** uses a cached value.
+///
+/// ## Example JSON output
+///
+/// ```json
+/// [
+///     {
+///         "profile_number": "76561198039230536",
+///         "user_name": "Zypeh",
+///         "total_points": 9712.2,
+///         "rank": 1
+///     },...]
+/// ```
+#[utoipa::path(tag = "points", path = "/points/coop", responses((status = 200, body = Vec<PointsAggregate>)))]
 #[get("points/coop")]
-async fn points_coop() -> impl Responder {
-    let res = read_points_from_file("coop").await;
-    match res {
-        Ok(coop_points) => HttpResponse::Ok().json(coop_points),
-        _ => HttpResponse::NotFound().body("No score entries found."),
+async fn points_coop(
+    pool: web::Data<PgPool>,
+    config: web::Data<Config>,
+    cache: web::Data<CacheState>,
+) -> error::Result<impl Responder> {
+    if !cache.get_current_state(COOP_AGGREGATE).await {
+        let points = calc_coop_aggregate_points(pool.get_ref(), &config, &cache.default_cat_ids.for_game(1)).await?;
+        if write_to_file(COOP_AGGREGATE, &points).await.is_ok() {
+            cache.update_current_state(COOP_AGGREGATE, true).await;
+        } else {
+            eprintln!("Could not write cache for coop aggregate points");
+        }
+        Ok(web::Json(points))
+    } else {
+        Ok(web::Json(read_from_file::<Vec<PointsAggregate>>(COOP_AGGREGATE).await?))
     }
 }
 
 /// Update chapter data, uses JSON ID (see [PointsReceiveWrapper]).
+#[utoipa::path(tag = "points", responses((status = 200, body = String)))]
 #[post("/points/chapter")]
 async fn points_chapter_add(
     data: web::Json<PointsReceiveWrapper>,
@@ -148,6 +221,7 @@ async fn points_chapter_add(
 }
 
 /// Get points data for a specific chapter.
+#[utoipa::path(tag = "points", path = "/points/chapter/{id}", responses((status = 200, body = PointsReadWrapper)))]
 #[get("points/chapter/{id}")]
 async fn points_chapter(id: web::Path<u64>) -> impl Responder {
     let res = read_points_from_file(&id.to_string()).await;
@@ -158,6 +232,7 @@ async fn points_chapter(id: web::Path<u64>) -> impl Responder {
 }
 
 /// Update overall points data.
+#[utoipa::path(tag = "points", responses((status = 200, body = String)))]
 #[post("/points/overall")]
 async fn points_overall_add(
     data: web::Json<PointsReceiveWrapper>,
@@ -180,6 +255,7 @@ async fn points_overall_add(
 }
 
 /// Get overall points data.
+#[utoipa::path(tag = "points", path = "/points/overall", responses((status = 200, body = PointsReadWrapper)))]
 #[get("points/overall")]
 async fn points_overall() -> impl Responder {
     let res = read_points_from_file("overall").await;
@@ -189,6 +265,56 @@ async fn points_overall() -> impl Responder {
     }
 }
 
+/// Most ranks that can be requested from [points_curve].
+const MAX_CURVE_RANKS: i32 = 1000;
+
+/// **GET** method for the curve used to convert ranks into points, so clients can render the scoring table.
+///
+/// The curve is set with the `POINTS.*` config values, see [crate::tools::config::PointsConfig].
+///
+/// ## Parameters:
+/// - `count`
+///     - **Optional** - `i32` : The number of ranks to return the points for, defaults to the curve's `max_rank`. Limited to 1000.
+///
+/// ## Example endpoints:
+/// - `/api/v1/points/curve`
+/// - `/api/v1/points/curve?count=3`
+///
+/// Makes a call to the underlying [points_for_rank]
+///
+/// ## Example JSON output
+///
+/// ```json
+/// {
+///     "curve": {
+///         "max_points": 200.0,
+///         "max_rank": 200,
+///         "decay": 2.0,
+///         "min_points": 1.0
+///     },
+///     "points": [
+///         { "rank": 1, "points": 200.0 },
+///         { "rank": 2, "points": 198.005 },
+///         { "rank": 3, "points": 196.02 }
+///     ]
+/// }
+/// ```
+#[utoipa::path(
+    tag = "points",
+    path = "/points/curve",
+    params(PointsCurveParams),
+    responses((status = 200, body = PointsCurve)),
+)]
+#[get("points/curve")]
+async fn points_curve(config: web::Data<Config>, query: web::Query<PointsCurveParams>) -> impl Responder {
+    let curve = config.points;
+    let count = query.count.unwrap_or(curve.max_rank).clamp(1, MAX_CURVE_RANKS);
+    let points = (1..=count)
+        .map(|rank| RankPoints { rank, points: points_for_rank(&curve, rank) })
+        .collect();
+    web::Json(PointsCurve { curve, points })
+}
+
 /// Writes out json data to cache points for the boards.
 pub async fn write_points_to_file(
     id: &str,
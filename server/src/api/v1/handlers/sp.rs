@@ -1,28 +1,41 @@
 use crate::{
     models::{
         changelog::{
-            Changelog, ChangelogInsert, HistoryParams, ScoreLookup, ScoreParams,
-            SubmissionChangelog,
+            CalcValues, Changelog, ChangelogInsert, ChangelogPatch, ChangelogSource, CompareParams, HistoryParams, PlayerComparison, PlayerRank,
+            PlayerRankParams, ScoreLookup, ScoreParams, SubmissionChangelog, WrHistory,
         },
-        chapters::OptIDs,
+        chapters::{ExportParams, FormatParams, Games, OptIDs, PageParams, ReviewParams},
+        maps::Maps,
         sp::*,
         users::{Users, UsersPage},
     },
     tools::{
-        cache::{read_from_file, write_to_file, CacheState, SP_PREVIEWS},
+        auth::AuthUser,
+        cache::{CacheState, SP_PREVIEWS},
+        client::client_id,
+        live::LiveUpdate,
         config::Config,
-        error::Result,
-        helpers::{check_for_valid_score, score},
+        discord::notify_if_wr,
+        error::{ErrorType, Result, ServerError},
+        export::{csv_response, wants_csv, SpCsvRow},
+        helpers::{
+            build_pb_timeline, check_for_valid_score, clean_note, flag_below_min_score, format_score, rank_sp_entries,
+            results_limit,
+        },
+        metrics::Metrics,
+        ratelimit::RateLimiter,
     },
 };
-use actix_web::{get, post, put, web, Responder};
+use actix_web::{get, patch, post, put, web, HttpRequest, HttpResponse, Responder};
 use sqlx::PgPool;
 
-// TODO: Invalidate cache when a time is banned/verified/when a player is banned.
 /// **GET** method to handle the preview page showing all singleplayer maps.
 ///
 /// Inital load tends to be relatively slow, but the information cached, and
-/// remains in chache until a new singleplayer score is submitted
+/// remains in chache until a new score is submitted, or it is older than the cache TTL (`CACHE.TTL_SECS`).
+/// The previews are rebuilt in the background before the TTL expires.
+///
+/// Responses include an `ETag`, requests with a matching `If-None-Match` get a 304 while the cached previews are unchanged.
 ///
 /// ## Example endpoints:
 ///  - **Default**           
@@ -47,22 +60,31 @@ use sqlx::PgPool;
 ///                 "map_id": "47458"
 ///             },...]}]
 /// ```
+#[utoipa::path(tag = "sp", responses((status = 200, body = Vec<Vec<SpPreview>>)))]
 #[get("/sp")]
-async fn sp(pool: web::Data<PgPool>, cache: web::Data<CacheState>) -> Result<impl Responder> {
+async fn sp(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    cache: web::Data<CacheState>,
+    metrics: web::Data<Metrics>,
+) -> Result<impl Responder> {
     // See if we can utilize the cache
-    if !cache.get_current_state(SP_PREVIEWS).await {
+    let cached = cache.get_current_state(SP_PREVIEWS).await;
+    metrics.record_cache_lookup(SP_PREVIEWS, cached);
+    if let Some(not_modified) = cache.not_modified(&req, SP_PREVIEWS).await {
+        return Ok(not_modified);
+    }
+    let sp_previews = if !cached {
+        let generation = cache.preview_generation(SP_PREVIEWS).await;
         let sp_previews = SpPreview::get_sp_previews(pool.get_ref()).await?;
-        if write_to_file("sp_previews", &sp_previews).await.is_ok() {
-            cache.update_current_state(SP_PREVIEWS, true).await;
-        } else {
-            eprintln!("Could not write cache for coop previews");
+        if let Err(e) = cache.write_previews(SP_PREVIEWS, generation, &sp_previews).await {
+            eprintln!("Could not write cache for sp previews -> {}", e);
         }
-        Ok(web::Json(sp_previews))
+        sp_previews
     } else {
-        Ok(web::Json(
-            read_from_file::<Vec<Vec<SpPreview>>>("sp_previews").await?,
-        ))
-    }
+        cache.read_previews::<Vec<Vec<SpPreview>>>(SP_PREVIEWS).await?
+    };
+    Ok(cache.preview_response(&req, SP_PREVIEWS, &sp_previews).await?)
 }
 
 /// **GET** method to generate a single player map page [SpRanked] for a given map_id
@@ -72,6 +94,24 @@ async fn sp(pool: web::Data<PgPool>, cache: web::Data<CacheState>) -> Result<imp
 ///     - **Optional** - `i32` - The ID of the category you want a Single Player Ranked Page for.
 /// - `game_id`
 ///     - **Optional** - `i32` - The ID of the game you want a Single Player Ranked Page for. Defaults to the base game (1).
+/// - `offset`
+///     - **Optional** - `i32` - The number of entries to skip, defaults to 0.
+/// - `limit`
+///     - **Optional** - `i32` - The max number of entries returned, defaults to `PROOF.RESULTS` and is clamped to between 1 and
+///       `PROOF.MAX_RESULTS`, see [results_limit].
+/// - `formatted`
+///     - **Optional** - `bool` - If `true`, each entry includes a `formatted_score` (`m:ss.cc`).
+/// - `include_unverified`
+///     - **Optional** - `bool` - **Admin only** - If `true`, unverified times are included.
+/// - `include_banned`
+///     - **Optional** - `bool` - **Admin only** - If `true`, banned times are included.
+/// - `format`
+///     - **Optional** - `String` - `json` or `csv`, if left blank `csv` is returned to clients sending `Accept: text/csv`.
+///
+/// When either of the admin flags are set each entry includes its `verified` and `banned` state.
+/// The flags are ignored for callers that are not authenticated as an admin.
+///
+/// As CSV, each entry is a row of `rank,profile_number,user_name,score,points` after a header line.
 ///
 /// ## Example endpoint
 /// - **Default**
@@ -80,8 +120,18 @@ async fn sp(pool: web::Data<PgPool>, cache: web::Data<CacheState>) -> Result<imp
 ///     - `/api/v1/map/sp/47802?cat_id=88`
 /// - **Specific Game**
 ///     - `/api/v1/map/sp/47802?game_id=1`
+/// - **Paged**
+///     - `/api/v1/map/sp/47802?offset=100&limit=100`
+/// - **Formatted Scores**
+///     - `/api/v1/map/sp/47802?formatted=true`
+/// - **Reviewing Unverified Times**
+///     - `/api/v1/map/sp/47802?include_unverified=true`
+/// - **As CSV**
+///     - `/api/v1/map/sp/47802?format=csv`
 ///
-/// Makes a call to the underlying [SpMap::get_sp_map_page].
+/// Makes a call to the underlying [SpMap::get_sp_map_review_page].
+///
+/// Tied scores share the same rank and points, ranks are absolute so the first entry at `offset=100` is rank 101 (unless tied).
 ///
 /// ## Example JSON output
 ///
@@ -104,37 +154,242 @@ async fn sp(pool: web::Data<PgPool>, cache: web::Data<CacheState>) -> Result<imp
 ///         "points": 200.0
 ///     },....]
 /// ```
+#[utoipa::path(
+    tag = "sp",
+    params(PageParams, FormatParams, ReviewParams, ExportParams),
+    responses((status = 200, body = Vec<SpRanked>)),
+)]
 #[get("/map/sp/{map_id}")]
+#[allow(clippy::too_many_arguments)]
 pub async fn sp_map(
+    req: HttpRequest,
     map_id: web::Path<String>,
-    ids: web::Query<OptIDs>,
+    ids: web::Query<PageParams>,
+    format: web::Query<FormatParams>,
+    review: web::Query<ReviewParams>,
+    export: web::Query<ExportParams>,
+    auth: Option<AuthUser>,
     config: web::Data<Config>,
     cache: web::Data<CacheState>,
     pool: web::Data<PgPool>,
 ) -> Result<impl Responder> {
     let map_id = map_id.into_inner();
-    let cat_id = ids
-        .cat_id
-        .unwrap_or(cache.into_inner().default_cat_ids[&map_id]);
-    let sp_map = SpMap::get_sp_map_page(
+    // Only admins can see unverified or banned times.
+    let review = match auth {
+        Some(user) if user.is_admin() => review.into_inner(),
+        _ => ReviewParams::default(),
+    };
+    let game_id = ids.game_id.unwrap_or(1);
+    let cat_id = match ids.cat_id {
+        Some(cat_id) => cat_id,
+        None => cache.default_cat_ids.get(&map_id, game_id).ok_or_else(|| {
+            ServerError::new(ErrorType::NotFound, format!("No map with id {}", map_id))
+        })?,
+    };
+    let offset = ids.offset.unwrap_or(0).max(0);
+    let limit = results_limit(ids.limit, &config.proof);
+    let sp_map = SpMap::get_sp_map_review_page(
         pool.get_ref(),
         &map_id,
-        config.proof.results,
+        limit,
         cat_id,
-        ids.game_id.unwrap_or(1),
+        game_id,
+        offset,
+        &review,
     )
     .await?;
-    let mut ranked_vec = Vec::with_capacity(config.proof.results as usize);
-    for (i, entry) in sp_map.into_iter().enumerate() {
-        // TODO: Fix tied ranks.
-        ranked_vec.push(SpRanked {
-            map_data: entry,
-            rank: i as i32 + 1,
-            points: score(i as i32 + 1),
-        })
+    // The first entry on a later page might be tied with entries on the previous page.
+    let first_rank = match sp_map.first() {
+        Some(first) if offset > 0 => {
+            SpMap::count_better_scores(pool.get_ref(), &map_id, first.score, cat_id, game_id, &review)
+                .await? as i32
+                + 1
+        }
+        _ => 1,
+    };
+    let mut ranked = rank_sp_entries(sp_map, offset, first_rank);
+    if wants_csv(&req, export.format) {
+        return Ok(csv_response(ranked.into_iter().map(SpCsvRow::from)));
+    }
+    if format.formatted.unwrap_or(false) {
+        let tick_rate = Games::get_tick_rate(pool.get_ref(), game_id).await?;
+        for entry in ranked.iter_mut() {
+            entry.formatted_score = Some(format_score(entry.map_data.score, tick_rate));
+        }
     }
-    Ok(web::Json(ranked_vec))
+    Ok(HttpResponse::Ok().json(ranked))
 }
+/// **GET** method to return the world record progression on a singleplayer map, oldest first.
+///
+/// Only verified, unbanned scores are considered, and a score that ties the current record is not a new record.
+///
+/// ## Parameters:
+/// - `cat_id`
+///     - **Optional** - `i32` : The ID of the category, if left blank will use the default.
+/// - `game_id`
+///     - **Optional** - `i32` : The ID of the game, defaults to the base game (id = 1).
+///
+/// ## Example endpoints
+/// - **Default**
+///     - `/api/v1/map/sp/47458/wr_history`
+/// - **Specific Category**
+///     - `/api/v1/map/sp/47458/wr_history?cat_id=49`
+///
+/// Makes a call to the underlying [Changelog::get_wr_history]
+///
+/// ## Example JSON output
+///
+/// ```json
+/// [
+///     {
+///         "profile_number": "76561198039230536",
+///         "user_name": "Zypeh",
+///         "score": 2333,
+///         "timestamp": "2021-06-29T17:42:10",
+///         "improvement": null
+///     },
+///     {
+///         "profile_number": "76561198795823814",
+///         "user_name": "Royal",
+///         "score": 2326,
+///         "timestamp": "2021-07-06T09:11:04",
+///         "improvement": 7
+///     }
+/// ]
+/// ```
+#[utoipa::path(tag = "sp", params(OptIDs), responses((status = 200, body = Vec<WrHistory>)))]
+#[get("/map/sp/{map_id}/wr_history")]
+pub async fn sp_wr_history(
+    map_id: web::Path<String>,
+    ids: web::Query<OptIDs>,
+    cache: web::Data<CacheState>,
+    pool: web::Data<PgPool>,
+) -> Result<impl Responder> {
+    let map_id = map_id.into_inner();
+    let game_id = ids.game_id.unwrap_or(1);
+    let cat_id = match ids.cat_id {
+        Some(cat_id) => cat_id,
+        None => cache.default_cat_ids.get(&map_id, game_id).ok_or_else(|| {
+            ServerError::new(ErrorType::NotFound, format!("No map with id {}", map_id))
+        })?,
+    };
+    Ok(web::Json(
+        Changelog::get_wr_history(pool.get_ref(), &map_id, cat_id, game_id).await?,
+    ))
+}
+
+/// **GET** method to return a single player's rank on a singleplayer map, without fetching the whole map page.
+///
+/// Ranks and points match the [sp_map] page, tied scores share the same rank. Returns `null` if the player has no valid time on the map.
+///
+/// ## Parameters:
+/// - `profile_number`
+///     - **Required** - `String` : ID of the player.
+/// - `cat_id`
+///     - **Optional** - `i32` : The ID of the category, if left blank will use the default.
+/// - `game_id`
+///     - **Optional** - `i32` : The ID of the game, defaults to the base game (id = 1).
+///
+/// ## Example endpoints
+/// - **Default**
+///     - `/api/v1/map/sp/47458/rank?profile_number=76561198039230536`
+/// - **Specific Category**
+///     - `/api/v1/map/sp/47458/rank?profile_number=76561198039230536&cat_id=49`
+///
+/// Makes a call to the underlying [Changelog::get_player_rank]
+///
+/// ## Example JSON output
+///
+/// ```json
+/// {
+///     "rank": 3,
+///     "score": 2341,
+///     "points": 196.01
+/// }
+/// ```
+#[utoipa::path(tag = "sp", params(PlayerRankParams), responses((status = 200, body = Option<PlayerRank>)))]
+#[get("/map/sp/{map_id}/rank")]
+pub async fn sp_player_rank(
+    map_id: web::Path<String>,
+    params: web::Query<PlayerRankParams>,
+    cache: web::Data<CacheState>,
+    pool: web::Data<PgPool>,
+) -> Result<impl Responder> {
+    let map_id = map_id.into_inner();
+    let game_id = params.game_id.unwrap_or(1);
+    let cat_id = match params.cat_id {
+        Some(cat_id) => cat_id,
+        None => cache.default_cat_ids.get(&map_id, game_id).ok_or_else(|| {
+            ServerError::new(ErrorType::NotFound, format!("No map with id {}", map_id))
+        })?,
+    };
+    Ok(web::Json(
+        Changelog::get_player_rank(pool.get_ref(), &params.profile_number, &map_id, cat_id, game_id).await?,
+    ))
+}
+
+/// **GET** method to compare two players' best times on a singleplayer map, for head-to-head rivalry pages.
+///
+/// Each side has the same rank and points as [sp_player_rank], and is `null` if that player has no valid time on the map.
+/// `gap` is `p2`'s score minus `p1`'s, so it is positive when `p1` is faster, and is `null` unless both players have a time.
+///
+/// ## Parameters:
+/// - `p1`
+///     - **Required** - `String` : ID of the first player.
+/// - `p2`
+///     - **Required** - `String` : ID of the second player.
+/// - `cat_id`
+///     - **Optional** - `i32` : The ID of the category, if left blank will use the default.
+/// - `game_id`
+///     - **Optional** - `i32` : The ID of the game, defaults to the base game (id = 1).
+///
+/// ## Example endpoints
+/// - **Default**
+///     - `/api/v1/map/sp/47458/compare?p1=76561198039230536&p2=76561198040982247`
+/// - **Specific Category**
+///     - `/api/v1/map/sp/47458/compare?p1=76561198039230536&p2=76561198040982247&cat_id=49`
+///
+/// Makes a call to the underlying [Changelog::compare_players]
+///
+/// ## Example JSON output
+///
+/// ```json
+/// {
+///     "p1": {
+///         "rank": 3,
+///         "score": 2341,
+///         "points": 196.01
+///     },
+///     "p2": {
+///         "rank": 7,
+///         "score": 2376,
+///         "points": 187.42
+///     },
+///     "gap": 35,
+///     "formatted_gap": "0.35"
+/// }
+/// ```
+#[utoipa::path(tag = "sp", params(CompareParams), responses((status = 200, body = PlayerComparison)))]
+#[get("/map/sp/{map_id}/compare")]
+pub async fn sp_compare(
+    map_id: web::Path<String>,
+    params: web::Query<CompareParams>,
+    cache: web::Data<CacheState>,
+    pool: web::Data<PgPool>,
+) -> Result<impl Responder> {
+    let map_id = map_id.into_inner();
+    let game_id = params.game_id.unwrap_or(1);
+    let cat_id = match params.cat_id {
+        Some(cat_id) => cat_id,
+        None => cache.default_cat_ids.get(&map_id, game_id).ok_or_else(|| {
+            ServerError::new(ErrorType::NotFound, format!("No map with id {}", map_id))
+        })?,
+    };
+    Ok(web::Json(
+        Changelog::compare_players(pool.get_ref(), &params.p1, &params.p2, &map_id, cat_id, game_id).await?,
+    ))
+}
+
 /// **GET** method to return the profile number and score for all banned times on a given singleplayer map.
 ///
 /// ## Example Endpoins
@@ -157,6 +412,7 @@ pub async fn sp_map(
 ///     }
 /// ]
 /// ```
+#[utoipa::path(tag = "sp", responses((status = 200, body = Vec<SpBanned>)))]
 #[get("/sp/all_banned/{map_id}")]
 async fn sp_all_banned(map_id: web::Path<u64>, pool: web::Data<PgPool>) -> Result<impl Responder> {
     Ok(web::Json(
@@ -190,6 +446,7 @@ async fn sp_all_banned(map_id: web::Path<u64>, pool: web::Data<PgPool>) -> Resul
 /// ```json
 /// true
 /// ```
+#[utoipa::path(tag = "sp", params(ScoreParams), responses((status = 200, body = bool)))]
 #[get("/sp/banned/{map_id}")]
 async fn sp_banned(
     map_id: web::Path<String>,
@@ -198,11 +455,12 @@ async fn sp_banned(
     pool: web::Data<PgPool>,
 ) -> Result<impl Responder> {
     let map_id = map_id.into_inner();
-    let cat_id = Some(
-        params
-            .cat_id
-            .unwrap_or(cache.into_inner().default_cat_ids[&map_id]),
-    );
+    let cat_id = match params.cat_id {
+        Some(cat_id) => Some(cat_id),
+        None => Some(cache.default_cat_ids.get(&map_id, params.game_id.unwrap_or(1)).ok_or_else(|| {
+            ServerError::new(ErrorType::NotFound, format!("No map with id {}", map_id))
+        })?),
+    };
     let is_banned = Changelog::check_banned_scores(
         pool.get_ref(),
         ScoreLookup {
@@ -279,6 +537,7 @@ async fn sp_banned(
 ///     "pb_history": null
 /// }
 /// ```
+#[utoipa::path(tag = "sp", params(HistoryParams), responses((status = 200, body = SpPbHistory)))]
 #[get("/sp/history")]
 async fn sp_history(
     query: web::Query<HistoryParams>,
@@ -286,6 +545,12 @@ async fn sp_history(
     cache: web::Data<CacheState>,
 ) -> Result<impl Responder> {
     let query = query.into_inner();
+    let cat_id = match query.cat_id {
+        Some(cat_id) => cat_id,
+        None => cache.default_cat_ids.get(&query.map_id, query.game_id.unwrap_or(1)).ok_or_else(|| {
+            ServerError::new(ErrorType::NotFound, format!("No map with id {}", query.map_id))
+        })?,
+    };
     let user_data: UsersPage;
     // Get information for the player (user_name and avatar).
     match Users::get_user_data(pool.get_ref(), &query.profile_number).await? {
@@ -303,9 +568,7 @@ async fn sp_history(
         pool.get_ref(),
         &query.profile_number,
         &query.map_id,
-        query
-            .cat_id
-            .unwrap_or(cache.into_inner().default_cat_ids[&query.map_id]),
+        cat_id,
         query.game_id.unwrap_or(1),
     )
     .await
@@ -326,6 +589,74 @@ async fn sp_history(
     }
 }
 
+/// **GET** method to return a compact timeline of a player's PB history on a map, oldest first.
+///
+/// Each point has the rank after the score was submitted, and the points that rank was worth.
+/// Entries with no recorded rank carry forward the previous rank.
+///
+/// Query parameters represented as [HistoryParams]
+///
+/// ## Parameters:
+/// - `profile_number`
+///     - **Required** - `String` : ID for the player.
+/// - `map_id`
+///     - **Required** - `String` : ID for the map.
+/// - `cat_id`
+///     - **Optional** `i32` : ID for the category. Defaults to the default category for the map.
+/// - `game_id`
+///     - **Optional**  `i32` : ID for the game. Defaults to the base game, or ID = 1.
+///
+/// ## Example Endpoints:
+/// - **With Parametes**
+///     - `/api/v1/sp/history/timeline?map_id=47458&profile_number=76561198795823814`
+/// - **With cat_id**
+///     - `/api/v1/sp/history/timeline?map_id=47458&profile_number=76561198795823814&cat_id=49`
+///
+/// Makes a call to the underlying [Changelog::get_sp_pb_history]
+///
+/// # Example JSON output
+///
+/// ```json
+/// [
+///     {
+///         "timestamp": "2021-06-29T17:42:10",
+///         "score": 2333,
+///         "rank": 1,
+///         "points": 200.0
+///     },
+///     {
+///         "timestamp": "2021-07-06T09:11:04",
+///         "score": 2326,
+///         "rank": 1,
+///         "points": 200.0
+///     }
+/// ]
+/// ```
+#[utoipa::path(tag = "sp", params(HistoryParams), responses((status = 200, body = Vec<SpHistoryPoint>)))]
+#[get("/sp/history/timeline")]
+pub async fn sp_history_timeline(
+    query: web::Query<HistoryParams>,
+    pool: web::Data<PgPool>,
+    cache: web::Data<CacheState>,
+) -> Result<impl Responder> {
+    let query = query.into_inner();
+    let cat_id = match query.cat_id {
+        Some(cat_id) => cat_id,
+        None => cache.default_cat_ids.get(&query.map_id, query.game_id.unwrap_or(1)).ok_or_else(|| {
+            ServerError::new(ErrorType::NotFound, format!("No map with id {}", query.map_id))
+        })?,
+    };
+    let history = Changelog::get_sp_pb_history(
+        pool.get_ref(),
+        &query.profile_number,
+        &query.map_id,
+        cat_id,
+        query.game_id.unwrap_or(1),
+    )
+    .await?;
+    Ok(web::Json(build_pb_timeline(history)))
+}
+
 // TODO: Potentially depricate this funciton.
 /// **GET** method for validating an SP Score. Mainly used by our backend that pulls times from the Steam leaderboards.
 ///
@@ -360,11 +691,15 @@ async fn sp_history(
 ///     "banned": false
 /// }
 ///
+/// A score that does not beat the player's current PB returns a 422 naming the PB, unless `SUBMISSIONS.REQUIRE_IMPROVEMENT`
+/// is turned off, then `score_delta` is zero or negative.
+///
 /// ## Example JSON output where score is **not** valid:
 ///
 /// ```json
 /// false
 /// ```
+#[utoipa::path(tag = "sp", params(ScoreLookup), responses((status = 200, body = CalcValues)))]
 #[get("/sp/validate")]
 pub async fn sp_validate(
     pool: web::Data<PgPool>,
@@ -372,6 +707,13 @@ pub async fn sp_validate(
     cache: web::Data<CacheState>,
     config: web::Data<Config>,
 ) -> Result<impl Responder> {
+    let game_id = data.game_id.unwrap_or(1);
+    let cat_id = match data.cat_id {
+        Some(cat_id) => cat_id,
+        None => cache.default_cat_ids.get(&data.map_id, game_id).ok_or_else(|| {
+            ServerError::new(ErrorType::NotFound, format!("No map with id {}", data.map_id))
+        })?,
+    };
     let details = check_for_valid_score(
         pool.get_ref(),
         &SubmissionChangelog {
@@ -379,15 +721,14 @@ pub async fn sp_validate(
             profile_number: data.profile_number.clone(),
             score: data.score,
             map_id: data.map_id.clone(),
-            category_id: Some(
-                data.cat_id
-                    .unwrap_or(cache.into_inner().default_cat_ids[&data.map_id]),
-            ),
-            game_id: Some(data.game_id.unwrap_or(1)),
+            category_id: Some(cat_id),
+            game_id: Some(game_id),
             note: None,
             youtube_id: None,
+            dry_run: None,
         },
         config.proof.results,
+        config.submissions.require_improvement,
     )
     .await?;
     Ok(web::Json(details))
@@ -395,23 +736,85 @@ pub async fn sp_validate(
 
 // TODO: Depricate this for changelog uploads.
 /// Receives a new score to add to the DB.
+///
+/// Requires an auth token, see [AuthUser]. Users can only submit their own scores, and only admins can set `banned`, `verified` or `admin_note`.
+/// The `note` and `admin_note` are cleaned the same way as a submission's note, see [clean_note].
+///
+/// This is the endpoint the Steam leaderboard puller posts to, so an admin's entry keeps the `source` it is sent with
+/// (defaulting to `steam_auto`). Entries posted by other users are always `manual`, see [ChangelogSource].
+///
+/// The score is validated with [check_for_valid_score], which also sets `previous_id`, `score_delta`, `pre_rank` and
+/// `post_rank`, any values sent for these are ignored. A score below the category's `min_score` is flagged, see [flag_below_min_score].
+///
+/// Submissions are rate limited for each `profile_number` and each client address, see [RateLimiter] and [client_id].
+/// Live clients are sent an `sp_update` for the map, see [LiveUpdate].
+#[utoipa::path(tag = "sp", responses((status = 200, body = i64)))]
 #[post("/sp/post_score")]
 async fn sp_post_score(
+    req: HttpRequest,
     params: web::Json<ChangelogInsert>,
     pool: web::Data<PgPool>,
+    config: web::Data<Config>,
     cache: web::Data<CacheState>,
+    limiter: web::Data<RateLimiter>,
+    auth: AuthUser,
 ) -> Result<impl Responder> {
-    let id = Changelog::insert_changelog(pool.get_ref(), params.0).await?;
-    cache.update_current_state(SP_PREVIEWS, false).await;
+    let mut params = params.into_inner();
+    auth.check_changelog_insert(&params)?;
+    if !auth.is_admin() {
+        params.source = ChangelogSource::Manual;
+    }
+    params.note = clean_note(params.note, config.submissions.max_note_length)?;
+    params.admin_note = clean_note(params.admin_note, config.submissions.max_note_length)?;
+    // The client is checked first, so a client over its limit cannot use up the player's submissions.
+    limiter.check(&client_id(&req, &config.trusted_proxies)).await?;
+    limiter.check(&params.profile_number).await?;
+    let chapter = Maps::get_chapter_from_map_id(pool.get_ref(), params.map_id.clone())
+        .await?
+        .ok_or_else(|| ServerError::new(ErrorType::NotFound, format!("No map with id {}", params.map_id)))?;
+    if chapter.is_multiplayer {
+        return Err(ServerError::new(ErrorType::BadRequest, format!("Map {} is not a singleplayer map", params.map_id)));
+    }
+    let values = check_for_valid_score(
+        pool.get_ref(),
+        &SubmissionChangelog {
+            timestamp: "PLACEHOLDER".to_string(),
+            profile_number: params.profile_number.clone(),
+            score: params.score,
+            map_id: params.map_id.clone(),
+            category_id: Some(params.category_id),
+            game_id: Some(chapter.game_id),
+            note: None,
+            youtube_id: None,
+            dry_run: None,
+        },
+        config.proof.results,
+        config.submissions.require_improvement,
+    )
+    .await?;
+    params.previous_id = values.previous_id;
+    params.score_delta = values.score_delta;
+    params.pre_rank = values.pre_rank;
+    params.post_rank = values.post_rank;
+    params.banned |= values.banned;
+    flag_below_min_score(pool.get_ref(), &mut params).await?;
+    let map_id = params.map_id.clone();
+    let id = Changelog::insert_changelog(pool.get_ref(), params.clone()).await?;
+    notify_if_wr(pool.get_ref(), &config, &params);
+    cache.invalidate_map_preview(pool.get_ref(), &map_id).await;
+    cache.invalidate_aggregate_points().await;
+    cache.live.publish(LiveUpdate::SpUpdate { map_id });
     Ok(web::Json(id))
 }
 
-// TODO: Make this more ergonomic? Don't require all values.
-// TODO: Authentication should impact what a user can update.
 // TODO: Update to return all.
 /// **PUT** Method to update data for an existing singleplayer score.
 ///
 /// Expects a JSON object as input. Best practice is to pass the current JSON [Changelog] object, and alter the fields you want changed.
+/// To only send the fields being changed, use [sp_patch].
+///
+/// Requires an auth token, see [AuthUser]. Admins can change any field, other users can only change the `note` and `youtube_id`
+/// of their own scores. Anything else returns a 403.
 ///
 /// ## Parameters:
 /// - `id`
@@ -450,6 +853,12 @@ async fn sp_post_score(
 ///     - **Optional** : `bool` : If the run is verified.
 /// - `admin_note`
 ///     - **Optional** : `String` : Note by admin.
+/// - `updated`
+///     - **Optional** : `String` : The `updated` time of the entry when it was fetched, `null` if it has never been updated.
+///
+/// If the entry has been changed since it was fetched (its `updated` time is different) the update is rejected with a 409,
+/// fetch the entry again and reapply the changes. A changed `note` or `admin_note` is trimmed, and rejected with a 422 if
+/// it is longer than `SUBMISSIONS.MAX_NOTE_LENGTH`. Only admins can change `admin_note`.
 ///
 /// Makes a call to the underlying [Changelog::update_changelog]
 ///
@@ -458,14 +867,111 @@ async fn sp_post_score(
 /// ```json
 /// true
 /// ```
+#[utoipa::path(
+    tag = "sp",
+    responses((status = 200, body = Changelog), (status = 409, description = "The entry was changed since it was fetched")),
+)]
 #[put("/sp/update")]
 async fn sp_update(
     params: web::Json<Changelog>,
     pool: web::Data<PgPool>,
+    config: web::Data<Config>,
     cache: web::Data<CacheState>,
+    auth: AuthUser,
 ) -> Result<impl Responder> {
+    let mut params = params.into_inner();
+    let existing = match Changelog::get_changelog(pool.get_ref(), params.id).await? {
+        Some(existing) => existing,
+        None => {
+            return Err(ServerError::new(
+                ErrorType::NotFound,
+                format!("No changelog entry with id {}", params.id),
+            ))
+        }
+    };
+    auth.check_changelog_update(&existing, &params)?;
+    clean_notes(&mut params, &existing, &config)?;
     // TODO: Handle demo uploads.
-    let changelog_entry = Changelog::update_changelog(pool.get_ref(), params.0).await?;
-    cache.update_current_state(SP_PREVIEWS, false).await;
+    let changelog_entry = Changelog::update_changelog(pool.get_ref(), params)
+        .await?
+        .ok_or_else(|| changed_since_read(existing.id))?;
+    // The update may have banned or verified the score.
+    cache.invalidate_all_previews().await;
+    cache.invalidate_aggregate_points().await;
+    Ok(web::Json(changelog_entry))
+}
+
+/// **PATCH** Method to update only some of the fields for an existing singleplayer score.
+///
+/// Accepts the same fields as [sp_update], but only `id` is required. Fields that are left out keep their current value.
+/// Has the same auth requirements as [sp_update].
+///
+/// Makes a call to the underlying [Changelog::get_changelog], [Changelog::apply_patch] and [Changelog::update_changelog]
+///
+/// ## Example endpoints:
+/// - `/api/v1/sp/update`
+///
+/// ## Example JSON String
+/// ```json
+/// {
+///     "id": 127825,
+///     "note": "Updated note"
+/// }
+/// ```
+///
+/// ## Example JSON output
+///
+/// The updated [Changelog].
+#[utoipa::path(
+    tag = "sp",
+    responses((status = 200, body = Changelog), (status = 409, description = "The entry was changed while it was updated")),
+)]
+#[patch("/sp/update")]
+pub async fn sp_patch(
+    params: web::Json<ChangelogPatch>,
+    pool: web::Data<PgPool>,
+    config: web::Data<Config>,
+    cache: web::Data<CacheState>,
+    auth: AuthUser,
+) -> Result<impl Responder> {
+    let patch = params.into_inner();
+    let existing = match Changelog::get_changelog(pool.get_ref(), patch.id).await? {
+        Some(existing) => existing,
+        None => {
+            return Err(ServerError::new(
+                ErrorType::NotFound,
+                format!("No changelog entry with id {}", patch.id),
+            ))
+        }
+    };
+    let mut changelog_entry = existing.clone();
+    changelog_entry.apply_patch(patch);
+    auth.check_changelog_update(&existing, &changelog_entry)?;
+    clean_notes(&mut changelog_entry, &existing, &config)?;
+    let changelog_entry = Changelog::update_changelog(pool.get_ref(), changelog_entry)
+        .await?
+        .ok_or_else(|| changed_since_read(existing.id))?;
+    cache.invalidate_all_previews().await;
+    cache.invalidate_aggregate_points().await;
     Ok(web::Json(changelog_entry))
 }
+
+/// The error for an update made to an out of date copy of a changelog entry, see [Changelog::update_changelog].
+fn changed_since_read(cl_id: i64) -> ServerError {
+    ServerError::new(
+        ErrorType::Conflict,
+        format!("Changelog entry {} was changed since it was fetched, fetch it again and retry.", cl_id),
+    )
+}
+
+/// Cleans the `note` and `admin_note` of an update with [clean_note], notes that are not being changed are left as they are.
+fn clean_notes(update: &mut Changelog, existing: &Changelog, config: &Config) -> Result<()> {
+    let max_len = config.submissions.max_note_length;
+    if update.note != existing.note {
+        update.note = clean_note(update.note.take(), max_len)?;
+    }
+    if update.admin_note != existing.admin_note {
+        update.admin_note = clean_note(update.admin_note.take(), max_len)?;
+    }
+    Ok(())
+}
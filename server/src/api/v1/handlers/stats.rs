@@ -1,6 +1,7 @@
-use crate::{models::changelog::*, models::stats::*, tools::error::Result};
+use crate::{models::changelog::*, models::stats::*, tools::cache::CacheState, tools::error::Result};
 use actix_web::{get, web, Responder};
 use sqlx::PgPool;
+use utoipa::{IntoParams, ToSchema};
 
 /// **GET** method to query for the number of scores per-user across all maps.
 ///
@@ -29,6 +30,7 @@ use sqlx::PgPool;
 ///         "avatar": "https://steamcdn-a.akamaihd.net/steamcommunity/public/images/avatars/b8/b8800fc58ceede98b32949b802aed05c8c2d9ece_full.jpg"
 ///     },...]
 /// ```
+#[utoipa::path(tag = "stats", responses((status = 200, body = Vec<NumScores>)))]
 #[get("/stats/count_scores")]
 pub async fn count_scores(pool: web::Data<PgPool>) -> Result<impl Responder> {
     Ok(web::Json(
@@ -64,6 +66,7 @@ pub async fn count_scores(pool: web::Data<PgPool>) -> Result<impl Responder> {
 ///         "avatar": "https://steamcdn-a.akamaihd.net/steamcommunity/public/images/avatars/e7/e7a266be79a99e3bd758c4ae8f1d386309b57a9a_full.jpg"
 ///     },...]
 /// ```
+#[utoipa::path(tag = "stats", responses((status = 200, body = Vec<NumScores>)))]
 #[get("/stats/count_scores/{map_id}")]
 pub async fn count_scores_by_map(
     pool: web::Data<PgPool>,
@@ -74,7 +77,7 @@ pub async fn count_scores_by_map(
     ))
 }
 
-#[derive(Deserialize, Clone, Debug)]
+#[derive(Deserialize, Clone, Debug, ToSchema, IntoParams)]
 pub struct LimitQuery {
     pub limit: Option<i32>,
 }
@@ -146,6 +149,7 @@ pub struct LimitQuery {
 ///     ]
 /// }
 /// ```
+#[utoipa::path(tag = "stats", params(LimitQuery), responses((status = 200, body = Recap)))]
 #[get("/stats/recap")]
 pub async fn recap(
     pool: web::Data<PgPool>,
@@ -156,11 +160,13 @@ pub async fn recap(
     ))
 }
 
+#[utoipa::path(tag = "stats", responses((status = 200, body = Vec<Badges>)))]
 #[get("/stats/badges")]
 pub async fn badges(pool: web::Data<PgPool>) -> Result<impl Responder> {
     Ok(web::Json(Badges::get_bages(pool.get_ref()).await?))
 }
 
+#[utoipa::path(tag = "stats", responses((status = 200, body = Vec<BadgeEntries>)))]
 #[get("/stats/badges/{profile_number}")]
 pub async fn users_badges(
     pool: web::Data<PgPool>,
@@ -170,3 +176,36 @@ pub async fn users_badges(
         BadgeEntries::get_badge_by_user(pool.get_ref(), &profile_number).await?,
     ))
 }
+
+/// **GET** method for board-wide totals.
+///
+/// Returns the number of registered users, verified runs, stored demos and maps, and the number of runs submitted in the last 24 hours.
+/// The result is cached for [crate::tools::cache::STATS_OVERVIEW_TTL].
+///
+/// ## Example endpoint:
+///  - **Default**
+///     - `/api/v1/stats/overview`
+///
+/// Makes a call to the underlying [StatsOverview::get_overview]
+///
+/// ## Example JSON output
+///
+/// ```json
+/// {
+///     "registered_users": 1403,
+///     "verified_runs": 251287,
+///     "demos": 40925,
+///     "maps": 108,
+///     "runs_last_24h": 57
+/// }
+/// ```
+#[utoipa::path(tag = "stats", responses((status = 200, body = StatsOverview)))]
+#[get("/stats/overview")]
+pub async fn stats_overview(pool: web::Data<PgPool>, cache: web::Data<CacheState>) -> Result<impl Responder> {
+    if let Some(overview) = cache.get_stats_overview().await {
+        return Ok(web::Json(overview));
+    }
+    let overview = StatsOverview::get_overview(pool.get_ref()).await?;
+    cache.set_stats_overview(overview.clone()).await;
+    Ok(web::Json(overview))
+}
@@ -1,15 +1,30 @@
 use crate::{
     models::{
-        points::{PointsProfileWrapper, ProfilePage},
-        users::{AvatarInsert, Users},
+        changelog::{Changelog, MissingMap, PersonalBest},
+        chapters::GameID,
+        maps::IsCoop,
+        points::{ChapterPoints, PointsProfileWrapper, ProfilePage},
+        users::{
+            AvatarInsert, AvatarRefresh, BannedOrder, BannedUsersParams, Socials, UserBanParams, UserProfile, UserSearchParams,
+            Users, UsersDisplay, UsersDisplayDonation, UsersPage,
+        },
     },
+    tools::auth::AuthUser,
     tools::cache::CacheState,
-    tools::error::Result,
+    tools::config::Config,
+    tools::helpers::{get_chapter_points, get_player_pbs},
+    tools::moderation::moderate_new_user,
+    tools::error::{ErrorType, Result, ServerError},
 };
 use actix_web::{get, post, put, web, Responder};
 use sqlx::PgPool;
 use std::collections::HashMap;
 
+/// Number of banned users returned by [users_banned] when no `limit` is given.
+const DEFAULT_BANNED_LIMIT: i64 = 50;
+/// The most banned users that can be requested at once.
+const MAX_BANNED_LIMIT: i64 = 500;
+
 /// **GET** method for user information for a specific `profile_number`.
 ///
 /// ## Example endpoints:
@@ -36,6 +51,7 @@ use std::collections::HashMap;
 ///     "discord_id": null
 /// }
 /// ```
+#[utoipa::path(tag = "users", responses((status = 200, body = Option<Users>)))]
 #[get("/user/{profile_number}")]
 async fn user(
     pool: web::Data<PgPool>,
@@ -46,6 +62,112 @@ async fn user(
     ))
 }
 
+/// **GET** method for the header of a user's profile, the user's information along with the oldest and newest scores
+/// from [profile].
+///
+/// The `admin` level, `discord_id` and `needs_review` are only returned to admins, see [AuthUser]. Returns a 404 if the
+/// user does not exist.
+///
+/// ## Example endpoints:
+///  - **Default**
+///     - `/api/v1/users/76561198040982247`
+///
+/// Makes a call to the underlying [Users::get_user] and [Users::get_profile]
+///
+/// ## Example JSON output
+///
+/// ```json
+/// {
+///     "profile_number": "76561198040982247",
+///     "board_name": "Daniel",
+///     "steam_name": "BigDaniel",
+///     "banned": false,
+///     "registered": 0,
+///     "avatar": "https://steamcdn-a.akamaihd.net/steamcommunity/public/images/avatars/92/921d9d7402a6e766759bcc0b2ac7b91f1dcf0ad2_full.jpg",
+///     "twitch": "bigdaniel",
+///     "youtube": "/channel/UCtwF46_PUGCefgRfrcIXOZA",
+///     "title": null,
+///     "donation_amount": null,
+///     "country_id": null,
+///     "data": {
+///         "oldest_sp": {
+///             "map": "47763",
+///             "map_name": "Laser vs Turret",
+///             "score": 1423,
+///             "timestamp": "2019-04-18T20:51:22"
+///         },
+///         "newest_sp": {
+///             "map": "47755",
+///             "map_name": "Bridge the Gap",
+///             "score": 1628,
+///             "timestamp": "2021-08-22T18:36:59"
+///         },
+///         "oldest_coop": null,
+///         "newest_coop": null
+///     }
+/// }
+/// ```
+#[utoipa::path(tag = "users", responses((status = 200, body = UserProfile)))]
+#[get("/users/{profile_number}")]
+async fn user_profile(
+    pool: web::Data<PgPool>,
+    profile_number: web::Path<String>,
+    auth: Option<AuthUser>,
+) -> Result<impl Responder> {
+    let profile_number = profile_number.into_inner();
+    let found = match Users::get_user(pool.get_ref(), profile_number.clone()).await? {
+        Some(found) => found,
+        None => {
+            return Err(ServerError::new(
+                ErrorType::NotFound,
+                format!("No user with profile_number {profile_number}"),
+            ))
+        }
+    };
+    let data = Users::get_profile(pool.get_ref(), &profile_number).await?;
+    Ok(web::Json(UserProfile::new(found, data, auth.is_some_and(|auth| auth.is_admin()))))
+}
+
+/// **GET** method for the social media accounts linked to a user's profile.
+///
+/// Discord ids are only returned to admins, see [AuthUser]. Returns a 404 if the user does not exist.
+///
+/// ## Example endpoints:
+///  - **Default**
+///     - `/api/v1/users/76561198040982247/socials`
+///
+/// Makes a call to the underlying [Users::get_socials]
+///
+/// ## Example JSON output
+///
+/// ```json
+/// {
+///     "twitch": "bigdaniel",
+///     "youtube": "/channel/UCtwF46_PUGCefgRfrcIXOZA"
+/// }
+/// ```
+#[utoipa::path(tag = "users", responses((status = 200, body = Socials)))]
+#[get("/users/{profile_number}/socials")]
+async fn user_socials(
+    pool: web::Data<PgPool>,
+    profile_number: web::Path<String>,
+    auth: Option<AuthUser>,
+) -> Result<impl Responder> {
+    let profile_number = profile_number.into_inner();
+    match Users::get_socials(pool.get_ref(), profile_number.clone()).await? {
+        Some(mut socials) => {
+            if !auth.is_some_and(|auth| auth.is_admin()) {
+                socials.discord_id = None;
+            }
+            Ok(web::Json(socials))
+        }
+        None => Err(ServerError::new(
+            ErrorType::NotFound,
+            format!("No user with profile_number {profile_number}"),
+        )),
+    }
+}
+
 /// **GET** method to get all `profile_number`s of all banned users on the board.
 ///
 /// ## Example endpoints:
@@ -66,11 +188,107 @@ async fn user(
 ///     "76561197963045874",
 ///     "76561197965946552",...]
 /// ```
+#[utoipa::path(tag = "users", responses((status = 200, body = Vec<String>)))]
 #[get("/banned_users_all")]
 async fn banned_users_all(pool: web::Data<PgPool>) -> Result<impl Responder> {
     Ok(web::Json(Users::get_banned(pool.get_ref()).await?))
 }
 
+/// The shortest query accepted by [search_users], shorter queries match too many users to be useful.
+const MIN_SEARCH_LENGTH: usize = 3;
+
+/// **GET** method to search for users by board or steam name, used for autocomplete.
+///
+/// The search is case-insensitive and matches anywhere in the name, at most 50 users are returned.
+/// A query shorter than 3 characters returns no users.
+///
+/// ## Parameters
+/// - `q`
+///     - **Required** - `String` - The (partial) name to search for.
+///
+/// ## Example endpoints:
+///  - **With Parameters**
+///     - `/api/v1/search/users?q=zypeh`
+///
+/// Makes a call to the underlying [Users::check_board_name] and [Users::get_users_display]
+///
+/// ## Example JSON output
+///
+/// ```json
+/// [
+///     {
+///         "profile_number": "76561198039230536",
+///         "user_name": "Zypeh",
+///         "avatar": "https://steamcdn-a.akamaihd.net/steamcommunity/public/images/avatars/dc/dc4c1cfa8f0c5b0c85354825c7711f60c3714a41_full.jpg"
+///     }
+/// ]
+/// ```
+#[utoipa::path(tag = "users", params(UserSearchParams), responses((status = 200, body = Vec<UsersDisplay>)))]
+#[get("/search/users")]
+async fn search_users(
+    query: web::Query<UserSearchParams>,
+    pool: web::Data<PgPool>,
+) -> Result<impl Responder> {
+    let q = query.into_inner().q.unwrap_or_default();
+    let q = q.trim();
+    if q.chars().count() < MIN_SEARCH_LENGTH {
+        return Ok(web::Json(Vec::new()));
+    }
+    let profile_numbers = Users::check_board_name(pool.get_ref(), q).await?;
+    if profile_numbers.is_empty() {
+        return Ok(web::Json(Vec::new()));
+    }
+    Ok(web::Json(
+        Users::get_users_display(pool.get_ref(), &profile_numbers).await?,
+    ))
+}
+
+/// The most `profile_number`s accepted by [users_batch] in one request.
+const MAX_BATCH_SIZE: usize = 500;
+
+/// **POST** method to return the display data for many users at once, keyed by `profile_number`.
+///
+/// Expects a JSON array of `profile_number`s, at most 500. Unknown `profile_number`s are left out of the result.
+///
+/// ## Example endpoints:
+///  - **Default**
+///     - `/api/v1/users/batch`
+///
+/// Makes a call to the underlying [Users::get_users_data_batch]
+///
+/// ## Example JSON string
+///
+/// ```json
+/// ["76561198039230536", "76561198040982247"]
+/// ```
+///
+/// ## Example JSON output
+///
+/// ```json
+/// {
+///     "76561198039230536": {
+///         "user_name": "Zypeh",
+///         "avatar": "https://steamcdn-a.akamaihd.net/steamcommunity/public/images/avatars/dc/dc4c1cfa8f0c5b0c85354825c7711f60c3714a41_full.jpg"
+///     },...
+/// }
+/// ```
+#[utoipa::path(tag = "users", responses((status = 200, body = HashMap<String, UsersPage>)))]
+#[post("/users/batch")]
+async fn users_batch(
+    profile_numbers: web::Json<Vec<String>>,
+    pool: web::Data<PgPool>,
+) -> Result<impl Responder> {
+    if profile_numbers.len() > MAX_BATCH_SIZE {
+        return Err(ServerError::new(
+            ErrorType::BadRequest,
+            format!("At most {} profile numbers can be requested at once.", MAX_BATCH_SIZE),
+        ));
+    }
+    Ok(web::Json(
+        Users::get_users_data_batch(pool.get_ref(), &profile_numbers).await?,
+    ))
+}
+
 /// **GET** method to return a bool based on if a user is banned or not.
 ///
 /// ## Parameters
@@ -88,6 +306,7 @@ async fn banned_users_all(pool: web::Data<PgPool>) -> Result<impl Responder> {
 /// ``` json
 /// true
 /// ```
+#[utoipa::path(tag = "users", responses((status = 200, body = bool)))]
 #[get("/banned_user")]
 async fn banned_user(
     profile_number: web::Query<String>,
@@ -98,6 +317,62 @@ async fn banned_user(
     ))
 }
 
+/// **PUT** method to ban or unban a user.
+///
+/// Banning a user also bans all of their times. Unbanning only clears the user's flag, times that were banned
+/// will stay banned and have to be unbanned individually.
+///
+/// Requires an admin's auth token, see [AuthUser].
+///
+/// ## Parameters (expects valid JSON Object):
+/// - `profile_number`
+///     - **Required** - `String` : ID of the user being banned/unbanned.
+/// - `banned`
+///     - **Required** - `bool` : `true` to ban the user, `false` to unban.
+///
+/// ## Example endpoints:
+/// - `/api/v1/users/ban`
+///
+/// Makes a call to the underlying [Users::set_banned]
+///
+/// ## Example JSON String
+/// ```json
+/// {
+///     "profile_number": "76561198040982247",
+///     "banned": true
+/// }
+/// ```
+///
+/// ## Example JSON output
+///
+/// The number of times that were banned.
+///
+/// ```json
+/// 57
+/// ```
+#[utoipa::path(tag = "users", responses((status = 200, body = u64)))]
+#[put("/users/ban")]
+pub async fn users_ban(
+    pool: web::Data<PgPool>,
+    cache: web::Data<CacheState>,
+    params: web::Json<UserBanParams>,
+    auth: AuthUser,
+) -> Result<impl Responder> {
+    auth.require_admin()?;
+    let params = params.into_inner();
+    match Users::set_banned(pool.get_ref(), &params.profile_number, params.banned).await? {
+        Some(banned_times) => {
+            cache.invalidate_all_previews().await;
+            cache.invalidate_aggregate_points().await;
+            Ok(web::Json(banned_times))
+        }
+        None => Err(ServerError::new(
+            ErrorType::NotFound,
+            format!("No user with profile_number {}", params.profile_number),
+        )),
+    }
+}
+
 /// **POST** method to upload a new user to the boards.
 ///
 /// Accepts field values for a new [Users]
@@ -129,6 +404,8 @@ async fn banned_user(
 /// - `discord_id`
 ///     - **Optional** - `String` : Discord tag for the user's discord account.
 ///
+/// If name filtering is enabled, an offensive `board_name` is dropped and the user is flagged for review.
+///
 /// ## Example endpoints:       
 /// - `/api/v1/user`
 ///
@@ -150,10 +427,17 @@ async fn banned_user(
 /// }
 /// ```
 // TODO: Just return whole user, not boolean.
+#[utoipa::path(tag = "users", responses((status = 200, body = Users)))]
 #[post("/user")]
-async fn user_add(pool: web::Data<PgPool>, new_user: web::Json<Users>) -> Result<impl Responder> {
+async fn user_add(
+    pool: web::Data<PgPool>,
+    config: web::Data<Config>,
+    new_user: web::Json<Users>,
+) -> Result<impl Responder> {
+    let mut new_user = new_user.into_inner();
+    moderate_new_user(&config.moderation, &mut new_user);
     Ok(web::Json(
-        Users::insert_new_users(pool.get_ref(), new_user.0.clone()).await?,
+        Users::insert_new_users(pool.get_ref(), new_user).await?,
     ))
 }
 
@@ -165,7 +449,7 @@ async fn user_add(pool: web::Data<PgPool>, new_user: web::Json<Users>) -> Result
 ///
 /// Makes a call to the underlying [Users::update_avatar]
 ///
-/// Should return the *previous* avatar for the user.
+/// Should return the *previous* avatar for the user, `null` if they did not have one.
 ///
 /// ## Example JSON string
 ///
@@ -180,6 +464,7 @@ async fn user_add(pool: web::Data<PgPool>, new_user: web::Json<Users>) -> Result
 /// ```json
 /// "https://steamcdn-a.akamaihd.net/steamcommunity/public/images/avatars/39/3948dd3ae4d21772c845d4b3416bc7110b5aafb1_full.jpg"
 /// ```
+#[utoipa::path(tag = "users", responses((status = 200, body = Option<String>)))]
 #[put("/user/avatar/{profile_number}")]
 async fn avatar_update(
     pool: web::Data<PgPool>,
@@ -193,7 +478,51 @@ async fn avatar_update(
     ))
 }
 
-/// **GET** method to return all user information for donators on the boards.
+/// **PUT** method to refresh a user's avatar from Steam.
+///
+/// Returns a 404 if the user is not on the boards, or if Steam has no user for the `profile_number`.
+///
+/// ## Example endpoints:
+///  - **Default**
+///     - `/api/v1/users/76561198040982247/avatar/refresh`
+///
+/// Makes a call to the underlying [Users::new_from_steam] and [Users::update_avatar]
+///
+/// ## Example JSON output
+///
+/// ```json
+/// {
+///     "old_avatar": "https://steamcdn-a.akamaihd.net/steamcommunity/public/images/avatars/92/921d9d7402a6e766759bcc0b2ac7b91f1dcf0ad2_full.jpg",
+///     "new_avatar": "https://avatars.akamai.steamstatic.com/39/3948dd3ae4d21772c845d4b3416bc7110b5aafb1_full.jpg"
+/// }
+/// ```
+#[utoipa::path(tag = "users", responses((status = 200, body = AvatarRefresh)))]
+#[put("/users/{profile_number}/avatar/refresh")]
+pub async fn avatar_refresh(
+    pool: web::Data<PgPool>,
+    config: web::Data<Config>,
+    profile_number: web::Path<String>,
+) -> Result<impl Responder> {
+    let profile_number = profile_number.into_inner();
+    if Users::get_user(pool.get_ref(), profile_number.clone()).await?.is_none() {
+        return Err(ServerError::new(
+            ErrorType::NotFound,
+            format!("No user with profile_number {profile_number}"),
+        ));
+    }
+    let steam_user = Users::new_from_steam(&config.steam.api_key, &profile_number).await?;
+    let new_avatar = steam_user.avatar.unwrap_or_default();
+    let old_avatar = Users::update_avatar(pool.get_ref(), &profile_number, &new_avatar).await?;
+    Ok(web::Json(AvatarRefresh {
+        old_avatar,
+        new_avatar,
+    }))
+}
+
+/// **GET** method to return [UsersDisplayDonation](crate::models::users::UsersDisplayDonation) for donators on the boards,
+/// ordered by the amount donated.
+///
+/// Donation amounts that are not a valid number are returned as `0`.
 ///
 /// ## Example endpoints:
 ///  - **Default**
@@ -207,19 +536,12 @@ async fn avatar_update(
 /// [
 ///     {
 ///         "profile_number": "76561198029488151",
-///         "board_name": null,
-///         "steam_name": "BreweryJake",
-///         "banned": false,
-///         "registered": 0,
+///         "user_name": "BreweryJake",
 ///         "avatar": "https://steamcdn-a.akamaihd.net/steamcommunity/public/images/avatars/e0/e0b43f4bb265cdfc5e2486c67215b1082054e1a9_full.jpg",
-///         "twitch": "breweryjake",
-///         "youtube": "/user/HCMonstrLP",
-///         "title": "Donator",
-///         "admin": 0,
-///         "donation_amount": "101",
-///         "discord_id": null
+///         "donation_amount": 101.0
 ///     },...]
 /// ```
+#[utoipa::path(tag = "users", responses((status = 200, body = Vec<UsersDisplayDonation>)))]
 #[get("/donators")]
 async fn donators(pool: web::Data<PgPool>) -> Result<impl Responder> {
     Ok(web::Json(Users::get_donators(pool.get_ref()).await?))
@@ -243,9 +565,53 @@ async fn donators(pool: web::Data<PgPool>) -> Result<impl Responder> {
 ///         "avatar": "https://steamcdn-a.akamaihd.net/steamcommunity/public/images/avatars/f6/f6963c618e35e95ca84c631fcf7d6bf8dec72485_full.jpg"
 ///     },...]
 /// ```
+#[utoipa::path(tag = "users", responses((status = 200, body = Vec<UsersDisplay>)))]
 #[get("/wall_of_shame")]
 async fn wall_of_shame(pool: web::Data<PgPool>) -> Result<impl Responder> {
-    Ok(web::Json(Users::get_banned_display(pool.get_ref()).await?))
+    Ok(web::Json(
+        Users::get_banned_display(pool.get_ref(), BannedOrder::Name, 0, None).await?,
+    ))
+}
+
+/// **GET** method to page through [UsersDisplay](crate::models::users::UsersDisplay) for banned users on the boards.
+///
+/// ## Parameters:
+/// - `offset`
+///     - **Optional** - `i64` - Number of users to skip, defaults to 0.
+/// - `limit`
+///     - **Optional** - `i64` - Number of users to return, defaults to 50, at most 500.
+/// - `order`
+///     - **Optional** - `name` or `recent` - `name` orders alphabetically (default), `recent` lists the most recently banned first.
+///
+/// ## Example endpoints:
+///  - **Default**
+///     - `/api/v1/users/banned`
+///  - **Most recent**
+///     - `/api/v1/users/banned?order=recent&limit=10`
+///  - **Second page**
+///     - `/api/v1/users/banned?offset=50&limit=50`
+///
+/// Makes a call to the underlying [Users::get_banned_display]
+///
+/// ## Example JSON output
+///
+/// ```json
+/// [
+///     {
+///         "profile_number": "76561197960339145",
+///         "user_name": "louis vuitton",
+///         "avatar": "https://steamcdn-a.akamaihd.net/steamcommunity/public/images/avatars/f6/f6963c618e35e95ca84c631fcf7d6bf8dec72485_full.jpg"
+///     },...]
+/// ```
+#[utoipa::path(tag = "users", params(BannedUsersParams), responses((status = 200, body = Vec<UsersDisplay>)))]
+#[get("/users/banned")]
+async fn users_banned(pool: web::Data<PgPool>, params: web::Query<BannedUsersParams>) -> Result<impl Responder> {
+    let params = params.into_inner();
+    let offset = params.offset.unwrap_or(0).max(0);
+    let limit = params.limit.unwrap_or(DEFAULT_BANNED_LIMIT).clamp(1, MAX_BANNED_LIMIT);
+    Ok(web::Json(
+        Users::get_banned_display(pool.get_ref(), params.order.unwrap_or_default(), offset, Some(limit)).await?,
+    ))
 }
 
 /// **GET** method for most of a given user's profile information.
@@ -259,7 +625,7 @@ async fn wall_of_shame(pool: web::Data<PgPool>) -> Result<impl Responder> {
 ///     - `/api/v1/profile/76561198040982247`
 ///
 /// Makes a call to the underlying [Users::get_profile], then utilizes the [cache](crate::tools::cache::CacheState)
-/// to get rank information per-map.
+/// to get rank information per-map. The oldest/newest scores for a game mode are `null` if the user has no scores in it.
 ///
 /// ## Example JSON output
 ///
@@ -287,18 +653,20 @@ async fn wall_of_shame(pool: web::Data<PgPool>) -> Result<impl Responder> {
 ///         },
 ///         ...],
 ///        "data": {
-///            "oldest": {
-///                "map": "52759",
-///                "map_name": "Gel Maze",
-///                "score": 2768,
+///            "oldest_sp": {
+///                "map": "47763",
+///                "map_name": "Laser vs Turret",
+///                "score": 1423,
 ///                "timestamp": "2019-04-18T20:51:22"
 ///            },
-///            "newest": {
+///            "newest_sp": {
 ///                "map": "47755",
 ///                "map_name": "Bridge the Gap",
 ///                "score": 1628,
 ///                "timestamp": "2021-08-22T18:36:59"
-///            }
+///            },
+///            "oldest_coop": null,
+///            "newest_coop": null
 ///        },
 ///        "ranks": {
 ///            "47798": 24,
@@ -307,6 +675,7 @@ async fn wall_of_shame(pool: web::Data<PgPool>) -> Result<impl Responder> {
 ///        }
 ///    }
 /// ```
+#[utoipa::path(tag = "users", responses((status = 200, body = ProfilePage)))]
 #[get("/profile/{profile_number}")]
 async fn profile(
     pool: web::Data<PgPool>,
@@ -325,6 +694,161 @@ async fn profile(
     Ok(web::Json(profile_page))
 }
 
+/// **GET** method for a user's points on each chapter, calculated from their current ranks on each map's default category.
+///
+/// `max_possible` is the points for holding the world record on every map in the chapter. Banned and unverified times are not counted.
+///
+/// ## Parameters:
+/// - `game_id`
+///     - **Optional** - `i32` : The game to return chapters for, defaults to the base game.
+///
+/// ## Example endpoints:
+///  - **Default**
+///     - `/api/v1/users/76561198040982247/chapter_points`
+///  - **With game_id**
+///     - `/api/v1/users/76561198040982247/chapter_points?game_id=1`
+///
+/// Makes a call to the underlying [get_chapter_points]
+///
+/// ## Example JSON output
+///
+/// ```json
+/// [
+///     {
+///         "chapter_id": 1,
+///         "chapter_name": "Team Building",
+///         "points": 1040.2,
+///         "max_possible": 1200.0
+///     },
+///     {
+///         "chapter_id": 7,
+///         "chapter_name": "The Courtesy Call",
+///         "points": 512.005,
+///         "max_possible": 1800.0
+///     },...]
+/// ```
+#[utoipa::path(tag = "users", params(GameID), responses((status = 200, body = Vec<ChapterPoints>)))]
+#[get("/users/{profile_number}/chapter_points")]
+async fn chapter_points(
+    pool: web::Data<PgPool>,
+    profile_number: web::Path<String>,
+    query: web::Query<GameID>,
+) -> Result<impl Responder> {
+    let profile_number = profile_number.into_inner();
+    if Users::get_user(pool.get_ref(), profile_number.clone()).await?.is_none() {
+        return Err(ServerError::new(
+            ErrorType::NotFound,
+            format!("No user with profile_number {profile_number}"),
+        ));
+    }
+    let game_id = query.into_inner().game_id.unwrap_or(1);
+    Ok(web::Json(get_chapter_points(pool.get_ref(), &profile_number, game_id).await?))
+}
+
+/// **GET** method for a user's personal best on every map, with the rank and points for each.
+///
+/// Only verified, unbanned times on each map's default category are included, coop times once they are bundled with a partner.
+/// `rank` is `null` for times that are not ranked, such as a banned player's.
+///
+/// ## Parameters:
+/// - `game_id`
+///     - **Optional** - `i32` : The game to return personal bests for, defaults to the base game.
+///
+/// ## Example endpoints:
+///  - **Default**
+///     - `/api/v1/users/76561198040982247/pbs`
+///  - **With game_id**
+///     - `/api/v1/users/76561198040982247/pbs?game_id=1`
+///
+/// Makes a call to the underlying [get_player_pbs]
+///
+/// ## Example JSON output
+///
+/// ```json
+/// [
+///     {
+///         "id": 127825,
+///         "map_id": "47763",
+///         "map_name": "Laser vs Turret",
+///         "is_coop": false,
+///         "score": 1763,
+///         "timestamp": "2020-08-18T23:59:59",
+///         "category_id": 19,
+///         "demo_id": 21885,
+///         "youtube_id": null,
+///         "rank": 4,
+///         "points": 194.045
+///     },...]
+/// ```
+#[utoipa::path(tag = "users", params(GameID), responses((status = 200, body = Vec<PersonalBest>)))]
+#[get("/users/{profile_number}/pbs")]
+async fn user_pbs(
+    pool: web::Data<PgPool>,
+    profile_number: web::Path<String>,
+    query: web::Query<GameID>,
+) -> Result<impl Responder> {
+    let profile_number = profile_number.into_inner();
+    if Users::get_user(pool.get_ref(), profile_number.clone()).await?.is_none() {
+        return Err(ServerError::new(
+            ErrorType::NotFound,
+            format!("No user with profile_number {profile_number}"),
+        ));
+    }
+    let game_id = query.into_inner().game_id.unwrap_or(1);
+    Ok(web::Json(get_player_pbs(pool.get_ref(), &profile_number, game_id).await?))
+}
+
+/// **GET** method for the maps a player has not completed yet, with the world record on each one.
+///
+/// A map counts as completed once the player has a verified, unbanned time on its default category. Returns a 404 if there
+/// is no user with the `profile_number`.
+///
+/// ## Parameters:
+/// - `game_id`
+///     - **Optional** - `i32` : The game to return maps for, defaults to the base game.
+/// - `is_coop`
+///     - **Optional** - `bool` : Only coop (`true`) or singleplayer (`false`) maps, both are returned if left empty.
+///
+/// ## Example endpoints:
+///  - **Default**
+///     - `/api/v1/users/76561198040982247/missing`
+///  - **Singleplayer maps only**
+///     - `/api/v1/users/76561198040982247/missing?game_id=1&is_coop=false`
+///
+/// Makes a call to the underlying [Changelog::get_missing_maps]
+///
+/// ## Example JSON output
+///
+/// ```json
+/// [
+///     {
+///         "map_id": "47763",
+///         "map_name": "Laser vs Turret",
+///         "chapter_id": 9,
+///         "is_coop": false,
+///         "category_id": 19,
+///         "wr_score": 1698
+///     },...]
+/// ```
+#[utoipa::path(tag = "users", params(IsCoop), responses((status = 200, body = Vec<MissingMap>)))]
+#[get("/users/{profile_number}/missing")]
+async fn user_missing_maps(
+    pool: web::Data<PgPool>,
+    profile_number: web::Path<String>,
+    query: web::Query<IsCoop>,
+) -> Result<impl Responder> {
+    let profile_number = profile_number.into_inner();
+    if Users::get_user(pool.get_ref(), profile_number.clone()).await?.is_none() {
+        return Err(ServerError::new(
+            ErrorType::NotFound,
+            format!("No user with profile_number {profile_number}"),
+        ));
+    }
+    let query = query.into_inner();
+    let game_id = query.game_id.unwrap_or(1);
+    Ok(web::Json(Changelog::get_missing_maps(pool.get_ref(), &profile_number, game_id, query.is_coop).await?))
+}
+
 /// Pulls & clones the data from the ranks cache to be used for the profile endpoint.
 pub async fn profile_from_cache(
     cache: web::Data<CacheState>,
@@ -1,9 +1,72 @@
 use crate::controllers::changelog::build_filtered_changelog;
 use crate::models::admin::*;
-use crate::models::changelog::{BannedTimeDetails, ChangelogPage, ChangelogQueryParams};
+use crate::models::changelog::{BannedTimeDetails, ChangelogInsert, ChangelogPage, ChangelogQueryParams};
 use sqlx::PgPool;
+use std::collections::{HashMap, HashSet};
 
 impl Admin {
+    /// Checks every row of a changelog import against the rows it references, returns why each row cannot be imported.
+    ///
+    /// `None` means the row can be inserted, the rows are checked with one query per referenced table.
+    pub async fn get_import_errors(pool: &PgPool, rows: &[ChangelogInsert]) -> Result<Vec<Option<String>>, sqlx::Error> {
+        let profile_numbers: Vec<String> = rows.iter().map(|row| row.profile_number.clone()).collect();
+        let users: HashSet<String> =
+            sqlx::query_scalar(r#"SELECT profile_number FROM users WHERE profile_number = ANY($1)"#)
+                .bind(&profile_numbers)
+                .fetch_all(pool)
+                .await?
+                .into_iter()
+                .collect();
+        let map_ids: Vec<String> = rows.iter().map(|row| row.map_id.clone()).collect();
+        let maps: HashSet<String> = sqlx::query_scalar(r#"SELECT steam_id FROM maps WHERE steam_id = ANY($1)"#)
+            .bind(&map_ids)
+            .fetch_all(pool)
+            .await?
+            .into_iter()
+            .collect();
+        let cat_ids: Vec<i32> = rows.iter().map(|row| row.category_id).collect();
+        let categories: HashMap<i32, String> =
+            sqlx::query_as::<_, (i32, String)>(r#"SELECT id, map_id FROM categories WHERE id = ANY($1)"#)
+                .bind(&cat_ids)
+                .fetch_all(pool)
+                .await?
+                .into_iter()
+                .collect();
+        let demo_ids: Vec<i64> = rows.iter().filter_map(|row| row.demo_id).collect();
+        let demos: HashSet<i64> = sqlx::query_scalar(r#"SELECT id FROM demos WHERE id = ANY($1)"#)
+            .bind(&demo_ids)
+            .fetch_all(pool)
+            .await?
+            .into_iter()
+            .collect();
+        let coop_ids: Vec<i64> = rows.iter().filter_map(|row| row.coop_id).collect();
+        let bundles: HashSet<i64> = sqlx::query_scalar(r#"SELECT id FROM coop_bundled WHERE id = ANY($1)"#)
+            .bind(&coop_ids)
+            .fetch_all(pool)
+            .await?
+            .into_iter()
+            .collect();
+        Ok(rows
+            .iter()
+            .map(|row| {
+                if !users.contains(&row.profile_number) {
+                    return Some(format!("No user with profile_number {}", row.profile_number));
+                }
+                if !maps.contains(&row.map_id) {
+                    return Some(format!("No map with map_id {}", row.map_id));
+                }
+                if categories.get(&row.category_id) != Some(&row.map_id) {
+                    return Some(format!("Category {} is not a category on map {}", row.category_id, row.map_id));
+                }
+                if let Some(demo_id) = row.demo_id.filter(|id| !demos.contains(id)) {
+                    return Some(format!("No demo with id {}", demo_id));
+                }
+                row.coop_id
+                    .filter(|id| !bundles.contains(id))
+                    .map(|coop_id| format!("No coop bundle with id {}", coop_id))
+            })
+            .collect())
+    }
     /// Returns a changelog page that filtered to information for ease of use for admins.
     ///
     /// Uses [build_filtered_changelog] to build the filtered query.
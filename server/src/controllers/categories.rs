@@ -0,0 +1,32 @@
+use crate::models::categories::*;
+use sqlx::PgPool;
+
+impl Categories {
+    /// Returns every [Category] on a map with the category rules, ordered by category `id`.
+    ///
+    /// `is_default` is set for the category that matches the map's `default_cat_id`.
+    pub async fn get_for_map(pool: &PgPool, map_id: &str) -> Result<Vec<Category>, sqlx::Error> {
+        sqlx::query_as::<_, Category>(
+            r#"
+                SELECT categories.id, categories.name, category_rules.rules, category_rules.external_link,
+                    (categories.id = maps.default_cat_id) AS is_default
+                    FROM categories
+                    INNER JOIN maps ON (categories.map_id = maps.steam_id)
+                    LEFT JOIN category_rules ON (categories.rules_id = category_rules.id)
+                    WHERE categories.map_id = $1
+                    ORDER BY categories.id"#,
+        )
+        .bind(map_id)
+        .fetch_all(pool)
+        .await
+    }
+    /// Returns the `min_score` of a category, `None` if the category has no minimum or does not exist.
+    pub async fn get_min_score(pool: &PgPool, cat_id: i32) -> Result<Option<i32>, sqlx::Error> {
+        let min_score: Option<Option<i32>> =
+            sqlx::query_scalar(r#"SELECT min_score FROM categories WHERE id = $1"#)
+                .bind(cat_id)
+                .fetch_optional(pool)
+                .await?;
+        Ok(min_score.flatten())
+    }
+}
@@ -1,11 +1,76 @@
-use std::collections::HashMap;
-use sqlx::PgPool;
+use sqlx::{PgPool, Postgres, QueryBuilder};
 use chrono::NaiveDateTime;
 use crate::models::changelog::*;
+use crate::models::chapters::Games;
+use crate::models::coop::CoopPbEntry;
 use crate::models::users::Users;
+use crate::tools::cache::DefaultCategories;
+use crate::tools::helpers::{format_score, score, Transaction, MIN_SCORE_FLAG_NOTE, SUBMISSION_TIMESTAMP_FORMAT};
+
+impl ChangelogSource {
+    /// The value stored in the `changelog_source` enum.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ChangelogSource::Manual => "manual",
+            ChangelogSource::SteamAuto => "steam_auto",
+            ChangelogSource::Import => "import",
+        }
+    }
+}
 
 // Implementations of associated functions for Changelog
 impl Changelog {
+    /// Applies the fields set in a [ChangelogPatch], leaving the rest of the entry unchanged.
+    pub fn apply_patch(&mut self, patch: ChangelogPatch) {
+        if let Some(timestamp) = patch.timestamp {
+            self.timestamp = Some(timestamp);
+        }
+        if let Some(profile_number) = patch.profile_number {
+            self.profile_number = profile_number;
+        }
+        if let Some(score) = patch.score {
+            self.score = score;
+        }
+        if let Some(map_id) = patch.map_id {
+            self.map_id = map_id;
+        }
+        if let Some(demo_id) = patch.demo_id {
+            self.demo_id = Some(demo_id);
+        }
+        if let Some(banned) = patch.banned {
+            self.banned = banned;
+        }
+        if let Some(youtube_id) = patch.youtube_id {
+            self.youtube_id = Some(youtube_id);
+        }
+        if let Some(coop_id) = patch.coop_id {
+            self.coop_id = Some(coop_id);
+        }
+        if let Some(post_rank) = patch.post_rank {
+            self.post_rank = Some(post_rank);
+        }
+        if let Some(pre_rank) = patch.pre_rank {
+            self.pre_rank = Some(pre_rank);
+        }
+        if let Some(submission) = patch.submission {
+            self.submission = submission;
+        }
+        if let Some(note) = patch.note {
+            self.note = Some(note);
+        }
+        if let Some(category_id) = patch.category_id {
+            self.category_id = category_id;
+        }
+        if let Some(score_delta) = patch.score_delta {
+            self.score_delta = Some(score_delta);
+        }
+        if let Some(verified) = patch.verified {
+            self.verified = Some(verified);
+        }
+        if let Some(admin_note) = patch.admin_note {
+            self.admin_note = Some(admin_note);
+        }
+    }
     /// Search for a [Changelog] by ID, return the entire [Changelog].
     pub async fn get_changelog(pool: &PgPool, cl_id: i64) -> Result<Option<Changelog>, sqlx::Error> {
         sqlx::query_as::<_, Changelog>(r#"SELECT * FROM changelog WHERE id = $1"#)
@@ -64,6 +129,262 @@ impl Changelog {
             .fetch_all(pool)
             .await
     }
+    /// Returns every bundled coop run for a player on a map, newest first, with the partner for each run.
+    ///
+    /// The player can be on either side of the bundle, so the partner is whichever player is on the other side.
+    pub async fn get_coop_pb_history(
+        pool: &PgPool,
+        profile_number: &str,
+        map_id: &str,
+        cat_id: i32,
+        game_id: i32,
+    ) -> Result<Vec<CoopPbEntry>, sqlx::Error> {
+        sqlx::query_as::<_, CoopPbEntry>(r#"
+                SELECT changelog.*, partner.profile_number AS partner_profile_number,
+                    COALESCE(users.board_name, users.steam_name) AS partner_name
+                FROM changelog
+                    INNER JOIN coop_bundled ON (coop_bundled.id = changelog.coop_id)
+                    INNER JOIN maps ON (maps.steam_id = changelog.map_id)
+                    INNER JOIN chapters ON (chapters.id = maps.chapter_id)
+                    CROSS JOIN LATERAL (
+                        SELECT CASE WHEN coop_bundled.cl_id1 = changelog.id
+                            THEN coop_bundled.p_id2 ELSE coop_bundled.p_id1 END AS profile_number
+                    ) partner
+                    LEFT JOIN users ON (users.profile_number = partner.profile_number)
+                WHERE changelog.profile_number = $1
+                    AND changelog.map_id = $2
+                    AND changelog.category_id = $3
+                    AND chapters.game_id = $4
+                ORDER BY changelog.timestamp DESC NULLS LAST"#)
+            .bind(profile_number)
+            .bind(map_id)
+            .bind(cat_id)
+            .bind(game_id)
+            .fetch_all(pool)
+            .await
+    }
+    /// Returns a player's best verified, unbanned time on the default category of every map on `game_id` they have a time on,
+    /// ordered by chapter. Coop times are only counted once they are bundled with a partner.
+    ///
+    /// Singleplayer ranks match [crate::tools::helpers::rank_sp_entries], coop ranks are left as `None` and `points` as `0`,
+    /// see [crate::tools::helpers::get_player_pbs].
+    pub async fn get_all_pbs(pool: &PgPool, profile_number: &str, game_id: i32) -> Result<Vec<PersonalBest>, sqlx::Error> {
+        sqlx::query_as::<_, PersonalBest>(r#"
+                SELECT pb.id, pb.map_id, maps.name AS map_name, chapters.is_multiplayer AS is_coop, pb.score, pb.timestamp,
+                    pb.category_id, pb.demo_id, pb.youtube_id, ranked.rank
+                FROM (
+                    SELECT DISTINCT ON (changelog.map_id) changelog.*
+                    FROM changelog
+                        INNER JOIN maps ON (maps.steam_id = changelog.map_id)
+                        INNER JOIN chapters ON (chapters.id = maps.chapter_id)
+                    WHERE changelog.profile_number = $1
+                        AND changelog.verified = True
+                        AND changelog.banned = False
+                        AND changelog.category_id = maps.default_cat_id
+                        AND (chapters.is_multiplayer = False OR changelog.coop_id IS NOT NULL)
+                    ORDER BY changelog.map_id, changelog.score, changelog.timestamp
+                ) pb
+                    INNER JOIN maps ON (maps.steam_id = pb.map_id)
+                    INNER JOIN chapters ON (chapters.id = maps.chapter_id)
+                    LEFT JOIN (
+                        SELECT best.map_id, best.profile_number,
+                            RANK() OVER (PARTITION BY best.map_id ORDER BY best.score) AS rank
+                        FROM (
+                            SELECT changelog.map_id, changelog.profile_number, MIN(changelog.score) AS score
+                            FROM changelog
+                                INNER JOIN users ON (users.profile_number = changelog.profile_number)
+                                INNER JOIN maps ON (maps.steam_id = changelog.map_id)
+                                INNER JOIN chapters ON (chapters.id = maps.chapter_id)
+                            WHERE users.banned = False
+                                AND changelog.verified = True
+                                AND changelog.banned = False
+                                AND changelog.category_id = maps.default_cat_id
+                                AND chapters.is_multiplayer = False
+                                AND chapters.game_id = $2
+                            GROUP BY changelog.map_id, changelog.profile_number
+                        ) best
+                    ) ranked ON (ranked.map_id = pb.map_id AND ranked.profile_number = $1)
+                WHERE chapters.game_id = $2
+                ORDER BY chapters.id, maps.id"#)
+            .bind(profile_number)
+            .bind(game_id)
+            .fetch_all(pool)
+            .await
+    }
+    /// Returns every map on `game_id` the player has no verified, unbanned time on in the map's default category, ordered by
+    /// chapter. `is_coop` limits the maps to coop (`true`) or singleplayer (`false`) maps, both are included if `None`.
+    ///
+    /// Each map includes the world record on its default category, from verified, unbanned times by unbanned players.
+    pub async fn get_missing_maps(
+        pool: &PgPool,
+        profile_number: &str,
+        game_id: i32,
+        is_coop: Option<bool>,
+    ) -> Result<Vec<MissingMap>, sqlx::Error> {
+        sqlx::query_as::<_, MissingMap>(r#"
+                SELECT maps.steam_id AS map_id, maps.name AS map_name, maps.chapter_id, chapters.is_multiplayer AS is_coop,
+                    maps.default_cat_id AS category_id, wr.score AS wr_score
+                FROM maps
+                    INNER JOIN chapters ON (chapters.id = maps.chapter_id)
+                    LEFT JOIN LATERAL (
+                        SELECT MIN(changelog.score) AS score
+                        FROM changelog
+                            INNER JOIN users ON (users.profile_number = changelog.profile_number)
+                        WHERE changelog.map_id = maps.steam_id
+                            AND changelog.category_id = maps.default_cat_id
+                            AND changelog.verified = True
+                            AND changelog.banned = False
+                            AND users.banned = False
+                    ) wr ON True
+                WHERE chapters.game_id = $2
+                    AND ($3::BOOLEAN IS NULL OR chapters.is_multiplayer = $3)
+                    AND NOT EXISTS (
+                        SELECT 1 FROM changelog
+                        WHERE changelog.profile_number = $1
+                            AND changelog.map_id = maps.steam_id
+                            AND changelog.category_id = maps.default_cat_id
+                            AND changelog.verified = True
+                            AND changelog.banned = False
+                    )
+                ORDER BY chapters.id, maps.id"#)
+            .bind(profile_number)
+            .bind(game_id)
+            .bind(is_coop)
+            .fetch_all(pool)
+            .await
+    }
+    /// Returns the newest verified, unbanned runs across both singleplayer and coop maps on `game_id`, newest first.
+    ///
+    /// Each coop run has a changelog entry per player, only the first player's entry is returned.
+    pub async fn get_recent(pool: &PgPool, limit: i64, game_id: i32) -> Result<Vec<RecentActivity>, sqlx::Error> {
+        sqlx::query_as::<_, RecentActivity>(r#"
+                SELECT cl.id, cl.timestamp, cl.profile_number,
+                    COALESCE(u.board_name, u.steam_name) AS user_name, u.avatar,
+                    cl.map_id, map.name AS map_name, chapter.is_multiplayer AS is_coop,
+                    cl.score, cl.post_rank, cl.category_id,
+                    coop.p_id2 AS partner_profile_number,
+                    COALESCE(partner.board_name, partner.steam_name) AS partner_name,
+                    partner.avatar AS partner_avatar,
+                    cl.demo_id IS NOT NULL AS has_demo,
+                    cl.youtube_id IS NOT NULL AS has_youtube
+                FROM changelog AS cl
+                    INNER JOIN users AS u ON (u.profile_number = cl.profile_number)
+                    INNER JOIN maps AS map ON (map.steam_id = cl.map_id)
+                    INNER JOIN chapters AS chapter ON (chapter.id = map.chapter_id)
+                    LEFT JOIN coop_bundled AS coop ON (coop.id = cl.coop_id)
+                    LEFT JOIN users AS partner ON (partner.profile_number = coop.p_id2)
+                WHERE chapter.game_id = $1
+                    AND cl.timestamp IS NOT NULL
+                    AND cl.verified = True
+                    AND cl.banned = False
+                    AND u.banned = False
+                    AND (coop.cl_id2 IS NULL OR coop.cl_id2 != cl.id)
+                ORDER BY cl.timestamp DESC, cl.id DESC
+                LIMIT $2"#)
+            .bind(game_id)
+            .bind(limit)
+            .fetch_all(pool)
+            .await
+    }
+    /// Returns the world record progression for a map, oldest first. Each entry beat the best verified, unbanned score before it,
+    /// so a score that only ties the record is not included.
+    pub async fn get_wr_history(pool: &PgPool, map_id: &str, cat_id: i32, game_id: i32) -> Result<Vec<WrHistory>, sqlx::Error> {
+        sqlx::query_as::<_, WrHistory>(r#"
+                SELECT t.profile_number, t.user_name, t.score, t.timestamp, t.previous_best - t.score AS improvement
+                FROM (
+                    SELECT changelog.profile_number, changelog.score, changelog.timestamp,
+                        COALESCE(users.board_name, users.steam_name) AS user_name,
+                        MIN(changelog.score) OVER (
+                            ORDER BY changelog.timestamp, changelog.id
+                            ROWS BETWEEN UNBOUNDED PRECEDING AND 1 PRECEDING
+                        ) AS previous_best
+                    FROM changelog
+                        INNER JOIN users ON (users.profile_number = changelog.profile_number)
+                        INNER JOIN maps ON (maps.steam_id = changelog.map_id)
+                        INNER JOIN chapters ON (chapters.id = maps.chapter_id)
+                    WHERE changelog.map_id = $1
+                        AND changelog.category_id = $2
+                        AND chapters.game_id = $3
+                        AND changelog.timestamp IS NOT NULL
+                        AND changelog.verified = True
+                        AND changelog.banned = False
+                        AND users.banned = False
+                ) t
+                WHERE t.previous_best IS NULL OR t.score < t.previous_best
+                ORDER BY t.timestamp"#)
+            .bind(map_id)
+            .bind(cat_id)
+            .bind(game_id)
+            .fetch_all(pool)
+            .await
+    }
+    /// Returns the [PlayerRank] of a player's best time on a singleplayer map, `None` if the player has no valid time.
+    pub async fn get_player_rank(
+        pool: &PgPool,
+        profile_number: &str,
+        map_id: &str,
+        cat_id: i32,
+        game_id: i32,
+    ) -> Result<Option<PlayerRank>, sqlx::Error> {
+        let player_rank = sqlx::query_as::<_, PlayerRank>(
+            r#"
+                SELECT ranked.rank, ranked.score
+                FROM (
+                    SELECT t.profile_number, t.score, RANK() OVER (ORDER BY t.score) AS rank
+                    FROM (
+                        SELECT DISTINCT ON (changelog.profile_number) changelog.profile_number, changelog.score
+                        FROM changelog
+                        INNER JOIN users ON (users.profile_number = changelog.profile_number)
+                        INNER JOIN maps ON (changelog.map_id = maps.steam_id)
+                        INNER JOIN chapters ON (maps.chapter_id = chapters.id)
+                            WHERE changelog.map_id = $1
+                            AND users.banned = False
+                            AND changelog.verified = True
+                            AND changelog.banned = False
+                            AND changelog.category_id = $2
+                            AND chapters.game_id = $3
+                        ORDER BY changelog.profile_number, changelog.score ASC
+                    ) t
+                ) ranked
+                WHERE ranked.profile_number = $4"#,
+        )
+        .bind(map_id)
+        .bind(cat_id)
+        .bind(game_id)
+        .bind(profile_number)
+        .fetch_optional(pool)
+        .await?;
+        Ok(player_rank.map(|player_rank| PlayerRank {
+            points: score(player_rank.rank as i32),
+            ..player_rank
+        }))
+    }
+    /// Compares the best times of two players on a singleplayer map, each side is ranked with [Changelog::get_player_rank].
+    pub async fn compare_players(
+        pool: &PgPool,
+        p1: &str,
+        p2: &str,
+        map_id: &str,
+        cat_id: i32,
+        game_id: i32,
+    ) -> Result<PlayerComparison, sqlx::Error> {
+        let p1 = Changelog::get_player_rank(pool, p1, map_id, cat_id, game_id).await?;
+        let p2 = Changelog::get_player_rank(pool, p2, map_id, cat_id, game_id).await?;
+        let gap = match (&p1, &p2) {
+            (Some(p1), Some(p2)) => Some(p2.score - p1.score),
+            _ => None,
+        };
+        let formatted_gap = match gap {
+            Some(gap) => Some(format_score(gap, Games::get_tick_rate(pool, game_id).await?)),
+            None => None,
+        };
+        Ok(PlayerComparison {
+            p1,
+            p2,
+            gap,
+            formatted_gap,
+        })
+    }
     /// Deletes all references to a `demo_id` in `changelog`.
     pub async fn delete_references_to_demo(pool: &PgPool, demo_id: i64) -> Result<Vec<i64>, sqlx::Error> {
         sqlx::query_scalar(r#"UPDATE changelog SET demo_id = NULL WHERE demo_id = $1 RETURNING id;"#)
@@ -85,31 +406,166 @@ impl Changelog {
                 INSERT INTO changelog 
                 (timestamp, profile_number, score, map_id, demo_id, banned, 
                 youtube_id, coop_id, post_rank, pre_rank, submission, note,
-                category_id, score_delta, verified, admin_note) VALUES 
-                ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16)
+                category_id, score_delta, verified, admin_note, source) VALUES 
+                ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17)
                 RETURNING id"#)
             .bind(cl.timestamp).bind(cl.profile_number).bind(cl.score).bind(cl.map_id) // TODO: There has GOT to be a better way to do this... https://crates.io/crates/sqlxinsert ?
             .bind(cl.demo_id).bind(cl.banned).bind(cl.youtube_id).bind(cl.coop_id).bind(cl.post_rank)
             .bind(cl.pre_rank).bind(cl.submission).bind(cl.note).bind(cl.category_id)
-            .bind(cl.score_delta).bind(cl.verified).bind(cl.admin_note)
+            .bind(cl.score_delta).bind(cl.verified).bind(cl.admin_note).bind(cl.source)
             .fetch_one(pool)
             .await
     }
+    /// Inserts every entry in a single statement inside a transaction, either all entries are inserted or none are.
+    ///
+    /// Returns the new IDs in the same order as `cls`.
+    pub async fn insert_changelog_batch(pool: &PgPool, cls: Vec<ChangelogInsert>) -> Result<Vec<i64>, sqlx::Error> {
+        if cls.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut transaction = pool.begin().await?;
+        let mut query = QueryBuilder::<Postgres>::new(r#"
+                INSERT INTO changelog 
+                (timestamp, profile_number, score, map_id, demo_id, banned, 
+                youtube_id, coop_id, post_rank, pre_rank, submission, note,
+                category_id, score_delta, verified, admin_note, source) "#);
+        query.push_values(cls, |mut row, cl| {
+            row.push_bind(cl.timestamp).push_bind(cl.profile_number).push_bind(cl.score).push_bind(cl.map_id)
+                .push_bind(cl.demo_id).push_bind(cl.banned).push_bind(cl.youtube_id).push_bind(cl.coop_id)
+                .push_bind(cl.post_rank).push_bind(cl.pre_rank).push_bind(cl.submission).push_bind(cl.note)
+                .push_bind(cl.category_id).push_bind(cl.score_delta).push_bind(cl.verified).push_bind(cl.admin_note)
+                .push_bind(cl.source);
+        });
+        // Postgres returns the rows of a multi-row insert in the order of the VALUES list.
+        query.push(" RETURNING id");
+        let ids = query.build_query_scalar::<i64>().fetch_all(&mut *transaction).await?;
+        transaction.commit().await?;
+        Ok(ids)
+    }
     /// Updates all fields (except ID) for a given changelog entry. Returns the updated [Changelog].
-    pub async fn update_changelog(pool: &PgPool, update: Changelog) -> Result<Changelog, sqlx::Error> {
+    ///
+    /// `updated` is the version of the entry the update was made from, `None` is returned without updating the entry if it
+    /// has been changed since (or does not exist).
+    pub async fn update_changelog(pool: &PgPool, update: Changelog) -> Result<Option<Changelog>, sqlx::Error> {
         sqlx::query_as::<_, Changelog>(r#"UPDATE changelog 
                 SET timestamp = $1, profile_number = $2, score = $3, map_id = $4, demo_id = $5, banned = $6, 
                 youtube_id = $7, coop_id = $8, post_rank = $9, pre_rank = $10, submission = $11, note = $12,
                 category_id = $13, score_delta = $14, verified = $15, admin_note = $16
-                WHERE id = $17 RETURNING *"#)
+                WHERE id = $17 AND updated IS NOT DISTINCT FROM $18 RETURNING *"#)
             .bind(update.timestamp).bind(update.profile_number).bind(update.score).bind(update.map_id) 
             .bind(update.demo_id).bind(update.banned).bind(update.youtube_id).bind(update.coop_id)
             .bind(update.post_rank).bind(update.pre_rank).bind(update.submission).bind(update.note)
             .bind(update.category_id).bind(update.score_delta).bind(update.verified).bind(update.admin_note)
-            .bind(update.id)
-            .fetch_one(pool)
+            .bind(update.id).bind(update.updated)
+            .fetch_optional(pool)
+            .await
+    }
+    /// Returns the verification queue, every entry that is neither verified nor banned, oldest first.
+    ///
+    /// Submissions that were banned for being below their category's `min_score` are included until they are reviewed,
+    /// see [crate::tools::helpers::flag_below_min_score]. Reviewing an entry sets `verified`, which takes it out of the
+    /// queue either way.
+    ///
+    /// Entries from banned users are left out, as their times are already banned. `game_id` limits the queue to one game,
+    /// all games are included if `None`.
+    pub async fn get_unverified(pool: &PgPool, game_id: Option<i32>) -> Result<Vec<ChangelogPage>, sqlx::Error> {
+        sqlx::query_as::<_, ChangelogPage>(r#"
+                SELECT cl.id, cl.timestamp, cl.profile_number, cl.score, cl.map_id, cl.demo_id, cl.banned,
+                    cl.youtube_id, cl.previous_id, cl.coop_id, cl.post_rank, cl.pre_rank, cl.submission, cl.note,
+                    cl.category_id, cl.score_delta, cl.verified, cl.admin_note, cl.source, map.name AS map_name,
+                    COALESCE(u.board_name, u.steam_name) AS user_name, u.avatar,
+                    COALESCE(p1.board_name, p1.steam_name) AS blue_name,
+                    COALESCE(p2.board_name, p2.steam_name) AS orange_name,
+                    p1.avatar AS blue_avatar, p2.avatar AS orange_avatar
+                FROM changelog AS cl
+                    INNER JOIN users AS u ON (u.profile_number = cl.profile_number)
+                    INNER JOIN maps AS map ON (map.steam_id = cl.map_id)
+                    INNER JOIN chapters AS chapter ON (map.chapter_id = chapter.id)
+                    LEFT JOIN coop_bundled AS coop ON (cl.coop_id = coop.id)
+                    LEFT JOIN users AS p1 ON coop.p_id1 = p1.profile_number
+                    LEFT JOIN users AS p2 ON coop.p_id2 = p2.profile_number
+                WHERE cl.verified IS NOT TRUE
+                    AND (cl.banned = 'false' OR (cl.verified IS NULL AND cl.admin_note = $2))
+                    AND u.banned = 'false'
+                    AND ($1::INTEGER IS NULL OR chapter.game_id = $1)
+                ORDER BY cl.timestamp ASC NULLS LAST, cl.id ASC"#)
+            .bind(game_id)
+            .bind(MIN_SCORE_FLAG_NOTE)
+            .fetch_all(pool)
             .await
     }
+    /// Sets `verified` and `banned` on an entry from an admin's decision, returns the new [Changelog].
+    ///
+    /// An approved entry is verified and unbanned, a rejected entry is banned and left unverified.
+    /// The existing `admin_note` is kept if `admin_note` is `None`. Returns `None` if there is no entry with `cl_id`.
+    pub async fn set_verification(
+        pool: &PgPool,
+        cl_id: i64,
+        approved: bool,
+        admin_note: Option<String>,
+    ) -> Result<Option<Changelog>, sqlx::Error> {
+        sqlx::query_as::<_, Changelog>(r#"UPDATE changelog
+                SET verified = $1, banned = NOT $1, admin_note = COALESCE($2, admin_note)
+                WHERE id = $3 RETURNING *"#)
+            .bind(approved)
+            .bind(admin_note)
+            .bind(cl_id)
+            .fetch_optional(pool)
+            .await
+    }
+    /// Rebuilds a player's PB chain on a map, for when manual edits or deletes have left it inconsistent.
+    ///
+    /// Unbanned entries are ordered by `timestamp`, and each entry is linked to the one before it through `previous_id`,
+    /// with `score_delta` recalculated from the previous score. `post_rank` is the rank the entry had when it was set,
+    /// and `pre_rank` the rank the previous entry had at that time, based on other players' verified, unbanned times.
+    /// Banned entries are left untouched.
+    ///
+    /// Returns the rebuilt chain, oldest first.
+    pub async fn rebuild_pb_chain(
+        pool: &PgPool,
+        profile_number: &str,
+        map_id: &str,
+        cat_id: i32,
+    ) -> Result<Vec<Changelog>, sqlx::Error> {
+        let mut transaction = pool.begin().await?;
+        let chain = sqlx::query_as::<_, Changelog>(r#"
+                SELECT * FROM changelog
+                WHERE profile_number = $1
+                    AND map_id = $2
+                    AND category_id = $3
+                    AND banned = 'false'
+                ORDER BY timestamp ASC NULLS LAST, id ASC
+                FOR UPDATE"#)
+            .bind(profile_number)
+            .bind(map_id)
+            .bind(cat_id)
+            .fetch_all(&mut *transaction)
+            .await?;
+        let mut rebuilt: Vec<Changelog> = Vec::with_capacity(chain.len());
+        for mut entry in chain {
+            let previous = rebuilt.last();
+            entry.previous_id = previous.map(|prev| prev.id);
+            entry.score_delta = previous.map(|prev| prev.score - entry.score);
+            entry.pre_rank = match previous {
+                Some(prev) => Some(rank_at(&mut transaction, &entry, prev.score).await?),
+                None => None,
+            };
+            entry.post_rank = Some(rank_at(&mut transaction, &entry, entry.score).await?);
+            let entry = sqlx::query_as::<_, Changelog>(r#"UPDATE changelog
+                    SET previous_id = $1, score_delta = $2, pre_rank = $3, post_rank = $4
+                    WHERE id = $5 RETURNING *"#)
+                .bind(entry.previous_id)
+                .bind(entry.score_delta)
+                .bind(entry.pre_rank)
+                .bind(entry.post_rank)
+                .bind(entry.id)
+                .fetch_one(&mut *transaction)
+                .await?;
+            rebuilt.push(entry);
+        }
+        transaction.commit().await?;
+        Ok(rebuilt)
+    }
     /// Updates `demo_id` in a given changelog entry, returns the new [Changelog].
     pub async fn update_demo_id_in_changelog(pool: &PgPool, cl_id: i64, demo_id: i64) -> Result<Changelog, sqlx::Error> {
         sqlx::query_as::<_, Changelog>(r#"UPDATE changelog 
@@ -119,6 +575,55 @@ impl Changelog {
             .fetch_one(pool)
             .await
     }
+    /// Inserts a new changelog entry as part of a transaction, returns the inserted [Changelog].
+    pub async fn transaction_insert_changelog(transaction: &mut Transaction<'_>, cl: ChangelogInsert) -> Result<Changelog, sqlx::Error> {
+        sqlx::query_as::<_, Changelog>(r#"
+                INSERT INTO changelog 
+                (timestamp, profile_number, score, map_id, demo_id, banned, 
+                youtube_id, coop_id, post_rank, pre_rank, submission, note,
+                category_id, score_delta, verified, admin_note, source) VALUES 
+                ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17)
+                RETURNING *"#)
+            .bind(cl.timestamp).bind(cl.profile_number).bind(cl.score).bind(cl.map_id)
+            .bind(cl.demo_id).bind(cl.banned).bind(cl.youtube_id).bind(cl.coop_id).bind(cl.post_rank)
+            .bind(cl.pre_rank).bind(cl.submission).bind(cl.note).bind(cl.category_id)
+            .bind(cl.score_delta).bind(cl.verified).bind(cl.admin_note).bind(cl.source)
+            .fetch_one(&mut **transaction)
+            .await
+    }
+    /// Updates all fields (except ID) for a given changelog entry as part of a transaction. Returns the updated [Changelog].
+    #[allow(dead_code)]
+    pub async fn transaction_update_changelog(transaction: &mut Transaction<'_>, update: Changelog) -> Result<Changelog, sqlx::Error> {
+        sqlx::query_as::<_, Changelog>(r#"UPDATE changelog 
+                SET timestamp = $1, profile_number = $2, score = $3, map_id = $4, demo_id = $5, banned = $6, 
+                youtube_id = $7, coop_id = $8, post_rank = $9, pre_rank = $10, submission = $11, note = $12,
+                category_id = $13, score_delta = $14, verified = $15, admin_note = $16
+                WHERE id = $17 RETURNING *"#)
+            .bind(update.timestamp).bind(update.profile_number).bind(update.score).bind(update.map_id) 
+            .bind(update.demo_id).bind(update.banned).bind(update.youtube_id).bind(update.coop_id)
+            .bind(update.post_rank).bind(update.pre_rank).bind(update.submission).bind(update.note)
+            .bind(update.category_id).bind(update.score_delta).bind(update.verified).bind(update.admin_note)
+            .bind(update.id)
+            .fetch_one(&mut **transaction)
+            .await
+    }
+    /// Updates `demo_id` in a given changelog entry as part of a transaction, returns the new [Changelog].
+    pub async fn transaction_update_demo_id_in_changelog(transaction: &mut Transaction<'_>, cl_id: i64, demo_id: i64) -> Result<Changelog, sqlx::Error> {
+        sqlx::query_as::<_, Changelog>(r#"UPDATE changelog 
+                SET demo_id = $1 WHERE id = $2 RETURNING *;"#)
+            .bind(demo_id)
+            .bind(cl_id)
+            .fetch_one(&mut **transaction)
+            .await
+    }
+    /// Deletes a changelog entry on the given ID as part of a transaction.
+    #[allow(dead_code)]
+    pub async fn transaction_delete_changelog(transaction: &mut Transaction<'_>, cl_id: i64) -> Result<Changelog, sqlx::Error> {
+        sqlx::query_as::<_, Changelog>(r#"DELETE FROM changelog WHERE id = $1 RETURNING *"#)
+            .bind(cl_id)
+            .fetch_one(&mut **transaction)
+            .await
+    }
     #[allow(dead_code)]
     /// Deletes a changelog entry on the give ID.
     pub async fn delete_changelog(pool: &PgPool, cl_id: i64) -> Result<Changelog, sqlx::Error> {
@@ -140,7 +645,12 @@ impl ChangelogPage {
         pool: &PgPool,
         params: ChangelogQueryParams,
     ) -> Result<Vec<ChangelogPage>, sqlx::Error> {        
-        let query_string = build_filtered_changelog(pool, params, None).await?;
+        let query_string = match build_filtered_changelog(pool, params, None).await {
+            Ok(query_string) => query_string,
+            // No users matched the `nick_name`, so there are no entries to return.
+            Err(sqlx::Error::RowNotFound) => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
         let res = sqlx::query_as::<_, ChangelogPage>(&query_string)
             .fetch_all(pool)
             .await?;
@@ -189,7 +699,7 @@ pub async fn build_filtered_changelog(pool: &PgPool, params: ChangelogQueryParam
         r#" 
         SELECT cl.id, cl.timestamp, cl.profile_number, cl.score, cl.map_id, cl.demo_id, cl.banned,
             cl.youtube_id, cl.previous_id, cl.coop_id, cl.post_rank, cl.pre_rank, cl.submission, cl.note,
-            cl.category_id, cl.score_delta, cl.verified, cl.admin_note, map.name AS map_name,
+            cl.category_id, cl.score_delta, cl.verified, cl.admin_note, cl.source, map.name AS map_name,
             COALESCE(u.board_name, u.steam_name) AS user_name, u.avatar,
             COALESCE(p1.board_name, p1.steam_name) AS blue_name,
             COALESCE(p2.board_name, p2.steam_name) AS orange_name,
@@ -236,7 +746,7 @@ pub async fn build_filtered_changelog(pool: &PgPool, params: ChangelogQueryParam
         filters.push(format!("cl.map_id = '{}'\n", &chamber));
     }
     if let Some(profile_number) = params.profile_number {
-        filters.push(format!("cl.profile_number = {}\n", &profile_number));
+        filters.push(format!("cl.profile_number = '{}'\n", profile_number.replace('\'', "''")));
     } else if let Some(nick_name) = params.nick_name {
         let mut profile_numbers = Users::check_board_name(pool, &nick_name).await?;
         if !profile_numbers.is_empty()
@@ -262,6 +772,24 @@ pub async fn build_filtered_changelog(pool: &PgPool, params: ChangelogQueryParam
             return Err(sqlx::Error::RowNotFound);
         }
     }
+    if let Some(cat_id) = params.cat_id {
+        filters.push(format!("cl.category_id = {}\n", cat_id));
+    }
+    if let Some(banned) = params.banned {
+        filters.push(format!("cl.banned = {}\n", banned));
+    }
+    if let Some(submission) = params.submission {
+        filters.push(format!("cl.submission = {}\n", submission));
+    }
+    if let Some(source) = params.source {
+        filters.push(format!("cl.source = '{}'\n", source.as_str()));
+    }
+    if let Some(start_date) = params.start_date {
+        filters.push(format!("cl.timestamp >= '{}'\n", start_date));
+    }
+    if let Some(end_date) = params.end_date {
+        filters.push(format!("cl.timestamp < '{}'::date + 1\n", end_date));
+    }
     if let Some(first) = params.first {
         filters.push(format!("cl.id > {}\n", &first));
     } else if let Some(last) = params.last {
@@ -286,6 +814,9 @@ pub async fn build_filtered_changelog(pool: &PgPool, params: ChangelogQueryParam
         // TODO: Update to use the correct
         query_string = format!("{} LIMIT 500\n", query_string);
     }
+    if let Some(offset) = params.offset {
+        query_string = format!("{} OFFSET {}\n", query_string, offset);
+    }
     Ok(query_string)
 }
 
@@ -303,30 +834,66 @@ impl Default for ChangelogQueryParams {
             yt: None,
             first: None,
             last: None,
+            cat_id: None,
+            banned: None,
+            submission: None,
+            source: None,
+            start_date: None,
+            end_date: None,
+            offset: None,
         }
     }
 }
 
+/// The rank `score` would have had on the entry's map when the entry was set, against other players' verified, unbanned
+/// times from before it. Tied scores share a rank.
+async fn rank_at(transaction: &mut Transaction<'_>, entry: &Changelog, score: i32) -> Result<i32, sqlx::Error> {
+    let better: i64 = sqlx::query_scalar(r#"
+            SELECT COUNT(*) FROM (
+                SELECT changelog.profile_number, MIN(changelog.score) AS best
+                FROM changelog
+                    INNER JOIN users ON (users.profile_number = changelog.profile_number)
+                WHERE changelog.map_id = $1
+                    AND changelog.category_id = $2
+                    AND changelog.profile_number <> $3
+                    AND changelog.verified = 'true'
+                    AND changelog.banned = 'false'
+                    AND users.banned = 'false'
+                    AND ($4::TIMESTAMP IS NULL OR changelog.timestamp < $4)
+                GROUP BY changelog.profile_number
+            ) AS others
+            WHERE others.best < $5"#)
+        .bind(&entry.map_id)
+        .bind(entry.category_id)
+        .bind(&entry.profile_number)
+        .bind(entry.timestamp)
+        .bind(score)
+        .fetch_one(&mut **transaction)
+        .await?;
+    Ok(better as i32 + 1)
+}
+
 // TODO: Handle Autosubmit
 impl ChangelogInsert {
     /// Create a [crate::models::changelog::ChangelogInsert] from a [crate::models::changelog::SubmissionChangelog]
     pub async fn new_from_submission(
         params: SubmissionChangelog,
         details: CalcValues,
-        cache: &HashMap<String, i32>,
+        cache: &DefaultCategories,
     ) -> ChangelogInsert {
         ChangelogInsert {
-            timestamp: match NaiveDateTime::parse_from_str(&params.timestamp, "%Y-%m-%d %H:%M:%S") {
-                Ok(val) => Some(val),
-                Err(_) => None,
-            },
+            timestamp: NaiveDateTime::parse_from_str(&params.timestamp, SUBMISSION_TIMESTAMP_FORMAT).ok(),
             profile_number: params.profile_number.clone(),
             score: params.score,
             map_id: params.map_id.clone(),
             youtube_id: params.youtube_id,
             note: params.note,
-            category_id: params.category_id.unwrap_or_else(|| cache[&params.map_id]),
+            category_id: params
+                .category_id
+                .or_else(|| cache.get(&params.map_id, params.game_id.unwrap_or(1)))
+                .unwrap_or_default(),
             submission: 1,
+            source: ChangelogSource::Manual,
             previous_id: details.previous_id,
             post_rank: details.post_rank,
             pre_rank: details.pre_rank,
@@ -1,5 +1,7 @@
-use crate::models::chapters::{ChapterQueryParams, Chapters, Games};
+use crate::models::chapters::{ChapterMap, ChapterQueryParams, ChapterWithMaps, Chapters, Games};
+use crate::tools::helpers::DEFAULT_TICK_RATE;
 use sqlx::PgPool;
+use std::collections::HashMap;
 
 impl Chapters {
     /// Returns the map ids for a given chapter.
@@ -31,7 +33,7 @@ impl Chapters {
     /// Gets the [Games] by the given `chapter_id`.
     pub async fn get_chapter_game(pool: &PgPool, chapter_id: i32) -> Result<Option<Games>, sqlx::Error> {
         sqlx::query_as::<_, Games>(
-            r#"SELECT games.id, games.game_name 
+            r#"SELECT games.id, games.game_name, games.tick_rate
             FROM games
             INNER JOIN chapters ON (games.id = chapters.game_id)
             WHERE chapters.id = $1"#,
@@ -40,6 +42,44 @@ impl Chapters {
             .fetch_optional(pool)
             .await
     }
+    /// Returns every chapter with its maps, singleplayer chapters first. `game_id` limits the results to one game, all
+    /// games are included if `None`.
+    pub async fn get_chapters_with_maps(
+        pool: &PgPool,
+        game_id: Option<i32>,
+    ) -> Result<Vec<ChapterWithMaps>, sqlx::Error> {
+        let mut chapters = sqlx::query_as::<_, ChapterWithMaps>(
+            r#"
+                SELECT id AS chapter_id, chapter_name AS name, is_multiplayer FROM chapters
+                WHERE ($1::INTEGER IS NULL OR game_id = $1)
+                ORDER BY is_multiplayer, id"#,
+        )
+        .bind(game_id)
+        .fetch_all(pool)
+        .await?;
+        let maps = sqlx::query_as::<_, (i32, String, String, i32)>(
+            r#"
+                SELECT maps.chapter_id, maps.steam_id, maps.name, maps.default_cat_id FROM maps
+                INNER JOIN chapters ON (maps.chapter_id = chapters.id)
+                WHERE ($1::INTEGER IS NULL OR chapters.game_id = $1)
+                ORDER BY maps.id"#,
+        )
+        .bind(game_id)
+        .fetch_all(pool)
+        .await?;
+        let mut by_chapter: HashMap<i32, Vec<ChapterMap>> = HashMap::with_capacity(chapters.len());
+        for (chapter_id, steam_id, name, default_cat_id) in maps {
+            by_chapter.entry(chapter_id).or_default().push(ChapterMap {
+                steam_id,
+                name,
+                default_cat_id,
+            });
+        }
+        for chapter in chapters.iter_mut() {
+            chapter.maps = by_chapter.remove(&chapter.chapter_id).unwrap_or_default();
+        }
+        Ok(chapters)
+    }
     /// Makes a call to [build_filtered_chapter] with the [ChapterQueryParams] to returned a filtered list of chapters.
     pub async fn get_filtered_chapters(
         pool: &PgPool,
@@ -51,7 +91,17 @@ impl Chapters {
     }
 }
 
-// TODO: Do we want to return a chapter/map bundled information?
+impl Games {
+    /// Returns the `tick_rate` scores for a game are counted at, [DEFAULT_TICK_RATE] if there is no game with the id.
+    pub async fn get_tick_rate(pool: &PgPool, game_id: i32) -> Result<i32, sqlx::Error> {
+        let tick_rate: Option<i32> = sqlx::query_scalar(r#"SELECT tick_rate FROM games WHERE id = $1"#)
+            .bind(game_id)
+            .fetch_optional(pool)
+            .await?;
+        Ok(tick_rate.unwrap_or(DEFAULT_TICK_RATE))
+    }
+}
+
 /// Helper function to build out a query string based on [ChapterQueryParams] passed by the user.
 pub async fn build_filtered_chapter(params: ChapterQueryParams) -> String {
     let mut query_string: String = String::from(r#"SELECT * FROM chapters"#);
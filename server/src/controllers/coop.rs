@@ -1,7 +1,8 @@
 use crate::models::{changelog::Changelog, coop::*, maps::Maps};
+use crate::tools::helpers::{dedup_coop_previews, Transaction};
 use futures::future::try_join_all;
 use sqlx::PgPool;
-use std::collections::HashSet;
+use std::collections::HashMap;
 
 impl CoopBundled {
     /// Inserts a [CoopBundledInsert], returns the `id` if operation was successful.
@@ -21,6 +22,23 @@ impl CoopBundled {
             .fetch_one(pool)
             .await
     }
+    /// Inserts a [CoopBundledInsert] as part of a transaction, returns the `id` if operation was successful.
+    pub async fn transaction_insert_coop_bundled(transaction: &mut Transaction<'_>, cl: CoopBundledInsert) -> Result<i64, sqlx::Error> {
+        sqlx::query_scalar(
+            r#"
+                INSERT INTO coop_bundled 
+                (p_id1, p_id2, p1_is_host, cl_id1, cl_id2) VALUES 
+                ($1, $2, $3, $4, $5)
+                RETURNING id"#,
+        )
+            .bind(cl.p_id1)
+            .bind(cl.p_id2)
+            .bind(cl.p1_is_host)
+            .bind(cl.cl_id1)
+            .bind(cl.cl_id2)
+            .fetch_one(&mut **transaction)
+            .await
+    }
     /// Grabs the temporary changelog entry for a given `map_id`. 
     /// 
     /// This is used for scores that have no partner so that filtering works correctly.
@@ -44,6 +62,96 @@ impl CoopBundled {
         .fetch_one(pool)
         .await
     }
+    /// Updates the the changelog entry on the given `cl_id` with a given `coop_id` as part of a transaction.
+    pub async fn transaction_update_changelog_with_coop_id(
+        transaction: &mut Transaction<'_>,
+        cl_id: i64,
+        coop_id: i64,
+    ) -> Result<Changelog, sqlx::Error> {
+        sqlx::query_as::<_, Changelog>(
+            r#"UPDATE changelog SET coop_id = $1 WHERE id = $2 RETURNING *"#,
+        )
+        .bind(coop_id)
+        .bind(cl_id)
+        .fetch_one(&mut **transaction)
+        .await
+    }
+    /// Returns changelog entries for a coop map/category that do not have a partner yet, newest first.
+    ///
+    /// This includes entries not referenced by any `coop_bundled`, and entries only bundled with the temporary `N/A` changelog entry.
+    pub async fn find_unbundled(pool: &PgPool, map_id: &str, cat_id: i32) -> Result<Vec<Changelog>, sqlx::Error> {
+        sqlx::query_as::<_, Changelog>(
+            r#"
+                SELECT cl.* FROM changelog cl
+                    INNER JOIN maps AS map ON (map.steam_id = cl.map_id)
+                    INNER JOIN chapters AS chapter ON (map.chapter_id = chapter.id)
+                WHERE cl.map_id = $1
+                AND cl.category_id = $2
+                AND chapter.is_multiplayer = True
+                AND cl.profile_number != 'N/A'
+                AND NOT EXISTS
+                    (SELECT 1 FROM coop_bundled cb
+                    WHERE (cb.cl_id1 = cl.id OR cb.cl_id2 = cl.id)
+                    AND 'N/A' != ALL(ARRAY[cb.p_id1, COALESCE(cb.p_id2, '')]))
+                ORDER BY cl.timestamp DESC NULLS LAST"#,
+        )
+        .bind(map_id)
+        .bind(cat_id)
+        .fetch_all(pool)
+        .await
+    }
+    /// Locks the given changelog entries until the transaction ends, then returns true if every one of them is still
+    /// waiting for a partner, as [CoopBundled::find_unbundled] would return them. Runs as part of a transaction.
+    ///
+    /// Concurrent transactions bundling any of the same entries wait on the lock, so they see this bundle once it commits.
+    pub async fn transaction_lock_unbundled(transaction: &mut Transaction<'_>, cl_ids: &[i64]) -> Result<bool, sqlx::Error> {
+        sqlx::query(r#"SELECT id FROM changelog WHERE id = ANY($1) ORDER BY id FOR UPDATE"#)
+            .bind(cl_ids)
+            .execute(&mut **transaction)
+            .await?;
+        let unbundled: i64 = sqlx::query_scalar(
+            r#"
+                SELECT COUNT(*) FROM changelog cl
+                    INNER JOIN maps AS map ON (map.steam_id = cl.map_id)
+                    INNER JOIN chapters AS chapter ON (map.chapter_id = chapter.id)
+                WHERE cl.id = ANY($1)
+                AND chapter.is_multiplayer = True
+                AND cl.profile_number != 'N/A'
+                AND NOT EXISTS
+                    (SELECT 1 FROM coop_bundled cb
+                    WHERE (cb.cl_id1 = cl.id OR cb.cl_id2 = cl.id)
+                    AND 'N/A' != ALL(ARRAY[cb.p_id1, COALESCE(cb.p_id2, '')]))"#,
+        )
+        .bind(cl_ids)
+        .fetch_one(&mut **transaction)
+        .await?;
+        Ok(unbundled == cl_ids.len() as i64)
+    }
+    /// Removes the bundles pairing the given changelog entries with the temporary `N/A` changelog entry,
+    /// any changelog entries still pointing at those bundles have their `coop_id` cleared. Runs as part of a transaction.
+    ///
+    /// Returns the number of bundles removed.
+    pub async fn transaction_clear_temp_bundles(transaction: &mut Transaction<'_>, cl_ids: &[i64]) -> Result<u64, sqlx::Error> {
+        let temp_ids: Vec<i64> = sqlx::query_scalar(
+            r#"
+                SELECT id FROM coop_bundled
+                WHERE (cl_id1 = ANY($1) OR cl_id2 = ANY($1))
+                AND 'N/A' IN (p_id1, p_id2)"#,
+        )
+        .bind(cl_ids)
+        .fetch_all(&mut **transaction)
+        .await?;
+        sqlx::query(r#"UPDATE changelog SET coop_id = NULL WHERE coop_id = ANY($1)"#)
+            .bind(&temp_ids)
+            .execute(&mut **transaction)
+            .await?;
+        let removed = sqlx::query(r#"DELETE FROM coop_bundled WHERE id = ANY($1)"#)
+            .bind(&temp_ids)
+            .execute(&mut **transaction)
+            .await?
+            .rows_affected();
+        Ok(removed)
+    }
 }
 
 impl CoopMap {
@@ -107,9 +215,8 @@ impl CoopMap {
 }
 
 impl CoopPreview {
-    // TODO: Filter by default cat_id
-    /// Gets the top 7 (unique on player) times on a given Coop Map.
-    pub async fn get_coop_preview(pool: &PgPool, map_id: &str) -> Result<Vec<CoopPreview>, sqlx::Error> {
+    /// Gets the top 7 (unique on player) times on a given Coop Map for the given category.
+    pub async fn get_coop_preview(pool: &PgPool, map_id: &str, cat_id: i32) -> Result<Vec<CoopPreview>, sqlx::Error> {
         // TODO: Open to PRs to contain all this functionality in the SQL statement.
         let res = sqlx::query_as::<_, CoopPreview>(
             r#"
@@ -136,40 +243,37 @@ impl CoopPreview {
                     AND c2.banned=False
                     AND c1.verified=True
                     AND c2.verified=True
+                    AND c1.category_id = $2
                 ORDER BY score ASC
                 LIMIT 40
                 "#,
         )
         .bind(map_id)
+        .bind(cat_id)
         .fetch_all(pool)
         .await?;
 
-        let mut vec_final = Vec::new();
-        let mut remove_dups = HashSet::with_capacity(80);
-        remove_dups.insert("N/A".to_string());
-        for entry in res {
-            match remove_dups.insert(entry.profile_number1.clone()) {
-                false => match remove_dups.insert(entry.profile_number2.clone().unwrap()) {
-                    false => (),
-                    true => vec_final.push(entry),
-                },
-                true => match remove_dups.insert(entry.profile_number2.clone().unwrap()) {
-                    false => vec_final.push(entry),
-                    true => vec_final.push(entry),
-                },
-            }
-        }
-        vec_final.truncate(7);
-        Ok(vec_final)
+        Ok(dedup_coop_previews(res, 7))
     }
-    /// Collects the top 7 preview data for all Coop maps.
-    pub async fn get_coop_previews(pool: &PgPool) -> Result<Vec<Vec<CoopPreview>>, sqlx::Error> {
-        let map_id_vec = Maps::get_steam_ids(pool, true).await?;
-        let futures: Vec<_> = map_id_vec
-            .iter()
-            .map(|map_id| CoopPreview::get_coop_preview(pool, map_id))
-            .collect();
-        try_join_all(futures).await
+    /// Collects the top 7 preview data for all Coop maps on a given `game_id`, using each map's default category.
+    ///
+    /// Returns an error if a map has no default category, rather than leaving it out, as the previews are expected to line
+    /// up with the map ids from [Maps::get_steam_ids].
+    pub async fn get_coop_previews(
+        pool: &PgPool,
+        default_cat_ids: &HashMap<String, i32>,
+        game_id: i32,
+    ) -> anyhow::Result<Vec<Vec<CoopPreview>>> {
+        let map_id_vec = Maps::get_steam_ids(pool, Some(true), Some(game_id)).await?;
+        let mut futures = Vec::with_capacity(map_id_vec.len());
+        for map_id in map_id_vec.iter() {
+            let cat_id = match default_cat_ids.get(map_id) {
+                Some(cat_id) => *cat_id,
+                None => anyhow::bail!("No default category for map {}", map_id),
+            };
+            futures.push(CoopPreview::get_coop_preview(pool, map_id, cat_id));
+        }
+        Ok(try_join_all(futures).await?)
     }
 }
 
@@ -193,8 +297,8 @@ impl CoopBanned {
                     AND coop_id IS NOT NULL)) as cb
                 LEFT JOIN changelog AS c1 ON (c1.id = cb.cl_id1)
                 LEFT JOIN changelog AS c2 ON (c2.id = cb.cl_id2)
-                    WHERE (c1.banned = True OR c1.verified = False)
-                    OR (c2.banned = True OR c2.verified = False)
+                    WHERE ((c1.banned = True OR c1.verified = False)
+                    OR (c2.banned = True OR c2.verified = False))
                     AND c1.category_id = $2
                 "#)
             .bind(map_id)
@@ -1,4 +1,5 @@
 use crate::models::demos::*;
+use crate::tools::helpers::Transaction;
 use sqlx::PgPool;
 
 impl Demos {
@@ -9,13 +10,75 @@ impl Demos {
             .fetch_optional(pool)
             .await
     }
-    /// Gets Demo information for a given changelog_id
+    /// Gets Demo information for the most recent demo uploaded for a given changelog_id
     pub async fn get_demo_by_cl_id(pool: &PgPool, cl_id: i64) -> Result<Option<Demos>, sqlx::Error> {
-        sqlx::query_as::<_, Demos>(r#"SELECT * FROM demos WHERE cl_id = $1"#)
+        sqlx::query_as::<_, Demos>(r#"SELECT * FROM demos WHERE cl_id = $1 ORDER BY id DESC LIMIT 1"#)
             .bind(cl_id)
             .fetch_optional(pool)
             .await
     }
+    /// Gets every demo uploaded for a given changelog_id, newest first
+    pub async fn get_all_demos_by_cl_id(pool: &PgPool, cl_id: i64) -> Result<Vec<Demos>, sqlx::Error> {
+        sqlx::query_as::<_, Demos>(r#"SELECT * FROM demos WHERE cl_id = $1 ORDER BY id DESC"#)
+            .bind(cl_id)
+            .fetch_all(pool)
+            .await
+    }
+    /// Gets the demos currently associated with both changelog entries of a coop bundle.
+    ///
+    /// Returns `None` if there is no bundle with `coop_id`.
+    pub async fn get_coop_demos(pool: &PgPool, coop_id: i64) -> Result<Option<CoopDemos>, sqlx::Error> {
+        sqlx::query_as::<_, CoopDemos>(
+            r#"
+                SELECT coop_bundled.id AS coop_id, cl1.demo_id AS demo_id1, cl2.demo_id AS demo_id2,
+                    d1.file_id AS file_id1, d2.file_id AS file_id2
+                FROM coop_bundled
+                    LEFT JOIN changelog AS cl1 ON (cl1.id = coop_bundled.cl_id1)
+                    LEFT JOIN changelog AS cl2 ON (cl2.id = coop_bundled.cl_id2)
+                    LEFT JOIN demos AS d1 ON (d1.id = cl1.demo_id)
+                    LEFT JOIN demos AS d2 ON (d2.id = cl2.demo_id)
+                WHERE coop_bundled.id = $1"#,
+        )
+        .bind(coop_id)
+        .fetch_optional(pool)
+        .await
+    }
+    /// Gets every demo uploaded for a player's changelog entries, newest first
+    pub async fn get_demos_by_profile(pool: &PgPool, profile_number: &str) -> Result<Vec<Demos>, sqlx::Error> {
+        sqlx::query_as::<_, Demos>(
+            r#"
+                SELECT demos.* FROM demos
+                    INNER JOIN changelog ON (changelog.id = demos.cl_id)
+                WHERE changelog.profile_number = $1
+                ORDER BY demos.id DESC"#,
+        )
+        .bind(profile_number)
+        .fetch_all(pool)
+        .await
+    }
+    /// Gets the newest demo with the given SHA1 that was uploaded for a player's score on a map, used to detect repeated uploads
+    pub async fn get_demo_by_sha1(
+        pool: &PgPool,
+        sha1: &str,
+        profile_number: &str,
+        map_id: &str,
+    ) -> Result<Option<Demos>, sqlx::Error> {
+        sqlx::query_as::<_, Demos>(
+            r#"
+                SELECT demos.* FROM demos
+                    INNER JOIN changelog ON (changelog.id = demos.cl_id)
+                WHERE demos.sha1 = $1
+                AND changelog.profile_number = $2
+                AND changelog.map_id = $3
+                ORDER BY demos.id DESC
+                LIMIT 1"#,
+        )
+        .bind(sha1)
+        .bind(profile_number)
+        .bind(map_id)
+        .fetch_optional(pool)
+        .await
+    }
     /// Returns a file id that can be used to download the demo
     #[allow(dead_code)]
     pub async fn get_demo_file_id(pool: &PgPool, demo_id: i64) -> Result<Option<String>, sqlx::Error> {
@@ -48,13 +111,63 @@ impl Demos {
             .fetch_one(pool) // This should NOT be optional, as the value can be NULL
             .await
     }
+    /// Returns up to `limit` demos that have not been parsed, with an id greater than `after_id`, in order of id.
+    pub async fn get_unparsed(pool: &PgPool, after_id: i64, limit: i64) -> Result<Vec<Demos>, sqlx::Error> {
+        sqlx::query_as::<_, Demos>(
+            r#"
+                SELECT * FROM demos
+                WHERE parsed_successfully = false AND id > $1
+                ORDER BY id LIMIT $2"#,
+        )
+        .bind(after_id)
+        .bind(limit)
+        .fetch_all(pool)
+        .await
+    }
+    /// Returns the number of demos that have not been parsed.
+    pub async fn count_unparsed(pool: &PgPool) -> Result<i64, sqlx::Error> {
+        sqlx::query_scalar(r#"SELECT COUNT(*) FROM demos WHERE parsed_successfully = false"#)
+            .fetch_one(pool)
+            .await
+    }
+    /// Returns the stored [DemoHeader] for a demo, `None` if the demo does not exist or its header has not been stored.
+    pub async fn get_demo_header(pool: &PgPool, demo_id: i64) -> Result<Option<DemoHeader>, sqlx::Error> {
+        sqlx::query_as::<_, DemoHeader>(
+            r#"
+                SELECT map_name, tick_count, playback_time, sar_version, protocol FROM demos
+                WHERE id = $1 AND map_name IS NOT NULL"#,
+        )
+        .bind(demo_id)
+        .fetch_optional(pool)
+        .await
+    }
+    /// Marks a demo as parsed, and stores the fields parsed from its header.
+    pub async fn set_parsed(pool: &PgPool, demo_id: i64, header: &DemoHeader) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+                UPDATE demos
+                SET parsed_successfully = true, sar_version = $2, map_name = $3,
+                tick_count = $4, playback_time = $5, protocol = $6
+                WHERE id = $1"#,
+        )
+        .bind(demo_id)
+        .bind(&header.sar_version)
+        .bind(&header.map_name)
+        .bind(header.tick_count)
+        .bind(header.playback_time)
+        .bind(header.protocol)
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
     /// Adds a new demo to the database, returns the demo's id
     pub async fn insert_demo(pool: &PgPool, demo: DemoInsert) -> Result<i64, sqlx::Error> {
         sqlx::query_scalar(
             r#"
                 INSERT INTO demos 
-                (file_id, partner_name, parsed_successfully, sar_version, cl_id) VALUES 
-                ($1, $2, $3, $4, $5)
+                (file_id, partner_name, parsed_successfully, sar_version, cl_id, sha1, bucket_id,
+                map_name, tick_count, playback_time, protocol) VALUES 
+                ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
                 RETURNING id"#,
         )
         .bind(demo.file_id)
@@ -62,9 +175,39 @@ impl Demos {
         .bind(demo.parsed_successfully)
         .bind(demo.sar_version)
         .bind(demo.cl_id)
+        .bind(demo.sha1)
+        .bind(demo.bucket_id)
+        .bind(demo.map_name)
+        .bind(demo.tick_count)
+        .bind(demo.playback_time)
+        .bind(demo.protocol)
         .fetch_one(pool)
         .await
     }
+    /// Inserts a new demo as part of a transaction, returns the new demo's id.
+    pub async fn transaction_insert_demo(transaction: &mut Transaction<'_>, demo: DemoInsert) -> Result<i64, sqlx::Error> {
+        sqlx::query_scalar(
+            r#"
+                INSERT INTO demos 
+                (file_id, partner_name, parsed_successfully, sar_version, cl_id, sha1, bucket_id,
+                map_name, tick_count, playback_time, protocol) VALUES 
+                ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+                RETURNING id"#,
+        )
+        .bind(demo.file_id)
+        .bind(demo.partner_name)
+        .bind(demo.parsed_successfully)
+        .bind(demo.sar_version)
+        .bind(demo.cl_id)
+        .bind(demo.sha1)
+        .bind(demo.bucket_id)
+        .bind(demo.map_name)
+        .bind(demo.tick_count)
+        .bind(demo.playback_time)
+        .bind(demo.protocol)
+        .fetch_one(&mut **transaction)
+        .await
+    }
     /// Updates an existing demo
     #[allow(dead_code)]
     pub async fn update_demo(pool: &PgPool, updated_demo: Demos) -> Result<Demos, sqlx::Error> {
@@ -73,14 +216,21 @@ impl Demos {
             r#"
                 UPDATE demos
                 SET file_id = $1, partner_name = $2, parsed_successfully = $3,
-                sar_version = $4, cl_id = $5
-                WHERE id = $6 RETURNING *"#,
+                sar_version = $4, cl_id = $5, sha1 = $6, bucket_id = $7,
+                map_name = $8, tick_count = $9, playback_time = $10, protocol = $11
+                WHERE id = $12 RETURNING *"#,
         )
         .bind(updated_demo.file_id)
         .bind(updated_demo.partner_name)
         .bind(updated_demo.parsed_successfully)
         .bind(updated_demo.sar_version)
         .bind(updated_demo.cl_id)
+        .bind(updated_demo.sha1)
+        .bind(updated_demo.bucket_id)
+        .bind(updated_demo.map_name)
+        .bind(updated_demo.tick_count)
+        .bind(updated_demo.playback_time)
+        .bind(updated_demo.protocol)
         .bind(updated_demo.id)
         .fetch_one(pool)
         .await
@@ -95,6 +245,34 @@ impl Demos {
         .fetch_one(pool)
         .await
     }
+    /// Deletes multiple demos in a single transaction, returns the number of demos removed.
+    ///
+    /// Changelog entries that pointed at a deleted demo are updated to point at their newest remaining demo (if any).
+    pub async fn delete_demos(pool: &PgPool, demo_ids: &[i64]) -> Result<u64, sqlx::Error> {
+        let mut transaction = pool.begin().await?;
+        let cl_ids: Vec<i64> = sqlx::query_scalar(
+            r#"UPDATE changelog SET demo_id = NULL WHERE demo_id = ANY($1) RETURNING id"#,
+        )
+        .bind(demo_ids)
+        .fetch_all(&mut *transaction)
+        .await?;
+        let deleted = sqlx::query(r#"DELETE FROM demos WHERE id = ANY($1)"#)
+            .bind(demo_ids)
+            .execute(&mut *transaction)
+            .await?
+            .rows_affected();
+        sqlx::query(
+            r#"
+                UPDATE changelog
+                SET demo_id = (SELECT MAX(id) FROM demos WHERE demos.cl_id = changelog.id)
+                WHERE id = ANY($1)"#,
+        )
+        .bind(&cl_ids)
+        .execute(&mut *transaction)
+        .await?;
+        transaction.commit().await?;
+        Ok(deleted)
+    }
 }
 
 impl Mtriggers {
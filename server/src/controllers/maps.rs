@@ -1,3 +1,4 @@
+use crate::models::categories::Categories;
 use crate::models::chapters::*;
 use crate::models::maps::*;
 use sqlx::postgres::PgRow;
@@ -17,19 +18,27 @@ impl Maps {
         .fetch_all(pool)
         .await
     }
-    /// `is_mp
-    /// - If `true`
+    /// Returns the `steam_id` for every map, optionally filtered by mode and game.
+    ///
+    /// `is_mp`
+    /// - If `Some(true)`
     ///     - Returns multiplayer `map_ids`.
-    /// - If `false`
+    /// - If `Some(false)`
     ///     - Returns all singleplayer `map_ids`.
-    pub async fn get_steam_ids(pool: &PgPool, is_mp: bool) -> Result<Vec<String>, sqlx::Error> {
+    /// - If `None`
+    ///     - Returns both.
+    ///
+    /// `game_id` limits the results to maps from one game, all games are included if `None`.
+    pub async fn get_steam_ids(pool: &PgPool, is_mp: Option<bool>, game_id: Option<i32>) -> Result<Vec<String>, sqlx::Error> {
         sqlx::query_scalar(
             r#"
                 SELECT maps.steam_id FROM maps
                     INNER JOIN chapters ON (maps.chapter_id = chapters.id)
-                    WHERE chapters.is_multiplayer = $1"#,
+                    WHERE ($1::BOOLEAN IS NULL OR chapters.is_multiplayer = $1)
+                    AND ($2::INTEGER IS NULL OR chapters.game_id = $2)"#,
         )
         .bind(is_mp)
+        .bind(game_id)
         .fetch_all(pool)
         .await
     }
@@ -49,6 +58,19 @@ impl Maps {
             .await?;
         Ok(hm)
     }
+    /// Returns the default categories for every map as a `HashMap` of `(map_id, game_id)` -> `cat_id`.
+    pub async fn get_default_cats_by_game(pool: &PgPool) -> Result<HashMap<(String, i32), i32>, sqlx::Error> {
+        let mut hm: HashMap<(String, i32), i32> = HashMap::with_capacity(108);
+        sqlx::query(
+            r#"
+                SELECT maps.steam_id, chapters.game_id, maps.default_cat_id FROM maps
+                INNER JOIN chapters ON (maps.chapter_id = chapters.id)"#,
+        )
+        .map(|row: PgRow| hm.insert((row.get(0), row.get(1)), row.get(2)))
+        .fetch_all(pool)
+        .await?;
+        Ok(hm)
+    }
     /// Returns the default category for a given `map_id`.
     pub async fn get_default_cat(pool: &PgPool, map_id: String) -> Result<Option<i32>, sqlx::Error> {
         sqlx::query_scalar(
@@ -60,6 +82,96 @@ impl Maps {
         .fetch_optional(pool)
         .await
     }
+    /// Sets the default category for a given `map_id`, the category is expected to already be checked to belong to the map.
+    pub async fn set_default_cat(pool: &PgPool, map_id: &str, cat_id: i32) -> Result<(), sqlx::Error> {
+        sqlx::query(r#"UPDATE maps SET default_cat_id = $2 WHERE steam_id = $1"#)
+            .bind(map_id)
+            .bind(cat_id)
+            .execute(pool)
+            .await?;
+        Ok(())
+    }
+    /// Returns a [MapDetail] for the given `map_id`, including all categories for the map.
+    pub async fn get_map_detail(pool: &PgPool, map_id: &str) -> Result<Option<MapDetail>, sqlx::Error> {
+        let detail = sqlx::query_as::<_, MapDetail>(
+            r#"
+                SELECT maps.steam_id, maps.name, maps.chapter_id, chapters.chapter_name, chapters.game_id,
+                chapters.is_multiplayer AS is_coop, maps.default_cat_id, maps.is_public
+                    FROM maps
+                    INNER JOIN chapters ON (maps.chapter_id = chapters.id)
+                    WHERE maps.steam_id = $1"#,
+        )
+        .bind(map_id)
+        .fetch_optional(pool)
+        .await?;
+        let Some(mut detail) = detail else {
+            return Ok(None);
+        };
+        detail.categories = sqlx::query_as::<_, Categories>(
+            r#"SELECT * FROM categories WHERE map_id = $1 ORDER BY id"#,
+        )
+        .bind(map_id)
+        .fetch_all(pool)
+        .await?;
+        Ok(Some(detail))
+    }
+    /// Case-insensitive search for maps whose name contains `query`, from every game unless `game_id` is given.
+    ///
+    /// Closest matches come first: an exact name, then names starting with `query`, then the earliest match in the name.
+    pub async fn search_by_name(
+        pool: &PgPool,
+        query: &str,
+        game_id: Option<i32>,
+    ) -> Result<Vec<MapSearchResult>, sqlx::Error> {
+        // Limitation to how SQLX inserts strings.
+        let pattern = format!("%{}%", query);
+        sqlx::query_as::<_, MapSearchResult>(
+            r#"
+                SELECT maps.steam_id, maps.name, maps.chapter_id, chapters.chapter_name,
+                chapters.is_multiplayer AS is_coop
+                    FROM maps
+                    INNER JOIN chapters ON (maps.chapter_id = chapters.id)
+                    WHERE LOWER(maps.name) LIKE LOWER($1)
+                    AND ($3::INTEGER IS NULL OR chapters.game_id = $3)
+                    ORDER BY LOWER(maps.name) = LOWER($2) DESC,
+                    POSITION(LOWER($2) IN LOWER(maps.name)),
+                    LENGTH(maps.name), maps.name"#,
+        )
+        .bind(pattern)
+        .bind(query)
+        .bind(game_id)
+        .fetch_all(pool)
+        .await
+    }
+    /// Returns a [MapSummary] for a map and category, using the same `banned`/`verified` filters as the map pages.
+    ///
+    /// For coop maps the world record holder is whichever partner submitted their half of the run first.
+    pub async fn get_map_summary(pool: &PgPool, map_id: &str, cat_id: i32) -> Result<MapSummary, sqlx::Error> {
+        sqlx::query_as::<_, MapSummary>(
+            r#"
+                WITH valid AS (
+                    SELECT changelog.profile_number, changelog.score, changelog.timestamp,
+                        COALESCE(users.board_name, users.steam_name) AS user_name, users.avatar
+                    FROM changelog
+                    INNER JOIN users ON (users.profile_number = changelog.profile_number)
+                        WHERE changelog.map_id = $1
+                        AND changelog.category_id = $2
+                        AND users.banned = False
+                        AND changelog.verified = True
+                        AND changelog.banned = False
+                )
+                SELECT $1 AS map_id, $2 AS category_id,
+                    wr.score AS wr_score, wr.profile_number AS wr_profile_number,
+                    wr.user_name AS wr_user_name, wr.avatar AS wr_avatar,
+                    (SELECT COUNT(DISTINCT profile_number) FROM valid) AS player_count
+                FROM (SELECT 1) AS summary
+                LEFT JOIN (SELECT * FROM valid ORDER BY score, timestamp NULLS LAST LIMIT 1) wr ON True"#,
+        )
+        .bind(map_id)
+        .bind(cat_id)
+        .fetch_one(pool)
+        .await
+    }
     /// Returns a [Chapters] for a given `map_id`.
     #[allow(dead_code)]
     pub async fn get_chapter_from_map_id(
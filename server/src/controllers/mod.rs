@@ -5,6 +5,9 @@
 //! ## Admin
 //! Admin controllers are implemented on [crate::models::admin::Admin].
 //! 
+//! ## Categories
+//! Category controllers are implemented on [crate::models::categories::Categories].
+//! 
 //! ## Changelog
 //! Changelog controllers are implmented on the following:
 //! 
@@ -40,6 +43,10 @@
 //! ## Maps
 //! Map controllers are implemented on [crate::models::maps::Maps].
 //! 
+//! ## Points
+//! Points controllers are implemented on [crate::models::points::PlayerMapRank], to find a player's rank on every map for
+//! [crate::tools::helpers::get_chapter_points].
+//! 
 //! ## Single Player (sp)
 //! SP controllers are implemented on the following:
 //! 
@@ -64,6 +71,8 @@
 //! 
 /// Controllers for admin-specific functions
 pub mod admin;
+/// Controllers for categories
+pub mod categories;
 /// Controllers for changelog
 pub mod changelog;
 /// Controllers for chapters
@@ -74,6 +83,8 @@ pub mod coop;
 pub mod demos;
 /// Controllers for maps
 pub mod maps;
+/// Controllers for points
+pub mod points;
 /// Controllers for sp
 pub mod sp;
 /// Controllers for stats
@@ -0,0 +1,52 @@
+use crate::models::points::*;
+use sqlx::PgPool;
+
+impl PlayerMapRank {
+    /// Returns every map on `game_id` ordered by chapter, with the player's rank on singleplayer maps and whether they have a
+    /// coop time on coop maps. Only verified, unbanned times on the map's default category are counted.
+    ///
+    /// Singleplayer ranks match [crate::tools::helpers::rank_sp_entries], tied scores share a rank.
+    pub async fn get_for_player(pool: &PgPool, profile_number: &str, game_id: i32) -> Result<Vec<PlayerMapRank>, sqlx::Error> {
+        sqlx::query_as::<_, PlayerMapRank>(
+            r#"
+                SELECT chapters.id AS chapter_id, chapters.chapter_name, chapters.is_multiplayer,
+                    maps.steam_id AS map_id, maps.default_cat_id, ranked.rank AS sp_rank,
+                    (chapters.is_multiplayer AND EXISTS (
+                        SELECT 1 FROM changelog
+                            INNER JOIN coop_bundled ON (coop_bundled.id = changelog.coop_id)
+                            WHERE changelog.profile_number = $1
+                            AND changelog.map_id = maps.steam_id
+                            AND changelog.category_id = maps.default_cat_id
+                            AND changelog.verified = True
+                            AND changelog.banned = False
+                    )) AS has_coop
+                FROM maps
+                    INNER JOIN chapters ON (chapters.id = maps.chapter_id)
+                    LEFT JOIN (
+                        SELECT best.map_id, best.profile_number,
+                            RANK() OVER (PARTITION BY best.map_id ORDER BY best.score) AS rank
+                        FROM (
+                            SELECT changelog.map_id, changelog.profile_number, MIN(changelog.score) AS score
+                            FROM changelog
+                                INNER JOIN users ON (users.profile_number = changelog.profile_number)
+                                INNER JOIN maps ON (maps.steam_id = changelog.map_id)
+                                INNER JOIN chapters ON (chapters.id = maps.chapter_id)
+                            WHERE users.banned = False
+                                AND changelog.verified = True
+                                AND changelog.banned = False
+                                AND changelog.category_id = maps.default_cat_id
+                                AND chapters.is_multiplayer = False
+                                AND chapters.game_id = $2
+                            GROUP BY changelog.map_id, changelog.profile_number
+                        ) best
+                    ) ranked ON (ranked.map_id = maps.steam_id AND ranked.profile_number = $1)
+                WHERE chapters.game_id = $2
+                    AND maps.default_cat_id IS NOT NULL
+                ORDER BY chapters.id, maps.id"#,
+        )
+        .bind(profile_number)
+        .bind(game_id)
+        .fetch_all(pool)
+        .await
+    }
+}
@@ -1,4 +1,4 @@
-use crate::models::{maps::Maps, sp::*};
+use crate::models::{chapters::ReviewParams, maps::Maps, sp::*};
 
 use futures::future::try_join_all;
 use sqlx::PgPool;
@@ -15,13 +15,32 @@ impl SpMap {
     ///     - The category the runs have to be from.
     /// - `game_id`
     ///     - The game thee runs have to be from.
+    /// - `offset`
+    ///     - The number of entries to skip, used for paging.
     pub async fn get_sp_map_page(
         pool: &PgPool,
         map_id: &String,
         limit: i32,
         cat_id: i32,
         game_id: i32,
+        offset: i32,
     ) -> Result<Vec<SpMap>, sqlx::Error> {
+        SpMap::get_sp_map_review_page(pool, map_id, limit, cat_id, game_id, offset, &ReviewParams::default()).await
+    }
+    /// Returns a Single Player Map Page that optionally includes unverified and/or banned times, see [SpMap::get_sp_map_page].
+    ///
+    /// When either are included each entry has its `verified` and `banned` state set.
+    pub async fn get_sp_map_review_page(
+        pool: &PgPool,
+        map_id: &String,
+        limit: i32,
+        cat_id: i32,
+        game_id: i32,
+        offset: i32,
+        review: &ReviewParams,
+    ) -> Result<Vec<SpMap>, sqlx::Error> {
+        let include_unverified = review.include_unverified.unwrap_or(false);
+        let include_banned = review.include_banned.unwrap_or(false);
         sqlx::query_as::<_, SpMap>(
             r#" 
                 SELECT t.timestamp,
@@ -33,33 +52,78 @@ impl SpMap {
                     t.note,
                     t.category_id,
                     COALESCE(t.board_name, t.steam_name) AS user_name,
-                    t.avatar
+                    t.avatar,
+                    CASE WHEN $6 OR $7 THEN COALESCE(t.CL_verified, False) END AS verified,
+                    CASE WHEN $6 OR $7 THEN t.CL_banned END AS banned
                 FROM (
                     SELECT DISTINCT ON (changelog.profile_number) 
                         changelog.profile_number as CL_profile_number,
-                        users.profile_number as U_profile_number, *
+                        users.profile_number as U_profile_number,
+                        changelog.verified as CL_verified,
+                        changelog.banned as CL_banned, *
                     FROM changelog
                     INNER JOIN users ON (users.profile_number = changelog.profile_number)
                     INNER JOIN maps ON (changelog.map_id = maps.steam_id)
                     INNER JOIN chapters ON (maps.chapter_id = chapters.id)
                         WHERE map_id = $1
                         AND users.banned = False
-                        AND changelog.verified = True
-                        AND changelog.banned = False
+                        AND (changelog.verified = True OR $6)
+                        AND (changelog.banned = False OR $7)
                         AND changelog.category_id = $2
                         AND chapters.game_id = $3
                     ORDER BY changelog.profile_number, changelog.score ASC
                 ) t
                 ORDER BY score
-                LIMIT $4"#,
+                LIMIT $4
+                OFFSET $5"#,
         )
         .bind(map_id)
         .bind(cat_id)
         .bind(game_id)
         .bind(limit)
+        .bind(offset)
+        .bind(include_unverified)
+        .bind(include_banned)
         .fetch_all(pool)
         .await
     }
+    /// Returns the number of players with a better score than `score` on a map page, used to give absolute ranks to entries past the first page.
+    pub async fn count_better_scores(
+        pool: &PgPool,
+        map_id: &String,
+        score: i32,
+        cat_id: i32,
+        game_id: i32,
+        review: &ReviewParams,
+    ) -> Result<i64, sqlx::Error> {
+        sqlx::query_scalar(
+            r#"
+                SELECT COUNT(*)
+                FROM (
+                    SELECT DISTINCT ON (changelog.profile_number) changelog.score
+                    FROM changelog
+                    INNER JOIN users ON (users.profile_number = changelog.profile_number)
+                    INNER JOIN maps ON (changelog.map_id = maps.steam_id)
+                    INNER JOIN chapters ON (maps.chapter_id = chapters.id)
+                        WHERE map_id = $1
+                        AND users.banned = False
+                        AND (changelog.verified = True OR $5)
+                        AND (changelog.banned = False OR $6)
+                        AND changelog.category_id = $2
+                        AND chapters.game_id = $3
+                    ORDER BY changelog.profile_number, changelog.score ASC
+                ) t
+                WHERE t.score < $4"#,
+        )
+        .bind(map_id)
+        .bind(cat_id)
+        .bind(game_id)
+        .bind(score)
+        .bind(review.include_unverified.unwrap_or(false))
+        .bind(review.include_banned.unwrap_or(false))
+        .fetch_one(pool)
+        .await
+    }
 }
 
 impl SpPreview {
@@ -89,7 +153,7 @@ impl SpPreview {
     }
     /// Collects the top 7 preview data for all SP maps.
     pub async fn get_sp_previews(pool: &PgPool) -> Result<Vec<Vec<SpPreview>>, sqlx::Error> {
-        let map_id_vec = Maps::get_steam_ids(pool, false).await?;
+        let map_id_vec = Maps::get_steam_ids(pool, Some(false), None).await?;
         let futures: Vec<_> = map_id_vec
             .iter()
             .map(|map_id| SpPreview::get_sp_preview(pool, map_id))
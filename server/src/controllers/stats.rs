@@ -221,3 +221,24 @@ impl BadgeEntries {
         .await
     }
 }
+
+impl StatsOverview {
+    /// Returns the board-wide [StatsOverview], counted in a single query.
+    ///
+    /// Verified runs and runs from the last 24 hours exclude banned times.
+    pub async fn get_overview(pool: &PgPool) -> Result<StatsOverview, sqlx::Error> {
+        sqlx::query_as::<_, StatsOverview>(
+            r#"
+                SELECT
+                    (SELECT COUNT(*) FROM users WHERE registered <> 0) AS registered_users,
+                    (SELECT COUNT(*) FROM changelog WHERE verified = True AND banned = False) AS verified_runs,
+                    (SELECT COUNT(*) FROM demos) AS demos,
+                    (SELECT COUNT(*) FROM maps) AS maps,
+                    (SELECT COUNT(*) FROM changelog
+                        WHERE banned = False
+                        AND timestamp > NOW() - INTERVAL '24 hours') AS runs_last_24h"#,
+        )
+        .fetch_one(pool)
+        .await
+    }
+}
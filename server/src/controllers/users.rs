@@ -1,12 +1,54 @@
-use crate::{models::{changelog::MapScoreDate, points::*, users::*}, tools::error::{ServerError, ErrorType}};
+use crate::{models::{changelog::MapScoreDate, points::*, users::*}, tools::{error::{ServerError, ErrorType}, helpers::escape_like}};
 use sqlx::PgPool;
+use std::collections::HashMap;
+
+/// The most users returned by [Users::check_board_name].
+pub const MAX_NAME_MATCHES: i64 = 50;
 
 impl Users {
-    // TODO: Testing for this
-    // TODO: Fix edge case parsing for steam user.
+    /// Parses a steamID64, vanity name, or a full `/profiles/` or `/id/` Steam profile URL.
+    pub fn parse_steam_input(input: &str) -> SteamIdInput {
+        let input = input.trim().trim_end_matches('/');
+        let id = input
+            .split_once("/profiles/")
+            .or_else(|| input.split_once("/id/"))
+            .map(|(_, id)| id.split('/').next().unwrap_or(id))
+            .unwrap_or(input);
+        if id.len() == 17 && id.bytes().all(|b| b.is_ascii_digit()) {
+            SteamIdInput::SteamId64(id.to_string())
+        } else {
+            SteamIdInput::Vanity(id.to_string())
+        }
+    }
+    /// Resolves a vanity name to a steamID64 using the official Steam API.
+    ///
+    /// Returns a [ErrorType::NotFound] error if no user has the vanity name, and [ErrorType::Reqwest] if Steam could not be reached.
+    pub async fn resolve_vanity_url(steam_api_key: &str, vanity: &str) -> Result<String, ServerError> {
+        let resolved = reqwest::Client::new()
+            .get("https://api.steampowered.com/ISteamUser/ResolveVanityURL/v1/")
+            .query(&[("key", steam_api_key), ("vanityurl", vanity)])
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<ResolveVanityUrlWrapper>()
+            .await?;
+        match resolved.response.steamid {
+            Some(steamid) if resolved.response.success == 1 => Ok(steamid),
+            _ => Err(ServerError::new(
+                ErrorType::NotFound,
+                format!("No Steam user found for {vanity}"),
+            )),
+        }
+    }
     /// Fetch a [Users] from the official Steam API.
+    ///
+    /// `profile_number` can be anything accepted by [Users::parse_steam_input], vanity names are resolved first.
+    /// Returns a [ErrorType::NotFound] error if the user does not exist, and [ErrorType::Reqwest] if Steam could not be reached.
     pub async fn new_from_steam(steam_api_key: &str, profile_number: &str) -> Result<Users, ServerError> {
-        // http://steamcommunity.com/profiles/{}/?xml=1
+        let profile_number = match Users::parse_steam_input(profile_number) {
+            SteamIdInput::SteamId64(steamid) => steamid,
+            SteamIdInput::Vanity(vanity) => Users::resolve_vanity_url(steam_api_key, &vanity).await?,
+        };
         // GET https://api.steampowered.com/ISteamUser/GetPlayerSummaries/v2/
         let steam_api_url = format!(
             "https://api.steampowered.com/ISteamUser/GetPlayerSummaries/v2/?key={}&steamids={}",
@@ -14,27 +56,30 @@ impl Users {
         );
         let user = reqwest::get(&steam_api_url)
             .await?
+            .error_for_status()?
             .json::<GetPlayerSummariesWrapper>()
             .await?;
-        println!("{user:#?}");
-        if user.response.players.is_empty() {
-            return Err(ServerError {
-                error_message: format!("User not found"),
-                error_type: ErrorType::Reqwest,
-            });
-        }
+        // Steam returns an empty list rather than an error for unknown ids.
+        let player = match user.response.players.into_iter().next() {
+            Some(player) => player,
+            None => {
+                return Err(ServerError::new(
+                    ErrorType::NotFound,
+                    format!("No Steam user found for {profile_number}"),
+                ))
+            }
+        };
         Ok(Users {
             profile_number: profile_number.to_string(),
             board_name: None,
-            steam_name: Some(user.response.players[0].personaname.clone()),
+            steam_name: Some(player.personaname),
             banned: false,
             registered: 0,
-            avatar: Some(user.response.players[0].avatarfull.clone()),
+            avatar: Some(player.avatarfull),
             ..Default::default()
         })
     }
     /// Returns a [Users] from the given `profile_number`.
-    #[allow(dead_code)]
     pub async fn get_user(pool: &PgPool, profile_number: String) -> Result<Option<Users>, sqlx::Error> {
         sqlx::query_as::<_, Users>(r#"SELECT * FROM users WHERE profile_number = $1"#)
             .bind(profile_number)
@@ -56,41 +101,87 @@ impl Users {
         .fetch_optional(pool)
         .await
     }
-    // TODO: There are faster ways to do this. <-----
-    /// Pattern match on a given string to find similar names (supports board/steam names).
+    /// Pattern match on a given string to find similar names (supports board/steam names), returns at most
+    /// [MAX_NAME_MATCHES] users ordered by name.
+    ///
+    /// `%` and `_` in `nick_name` match themselves rather than acting as wildcards, see [escape_like].
+    ///
+    /// The expression matches the `users_name_trgm_idx` index (see `db/migrations`), so keep them in sync.
     pub async fn check_board_name(pool: &PgPool, nick_name: &str) -> std::result::Result<Vec<String>, sqlx::Error> {
         // Limitation to how SQLX inserts strings.
-        let nick_name = format!("%{}%", &nick_name);
+        let nick_name = format!("%{}%", escape_like(nick_name));
         sqlx::query_scalar(
             r#"
                 SELECT users.profile_number FROM users
-                WHERE 
-                    CASE
-                        WHEN users.board_name IS NULL
-                            THEN LOWER(users.steam_name) LIKE LOWER($1)
-                        WHEN users.board_name IS NOT NULL
-                            THEN LOWER(users.board_name) LIKE LOWER($1)
-                    END
+                WHERE LOWER(COALESCE(users.board_name, users.steam_name)) LIKE LOWER($1) ESCAPE '\'
+                ORDER BY LOWER(COALESCE(users.board_name, users.steam_name)), users.profile_number
+                LIMIT $2
                 "#,
         )
         .bind(nick_name)
+        .bind(MAX_NAME_MATCHES)
         .fetch_all(pool)
         .await
     }
+    /// Returns a [UsersDisplay] for every `profile_number` given, in one query.
+    pub async fn get_users_display(pool: &PgPool, profile_numbers: &[String]) -> Result<Vec<UsersDisplay>, sqlx::Error> {
+        sqlx::query_as::<_, UsersDisplay>(
+            r#" SELECT users.profile_number,
+            COALESCE(users.board_name, users.steam_name) as user_name, 
+            users.avatar
+                FROM users WHERE users.profile_number = ANY($1)
+                ORDER BY user_name"#,
+        )
+        .bind(profile_numbers)
+        .fetch_all(pool)
+        .await
+    }
+    /// Returns the [UsersPage] for every given `profile_number` that exists, keyed by `profile_number`.
+    pub async fn get_users_data_batch(
+        pool: &PgPool,
+        profile_numbers: &[String],
+    ) -> Result<HashMap<String, UsersPage>, sqlx::Error> {
+        let users = sqlx::query_as::<_, UsersDisplay>(
+            r#" SELECT users.profile_number,
+            COALESCE(users.board_name, users.steam_name) as user_name, 
+            users.avatar
+                FROM users WHERE users.profile_number = ANY($1)"#,
+        )
+        .bind(profile_numbers)
+        .fetch_all(pool)
+        .await?;
+        Ok(users
+            .into_iter()
+            .map(|u| (u.profile_number, UsersPage { user_name: u.user_name, avatar: u.avatar }))
+            .collect())
+    }
     /// Returns a list of all banned player's `profile_numbers`.
     pub async fn get_banned(pool: &PgPool) -> Result<Vec<String>, sqlx::Error> {
         sqlx::query_scalar(r#"SELECT users.profile_number FROM users WHERE users.banned = True"#)
             .fetch_all(pool)
             .await
     }
-    /// Returns a list of all banned player's as a [UsersDisplay].
-    pub async fn get_banned_display(pool: &PgPool) -> Result<Vec<UsersDisplay>, sqlx::Error> {
+    /// Returns a page of banned players as a [UsersDisplay], ordered by [BannedOrder].
+    ///
+    /// A `limit` of `None` returns every banned player after `offset`.
+    pub async fn get_banned_display(
+        pool: &PgPool,
+        order: BannedOrder,
+        offset: i64,
+        limit: Option<i64>,
+    ) -> Result<Vec<UsersDisplay>, sqlx::Error> {
         sqlx::query_as::<_, UsersDisplay>(
             r#" SELECT users.profile_number,
             COALESCE(users.board_name, users.steam_name) as user_name, 
             users.avatar
-                FROM users WHERE users.banned = 'true'"#,
+                FROM users WHERE users.banned = 'true'
+                ORDER BY CASE WHEN $1 THEN users.banned_at END DESC NULLS LAST,
+                    LOWER(COALESCE(users.board_name, users.steam_name)), users.profile_number
+                OFFSET $2 LIMIT $3"#,
         )
+        .bind(order == BannedOrder::Recent)
+        .bind(offset)
+        .bind(limit)
         .fetch_all(pool)
         .await
     }
@@ -101,6 +192,39 @@ impl Users {
             .fetch_one(pool)
             .await
     }
+    /// Sets the banned flag for a user. Banning also bans all of the user's changelog entries in the same transaction,
+    /// unbanning only clears the flag as individual times may have been banned for other reasons.
+    ///
+    /// Returns the number of changelog entries that were newly banned, or `None` if the user does not exist.
+    pub async fn set_banned(pool: &PgPool, profile_number: &str, banned: bool) -> Result<Option<u64>, sqlx::Error> {
+        let mut transaction = pool.begin().await?;
+        let user = sqlx::query(
+            r#"
+                UPDATE users SET banned = $2,
+                    banned_at = CASE WHEN NOT $2 THEN NULL WHEN banned THEN banned_at ELSE NOW() END
+                WHERE profile_number = $1"#,
+        )
+        .bind(profile_number)
+        .bind(banned)
+        .execute(&mut *transaction)
+        .await?;
+        if user.rows_affected() == 0 {
+            transaction.rollback().await?;
+            return Ok(None);
+        }
+        let mut banned_times = 0;
+        if banned {
+            banned_times = sqlx::query(
+                r#"UPDATE changelog SET banned = true WHERE profile_number = $1 AND banned = false"#,
+            )
+            .bind(profile_number)
+            .execute(&mut *transaction)
+            .await?
+            .rows_affected();
+        }
+        transaction.commit().await?;
+        Ok(Some(banned_times))
+    }
     #[allow(dead_code)]
     /// Returns the title associated with the user.
     pub async fn get_title(pool: &PgPool, profile_number: String) -> Result<Option<String>, sqlx::Error> {
@@ -109,7 +233,6 @@ impl Users {
             .fetch_optional(pool)
             .await
     }
-    #[allow(dead_code)]
     /// Returns a [Socials] associated with a given user's `profile_number`.
     pub async fn get_socials(pool: &PgPool, profile_number: String) -> Result<Option<Socials>, sqlx::Error> {
         sqlx::query_as::<_, Socials>(
@@ -122,7 +245,13 @@ impl Users {
         .fetch_optional(pool)
         .await
     }
-    #[allow(dead_code)]
+    /// Returns the `profile_number` of the user with the given `auth_hash`.
+    pub async fn get_profile_number_by_auth_hash(pool: &PgPool, auth_hash: &str) -> Result<Option<String>, sqlx::Error> {
+        sqlx::query_scalar(r#"SELECT profile_number FROM users WHERE auth_hash = $1"#)
+            .bind(auth_hash)
+            .fetch_optional(pool)
+            .await
+    }
     /// Returns the admin information associated with the user.
     pub async fn get_admin_for_user(pool: &PgPool, profile_number: String) -> Result<Option<i32>, sqlx::Error> {
         sqlx::query_scalar(r#"SELECT admin FROM users WHERE profile_number = $1"#)
@@ -156,17 +285,27 @@ impl Users {
             .await
     }
     /// Returns all users that have donated to the board. Ordered by highest amount.
-    pub async fn get_donators(pool: &PgPool) -> Result<Vec<Users>, sqlx::Error> {
-        sqlx::query_as::<_, Users>(
+    ///
+    /// `donation_amount` is free text, amounts that are not a plain decimal number are treated as `0` rather than failing the cast.
+    /// Missing names and avatars are returned as empty strings.
+    pub async fn get_donators(pool: &PgPool) -> Result<Vec<UsersDisplayDonation>, sqlx::Error> {
+        sqlx::query_as::<_, UsersDisplayDonation>(
             r#"
-        SELECT * FROM users
-            WHERE donation_amount IS NOT NULL
-            ORDER BY CAST(donation_amount AS decimal) DESC;"#,
+        SELECT t.profile_number, t.user_name, t.avatar,
+            CASE WHEN t.amount ~ '^[0-9]+(\.[0-9]+)?$' THEN CAST(t.amount AS DOUBLE PRECISION) ELSE 0 END AS donation_amount
+            FROM (
+                SELECT users.profile_number,
+                    COALESCE(users.board_name, users.steam_name, '') AS user_name,
+                    COALESCE(users.avatar, '') AS avatar, TRIM(users.donation_amount) AS amount
+                FROM users
+                WHERE users.donation_amount IS NOT NULL
+            ) t
+            ORDER BY donation_amount DESC, t.profile_number;"#,
         )
         .fetch_all(pool)
         .await
     }
-    /// Returns a [ProfileData] for the given `profile_number`.
+    /// Returns a [ProfileData] for the given `profile_number`, game modes the player has no scores in are `None`.
     pub async fn get_profile(pool: &PgPool, profile_number: &String) -> Result<ProfileData, sqlx::Error> {
         let s1 = r#"SELECT old.steam_id AS map, old.name AS map_name, old.score, old.timestamp FROM 
             (SELECT maps.steam_id, maps.name, changelog.score, changelog.timestamp FROM maps 
@@ -192,22 +331,22 @@ impl Users {
         let oldest_sp = sqlx::query_as::<_, MapScoreDate>(&format!("{}{}{}", s1, "MIN", s2))
             .bind(profile_number)
             .bind(false)
-            .fetch_one(pool)
+            .fetch_optional(pool)
             .await?;
         let newest_sp = sqlx::query_as::<_, MapScoreDate>(&format!("{}{}{}", s1, "MAX", s2))
             .bind(profile_number)
             .bind(false)
-            .fetch_one(pool)
+            .fetch_optional(pool)
             .await?;
         let oldest_coop = sqlx::query_as::<_, MapScoreDate>(&format!("{}{}{}", s1, "MIN", s2))
             .bind(profile_number)
             .bind(true)
-            .fetch_one(pool)
+            .fetch_optional(pool)
             .await?;
         let newest_coop = sqlx::query_as::<_, MapScoreDate>(&format!("{}{}{}", s1, "MAX", s2))
             .bind(profile_number)
             .bind(true)
-            .fetch_one(pool)
+            .fetch_optional(pool)
             .await?;
         Ok(ProfileData {
             oldest_sp,
@@ -216,8 +355,9 @@ impl Users {
             newest_coop,
         })
     }
-    // TODO: Consider using profanity filter (only for really bad names): https://docs.rs/censor/latest/censor/
     /// Inserts a new user into the databse from a given [Users]. Returns the [Users] object.
+    ///
+    /// Names are not filtered here, see [moderate_new_user](crate::tools::moderation::moderate_new_user).
     pub async fn insert_new_users(pool: &PgPool, new_user: Users) -> Result<Users, sqlx::Error> {
         // let mut res = String::new();
         // We do not care about the returning profile_number. As it is not generated and we already have it
@@ -225,8 +365,8 @@ impl Users {
             r#"
                 INSERT INTO Users
                 (profile_number, board_name, steam_name, banned, registered, 
-                avatar, twitch, youtube, title, admin, donation_amount, discord_id)
-                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
+                avatar, twitch, youtube, title, admin, donation_amount, discord_id, needs_review)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)
                 RETURNING *"#,
         )
         .bind(new_user.profile_number)
@@ -241,21 +381,32 @@ impl Users {
         .bind(new_user.admin)
         .bind(new_user.donation_amount)
         .bind(new_user.discord_id)
+        .bind(new_user.needs_review)
         .fetch_one(pool)
         .await
     }
+    /// Returns all users whose names were flagged for review.
+    pub async fn get_flagged(pool: &PgPool) -> Result<Vec<FlaggedUser>, sqlx::Error> {
+        sqlx::query_as::<_, FlaggedUser>(
+            r#"SELECT profile_number, board_name, steam_name, banned, avatar, needs_review
+                FROM users WHERE needs_review = true ORDER BY profile_number"#,
+        )
+            .fetch_all(pool)
+            .await
+    }
     #[allow(dead_code)]
     /// Update a given user with a new [Users] object.
     pub async fn update_existing_user(pool: &PgPool, updated_user: Users) -> Result<Users, sqlx::Error> {
-        // TODO: Check to make sure user has correct AUTH to update specific items
+        // TODO: Check to make sure user has correct AUTH to update specific items, see crate::tools::auth
         // (board_name should only be changed by the backend, admin should only be updated by admin etc)
         sqlx::query_as::<_, Users>(
             r#"
                 UPDATE users
-                SET board_name = $1, steam_name = $2, banned = $3, registered = $4, 
+                SET board_name = $1, steam_name = $2, banned = $3, registered = $4,
+                banned_at = CASE WHEN NOT $3 THEN NULL WHEN banned THEN banned_at ELSE NOW() END,
                 avatar = $5, twitch = $6, youtube = $7, title = $8, admin = $9,
-                donation_amount = $10, discord_id = $11
-                WHERE profile_number = $12 RETURNING *"#,
+                donation_amount = $10, discord_id = $11, needs_review = $12
+                WHERE profile_number = $13 RETURNING *"#,
         )
         .bind(updated_user.board_name)
         .bind(updated_user.steam_name)
@@ -268,16 +419,17 @@ impl Users {
         .bind(updated_user.admin)
         .bind(updated_user.donation_amount)
         .bind(updated_user.discord_id)
+        .bind(updated_user.needs_review)
         .bind(updated_user.profile_number)
         .fetch_one(pool)
         .await
     }
-    /// Returns the **PREVIOUS** `avatar` after updating.
+    /// Returns the **PREVIOUS** `avatar` after updating, `None` if the user did not have one.
     pub async fn update_avatar(
         pool: &PgPool,
         profile_number: &str,
         avatar: &str,
-    ) -> Result<String, sqlx::Error> {
+    ) -> Result<Option<String>, sqlx::Error> {
         sqlx::query_scalar(
             r#"WITH old AS (
                 SELECT avatar FROM users WHERE profile_number = $2
@@ -302,3 +454,27 @@ impl Users {
         .await
     }
 }
+
+impl UserProfile {
+    /// Builds the [UserProfile] for `user`. The `auth_hash` is always dropped, and fields only admins can see are dropped
+    /// unless `is_admin`.
+    pub fn new(user: Users, data: ProfileData, is_admin: bool) -> UserProfile {
+        UserProfile {
+            profile_number: user.profile_number,
+            board_name: user.board_name,
+            steam_name: user.steam_name,
+            banned: user.banned,
+            registered: user.registered,
+            avatar: user.avatar,
+            twitch: user.twitch,
+            youtube: user.youtube,
+            title: user.title,
+            donation_amount: user.donation_amount,
+            country_id: user.country_id,
+            admin: is_admin.then_some(user.admin),
+            discord_id: user.discord_id.filter(|_| is_admin),
+            needs_review: is_admin.then_some(user.needs_review),
+            data,
+        }
+    }
+}
@@ -33,6 +33,9 @@
 //! #### Cache
 //! [Caching implementations](tools::cache) specific to the board, well documented.
 //!
+//! #### Demo
+//! [Parsing](tools::demo) for uploaded demo files, and a small [BackBlaze client](tools::backblaze) used to store them.
+//!
 //! #### Config
 //! Extracts [configuration](tools::config) information from the local .env file to be used to customize boards. Includes networking information,
 //! proof requirements for the boards, connection information for the database and external file servers etc.
@@ -40,12 +43,13 @@
 #![allow(rustdoc::private_intra_doc_links)]
 #[macro_use]
 extern crate serde_derive;
-use actix_cors::Cors;
-use actix_web::{middleware::Logger, web, App, HttpServer};
+use actix_web::{
+    middleware::{from_fn, Compress, Logger},
+    web, App, HttpServer,
+};
 use anyhow::{Error, Result};
 use dotenv::dotenv;
 use env_logger::Env;
-use sqlx::PgPool;
 
 /// Module for the API versions containing handlers for API endpoints.
 mod api;
@@ -68,9 +72,12 @@ async fn main() -> Result<(), Error> {
     // println!("{:#?}", config);
     // Database pool, uses manager to build new database pool, saved in web::Data.
     // Reference Code: https://github.com/actix/examples/blob/master/database_interactions/diesel/src/main.rs
-    let pool = PgPool::connect(&config.database_url).await?;
+    // Pool size and timeouts come from `DATABASE.*`, see `DatabaseConfig`.
+    let pool = crate::tools::db::connect(&config.database_url, &config.database).await?;
+    crate::tools::helpers::set_points_curve(config.points);
 
-    // Initializes Logger with "default" format:  %a %t "%r" %s %b "%{Referer}i" "%{User-Agent}i" %T
+    // Initializes Logger with the "default" format, with the client address in place of %a (see `client_logger`):
+    // %a %t "%r" %s %b "%{Referer}i" "%{User-Agent}i" %T
     // Remote-IP, Time, First line of request, Response status, Size of response in bytes, Referer, User-Agent, Time to serve
     // std::env::set_var("RUST_LOG", "actix_web=info");
     env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
@@ -80,22 +87,36 @@ async fn main() -> Result<(), Error> {
     let default_cat_ids = crate::tools::helpers::get_default_cat_ids(&pool).await;
     // Construct the cache.
     let init_data = crate::tools::cache::CacheState::new(&pool, &config, default_cat_ids).await;
+    // Keep the previews warm in the background.
+    init_data.spawn_preview_refresh(pool.clone());
+    let metrics = crate::tools::metrics::Metrics::new()?;
+    // Warn when requests have to wait for a database connection.
+    crate::tools::db::spawn_acquire_monitor(pool.clone(), config.database, metrics.clone());
+    let limiter = crate::tools::ratelimit::RateLimiter::new(config.ratelimit);
+    let maintenance = crate::tools::maintenance::Maintenance::new(&config.maintenance);
+    // Where demo files are kept, see `STORAGE.BACKEND`.
+    let storage = web::Data::from(crate::tools::storage::demo_storage(&config));
     println!(
         "Server starting at http://{}:{}/",
         config.server.host, config.server.port
     );
     // Start our web server, mount and set up routes, data, wrapping, middleware and loggers
     HttpServer::new(move || {
-        let cors = Cors::default()
-            .allowed_origin("http://localhost:3000")
-            .allowed_methods(vec!["GET", "POST", "PUT", "DELETE"])
-            .max_age(3600);
         App::new()
-            .wrap(cors)
-            .wrap(Logger::default())
+            // Inside CORS, so browsers can read the 503 sent while in maintenance mode.
+            .wrap(from_fn(crate::tools::maintenance::block_changes))
+            .wrap(crate::tools::cors::cors(&config.cors))
+            // Responses are compressed for clients that send `Accept-Encoding`, the previews and leaderboards are large.
+            .wrap(Compress::default())
+            .wrap(client_logger(config.trusted_proxies.clone()))
+            .wrap(from_fn(crate::tools::metrics::track_requests))
             .app_data(web::Data::new(pool.clone()))
             .app_data(web::Data::new(config.clone()))
             .app_data(web::Data::new(init_data.clone()))
+            .app_data(web::Data::new(metrics.clone()))
+            .app_data(web::Data::new(limiter.clone()))
+            .app_data(web::Data::new(maintenance.clone()))
+            .app_data(storage.clone())
             .configure(api::v1::handlers::init::init)
     })
     .bind(format!("{}:{}", host, port))?
@@ -103,3 +124,15 @@ async fn main() -> Result<(), Error> {
     .await?;
     Ok(())
 }
+
+/// [Logger::default], but logs the client address from [crate::tools::client::client_ip] in place of the peer address.
+/// Forwarding headers are only trusted from `TRUSTED_PROXIES`, unlike Logger's own `%{r}a`.
+fn client_logger(trusted_proxies: Vec<std::net::IpAddr>) -> Logger {
+    Logger::new(r#"%{client}xi %t "%r" %s %b "%{Referer}i" "%{User-Agent}i" %T"#).custom_request_replace(
+        "client",
+        move |req| match crate::tools::client::client_ip(req.request(), &trusted_proxies) {
+            Some(ip) => ip.to_string(),
+            None => crate::tools::client::UNKNOWN_CLIENT.to_string(),
+        },
+    )
+}
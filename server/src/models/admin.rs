@@ -1,10 +1,88 @@
+use utoipa::{IntoParams, ToSchema};
+
 // Database
 
 /// Empty struct to allow for implementation blocks for admin specific db interactions
 pub struct Admin {}
 
 /// Wrapper around an optional i32, for use in [actix_web::web::Query]
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema, IntoParams)]
 pub struct AdminLevel {
     pub admin_level: Option<i32>,
 }
+
+/// An admin's decision on a submission in the verification queue.
+///
+/// Approving verifies the score, rejecting bans it. `admin_note` replaces the current note when given.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct VerificationDecision {
+    pub approved: bool,
+    pub admin_note: Option<String>,
+}
+
+/// The PB chain to rebuild, `cat_id` defaults to the map's default category in `game_id` (base game if not given).
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ChainRepairParams {
+    pub profile_number: String,
+    pub map_id: String,
+    pub cat_id: Option<i32>,
+    pub game_id: Option<i32>,
+}
+
+/// Options for a changelog import, see [crate::api::v1::handlers::admin::admin_changelog_import].
+#[derive(Debug, Serialize, Deserialize, ToSchema, IntoParams)]
+pub struct ImportParams {
+    /// Import nothing if any row is invalid, rather than importing the valid rows.
+    pub all_or_nothing: Option<bool>,
+}
+
+/// A row that was imported, `index` is the row's position in the import.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct ImportedRow {
+    pub index: usize,
+    pub id: i64,
+}
+
+/// A row that could not be imported, and why.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct FailedRow {
+    pub index: usize,
+    pub error: String,
+}
+
+/// Summary of a changelog import, every row is either `imported` or `failed`, unless nothing was imported because of
+/// `all_or_nothing`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct ChangelogImport {
+    pub imported: Vec<ImportedRow>,
+    pub failed: Vec<FailedRow>,
+}
+
+/// Which cached previews to rebuild, see [crate::api::v1::handlers::admin::admin_cache_rebuild].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum CacheTarget {
+    SpPreviews,
+    CoopPreviews,
+    #[default]
+    All,
+}
+
+/// Wrapper around an optional [CacheTarget], for use in [actix_web::web::Query]
+#[derive(Debug, Serialize, Deserialize, ToSchema, IntoParams)]
+pub struct CacheRebuildParams {
+    pub target: Option<CacheTarget>,
+}
+
+/// A cache entry that was rebuilt, and how long it took in milliseconds.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct CacheRebuilt {
+    pub target: String,
+    pub millis: u64,
+}
+
+/// Whether maintenance mode is enabled, see [crate::tools::maintenance].
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+pub struct MaintenanceState {
+    pub enabled: bool,
+}
@@ -0,0 +1,36 @@
+use chrono::NaiveDateTime;
+use sqlx::FromRow;
+use utoipa::ToSchema;
+
+/// One-to-one struct for Category data.
+#[derive(Serialize, Deserialize, Debug, FromRow, ToSchema)]
+pub struct Categories {
+    pub id: i32,
+    pub name: String,
+    pub map_id: String,
+    pub rules_id: Option<i32>,
+    pub updated: Option<NaiveDateTime>,
+    /// The lowest possible score on the category, lower submissions are auto-flagged for review.
+    pub min_score: Option<i32>,
+}
+
+/// One-to-one struct for category rules.
+#[allow(dead_code)]
+#[derive(Serialize, Deserialize, Debug, FromRow, ToSchema)]
+pub struct CategoryRules {
+    pub id: i32,
+    pub rules: Option<String>,
+    pub external_link: Option<String>,
+    pub is_active: Option<bool>,
+    pub updated: Option<NaiveDateTime>,
+}
+
+/// A category on a map with its rules, `is_default` marks the category that should be preselected for the map.
+#[derive(Serialize, Deserialize, Debug, Clone, FromRow, ToSchema)]
+pub struct Category {
+    pub id: i32,
+    pub name: String,
+    pub rules: Option<String>,
+    pub external_link: Option<String>,
+    pub is_default: bool,
+}
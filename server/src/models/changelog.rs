@@ -3,9 +3,26 @@ use chrono::NaiveDate;
 use sqlx::FromRow;
 
 use super::users::UsersDisplayCount;
+use utoipa::{IntoParams, ToSchema};
+
+/// Where a changelog entry came from, stored in the `changelog_source` enum.
+///
+/// Defaults to `steam_auto`, like the column, as entries that are not submitted to the boards are pulled from Steam.
+#[derive(Serialize, Deserialize, sqlx::Type, Debug, Clone, Copy, PartialEq, Eq, Default, ToSchema)]
+#[sqlx(type_name = "changelog_source", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum ChangelogSource {
+    /// Submitted by a player.
+    Manual,
+    /// Pulled from the Steam leaderboards.
+    #[default]
+    SteamAuto,
+    /// Added through the admin changelog import.
+    Import,
+}
 
 /// One-to-one struct for changelog data.
-#[derive(Serialize, Deserialize, FromRow, Debug, Clone)]
+#[derive(Serialize, Deserialize, FromRow, Debug, Clone, PartialEq, ToSchema)]
 pub struct Changelog {
     pub id: i64,
     pub timestamp: Option<NaiveDateTime>,
@@ -26,10 +43,13 @@ pub struct Changelog {
     pub verified: Option<bool>,
     pub admin_note: Option<String>,
     pub updated: Option<NaiveDateTime>,
+    /// Set when the entry is inserted, updates leave it unchanged.
+    #[serde(default)]
+    pub source: ChangelogSource,
 }
 
 /// One-to-one struct for evidence_requirements
-#[derive(Serialize, Deserialize, Debug, Clone, FromRow)]
+#[derive(Serialize, Deserialize, Debug, Clone, FromRow, ToSchema)]
 pub struct EvidenceRequirements {
     pub id: i32,
     pub rank: i32,
@@ -41,7 +61,7 @@ pub struct EvidenceRequirements {
 }
 
 /// All changelog data except for the ID, for table insertion.
-#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+#[derive(Serialize, Deserialize, Default, Debug, Clone, ToSchema)]
 pub struct ChangelogInsert {
     pub timestamp: Option<NaiveDateTime>,
     pub profile_number: String,
@@ -60,10 +80,36 @@ pub struct ChangelogInsert {
     pub score_delta: Option<i32>,
     pub verified: Option<bool>,
     pub admin_note: Option<String>,
+    #[serde(default)]
+    pub source: ChangelogSource,
+}
+
+/// Changes to an existing changelog entry, only the fields that are provided are updated.
+///
+/// Fields can be changed but not cleared, use a full update through [Changelog] to set a field to `null`.
+#[derive(Serialize, Deserialize, Default, Debug, Clone, ToSchema)]
+pub struct ChangelogPatch {
+    pub id: i64,
+    pub timestamp: Option<NaiveDateTime>,
+    pub profile_number: Option<String>,
+    pub score: Option<i32>,
+    pub map_id: Option<String>,
+    pub demo_id: Option<i64>,
+    pub banned: Option<bool>,
+    pub youtube_id: Option<String>,
+    pub coop_id: Option<i64>,
+    pub post_rank: Option<i32>,
+    pub pre_rank: Option<i32>,
+    pub submission: Option<i32>,
+    pub note: Option<String>,
+    pub category_id: Option<i32>,
+    pub score_delta: Option<i32>,
+    pub verified: Option<bool>,
+    pub admin_note: Option<String>,
 }
 
 /// Indlues additional information from joins that includes details like map name, username and profile image.
-#[derive(Serialize, FromRow, Debug)]
+#[derive(Serialize, FromRow, Debug, ToSchema)]
 pub struct ChangelogPage {
     pub id: i64,
     pub timestamp: Option<NaiveDateTime>,
@@ -83,6 +129,7 @@ pub struct ChangelogPage {
     pub score_delta: Option<i32>,
     pub verified: Option<bool>,
     pub admin_note: Option<String>,
+    pub source: ChangelogSource,
     pub map_name: String,
     pub user_name: String,
     pub avatar: String,
@@ -93,7 +140,7 @@ pub struct ChangelogPage {
 }
 
 /// Indlues additional information from joins that includes details like map name, username and profile image.
-#[derive(Serialize, FromRow, Debug)]
+#[derive(Serialize, FromRow, Debug, ToSchema)]
 pub struct Graph {
     pub date: Option<NaiveDate>,
     pub count: i64,
@@ -102,7 +149,7 @@ pub struct Graph {
 // Helpers
 
 /// Details on a user's banned/unverified runs for the admin display page
-#[derive(Debug, FromRow, Deserialize, Serialize, Clone)]
+#[derive(Debug, FromRow, Deserialize, Serialize, Clone, ToSchema)]
 pub struct BannedTimeDetails {
     pub profile_number: String,
     pub user_name: String,
@@ -113,7 +160,7 @@ pub struct BannedTimeDetails {
 }
 
 /// Values that we return after checking if a score is valid to be added to the database.
-#[derive(Default, Debug, Serialize)]
+#[derive(Default, Debug, Serialize, ToSchema)]
 pub struct CalcValues {
     pub previous_id: Option<i64>,
     pub post_rank: Option<i32>,
@@ -123,7 +170,7 @@ pub struct CalcValues {
 }
 
 /// Wrapper to send a profile number as a search result
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, ToSchema, IntoParams)]
 pub struct ScoreParams {
     pub profile_number: String,
     pub score: i32,
@@ -131,8 +178,18 @@ pub struct ScoreParams {
     pub game_id: Option<i32>,
 }
 
+/// A world record in a map's record progression, `improvement` is how much it beat the previous record by.
+#[derive(Serialize, Deserialize, FromRow, Debug, Clone, ToSchema)]
+pub struct WrHistory {
+    pub profile_number: String,
+    pub user_name: Option<String>,
+    pub score: i32,
+    pub timestamp: Option<NaiveDateTime>,
+    pub improvement: Option<i32>,
+}
+
 /// Wrapper to allow queries to include `map_id`, `profile_number` and optional `cat_id`.
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, ToSchema, IntoParams)]
 pub struct HistoryParams {
     pub profile_number: String,
     pub map_id: String,
@@ -140,8 +197,111 @@ pub struct HistoryParams {
     pub game_id: Option<i32>,
 }
 
+/// A single run in the recent activity feed, with what is needed to display it.
+///
+/// Coop runs are returned once, with the second player as the partner.
+#[derive(Serialize, Deserialize, FromRow, Debug, Clone, ToSchema)]
+pub struct RecentActivity {
+    pub id: i64,
+    pub timestamp: Option<NaiveDateTime>,
+    pub profile_number: String,
+    pub user_name: Option<String>,
+    pub avatar: Option<String>,
+    pub map_id: String,
+    pub map_name: String,
+    pub is_coop: bool,
+    pub score: i32,
+    pub post_rank: Option<i32>,
+    pub category_id: i32,
+    pub partner_profile_number: Option<String>,
+    pub partner_name: Option<String>,
+    pub partner_avatar: Option<String>,
+    pub has_demo: bool,
+    pub has_youtube: bool,
+}
+
+/// A player's best verified time on a map's default category, with the rank and points it is worth.
+///
+/// `rank` is `None` if the time is not ranked, such as when the player is banned.
+#[derive(Serialize, Deserialize, FromRow, Debug, Clone, ToSchema)]
+pub struct PersonalBest {
+    pub id: i64,
+    pub map_id: String,
+    pub map_name: String,
+    pub is_coop: bool,
+    pub score: i32,
+    pub timestamp: Option<NaiveDateTime>,
+    pub category_id: i32,
+    pub demo_id: Option<i64>,
+    pub youtube_id: Option<String>,
+    pub rank: Option<i64>,
+    #[sqlx(default)]
+    pub points: f32,
+}
+
+/// A map a player has no verified time on, with the world record to aim for on its default category.
+///
+/// `wr_score` is `None` if nobody has a valid time on the map yet.
+#[derive(Serialize, Deserialize, FromRow, Debug, Clone, PartialEq, ToSchema)]
+pub struct MissingMap {
+    pub map_id: String,
+    pub map_name: String,
+    pub chapter_id: i32,
+    pub is_coop: bool,
+    pub category_id: i32,
+    pub wr_score: Option<i32>,
+}
+
+/// A player's rank on a single map, using the same `banned`/`verified` filters as the map pages.
+///
+/// Tied scores share the same rank, matching the ranks on the map page.
+#[derive(Serialize, Deserialize, FromRow, Debug, Clone, PartialEq, ToSchema)]
+pub struct PlayerRank {
+    pub rank: i64,
+    pub score: i32,
+    #[sqlx(default)]
+    pub points: f32,
+}
+
+/// Query parameters for looking up a player's rank on a map.
+#[derive(Deserialize, Debug, ToSchema, IntoParams)]
+pub struct PlayerRankParams {
+    pub profile_number: String,
+    pub cat_id: Option<i32>,
+    pub game_id: Option<i32>,
+}
+
+/// Two players' best times on a singleplayer map compared head to head, see [PlayerRank].
+///
+/// A side is `None` if that player has no valid time on the map.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, ToSchema)]
+pub struct PlayerComparison {
+    pub p1: Option<PlayerRank>,
+    pub p2: Option<PlayerRank>,
+    /// `p2`'s score minus `p1`'s score, positive when `p1` is faster. `None` unless both players have a time.
+    pub gap: Option<i32>,
+    /// The `gap` formatted as a time, e.g. `-0.35`.
+    pub formatted_gap: Option<String>,
+}
+
+/// Query parameters for comparing two players on a map.
+#[derive(Deserialize, Debug, ToSchema, IntoParams)]
+pub struct CompareParams {
+    pub p1: String,
+    pub p2: String,
+    pub cat_id: Option<i32>,
+    pub game_id: Option<i32>,
+}
+
+/// Query parameters for the recent activity feed.
+#[derive(Deserialize, Debug, ToSchema, IntoParams)]
+pub struct RecentParams {
+    pub limit: Option<i64>,
+    pub game_id: Option<i32>,
+}
+
 /// All the accepted query parameters for the SubmissionChangelog page.
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, ToSchema, IntoParams)]
 pub struct ChangelogQueryParams {
     pub limit: Option<u32>,
     pub nick_name: Option<String>,
@@ -154,10 +314,24 @@ pub struct ChangelogQueryParams {
     pub yt: Option<bool>,
     pub first: Option<i64>,
     pub last: Option<i64>,
+    /// Only scores in the given category.
+    pub cat_id: Option<i32>,
+    /// Only banned (`true`) or unbanned (`false`) scores.
+    pub banned: Option<bool>,
+    /// Only scores with the given submission type.
+    pub submission: Option<i32>,
+    /// Only scores from the given source.
+    pub source: Option<ChangelogSource>,
+    /// Only scores on or after this date (`%Y-%m-%d`).
+    pub start_date: Option<NaiveDate>,
+    /// Only scores on or before this date (`%Y-%m-%d`).
+    pub end_date: Option<NaiveDate>,
+    /// Skip this many results, used with `limit` for paging.
+    pub offset: Option<u32>,
 }
 
 /// Query parameters for searching for a given
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, ToSchema)]
 pub struct ChangelogSearchQuery {
     pub profile_number: String,
     pub map_id: String,
@@ -167,7 +341,7 @@ pub struct ChangelogSearchQuery {
 }
 
 /// Fields for a submission to the changelog
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema, IntoParams)]
 pub struct SubmissionChangelog {
     pub timestamp: String,
     pub profile_number: String,
@@ -177,9 +351,11 @@ pub struct SubmissionChangelog {
     pub note: Option<String>,
     pub category_id: Option<i32>,
     pub game_id: Option<i32>,
+    /// Only validate the submission, nothing is uploaded or saved.
+    pub dry_run: Option<bool>,
 }
 /// Used to lookup information on a specific score.
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, ToSchema, IntoParams)]
 pub struct ScoreLookup {
     pub profile_number: String,
     pub score: i32,
@@ -189,7 +365,7 @@ pub struct ScoreLookup {
 }
 
 /// Map ID & Name, score and timestamp for a given score.
-#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow, ToSchema)]
 pub struct MapScoreDate {
     pub map: String,
     pub map_name: String,
@@ -198,7 +374,7 @@ pub struct MapScoreDate {
 }
 
 /// Used to count the number of scores per-user.
-#[derive(Serialize, Deserialize, FromRow, Clone, Debug)]
+#[derive(Serialize, Deserialize, FromRow, Clone, Debug, ToSchema)]
 pub struct NumScores {
     pub count: i64,
     pub profile_number: String,
@@ -207,7 +383,7 @@ pub struct NumScores {
 }
 
 /// Used to represent users and their score deltas on a given map.
-#[derive(Serialize, Deserialize, Clone, Debug, FromRow)]
+#[derive(Serialize, Deserialize, Clone, Debug, FromRow, ToSchema)]
 pub struct ScoreDeltaComparison {
     pub profile_number: String,
     pub user_name: String,
@@ -218,14 +394,14 @@ pub struct ScoreDeltaComparison {
 }
 
 /// Representation of the number of World Records per Map for a given map.
-#[derive(Serialize, Deserialize, Clone, Debug, FromRow)]
+#[derive(Serialize, Deserialize, Clone, Debug, FromRow, ToSchema)]
 pub struct NumUpdatePerMap {
     pub map_id: String,
     pub map_name: String,
     pub count: i64,
 }
 /// Struct for the "Recap", taken from NeKz's recap bot on the Discord server.
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
 pub struct Recap {
     pub num_wrs: Vec<UsersDisplayCount>,
     pub num_demos: Vec<UsersDisplayCount>,
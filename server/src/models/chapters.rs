@@ -1,7 +1,8 @@
 use sqlx::FromRow;
+use utoipa::{IntoParams, ToSchema};
 
 /// One-to-one struct for chapter data.
-#[derive(Serialize, Deserialize, FromRow, Debug)]
+#[derive(Serialize, Deserialize, FromRow, Debug, ToSchema)]
 pub struct Chapters {
     pub id: i32,
     pub chapter_name: Option<String>,
@@ -9,29 +10,94 @@ pub struct Chapters {
     pub game_id: i32,
 }
 
+/// A chapter with the maps in it, in the order they appear in the game.
+#[derive(Serialize, Deserialize, FromRow, Debug, ToSchema)]
+pub struct ChapterWithMaps {
+    pub chapter_id: i32,
+    pub name: Option<String>,
+    pub is_multiplayer: bool,
+    #[sqlx(skip)]
+    pub maps: Vec<ChapterMap>,
+}
+
+/// A map listed under a [ChapterWithMaps].
+#[derive(Serialize, Deserialize, Debug, PartialEq, ToSchema)]
+pub struct ChapterMap {
+    pub steam_id: String,
+    pub name: String,
+    pub default_cat_id: i32,
+}
+
 /// One-to-one struct for game data.
-#[derive(Serialize, Deserialize, FromRow)]
+///
+/// `tick_rate` is the number of score units in a second for the game's boards, used to format scores as times. It is 100
+/// for games whose scores come from the Steam leaderboards (in centiseconds), mods that count scores in ticks use their tick
+/// rate (e.g. 60 or 66).
+#[derive(Serialize, Deserialize, FromRow, ToSchema)]
 pub struct Games {
     pub id: i32,
     pub game_name: String,
+    pub tick_rate: i32,
 }
 
 /// Query wrapper for game_id
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, ToSchema, IntoParams)]
 pub struct GameID {
     pub game_id: Option<i32>,
 }
 
 // Currently a dumbass work around to issues with deserializing an option natively theough the Query
 /// Generic wrapper around an Option i32 for [actix_web::web::Query]
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema, IntoParams)]
 pub struct OptIDs {
     pub cat_id: Option<i32>,
     pub game_id: Option<i32>,
 }
 
+/// [OptIDs] with an optional `offset` and `limit` for paging through a map's leaderboard.
+#[derive(Debug, Deserialize, ToSchema, IntoParams)]
+pub struct PageParams {
+    pub cat_id: Option<i32>,
+    pub game_id: Option<i32>,
+    pub offset: Option<i32>,
+    pub limit: Option<i32>,
+}
+
+/// Optional `limit` query parameter for the number of entries on a leaderboard, see [crate::tools::helpers::results_limit].
+#[derive(Debug, Deserialize, ToSchema, IntoParams)]
+pub struct LimitParams {
+    pub limit: Option<i32>,
+}
+
+/// Optional `formatted` query parameter, when `true` ranked entries include a `formatted_score` (`m:ss.cc`).
+#[derive(Debug, Deserialize, ToSchema, IntoParams)]
+pub struct FormatParams {
+    pub formatted: Option<bool>,
+}
+
+/// The format a leaderboard page is returned in, see [crate::tools::export].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    Json,
+    Csv,
+}
+
+/// Optional `format` query parameter, when not given the format is picked from the `Accept` header (JSON by default).
+#[derive(Debug, Deserialize, ToSchema, IntoParams)]
+pub struct ExportParams {
+    pub format: Option<ExportFormat>,
+}
+
+/// Optional query parameters for admins reviewing a map page, unverified and/or banned times are included when `true`.
+#[derive(Debug, Deserialize, Default, ToSchema, IntoParams)]
+pub struct ReviewParams {
+    pub include_unverified: Option<bool>,
+    pub include_banned: Option<bool>,
+}
+
 /// Querying for Chapters
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, ToSchema, IntoParams)]
 pub struct ChapterQueryParams {
     pub chapter_name: Option<String>,
     pub is_multiplayer: Option<bool>,
@@ -1,8 +1,10 @@
+use crate::models::changelog::Changelog;
 use chrono::NaiveDateTime;
 use sqlx::FromRow;
+use utoipa::ToSchema;
 
 /// One-to-one struct for coop_bundled data.
-#[derive(Serialize, Deserialize, FromRow)]
+#[derive(Serialize, Deserialize, FromRow, ToSchema)]
 pub struct CoopBundled {
     pub id: i64,
     pub p_id1: String,
@@ -14,7 +16,7 @@ pub struct CoopBundled {
 }
 
 /// Insert struct for creating a new `CoopBundled`
-#[derive(Serialize, Deserialize, FromRow)]
+#[derive(Serialize, Deserialize, FromRow, ToSchema)]
 pub struct CoopBundledInsert {
     pub p_id1: String,
     pub p_id2: Option<String>,
@@ -23,8 +25,16 @@ pub struct CoopBundledInsert {
     pub cl_id2: Option<i64>,
 }
 
+/// Parameters for bundling two existing changelog entries into a coop score.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct CoopBundleParams {
+    pub cl_id1: i64,
+    pub cl_id2: i64,
+    pub p1_is_host: Option<bool>,
+}
+
 /// The minimal data we want for Coop map pages to lower bandwitch usage.
-#[derive(Serialize, FromRow, Clone)]
+#[derive(Serialize, FromRow, Clone, ToSchema)]
 pub struct CoopMap {
     pub timestamp: Option<NaiveDateTime>,
     pub profile_number1: String,
@@ -47,7 +57,7 @@ pub struct CoopMap {
 }
 
 /// The data for the preview page for all Coop Maps
-#[derive(Serialize, Deserialize, FromRow, Clone)]
+#[derive(Debug, Serialize, Deserialize, FromRow, Clone, ToSchema)]
 pub struct CoopPreview {
     pub profile_number1: String,
     pub profile_number2: Option<String>,
@@ -61,23 +71,45 @@ pub struct CoopPreview {
 }
 
 /// Wrapper for the coop map data and the rank/score.
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 pub struct CoopRanked {
     pub map_data: CoopMap,
     pub rank: i32,
     pub points: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub formatted_score: Option<String>,
 }
 
-#[derive(Deserialize, Serialize, Debug, FromRow)]
+#[derive(Deserialize, Serialize, Debug, FromRow, ToSchema)]
 pub struct CoopTempUser {
     pub cl_id: i64,
     pub profile_number: String,
 }
 
 /// Banned times for Coop
-#[derive(Serialize, FromRow)]
+#[derive(Serialize, FromRow, ToSchema)]
 pub struct CoopBanned {
     pub profile_number1: String,
     pub profile_number2: Option<String>,
     pub score: i32,
 }
+
+/// A player's bundled run in their coop PB history, with the partner they ran it with.
+///
+/// `partner_profile_number` is `None` if the run was bundled without a partner.
+#[derive(Serialize, Deserialize, FromRow, Debug, Clone, ToSchema)]
+pub struct CoopPbEntry {
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    pub changelog: Changelog,
+    pub partner_profile_number: Option<String>,
+    pub partner_name: Option<String>,
+}
+
+/// A player's coop PB history for a map, the fields are all `None` if the player does not exist.
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct CoopPbHistory {
+    pub user_name: Option<String>,
+    pub avatar: Option<String>,
+    pub pb_history: Option<Vec<CoopPbEntry>>,
+}
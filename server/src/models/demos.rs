@@ -1,8 +1,10 @@
+use crate::models::changelog::ChangelogInsert;
 use chrono::NaiveDateTime;
 use sqlx::FromRow;
+use utoipa::{IntoParams, ToSchema};
 
 /// One-to-one struct for demo data.
-#[derive(Serialize, Deserialize, FromRow, Debug, Clone)]
+#[derive(Serialize, Deserialize, FromRow, Debug, Clone, ToSchema)]
 pub struct Demos {
     pub id: i64,
     pub file_id: String,
@@ -11,10 +13,16 @@ pub struct Demos {
     pub sar_version: Option<String>,
     pub cl_id: i64,
     pub updated: Option<NaiveDateTime>,
+    pub sha1: Option<String>,
+    pub bucket_id: Option<String>,
+    pub map_name: Option<String>,
+    pub tick_count: Option<i32>,
+    pub playback_time: Option<f32>,
+    pub protocol: Option<i32>,
 }
 
 /// One-to-one struct for mtrigger data.
-#[derive(Serialize, Deserialize, FromRow, Debug, Clone)]
+#[derive(Serialize, Deserialize, FromRow, Debug, Clone, ToSchema)]
 pub struct Mtriggers {
     pub id: i32,
     pub map_id: String,
@@ -24,7 +32,7 @@ pub struct Mtriggers {
 }
 
 /// One-to-one struct for mtrigger_entry data.
-#[derive(Serialize, Deserialize, FromRow, Debug, Clone)]
+#[derive(Serialize, Deserialize, FromRow, Debug, Clone, ToSchema)]
 pub struct MtriggerEntries {
     pub id: i32,
     pub mtrigger_id: i32,
@@ -33,7 +41,7 @@ pub struct MtriggerEntries {
 }
 
 /// The bundled mtrigger & mtrigger entry
-#[derive(Serialize, Deserialize, FromRow, Debug, Clone)]
+#[derive(Serialize, Deserialize, FromRow, Debug, Clone, ToSchema)]
 pub struct MtriggerBundle {
     pub mtrigger_id: i32,
     pub map_id: String,
@@ -46,17 +54,55 @@ pub struct MtriggerBundle {
 }
 
 /// Insert struct for `Demos`, excludes `id`
-#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+#[derive(Debug, Default, Serialize, Deserialize, Clone, ToSchema)]
 pub struct DemoInsert {
     pub file_id: String,
     pub partner_name: Option<String>,
     pub parsed_successfully: bool,
     pub sar_version: Option<String>,
     pub cl_id: i64,
+    pub sha1: Option<String>,
+    pub bucket_id: Option<String>,
+    pub map_name: Option<String>,
+    pub tick_count: Option<i32>,
+    pub playback_time: Option<f32>,
+    pub protocol: Option<i32>,
+}
+
+/// The fields from a demo's header that are stored with the demo, see [DemoMetadata] for the full header.
+#[derive(Serialize, Deserialize, FromRow, Debug, Clone, PartialEq, ToSchema)]
+pub struct DemoHeader {
+    pub map_name: String,
+    pub tick_count: i32,
+    pub playback_time: f32,
+    pub sar_version: Option<String>,
+    pub protocol: i32,
+}
+
+/// Information extracted from the header of an uploaded demo file.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, ToSchema)]
+pub struct DemoMetadata {
+    pub demo_protocol: i32,
+    pub network_protocol: i32,
+    pub server_name: String,
+    pub client_name: String,
+    pub map_name: String,
+    pub game_directory: String,
+    pub playback_time: f32,
+    pub playback_ticks: i32,
+    pub playback_frames: i32,
+    pub sar_checksum: Option<SarChecksum>,
+}
+
+/// The checksums SAR appends to the end of a demo. `sar_sum` is a checksum of the SAR build used to record.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, ToSchema)]
+pub struct SarChecksum {
+    pub demo_sum: u32,
+    pub sar_sum: u32,
 }
 
 /// Insert struct for `MtriggerEntries`, excludes `id`
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
 pub struct MtriggerEntriesInsert {
     mtrigger_id: i32,
     changelog_id: i64,
@@ -64,7 +110,7 @@ pub struct MtriggerEntriesInsert {
 }
 
 /// Insert struct for `Mtriggers`, excludes `id`
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
 pub struct MtriggersInsert {
     pub map_id: String,
     pub category_id: String,
@@ -72,12 +118,90 @@ pub struct MtriggersInsert {
     pub description: Option<String>,
 }
 
+/// Result of comparing a stored demo file against the SHA1 recorded when it was uploaded.
+///
+/// `matches` is `None` for older demos that do not have a recorded SHA1.
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct DemoVerification {
+    pub demo_id: i64,
+    pub expected_sha1: Option<String>,
+    pub actual_sha1: String,
+    pub matches: Option<bool>,
+}
+
+/// The entries a demo submission would create, returned instead of saving them for a dry run.
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct DemoSubmissionPreview {
+    pub changelog: ChangelogInsert,
+    pub demo: DemoInsert,
+    pub metadata: DemoMetadata,
+}
+
+/// The demos for both sides of a bundled coop run, a side is `None` if that partner has not uploaded a demo.
+#[derive(Serialize, Deserialize, FromRow, Debug, Clone, PartialEq, ToSchema)]
+pub struct CoopDemos {
+    pub coop_id: i64,
+    pub demo_id1: Option<i64>,
+    pub demo_id2: Option<i64>,
+    pub file_id1: Option<String>,
+    pub file_id2: Option<String>,
+}
+
+/// Query parameters for looking up the demos of a bundled coop run.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema, IntoParams)]
+pub struct CoopDemoParams {
+    pub coop_id: i64,
+}
+
+/// Query parameters for demo endpoints that act on all of a player's demos.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema, IntoParams)]
+pub struct DemoPlayerParams {
+    pub profile_number: String,
+}
+
+/// Summary of a bulk demo deletion, demos that could not be removed from BackBlaze are kept in the database.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, ToSchema)]
+pub struct DemoBulkDeletion {
+    pub deleted: Vec<i64>,
+    pub failed: Vec<i64>,
+}
+
 /// Allows us to accept an optional demo_id or cl_id as a set of query parameters for demo endpoints.
 ///
 /// Intended to be used exclusively (you should either use one or the other, never both or neither) if you're calling to query for a demo,
 /// if you're using this to update a demo, both are required fields.
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Deserialize, ToSchema, IntoParams)]
 pub struct DemoOptions {
     pub demo_id: Option<i64>,
     pub cl_id: Option<i64>,
 }
+
+/// Progress of the background job that parses stored demos missing their metadata.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, ToSchema)]
+pub struct ReparseProgress {
+    pub running: bool,
+    /// Demos parsed and updated by the current (or last) job.
+    pub parsed: u64,
+    /// Demos that could not be downloaded or parsed, these are left to be retried by a later job.
+    pub failed: u64,
+    /// The last demo id the job processed, demos are processed in order of id.
+    pub last_id: i64,
+    /// Demos left without metadata as of the last finished batch.
+    pub remaining: i64,
+}
+
+/// The outcome of parsing a single batch of demos.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ReparseBatch {
+    pub parsed: u64,
+    pub failed: u64,
+    /// Highest demo id in the batch, `None` if there were no demos left to parse.
+    pub last_id: Option<i64>,
+}
+
+/// Query parameters for starting a demo re-parse.
+#[derive(Deserialize, Debug, Clone, ToSchema, IntoParams)]
+pub struct ReparseParams {
+    /// The most demos to process in this job.
+    pub limit: Option<i64>,
+}
@@ -0,0 +1,9 @@
+use utoipa::ToSchema;
+
+/// Status of each dependency checked by the readiness endpoint, `failed` names any that are unavailable.
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct ReadyStatus {
+    pub database: bool,
+    pub backblaze: bool,
+    pub failed: Vec<String>,
+}
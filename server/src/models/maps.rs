@@ -1,8 +1,9 @@
-use chrono::NaiveDateTime;
+use crate::models::categories::Categories;
 use sqlx::FromRow;
+use utoipa::{IntoParams, ToSchema};
 
 /// One-to-one struct for map data.
-#[derive(Serialize, Deserialize, Debug, FromRow)]
+#[derive(Serialize, Deserialize, Debug, FromRow, ToSchema)]
 pub struct Maps {
     pub id: i32,
     pub steam_id: String,
@@ -13,29 +14,61 @@ pub struct Maps {
     pub is_public: bool,
 }
 
-/// One-to-one struct for Category data.
-#[derive(Serialize, Deserialize, Debug, FromRow)]
-pub struct Categories {
-    pub id: i32,
+/// A map with its chapter information and all valid categories for the map.
+#[derive(Serialize, Deserialize, Debug, FromRow, ToSchema)]
+pub struct MapDetail {
+    pub steam_id: String,
+    pub name: String,
+    pub chapter_id: i32,
+    pub chapter_name: Option<String>,
+    pub game_id: i32,
+    pub is_coop: bool,
+    pub default_cat_id: i32,
+    pub is_public: bool,
+    #[sqlx(skip)]
+    pub categories: Vec<Categories>,
+}
+
+/// A map matched by a name search, see [crate::api::v1::handlers::maps::search_maps].
+#[derive(Serialize, Deserialize, Debug, Clone, FromRow, PartialEq, ToSchema)]
+pub struct MapSearchResult {
+    pub steam_id: String,
     pub name: String,
+    pub chapter_id: i32,
+    pub chapter_name: Option<String>,
+    pub is_coop: bool,
+}
+
+/// Summary of a map's leaderboard for a category, the current world record and how many players have a valid time.
+///
+/// The world record fields are `None` when there are no valid times on the map.
+#[derive(Serialize, Deserialize, Debug, Clone, FromRow, PartialEq, ToSchema)]
+pub struct MapSummary {
     pub map_id: String,
-    pub rules_id: Option<i32>,
-    pub updated: Option<NaiveDateTime>,
+    pub category_id: i32,
+    pub wr_score: Option<i32>,
+    pub wr_profile_number: Option<String>,
+    pub wr_user_name: Option<String>,
+    pub wr_avatar: Option<String>,
+    pub player_count: i64,
 }
 
-/// One-to-one struct for category rules.
-#[derive(Serialize, Deserialize, Debug, FromRow)]
-pub struct CategoryRules {
-    pub id: i32,
-    pub rules: Option<String>,
-    pub external_link: Option<String>,
-    pub is_active: Option<bool>,
-    pub updated: Option<NaiveDateTime>,
+/// The new default category for a map, see [crate::api::v1::handlers::maps::set_default_category].
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct DefaultCategoryUpdate {
+    pub cat_id: i32,
 }
 
-/// Handles game id and if the request is for a coop or singleplayer map.
-#[derive(Deserialize, Debug)]
+/// Handles game id and if the request is for a coop or singleplayer map, both are optional.
+#[derive(Deserialize, Debug, ToSchema, IntoParams)]
 pub struct IsCoop {
-    pub is_coop: bool,
+    pub is_coop: Option<bool>,
+    pub game_id: Option<i32>,
+}
+
+/// Query parameters for searching maps by name, `game_id` limits the results to one game.
+#[derive(Clone, Debug, Deserialize, ToSchema, IntoParams)]
+pub struct MapSearchParams {
+    pub q: Option<String>,
     pub game_id: Option<i32>,
 }
@@ -5,6 +5,8 @@
 //!
 /// Admin-specific models.
 pub mod admin;
+/// Category models.
+pub mod categories;
 /// Changelog-specific models.
 pub mod changelog;
 /// Chapter-related models.
@@ -13,6 +15,8 @@ pub mod chapters;
 pub mod coop;
 /// Demo models
 pub mod demos;
+/// Health check models.
+pub mod health;
 /// Maps-based models.
 pub mod maps;
 /// Point-based models.
@@ -1,8 +1,11 @@
 use super::changelog::MapScoreDate;
+use crate::tools::config::PointsConfig;
+use sqlx::FromRow;
 use std::collections::HashMap;
+use utoipa::{IntoParams, ToSchema};
 
 /// Wrapper for us receiving points from the backend
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct PointsReceiveWrapper {
     pub id: Option<i32>,
     pub hm_points: HashMap<String, Points>,
@@ -17,14 +20,14 @@ pub struct PointsWriteWrapper<'a> {
 }
 
 /// Wrapper for reading points from a file.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct PointsReadWrapper {
     pub id: Option<i32>,
     pub points: Vec<(String, Points)>,
 }
 
 /// Point information for a given player.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct Points {
     pub points: f32,
     pub score: i32, // TODO: Need to change the format to support SAR timing
@@ -36,26 +39,78 @@ pub struct Points {
     pub avatar: Option<String>,
 }
 
-/// Oldest and newest `MapScoreDate` for a profile.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// A player's total points across every map, used for the aggregate points leaderboards.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct PointsAggregate {
+    pub profile_number: String,
+    pub user_name: Option<String>,
+    pub total_points: f32,
+    pub rank: i32,
+}
+
+/// Oldest and newest `MapScoreDate` for a profile, `None` if the player has no scores for that game mode.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct ProfileData {
-    pub oldest_sp: MapScoreDate,
-    pub newest_sp: MapScoreDate,
-    pub oldest_coop: MapScoreDate,
-    pub newest_coop: MapScoreDate,
+    pub oldest_sp: Option<MapScoreDate>,
+    pub newest_sp: Option<MapScoreDate>,
+    pub oldest_coop: Option<MapScoreDate>,
+    pub newest_coop: Option<MapScoreDate>,
 }
 
 /// Wrapper for a profile page, includes the ID associated with the points and the poits themselves.
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, ToSchema)]
 pub struct PointsProfileWrapper {
     pub id: i32,
     pub points: Points,
 }
 
 /// Profile Page that includes a Vec of PointsProfileWrappers, ProfileData and a hasmap of map_ids to current ranks.
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, ToSchema)]
 pub struct ProfilePage {
     pub points: Vec<PointsProfileWrapper>,
     pub data: ProfileData,
     pub ranks: HashMap<String, i32>,
 }
+
+/// A player's points on a single chapter, `max_possible` is the points for holding the world record on every map in the chapter.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct ChapterPoints {
+    pub chapter_id: i32,
+    pub chapter_name: Option<String>,
+    pub points: f32,
+    pub max_possible: f32,
+}
+
+/// A map in a chapter, with a player's rank on the map's default category.
+///
+/// `sp_rank` is only set for singleplayer maps, `has_coop` is set if the player has a valid coop time on the map.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow, ToSchema)]
+pub struct PlayerMapRank {
+    pub chapter_id: i32,
+    pub chapter_name: Option<String>,
+    pub is_multiplayer: bool,
+    pub map_id: String,
+    pub default_cat_id: i32,
+    pub sp_rank: Option<i64>,
+    pub has_coop: bool,
+}
+
+/// The points curve in use, with the points for the first `count` ranks.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct PointsCurve {
+    pub curve: PointsConfig,
+    pub points: Vec<RankPoints>,
+}
+
+/// The points a rank is worth.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct RankPoints {
+    pub rank: i32,
+    pub points: f32,
+}
+
+/// Query parameters for the points curve, `count` defaults to the curve's `max_rank`.
+#[derive(Debug, Clone, Deserialize, ToSchema, IntoParams)]
+pub struct PointsCurveParams {
+    pub count: Option<i32>,
+}
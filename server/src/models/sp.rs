@@ -1,9 +1,10 @@
 use super::changelog::Changelog;
 use chrono::NaiveDateTime;
 use sqlx::FromRow;
+use utoipa::ToSchema;
 
 /// The minimal data we want for SP map pages to lower bandwidth usage.
-#[derive(Serialize, FromRow, Debug)]
+#[derive(Serialize, FromRow, Debug, ToSchema)]
 pub struct SpMap {
     pub timestamp: Option<NaiveDateTime>,
     #[sqlx(rename = "cl_profile_number")]
@@ -16,10 +17,15 @@ pub struct SpMap {
     pub category_id: i32,
     pub user_name: Option<String>,
     pub avatar: Option<String>,
+    /// Only set when unverified or banned times are included, see [crate::models::chapters::ReviewParams].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub verified: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub banned: Option<bool>,
 }
 
 /// The data for the preview page for all SP Maps
-#[derive(Serialize, Deserialize, FromRow)]
+#[derive(Serialize, Deserialize, FromRow, ToSchema)]
 pub struct SpPreview {
     #[sqlx(rename = "cl_profile_number")]
     pub profile_number: String,
@@ -38,22 +44,32 @@ pub struct SpPreview {
 // }
 
 /// Wrapper for a player's SP PB history.
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct SpPbHistory {
     pub user_name: Option<String>,
     pub avatar: Option<String>,
     pub pb_history: Option<Vec<Changelog>>,
 }
+/// A single point on a player's PB timeline for a map, used for charting rank and points over time.
+#[derive(Serialize, Deserialize, Debug, PartialEq, ToSchema)]
+pub struct SpHistoryPoint {
+    pub timestamp: Option<NaiveDateTime>,
+    pub score: i32,
+    pub rank: Option<i32>,
+    pub points: f32,
+}
 /// Wrapper for the sp map data and the rank/score.
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 pub struct SpRanked {
     pub map_data: SpMap,
     pub rank: i32,
     pub points: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub formatted_score: Option<String>,
 }
 
 /// Banned times for SP
-#[derive(Serialize, FromRow)]
+#[derive(Serialize, FromRow, ToSchema)]
 pub struct SpBanned {
     pub profile_number: String,
     pub score: i32,
@@ -1,8 +1,9 @@
 use chrono::NaiveDateTime;
 use sqlx::FromRow;
+use utoipa::ToSchema;
 
 /// One-to-one mapping for badges.
-#[derive(Serialize, Deserialize, Debug, Clone, FromRow)]
+#[derive(Serialize, Deserialize, Debug, Clone, FromRow, ToSchema)]
 pub struct Badges {
     pub id: i32,
     pub name: String,
@@ -12,7 +13,7 @@ pub struct Badges {
 }
 
 /// One-to-one mapping for profile badge entries.
-#[derive(Serialize, Deserialize, Debug, Clone, FromRow)]
+#[derive(Serialize, Deserialize, Debug, Clone, FromRow, ToSchema)]
 pub struct BadgeEntries {
     pub id: i32,
     pub badge_id: i32,
@@ -22,7 +23,7 @@ pub struct BadgeEntries {
     pub updated: Option<NaiveDateTime>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
 pub struct BadgeInsert {
     pub name: String,
     pub image: String,
@@ -30,7 +31,7 @@ pub struct BadgeInsert {
     pub tier: i32,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
 pub struct BadgeEntryInsert {
     pub badge_id: i32,
     pub profile_number: String,
@@ -38,3 +39,13 @@ pub struct BadgeEntryInsert {
     pub timestamp: Option<NaiveDateTime>,
     pub updated: Option<NaiveDateTime>,
 }
+
+/// Board-wide totals for the stats overview.
+#[derive(Serialize, Deserialize, Debug, Clone, FromRow, ToSchema)]
+pub struct StatsOverview {
+    pub registered_users: i64,
+    pub verified_runs: i64,
+    pub demos: i64,
+    pub maps: i64,
+    pub runs_last_24h: i64,
+}
@@ -1,7 +1,9 @@
+use crate::models::points::ProfileData;
 use sqlx::FromRow;
+use utoipa::{IntoParams, ToSchema};
 
 /// One-to-one struct for user data.
-#[derive(Debug, Clone, Serialize, Deserialize, FromRow, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow, Default, ToSchema)]
 pub struct Users {
     pub profile_number: String,
     pub board_name: Option<String>,
@@ -17,10 +19,12 @@ pub struct Users {
     pub discord_id: Option<String>,
     pub auth_hash: Option<String>,
     pub country_id: Option<i32>,
+    #[serde(default)]
+    pub needs_review: bool,
 }
 
 /// One-to-one struct for countries
-#[derive(Serialize, Deserialize, FromRow, Clone, Debug)]
+#[derive(Serialize, Deserialize, FromRow, Clone, Debug, ToSchema)]
 pub struct Countries {
     id: i32,
     iso: String,
@@ -32,7 +36,7 @@ pub struct Countries {
 }
 
 /// One-to-one struct for countries
-#[derive(Serialize, Deserialize, FromRow, Clone, Debug)]
+#[derive(Serialize, Deserialize, FromRow, Clone, Debug, ToSchema)]
 pub struct CountriesMin {
     id: i32,
     iso3: String,
@@ -42,21 +46,21 @@ pub struct CountriesMin {
 /// Includes only a `user_name` and `avatar`, does not include the `profile_number`
 ///
 /// Used for when the `profile_number` is included in another portion of the returned values.
-#[derive(Debug, FromRow, Deserialize, Serialize, Clone)]
+#[derive(Debug, FromRow, Deserialize, Serialize, Clone, ToSchema)]
 pub struct UsersPage {
     pub user_name: String,
     pub avatar: String,
 }
 
 /// Wraps `profile_number`, `user_name` and `avatar` for displaying a user.
-#[derive(Debug, FromRow, Deserialize, Serialize, Clone)]
+#[derive(Debug, FromRow, Deserialize, Serialize, Clone, ToSchema)]
 pub struct UsersDisplay {
     pub profile_number: String,
     pub user_name: String,
     pub avatar: String,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone, FromRow)]
+#[derive(Debug, Deserialize, Serialize, Clone, FromRow, ToSchema)]
 pub struct UsersDisplayCount {
     pub profile_number: String,
     pub user_name: String,
@@ -64,20 +68,130 @@ pub struct UsersDisplayCount {
     pub count: i64,
 }
 
+/// A [UsersDisplay] for a donator, with the amount they have donated.
+///
+/// `donation_amount` is stored as text, amounts that are not a valid number are returned as `0`.
+#[derive(Debug, Deserialize, Serialize, Clone, FromRow, ToSchema)]
+pub struct UsersDisplayDonation {
+    pub profile_number: String,
+    pub user_name: String,
+    pub avatar: String,
+    pub donation_amount: f64,
+}
+
+/// The header of a user's profile page, a [Users] without internal fields, along with the user's [ProfileData].
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct UserProfile {
+    pub profile_number: String,
+    pub board_name: Option<String>,
+    pub steam_name: Option<String>,
+    pub banned: bool,
+    pub registered: i32,
+    pub avatar: Option<String>,
+    pub twitch: Option<String>,
+    pub youtube: Option<String>,
+    pub title: Option<String>,
+    pub donation_amount: Option<String>,
+    pub country_id: Option<i32>,
+    /// Only returned to admins.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub admin: Option<i32>,
+    /// Only returned to admins.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub discord_id: Option<String>,
+    /// Only returned to admins.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub needs_review: Option<bool>,
+    pub data: ProfileData,
+}
+
+/// A user whose name was flagged for review, a [Users] without private fields such as `auth_hash`.
+#[derive(Debug, Deserialize, Serialize, Clone, FromRow, ToSchema)]
+pub struct FlaggedUser {
+    pub profile_number: String,
+    pub board_name: Option<String>,
+    pub steam_name: Option<String>,
+    pub banned: bool,
+    pub avatar: Option<String>,
+    pub needs_review: bool,
+}
+
 /// Social media accounts from `Users`
-#[derive(Serialize, Deserialize, Debug, FromRow)]
+#[derive(Serialize, Deserialize, Debug, FromRow, ToSchema)]
 pub struct Socials {
     pub twitch: Option<String>,
     pub youtube: Option<String>,
+    /// Only returned to admins.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub discord_id: Option<String>,
 }
 
 /// An avatar being added to the db.
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, ToSchema)]
 pub struct AvatarInsert {
     pub avatar: String,
 }
 
+/// The previous and refreshed avatar for a user.
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct AvatarRefresh {
+    pub old_avatar: Option<String>,
+    pub new_avatar: String,
+}
+
+/// Request body for banning or unbanning a user.
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct UserBanParams {
+    pub profile_number: String,
+    pub banned: bool,
+}
+
+/// Ordering for the banned users list.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum BannedOrder {
+    /// Alphabetically by user name.
+    #[default]
+    Name,
+    /// Most recently banned first, users without a ban date are listed last.
+    Recent,
+}
+
+/// Query parameters for paging through banned users.
+#[derive(Clone, Debug, Deserialize, ToSchema, IntoParams)]
+pub struct BannedUsersParams {
+    pub offset: Option<i64>,
+    pub limit: Option<i64>,
+    pub order: Option<BannedOrder>,
+}
+
+/// Query parameters for searching users by name.
+#[derive(Clone, Debug, Deserialize, ToSchema, IntoParams)]
+pub struct UserSearchParams {
+    pub q: Option<String>,
+}
+
+/// A user given either by their steamID64, or their vanity name (`steamcommunity.com/id/{vanity}`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SteamIdInput {
+    SteamId64(String),
+    Vanity(String),
+}
+
+/// Wrapper for the `ResolveVanityURL` API call
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ResolveVanityUrlWrapper {
+    pub response: ResolveVanityUrl,
+}
+
+/// `success` is 1 when the vanity name matched a user, `steamid` is only set on success.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ResolveVanityUrl {
+    pub success: i32,
+    pub steamid: Option<String>,
+    pub message: Option<String>,
+}
+
 /// Wrapper for our API call
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct GetPlayerSummariesWrapper {
@@ -0,0 +1,55 @@
+use crate::models::changelog::ChangelogInsert;
+use crate::tools::auth::{hash_token, AuthUser};
+use crate::tools::error::ErrorType;
+
+fn auth_user(admin: i32) -> AuthUser {
+    AuthUser {
+        profile_number: "76561198040982247".to_string(),
+        admin,
+    }
+}
+
+#[test]
+fn test_hash_token() {
+    let hash = hash_token("token");
+    assert_eq!(hash.len(), 64);
+    assert_eq!(hash, hash_token("token"));
+    assert_ne!(hash, hash_token("other token"));
+}
+
+#[test]
+fn test_admin_levels() {
+    assert!(auth_user(0).require_admin().is_err());
+    for admin in 1..=3 {
+        assert!(auth_user(admin).require_admin().is_ok());
+    }
+}
+
+#[test]
+fn test_check_changelog_insert() {
+    let own = ChangelogInsert {
+        profile_number: "76561198040982247".to_string(),
+        score: 1763,
+        map_id: "47763".to_string(),
+        ..Default::default()
+    };
+    assert!(auth_user(0).check_changelog_insert(&own).is_ok());
+    let other = ChangelogInsert {
+        profile_number: "76561198039230536".to_string(),
+        ..own.clone()
+    };
+    let err = auth_user(0).check_changelog_insert(&other).unwrap_err();
+    assert_eq!(err.error_type, ErrorType::Forbidden);
+    let verified = ChangelogInsert {
+        verified: Some(true),
+        ..own.clone()
+    };
+    assert!(auth_user(0).check_changelog_insert(&verified).is_err());
+    let admin_note = ChangelogInsert {
+        admin_note: Some("Looks good".to_string()),
+        ..own
+    };
+    assert!(auth_user(0).check_changelog_insert(&admin_note).is_err());
+    assert!(auth_user(1).check_changelog_insert(&admin_note).is_ok());
+    assert!(auth_user(1).check_changelog_insert(&other).is_ok());
+}
@@ -0,0 +1,78 @@
+use crate::tools::client::{client_id, client_ip, UNKNOWN_CLIENT};
+use actix_web::test::TestRequest;
+use std::net::IpAddr;
+
+const PROXY: &str = "10.0.0.1";
+
+fn proxies() -> Vec<IpAddr> {
+    vec![PROXY.parse().unwrap()]
+}
+
+fn ip(ip: &str) -> Option<IpAddr> {
+    Some(ip.parse().unwrap())
+}
+
+#[test]
+fn test_client_ip_spoofed_forwarded_for() {
+    // Anyone can send the headers, they are ignored unless the request comes from a trusted proxy.
+    let req = TestRequest::default()
+        .peer_addr("203.0.113.7:4000".parse().unwrap())
+        .insert_header(("X-Forwarded-For", "198.51.100.1"))
+        .insert_header(("X-Real-IP", "198.51.100.2"))
+        .to_http_request();
+    assert_eq!(client_ip(&req, &proxies()), ip("203.0.113.7"));
+    assert_eq!(client_ip(&req, &[]), ip("203.0.113.7"));
+}
+
+#[test]
+fn test_client_ip_trusted_proxy() {
+    let req = TestRequest::default()
+        .peer_addr(format!("{PROXY}:4000").parse().unwrap())
+        .insert_header(("X-Forwarded-For", "198.51.100.1"))
+        .to_http_request();
+    assert_eq!(client_ip(&req, &proxies()), ip("198.51.100.1"));
+    // Entries left of the first untrusted hop were added by the client.
+    let req = TestRequest::default()
+        .peer_addr(format!("{PROXY}:4000").parse().unwrap())
+        .insert_header(("X-Forwarded-For", "1.1.1.1, 198.51.100.1, 10.0.0.1"))
+        .to_http_request();
+    assert_eq!(client_ip(&req, &proxies()), ip("198.51.100.1"));
+    let req = TestRequest::default()
+        .peer_addr(format!("{PROXY}:4000").parse().unwrap())
+        .insert_header(("X-Real-IP", "198.51.100.2"))
+        .to_http_request();
+    assert_eq!(client_ip(&req, &proxies()), ip("198.51.100.2"));
+    // A malformed header falls back to the proxy.
+    let req = TestRequest::default()
+        .peer_addr(format!("{PROXY}:4000").parse().unwrap())
+        .insert_header(("X-Forwarded-For", "not an address"))
+        .to_http_request();
+    assert_eq!(client_ip(&req, &proxies()), ip(PROXY));
+    // A malformed entry the client added does not hide the address the proxy added after it.
+    let req = TestRequest::default()
+        .peer_addr(format!("{PROXY}:4000").parse().unwrap())
+        .insert_header(("X-Forwarded-For", "not an address, 198.51.100.1"))
+        .to_http_request();
+    assert_eq!(client_ip(&req, &proxies()), ip("198.51.100.1"));
+    // A malformed entry right of the client stops at the last trusted hop before it.
+    let req = TestRequest::default()
+        .peer_addr(format!("{PROXY}:4000").parse().unwrap())
+        .insert_header(("X-Forwarded-For", "198.51.100.1, not an address, 10.0.0.1"))
+        .to_http_request();
+    assert_eq!(client_ip(&req, &proxies()), ip(PROXY));
+}
+
+#[test]
+fn test_client_id_normalized() {
+    let req = TestRequest::default().peer_addr("[::ffff:203.0.113.7]:4000".parse().unwrap()).to_http_request();
+    assert_eq!(client_id(&req, &[]), "203.0.113.7");
+    let req = TestRequest::default().peer_addr("[2001:db8:1:2:3:4:5:6]:4000".parse().unwrap()).to_http_request();
+    assert_eq!(client_id(&req, &[]), "2001:db8:1:2::/64");
+    let req = TestRequest::default()
+        .peer_addr(format!("{PROXY}:4000").parse().unwrap())
+        .insert_header(("X-Forwarded-For", "[2001:db8::1]:5678"))
+        .to_http_request();
+    assert_eq!(client_id(&req, &proxies()), "2001:db8::/64");
+    assert_eq!(client_id(&TestRequest::default().to_http_request(), &[]), UNKNOWN_CLIENT);
+}
+
@@ -0,0 +1,84 @@
+use crate::tools::config::CorsConfig;
+use crate::tools::cors::cors;
+use actix_web::{get, http::header, test, App, HttpResponse, Responder};
+
+#[get("/maps")]
+async fn maps() -> impl Responder {
+    HttpResponse::Ok()
+}
+
+fn cors_config(origins: &str, allow_credentials: bool) -> CorsConfig {
+    serde_json::from_value(serde_json::json!({
+        "allowed_origins": origins,
+        "allow_credentials": allow_credentials,
+    }))
+    .unwrap()
+}
+
+#[actix_web::test]
+async fn test_cors_config_origins() {
+    let config = cors_config("https://board.portal2.sr, ,http://localhost:3000", false);
+    assert_eq!(config.allowed_origins, vec!["https://board.portal2.sr", "http://localhost:3000"]);
+    assert!(cors_config("", false).allowed_origins.is_empty());
+    assert!(CorsConfig::default().allowed_origins.is_empty());
+}
+
+#[actix_web::test]
+async fn test_cors_allowed_origins() {
+    let app = test::init_service(
+        App::new()
+            .wrap(cors(&cors_config("https://board.portal2.sr", false)))
+            .service(maps),
+    )
+    .await;
+    let req = test::TestRequest::default()
+        .method(actix_web::http::Method::OPTIONS)
+        .uri("/maps")
+        .insert_header((header::ORIGIN, "https://board.portal2.sr"))
+        .insert_header((header::ACCESS_CONTROL_REQUEST_METHOD, "DELETE"))
+        .insert_header((header::ACCESS_CONTROL_REQUEST_HEADERS, "authorization"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+    assert_eq!(resp.headers().get(header::ACCESS_CONTROL_ALLOW_ORIGIN).unwrap(), "https://board.portal2.sr");
+    assert!(resp.headers().get(header::ACCESS_CONTROL_ALLOW_CREDENTIALS).is_none());
+
+    let req = test::TestRequest::get()
+        .uri("/maps")
+        .insert_header((header::ORIGIN, "https://elsewhere.example"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.headers().get(header::ACCESS_CONTROL_ALLOW_ORIGIN).is_none());
+
+    // Requests without an origin are not cross-origin, and are served as usual.
+    let req = test::TestRequest::get().uri("/maps").to_request();
+    assert!(test::call_service(&app, req).await.status().is_success());
+}
+
+#[actix_web::test]
+async fn test_cors_denies_all_when_unset() {
+    let app = test::init_service(App::new().wrap(cors(&CorsConfig::default())).service(maps)).await;
+    let req = test::TestRequest::get()
+        .uri("/maps")
+        .insert_header((header::ORIGIN, "http://localhost:3000"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.headers().get(header::ACCESS_CONTROL_ALLOW_ORIGIN).is_none());
+}
+
+#[actix_web::test]
+async fn test_cors_credentials() {
+    let app = test::init_service(
+        App::new()
+            .wrap(cors(&cors_config("https://board.portal2.sr", true)))
+            .service(maps),
+    )
+    .await;
+    let req = test::TestRequest::get()
+        .uri("/maps")
+        .insert_header((header::ORIGIN, "https://board.portal2.sr"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.headers().get(header::ACCESS_CONTROL_ALLOW_CREDENTIALS).unwrap(), "true");
+    assert!(resp.headers().get(header::ACCESS_CONTROL_EXPOSE_HEADERS).is_some());
+}
@@ -3,7 +3,13 @@
 use sqlx::PgPool;
 use dotenv::dotenv;
 use anyhow::Result;
-use crate::tools::config::Config;
+use crate::models::changelog::ChangelogSource;
+use crate::tools::cache::CacheState;
+use crate::tools::config::{Config, RateLimitConfig};
+use crate::tools::ratelimit::RateLimiter;
+use crate::tools::storage::{DemoStorage, StoredFile};
+use futures::future::BoxFuture;
+use std::sync::Arc;
 
 #[allow(dead_code)]
 const DEFAULT_PAGE_SIZE: usize = 500;
@@ -16,12 +22,97 @@ async fn get_config() -> Result<(Config, PgPool)> {
     Ok((config, pool))
 }
 
+/// A [CacheState] with every preview marked as cached, and no default categories, ranks or points loaded.
+#[allow(dead_code)]
+fn test_cache_state() -> CacheState {
+    use crate::tools::cache::{DefaultCategories, Ranks, COOP_AGGREGATE, COOP_PREVIEWS, SP_AGGREGATE, SP_PREVIEWS};
+    use std::{collections::HashMap, sync::Arc};
+    use tokio::sync::Mutex;
+    CacheState {
+        current_state: Arc::new(Mutex::new(HashMap::from([
+            (SP_PREVIEWS, true),
+            (COOP_PREVIEWS, true),
+            (SP_AGGREGATE, true),
+            (COOP_AGGREGATE, true),
+        ]))),
+        default_cat_ids: DefaultCategories::default(),
+        points: Arc::new(Mutex::new(HashMap::new())),
+        ranks: Arc::new(Mutex::new(Ranks { current_ranks: HashMap::new() })),
+        cached_at: Arc::new(Mutex::new(HashMap::new())),
+        ttl: None,
+        b2_authorized_at: Arc::new(Mutex::new(None)),
+        map_summaries: Arc::new(Mutex::new(HashMap::new())),
+        game_coop_previews: Arc::new(Mutex::new(HashMap::new())),
+        etags: Arc::new(Mutex::new(HashMap::new())),
+        recent_activity: Arc::new(Mutex::new(HashMap::new())),
+        stats_overview: Arc::new(Mutex::new(None)),
+        demo_reparse: Arc::new(Mutex::new(Default::default())),
+        live: Default::default(),
+        generations: Arc::new(Mutex::new(HashMap::new())),
+        preview_files: Arc::new(Mutex::new(())),
+    }
+}
+
+/// Demo storage that fails every call the way BackBlaze rejects a bad key, without making any requests.
+struct RejectedKeyStorage;
+
+fn rejected_key<'a, T: Send + 'a>() -> BoxFuture<'a, Result<T>> {
+    use crate::tools::backblaze::B2Error;
+    Box::pin(async {
+        Err(anyhow::Error::new(B2Error {
+            status: 401,
+            code: "bad_auth_token".to_string(),
+            message: "Invalid authorization token".to_string(),
+        }))
+    })
+}
+
+impl DemoStorage for RejectedKeyStorage {
+    fn upload<'a>(&'a self, _: &'a str, _: &'a str) -> BoxFuture<'a, Result<String>> {
+        rejected_key()
+    }
+    fn download<'a>(&'a self, _: StoredFile<'a>) -> BoxFuture<'a, Result<Vec<u8>>> {
+        rejected_key()
+    }
+    fn delete<'a>(&'a self, _: StoredFile<'a>, _: &'a str) -> BoxFuture<'a, Result<()>> {
+        rejected_key()
+    }
+    fn bucket(&self) -> Option<String> {
+        Some("bucket".to_string())
+    }
+}
+
+/// A [RejectedKeyStorage] to use in place of [crate::tools::storage::demo_storage].
+fn rejected_key_storage() -> Arc<dyn DemoStorage> {
+    Arc::new(RejectedKeyStorage)
+}
+
+/// Creates (or updates) a user with the given admin level and an auth token, and returns the `Authorization` header value for them.
+///
+/// Test admins use the shadow admin level so they are not listed with the standard admins.
+#[allow(dead_code)]
+async fn test_auth_header(pool: &PgPool, profile_number: &str, admin: i32) -> String {
+    use crate::tools::auth::hash_token;
+    let token = format!("test-token-{profile_number}");
+    sqlx::query(
+        r#"INSERT INTO users (profile_number, admin, auth_hash) VALUES ($1, $2, $3)
+            ON CONFLICT (profile_number) DO UPDATE SET admin = $2, auth_hash = $3"#,
+    )
+    .bind(profile_number)
+    .bind(admin)
+    .bind(hash_token(&token))
+    .execute(pool)
+    .await
+    .unwrap();
+    format!("Bearer {token}")
+}
+
 #[actix_web::test]
 async fn test_db_maps() {
     use crate::models::maps::*;
     let (_, pool) = get_config().await.expect("Error getting config and DB pool");
-    let sp = Maps::get_steam_ids(&pool, false).await.unwrap();
-    let coop = Maps::get_steam_ids(&pool, true).await.unwrap();
+    let sp = Maps::get_steam_ids(&pool, Some(false), None).await.unwrap();
+    let coop = Maps::get_steam_ids(&pool, Some(true), None).await.unwrap();
     assert_eq!(sp.len(), 60);
     assert_eq!(coop.len(), 48);
     let map_name = Maps::get_map_name(&pool, sp[0].clone()).await.unwrap().unwrap();
@@ -38,6 +129,22 @@ async fn test_db_maps() {
     assert!(public);
 }
 
+#[actix_web::test]
+async fn test_db_map_ids_filters() {
+    use crate::models::maps::Maps;
+    let (_, pool) = get_config().await.expect("Error getting config and DB pool");
+    let sp = Maps::get_steam_ids(&pool, Some(false), Some(1)).await.unwrap();
+    let coop = Maps::get_steam_ids(&pool, Some(true), Some(1)).await.unwrap();
+    let mut all = Maps::get_steam_ids(&pool, None, Some(1)).await.unwrap();
+    assert!(!sp.is_empty() && !coop.is_empty());
+    all.sort();
+    let mut both = [sp, coop].concat();
+    both.sort();
+    assert_eq!(all, both);
+    assert!(Maps::get_steam_ids(&pool, None, Some(-1)).await.unwrap().is_empty());
+    assert!(Maps::get_steam_ids(&pool, None, None).await.unwrap().len() >= all.len());
+}
+
 #[actix_web::test]
 async fn test_db_chapters() {
     use crate::models::chapters::*;
@@ -68,6 +175,49 @@ async fn test_db_chapters() {
     assert_eq!(chapter.game_id, game.id);
 }
 
+#[actix_web::test]
+async fn test_db_games_tick_rate() {
+    use crate::models::chapters::Games;
+    use crate::tools::helpers::DEFAULT_TICK_RATE;
+    let (_, pool) = get_config().await.expect("Error getting config and DB pool");
+    assert_eq!(Games::get_tick_rate(&pool, 1).await.unwrap(), DEFAULT_TICK_RATE);
+    assert_eq!(Games::get_tick_rate(&pool, -1).await.unwrap(), DEFAULT_TICK_RATE);
+    // The id is given explicitly, the sequence is behind the seeded games.
+    let game_id: i32 = sqlx::query_scalar(
+        "INSERT INTO games (id, game_name, tick_rate) SELECT MAX(id) + 1000, 'tick rate test', 66 FROM games RETURNING id",
+    )
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+    let tick_rate = Games::get_tick_rate(&pool, game_id).await;
+    sqlx::query("DELETE FROM games WHERE id = $1").bind(game_id).execute(&pool).await.unwrap();
+    assert_eq!(tick_rate.unwrap(), 66);
+}
+
+#[actix_web::test]
+async fn test_db_chapters_with_maps() {
+    use crate::models::chapters::Chapters;
+    use crate::models::maps::Maps;
+    let (_, pool) = get_config().await.expect("Error getting config and DB pool");
+    let chapters = Chapters::get_chapters_with_maps(&pool, Some(1)).await.unwrap();
+    assert!(!chapters.is_empty());
+    // Singleplayer chapters come first.
+    let first_mp = chapters.iter().position(|c| c.is_multiplayer).unwrap_or(chapters.len());
+    assert!(chapters[first_mp..].iter().all(|c| c.is_multiplayer));
+    for chapter in chapters.iter() {
+        let map_ids: Vec<String> = chapter.maps.iter().map(|m| m.steam_id.clone()).collect();
+        let mut expected = Chapters::get_map_ids(&pool, chapter.chapter_id).await.unwrap();
+        expected.sort();
+        let mut sorted = map_ids.clone();
+        sorted.sort();
+        assert_eq!(expected, sorted);
+    }
+    let map_count: usize = chapters.iter().map(|c| c.maps.len()).sum();
+    assert_eq!(Maps::get_steam_ids(&pool, None, Some(1)).await.unwrap().len(), map_count);
+    assert!(Chapters::get_chapters_with_maps(&pool, Some(-1)).await.unwrap().is_empty());
+    assert!(Chapters::get_chapters_with_maps(&pool, None).await.unwrap().len() >= chapters.len());
+}
+
 
 // TODO: We want to make this prone to handling changes, right now many of the tests
 //       are hard-coded to only work on this current version of the db.
@@ -91,6 +241,7 @@ async fn test_db_users() {
         discord_id: None,
         auth_hash: None,
         country_id: None,
+        needs_review: false,
     };
     let mut insert_user = user.clone();
     let test_user = Users::get_user(&pool, user.profile_number.clone()).await.unwrap().unwrap();
@@ -111,6 +262,8 @@ async fn test_db_users() {
     assert_eq!(user.avatar, Some(test_user.avatar));
     let test_vec = Users::check_board_name(&pool, "Daniel").await.unwrap();
     assert!(test_vec.len() != 0);
+    let display = Users::get_users_display(&pool, &test_vec).await.unwrap();
+    assert_eq!(display.len(), test_vec.len());
     let banned = Users::get_banned(&pool).await.unwrap();
     assert!(banned.len() > 148);
     let banned = Users::check_banned(&pool, &user.profile_number).await.unwrap();
@@ -135,7 +288,7 @@ async fn test_db_users() {
     insert_user.board_name = Some("BigDaniel11AtlasPog".to_string());
     Users::update_existing_user(&pool, insert_user.clone()).await.unwrap();
     let new_avi = Users::update_avatar(&pool, &user.profile_number, user.avatar.as_ref().unwrap()).await.unwrap();
-    assert_eq!(new_avi, user.avatar.unwrap());
+    assert_eq!(new_avi, user.avatar);
     Users::delete_user(&pool, insert_user.profile_number.clone()).await.unwrap();
     let _res = Users::get_user_data(&pool, &insert_user.profile_number).await;
 
@@ -148,8 +301,9 @@ async fn test_db_users() {
 async fn test_db_demos() {
     use crate::models::demos::*;
     use crate::models::changelog::{Changelog, ChangelogInsert};
+    use crate::tools::storage::{B2Storage, StoredFile};
     use chrono::NaiveDateTime;
-    let (_, pool) = get_config().await.expect("Error getting config and DB pool");
+    let (config, pool) = get_config().await.expect("Error getting config and DB pool");
 
     let demo = Demos {
         id: 14607,
@@ -159,6 +313,12 @@ async fn test_db_demos() {
         sar_version: None,
         cl_id: 127825,
         updated: None,
+        sha1: None,
+        bucket_id: None,
+        map_name: None,
+        tick_count: None,
+        playback_time: None,
+        protocol: None,
     };
     let demo_by_cl_id = Demos::get_demo_by_cl_id(&pool, demo.cl_id).await.unwrap().unwrap();
 
@@ -185,6 +345,12 @@ async fn test_db_demos() {
         parsed_successfully: false,
         sar_version: Some("12.7.2-pre".to_string()),
         cl_id: 1,
+        sha1: Some("a9993e364706816aba3e25717850c26c9cd0d89d".to_string()),
+        bucket_id: Some("retired_bucket".to_string()),
+        map_name: Some("sp_a2_laser_vs_turret".to_string()),
+        tick_count: Some(1060),
+        playback_time: Some(17.666666),
+        protocol: Some(4),
     };
     let demo_insert = Demos::insert_demo(&pool, new_demo.clone()).await.unwrap();
     let clinsert = ChangelogInsert {
@@ -205,6 +371,7 @@ async fn test_db_demos() {
         score_delta: Some(-65),
         verified: Some(true),
         admin_note: None,
+        source: ChangelogSource::Manual,
     };
     let mut check_insert = Demos::get_demo(&pool, demo_insert).await.unwrap().unwrap();
     assert_eq!(demo_insert, check_insert.id);
@@ -213,6 +380,14 @@ async fn test_db_demos() {
     assert_eq!(new_demo.parsed_successfully, check_insert.parsed_successfully);
     assert_eq!(new_demo.sar_version, check_insert.sar_version);
     assert_eq!(new_demo.cl_id, check_insert.cl_id);
+    assert_eq!(new_demo.sha1, check_insert.sha1);
+    assert_eq!(new_demo.bucket_id, check_insert.bucket_id);
+    assert_eq!(new_demo.tick_count, check_insert.tick_count);
+    // Demos in other buckets are found in the bucket they were uploaded to, demos without a bucket are in the active bucket.
+    let storage = B2Storage::new(config.backblaze.clone());
+    assert_eq!(storage.bucket_for(&StoredFile::from(&check_insert)), "retired_bucket");
+    let unknown_bucket = Demos { bucket_id: None, ..check_insert.clone() };
+    assert_eq!(storage.bucket_for(&StoredFile::from(&unknown_bucket)), config.backblaze.bucket);
     // Testing deleting demos from changelog entries.    
     let new_cl_id = Changelog::insert_changelog(&pool, clinsert.clone()).await.unwrap();
     let new_fid = "Hello World".to_string();
@@ -231,6 +406,37 @@ async fn test_db_demos() {
     let _ = Changelog::delete_changelog(&pool, new_cl_id).await.unwrap();
 }
 
+#[actix_web::test]
+async fn test_db_demos_bad_backblaze_key() {
+    use crate::api::v1::handlers::demos::{demos_delete, demos_download, demos_verify};
+    use actix_web::{http::StatusCode, test, web, App};
+    let (config, pool) = get_config().await.expect("Error getting config and DB pool");
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::from(rejected_key_storage()))
+            .app_data(web::Data::new(config))
+            .app_data(web::Data::new(pool.clone()))
+            .service(demos_download)
+            .service(demos_verify)
+            .service(demos_delete),
+    )
+    .await;
+    // Backblaze rejecting the key should surface as a 500, not a panic.
+    let req = test::TestRequest::get().uri("/demos/verify?demo_id=14607").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    let req = test::TestRequest::get().uri("/demos/download?demo_id=14607").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    let auth = test_auth_header(&pool, "auth_test_admin", 2).await;
+    let req = test::TestRequest::delete()
+        .uri("/demos?demo_id=14607")
+        .insert_header(("Authorization", auth))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
+}
+
 #[actix_web::test]
 async fn test_db_changelog() {
     use crate::models::changelog::*;
@@ -258,6 +464,7 @@ async fn test_db_changelog() {
         verified: Some(true),
         admin_note: None,
         updated: None,
+        source: ChangelogSource::SteamAuto,
     };
 
     let clinsert = ChangelogInsert {
@@ -278,6 +485,7 @@ async fn test_db_changelog() {
         score_delta: Some(-65),
         verified: Some(true),
         admin_note: None,
+        source: ChangelogSource::Manual,
     };
     
     let banned_scores = Changelog::check_banned_scores(&pool, ScoreLookup {
@@ -309,6 +517,13 @@ async fn test_db_changelog() {
         yt: None,
         first: None,
         last: None,
+        cat_id: None,
+        banned: None,
+        submission: None,
+        source: None,
+        start_date: None,
+        end_date: None,
+        offset: None,
     };
 
     // ChangelogPage
@@ -326,6 +541,13 @@ async fn test_db_changelog() {
         yt: None,
         first: None,
         last: None,
+        cat_id: None,
+        banned: None,
+        submission: None,
+        source: None,
+        start_date: None,
+        end_date: None,
+        offset: None,
     };
     let filtered_cl_page = ChangelogPage::get_changelog_page(&pool, filter).await.unwrap();
     assert_eq!(filtered_cl_page.len(), 1);
@@ -342,27 +564,33 @@ async fn test_db_pages() {
 
     let sp_map_id = "47763".to_string();
     let coop_map_id = "52642".to_string();
-    let smp = SpMap::get_sp_map_page(&pool, &sp_map_id, DEFAULT_PAGE_SIZE as i32, 67, 1).await.unwrap();
+    let smp = SpMap::get_sp_map_page(&pool, &sp_map_id, DEFAULT_PAGE_SIZE as i32, 67, 1, 0).await.unwrap();
     assert_ne!(smp.len(), 0);
     let cmp = CoopMap::get_coop_map_page(&pool, &coop_map_id, 21, 1).await.unwrap();
     assert_ne!(cmp.len(), 0);
     let coop_entries_filtered = filter_coop_entries(cmp, config.proof.results as usize).await;
-    // Ensure we didn't mess up the ranking/points algorithm.
+    // Ensure we didn't mess up the ranking/points algorithm, tied scores share a rank.
     for i in 0..coop_entries_filtered.len() {
-        assert_eq!((i + 1) as i32, coop_entries_filtered[i].rank);
-        assert_eq!(score((i + 1) as i32), coop_entries_filtered[i].points);
+        let expected_rank = match i {
+            0 => 1,
+            _ if coop_entries_filtered[i].map_data.score == coop_entries_filtered[i - 1].map_data.score => coop_entries_filtered[i - 1].rank,
+            _ => (i + 1) as i32,
+        };
+        assert_eq!(expected_rank, coop_entries_filtered[i].rank);
+        assert_eq!(score(expected_rank), coop_entries_filtered[i].points);
         if i == 0 {             // Point check for the first entry.
             assert_eq!(200.0, coop_entries_filtered[i].points);
-        } else if i == 149 {    // Point check for the 150th entry.
+        } else if expected_rank == 150 {    // Point check for rank 150.
             assert_eq!(13.005, coop_entries_filtered[i].points);
-        } else if i == 200 {    // Point check for the 201st entry.
+        } else if expected_rank == 201 {    // Point check for rank 201.
             assert_eq!(0.0, coop_entries_filtered[i].points)
         }
     }
 
     let sppres = SpPreview::get_sp_previews(&pool).await.unwrap();
     assert_eq!(sppres.len(), 60);
-    let cooppres = CoopPreview::get_coop_previews(&pool).await.unwrap();
+    let default_cat_ids = crate::tools::helpers::get_default_cat_ids(&pool).await;
+    let cooppres = CoopPreview::get_coop_previews(&pool, &default_cat_ids.for_game(1), 1).await.unwrap();
     assert_eq!(cooppres.len(), 48);
 
     let _spbanned = SpBanned::get_sp_banned(&pool, sp_map_id).await.unwrap();
@@ -386,10 +614,3973 @@ async fn test_db_admins() {
         yt: None,
         first: None,
         last: None,
+        cat_id: None,
+        banned: None,
+        submission: None,
+        source: None,
+        start_date: None,
+        end_date: None,
+        offset: None,
     };
     let ban_page = Admin::get_admin_page(&pool, query_params).await.unwrap().unwrap();
     assert!(ban_page.len() == 5);
 
     let ban_stats = Admin::get_user_banned_time_stats(&pool).await.unwrap().unwrap();
     assert!(!ban_stats.is_empty());
-}
\ No newline at end of file
+}
+
+#[actix_web::test]
+async fn test_db_coop_post_score() {
+    use crate::api::v1::handlers::coop::coop_add;
+    use crate::models::coop::CoopBundledInsert;
+    use crate::tools::cache::COOP_PREVIEWS;
+    use actix_web::{test, web, App};
+    let (config, pool) = get_config().await.expect("Error getting config and DB pool");
+    let cache = web::Data::new(test_cache_state());
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(config))
+            .app_data(web::Data::new(pool.clone()))
+            .app_data(cache.clone())
+            .app_data(web::Data::new(RateLimiter::new(RateLimitConfig::default())))
+            .service(coop_add),
+    )
+    .await;
+    let bundle = CoopBundledInsert {
+        p_id1: "76561198040982247".to_string(),
+        p_id2: None,
+        p1_is_host: None,
+        cl_id1: 127825,
+        cl_id2: None,
+    };
+    let req = test::TestRequest::post().uri("/coop/post_score").set_json(&bundle).to_request();
+    let id: i64 = test::call_and_read_body_json(&app, req).await;
+    let db_id: i64 = sqlx::query_scalar("SELECT id FROM coop_bundled WHERE cl_id1 = $1 ORDER BY id DESC LIMIT 1")
+        .bind(bundle.cl_id1)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(id, db_id);
+    assert!(!cache.get_current_state(COOP_PREVIEWS).await);
+    sqlx::query("DELETE FROM coop_bundled WHERE id = $1").bind(id).execute(&pool).await.unwrap();
+}
+
+#[actix_web::test]
+async fn test_db_coop_preview_map_filter() {
+    use crate::models::coop::CoopPreview;
+    use std::collections::HashSet;
+    let (_, pool) = get_config().await.expect("Error getting config and DB pool");
+    let default_cat_ids = crate::tools::helpers::get_default_cat_ids(&pool).await;
+    let doors = CoopPreview::get_coop_preview(&pool, "47741", default_cat_ids.get("47741", 1).unwrap()).await.unwrap();
+    let buttons = CoopPreview::get_coop_preview(&pool, "47825", default_cat_ids.get("47825", 1).unwrap()).await.unwrap();
+    assert!(doors.len() <= 7);
+    assert!(doors.iter().all(|entry| entry.map_id == "47741" && entry.category_id == default_cat_ids.get("47741", 1).unwrap()));
+    assert!(buttons.iter().all(|entry| entry.map_id == "47825"));
+    let doors_players: HashSet<String> = doors.into_iter().map(|entry| entry.profile_number1).collect();
+    let buttons_players: HashSet<String> = buttons.into_iter().map(|entry| entry.profile_number1).collect();
+    assert_ne!(doors_players, buttons_players);
+}
+
+#[actix_web::test]
+async fn test_db_ban_invalidates_previews() {
+    use crate::api::v1::handlers::sp::sp_update;
+    use crate::models::changelog::{Changelog, ChangelogInsert};
+    use crate::tools::cache::{COOP_PREVIEWS, SP_AGGREGATE, SP_PREVIEWS};
+    use actix_web::{test, web, App};
+    let (config, pool) = get_config().await.expect("Error getting config and DB pool");
+    let cache = web::Data::new(test_cache_state());
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(pool.clone()))
+            .app_data(web::Data::new(config))
+            .app_data(cache.clone())
+            .service(sp_update),
+    )
+    .await;
+    // A score of its own, so other tests updating the seeded scores can't change it between the fetch and the update.
+    let cl_id = Changelog::insert_changelog(
+        &pool,
+        ChangelogInsert {
+            profile_number: "seed12".to_string(),
+            score: 900700,
+            map_id: "47763".to_string(),
+            category_id: 19,
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    let original = Changelog::get_changelog(&pool, cl_id).await.unwrap().unwrap();
+    let mut banned = original.clone();
+    banned.banned = !original.banned;
+    let auth = test_auth_header(&pool, "auth_test_admin", 2).await;
+    let req = test::TestRequest::put()
+        .uri("/sp/update")
+        .insert_header(("Authorization", auth))
+        .set_json(&banned)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    Changelog::delete_changelog(&pool, cl_id).await.unwrap();
+    assert!(resp.status().is_success());
+    assert!(!cache.get_current_state(SP_PREVIEWS).await);
+    assert!(!cache.get_current_state(COOP_PREVIEWS).await);
+    assert!(!cache.get_current_state(SP_AGGREGATE).await);
+}
+
+#[actix_web::test]
+async fn test_db_sp_update_conflict() {
+    use crate::api::v1::handlers::sp::{sp_patch, sp_update};
+    use crate::models::changelog::{Changelog, ChangelogInsert, ChangelogPatch};
+    use actix_web::{http::StatusCode, test, web, App};
+    let (config, pool) = get_config().await.expect("Error getting config and DB pool");
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(pool.clone()))
+            .app_data(web::Data::new(config))
+            .app_data(web::Data::new(test_cache_state()))
+            .service(sp_update)
+            .service(sp_patch),
+    )
+    .await;
+    let cl_id = Changelog::insert_changelog(
+        &pool,
+        ChangelogInsert {
+            profile_number: "seed13".to_string(),
+            score: 900800,
+            map_id: "47763".to_string(),
+            category_id: 19,
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    let auth = test_auth_header(&pool, "auth_test_admin", 2).await;
+    let put = |changelog: &Changelog| {
+        test::TestRequest::put()
+            .uri("/sp/update")
+            .insert_header(("Authorization", auth.clone()))
+            .set_json(changelog)
+            .to_request()
+    };
+    // Two admins fetch the same entry, the first to save wins.
+    let first = Changelog::get_changelog(&pool, cl_id).await.unwrap().unwrap();
+    let second = first.clone();
+    let updated: Changelog =
+        test::call_and_read_body_json(&app, put(&Changelog { note: Some("first".to_string()), ..first })).await;
+    let stale = test::call_service(&app, put(&Changelog { note: Some("second".to_string()), ..second })).await;
+    let stale_status = stale.status();
+    let body = String::from_utf8(test::read_body(stale).await.to_vec()).unwrap();
+    let current = Changelog::get_changelog(&pool, cl_id).await.unwrap().unwrap();
+    // Retrying from the current version succeeds.
+    let retry = test::call_service(&app, put(&Changelog { note: Some("second".to_string()), ..current.clone() })).await;
+    let retry_status = retry.status();
+    let patch = test::TestRequest::patch()
+        .uri("/sp/update")
+        .insert_header(("Authorization", auth.clone()))
+        .set_json(ChangelogPatch { id: cl_id, note: Some("patched".to_string()), ..Default::default() })
+        .to_request();
+    let patched: Changelog = test::call_and_read_body_json(&app, patch).await;
+    Changelog::delete_changelog(&pool, cl_id).await.unwrap();
+    assert!(updated.updated.is_some());
+    assert_eq!(stale_status, StatusCode::CONFLICT);
+    assert!(body.contains("\"error_type\":\"conflict\""), "{body}");
+    assert_eq!(current.note, Some("first".to_string()));
+    assert_eq!(current.updated, updated.updated);
+    assert_eq!(retry_status, StatusCode::OK);
+    assert_eq!(patched.note, Some("patched".to_string()));
+    // An update from a copy that doesn't match is not written.
+    assert!(Changelog::update_changelog(&pool, current).await.unwrap().is_none());
+}
+
+#[actix_web::test]
+async fn test_db_sp_map_offset() {
+    use crate::api::v1::handlers::sp::sp_map;
+    use actix_web::{test, web, App};
+    let (config, pool) = get_config().await.expect("Error getting config and DB pool");
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(config))
+            .app_data(web::Data::new(pool))
+            .app_data(web::Data::new(test_cache_state()))
+            .service(sp_map),
+    )
+    .await;
+    let req = test::TestRequest::get().uri("/map/sp/47763?cat_id=19&offset=100&limit=50").to_request();
+    let page: Vec<serde_json::Value> = test::call_and_read_body_json(&app, req).await;
+    assert!(page.len() <= 50);
+    assert_eq!(page[0]["rank"], 101);
+}
+
+#[actix_web::test]
+async fn test_db_sp_map_unknown_map() {
+    use crate::api::v1::handlers::sp::{sp_banned, sp_map};
+    use crate::tools::helpers::get_default_cat_ids;
+    use actix_web::{http::StatusCode, test, web, App};
+    let (config, pool) = get_config().await.expect("Error getting config and DB pool");
+    let mut cache = test_cache_state();
+    cache.default_cat_ids = get_default_cat_ids(&pool).await;
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(config))
+            .app_data(web::Data::new(pool))
+            .app_data(web::Data::new(cache))
+            .service(sp_map)
+            .service(sp_banned),
+    )
+    .await;
+    let req = test::TestRequest::get().uri("/map/sp/00000").to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::NOT_FOUND);
+    let req = test::TestRequest::get().uri("/sp/banned/00000?profile_number=seed1&score=1000").to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::NOT_FOUND);
+    // Known maps still fall back to their default category.
+    let req = test::TestRequest::get().uri("/map/sp/47763?limit=5").to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+    let req = test::TestRequest::get().uri("/sp/banned/47763?profile_number=seed1&score=1000").to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn test_db_default_cat_ids_by_game() {
+    use crate::api::v1::handlers::maps::default_categories;
+    use crate::api::v1::handlers::sp::sp_map;
+    use std::collections::HashMap;
+    use crate::models::maps::Maps;
+    use crate::tools::helpers::get_default_cat_ids;
+    use actix_web::{http::StatusCode, test, web, App};
+    let (config, pool) = get_config().await.expect("Error getting config and DB pool");
+    let _lock = MAP_DEFAULTS.lock().await;
+    let default_cat_ids = get_default_cat_ids(&pool).await;
+    let legacy = Maps::get_all_default_cats(&pool).await.unwrap();
+    assert_eq!(default_cat_ids.get("47763", 1), Some(legacy["47763"]));
+    assert_eq!(default_cat_ids.for_game(1), legacy);
+    // Base game maps have no default category in other games.
+    assert_eq!(default_cat_ids.get("47763", 2), None);
+
+    let mut cache = test_cache_state();
+    cache.default_cat_ids = default_cat_ids;
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(config))
+            .app_data(web::Data::new(pool))
+            .app_data(web::Data::new(cache))
+            .service(sp_map)
+            .service(default_categories),
+    )
+    .await;
+    let req = test::TestRequest::get().uri("/default_categories?game_id=1").to_request();
+    let base_game: HashMap<String, i32> = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(base_game, legacy);
+    let req = test::TestRequest::get().uri("/default_categories").to_request();
+    let all: HashMap<String, i32> = test::call_and_read_body_json(&app, req).await;
+    assert!(all.len() >= base_game.len());
+    assert_eq!(all["47763"], legacy["47763"]);
+    let req = test::TestRequest::get().uri("/map/sp/47763?game_id=1&limit=5").to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+    let req = test::TestRequest::get().uri("/map/sp/47763?game_id=2&limit=5").to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn test_db_sp_map_formatted() {
+    use crate::api::v1::handlers::sp::sp_map;
+    use crate::tools::helpers::{format_score, DEFAULT_TICK_RATE};
+    use actix_web::{test, web, App};
+    let (config, pool) = get_config().await.expect("Error getting config and DB pool");
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(config))
+            .app_data(web::Data::new(pool))
+            .app_data(web::Data::new(test_cache_state()))
+            .service(sp_map),
+    )
+    .await;
+    let req = test::TestRequest::get().uri("/map/sp/47763?cat_id=19&limit=5").to_request();
+    let page: Vec<serde_json::Value> = test::call_and_read_body_json(&app, req).await;
+    assert!(page[0].get("formatted_score").is_none());
+    let req = test::TestRequest::get().uri("/map/sp/47763?cat_id=19&limit=5&formatted=true").to_request();
+    let page: Vec<serde_json::Value> = test::call_and_read_body_json(&app, req).await;
+    for entry in page {
+        let score = entry["map_data"]["score"].as_i64().unwrap() as i32;
+        assert_eq!(entry["formatted_score"], format_score(score, DEFAULT_TICK_RATE));
+    }
+}
+
+#[actix_web::test]
+async fn test_db_sp_map_review() {
+    use crate::api::v1::handlers::sp::sp_map;
+    use crate::models::changelog::{Changelog, ChangelogInsert};
+    use actix_web::{test, web, App};
+    let (config, pool) = get_config().await.expect("Error getting config and DB pool");
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(config))
+            .app_data(web::Data::new(pool.clone()))
+            .app_data(web::Data::new(test_cache_state()))
+            .service(sp_map),
+    )
+    .await;
+    let admin = test_auth_header(&pool, "auth_test_admin", 2).await;
+    let user = test_auth_header(&pool, "auth_test_user", 0).await;
+    let mut cl_ids = Vec::new();
+    for (profile_number, score, banned, verified) in [("seed10", 3, false, false), ("seed11", 4, true, true)] {
+        let cl = ChangelogInsert {
+            profile_number: profile_number.to_string(),
+            score,
+            map_id: "47763".to_string(),
+            category_id: 19,
+            banned,
+            verified: Some(verified),
+            ..Default::default()
+        };
+        cl_ids.push(Changelog::insert_changelog(&pool, cl).await.unwrap());
+    }
+    let page = |query: &str, auth: Option<&String>| {
+        let mut req = test::TestRequest::get().uri(&format!("/map/sp/47763?cat_id=19&limit=5{query}"));
+        if let Some(auth) = auth {
+            req = req.insert_header(("Authorization", auth.clone()));
+        }
+        req.to_request()
+    };
+    let profiles = |page: &Vec<serde_json::Value>| {
+        page.iter().map(|e| e["map_data"]["profile_number"].as_str().unwrap().to_string()).collect::<Vec<String>>()
+    };
+
+    // Non-admins never see unverified or banned times.
+    for auth in [None, Some(&user)] {
+        let res: Vec<serde_json::Value> =
+            test::call_and_read_body_json(&app, page("&include_unverified=true&include_banned=true", auth)).await;
+        assert!(!profiles(&res).contains(&"seed10".to_string()));
+        assert!(!profiles(&res).contains(&"seed11".to_string()));
+        assert!(res[0]["map_data"].get("verified").is_none());
+    }
+    let res: Vec<serde_json::Value> = test::call_and_read_body_json(&app, page("", Some(&admin))).await;
+    assert!(!profiles(&res).contains(&"seed10".to_string()));
+    assert!(res[0]["map_data"].get("verified").is_none());
+
+    let res: Vec<serde_json::Value> =
+        test::call_and_read_body_json(&app, page("&include_unverified=true", Some(&admin))).await;
+    assert_eq!(res[0]["map_data"]["profile_number"], "seed10");
+    assert_eq!(res[0]["map_data"]["verified"], false);
+    assert_eq!(res[0]["map_data"]["banned"], false);
+    assert!(!profiles(&res).contains(&"seed11".to_string()));
+
+    let res: Vec<serde_json::Value> =
+        test::call_and_read_body_json(&app, page("&include_unverified=true&include_banned=true", Some(&admin))).await;
+    assert_eq!(profiles(&res)[..2], ["seed10".to_string(), "seed11".to_string()]);
+    assert_eq!(res[1]["map_data"]["verified"], true);
+    assert_eq!(res[1]["map_data"]["banned"], true);
+    for id in cl_ids {
+        Changelog::delete_changelog(&pool, id).await.unwrap();
+    }
+}
+
+#[actix_web::test]
+async fn test_db_points_sp_aggregate() {
+    use crate::api::v1::handlers::points::points_sp;
+    use crate::models::points::PointsAggregate;
+    use crate::models::users::Users;
+    use crate::tools::cache::SP_AGGREGATE;
+    use crate::tools::helpers::get_default_cat_ids;
+    use actix_web::{test, web, App};
+    let (config, pool) = get_config().await.expect("Error getting config and DB pool");
+    let mut cache = test_cache_state();
+    cache.default_cat_ids = get_default_cat_ids(&pool).await;
+    cache.update_current_state(SP_AGGREGATE, false).await;
+    let cache = web::Data::new(cache);
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(config))
+            .app_data(web::Data::new(pool.clone()))
+            .app_data(cache.clone())
+            .service(points_sp),
+    )
+    .await;
+    let req = test::TestRequest::get().uri("/points/sp").to_request();
+    let points: Vec<PointsAggregate> = test::call_and_read_body_json(&app, req).await;
+    assert!(cache.get_current_state(SP_AGGREGATE).await);
+    assert_eq!(points[0].rank, 1);
+    for pair in points.windows(2) {
+        assert!(pair[0].total_points >= pair[1].total_points);
+        assert!(pair[0].rank <= pair[1].rank);
+    }
+    for player in points.iter() {
+        assert!(!Users::check_banned(&pool, &player.profile_number).await.unwrap());
+    }
+    // The second request is served from the cache.
+    let req = test::TestRequest::get().uri("/points/sp").to_request();
+    let cached: Vec<PointsAggregate> = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(cached.len(), points.len());
+}
+
+#[actix_web::test]
+async fn test_db_demos_multiple_per_changelog() {
+    use crate::api::v1::handlers::demos::{demos_add, demos_all};
+    use crate::models::changelog::Changelog;
+    use crate::models::demos::{DemoInsert, Demos};
+    use actix_web::{http::StatusCode, test, web, App};
+    let (_, pool) = get_config().await.expect("Error getting config and DB pool");
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(pool.clone()))
+            .service(demos_add)
+            .service(demos_all),
+    )
+    .await;
+    let original = Changelog::get_changelog(&pool, 127825).await.unwrap().unwrap();
+    let admin_auth = test_auth_header(&pool, "auth_test_admin", 2).await;
+    let other_auth = test_auth_header(&pool, "auth_test_other", 0).await;
+    let demo = DemoInsert { file_id: "test_multiple_0.dem".to_string(), cl_id: 127825, ..Default::default() };
+    let req = test::TestRequest::post().uri("/demos").set_json(&demo).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::UNAUTHORIZED);
+    // Only the owner of the changelog entry (or an admin) can point it at a demo.
+    let req = test::TestRequest::post().uri("/demos").insert_header(("Authorization", other_auth)).set_json(&demo).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::FORBIDDEN);
+    let mut ids = Vec::new();
+    for file_id in ["test_multiple_1.dem", "test_multiple_2.dem"] {
+        let demo = DemoInsert {
+            file_id: file_id.to_string(),
+            cl_id: 127825,
+            ..Default::default()
+        };
+        let req = test::TestRequest::post()
+            .uri("/demos")
+            .insert_header(("Authorization", admin_auth.clone()))
+            .set_json(&demo)
+            .to_request();
+        let id: i64 = test::call_and_read_body_json(&app, req).await;
+        ids.push(id);
+    }
+    let req = test::TestRequest::get().uri("/demos/all?cl_id=127825").to_request();
+    let all: Vec<Demos> = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(all[0].id, ids[1]);
+    assert!(all.iter().any(|d| d.id == ids[0]));
+    let cl = Changelog::get_changelog(&pool, 127825).await.unwrap().unwrap();
+    assert_eq!(cl.demo_id, Some(ids[1]));
+    Changelog::delete_references_to_demo(&pool, ids[1]).await.unwrap();
+    if let Some(demo_id) = original.demo_id {
+        Changelog::update_demo_id_in_changelog(&pool, 127825, demo_id).await.unwrap();
+    }
+    for id in ids {
+        Demos::delete_demo(&pool, id).await.unwrap();
+    }
+}
+
+#[actix_web::test]
+async fn test_db_coop_map_game_id() {
+    use crate::api::v1::handlers::coop::coop_map;
+    use actix_web::{test, web, App};
+    let (config, pool) = get_config().await.expect("Error getting config and DB pool");
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(config))
+            .app_data(web::Data::new(pool))
+            .app_data(web::Data::new(test_cache_state()))
+            .service(coop_map),
+    )
+    .await;
+    // Base game maps have no scores under another game's ID.
+    let req = test::TestRequest::get().uri("/map/coop/47741?cat_id=61&game_id=2").to_request();
+    let page: Vec<serde_json::Value> = test::call_and_read_body_json(&app, req).await;
+    assert!(page.is_empty());
+}
+
+#[actix_web::test]
+async fn test_db_coop_previews_game_id() {
+    use crate::api::v1::handlers::coop::coop;
+    use crate::models::coop::CoopPreview;
+    use crate::tools::metrics::Metrics;
+    use actix_web::{test, web, App};
+    let (_, pool) = get_config().await.expect("Error getting config and DB pool");
+    let mut cache = test_cache_state();
+    cache.default_cat_ids = crate::tools::helpers::get_default_cat_ids(&pool).await;
+    let cache = web::Data::new(cache);
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(pool.clone()))
+            .app_data(cache.clone())
+            .app_data(web::Data::new(Metrics::new().unwrap()))
+            .service(coop),
+    )
+    .await;
+    // Base game coop maps are not previewed under another game's ID.
+    let base = CoopPreview::get_coop_previews(&pool, &cache.default_cat_ids.for_game(1), 1).await.unwrap();
+    assert!(!base.is_empty());
+    let req = test::TestRequest::get().uri("/coop?game_id=2").to_request();
+    let previews: Vec<Vec<serde_json::Value>> = test::call_and_read_body_json(&app, req).await;
+    assert!(previews.is_empty());
+    assert!(cache.get_game_coop_previews(2).await.is_some());
+    cache.invalidate_all_previews().await;
+    assert!(cache.get_game_coop_previews(2).await.is_none());
+}
+
+#[actix_web::test]
+async fn test_db_coop_history() {
+    use crate::api::v1::handlers::coop::coop_history;
+    use crate::models::changelog::{Changelog, ChangelogInsert};
+    use crate::models::coop::{CoopBundled, CoopBundledInsert, CoopPbHistory};
+    use actix_web::{test, web, App};
+    use chrono::NaiveDateTime;
+    let (_, pool) = get_config().await.expect("Error getting config and DB pool");
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(pool.clone()))
+            .app_data(web::Data::new(test_cache_state()))
+            .service(coop_history),
+    )
+    .await;
+    // seed118 ran with seed119, then improved with seed120 who was on the first side of the bundle.
+    let runs = [
+        ("seed118", "seed119", 900210, "2021-01-01 12:00:00"),
+        ("seed120", "seed118", 900200, "2021-02-01 12:00:00"),
+    ];
+    let mut cl_ids = Vec::new();
+    let mut coop_ids = Vec::new();
+    for (p_id1, p_id2, score, timestamp) in runs {
+        let mut ids = Vec::new();
+        for profile_number in [p_id1, p_id2] {
+            let cl = ChangelogInsert {
+                timestamp: Some(NaiveDateTime::parse_from_str(timestamp, "%Y-%m-%d %H:%M:%S").unwrap()),
+                profile_number: profile_number.to_string(),
+                score,
+                map_id: "47741".to_string(),
+                category_id: 61,
+                ..Default::default()
+            };
+            ids.push(Changelog::insert_changelog(&pool, cl).await.unwrap());
+        }
+        let bundle = CoopBundledInsert {
+            p_id1: p_id1.to_string(),
+            p_id2: Some(p_id2.to_string()),
+            p1_is_host: None,
+            cl_id1: ids[0],
+            cl_id2: Some(ids[1]),
+        };
+        let coop_id = CoopBundled::insert_coop_bundled(&pool, bundle).await.unwrap();
+        for id in &ids {
+            CoopBundled::update_changelog_with_coop_id(&pool, *id, coop_id).await.unwrap();
+        }
+        cl_ids.extend(ids);
+        coop_ids.push(coop_id);
+    }
+    // An unbundled run is not part of the history.
+    let unbundled = ChangelogInsert {
+        profile_number: "seed118".to_string(),
+        score: 900190,
+        map_id: "47741".to_string(),
+        category_id: 61,
+        ..Default::default()
+    };
+    cl_ids.push(Changelog::insert_changelog(&pool, unbundled).await.unwrap());
+
+    let req = test::TestRequest::get().uri("/coop/history?map_id=47741&profile_number=seed118&cat_id=61").to_request();
+    let history: CoopPbHistory = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(history.user_name, Some("Seed 118".to_string()));
+    let pb_history = history.pb_history.unwrap();
+    assert_eq!(pb_history.len(), 2);
+    assert_eq!(pb_history[0].changelog.score, 900200);
+    assert_eq!(pb_history[0].partner_profile_number, Some("seed120".to_string()));
+    assert_eq!(pb_history[0].partner_name, Some("Seed 120".to_string()));
+    assert_eq!(pb_history[1].changelog.score, 900210);
+    assert_eq!(pb_history[1].partner_profile_number, Some("seed119".to_string()));
+    assert!(pb_history.iter().all(|entry| entry.changelog.profile_number == "seed118"));
+
+    let req = test::TestRequest::get().uri("/coop/history?map_id=47741&profile_number=not_a_player&cat_id=61").to_request();
+    let history: CoopPbHistory = test::call_and_read_body_json(&app, req).await;
+    assert!(history.user_name.is_none() && history.avatar.is_none() && history.pb_history.is_none());
+
+    sqlx::query("UPDATE changelog SET coop_id = NULL WHERE id = ANY($1)")
+        .bind(&cl_ids)
+        .execute(&pool)
+        .await
+        .unwrap();
+    sqlx::query("DELETE FROM coop_bundled WHERE id = ANY($1)").bind(&coop_ids).execute(&pool).await.unwrap();
+    for id in cl_ids {
+        Changelog::delete_changelog(&pool, id).await.unwrap();
+    }
+}
+
+#[actix_web::test]
+async fn test_db_coop_banned_category_filter() {
+    use crate::models::changelog::{Changelog, ChangelogInsert};
+    use crate::models::coop::{CoopBanned, CoopBundled, CoopBundledInsert};
+    let (_, pool) = get_config().await.expect("Error getting config and DB pool");
+    let default_cat_ids = crate::tools::helpers::get_default_cat_ids(&pool).await;
+    let cat_id = default_cat_ids.get("47741", 1).unwrap();
+    let other_cat_id = default_cat_ids.get("47763", 1).unwrap();
+    let mut cl_ids = Vec::new();
+    let mut bundle_ids = Vec::new();
+    // One banned bundle in the requested category, and one in another category.
+    for (category_id, score) in [(cat_id, 90001), (other_cat_id, 90002)] {
+        let mut ids = Vec::new();
+        for profile_number in ["76561198040982247", "seed1"] {
+            let cl = ChangelogInsert {
+                profile_number: profile_number.to_string(),
+                score,
+                map_id: "47741".to_string(),
+                banned: true,
+                category_id,
+                verified: Some(true),
+                ..Default::default()
+            };
+            ids.push(Changelog::insert_changelog(&pool, cl).await.unwrap());
+        }
+        let bundle = CoopBundledInsert {
+            p_id1: "76561198040982247".to_string(),
+            p_id2: Some("seed1".to_string()),
+            p1_is_host: None,
+            cl_id1: ids[0],
+            cl_id2: Some(ids[1]),
+        };
+        let bundle_id = CoopBundled::insert_coop_bundled(&pool, bundle).await.unwrap();
+        for id in ids.iter() {
+            CoopBundled::update_changelog_with_coop_id(&pool, *id, bundle_id).await.unwrap();
+        }
+        cl_ids.extend(ids);
+        bundle_ids.push(bundle_id);
+    }
+    let banned = CoopBanned::get_coop_banned(&pool, "47741", cat_id).await.unwrap();
+    assert!(banned.iter().any(|b| b.score == 90001));
+    assert!(!banned.iter().any(|b| b.score == 90002));
+    sqlx::query("UPDATE changelog SET coop_id = NULL WHERE id = ANY($1)")
+        .bind(&cl_ids)
+        .execute(&pool)
+        .await
+        .unwrap();
+    for id in bundle_ids {
+        sqlx::query("DELETE FROM coop_bundled WHERE id = $1").bind(id).execute(&pool).await.unwrap();
+    }
+    for id in cl_ids {
+        Changelog::delete_changelog(&pool, id).await.unwrap();
+    }
+}
+
+#[actix_web::test]
+async fn test_db_demos_coop() {
+    use crate::api::v1::handlers::demos::demos_coop;
+    use crate::models::changelog::{Changelog, ChangelogInsert};
+    use crate::models::coop::{CoopBundled, CoopBundledInsert};
+    use crate::models::demos::{CoopDemos, DemoInsert, Demos};
+    use actix_web::{http::StatusCode, test, web, App};
+    let (_, pool) = get_config().await.expect("Error getting config and DB pool");
+    let app = test::init_service(App::new().app_data(web::Data::new(pool.clone())).service(demos_coop)).await;
+    let category_id = crate::tools::helpers::get_default_cat_ids(&pool).await.get("47741", 1).unwrap();
+    let mut cl_ids = Vec::new();
+    for profile_number in ["76561198040982247", "seed1"] {
+        let cl = ChangelogInsert {
+            profile_number: profile_number.to_string(),
+            score: 900400,
+            map_id: "47741".to_string(),
+            category_id,
+            verified: Some(true),
+            ..Default::default()
+        };
+        cl_ids.push(Changelog::insert_changelog(&pool, cl).await.unwrap());
+    }
+    let bundle = CoopBundledInsert {
+        p_id1: "76561198040982247".to_string(),
+        p_id2: Some("seed1".to_string()),
+        p1_is_host: None,
+        cl_id1: cl_ids[0],
+        cl_id2: Some(cl_ids[1]),
+    };
+    let coop_id = CoopBundled::insert_coop_bundled(&pool, bundle).await.unwrap();
+    // Only the first player has uploaded a demo.
+    let demo = DemoInsert { file_id: "test_coop_demo.dem".to_string(), cl_id: cl_ids[0], ..Default::default() };
+    let demo_id = Demos::insert_demo(&pool, demo).await.unwrap();
+    Changelog::update_demo_id_in_changelog(&pool, cl_ids[0], demo_id).await.unwrap();
+
+    let req = test::TestRequest::get().uri(&format!("/demos/coop?coop_id={coop_id}")).to_request();
+    let coop_demos: CoopDemos = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(
+        coop_demos,
+        CoopDemos {
+            coop_id,
+            demo_id1: Some(demo_id),
+            demo_id2: None,
+            file_id1: Some("test_coop_demo.dem".to_string()),
+            file_id2: None,
+        }
+    );
+    let req = test::TestRequest::get().uri("/demos/coop?coop_id=-1").to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::NOT_FOUND);
+
+    sqlx::query("UPDATE changelog SET demo_id = NULL WHERE id = ANY($1)")
+        .bind(&cl_ids)
+        .execute(&pool)
+        .await
+        .unwrap();
+    Demos::delete_demo(&pool, demo_id).await.unwrap();
+    sqlx::query("DELETE FROM coop_bundled WHERE id = $1").bind(coop_id).execute(&pool).await.unwrap();
+    for id in cl_ids {
+        Changelog::delete_changelog(&pool, id).await.unwrap();
+    }
+}
+
+#[actix_web::test]
+async fn test_db_users_batch() {
+    use crate::api::v1::handlers::users::users_batch;
+    use crate::models::users::UsersPage;
+    use crate::tools::error::{ErrResponse, ErrorType};
+    use actix_web::{http::StatusCode, test, web, App};
+    use std::collections::HashMap;
+    let (_, pool) = get_config().await.expect("Error getting config and DB pool");
+    let app = test::init_service(App::new().app_data(web::Data::new(pool)).service(users_batch)).await;
+    let req = test::TestRequest::post()
+        .uri("/users/batch")
+        .set_json(["76561198040982247", "not_a_user"])
+        .to_request();
+    let users: HashMap<String, UsersPage> = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(users.len(), 1);
+    assert!(users.contains_key("76561198040982247"));
+    let too_many = vec!["76561198040982247".to_string(); 501];
+    let req = test::TestRequest::post().uri("/users/batch").set_json(too_many).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    let body: ErrResponse = test::read_body_json(resp).await;
+    assert_eq!(body.error_type, ErrorType::BadRequest);
+}
+
+#[actix_web::test]
+async fn test_cache_ttl() {
+    use crate::tools::cache::SP_PREVIEWS;
+    use std::time::Duration;
+    let mut cache = test_cache_state();
+    cache.ttl = Some(Duration::from_millis(50));
+    cache.update_current_state(SP_PREVIEWS, true).await;
+    assert!(cache.get_current_state(SP_PREVIEWS).await);
+    tokio::time::sleep(Duration::from_millis(60)).await;
+    assert!(!cache.get_current_state(SP_PREVIEWS).await);
+    // Re-caching resets the timestamp.
+    cache.update_current_state(SP_PREVIEWS, true).await;
+    assert!(cache.get_current_state(SP_PREVIEWS).await);
+}
+
+#[actix_web::test]
+async fn test_db_changelog_history_filters() {
+    use crate::models::changelog::{ChangelogPage, ChangelogQueryParams};
+    use chrono::NaiveDate;
+    let (_, pool) = get_config().await.expect("Error getting config and DB pool");
+    let params = ChangelogQueryParams {
+        profile_number: Some("76561198040982247".to_string()),
+        cat_id: Some(19),
+        banned: Some(false),
+        start_date: NaiveDate::from_ymd_opt(2000, 1, 1),
+        end_date: NaiveDate::from_ymd_opt(2100, 1, 1),
+        ..Default::default()
+    };
+    let history = ChangelogPage::get_changelog_page(&pool, params).await.unwrap();
+    assert!(history.windows(2).all(|w| w[0].timestamp >= w[1].timestamp));
+    for entry in history.iter() {
+        assert_eq!(entry.profile_number, "76561198040982247");
+        assert_eq!(entry.category_id, 19);
+        assert!(!entry.banned);
+    }
+    let offset = ChangelogPage::get_changelog_page(
+        &pool,
+        ChangelogQueryParams {
+            profile_number: Some("76561198040982247".to_string()),
+            offset: Some(1),
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    let all = ChangelogPage::get_changelog_page(
+        &pool,
+        ChangelogQueryParams {
+            profile_number: Some("76561198040982247".to_string()),
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(offset.len(), all.len().saturating_sub(1));
+    // No matches are an empty list rather than an error.
+    let none = ChangelogPage::get_changelog_page(
+        &pool,
+        ChangelogQueryParams {
+            nick_name: Some("no user has this name".to_string()),
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    assert!(none.is_empty());
+    let none = ChangelogPage::get_changelog_page(
+        &pool,
+        ChangelogQueryParams {
+            start_date: NaiveDate::from_ymd_opt(1990, 1, 1),
+            end_date: NaiveDate::from_ymd_opt(1990, 1, 2),
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    assert!(none.is_empty());
+}
+
+#[actix_web::test]
+async fn test_db_profile_sp_only() {
+    use crate::models::changelog::{Changelog, ChangelogInsert};
+    use crate::models::users::Users;
+    use chrono::NaiveDateTime;
+    let (_, pool) = get_config().await.expect("Error getting config and DB pool");
+    let default_cat_ids = crate::tools::helpers::get_default_cat_ids(&pool).await;
+    let profile_number = "sp_only_profile".to_string();
+    Users::insert_new_users(
+        &pool,
+        Users {
+            profile_number: profile_number.clone(),
+            board_name: Some("SP Only".to_string()),
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    let cl = ChangelogInsert {
+        profile_number: profile_number.clone(),
+        timestamp: NaiveDateTime::parse_from_str("2022-01-01 12:00:00", "%Y-%m-%d %H:%M:%S").ok(),
+        score: 1900,
+        map_id: "47763".to_string(),
+        category_id: default_cat_ids.get("47763", 1).unwrap(),
+        verified: Some(true),
+        ..Default::default()
+    };
+    let cl_id = Changelog::insert_changelog(&pool, cl).await.unwrap();
+    let profile = Users::get_profile(&pool, &profile_number).await;
+    Changelog::delete_changelog(&pool, cl_id).await.unwrap();
+    Users::delete_user(&pool, profile_number).await.unwrap();
+    let profile = profile.unwrap();
+    assert_eq!(profile.oldest_sp.unwrap().map, "47763");
+    assert_eq!(profile.newest_sp.unwrap().score, 1900);
+    assert!(profile.oldest_coop.is_none());
+    assert!(profile.newest_coop.is_none());
+}
+
+#[actix_web::test]
+async fn test_db_users_ban() {
+    use crate::api::v1::handlers::users::users_ban;
+    use crate::models::changelog::{Changelog, ChangelogInsert};
+    use crate::models::users::{UserBanParams, Users};
+    use crate::tools::cache::{SP_AGGREGATE, SP_PREVIEWS};
+    use actix_web::{http::StatusCode, test, web, App};
+    let (_, pool) = get_config().await.expect("Error getting config and DB pool");
+    let cache = web::Data::new(test_cache_state());
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(pool.clone()))
+            .app_data(cache.clone())
+            .service(users_ban),
+    )
+    .await;
+    let profile_number = "ban_test_profile".to_string();
+    Users::insert_new_users(
+        &pool,
+        Users {
+            profile_number: profile_number.clone(),
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    let mut cl_ids = Vec::new();
+    for score in [2000, 1950] {
+        let cl = ChangelogInsert {
+            profile_number: profile_number.clone(),
+            score,
+            map_id: "47763".to_string(),
+            category_id: 19,
+            verified: Some(true),
+            ..Default::default()
+        };
+        cl_ids.push(Changelog::insert_changelog(&pool, cl).await.unwrap());
+    }
+    let auth = test_auth_header(&pool, "auth_test_admin", 2).await;
+    let ban = |banned| {
+        test::TestRequest::put()
+            .uri("/users/ban")
+            .insert_header(("Authorization", auth.clone()))
+            .set_json(UserBanParams {
+                profile_number: profile_number.clone(),
+                banned,
+            })
+            .to_request()
+    };
+    let banned_times: u64 = test::call_and_read_body_json(&app, ban(true)).await;
+    assert_eq!(banned_times, 2);
+    assert!(Users::check_banned(&pool, &profile_number).await.unwrap());
+    assert!(!cache.get_current_state(SP_PREVIEWS).await);
+    assert!(!cache.get_current_state(SP_AGGREGATE).await);
+    // Unbanning the user leaves their times banned.
+    let banned_times: u64 = test::call_and_read_body_json(&app, ban(false)).await;
+    assert_eq!(banned_times, 0);
+    assert!(!Users::check_banned(&pool, &profile_number).await.unwrap());
+    for id in cl_ids.iter() {
+        assert!(Changelog::get_changelog(&pool, *id).await.unwrap().unwrap().banned);
+    }
+    for id in cl_ids {
+        Changelog::delete_changelog(&pool, id).await.unwrap();
+    }
+    Users::delete_user(&pool, profile_number).await.unwrap();
+    let req = test::TestRequest::put()
+        .uri("/users/ban")
+        .insert_header(("Authorization", auth))
+        .set_json(UserBanParams {
+            profile_number: "not_a_user".to_string(),
+            banned: true,
+        })
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn test_db_admin_verify() {
+    use crate::api::v1::handlers::admin::{admin_verify, admin_verify_queue};
+    use crate::models::admin::VerificationDecision;
+    use crate::models::changelog::{Changelog, ChangelogInsert, ChangelogPage};
+    use crate::models::users::Users;
+    use crate::tools::cache::SP_AGGREGATE;
+    use actix_web::{http::StatusCode, test, web, App};
+    let (_, pool) = get_config().await.expect("Error getting config and DB pool");
+    let cache = web::Data::new(test_cache_state());
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(pool.clone()))
+            .app_data(cache.clone())
+            .service(admin_verify_queue)
+            .service(admin_verify),
+    )
+    .await;
+    let profile_number = "verify_test_profile".to_string();
+    Users::insert_new_users(
+        &pool,
+        Users {
+            profile_number: profile_number.clone(),
+            steam_name: Some("verify_test".to_string()),
+            avatar: Some(String::new()),
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    let mut cl_ids = Vec::new();
+    for score in [900100, 900050] {
+        let cl = ChangelogInsert {
+            profile_number: profile_number.clone(),
+            score,
+            map_id: "47763".to_string(),
+            category_id: 19,
+            verified: Some(false),
+            ..Default::default()
+        };
+        cl_ids.push(Changelog::insert_changelog(&pool, cl).await.unwrap());
+    }
+    let admin = test_auth_header(&pool, "auth_test_admin", 2).await;
+    let player = test_auth_header(&pool, "auth_test_player", 0).await;
+    let queue = |auth: &str| {
+        test::TestRequest::get()
+            .uri("/admin/verify/queue?game_id=1")
+            .insert_header(("Authorization", auth.to_string()))
+            .to_request()
+    };
+    let verify = |cl_id: i64, approved: bool, admin_note: Option<&str>, auth: &str| {
+        test::TestRequest::post()
+            .uri(&format!("/admin/verify/{cl_id}"))
+            .insert_header(("Authorization", auth.to_string()))
+            .set_json(VerificationDecision {
+                approved,
+                admin_note: admin_note.map(|note| note.to_string()),
+            })
+            .to_request()
+    };
+    assert_eq!(test::call_service(&app, queue(&player)).await.status(), StatusCode::FORBIDDEN);
+    assert_eq!(
+        test::call_service(&app, verify(cl_ids[0], true, None, &player)).await.status(),
+        StatusCode::FORBIDDEN
+    );
+    let pending: Vec<serde_json::Value> = test::call_and_read_body_json(&app, queue(&admin)).await;
+    for id in cl_ids.iter() {
+        assert!(pending.iter().any(|entry| entry["id"] == *id));
+    }
+    let pending: Vec<ChangelogPage> = Changelog::get_unverified(&pool, Some(-1)).await.unwrap();
+    assert!(pending.is_empty());
+
+    // Approving verifies the score.
+    let approved: Changelog = test::call_and_read_body_json(&app, verify(cl_ids[0], true, None, &admin)).await;
+    assert_eq!(approved.verified, Some(true));
+    assert!(!approved.banned);
+    assert!(!cache.get_current_state(SP_AGGREGATE).await);
+    // Rejecting bans the score and records the note.
+    let rejected: Changelog =
+        test::call_and_read_body_json(&app, verify(cl_ids[1], false, Some("Demo does not match"), &admin)).await;
+    assert_eq!(rejected.verified, Some(false));
+    assert!(rejected.banned);
+    assert_eq!(rejected.admin_note.as_deref(), Some("Demo does not match"));
+    let pending: Vec<serde_json::Value> = test::call_and_read_body_json(&app, queue(&admin)).await;
+    for id in cl_ids.iter() {
+        assert!(!pending.iter().any(|entry| entry["id"] == *id));
+    }
+
+    for id in cl_ids {
+        Changelog::delete_changelog(&pool, id).await.unwrap();
+    }
+    Users::delete_user(&pool, profile_number).await.unwrap();
+    assert_eq!(
+        test::call_service(&app, verify(-1, true, None, &admin)).await.status(),
+        StatusCode::NOT_FOUND
+    );
+}
+
+#[actix_web::test]
+async fn test_db_admin_repair_chain() {
+    use crate::api::v1::handlers::admin::admin_repair_chain;
+    use crate::models::admin::ChainRepairParams;
+    use crate::models::changelog::{Changelog, ChangelogInsert};
+    use crate::models::users::Users;
+    use actix_web::{http::StatusCode, test, web, App};
+    use chrono::NaiveDate;
+    let (_, pool) = get_config().await.expect("Error getting config and DB pool");
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(pool.clone()))
+            .app_data(web::Data::new(test_cache_state()))
+            .service(admin_repair_chain),
+    )
+    .await;
+    let profile_number = "chain_test_profile".to_string();
+    let rival = "chain_test_rival".to_string();
+    for profile in [&profile_number, &rival] {
+        Users::insert_new_users(
+            &pool,
+            Users {
+                profile_number: profile.clone(),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+    }
+    let at = |year| NaiveDate::from_ymd_opt(year, 1, 1).unwrap().and_hms_opt(0, 0, 0);
+    let entry = |profile: &String, score, year| ChangelogInsert {
+        timestamp: at(year),
+        profile_number: profile.clone(),
+        score,
+        map_id: "47763".to_string(),
+        category_id: 19,
+        verified: Some(true),
+        ..Default::default()
+    };
+    let rival_id = Changelog::insert_changelog(&pool, entry(&rival, 900150, 1999)).await.unwrap();
+    // Inserted out of timestamp order, with the middle of the chain missing its `previous_id`.
+    let last = Changelog::insert_changelog(&pool, entry(&profile_number, 900100, 2002)).await.unwrap();
+    let first = Changelog::insert_changelog(&pool, entry(&profile_number, 900300, 2000)).await.unwrap();
+    let middle = Changelog::insert_changelog(&pool, entry(&profile_number, 900200, 2001)).await.unwrap();
+    sqlx::query("UPDATE changelog SET previous_id = $1, score_delta = 100 WHERE id = $2")
+        .bind(middle)
+        .bind(last)
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let repair = |auth: String| {
+        test::TestRequest::post()
+            .uri("/admin/repair/chain")
+            .insert_header(("Authorization", auth))
+            .set_json(ChainRepairParams {
+                profile_number: profile_number.clone(),
+                map_id: "47763".to_string(),
+                cat_id: Some(19),
+                game_id: None,
+            })
+            .to_request()
+    };
+    let player = test_auth_header(&pool, "auth_test_player", 0).await;
+    assert_eq!(test::call_service(&app, repair(player)).await.status(), StatusCode::FORBIDDEN);
+    let admin = test_auth_header(&pool, "auth_test_admin", 2).await;
+    let chain: Vec<Changelog> = test::call_and_read_body_json(&app, repair(admin)).await;
+    assert_eq!(chain.iter().map(|cl| cl.id).collect::<Vec<_>>(), vec![first, middle, last]);
+    assert_eq!(chain[0].previous_id, None);
+    assert_eq!(chain[1].previous_id, Some(first));
+    assert_eq!(chain[2].previous_id, Some(middle));
+    assert_eq!(chain.iter().map(|cl| cl.score_delta).collect::<Vec<_>>(), vec![None, Some(100), Some(100)]);
+    // The rival's 900150 is the only time ahead until the last entry beats it.
+    assert_eq!(chain.iter().map(|cl| cl.pre_rank).collect::<Vec<_>>(), vec![None, Some(2), Some(2)]);
+    assert_eq!(chain.iter().map(|cl| cl.post_rank).collect::<Vec<_>>(), vec![Some(2), Some(2), Some(1)]);
+    assert_eq!(Changelog::get_changelog(&pool, last).await.unwrap().unwrap(), chain[2]);
+
+    for id in [rival_id, first, middle, last] {
+        Changelog::delete_changelog(&pool, id).await.unwrap();
+    }
+    Users::delete_user(&pool, profile_number).await.unwrap();
+    Users::delete_user(&pool, rival).await.unwrap();
+}
+
+#[actix_web::test]
+async fn test_db_avatar_refresh_unknown_user() {
+    use crate::api::v1::handlers::users::avatar_refresh;
+    use crate::tools::error::{ErrResponse, ErrorType};
+    use actix_web::{http::StatusCode, test, web, App};
+    let (config, pool) = get_config().await.expect("Error getting config and DB pool");
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(pool))
+            .app_data(web::Data::new(config))
+            .service(avatar_refresh),
+    )
+    .await;
+    let req = test::TestRequest::put().uri("/users/not_a_user/avatar/refresh").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    let body: ErrResponse = test::read_body_json(resp).await;
+    assert_eq!(body.error_type, ErrorType::NotFound);
+}
+
+#[actix_web::test]
+async fn test_db_users_flagged() {
+    use crate::api::v1::handlers::admin::users_flagged;
+    use crate::models::users::{FlaggedUser, Users};
+    use actix_web::{http::StatusCode, test, web, App};
+    let (_, pool) = get_config().await.expect("Error getting config and DB pool");
+    let app = test::init_service(App::new().app_data(web::Data::new(pool.clone())).service(users_flagged)).await;
+    let profile_number = "flagged_test_profile".to_string();
+    Users::insert_new_users(
+        &pool,
+        Users {
+            profile_number: profile_number.clone(),
+            steam_name: Some("flagged".to_string()),
+            needs_review: true,
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    let flagged = Users::get_flagged(&pool).await;
+    let unauthenticated = test::call_service(&app, test::TestRequest::get().uri("/users/flagged").to_request()).await.status();
+    let user_auth = test_auth_header(&pool, "auth_test_user", 0).await;
+    let req = test::TestRequest::get().uri("/users/flagged").insert_header(("Authorization", user_auth)).to_request();
+    let forbidden = test::call_service(&app, req).await.status();
+    let admin_auth = test_auth_header(&pool, "auth_test_admin", 2).await;
+    let req = test::TestRequest::get().uri("/users/flagged").insert_header(("Authorization", admin_auth)).to_request();
+    let body: Vec<serde_json::Value> = test::call_and_read_body_json(&app, req).await;
+    Users::delete_user(&pool, profile_number.clone()).await.unwrap();
+    let flagged: Vec<FlaggedUser> = flagged.unwrap();
+    assert!(flagged.iter().any(|u| u.profile_number == profile_number));
+    assert!(flagged.iter().all(|u| u.needs_review));
+    assert_eq!(unauthenticated, StatusCode::UNAUTHORIZED);
+    assert_eq!(forbidden, StatusCode::FORBIDDEN);
+    // Private columns are never returned.
+    let user = body.iter().find(|u| u["profile_number"] == profile_number.as_str()).unwrap();
+    assert!(user.get("auth_hash").is_none());
+    assert!(user.get("discord_id").is_none());
+    assert!(user.get("donation_amount").is_none());
+}
+
+#[actix_web::test]
+async fn test_db_map_detail() {
+    use crate::models::maps::Maps;
+    let (_, pool) = get_config().await.expect("Error getting config and DB pool");
+    let sp = Maps::get_map_detail(&pool, "47763").await.unwrap().unwrap();
+    assert_eq!(sp.steam_id, "47763");
+    assert!(!sp.is_coop);
+    assert_eq!(sp.game_id, 1);
+    assert!(sp.categories.iter().all(|c| c.map_id == "47763"));
+    assert!(sp.categories.iter().any(|c| c.id == sp.default_cat_id));
+    let coop = Maps::get_map_detail(&pool, "47741").await.unwrap().unwrap();
+    assert!(coop.is_coop);
+    assert!(Maps::get_map_detail(&pool, "0").await.unwrap().is_none());
+}
+
+#[actix_web::test]
+async fn test_db_health_and_ready() {
+    use crate::api::v1::handlers::health::{health, ready};
+    use crate::models::health::ReadyStatus;
+    use actix_web::{http::StatusCode, test, web, App};
+    let (mut config, pool) = get_config().await.expect("Error getting config and DB pool");
+    config.backblaze.keyid = "not_a_real_keyid".to_string();
+    let cache = web::Data::new(test_cache_state());
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(config))
+            .app_data(web::Data::new(pool))
+            .app_data(cache.clone())
+            .service(health)
+            .service(ready),
+    )
+    .await;
+    let req = test::TestRequest::get().uri("/health").to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+    let req = test::TestRequest::get().uri("/ready").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+    let status: ReadyStatus = test::read_body_json(resp).await;
+    assert!(status.database);
+    assert_eq!(status.failed, vec!["backblaze".to_string()]);
+    // A recent successful authorization is reused rather than authorizing again.
+    cache.set_b2_authorized().await;
+    let req = test::TestRequest::get().uri("/ready").to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn test_db_sp_patch_note() {
+    use crate::api::v1::handlers::sp::sp_patch;
+    use crate::models::changelog::{Changelog, ChangelogPatch};
+    use actix_web::{http::StatusCode, test, web, App};
+    let (config, pool) = get_config().await.expect("Error getting config and DB pool");
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(pool.clone()))
+            .app_data(web::Data::new(config))
+            .app_data(web::Data::new(test_cache_state()))
+            .service(sp_patch),
+    )
+    .await;
+    let original = Changelog::get_changelog(&pool, 127825).await.unwrap().unwrap();
+    let patch = ChangelogPatch {
+        id: original.id,
+        note: Some("patched note".to_string()),
+        ..Default::default()
+    };
+    let auth = test_auth_header(&pool, "auth_test_admin", 2).await;
+    let req = test::TestRequest::patch()
+        .uri("/sp/update")
+        .insert_header(("Authorization", auth.clone()))
+        .set_json(&patch)
+        .to_request();
+    let patched: Changelog = test::call_and_read_body_json(&app, req).await;
+    Changelog::update_changelog(&pool, Changelog { updated: patched.updated, ..original.clone() }).await.unwrap();
+    assert_eq!(patched.note, Some("patched note".to_string()));
+    assert_eq!(patched.timestamp, original.timestamp);
+    assert_eq!(patched.profile_number, original.profile_number);
+    assert_eq!(patched.score, original.score);
+    assert_eq!(patched.map_id, original.map_id);
+    assert_eq!(patched.demo_id, original.demo_id);
+    assert_eq!(patched.banned, original.banned);
+    assert_eq!(patched.youtube_id, original.youtube_id);
+    assert_eq!(patched.coop_id, original.coop_id);
+    assert_eq!(patched.submission, original.submission);
+    assert_eq!(patched.category_id, original.category_id);
+    assert_eq!(patched.verified, original.verified);
+    assert_eq!(patched.admin_note, original.admin_note);
+    let req = test::TestRequest::patch()
+        .uri("/sp/update")
+        .insert_header(("Authorization", auth))
+        .set_json(ChangelogPatch { id: -1, ..Default::default() })
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn test_db_note_validation() {
+    use crate::api::v1::handlers::changelog::changelog_new;
+    use crate::api::v1::handlers::sp::{sp_patch, sp_update};
+    use crate::models::changelog::{Changelog, ChangelogInsert, ChangelogPatch};
+    use actix_web::{http::StatusCode, test, web, App};
+    let (mut config, pool) = get_config().await.expect("Error getting config and DB pool");
+    config.submissions.max_note_length = 20;
+    config.submissions.require_improvement = false;
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(pool.clone()))
+            .app_data(web::Data::new(config))
+            .app_data(web::Data::new(test_cache_state()))
+            .service(changelog_new)
+            .service(sp_update)
+            .service(sp_patch),
+    )
+    .await;
+    let cl_id = Changelog::insert_changelog(
+        &pool,
+        ChangelogInsert {
+            profile_number: "seed17".to_string(),
+            score: 901100,
+            map_id: "47763".to_string(),
+            category_id: 19,
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    let user_auth = test_auth_header(&pool, "seed17", 0).await;
+    let patch = |patch: ChangelogPatch| {
+        test::TestRequest::patch()
+            .uri("/sp/update")
+            .insert_header(("Authorization", user_auth.clone()))
+            .set_json(patch)
+            .to_request()
+    };
+    let long_note = ChangelogPatch { id: cl_id, note: Some("a".repeat(21)), ..Default::default() };
+    let too_long = test::call_service(&app, patch(long_note)).await;
+    let too_long_status = too_long.status();
+    let too_long_body = String::from_utf8(test::read_body(too_long).await.to_vec()).unwrap();
+    let messy_note = ChangelogPatch { id: cl_id, note: Some("  good\u{0} run  ".to_string()), ..Default::default() };
+    let cleaned: Changelog = test::call_and_read_body_json(&app, patch(messy_note)).await;
+    // Only admins can set an admin note, through either kind of update.
+    let admin_note = patch(ChangelogPatch { id: cl_id, admin_note: Some("looks fine".to_string()), ..Default::default() });
+    let patch_admin_note = test::call_service(&app, admin_note).await.status();
+    let req = test::TestRequest::put()
+        .uri("/sp/update")
+        .insert_header(("Authorization", user_auth.clone()))
+        .set_json(Changelog { admin_note: Some("looks fine".to_string()), ..cleaned.clone() })
+        .to_request();
+    let update_admin_note = test::call_service(&app, req).await.status();
+    let admin_auth = test_auth_header(&pool, "auth_test_admin", 2).await;
+    let req = test::TestRequest::put()
+        .uri("/sp/update")
+        .insert_header(("Authorization", admin_auth))
+        .set_json(Changelog { admin_note: Some("b".repeat(21)), ..cleaned.clone() })
+        .to_request();
+    let admin_too_long = test::call_service(&app, req).await.status();
+    // Submissions are held to the same limit.
+    let req = test::TestRequest::post()
+        .uri("/changelog")
+        .set_json(serde_json::json!({
+            "timestamp": "2022-01-01 00:00:00",
+            "profile_number": "seed17",
+            "score": 901200,
+            "map_id": "47763",
+            "category_id": 19,
+            "note": "c".repeat(21),
+        }))
+        .to_request();
+    let submission_too_long = test::call_service(&app, req).await.status();
+    let stored = Changelog::get_changelog(&pool, cl_id).await.unwrap().unwrap();
+    let submitted: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM changelog WHERE profile_number = 'seed17' AND score = 901200")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    Changelog::delete_changelog(&pool, cl_id).await.unwrap();
+    assert_eq!(too_long_status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(too_long_body.contains("21 characters"), "{too_long_body}");
+    assert_eq!(cleaned.note, Some("good run".to_string()));
+    assert_eq!(patch_admin_note, StatusCode::FORBIDDEN);
+    assert_eq!(update_admin_note, StatusCode::FORBIDDEN);
+    assert_eq!(admin_too_long, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(submission_too_long, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(submitted, 0);
+    assert_eq!(stored.note, Some("good run".to_string()));
+    assert_eq!(stored.admin_note, None);
+}
+
+#[actix_web::test]
+async fn test_db_auth_permissions() {
+    use crate::api::v1::handlers::{sp::sp_patch, users::users_ban};
+    use crate::models::changelog::{Changelog, ChangelogInsert, ChangelogPatch};
+    use crate::models::users::UserBanParams;
+    use actix_web::{http::StatusCode, test, web, App};
+    let (config, pool) = get_config().await.expect("Error getting config and DB pool");
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(pool.clone()))
+            .app_data(web::Data::new(config))
+            .app_data(web::Data::new(test_cache_state()))
+            .service(sp_patch)
+            .service(users_ban),
+    )
+    .await;
+    let user = test_auth_header(&pool, "auth_test_user", 0).await;
+    let other = test_auth_header(&pool, "auth_test_other", 0).await;
+    let cl = ChangelogInsert {
+        profile_number: "auth_test_user".to_string(),
+        score: 2100,
+        map_id: "47763".to_string(),
+        category_id: 19,
+        ..Default::default()
+    };
+    let cl_id = Changelog::insert_changelog(&pool, cl).await.unwrap();
+    let patch = |auth: Option<&str>, patch: ChangelogPatch| {
+        let req = test::TestRequest::patch().uri("/sp/update").set_json(patch);
+        match auth {
+            Some(auth) => req.insert_header(("Authorization", auth.to_string())).to_request(),
+            None => req.to_request(),
+        }
+    };
+    let note = ChangelogPatch {
+        id: cl_id,
+        note: Some("my run".to_string()),
+        youtube_id: Some("DPgJgmLmzCw".to_string()),
+        ..Default::default()
+    };
+    let ban = ChangelogPatch {
+        id: cl_id,
+        banned: Some(true),
+        ..Default::default()
+    };
+    let no_auth = test::call_service(&app, patch(None, note.clone())).await.status();
+    let bad_token = test::call_service(&app, patch(Some("Bearer not-a-token"), note.clone())).await.status();
+    let own_note = test::call_service(&app, patch(Some(&user), note.clone())).await.status();
+    let own_ban = test::call_service(&app, patch(Some(&user), ban)).await.status();
+    let other_note = test::call_service(&app, patch(Some(&other), note)).await.status();
+    let entry = Changelog::get_changelog(&pool, cl_id).await.unwrap().unwrap();
+    Changelog::delete_changelog(&pool, cl_id).await.unwrap();
+    assert_eq!(no_auth, StatusCode::UNAUTHORIZED);
+    assert_eq!(bad_token, StatusCode::UNAUTHORIZED);
+    assert_eq!(own_note, StatusCode::OK);
+    assert_eq!(own_ban, StatusCode::FORBIDDEN);
+    assert_eq!(other_note, StatusCode::FORBIDDEN);
+    assert_eq!(entry.note, Some("my run".to_string()));
+    assert!(!entry.banned);
+    let req = test::TestRequest::put()
+        .uri("/users/ban")
+        .insert_header(("Authorization", user))
+        .set_json(UserBanParams {
+            profile_number: "auth_test_other".to_string(),
+            banned: true,
+        })
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::FORBIDDEN);
+}
+
+#[actix_web::test]
+async fn test_db_coop_bundle() {
+    use crate::api::v1::handlers::coop::coop_bundle;
+    use crate::models::changelog::{Changelog, ChangelogInsert};
+    use crate::models::coop::{CoopBundleParams, CoopBundled, CoopBundledInsert};
+    use actix_web::{http::StatusCode, test, web, App};
+    let (_, pool) = get_config().await.expect("Error getting config and DB pool");
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(pool.clone()))
+            .app_data(web::Data::new(test_cache_state()))
+            .service(coop_bundle),
+    )
+    .await;
+    let default_cat_ids = crate::tools::helpers::get_default_cat_ids(&pool).await;
+    let cat_id = default_cat_ids.get("47741", 1).unwrap();
+    let other_cat_id = default_cat_ids.get("47763", 1).unwrap();
+    sqlx::query("INSERT INTO users (profile_number, banned, registered, admin) VALUES ('N/A', false, 0, 0) ON CONFLICT DO NOTHING")
+        .execute(&pool)
+        .await
+        .unwrap();
+    let temp_cl = match CoopBundled::get_temp_coop_changelog(&pool, "47741").await.unwrap() {
+        Some(_) => None,
+        None => {
+            let cl = ChangelogInsert {
+                profile_number: "N/A".to_string(),
+                map_id: "47741".to_string(),
+                category_id: cat_id,
+                ..Default::default()
+            };
+            Some(Changelog::insert_changelog(&pool, cl).await.unwrap())
+        }
+    };
+    let temp_id = CoopBundled::get_temp_coop_changelog(&pool, "47741").await.unwrap().unwrap().cl_id;
+    let mut cl_ids = Vec::new();
+    for (profile_number, category_id) in [("seed1", cat_id), ("seed2", cat_id), ("seed3", other_cat_id), ("seed4", cat_id), ("seed5", cat_id)] {
+        let cl = ChangelogInsert {
+            profile_number: profile_number.to_string(),
+            score: 90101,
+            map_id: "47741".to_string(),
+            category_id,
+            ..Default::default()
+        };
+        cl_ids.push(Changelog::insert_changelog(&pool, cl).await.unwrap());
+    }
+    // The first score was submitted without a partner, and is bundled with the temp entry.
+    let temp_bundle = CoopBundledInsert {
+        p_id1: "seed1".to_string(),
+        p_id2: Some("N/A".to_string()),
+        p1_is_host: None,
+        cl_id1: cl_ids[0],
+        cl_id2: Some(temp_id),
+    };
+    let temp_bundle_id = CoopBundled::insert_coop_bundled(&pool, temp_bundle).await.unwrap();
+    CoopBundled::update_changelog_with_coop_id(&pool, cl_ids[0], temp_bundle_id).await.unwrap();
+
+    let unbundled = CoopBundled::find_unbundled(&pool, "47741", cat_id).await.unwrap();
+    assert!(unbundled.iter().any(|cl| cl.id == cl_ids[0]));
+    assert!(unbundled.iter().any(|cl| cl.id == cl_ids[1]));
+    assert!(!unbundled.iter().any(|cl| cl.id == cl_ids[2] || cl.profile_number == "N/A"));
+
+    let user_auth = test_auth_header(&pool, "seed1", 0).await;
+    let other_auth = test_auth_header(&pool, "auth_test_other", 0).await;
+    let bundle = |cl_id1, cl_id2, auth: &str| {
+        test::TestRequest::post()
+            .uri("/coop/bundle")
+            .insert_header(("Authorization", auth.to_string()))
+            .set_json(CoopBundleParams { cl_id1, cl_id2, p1_is_host: Some(true) })
+            .to_request()
+    };
+    let res = test::call_service(
+        &app,
+        test::TestRequest::post()
+            .uri("/coop/bundle")
+            .set_json(CoopBundleParams { cl_id1: cl_ids[0], cl_id2: cl_ids[1], p1_is_host: None })
+            .to_request(),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    // Only a player in the run (or an admin) can bundle it.
+    let res = test::call_service(&app, bundle(cl_ids[0], cl_ids[1], &other_auth)).await;
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    let res = test::call_service(&app, bundle(cl_ids[0], cl_ids[2], &user_auth)).await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    let res = test::call_service(&app, bundle(cl_ids[0], -1, &user_auth)).await;
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    let coop_id: i64 = test::call_and_read_body_json(&app, bundle(cl_ids[0], cl_ids[1], &user_auth)).await;
+    for id in &cl_ids[..2] {
+        let cl = Changelog::get_changelog(&pool, *id).await.unwrap().unwrap();
+        assert_eq!(cl.coop_id, Some(coop_id));
+    }
+    let temp_bundle_left: Option<i64> = sqlx::query_scalar("SELECT id FROM coop_bundled WHERE id = $1")
+        .bind(temp_bundle_id)
+        .fetch_optional(&pool)
+        .await
+        .unwrap();
+    assert!(temp_bundle_left.is_none());
+    let unbundled = CoopBundled::find_unbundled(&pool, "47741", cat_id).await.unwrap();
+    assert!(!unbundled.iter().any(|cl| cl_ids[..2].contains(&cl.id)));
+    // Already bundled entries cannot be bundled again.
+    let res = test::call_service(&app, bundle(cl_ids[1], cl_ids[0], &user_auth)).await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    // Only one of two concurrent bundles of the same entries goes through.
+    let admin_auth = test_auth_header(&pool, "auth_test_admin", 2).await;
+    let (first, second) = futures::join!(
+        test::call_service(&app, bundle(cl_ids[3], cl_ids[4], &admin_auth)),
+        test::call_service(&app, bundle(cl_ids[4], cl_ids[3], &admin_auth)),
+    );
+    let mut statuses = vec![first.status(), second.status()];
+    statuses.sort();
+    let concurrent_bundles: Vec<i64> = sqlx::query_scalar("SELECT id FROM coop_bundled WHERE cl_id1 = ANY($1)")
+        .bind(&cl_ids[3..])
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+
+    sqlx::query("UPDATE changelog SET coop_id = NULL WHERE id = ANY($1)")
+        .bind(&cl_ids)
+        .execute(&pool)
+        .await
+        .unwrap();
+    sqlx::query("DELETE FROM coop_bundled WHERE id = $1").bind(coop_id).execute(&pool).await.unwrap();
+    sqlx::query("DELETE FROM coop_bundled WHERE id = ANY($1)").bind(&concurrent_bundles).execute(&pool).await.unwrap();
+    assert_eq!(statuses, vec![StatusCode::OK, StatusCode::BAD_REQUEST]);
+    assert_eq!(concurrent_bundles.len(), 1);
+    for id in cl_ids.into_iter().chain(temp_cl) {
+        Changelog::delete_changelog(&pool, id).await.unwrap();
+    }
+}
+
+#[actix_web::test]
+async fn test_db_demos_delete_by_player() {
+    use crate::api::v1::handlers::demos::demos_delete_by_player;
+    use crate::models::changelog::{Changelog, ChangelogInsert};
+    use crate::models::demos::{DemoBulkDeletion, DemoInsert, Demos};
+    use actix_web::{http::StatusCode, test, web, App};
+    let (config, pool) = get_config().await.expect("Error getting config and DB pool");
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::from(rejected_key_storage()))
+            .app_data(web::Data::new(config))
+            .app_data(web::Data::new(pool.clone()))
+            .service(demos_delete_by_player),
+    )
+    .await;
+    let cl = ChangelogInsert {
+        profile_number: "seed4".to_string(),
+        score: 90201,
+        map_id: "47763".to_string(),
+        category_id: 19,
+        ..Default::default()
+    };
+    let cl_id = Changelog::insert_changelog(&pool, cl).await.unwrap();
+    let mut demo_ids = Vec::new();
+    for file_id in ["test_by_player_1.dem", "test_by_player_2.dem", "test_by_player_3.dem"] {
+        let demo = DemoInsert { file_id: file_id.to_string(), cl_id, ..Default::default() };
+        demo_ids.push(Demos::insert_demo(&pool, demo).await.unwrap());
+    }
+    Changelog::update_demo_id_in_changelog(&pool, cl_id, demo_ids[2]).await.unwrap();
+    let found = Demos::get_demos_by_profile(&pool, "seed4").await.unwrap();
+    assert_eq!(found.iter().map(|d| d.id).collect::<Vec<i64>>(), demo_ids.iter().rev().copied().collect::<Vec<i64>>());
+
+    let delete = |auth: String| {
+        test::TestRequest::delete()
+            .uri("/demos/by_player?profile_number=seed4")
+            .insert_header(("Authorization", auth))
+            .to_request()
+    };
+    let user_auth = test_auth_header(&pool, "seed4", 0).await;
+    assert_eq!(test::call_service(&app, delete(user_auth)).await.status(), StatusCode::FORBIDDEN);
+    // Backblaze rejecting the key fails every demo, but still returns a summary and keeps the entries.
+    let admin_auth = test_auth_header(&pool, "auth_test_admin", 2).await;
+    let summary: DemoBulkDeletion = test::call_and_read_body_json(&app, delete(admin_auth)).await;
+    assert!(summary.deleted.is_empty());
+    assert_eq!(summary.failed.len(), 3);
+    assert_eq!(Demos::get_demos_by_profile(&pool, "seed4").await.unwrap().len(), 3);
+
+    // Removing the changelog's current demo falls back to the newest remaining one.
+    assert_eq!(Demos::delete_demos(&pool, &demo_ids[1..]).await.unwrap(), 2);
+    let cl = Changelog::get_changelog(&pool, cl_id).await.unwrap().unwrap();
+    assert_eq!(cl.demo_id, Some(demo_ids[0]));
+    assert_eq!(Demos::delete_demos(&pool, &demo_ids[..1]).await.unwrap(), 1);
+    let cl = Changelog::get_changelog(&pool, cl_id).await.unwrap().unwrap();
+    assert_eq!(cl.demo_id, None);
+    Changelog::delete_changelog(&pool, cl_id).await.unwrap();
+}
+
+#[actix_web::test]
+async fn test_db_map_summary() {
+    use crate::api::v1::handlers::maps::map_summary;
+    use crate::models::changelog::{Changelog, ChangelogInsert};
+    use crate::models::maps::{MapSummary, Maps};
+    use crate::tools::cache::SP_PREVIEWS;
+    use crate::tools::helpers::get_default_cat_ids;
+    use actix_web::{http::StatusCode, test, web, App};
+    let (_, pool) = get_config().await.expect("Error getting config and DB pool");
+    let mut cache = test_cache_state();
+    cache.default_cat_ids = get_default_cat_ids(&pool).await;
+    let cache = web::Data::new(cache);
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(pool.clone()))
+            .app_data(cache.clone())
+            .service(map_summary),
+    )
+    .await;
+    let before = Maps::get_map_summary(&pool, "47763", 19).await.unwrap();
+    let mut cl_ids = Vec::new();
+    // A banned score and an unverified score are faster, but should not count.
+    for (profile_number, score, banned, verified) in [("seed5", 2, false, true), ("seed6", 1, true, true), ("seed7", 1, false, false)] {
+        let cl = ChangelogInsert {
+            profile_number: profile_number.to_string(),
+            score,
+            map_id: "47763".to_string(),
+            category_id: 19,
+            banned,
+            verified: Some(verified),
+            ..Default::default()
+        };
+        cl_ids.push(Changelog::insert_changelog(&pool, cl).await.unwrap());
+    }
+    let req = test::TestRequest::get().uri("/maps/47763/summary?cat_id=19").to_request();
+    let summary: MapSummary = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(summary.wr_score, Some(2));
+    assert_eq!(summary.wr_profile_number, Some("seed5".to_string()));
+    assert!(summary.wr_user_name.is_some());
+    // seed5 may already have had a valid time on the map.
+    assert!((before.player_count..=before.player_count + 1).contains(&summary.player_count));
+
+    // Served from the cache until the previews are invalidated.
+    sqlx::query("UPDATE changelog SET verified = True WHERE id = $1").bind(cl_ids[2]).execute(&pool).await.unwrap();
+    let req = test::TestRequest::get().uri("/maps/47763/summary?cat_id=19").to_request();
+    let cached: MapSummary = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(cached, summary);
+    cache.update_current_state(SP_PREVIEWS, false).await;
+    let req = test::TestRequest::get().uri("/maps/47763/summary?cat_id=19").to_request();
+    let refreshed: MapSummary = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(refreshed.wr_profile_number, Some("seed7".to_string()));
+    assert_eq!(refreshed.wr_score, Some(1));
+
+    let req = test::TestRequest::get().uri("/maps/1/summary").to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::NOT_FOUND);
+    // Categories from other maps are not looked up or cached.
+    let req = test::TestRequest::get().uri("/maps/47763/summary?cat_id=999999").to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::NOT_FOUND);
+    assert!(cache.get_map_summary("47763", 999999).await.is_none());
+    for id in cl_ids {
+        Changelog::delete_changelog(&pool, id).await.unwrap();
+    }
+}
+
+#[actix_web::test]
+async fn test_db_map_categories() {
+    use crate::api::v1::handlers::maps::map_categories;
+    use crate::models::categories::Category;
+    use crate::tools::helpers::get_default_cat_ids;
+    use actix_web::{http::StatusCode, test, web, App};
+    let (_, pool) = get_config().await.expect("Error getting config and DB pool");
+    let mut cache = test_cache_state();
+    cache.default_cat_ids = get_default_cat_ids(&pool).await;
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(pool.clone()))
+            .app_data(web::Data::new(cache))
+            .service(map_categories),
+    )
+    .await;
+    let req = test::TestRequest::get().uri("/maps/47763/categories").to_request();
+    let categories: Vec<Category> = test::call_and_read_body_json(&app, req).await;
+    assert!(!categories.is_empty());
+    let defaults: Vec<&Category> = categories.iter().filter(|c| c.is_default).collect();
+    assert_eq!(defaults.len(), 1);
+    assert_eq!(defaults[0].id, 19);
+
+    let req = test::TestRequest::get().uri("/maps/1/categories").to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn test_db_demos_changelog_idempotent() {
+    use crate::api::v1::handlers::demos::{demos_changelog, DEMO_SHA1_HEADER};
+    use crate::models::changelog::Changelog;
+    use crate::models::demos::Demos;
+    use crate::models::users::Users;
+    use crate::tests::demo_tests::demo_header;
+    use crate::tools::backblaze::content_sha1;
+    use crate::tools::config::StorageBackend;
+    use crate::tools::helpers::get_default_cat_ids;
+    use crate::tools::metrics::Metrics;
+    use crate::tools::storage::demo_storage;
+    use actix_web::{http::StatusCode, test, web, App};
+    let (mut config, pool) = get_config().await.expect("Error getting config and DB pool");
+    let dir = std::env::temp_dir().join(format!("p2boards_demos_idempotent_{}", std::process::id()));
+    config.storage.backend = StorageBackend::Local;
+    config.storage.local_dir = dir.to_string_lossy().to_string();
+    let mut cache = test_cache_state();
+    cache.default_cat_ids = get_default_cat_ids(&pool).await;
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::from(demo_storage(&config)))
+            .app_data(web::Data::new(config))
+            .app_data(web::Data::new(pool.clone()))
+            .app_data(web::Data::new(cache))
+            .app_data(web::Data::new(Metrics::new().unwrap()))
+            .app_data(web::Data::new(RateLimiter::new(RateLimitConfig::default())))
+            .service(demos_changelog),
+    )
+    .await;
+    let profile_number = "demo_idempotent_test".to_string();
+    Users::insert_new_users(
+        &pool,
+        Users {
+            profile_number: profile_number.clone(),
+            steam_name: Some("demo_idempotent_test".to_string()),
+            avatar: Some(String::new()),
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    let auth = test_auth_header(&pool, &profile_number, 0).await;
+    let demo = demo_header("sp_a2_laser_vs_turret");
+    let sha1 = content_sha1(&demo);
+    let boundary = "demoboundary";
+    let mut body = format!(
+        "--{boundary}\r\nContent-Disposition: form-data; name=\"demo\"; filename=\"idempotent_test.dem\"\r\n\r\n"
+    )
+    .into_bytes();
+    body.extend(&demo);
+    body.extend(format!("\r\n--{boundary}--\r\n").into_bytes());
+    let upload = |score: i32, sha1: &str| {
+        test::TestRequest::post()
+            .uri(&format!(
+                "/demos/changelog?timestamp=2022-01-01%2000:00:00&profile_number={profile_number}&score={score}&map_id=47763&category_id=19"
+            ))
+            .insert_header(("Content-Type", format!("multipart/form-data; boundary={boundary}")))
+            .insert_header(("Authorization", auth.clone()))
+            .insert_header((DEMO_SHA1_HEADER, sha1.to_string()))
+            .set_payload(body.clone())
+            .to_request()
+    };
+    // The client retries the same upload, e.g. because it never saw the first response.
+    let first: (i64, i64) = test::call_and_read_body_json(&app, upload(900800, &sha1)).await;
+    let retry: (i64, i64) = test::call_and_read_body_json(&app, upload(900800, &sha1.to_uppercase())).await;
+    assert_eq!(first, retry);
+    let cl_count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM changelog WHERE profile_number = $1")
+        .bind(&profile_number)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(cl_count, 1);
+    // Other tests upload the same demo, so only this player's demos are counted.
+    let demo_count: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM demos INNER JOIN changelog ON (demos.cl_id = changelog.id) WHERE changelog.profile_number = $1",
+    )
+    .bind(&profile_number)
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+    assert_eq!(demo_count, 1);
+    // A SHA1 that does not match the uploaded demo is rejected.
+    let res = test::call_service(&app, upload(900700, &"0".repeat(40))).await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    assert!(!std::path::Path::new("./demos/idempotent_test.dem").exists());
+
+    let (cl_id, demo_id) = first;
+    Changelog::delete_references_to_demo(&pool, demo_id).await.unwrap();
+    Demos::delete_demo(&pool, demo_id).await.unwrap();
+    Changelog::delete_changelog(&pool, cl_id).await.unwrap();
+    Users::delete_user(&pool, profile_number).await.unwrap();
+    let _ = tokio::fs::remove_dir_all(&dir).await;
+}
+
+#[actix_web::test]
+async fn test_db_demos_changelog_commits() {
+    use crate::api::v1::handlers::demos::demos_changelog;
+    use crate::models::changelog::Changelog;
+    use crate::models::demos::Demos;
+    use crate::models::users::Users;
+    use crate::tests::demo_tests::demo_header;
+    use crate::tools::config::StorageBackend;
+    use crate::tools::helpers::get_default_cat_ids;
+    use crate::tools::metrics::Metrics;
+    use crate::tools::storage::demo_storage;
+    use actix_web::{http::StatusCode, test, web, App};
+    let (mut config, pool) = get_config().await.expect("Error getting config and DB pool");
+    let dir = std::env::temp_dir().join(format!("p2boards_demos_commit_{}", std::process::id()));
+    config.storage.backend = StorageBackend::Local;
+    config.storage.local_dir = dir.to_string_lossy().to_string();
+    let mut cache = test_cache_state();
+    cache.default_cat_ids = get_default_cat_ids(&pool).await;
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::from(demo_storage(&config)))
+            .app_data(web::Data::new(config))
+            .app_data(web::Data::new(pool.clone()))
+            .app_data(web::Data::new(cache))
+            .app_data(web::Data::new(Metrics::new().unwrap()))
+            .app_data(web::Data::new(RateLimiter::new(RateLimitConfig::default())))
+            .service(demos_changelog),
+    )
+    .await;
+    let profile_number = "demo_commit_test".to_string();
+    Users::insert_new_users(
+        &pool,
+        Users {
+            profile_number: profile_number.clone(),
+            steam_name: Some("demo_commit_test".to_string()),
+            avatar: Some(String::new()),
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    let boundary = "demoboundary";
+    let mut body = format!(
+        "--{boundary}\r\nContent-Disposition: form-data; name=\"demo\"; filename=\"commit_test.dem\"\r\n\r\n"
+    )
+    .into_bytes();
+    body.extend(demo_header("sp_a2_laser_vs_turret"));
+    body.extend(format!("\r\n--{boundary}--\r\n").into_bytes());
+    let upload = |auth: Option<String>| {
+        let mut req = test::TestRequest::post()
+            .uri(&format!(
+                "/demos/changelog?timestamp=2022-01-01%2000:00:00&profile_number={profile_number}&score=900700&map_id=47763&category_id=19"
+            ))
+            .insert_header(("Content-Type", format!("multipart/form-data; boundary={boundary}")));
+        if let Some(auth) = auth {
+            req = req.insert_header(("Authorization", auth));
+        }
+        req.set_payload(body.clone()).to_request()
+    };
+    assert_eq!(test::call_service(&app, upload(None)).await.status(), StatusCode::UNAUTHORIZED);
+    // Players can only submit their own scores.
+    let other_auth = test_auth_header(&pool, "auth_test_other", 0).await;
+    assert_eq!(test::call_service(&app, upload(Some(other_auth))).await.status(), StatusCode::FORBIDDEN);
+    let auth = test_auth_header(&pool, &profile_number, 0).await;
+    let (cl_id, demo_id): (i64, i64) = test::call_and_read_body_json(&app, upload(Some(auth))).await;
+
+    // Both entries are committed, and the demo was uploaded to storage.
+    let changelog = Changelog::get_changelog(&pool, cl_id).await.unwrap().unwrap();
+    assert_eq!(changelog.demo_id, Some(demo_id));
+    let demo = Demos::get_demo(&pool, demo_id).await.unwrap().unwrap();
+    assert_eq!(demo.cl_id, cl_id);
+    assert!(dir.join(&demo.file_id).exists());
+
+    Changelog::delete_references_to_demo(&pool, demo_id).await.unwrap();
+    Demos::delete_demo(&pool, demo_id).await.unwrap();
+    Changelog::delete_changelog(&pool, cl_id).await.unwrap();
+    Users::delete_user(&pool, profile_number).await.unwrap();
+    let _ = tokio::fs::remove_dir_all(&dir).await;
+}
+
+#[actix_web::test]
+async fn test_db_demos_changelog_dry_run() {
+    use crate::api::v1::handlers::demos::demos_changelog;
+    use crate::models::demos::DemoSubmissionPreview;
+    use crate::tests::demo_tests::demo_header;
+    use crate::tools::backblaze::content_sha1;
+    use crate::tools::helpers::get_default_cat_ids;
+    use crate::tools::metrics::Metrics;
+    use crate::tools::storage::demo_storage;
+    use actix_web::{http::StatusCode, test, web, App};
+    let (config, pool) = get_config().await.expect("Error getting config and DB pool");
+    let mut cache = test_cache_state();
+    cache.default_cat_ids = get_default_cat_ids(&pool).await;
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::from(demo_storage(&config)))
+            .app_data(web::Data::new(config))
+            .app_data(web::Data::new(pool.clone()))
+            .app_data(web::Data::new(cache))
+            .app_data(web::Data::new(Metrics::new().unwrap()))
+            .app_data(web::Data::new(RateLimiter::new(RateLimitConfig::default())))
+            .service(demos_changelog),
+    )
+    .await;
+    let auth = test_auth_header(&pool, "seed8", 0).await;
+    let boundary = "demoboundary";
+    let upload = |map_name: &str, timestamp: &str| {
+        let mut body = format!(
+            "--{boundary}\r\nContent-Disposition: form-data; name=\"demo\"; filename=\"dry_run_test.dem\"\r\n\r\n"
+        )
+        .into_bytes();
+        body.extend(demo_header(map_name));
+        body.extend(format!("\r\n--{boundary}--\r\n").into_bytes());
+        test::TestRequest::post()
+            .uri(&format!(
+                "/demos/changelog?timestamp={timestamp}&profile_number=seed8&score=1004&map_id=47763&category_id=19&dry_run=true"
+            ))
+            .insert_header(("Content-Type", format!("multipart/form-data; boundary={boundary}")))
+            .insert_header(("Authorization", auth.clone()))
+            .set_payload(body)
+            .to_request()
+    };
+    let count_changelog = || {
+        sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM changelog WHERE profile_number = 'seed8' AND map_id = '47763'")
+            .fetch_one(&pool)
+    };
+    let cl_count = count_changelog().await.unwrap();
+    let preview: DemoSubmissionPreview = test::call_and_read_body_json(&app, upload("sp_a2_laser_vs_turret", "2022-01-01%2000:00:00")).await;
+    assert_eq!(preview.changelog.score, 1004);
+    assert_eq!(preview.changelog.category_id, 19);
+    assert_eq!(preview.metadata.map_name, "sp_a2_laser_vs_turret");
+    assert_eq!(preview.demo.sha1, Some(content_sha1(&demo_header("sp_a2_laser_vs_turret"))));
+    assert_eq!(count_changelog().await.unwrap(), cl_count);
+    assert!(!std::path::Path::new("./demos/dry_run_test.dem").exists());
+    // The demo is still checked against the submitted map.
+    let res = test::call_service(&app, upload("sp_a2_pull_the_rug", "2022-01-01%2000:00:00")).await;
+    assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(!std::path::Path::new("./demos/dry_run_test.dem").exists());
+    // Invalid timestamps are rejected with the reason.
+    let res = test::call_service(&app, upload("sp_a2_laser_vs_turret", "2022-01-01%2024:60:60")).await;
+    assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let body = String::from_utf8(test::read_body(res).await.to_vec()).unwrap();
+    assert!(body.contains("Invalid timestamp"));
+}
+
+#[actix_web::test]
+async fn test_db_demos_local_storage() {
+    use crate::api::v1::handlers::demos::{demos_delete, demos_download, demos_verify, generate_file_name};
+    use crate::models::changelog::{Changelog, ChangelogInsert};
+    use crate::models::demos::{DemoInsert, Demos};
+    use crate::tests::demo_tests::demo_header;
+    use crate::tools::backblaze::content_sha1;
+    use crate::tools::config::StorageBackend;
+    use crate::tools::storage::demo_storage;
+    use actix_web::{http::StatusCode, test, web, App};
+    let (mut config, pool) = get_config().await.expect("Error getting config and DB pool");
+    let dir = std::env::temp_dir().join(format!("p2boards_local_demos_{}", std::process::id()));
+    config.storage.backend = StorageBackend::Local;
+    config.storage.local_dir = dir.join("stored").to_string_lossy().to_string();
+    // BackBlaze is never contacted when storing locally.
+    config.backblaze.keyid = "not_a_real_keyid".to_string();
+    let storage = demo_storage(&config);
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::from(storage.clone()))
+            .app_data(web::Data::new(pool.clone()))
+            .service(demos_download)
+            .service(demos_verify)
+            .service(demos_delete),
+    )
+    .await;
+    let cl = ChangelogInsert {
+        profile_number: "seed9".to_string(),
+        score: 900950,
+        map_id: "47763".to_string(),
+        category_id: 19,
+        ..Default::default()
+    };
+    let cl_id = Changelog::insert_changelog(&pool, cl.clone()).await.unwrap();
+    let demo = demo_header("sp_a2_laser_vs_turret");
+    tokio::fs::create_dir_all(&dir).await.unwrap();
+    let upload = dir.join("upload.dem").to_string_lossy().to_string();
+    tokio::fs::write(&upload, &demo).await.unwrap();
+    let file_name = generate_file_name(&pool, &cl.map_id, cl.score, &cl.profile_number).await.unwrap();
+    let file_id = storage.upload(&upload, &file_name).await.unwrap();
+    let demo_insert = DemoInsert {
+        file_id: file_id.clone(),
+        cl_id,
+        sha1: Some(content_sha1(&demo)),
+        bucket_id: storage.bucket(),
+        ..Default::default()
+    };
+    let demo_id = Demos::insert_demo(&pool, demo_insert).await.unwrap();
+    sqlx::query("UPDATE changelog SET demo_id = $1 WHERE id = $2").bind(demo_id).bind(cl_id).execute(&pool).await.unwrap();
+
+    let req = test::TestRequest::get().uri(&format!("/demos/download?demo_id={demo_id}")).to_request();
+    assert_eq!(test::call_and_read_body(&app, req).await.to_vec(), demo);
+    let req = test::TestRequest::get().uri(&format!("/demos/verify?demo_id={demo_id}")).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+    assert!(body.contains(&content_sha1(&demo)));
+    // A stored file that no longer matches its SHA1 is rejected before any of it is sent.
+    tokio::fs::write(dir.join("stored").join(&file_id), b"HL2DEMO\0corrupted").await.unwrap();
+    let req = test::TestRequest::get().uri(&format!("/demos/download?demo_id={demo_id}")).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::BAD_GATEWAY);
+
+    let delete = |auth: String| {
+        test::TestRequest::delete()
+            .uri(&format!("/demos?demo_id={demo_id}"))
+            .insert_header(("Authorization", auth))
+            .to_request()
+    };
+    let other_auth = test_auth_header(&pool, "auth_test_other", 0).await;
+    assert_eq!(test::call_service(&app, delete(other_auth)).await.status(), StatusCode::FORBIDDEN);
+    assert!(dir.join("stored").join(&file_id).exists());
+    let owner_auth = test_auth_header(&pool, "seed9", 0).await;
+    assert_eq!(test::call_service(&app, delete(owner_auth)).await.status(), StatusCode::OK);
+    assert!(!dir.join("stored").join(&file_id).exists());
+    assert!(Demos::get_demo(&pool, demo_id).await.unwrap().is_none());
+    Changelog::delete_changelog(&pool, cl_id).await.unwrap();
+    tokio::fs::remove_dir_all(dir).await.unwrap();
+}
+
+/// Storage that has lost every file, deletes fail the way BackBlaze reports a missing file.
+struct MissingFileStorage;
+
+impl crate::tools::storage::DemoStorage for MissingFileStorage {
+    fn upload<'a>(&'a self, _path: &'a str, _file_name: &'a str) -> futures::future::BoxFuture<'a, anyhow::Result<String>> {
+        Box::pin(async { anyhow::bail!("Uploads are not supported") })
+    }
+    fn download<'a>(
+        &'a self,
+        _file: crate::tools::storage::StoredFile<'a>,
+    ) -> futures::future::BoxFuture<'a, anyhow::Result<Vec<u8>>> {
+        Box::pin(async { anyhow::bail!("Downloads are not supported") })
+    }
+    fn delete<'a>(
+        &'a self,
+        file: crate::tools::storage::StoredFile<'a>,
+        _file_name: &'a str,
+    ) -> futures::future::BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(async move {
+            Err(anyhow::Error::new(crate::tools::backblaze::B2Error {
+                status: 400,
+                code: "file_not_present".to_string(),
+                message: format!("File not present: {}", file.file_id),
+            }))
+        })
+    }
+    fn bucket(&self) -> Option<String> {
+        None
+    }
+}
+
+#[actix_web::test]
+async fn test_db_demos_delete_missing_file() {
+    use crate::api::v1::handlers::demos::{demos_delete, demos_delete_by_player};
+    use crate::models::changelog::{Changelog, ChangelogInsert};
+    use crate::models::demos::{DemoBulkDeletion, DemoInsert, Demos};
+    use crate::tools::storage::DemoStorage;
+    use actix_web::{http::StatusCode, test, web, App};
+    use std::sync::Arc;
+    let (_, pool) = get_config().await.expect("Error getting config and DB pool");
+    let storage: Arc<dyn DemoStorage> = Arc::new(MissingFileStorage);
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::from(storage))
+            .app_data(web::Data::new(pool.clone()))
+            .service(demos_delete)
+            .service(demos_delete_by_player),
+    )
+    .await;
+    let cl = ChangelogInsert {
+        profile_number: "seed14".to_string(),
+        score: 900960,
+        map_id: "47763".to_string(),
+        category_id: 19,
+        ..Default::default()
+    };
+    let cl_id = Changelog::insert_changelog(&pool, cl).await.unwrap();
+    let mut demo_ids = Vec::new();
+    for file_id in ["test_missing_1.dem", "test_missing_2.dem", "test_missing_3.dem"] {
+        let demo = DemoInsert { file_id: file_id.to_string(), cl_id, ..Default::default() };
+        demo_ids.push(Demos::insert_demo(&pool, demo).await.unwrap());
+    }
+    Changelog::update_demo_id_in_changelog(&pool, cl_id, demo_ids[2]).await.unwrap();
+
+    // The file is already gone from storage, the entry is removed anyway.
+    let admin_auth = test_auth_header(&pool, "auth_test_admin", 2).await;
+    let req = test::TestRequest::delete()
+        .uri(&format!("/demos?demo_id={}", demo_ids[2]))
+        .insert_header(("Authorization", admin_auth.clone()))
+        .to_request();
+    let status = test::call_service(&app, req).await.status();
+    let removed = Demos::get_demo(&pool, demo_ids[2]).await.unwrap();
+    let cl_demo_id = Changelog::get_changelog(&pool, cl_id).await.unwrap().unwrap().demo_id;
+    let req = test::TestRequest::delete()
+        .uri("/demos/by_player?profile_number=seed14")
+        .insert_header(("Authorization", admin_auth))
+        .to_request();
+    let summary: DemoBulkDeletion = test::call_and_read_body_json(&app, req).await;
+    let remaining = Demos::get_demos_by_profile(&pool, "seed14").await.unwrap();
+    Demos::delete_demos(&pool, &demo_ids).await.unwrap();
+    Changelog::delete_changelog(&pool, cl_id).await.unwrap();
+    assert_eq!(status, StatusCode::OK);
+    assert!(removed.is_none());
+    assert_eq!(cl_demo_id, Some(demo_ids[1]));
+    assert_eq!(summary.deleted.len(), 2);
+    assert!(summary.failed.is_empty());
+    assert!(remaining.is_empty());
+}
+
+#[actix_web::test]
+async fn test_db_cache_rebuild() {
+    use crate::api::v1::handlers::admin::admin_cache_rebuild;
+    use crate::models::admin::CacheRebuilt;
+    use crate::tools::cache::{COOP_PREVIEWS, SP_PREVIEWS};
+    use actix_web::{http::StatusCode, test, web, App};
+    let (_, pool) = get_config().await.expect("Error getting config and DB pool");
+    let mut cache = test_cache_state();
+    cache.default_cat_ids = crate::tools::helpers::get_default_cat_ids(&pool).await;
+    let cache = web::Data::new(cache);
+    cache.update_current_states(&[SP_PREVIEWS, COOP_PREVIEWS], &[false, false]).await;
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(pool.clone()))
+            .app_data(cache.clone())
+            .service(admin_cache_rebuild),
+    )
+    .await;
+    let rebuild = |uri: &str, auth: String| {
+        test::TestRequest::post().uri(uri).insert_header(("Authorization", auth)).to_request()
+    };
+    let user_auth = test_auth_header(&pool, "seed18", 0).await;
+    let admin_auth = test_auth_header(&pool, "auth_test_admin", 2).await;
+    let forbidden = test::call_service(&app, rebuild("/admin/cache/rebuild", user_auth)).await.status();
+    assert_eq!(forbidden, StatusCode::FORBIDDEN);
+    assert!(!cache.get_current_state(SP_PREVIEWS).await);
+    let bad_target = test::call_service(&app, rebuild("/admin/cache/rebuild?target=points", admin_auth.clone())).await;
+    assert_eq!(bad_target.status(), StatusCode::BAD_REQUEST);
+
+    let rebuilt: Vec<CacheRebuilt> =
+        test::call_and_read_body_json(&app, rebuild("/admin/cache/rebuild?target=sp_previews", admin_auth.clone())).await;
+    assert_eq!(rebuilt.iter().map(|r| r.target.as_str()).collect::<Vec<&str>>(), vec![SP_PREVIEWS]);
+    assert!(cache.get_current_state(SP_PREVIEWS).await);
+    assert!(!cache.get_current_state(COOP_PREVIEWS).await);
+
+    let rebuilt: Vec<CacheRebuilt> = test::call_and_read_body_json(&app, rebuild("/admin/cache/rebuild", admin_auth)).await;
+    assert_eq!(rebuilt.iter().map(|r| r.target.as_str()).collect::<Vec<&str>>(), vec![SP_PREVIEWS, COOP_PREVIEWS]);
+    assert!(cache.get_current_state(SP_PREVIEWS).await);
+    assert!(cache.get_current_state(COOP_PREVIEWS).await);
+}
+
+#[actix_web::test]
+async fn test_db_changelog_import() {
+    use crate::api::v1::handlers::admin::admin_changelog_import;
+    use crate::models::admin::{ChangelogImport, FailedRow};
+    use crate::models::changelog::{Changelog, ChangelogInsert};
+    use actix_web::{http::StatusCode, test, web, App};
+    let (_, pool) = get_config().await.expect("Error getting config and DB pool");
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(pool.clone()))
+            .app_data(web::Data::new(test_cache_state()))
+            .service(admin_changelog_import),
+    )
+    .await;
+    let row = |profile_number: &str, score: i32, map_id: &str, category_id: i32| ChangelogInsert {
+        profile_number: profile_number.to_string(),
+        score,
+        map_id: map_id.to_string(),
+        category_id,
+        note: Some("import test".to_string()),
+        ..Default::default()
+    };
+    let rows = vec![
+        row("seed16", 901000, "47763", 19),
+        row("not_a_real_user", 901001, "47763", 19),
+        row("seed16", 901002, "47741", 61),
+        // Category 19 is on Laser vs Turret, not Doors.
+        row("seed16", 901003, "47741", 19),
+        ChangelogInsert { demo_id: Some(-1), ..row("seed16", 901004, "47763", 19) },
+    ];
+    let import = |all_or_nothing: bool, auth: String| {
+        test::TestRequest::post()
+            .uri(&format!("/admin/changelog/import?all_or_nothing={all_or_nothing}"))
+            .insert_header(("Authorization", auth))
+            .set_json(&rows)
+            .to_request()
+    };
+    let user_auth = test_auth_header(&pool, "seed16", 0).await;
+    let admin_auth = test_auth_header(&pool, "auth_test_admin", 2).await;
+    let forbidden = test::call_service(&app, import(false, user_auth)).await.status();
+    let none: ChangelogImport = test::call_and_read_body_json(&app, import(true, admin_auth.clone())).await;
+    let mixed: ChangelogImport = test::call_and_read_body_json(&app, import(false, admin_auth)).await;
+    let mut imported = Vec::new();
+    for row in &mixed.imported {
+        imported.push(Changelog::get_changelog(&pool, row.id).await.unwrap().unwrap());
+        Changelog::delete_changelog(&pool, row.id).await.unwrap();
+    }
+    assert_eq!(forbidden, StatusCode::FORBIDDEN);
+    assert!(none.imported.is_empty());
+    assert_eq!(none.failed, mixed.failed);
+    assert_eq!(mixed.imported.iter().map(|row| row.index).collect::<Vec<usize>>(), vec![0, 2]);
+    assert_eq!(mixed.failed.iter().map(|row| row.index).collect::<Vec<usize>>(), vec![1, 3, 4]);
+    assert_eq!(
+        mixed.failed[0],
+        FailedRow { index: 1, error: "No user with profile_number not_a_real_user".to_string() }
+    );
+    // The ids match the rows they were imported from.
+    assert_eq!(imported[0].score, 901000);
+    assert_eq!(imported[0].map_id, "47763");
+    assert_eq!(imported[1].score, 901002);
+    assert_eq!(imported[1].category_id, 61);
+    assert!(imported.iter().all(|cl| cl.note.as_deref() == Some("import test")));
+    assert!(imported.iter().all(|cl| cl.source == ChangelogSource::Import));
+}
+
+#[actix_web::test]
+async fn test_db_changelog_source_filter() {
+    use crate::models::changelog::{Changelog, ChangelogInsert, ChangelogPage, ChangelogQueryParams};
+    let (_, pool) = get_config().await.expect("Error getting config and DB pool");
+    let mut ids = Vec::new();
+    for (score, source) in [
+        (901100, ChangelogSource::Manual),
+        (901101, ChangelogSource::SteamAuto),
+        (901102, ChangelogSource::Import),
+    ] {
+        let cl = ChangelogInsert {
+            profile_number: "seed17".to_string(),
+            score,
+            map_id: "47763".to_string(),
+            category_id: 19,
+            source,
+            ..Default::default()
+        };
+        ids.push(Changelog::insert_changelog(&pool, cl).await.unwrap());
+    }
+    let page = |source: Option<ChangelogSource>| {
+        let pool = pool.clone();
+        async move {
+            let params = ChangelogQueryParams {
+                profile_number: Some("seed17".to_string()),
+                source,
+                ..Default::default()
+            };
+            let entries = ChangelogPage::get_changelog_page(&pool, params).await.unwrap();
+            entries.into_iter().filter(|cl| cl.score >= 901100).map(|cl| (cl.id, cl.source)).collect::<Vec<_>>()
+        }
+    };
+    let all = page(None).await;
+    let manual = page(Some(ChangelogSource::Manual)).await;
+    let steam = page(Some(ChangelogSource::SteamAuto)).await;
+    for id in &ids {
+        Changelog::delete_changelog(&pool, *id).await.unwrap();
+    }
+    assert_eq!(all.len(), 3);
+    assert_eq!(manual, vec![(ids[0], ChangelogSource::Manual)]);
+    assert_eq!(steam, vec![(ids[1], ChangelogSource::SteamAuto)]);
+}
+
+/// Held by tests that add maps or change their default category, so tests comparing every map's default don't see them.
+static MAP_DEFAULTS: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+#[actix_web::test]
+async fn test_db_set_default_category() {
+    use crate::api::v1::handlers::maps::set_default_category;
+    use crate::models::categories::Categories;
+    use crate::models::maps::{DefaultCategoryUpdate, MapDetail, Maps};
+    use crate::tools::helpers::{calc_coop_aggregate_points, calc_sp_aggregate_points, get_default_cat_ids};
+    use actix_web::{http::StatusCode, test, web, App};
+    let (config, pool) = get_config().await.expect("Error getting config and DB pool");
+    let _lock = MAP_DEFAULTS.lock().await;
+    // A coop map in a game of its own, so other tests never load its default category.
+    let game_id: i32 = sqlx::query_scalar(
+        "INSERT INTO games (id, game_name) SELECT MAX(id) + 2000, 'default category test' FROM games RETURNING id",
+    )
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+    let chapter_id: i32 = sqlx::query_scalar(
+        "INSERT INTO chapters (id, chapter_name, is_multiplayer, game_id) SELECT MAX(id) + 1000, 'default category test', true, $1 FROM chapters RETURNING id",
+    )
+    .bind(game_id)
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+    let map_id = "999901";
+    let cat_ids: Vec<i32> = sqlx::query_scalar(
+        "INSERT INTO categories (name, map_id) VALUES ('default', $1), ('other', $1) RETURNING id",
+    )
+    .bind(map_id)
+    .fetch_all(&pool)
+    .await
+    .unwrap();
+    sqlx::query(
+        "INSERT INTO maps (id, steam_id, lp_id, name, chapter_id, default_cat_id, is_public) SELECT MAX(id) + 1000, $1, $1, 'Default Category Test', $2, $3, true FROM maps",
+    )
+    .bind(map_id)
+    .bind(chapter_id)
+    .bind(cat_ids[0])
+    .execute(&pool)
+    .await
+    .unwrap();
+
+    let mut cache = test_cache_state();
+    cache.default_cat_ids = get_default_cat_ids(&pool).await;
+    let cache = web::Data::new(cache);
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(pool.clone()))
+            .app_data(cache.clone())
+            .service(set_default_category),
+    )
+    .await;
+    let put = |map_id: &str, cat_id: i32, auth: String| {
+        test::TestRequest::put()
+            .uri(&format!("/maps/{map_id}/default_category"))
+            .insert_header(("Authorization", auth))
+            .set_json(DefaultCategoryUpdate { cat_id })
+            .to_request()
+    };
+    let user_auth = test_auth_header(&pool, "seed15", 0).await;
+    let admin_auth = test_auth_header(&pool, "auth_test_admin", 2).await;
+    let forbidden = test::call_service(&app, put(map_id, cat_ids[1], user_auth)).await.status();
+    let missing_map = test::call_service(&app, put("999902", cat_ids[1], admin_auth.clone())).await.status();
+    // Category 19 is on Laser vs Turret, not this map.
+    let wrong_category = test::call_service(&app, put(map_id, 19, admin_auth.clone())).await.status();
+    let unchanged = Maps::get_default_cat(&pool, map_id.to_string()).await.unwrap();
+    let detail: MapDetail = test::call_and_read_body_json(&app, put(map_id, cat_ids[1], admin_auth)).await;
+    let stored = Maps::get_default_cat(&pool, map_id.to_string()).await.unwrap();
+    let categories = Categories::get_for_map(&pool, map_id).await.unwrap();
+    // Base game ranks and points skip maps from other games.
+    let base_cat_ids = cache.default_cat_ids.for_game(1);
+    let sp_points = calc_sp_aggregate_points(&pool, &config, &base_cat_ids).await;
+    let coop_points = calc_coop_aggregate_points(&pool, &config, &base_cat_ids).await;
+
+    sqlx::query("DELETE FROM maps WHERE steam_id = $1").bind(map_id).execute(&pool).await.unwrap();
+    sqlx::query("DELETE FROM categories WHERE map_id = $1").bind(map_id).execute(&pool).await.unwrap();
+    sqlx::query("DELETE FROM chapters WHERE id = $1").bind(chapter_id).execute(&pool).await.unwrap();
+    sqlx::query("DELETE FROM games WHERE id = $1").bind(game_id).execute(&pool).await.unwrap();
+    assert_eq!(forbidden, StatusCode::FORBIDDEN);
+    assert_eq!(missing_map, StatusCode::NOT_FOUND);
+    assert_eq!(wrong_category, StatusCode::BAD_REQUEST);
+    assert!(sp_points.is_ok());
+    assert!(coop_points.is_ok());
+    assert_eq!(unchanged, Some(cat_ids[0]));
+    assert_eq!(detail.default_cat_id, cat_ids[1]);
+    assert_eq!(detail.game_id, game_id);
+    assert_eq!(stored, Some(cat_ids[1]));
+    assert!(categories.iter().any(|c| c.id == cat_ids[1] && c.is_default));
+    // The cache is updated without reloading, only for the map's game.
+    assert_eq!(cache.default_cat_ids.get(map_id, game_id), Some(cat_ids[1]));
+    assert_eq!(cache.default_cat_ids.get(map_id, 1), None);
+}
+
+#[actix_web::test]
+async fn test_db_sp_wr_history() {
+    use crate::api::v1::handlers::sp::sp_wr_history;
+    use crate::models::changelog::{Changelog, ChangelogInsert, WrHistory};
+    use actix_web::{test, web, App};
+    use chrono::NaiveDate;
+    let (_, pool) = get_config().await.expect("Error getting config and DB pool");
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(pool.clone()))
+            .app_data(web::Data::new(test_cache_state()))
+            .service(sp_wr_history),
+    )
+    .await;
+    // A fresh category, so existing scores on the map don't affect the progression.
+    let cat_id: i32 = sqlx::query_scalar("INSERT INTO categories (name, map_id) VALUES ('wr history test', '47763') RETURNING id")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    let mut cl_ids = Vec::new();
+    let entries = [
+        ("seed1", 1000, false, true),
+        // Ties the record.
+        ("seed2", 1000, false, true),
+        ("seed3", 990, true, true),
+        ("seed4", 980, false, false),
+        ("seed5", 995, false, true),
+        ("seed6", 997, false, true),
+    ];
+    for (day, (profile_number, score, banned, verified)) in entries.into_iter().enumerate() {
+        let cl = ChangelogInsert {
+            timestamp: NaiveDate::from_ymd_opt(2022, 1, day as u32 + 1).and_then(|d| d.and_hms_opt(0, 0, 0)),
+            profile_number: profile_number.to_string(),
+            score,
+            map_id: "47763".to_string(),
+            category_id: cat_id,
+            banned,
+            verified: Some(verified),
+            ..Default::default()
+        };
+        cl_ids.push(Changelog::insert_changelog(&pool, cl).await.unwrap());
+    }
+    let req = test::TestRequest::get()
+        .uri(&format!("/map/sp/47763/wr_history?cat_id={cat_id}"))
+        .to_request();
+    let history: Vec<WrHistory> = test::call_and_read_body_json(&app, req).await;
+    for id in cl_ids {
+        Changelog::delete_changelog(&pool, id).await.unwrap();
+    }
+    sqlx::query("DELETE FROM categories WHERE id = $1").bind(cat_id).execute(&pool).await.unwrap();
+    let records: Vec<(&str, i32, Option<i32>)> = history
+        .iter()
+        .map(|wr| (wr.profile_number.as_str(), wr.score, wr.improvement))
+        .collect();
+    assert_eq!(records, vec![("seed1", 1000, None), ("seed5", 995, Some(5))]);
+    assert!(history[0].user_name.is_some());
+}
+
+#[actix_web::test]
+async fn test_db_insert_uploaded_demo_rollback() {
+    use crate::api::v1::handlers::demos::insert_uploaded_demo;
+    use crate::models::changelog::ChangelogInsert;
+    use crate::models::demos::DemoInsert;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    let (_, pool) = get_config().await.expect("Error getting config and DB pool");
+    let count_changelog = || {
+        sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM changelog WHERE profile_number = 'seed9' AND map_id = '47763' AND score = 1")
+            .fetch_one(&pool)
+    };
+    let cl = ChangelogInsert {
+        profile_number: "seed9".to_string(),
+        score: 1,
+        map_id: "47763".to_string(),
+        category_id: 19,
+        ..Default::default()
+    };
+    // partner_name is limited to 50 characters, so the demo insert fails after the changelog entry was inserted.
+    let demo = DemoInsert {
+        file_id: "rollback_test.dem".to_string(),
+        partner_name: Some("x".repeat(51)),
+        ..Default::default()
+    };
+    let cleaned_up = AtomicBool::new(false);
+    let res = insert_uploaded_demo(&pool, cl, demo, true, || async {
+        cleaned_up.store(true, Ordering::SeqCst);
+        Ok(())
+    })
+    .await;
+    assert!(res.is_err());
+    assert!(cleaned_up.load(Ordering::SeqCst));
+    assert_eq!(count_changelog().await.unwrap(), 0);
+}
+
+#[actix_web::test]
+async fn test_db_demo_file_name_round_trip() {
+    use crate::api::v1::handlers::demos::generate_file_name;
+    use crate::models::changelog::{Changelog, ChangelogInsert};
+    let (_, pool) = get_config().await.expect("Error getting config and DB pool");
+    let cl = ChangelogInsert {
+        profile_number: "seed12".to_string(),
+        score: 1234,
+        map_id: "47763".to_string(),
+        category_id: 19,
+        ..Default::default()
+    };
+    // The name a demo is uploaded under, from the submission.
+    let uploaded = generate_file_name(&pool, &cl.map_id, cl.score, &cl.profile_number).await.unwrap();
+    let map_name: String = sqlx::query_scalar("SELECT name FROM maps WHERE steam_id = '47763'")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(uploaded, format!("{}_1234_seed12.dem", map_name.replace(' ', "")));
+    // The name recomputed to delete the demo, from the stored changelog entry.
+    let cl_id = Changelog::insert_changelog(&pool, cl).await.unwrap();
+    let stored = Changelog::get_changelog(&pool, cl_id).await.unwrap().unwrap();
+    let deleted = generate_file_name(&pool, &stored.map_id, stored.score, &stored.profile_number).await.unwrap();
+    assert_eq!(uploaded, deleted);
+    assert!(generate_file_name(&pool, "1", 1234, "seed12").await.is_err());
+    Changelog::delete_changelog(&pool, cl_id).await.unwrap();
+}
+
+#[actix_web::test]
+async fn test_preview_etags() {
+    use crate::tools::cache::{compute_etag, SP_PREVIEWS};
+    use actix_web::{body::to_bytes, http::StatusCode, test};
+    let cache = test_cache_state();
+    let previews = vec![vec!["47763".to_string()]];
+    let req = test::TestRequest::default().to_http_request();
+    // Nothing to compare against until the previews have been sent once.
+    assert!(cache.not_modified(&req, SP_PREVIEWS).await.is_none());
+    let res = cache.preview_response(&req, SP_PREVIEWS, &previews).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let etag = res.headers().get("ETag").unwrap().to_str().unwrap().to_string();
+    let body = to_bytes(res.into_body()).await.unwrap();
+    assert_eq!(etag, compute_etag(&body));
+
+    let conditional = |tag: &str| test::TestRequest::default().insert_header(("If-None-Match", tag)).to_http_request();
+    for tag in [etag.clone(), format!("W/{etag}"), format!("\"other\", {etag}"), "*".to_string()] {
+        let res = cache.not_modified(&conditional(&tag), SP_PREVIEWS).await.unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(res.headers().get("ETag").unwrap().to_str().unwrap(), etag);
+    }
+    assert!(cache.not_modified(&conditional("\"other\""), SP_PREVIEWS).await.is_none());
+    // Invalidating the previews clears the ETag, even if the client has the latest version.
+    cache.update_current_state(SP_PREVIEWS, false).await;
+    assert!(cache.not_modified(&conditional(&etag), SP_PREVIEWS).await.is_none());
+    cache.update_current_state(SP_PREVIEWS, true).await;
+    assert!(cache.not_modified(&conditional(&etag), SP_PREVIEWS).await.is_none());
+    // Rebuilt previews that did not change still match.
+    let res = cache.preview_response(&conditional(&etag), SP_PREVIEWS, &previews).await.unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+    let changed = vec![vec!["47741".to_string()]];
+    let res = cache.preview_response(&conditional(&etag), SP_PREVIEWS, &changed).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_ne!(res.headers().get("ETag").unwrap().to_str().unwrap(), etag);
+}
+
+#[actix_web::test]
+async fn test_db_recent_activity() {
+    use crate::api::v1::handlers::changelog::recent;
+    use crate::models::changelog::{Changelog, ChangelogInsert, RecentActivity};
+    use crate::models::coop::{CoopBundled, CoopBundledInsert};
+    use actix_web::{test, web, App};
+    use chrono::NaiveDateTime;
+    let (_, pool) = get_config().await.expect("Error getting config and DB pool");
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(pool.clone()))
+            .app_data(web::Data::new(test_cache_state()))
+            .service(recent),
+    )
+    .await;
+    let timestamp = |s: &str| Some(NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S").unwrap());
+    let default_cat_ids = crate::tools::helpers::get_default_cat_ids(&pool).await;
+    // Timestamped in the future so they are the newest runs, the unverified run should not be shown.
+    let mut cl_ids = Vec::new();
+    for (profile_number, map_id, time, verified) in [
+        ("seed5", "47763", "2100-01-01 00:00:00", true),
+        ("seed6", "47763", "2100-01-03 00:00:00", false),
+        ("76561198040982247", "47741", "2100-01-02 00:00:00", true),
+        ("seed1", "47741", "2100-01-02 00:00:00", true),
+    ] {
+        let cl = ChangelogInsert {
+            timestamp: timestamp(time),
+            profile_number: profile_number.to_string(),
+            score: 1500,
+            map_id: map_id.to_string(),
+            category_id: default_cat_ids.get(map_id, 1).unwrap(),
+            post_rank: Some(1),
+            youtube_id: Some("-c0gaEXuKZA".to_string()),
+            verified: Some(verified),
+            ..Default::default()
+        };
+        cl_ids.push(Changelog::insert_changelog(&pool, cl).await.unwrap());
+    }
+    let bundle = CoopBundledInsert {
+        p_id1: "76561198040982247".to_string(),
+        p_id2: Some("seed1".to_string()),
+        p1_is_host: None,
+        cl_id1: cl_ids[2],
+        cl_id2: Some(cl_ids[3]),
+    };
+    let bundle_id = CoopBundled::insert_coop_bundled(&pool, bundle).await.unwrap();
+    for id in &cl_ids[2..] {
+        CoopBundled::update_changelog_with_coop_id(&pool, *id, bundle_id).await.unwrap();
+    }
+
+    let req = test::TestRequest::get().uri("/recent?limit=2").to_request();
+    let feed: Vec<RecentActivity> = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(feed.len(), 2);
+    // The coop run is only listed once, with the second player as the partner.
+    assert_eq!(feed[0].id, cl_ids[2]);
+    assert!(feed[0].is_coop);
+    assert_eq!(feed[0].partner_profile_number, Some("seed1".to_string()));
+    assert!(feed[0].partner_name.is_some());
+    assert_eq!(feed[1].id, cl_ids[0]);
+    assert!(!feed[1].is_coop);
+    assert_eq!(feed[1].map_name, "Laser vs Turret");
+    assert_eq!(feed[1].post_rank, Some(1));
+    assert!(feed[1].has_youtube);
+    assert!(!feed[1].has_demo);
+    assert!(feed[1].partner_profile_number.is_none());
+
+    // Served from the cache, so the newly verified run does not show up yet.
+    sqlx::query("UPDATE changelog SET verified = True WHERE id = $1").bind(cl_ids[1]).execute(&pool).await.unwrap();
+    let req = test::TestRequest::get().uri("/recent?limit=2").to_request();
+    let cached: Vec<RecentActivity> = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(cached[0].id, cl_ids[2]);
+    let req = test::TestRequest::get().uri("/recent?limit=3").to_request();
+    let uncached: Vec<RecentActivity> = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(uncached[0].id, cl_ids[1]);
+
+    sqlx::query("UPDATE changelog SET coop_id = NULL WHERE id = ANY($1)")
+        .bind(&cl_ids)
+        .execute(&pool)
+        .await
+        .unwrap();
+    sqlx::query("DELETE FROM coop_bundled WHERE id = $1").bind(bundle_id).execute(&pool).await.unwrap();
+    for id in cl_ids {
+        Changelog::delete_changelog(&pool, id).await.unwrap();
+    }
+}
+
+#[actix_web::test]
+async fn test_db_user_socials() {
+    use crate::api::v1::handlers::users::user_socials;
+    use actix_web::{http::StatusCode, test, web, App};
+    let (_, pool) = get_config().await.expect("Error getting config and DB pool");
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(pool.clone()))
+            .service(user_socials),
+    )
+    .await;
+    let admin = test_auth_header(&pool, "auth_test_admin", 2).await;
+    let user = test_auth_header(&pool, "socials_test_user", 0).await;
+    sqlx::query("UPDATE users SET twitch = 'socials_twitch', youtube = NULL, discord_id = '123456789' WHERE profile_number = 'socials_test_user'")
+        .execute(&pool)
+        .await
+        .unwrap();
+    let socials = |auth: Option<&String>| {
+        let mut req = test::TestRequest::get().uri("/users/socials_test_user/socials");
+        if let Some(auth) = auth {
+            req = req.insert_header(("Authorization", auth.clone()));
+        }
+        req.to_request()
+    };
+    // The discord id is left out for anyone but admins.
+    for auth in [None, Some(&user)] {
+        let body: serde_json::Value = test::call_and_read_body_json(&app, socials(auth)).await;
+        assert_eq!(body["twitch"], "socials_twitch");
+        assert!(body["youtube"].is_null());
+        assert!(body.get("discord_id").is_none());
+    }
+    let body: serde_json::Value = test::call_and_read_body_json(&app, socials(Some(&admin))).await;
+    assert_eq!(body["discord_id"], "123456789");
+
+    let req = test::TestRequest::get().uri("/users/no_such_user/socials").to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn test_db_donators_malformed_amount() {
+    use crate::api::v1::handlers::users::donators;
+    use crate::models::users::UsersDisplayDonation;
+    use actix_web::{test, web, App};
+    let (_, pool) = get_config().await.expect("Error getting config and DB pool");
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(pool.clone()))
+            .service(donators),
+    )
+    .await;
+    for (profile_number, amount) in [("donation_test_1", " 12.50 "), ("donation_test_2", "$20 thanks"), ("donation_test_3", "7")] {
+        test_auth_header(&pool, profile_number, 0).await;
+        sqlx::query("UPDATE users SET donation_amount = $1 WHERE profile_number = $2")
+            .bind(amount)
+            .bind(profile_number)
+            .execute(&pool)
+            .await
+            .unwrap();
+    }
+    // The malformed amount is zeroed out rather than failing the whole request.
+    let req = test::TestRequest::get().uri("/donators").to_request();
+    let list: Vec<UsersDisplayDonation> = test::call_and_read_body_json(&app, req).await;
+    let amount = |profile_number: &str| {
+        list.iter().find(|d| d.profile_number == profile_number).map(|d| d.donation_amount)
+    };
+    assert_eq!(amount("donation_test_1"), Some(12.5));
+    assert_eq!(amount("donation_test_2"), Some(0.0));
+    assert_eq!(amount("donation_test_3"), Some(7.0));
+    assert!(list.windows(2).all(|w| w[0].donation_amount >= w[1].donation_amount));
+    sqlx::query("UPDATE users SET donation_amount = NULL WHERE profile_number LIKE 'donation_test_%'")
+        .execute(&pool)
+        .await
+        .unwrap();
+}
+
+#[actix_web::test]
+async fn test_db_chapter_points() {
+    use crate::api::v1::handlers::users::chapter_points;
+    use crate::models::changelog::{Changelog, ChangelogInsert};
+    use crate::models::coop::{CoopBundled, CoopBundledInsert};
+    use crate::models::points::ChapterPoints;
+    use actix_web::{http::StatusCode, test, web, App};
+    let (_, pool) = get_config().await.expect("Error getting config and DB pool");
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(pool.clone()))
+            .service(chapter_points),
+    )
+    .await;
+    let default_cat_ids = crate::tools::helpers::get_default_cat_ids(&pool).await;
+    let chapter_of = |map_id: &'static str| {
+        let pool = pool.clone();
+        async move {
+            sqlx::query_as::<_, (i32, i64)>(
+                "SELECT maps.chapter_id, (SELECT COUNT(*) FROM maps AS m WHERE m.chapter_id = maps.chapter_id AND m.default_cat_id IS NOT NULL) FROM maps WHERE steam_id = $1",
+            )
+            .bind(map_id)
+            .fetch_one(&pool)
+            .await
+            .unwrap()
+        }
+    };
+    let profile_number = "chapter_points_test";
+    test_auth_header(&pool, profile_number, 0).await;
+    sqlx::query("UPDATE users SET board_name = 'Chapter Points' WHERE profile_number = $1")
+        .bind(profile_number)
+        .execute(&pool)
+        .await
+        .unwrap();
+    // A world record on an SP map and a coop map. The banned time is faster, but should not count.
+    let mut cl_ids = Vec::new();
+    for (map_id, profile_number, score, banned) in [
+        ("47763", profile_number, 0, false),
+        ("47763", "seed2", -1, true),
+        ("47741", profile_number, 0, false),
+        ("47741", "seed1", 0, false),
+    ] {
+        let cl = ChangelogInsert {
+            profile_number: profile_number.to_string(),
+            score,
+            map_id: map_id.to_string(),
+            category_id: default_cat_ids.get(map_id, 1).unwrap(),
+            banned,
+            verified: Some(true),
+            ..Default::default()
+        };
+        cl_ids.push(Changelog::insert_changelog(&pool, cl).await.unwrap());
+    }
+    let bundle = CoopBundledInsert {
+        p_id1: profile_number.to_string(),
+        p_id2: Some("seed1".to_string()),
+        p1_is_host: None,
+        cl_id1: cl_ids[2],
+        cl_id2: Some(cl_ids[3]),
+    };
+    let bundle_id = CoopBundled::insert_coop_bundled(&pool, bundle).await.unwrap();
+    for id in &cl_ids[2..] {
+        CoopBundled::update_changelog_with_coop_id(&pool, *id, bundle_id).await.unwrap();
+    }
+
+    let req = test::TestRequest::get().uri(&format!("/users/{profile_number}/chapter_points")).to_request();
+    let chapters: Vec<ChapterPoints> = test::call_and_read_body_json(&app, req).await;
+    for (map_id, points) in [("47763", 200.0), ("47741", 200.0)] {
+        let (chapter_id, num_maps) = chapter_of(map_id).await;
+        let chapter = chapters.iter().find(|c| c.chapter_id == chapter_id).unwrap();
+        assert_eq!(chapter.points, points);
+        assert_eq!(chapter.max_possible, 200.0 * num_maps as f32);
+    }
+    assert_eq!(chapters.iter().map(|c| c.points).sum::<f32>(), 400.0);
+
+    let req = test::TestRequest::get().uri("/users/no_such_user/chapter_points").to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::NOT_FOUND);
+
+    sqlx::query("UPDATE changelog SET coop_id = NULL WHERE id = ANY($1)")
+        .bind(&cl_ids)
+        .execute(&pool)
+        .await
+        .unwrap();
+    sqlx::query("DELETE FROM coop_bundled WHERE id = $1").bind(bundle_id).execute(&pool).await.unwrap();
+    for id in cl_ids {
+        Changelog::delete_changelog(&pool, id).await.unwrap();
+    }
+}
+
+#[actix_web::test]
+async fn test_db_user_missing_maps() {
+    use crate::api::v1::handlers::users::user_missing_maps;
+    use crate::models::changelog::{Changelog, ChangelogInsert, MissingMap};
+    use actix_web::{http::StatusCode, test, web, App};
+    let (_, pool) = get_config().await.expect("Error getting config and DB pool");
+    let app = test::init_service(App::new().app_data(web::Data::new(pool.clone())).service(user_missing_maps)).await;
+    let profile_number = "missing_maps_test";
+    test_auth_header(&pool, profile_number, 0).await;
+    // Only the verified time completes a map.
+    let mut cl_ids = Vec::new();
+    for (map_id, category_id, verified) in [("47763", 19, true), ("47741", 61, false)] {
+        let cl = ChangelogInsert {
+            profile_number: profile_number.to_string(),
+            score: 900000,
+            map_id: map_id.to_string(),
+            category_id,
+            verified: Some(verified),
+            ..Default::default()
+        };
+        cl_ids.push(Changelog::insert_changelog(&pool, cl).await.unwrap());
+    }
+    let missing = |query: &str| test::TestRequest::get().uri(&format!("/users/{profile_number}/missing{query}")).to_request();
+    let all: Vec<MissingMap> = test::call_and_read_body_json(&app, missing("")).await;
+    let sp: Vec<MissingMap> = test::call_and_read_body_json(&app, missing("?game_id=1&is_coop=false")).await;
+    let coop: Vec<MissingMap> = test::call_and_read_body_json(&app, missing("?is_coop=true")).await;
+    let unknown = test::TestRequest::get().uri("/users/not_a_real_user/missing").to_request();
+    let unknown = test::call_service(&app, unknown).await.status();
+    for id in cl_ids {
+        Changelog::delete_changelog(&pool, id).await.unwrap();
+    }
+    let wr: Option<i32> = sqlx::query_scalar(
+        "SELECT MIN(cl.score) FROM changelog cl INNER JOIN users u ON (u.profile_number = cl.profile_number)
+        WHERE cl.map_id = '47741' AND cl.category_id = 61 AND cl.verified = True AND cl.banned = False AND u.banned = False",
+    )
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+    assert_eq!(all.iter().map(|map| map.map_id.as_str()).collect::<Vec<_>>(), vec!["47741", "47825"]);
+    assert!(sp.is_empty());
+    assert_eq!(coop, all);
+    assert!(all.iter().all(|map| map.is_coop));
+    assert_eq!(all[0].category_id, 61);
+    assert_eq!(all[0].wr_score, wr);
+    assert_eq!(unknown, StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn test_db_user_pbs() {
+    use crate::api::v1::handlers::users::user_pbs;
+    use crate::models::changelog::{Changelog, ChangelogInsert, PersonalBest};
+    use crate::models::coop::{CoopBundled, CoopBundledInsert};
+    use crate::tools::helpers::score;
+    use actix_web::{http::StatusCode, test, web, App};
+    let (_, pool) = get_config().await.expect("Error getting config and DB pool");
+    let app = test::init_service(App::new().app_data(web::Data::new(pool.clone())).service(user_pbs)).await;
+    let default_cat_ids = crate::tools::helpers::get_default_cat_ids(&pool).await;
+    let profile_number = "user_pbs_test";
+    test_auth_header(&pool, profile_number, 0).await;
+    sqlx::query("UPDATE users SET board_name = 'User PBs' WHERE profile_number = $1")
+        .bind(profile_number)
+        .execute(&pool)
+        .await
+        .unwrap();
+    // Slow times so the test does not take any world records. Only the faster verified SP time is a PB,
+    // and the unbundled coop time is not counted.
+    let mut cl_ids = Vec::new();
+    for (map_id, profile_number, score, verified) in [
+        ("47763", profile_number, 900002, true),
+        ("47763", profile_number, 900001, true),
+        ("47763", profile_number, 900000, false),
+        ("47741", profile_number, 900000, true),
+        ("47741", "seed1", 900000, true),
+        ("47825", profile_number, 900000, true),
+    ] {
+        let cl = ChangelogInsert {
+            profile_number: profile_number.to_string(),
+            score,
+            map_id: map_id.to_string(),
+            category_id: default_cat_ids.get(map_id, 1).unwrap(),
+            verified: Some(verified),
+            ..Default::default()
+        };
+        cl_ids.push(Changelog::insert_changelog(&pool, cl).await.unwrap());
+    }
+    let bundle = CoopBundledInsert {
+        p_id1: profile_number.to_string(),
+        p_id2: Some("seed1".to_string()),
+        p1_is_host: None,
+        cl_id1: cl_ids[3],
+        cl_id2: Some(cl_ids[4]),
+    };
+    let bundle_id = CoopBundled::insert_coop_bundled(&pool, bundle).await.unwrap();
+    for id in &cl_ids[3..5] {
+        CoopBundled::update_changelog_with_coop_id(&pool, *id, bundle_id).await.unwrap();
+    }
+
+    let req = test::TestRequest::get().uri(&format!("/users/{profile_number}/pbs")).to_request();
+    let pbs: Vec<PersonalBest> = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(pbs.len(), 2);
+    let sp = pbs.iter().find(|pb| pb.map_id == "47763").unwrap();
+    assert_eq!((sp.id, sp.score, sp.is_coop), (cl_ids[1], 900001, false));
+    assert_eq!(sp.map_name, "Laser vs Turret");
+    let coop = pbs.iter().find(|pb| pb.map_id == "47741").unwrap();
+    assert_eq!((coop.id, coop.is_coop), (cl_ids[3], true));
+    for pb in [sp, coop] {
+        let rank = pb.rank.expect("PBs should be ranked");
+        assert_eq!(pb.points, score(rank as i32));
+    }
+
+    let req = test::TestRequest::get().uri("/users/no_such_user/pbs").to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::NOT_FOUND);
+
+    sqlx::query("UPDATE changelog SET coop_id = NULL WHERE id = ANY($1)")
+        .bind(&cl_ids)
+        .execute(&pool)
+        .await
+        .unwrap();
+    sqlx::query("DELETE FROM coop_bundled WHERE id = $1").bind(bundle_id).execute(&pool).await.unwrap();
+    for id in cl_ids {
+        Changelog::delete_changelog(&pool, id).await.unwrap();
+    }
+}
+
+#[actix_web::test]
+async fn test_db_points_curve() {
+    use crate::api::v1::handlers::points::points_curve;
+    use crate::models::points::{PointsCurve, RankPoints};
+    use crate::tools::config::PointsConfig;
+    use actix_web::{test, web, App};
+    let (mut config, _) = get_config().await.expect("Error getting config and DB pool");
+    config.points = PointsConfig { max_points: 100.0, max_rank: 4, decay: 1.0, min_points: 0.0 };
+    let app = test::init_service(App::new().app_data(web::Data::new(config)).service(points_curve)).await;
+    let req = test::TestRequest::get().uri("/points/curve").to_request();
+    let res: PointsCurve = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(res.curve.max_rank, 4);
+    let points: Vec<f32> = res.points.iter().map(|p| p.points).collect();
+    assert_eq!(points, vec![100.0, 75.0, 50.0, 25.0]);
+    let req = test::TestRequest::get().uri("/points/curve?count=6").to_request();
+    let res: PointsCurve = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(res.points.len(), 6);
+    assert_eq!(res.points[5], RankPoints { rank: 6, points: 0.0 });
+}
+
+#[actix_web::test]
+async fn test_db_sp_previews_compressed() {
+    use crate::api::v1::handlers::sp::sp;
+    use crate::tools::cache::SP_PREVIEWS;
+    use crate::tools::metrics::Metrics;
+    use actix_web::{http::header, middleware::Compress, test, web, App};
+    let (_, pool) = get_config().await.expect("Error getting config and DB pool");
+    let cache = test_cache_state();
+    cache.update_current_state(SP_PREVIEWS, false).await;
+    let app = test::init_service(
+        App::new()
+            .wrap(Compress::default())
+            .app_data(web::Data::new(pool))
+            .app_data(web::Data::new(cache))
+            .app_data(web::Data::new(Metrics::new().unwrap()))
+            .service(sp),
+    )
+    .await;
+    let request = |encoding: Option<&str>| {
+        let mut req = test::TestRequest::get().uri("/sp");
+        if let Some(encoding) = encoding {
+            req = req.insert_header((header::ACCEPT_ENCODING, encoding));
+        }
+        req.to_request()
+    };
+    // The first request builds the previews, the second reads them back from the cached file.
+    for _ in 0..2 {
+        let res = test::call_service(&app, request(Some("gzip"))).await;
+        assert_eq!(res.headers().get(header::CONTENT_ENCODING).unwrap(), "gzip");
+        let compressed = test::read_body(res).await.len();
+        let res = test::call_service(&app, request(None)).await;
+        assert!(res.headers().get(header::CONTENT_ENCODING).is_none());
+        let uncompressed = test::read_body(res).await.len();
+        assert!(compressed < uncompressed);
+    }
+}
+
+#[actix_web::test]
+async fn test_db_sp_post_score_rate_limited() {
+    use crate::api::v1::handlers::sp::sp_post_score;
+    use crate::models::changelog::{Changelog, ChangelogInsert};
+    use actix_web::{
+        http::{header::RETRY_AFTER, StatusCode},
+        test, web, App,
+    };
+    let (config, pool) = get_config().await.expect("Error getting config and DB pool");
+    let limit = 3;
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(config))
+            .app_data(web::Data::new(pool.clone()))
+            .app_data(web::Data::new(test_cache_state()))
+            .app_data(web::Data::new(RateLimiter::new(RateLimitConfig { submissions: limit, per_secs: 3600 })))
+            .service(sp_post_score),
+    )
+    .await;
+    let profile_number = "ratelimit_test";
+    let auth = test_auth_header(&pool, profile_number, 0).await;
+    let submit = |score: i32| {
+        test::TestRequest::post()
+            .uri("/sp/post_score")
+            .insert_header(("Authorization", auth.clone()))
+            .set_json(ChangelogInsert {
+                profile_number: profile_number.to_string(),
+                score,
+                map_id: "47763".to_string(),
+                category_id: 19,
+                ..Default::default()
+            })
+            .to_request()
+    };
+    let mut cl_ids = Vec::new();
+    // Each score beats the last, so none are rejected for not improving.
+    for i in 0..limit {
+        let id: i64 = test::call_and_read_body_json(&app, submit(900010 - i as i32)).await;
+        cl_ids.push(id);
+    }
+    let res = test::call_service(&app, submit(900000)).await;
+    assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+    // One submission is refilled every 1200 seconds.
+    assert_eq!(res.headers().get(RETRY_AFTER).unwrap(), "1200");
+    // Scores posted by players are always manual, even though the puller's default is `steam_auto`.
+    let posted = Changelog::get_changelog(&pool, cl_ids[0]).await.unwrap().unwrap();
+    assert_eq!(posted.source, ChangelogSource::Manual);
+    for id in cl_ids {
+        Changelog::delete_changelog(&pool, id).await.unwrap();
+    }
+}
+
+#[actix_web::test]
+async fn test_db_sp_post_score_ranks() {
+    use crate::api::v1::handlers::sp::sp_post_score;
+    use crate::models::changelog::{Changelog, ChangelogInsert};
+    use crate::models::sp::SpMap;
+    use actix_web::{http::StatusCode, test, web, App};
+    let (config, pool) = get_config().await.expect("Error getting config and DB pool");
+    let ranked = SpMap::get_sp_map_page(&pool, &"47763".to_string(), config.proof.results, 19, 1, 0).await.unwrap().len() as i32;
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(config))
+            .app_data(web::Data::new(pool.clone()))
+            .app_data(web::Data::new(test_cache_state()))
+            .app_data(web::Data::new(RateLimiter::new(RateLimitConfig { submissions: 10, per_secs: 60 })))
+            .service(sp_post_score),
+    )
+    .await;
+    let profile_number = "post_rank_test";
+    let auth = test_auth_header(&pool, profile_number, 0).await;
+    let admin_auth = test_auth_header(&pool, "auth_test_admin", 2).await;
+    let submit_as = |auth: &str, profile_number: &str, score: i32, map_id: &str| {
+        test::TestRequest::post()
+            .uri("/sp/post_score")
+            .insert_header(("Authorization", auth.to_string()))
+            .set_json(ChangelogInsert {
+                profile_number: profile_number.to_string(),
+                score,
+                map_id: map_id.to_string(),
+                category_id: 19,
+                // Claiming a world record does not make it one.
+                post_rank: Some(1),
+                pre_rank: Some(1),
+                ..Default::default()
+            })
+            .to_request()
+    };
+    let submit = |score: i32, map_id: &str| submit_as(&auth, profile_number, score, map_id);
+    let first: i64 = test::call_and_read_body_json(&app, submit(900500, "47763")).await;
+    // Ties seed3's 1003, so it shares their rank of 3.
+    let second: i64 = test::call_and_read_body_json(&app, submit(1003, "47763")).await;
+    let not_improved = test::call_service(&app, submit(900600, "47763")).await.status();
+    let missing_map = test::call_service(&app, submit(900600, "999999")).await.status();
+    let missing_user = test::call_service(&app, submit_as(&admin_auth, "post_rank_missing_test", 900600, "47763")).await.status();
+    let first_cl = Changelog::get_changelog(&pool, first).await.unwrap().unwrap();
+    let second_cl = Changelog::get_changelog(&pool, second).await.unwrap().unwrap();
+    Changelog::delete_changelog(&pool, second).await.unwrap();
+    Changelog::delete_changelog(&pool, first).await.unwrap();
+    // A first score slower than every ranked score is placed last.
+    assert_eq!(first_cl.post_rank, Some(ranked + 1));
+    assert_eq!(first_cl.pre_rank, None);
+    assert_eq!(second_cl.post_rank, Some(3));
+    assert_eq!(second_cl.score_delta, Some(900500 - 1003));
+    assert_eq!(not_improved, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(missing_map, StatusCode::NOT_FOUND);
+    assert_eq!(missing_user, StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn test_db_stats_overview() {
+    use crate::api::v1::handlers::stats::stats_overview;
+    use crate::models::changelog::{Changelog, ChangelogInsert};
+    use crate::models::stats::StatsOverview;
+    use actix_web::{test, web, App};
+    let (_, pool) = get_config().await.expect("Error getting config and DB pool");
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(pool.clone()))
+            .app_data(web::Data::new(test_cache_state()))
+            .service(stats_overview),
+    )
+    .await;
+    let req = test::TestRequest::get().uri("/stats/overview").to_request();
+    let overview: StatsOverview = test::call_and_read_body_json(&app, req).await;
+    let maps: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM maps").fetch_one(&pool).await.unwrap();
+    assert_eq!(overview.maps, maps);
+
+    // A new run is counted by the query, but the endpoint keeps serving the cached overview.
+    let default_cat_ids = crate::tools::helpers::get_default_cat_ids(&pool).await;
+    let cl = ChangelogInsert {
+        timestamp: Some(chrono::Utc::now().naive_utc()),
+        profile_number: "seed5".to_string(),
+        score: 900101,
+        map_id: "47763".to_string(),
+        category_id: default_cat_ids.get("47763", 1).unwrap(),
+        verified: Some(true),
+        ..Default::default()
+    };
+    let cl_id = Changelog::insert_changelog(&pool, cl).await.unwrap();
+    let fresh = StatsOverview::get_overview(&pool).await.unwrap();
+    assert!(fresh.runs_last_24h >= 1);
+    let req = test::TestRequest::get().uri("/stats/overview").to_request();
+    let cached: StatsOverview = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(cached.verified_runs, overview.verified_runs);
+    assert_eq!(cached.runs_last_24h, overview.runs_last_24h);
+
+    Changelog::delete_changelog(&pool, cl_id).await.unwrap();
+}
+
+#[actix_web::test]
+async fn test_db_users_banned_paging() {
+    use crate::api::v1::handlers::users::users_banned;
+    use crate::models::users::{Users, UsersDisplay};
+    use actix_web::{http::StatusCode, test, web, App};
+    let (_, pool) = get_config().await.expect("Error getting config and DB pool");
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(pool.clone()))
+            .service(users_banned),
+    )
+    .await;
+    let profile_numbers = ["banned_page_test1", "banned_page_test2", "banned_page_test3"];
+    for profile_number in profile_numbers {
+        Users::insert_new_users(
+            &pool,
+            Users {
+                profile_number: profile_number.to_string(),
+                steam_name: Some(profile_number.to_string()),
+                avatar: Some(String::new()),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        Users::set_banned(&pool, profile_number, true).await.unwrap();
+    }
+    let banned_at: Option<chrono::NaiveDateTime> =
+        sqlx::query_scalar("SELECT banned_at FROM users WHERE profile_number = $1")
+            .bind(profile_numbers[0])
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert!(banned_at.is_some());
+    // Dated in the future so the test users are the most recently banned, test3 first.
+    for (i, profile_number) in profile_numbers.iter().enumerate() {
+        sqlx::query("UPDATE users SET banned_at = $2 WHERE profile_number = $1")
+            .bind(profile_number)
+            .bind(chrono::NaiveDate::from_ymd_opt(2100, 1, i as u32 + 1).unwrap().and_hms_opt(0, 0, 0))
+            .execute(&pool)
+            .await
+            .unwrap();
+    }
+
+    let req = test::TestRequest::get().uri("/users/banned?order=recent&limit=2").to_request();
+    let page: Vec<UsersDisplay> = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(page.len(), 2);
+    assert_eq!(page[0].profile_number, "banned_page_test3");
+    assert_eq!(page[1].profile_number, "banned_page_test2");
+    let req = test::TestRequest::get().uri("/users/banned?order=recent&offset=2&limit=1").to_request();
+    let page: Vec<UsersDisplay> = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(page.len(), 1);
+    assert_eq!(page[0].profile_number, "banned_page_test1");
+    let req = test::TestRequest::get().uri("/users/banned?order=oldest").to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::BAD_REQUEST);
+
+    // Unbanning clears the ban date.
+    Users::set_banned(&pool, profile_numbers[0], false).await.unwrap();
+    let banned_at: Option<chrono::NaiveDateTime> =
+        sqlx::query_scalar("SELECT banned_at FROM users WHERE profile_number = $1")
+            .bind(profile_numbers[0])
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert!(banned_at.is_none());
+    for profile_number in profile_numbers {
+        Users::delete_user(&pool, profile_number.to_string()).await.unwrap();
+    }
+}
+
+#[actix_web::test]
+async fn test_db_demos_reparse() {
+    use crate::api::v1::handlers::admin::admin_demos_reparse_progress;
+    use crate::api::v1::handlers::demos::reparse_demo_batch;
+    use crate::models::demos::{DemoInsert, Demos, ReparseProgress};
+    use crate::tests::demo_tests::demo_header;
+    use actix_web::{http::StatusCode, test, web, App};
+    use std::collections::HashMap;
+    use std::sync::Mutex as StdMutex;
+    let (_, pool) = get_config().await.expect("Error getting config and DB pool");
+    // Fixtures for the "stored" demos, keyed by file_id. The corrupt demo fails to parse.
+    let mut with_sar = demo_header("sp_a2_triple_laser");
+    with_sar.extend(17i32.to_le_bytes());
+    with_sar.extend([0u8; 8]);
+    with_sar.push(0xFF);
+    with_sar.extend(0xDEADBEEFu32.to_le_bytes());
+    with_sar.extend(0x1234ABCDu32.to_le_bytes());
+    let fixtures: HashMap<&str, Vec<u8>> = HashMap::from([
+        ("reparse_test_sar.dem", with_sar),
+        ("reparse_test_plain.dem", demo_header("sp_a2_laser_vs_turret")),
+        ("reparse_test_corrupt.dem", b"HL2DEMO\0".to_vec()),
+        ("reparse_test_parsed.dem", demo_header("sp_a2_laser_vs_turret")),
+    ]);
+    let mut demo_ids = HashMap::new();
+    for file_id in ["reparse_test_sar.dem", "reparse_test_plain.dem", "reparse_test_corrupt.dem", "reparse_test_parsed.dem"] {
+        let demo = DemoInsert {
+            file_id: file_id.to_string(),
+            parsed_successfully: file_id == "reparse_test_parsed.dem",
+            ..Default::default()
+        };
+        demo_ids.insert(file_id, Demos::insert_demo(&pool, demo).await.unwrap());
+    }
+    let after_id = demo_ids.values().min().unwrap() - 1;
+    let fetched = StdMutex::new(Vec::new());
+    let fetch = |demo: Demos| {
+        fetched.lock().unwrap().push(demo.file_id.clone());
+        let contents = fixtures.get(demo.file_id.as_str()).cloned();
+        async move { contents.ok_or_else(|| anyhow::anyhow!("Not a test demo")) }
+    };
+
+    let batch = reparse_demo_batch(&pool, after_id, 3, &fetch).await.unwrap();
+    assert_eq!(batch.parsed, 2);
+    assert_eq!(batch.failed, 1);
+    assert_eq!(batch.last_id, Some(demo_ids["reparse_test_corrupt.dem"]));
+    assert!(!fetched.lock().unwrap().contains(&"reparse_test_parsed.dem".to_string()));
+    let parsed = Demos::get_demo(&pool, demo_ids["reparse_test_sar.dem"]).await.unwrap().unwrap();
+    assert!(parsed.parsed_successfully);
+    assert_eq!(parsed.sar_version, Some("1234ABCD".to_string()));
+    let parsed = Demos::get_demo(&pool, demo_ids["reparse_test_plain.dem"]).await.unwrap().unwrap();
+    assert!(parsed.parsed_successfully);
+    assert_eq!(parsed.sar_version, None);
+    assert!(!Demos::check_parsed(&pool, demo_ids["reparse_test_corrupt.dem"]).await.unwrap());
+
+    // Running again only retries the demo that failed.
+    fetched.lock().unwrap().clear();
+    let batch = reparse_demo_batch(&pool, after_id, 3, &fetch).await.unwrap();
+    assert_eq!(batch.failed, 1);
+    assert_eq!(batch.parsed, 0);
+    assert_eq!(fetched.lock().unwrap()[0], "reparse_test_corrupt.dem");
+
+    let cache = test_cache_state();
+    *cache.demo_reparse.lock().await = ReparseProgress { parsed: 2, failed: 1, ..Default::default() };
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(pool.clone()))
+            .app_data(web::Data::new(cache))
+            .service(admin_demos_reparse_progress),
+    )
+    .await;
+    let req = test::TestRequest::get().uri("/admin/demos/reparse").to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::UNAUTHORIZED);
+    let auth = test_auth_header(&pool, "reparse_test_admin", 2).await;
+    let req = test::TestRequest::get()
+        .uri("/admin/demos/reparse")
+        .insert_header(("Authorization", auth))
+        .to_request();
+    let progress: ReparseProgress = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(progress.parsed, 2);
+    assert!(!progress.running);
+
+    for demo_id in demo_ids.values() {
+        Demos::delete_demo(&pool, *demo_id).await.unwrap();
+    }
+    crate::models::users::Users::delete_user(&pool, "reparse_test_admin".to_string()).await.unwrap();
+}
+
+#[actix_web::test]
+async fn test_db_demos_metadata() {
+    use crate::api::v1::handlers::demos::{demos_metadata, load_demo_header};
+    use crate::models::demos::{DemoHeader, DemoInsert, Demos};
+    use crate::tests::demo_tests::demo_header;
+    use crate::tools::storage::demo_storage;
+    use actix_web::{http::StatusCode, test, web, App};
+    let (config, pool) = get_config().await.expect("Error getting config and DB pool");
+    let stored = DemoInsert {
+        file_id: "metadata_test_stored.dem".to_string(),
+        parsed_successfully: true,
+        sar_version: Some("1234ABCD".to_string()),
+        map_name: Some("sp_a2_triple_laser".to_string()),
+        tick_count: Some(2100),
+        playback_time: Some(35.0),
+        protocol: Some(4),
+        ..Default::default()
+    };
+    let stored_id = Demos::insert_demo(&pool, stored).await.unwrap();
+    let legacy = DemoInsert { file_id: "metadata_test_legacy.dem".to_string(), parsed_successfully: true, ..Default::default() };
+    let legacy_id = Demos::insert_demo(&pool, legacy).await.unwrap();
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::from(demo_storage(&config)))
+            .app_data(web::Data::new(config))
+            .app_data(web::Data::new(pool.clone()))
+            .service(demos_metadata),
+    )
+    .await;
+    let req = test::TestRequest::get().uri(&format!("/demos/metadata?demo_id={stored_id}")).to_request();
+    let header: DemoHeader = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(header.map_name, "sp_a2_triple_laser");
+    assert_eq!(header.tick_count, 2100);
+    assert_eq!(header.sar_version, Some("1234ABCD".to_string()));
+    let req = test::TestRequest::get().uri("/demos/metadata?demo_id=-1").to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::NOT_FOUND);
+    let req = test::TestRequest::get().uri("/demos/metadata").to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::BAD_REQUEST);
+
+    // Demos uploaded before the header was stored are parsed once, and the header is stored for later requests.
+    let header = load_demo_header(&pool, legacy_id, |_| async { Ok(demo_header("sp_a2_laser_vs_turret")) }).await.unwrap();
+    assert_eq!(header.map_name, "sp_a2_laser_vs_turret");
+    assert_eq!(header.tick_count, 1050);
+    assert_eq!(header.protocol, 4);
+    assert_eq!(Demos::get_demo_header(&pool, legacy_id).await.unwrap(), Some(header.clone()));
+    let cached = load_demo_header(&pool, legacy_id, |_| async { anyhow::bail!("Should not be downloaded again") }).await.unwrap();
+    assert_eq!(cached, header);
+
+    for demo_id in [stored_id, legacy_id] {
+        Demos::delete_demo(&pool, demo_id).await.unwrap();
+    }
+}
+
+#[actix_web::test]
+async fn test_db_user_profile() {
+    use crate::api::v1::handlers::users::user_profile;
+    use crate::models::users::{UserProfile, Users};
+    use actix_web::{http::StatusCode, test, web, App};
+    let (_, pool) = get_config().await.expect("Error getting config and DB pool");
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(pool.clone()))
+            .service(user_profile),
+    )
+    .await;
+    let profile_number = "user_profile_test".to_string();
+    Users::insert_new_users(
+        &pool,
+        Users {
+            profile_number: profile_number.clone(),
+            board_name: Some("Profile Test".to_string()),
+            title: Some("Tester".to_string()),
+            discord_id: Some("1234".to_string()),
+            auth_hash: Some("not_a_real_hash".to_string()),
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+
+    let req = test::TestRequest::get().uri("/users/user_profile_test").to_request();
+    let body = test::call_and_read_body(&app, req).await;
+    let profile: UserProfile = serde_json::from_slice(&body).unwrap();
+    assert_eq!(profile.board_name, Some("Profile Test".to_string()));
+    assert_eq!(profile.title, Some("Tester".to_string()));
+    assert!(profile.data.oldest_sp.is_none());
+    assert!(profile.admin.is_none());
+    assert!(profile.discord_id.is_none());
+    assert!(!String::from_utf8_lossy(&body).contains("auth_hash"));
+
+    let auth = test_auth_header(&pool, "auth_test_admin", 2).await;
+    let req = test::TestRequest::get()
+        .uri("/users/user_profile_test")
+        .insert_header(("Authorization", auth))
+        .to_request();
+    let profile: UserProfile = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(profile.admin, Some(0));
+    assert_eq!(profile.discord_id, Some("1234".to_string()));
+
+    let req = test::TestRequest::get().uri("/users/not_a_user").to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::NOT_FOUND);
+    Users::delete_user(&pool, profile_number).await.unwrap();
+}
+
+#[actix_web::test]
+async fn test_db_sp_player_rank() {
+    use crate::api::v1::handlers::sp::sp_player_rank;
+    use crate::models::changelog::{Changelog, ChangelogInsert, PlayerRank};
+    use crate::models::chapters::ReviewParams;
+    use crate::models::sp::SpMap;
+    use crate::models::users::Users;
+    use crate::tools::helpers::score;
+    use actix_web::{test, web, App};
+    let (_, pool) = get_config().await.expect("Error getting config and DB pool");
+    let cache = test_cache_state();
+    let cat_id = crate::tools::helpers::get_default_cat_ids(&pool).await.get("47763", 1).unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(pool.clone()))
+            .app_data(web::Data::new(cache))
+            .service(sp_player_rank),
+    )
+    .await;
+    let mut cl_ids = Vec::new();
+    for (profile_number, score) in [("rank_test_1", 900200), ("rank_test_2", 900200), ("rank_test_3", 900300)] {
+        Users::insert_new_users(
+            &pool,
+            Users {
+                profile_number: profile_number.to_string(),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        let cl = ChangelogInsert {
+            profile_number: profile_number.to_string(),
+            score,
+            map_id: "47763".to_string(),
+            category_id: cat_id,
+            verified: Some(true),
+            ..Default::default()
+        };
+        cl_ids.push(Changelog::insert_changelog(&pool, cl).await.unwrap());
+    }
+    let better = SpMap::count_better_scores(&pool, &"47763".to_string(), 900200, cat_id, 1, &ReviewParams::default())
+        .await
+        .unwrap();
+    let get_rank = |profile_number: &str| {
+        test::TestRequest::get()
+            .uri(&format!("/map/sp/47763/rank?profile_number={profile_number}&cat_id={cat_id}"))
+            .to_request()
+    };
+    let first: Option<PlayerRank> = test::call_and_read_body_json(&app, get_rank("rank_test_1")).await;
+    let second: Option<PlayerRank> = test::call_and_read_body_json(&app, get_rank("rank_test_2")).await;
+    let third: Option<PlayerRank> = test::call_and_read_body_json(&app, get_rank("rank_test_3")).await;
+    let missing: Option<PlayerRank> = test::call_and_read_body_json(&app, get_rank("rank_test_missing")).await;
+    for id in cl_ids {
+        Changelog::delete_changelog(&pool, id).await.unwrap();
+    }
+    for profile_number in ["rank_test_1", "rank_test_2", "rank_test_3"] {
+        Users::delete_user(&pool, profile_number.to_string()).await.unwrap();
+    }
+    let first = first.unwrap();
+    assert_eq!(first.rank, better + 1);
+    assert_eq!(first.score, 900200);
+    assert_eq!(first.points, score(first.rank as i32));
+    // Tied times share a rank, and the next time skips past both.
+    assert_eq!(second.unwrap().rank, first.rank);
+    assert_eq!(third.unwrap().rank, first.rank + 2);
+    assert!(missing.is_none());
+}
+
+#[actix_web::test]
+async fn test_db_sp_compare() {
+    use crate::api::v1::handlers::sp::sp_compare;
+    use crate::models::changelog::{Changelog, ChangelogInsert, PlayerComparison};
+    use crate::models::users::Users;
+    use actix_web::{test, web, App};
+    let (_, pool) = get_config().await.expect("Error getting config and DB pool");
+    let mut cache = test_cache_state();
+    cache.default_cat_ids = crate::tools::helpers::get_default_cat_ids(&pool).await;
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(pool.clone()))
+            .app_data(web::Data::new(cache))
+            .service(sp_compare),
+    )
+    .await;
+    let mut cl_ids = Vec::new();
+    // The unverified time is filtered out, like on the map page.
+    for (profile_number, score, verified) in
+        [("compare_test_1", 900200, true), ("compare_test_2", 900235, true), ("compare_test_3", 900100, false)]
+    {
+        Users::insert_new_users(
+            &pool,
+            Users {
+                profile_number: profile_number.to_string(),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        let cl = ChangelogInsert {
+            profile_number: profile_number.to_string(),
+            score,
+            map_id: "47763".to_string(),
+            category_id: 19,
+            verified: Some(verified),
+            ..Default::default()
+        };
+        cl_ids.push(Changelog::insert_changelog(&pool, cl).await.unwrap());
+    }
+    let compare = |p1: &str, p2: &str| {
+        test::TestRequest::get().uri(&format!("/map/sp/47763/compare?p1={p1}&p2={p2}")).to_request()
+    };
+    let both: PlayerComparison = test::call_and_read_body_json(&app, compare("compare_test_1", "compare_test_2")).await;
+    let swapped: PlayerComparison = test::call_and_read_body_json(&app, compare("compare_test_2", "compare_test_1")).await;
+    let one_missing: PlayerComparison = test::call_and_read_body_json(&app, compare("compare_test_1", "compare_test_3")).await;
+    let both_missing: PlayerComparison = test::call_and_read_body_json(&app, compare("compare_test_3", "compare_test_missing")).await;
+    for id in cl_ids {
+        Changelog::delete_changelog(&pool, id).await.unwrap();
+    }
+    for profile_number in ["compare_test_1", "compare_test_2", "compare_test_3"] {
+        Users::delete_user(&pool, profile_number.to_string()).await.unwrap();
+    }
+    let (p1, p2) = (both.p1.unwrap(), both.p2.unwrap());
+    assert_eq!((p1.score, p2.score), (900200, 900235));
+    assert_eq!(p2.rank, p1.rank + 1);
+    assert_eq!(both.gap, Some(35));
+    assert_eq!(both.formatted_gap.as_deref(), Some("0.35"));
+    assert_eq!(swapped.gap, Some(-35));
+    assert_eq!(swapped.formatted_gap.as_deref(), Some("-0.35"));
+    assert_eq!(one_missing.p1, Some(p1));
+    assert_eq!(one_missing.p2, None);
+    assert_eq!(one_missing.gap, None);
+    assert_eq!(one_missing.formatted_gap, None);
+    assert_eq!(both_missing.p1, None);
+    assert_eq!(both_missing.p2, None);
+    assert_eq!(both_missing.gap, None);
+}
+
+#[actix_web::test]
+async fn test_db_changelog_require_improvement() {
+    use crate::api::v1::handlers::changelog::changelog_new;
+    use crate::models::changelog::{Changelog, ChangelogInsert};
+    use actix_web::{http::StatusCode, test, web, App};
+    let (mut config, pool) = get_config().await.expect("Error getting config and DB pool");
+    config.submissions.require_improvement = true;
+    // A fresh category, so the seeded scores on the map are not the PB.
+    let cat_id: i32 = sqlx::query_scalar("INSERT INTO categories (name, map_id) VALUES ('improvement test', '47763') RETURNING id")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    let pb_id = Changelog::insert_changelog(
+        &pool,
+        ChangelogInsert {
+            profile_number: "seed10".to_string(),
+            score: 900500,
+            map_id: "47763".to_string(),
+            category_id: cat_id,
+            timestamp: Some(chrono::NaiveDate::from_ymd_opt(2022, 1, 1).unwrap().and_hms_opt(0, 0, 0).unwrap()),
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    let mut ids = vec![pb_id];
+    let submit = |score: i32, timestamp: &str| {
+        test::TestRequest::post()
+            .uri("/changelog")
+            .set_json(serde_json::json!({
+                "timestamp": timestamp,
+                "profile_number": "seed10",
+                "score": score,
+                "map_id": "47763",
+                "category_id": cat_id,
+            }))
+            .to_request()
+    };
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(config.clone()))
+            .app_data(web::Data::new(pool.clone()))
+            .app_data(web::Data::new(test_cache_state()))
+            .service(changelog_new),
+    )
+    .await;
+    // Better than the PB.
+    let better_id: i64 = test::call_and_read_body_json(&app, submit(900400, "2022-01-02 00:00:00")).await;
+    ids.push(better_id);
+    let better = Changelog::get_changelog(&pool, better_id).await.unwrap().unwrap();
+    assert_eq!(better.score_delta, Some(100));
+    // Equal to, and worse than the PB, the 422 names the existing PB.
+    for score in [900400, 900450] {
+        let resp = test::call_service(&app, submit(score, "2022-01-03 00:00:00")).await;
+        assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let body = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+        assert!(body.contains("900400"), "{body}");
+    }
+    let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM changelog WHERE profile_number = 'seed10' AND score >= 900000")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(count, 2);
+
+    // Without the rule, worse times are accepted but are not a PB.
+    config.submissions.require_improvement = false;
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(config))
+            .app_data(web::Data::new(pool.clone()))
+            .app_data(web::Data::new(test_cache_state()))
+            .service(changelog_new),
+    )
+    .await;
+    let worse_id: i64 = test::call_and_read_body_json(&app, submit(900450, "2022-01-03 00:00:00")).await;
+    ids.push(worse_id);
+    let worse = Changelog::get_changelog(&pool, worse_id).await.unwrap().unwrap();
+    assert_eq!(worse.score_delta, Some(-50));
+    // The PB is still the better time, not the newest submission.
+    let equal_id: i64 = test::call_and_read_body_json(&app, submit(900400, "2022-01-04 00:00:00")).await;
+    ids.push(equal_id);
+    let equal = Changelog::get_changelog(&pool, equal_id).await.unwrap().unwrap();
+    assert_eq!(equal.score_delta, Some(0));
+    for id in ids.into_iter().rev() {
+        Changelog::delete_changelog(&pool, id).await.unwrap();
+    }
+    sqlx::query("DELETE FROM categories WHERE id = $1").bind(cat_id).execute(&pool).await.unwrap();
+}
+
+#[actix_web::test]
+async fn test_db_demos_changelog_upload_limits() {
+    use crate::api::v1::handlers::demos::demos_changelog;
+    use crate::tests::demo_tests::demo_header;
+    use crate::tools::helpers::get_default_cat_ids;
+    use crate::tools::metrics::Metrics;
+    use crate::tools::storage::demo_storage;
+    use actix_web::{http::StatusCode, test, web, App};
+    let (mut config, pool) = get_config().await.expect("Error getting config and DB pool");
+    let demo = demo_header("sp_a2_laser_vs_turret");
+    config.demos.max_bytes = demo.len() as u64;
+    let mut cache = test_cache_state();
+    cache.default_cat_ids = get_default_cat_ids(&pool).await;
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::from(demo_storage(&config)))
+            .app_data(web::Data::new(config))
+            .app_data(web::Data::new(pool.clone()))
+            .app_data(web::Data::new(cache))
+            .app_data(web::Data::new(Metrics::new().unwrap()))
+            .app_data(web::Data::new(RateLimiter::new(RateLimitConfig::default())))
+            .service(demos_changelog),
+    )
+    .await;
+    let auth = test_auth_header(&pool, "seed8", 0).await;
+    let boundary = "demoboundary";
+    let upload = |file_name: &str, contents: &[u8]| {
+        let mut body = format!(
+            "--{boundary}\r\nContent-Disposition: form-data; name=\"demo\"; filename=\"{file_name}\"\r\n\r\n"
+        )
+        .into_bytes();
+        body.extend(contents);
+        body.extend(format!("\r\n--{boundary}--\r\n").into_bytes());
+        test::TestRequest::post()
+            .uri("/demos/changelog?timestamp=2022-01-01%2000:00:00&profile_number=seed8&score=1004&map_id=47763&category_id=19&dry_run=true")
+            .insert_header(("Content-Type", format!("multipart/form-data; boundary={boundary}")))
+            .insert_header(("Authorization", auth.clone()))
+            .set_payload(body)
+            .to_request()
+    };
+    let resp = test::call_service(&app, upload("limits_test.dem", &demo)).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let mut oversize = demo.clone();
+    oversize.push(0);
+    let resp = test::call_service(&app, upload("limits_test.dem", &oversize)).await;
+    assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    let resp = test::call_service(&app, upload("limits_test.zip", b"PK\x03\x04")).await;
+    assert_eq!(resp.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    let resp = test::call_service(&app, upload("limits_test.dem", b"PK\x03\x04 not a demo")).await;
+    assert_eq!(resp.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    assert!(!std::path::Path::new("./demos/limits_test.dem").exists());
+}
+
+#[actix_web::test]
+async fn test_live_updates() {
+    use crate::api::v1::handlers::live::live;
+    use crate::tools::live::LiveUpdate;
+    use actix_web::{
+        body::MessageBody,
+        dev::Payload,
+        error::PayloadError,
+        http::StatusCode,
+        test,
+        web::{self, Bytes},
+        App,
+    };
+    use futures::{channel::mpsc, future::poll_fn};
+    use std::pin::Pin;
+    let cache = web::Data::new(test_cache_state());
+    let app = test::init_service(App::new().app_data(cache.clone()).service(live)).await;
+    let not_upgrade = test::call_service(&app, test::TestRequest::get().uri("/ws/live").to_request()).await;
+    assert_eq!(not_upgrade.status(), StatusCode::BAD_REQUEST);
+
+    // Frames the client sends, kept open until the test closes the socket.
+    let (client, frames) = mpsc::unbounded::<Result<Bytes, PayloadError>>();
+    let mut req = test::TestRequest::get()
+        .uri("/ws/live")
+        .insert_header(("Upgrade", "websocket"))
+        .insert_header(("Connection", "Upgrade"))
+        .insert_header(("Sec-WebSocket-Version", "13"))
+        .insert_header(("Sec-WebSocket-Key", "dGhlIHNhbXBsZSBub25jZQ=="))
+        .to_request();
+    *req.payload() = Payload::Stream { payload: Box::pin(frames) };
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::SWITCHING_PROTOCOLS);
+    let mut body = res.into_body();
+    // Server frames are unmasked, so short frames are the opcode, the payload length and the payload.
+
+    cache.live.publish(LiveUpdate::SpUpdate { map_id: "47763".to_string() });
+    let frame = poll_fn(|cx| Pin::new(&mut body).poll_next(cx)).await.unwrap().unwrap();
+    assert_eq!(frame[0], 0x81);
+    let update: serde_json::Value = serde_json::from_slice(&frame[2..]).unwrap();
+    assert_eq!(update, serde_json::json!({ "type": "sp_update", "map_id": "47763" }));
+
+    // A masked close frame from the client is answered with a close frame, then the socket ends.
+    client.unbounded_send(Ok(Bytes::from_static(&[0x88, 0x80, 0, 0, 0, 0]))).unwrap();
+    let frame = poll_fn(|cx| Pin::new(&mut body).poll_next(cx)).await.unwrap().unwrap();
+    assert_eq!(frame[0], 0x88);
+    assert!(poll_fn(|cx| Pin::new(&mut body).poll_next(cx)).await.is_none());
+    assert_eq!(serde_json::to_value(LiveUpdate::Resync).unwrap(), serde_json::json!({ "type": "resync" }));
+}
+
+#[actix_web::test]
+async fn test_db_maintenance_mode() {
+    use crate::api::v1::handlers::{admin::admin_maintenance, sp::sp_map, sp::sp_post_score};
+    use crate::models::{admin::MaintenanceState, changelog::{Changelog, ChangelogInsert}};
+    use crate::tools::error::{ErrResponse, ErrorType};
+    use crate::tools::{config::MaintenanceConfig, maintenance::{block_changes, Maintenance}};
+    use actix_web::{http::StatusCode, middleware::from_fn, test, web, App};
+    let (config, pool) = get_config().await.expect("Error getting config and DB pool");
+    let maintenance = Maintenance::new(&MaintenanceConfig { enabled: true });
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(block_changes))
+            .app_data(web::Data::new(config))
+            .app_data(web::Data::new(pool.clone()))
+            .app_data(web::Data::new(test_cache_state()))
+            .app_data(web::Data::new(RateLimiter::new(RateLimitConfig { submissions: 0, per_secs: 60 })))
+            .app_data(web::Data::new(maintenance.clone()))
+            .service(sp_map)
+            .service(sp_post_score)
+            .service(admin_maintenance),
+    )
+    .await;
+    let profile_number = "maintenance_test";
+    let auth = test_auth_header(&pool, profile_number, 0).await;
+    let submit = || {
+        test::TestRequest::post()
+            .uri("/sp/post_score")
+            .insert_header(("Authorization", auth.clone()))
+            .set_json(ChangelogInsert {
+                profile_number: profile_number.to_string(),
+                score: 900000,
+                map_id: "47763".to_string(),
+                category_id: 19,
+                ..Default::default()
+            })
+            .to_request()
+    };
+    let toggle = |auth: &str, enabled: bool| {
+        test::TestRequest::put()
+            .uri("/admin/maintenance")
+            .insert_header(("Authorization", auth.to_string()))
+            .set_json(MaintenanceState { enabled })
+            .to_request()
+    };
+
+    // Reads still work, submissions are rejected before they reach the handler.
+    let res = test::call_service(&app, test::TestRequest::get().uri("/map/sp/47763?cat_id=19&limit=5").to_request()).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let res = test::call_service(&app, submit()).await;
+    assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+    let err: ErrResponse = test::read_body_json(res).await;
+    assert_eq!(err.error_type, ErrorType::ServiceUnavailable);
+
+    // The toggle is let through, but only admins can use it.
+    let res = test::call_service(&app, toggle(&auth, false)).await;
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    assert!(maintenance.is_enabled());
+    let admin = test_auth_header(&pool, "auth_test_admin", 2).await;
+    let state: MaintenanceState = test::call_and_read_body_json(&app, toggle(&admin, false)).await;
+    assert_eq!(state, MaintenanceState { enabled: false });
+    assert!(!maintenance.is_enabled());
+
+    let id: i64 = test::call_and_read_body_json(&app, submit()).await;
+    Changelog::delete_changelog(&pool, id).await.unwrap();
+}
+
+#[actix_web::test]
+async fn test_db_search_users() {
+    use crate::api::v1::handlers::users::search_users;
+    use crate::models::users::UsersDisplay;
+    use actix_web::{test, web, App};
+    let (_, pool) = get_config().await.expect("Error getting config and DB pool");
+    let app = test::init_service(App::new().app_data(web::Data::new(pool.clone())).service(search_users)).await;
+    let search = |uri: &str| test::TestRequest::get().uri(uri).to_request();
+    let profile_numbers: Vec<String> = (0..55).map(|i| format!("search_limit_{i:02}")).collect();
+    sqlx::query("INSERT INTO users (profile_number, board_name, avatar, banned, registered, admin) SELECT p, p, '', false, 0, 0 FROM UNNEST($1::TEXT[]) AS p")
+        .bind(&profile_numbers)
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let limited: Vec<UsersDisplay> = test::call_and_read_body_json(&app, search("/search/users?q=SEARCH_LIMIT")).await;
+    let short: Vec<UsersDisplay> = test::call_and_read_body_json(&app, search("/search/users?q=%20se%20")).await;
+    // Wildcards in the query only match themselves.
+    let wildcard: Vec<UsersDisplay> = test::call_and_read_body_json(&app, search("/search/users?q=limit%25")).await;
+    let underscore: Vec<UsersDisplay> = test::call_and_read_body_json(&app, search("/search/users?q=limit_0")).await;
+    sqlx::query("DELETE FROM users WHERE profile_number = ANY($1)").bind(&profile_numbers).execute(&pool).await.unwrap();
+    assert_eq!(limited.len(), 50);
+    assert!(limited.iter().all(|user| user.profile_number.starts_with("search_limit_")));
+    assert!(short.is_empty());
+    assert!(wildcard.is_empty());
+    assert_eq!(underscore.len(), 10);
+}
+
+#[actix_web::test]
+async fn test_db_search_maps() {
+    use crate::api::v1::handlers::maps::search_maps;
+    use crate::models::maps::MapSearchResult;
+    use actix_web::{test, web, App};
+    let (_, pool) = get_config().await.expect("Error getting config and DB pool");
+    let app = test::init_service(App::new().app_data(web::Data::new(pool)).service(search_maps)).await;
+    let search = |uri: &str| test::TestRequest::get().uri(uri).to_request();
+
+    let maps: Vec<MapSearchResult> = test::call_and_read_body_json(&app, search("/search/maps?q=tURRet")).await;
+    let map = maps.iter().find(|map| map.steam_id == "47763").unwrap();
+    assert_eq!(map.name, "Laser vs Turret");
+    assert!(!map.is_coop);
+    let maps: Vec<MapSearchResult> = test::call_and_read_body_json(&app, search("/search/maps?q=door")).await;
+    assert!(maps.iter().any(|map| map.steam_id == "47741" && map.is_coop));
+    let maps: Vec<MapSearchResult> = test::call_and_read_body_json(&app, search("/search/maps?q=door&game_id=2")).await;
+    assert!(maps.iter().all(|map| map.steam_id != "47741"));
+
+    // Names starting with the query come before names that only contain it.
+    let maps: Vec<MapSearchResult> = test::call_and_read_body_json(&app, search("/search/maps?q=s")).await;
+    let positions: Vec<usize> = maps.iter().map(|map| map.name.to_lowercase().find('s').unwrap()).collect();
+    assert!(positions.windows(2).all(|pair| pair[0] <= pair[1]));
+
+    let maps: Vec<MapSearchResult> = test::call_and_read_body_json(&app, search("/search/maps?q=%20%20")).await;
+    assert!(maps.is_empty());
+    let maps: Vec<MapSearchResult> = test::call_and_read_body_json(&app, search("/search/maps")).await;
+    assert!(maps.is_empty());
+}
+
+#[actix_web::test]
+async fn test_db_map_csv_export() {
+    use crate::api::v1::handlers::{coop::coop_map, sp::sp_map};
+    use actix_web::{http::header, test, web, App};
+    let (config, pool) = get_config().await.expect("Error getting config and DB pool");
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(config))
+            .app_data(web::Data::new(pool))
+            .app_data(web::Data::new(test_cache_state()))
+            .service(sp_map)
+            .service(coop_map),
+    )
+    .await;
+    let read_csv = |body: &[u8]| {
+        let mut reader = csv::Reader::from_reader(body);
+        let headers: Vec<String> = reader.headers().unwrap().iter().map(String::from).collect();
+        let rows: Vec<csv::StringRecord> = reader.records().map(Result::unwrap).collect();
+        (headers, rows)
+    };
+
+    let json: Vec<serde_json::Value> =
+        test::call_and_read_body_json(&app, test::TestRequest::get().uri("/map/sp/47763?cat_id=19&limit=50").to_request())
+            .await;
+    let req = test::TestRequest::get()
+        .uri("/map/sp/47763?cat_id=19&limit=50")
+        .insert_header((header::ACCEPT, "text/csv"))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.headers().get(header::CONTENT_TYPE).unwrap(), "text/csv; charset=utf-8");
+    let (headers, rows) = read_csv(&test::read_body(res).await);
+    assert_eq!(headers, ["rank", "profile_number", "user_name", "score", "points"]);
+    assert_eq!(rows.len(), json.len());
+    assert_eq!(rows[0][1], json[0]["map_data"]["profile_number"]);
+    assert_eq!(rows[0][3], json[0]["map_data"]["score"].to_string());
+
+    let json: Vec<serde_json::Value> =
+        test::call_and_read_body_json(&app, test::TestRequest::get().uri("/map/coop/47741?cat_id=61").to_request()).await;
+    let req = test::TestRequest::get().uri("/map/coop/47741?cat_id=61&format=csv").to_request();
+    let (headers, rows) = read_csv(&test::call_and_read_body(&app, req).await);
+    assert_eq!(
+        headers,
+        ["rank", "profile_number1", "user_name1", "profile_number2", "user_name2", "score", "points"]
+    );
+    assert_eq!(rows.len(), json.len());
+    if let (Some(row), Some(entry)) = (rows.first(), json.first()) {
+        assert_eq!(row[0], entry["rank"].to_string());
+    }
+}
+
+#[actix_web::test]
+async fn test_db_min_score_flagging() {
+    use crate::models::changelog::{Changelog, SubmissionChangelog};
+    use crate::models::users::Users;
+    use crate::tools::helpers::{get_valid_changelog_insert, MIN_SCORE_FLAG_NOTE, SUBMISSION_TIMESTAMP_FORMAT};
+    let (config, pool) = get_config().await.expect("Error getting config and DB pool");
+    let cache = test_cache_state();
+    let profile_number = "min_score_test_profile".to_string();
+    Users::insert_new_users(
+        &pool,
+        Users {
+            profile_number: profile_number.clone(),
+            steam_name: Some("min_score_test".to_string()),
+            avatar: Some(String::new()),
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    let cat_id = 900001;
+    sqlx::query("INSERT INTO categories (id, name, map_id, min_score) VALUES ($1, 'min score test', '47763', 900500)")
+        .bind(cat_id)
+        .execute(&pool)
+        .await
+        .unwrap();
+    let submission = |score: i32| SubmissionChangelog {
+        timestamp: chrono::Utc::now().naive_utc().format(SUBMISSION_TIMESTAMP_FORMAT).to_string(),
+        profile_number: profile_number.clone(),
+        score,
+        map_id: "47763".to_string(),
+        youtube_id: None,
+        note: None,
+        category_id: Some(cat_id),
+        game_id: None,
+        dry_run: None,
+    };
+
+    // A score at the minimum is accepted as usual.
+    let at_min = get_valid_changelog_insert(&pool, &config, &cache, submission(900500)).await.unwrap();
+    assert!(!at_min.banned);
+    assert_eq!(at_min.verified, Some(false));
+    assert_eq!(at_min.admin_note, None);
+    let at_min_id = Changelog::insert_changelog(&pool, at_min).await.unwrap();
+    // A faster score is stored banned, and waits in the verification queue.
+    let below_min = get_valid_changelog_insert(&pool, &config, &cache, submission(900499)).await.unwrap();
+    assert!(below_min.banned);
+    assert_eq!(below_min.verified, None);
+    assert_eq!(below_min.admin_note.as_deref(), Some(MIN_SCORE_FLAG_NOTE));
+    let below_min_id = Changelog::insert_changelog(&pool, below_min).await.unwrap();
+    let queue = Changelog::get_unverified(&pool, Some(1)).await.unwrap();
+    let flagged = queue.iter().find(|entry| entry.id == below_min_id).unwrap();
+    assert!(flagged.banned);
+    assert!(queue.iter().any(|entry| entry.id == at_min_id));
+    // Rejecting it keeps it banned, and takes it out of the queue.
+    Changelog::set_verification(&pool, below_min_id, false, None).await.unwrap();
+    let queue = Changelog::get_unverified(&pool, Some(1)).await.unwrap();
+    assert!(!queue.iter().any(|entry| entry.id == below_min_id));
+
+    for id in [below_min_id, at_min_id] {
+        Changelog::delete_changelog(&pool, id).await.unwrap();
+    }
+    sqlx::query("DELETE FROM categories WHERE id = $1").bind(cat_id).execute(&pool).await.unwrap();
+    Users::delete_user(&pool, profile_number).await.unwrap();
+}
+
+#[actix_web::test]
+async fn test_db_pool_acquire_timeout() {
+    use crate::tools::config::DatabaseConfig;
+    use crate::tools::db::{connect, pool_options};
+    use crate::tools::error::{ErrorType, ServerError};
+    use std::time::Duration;
+    let (config, _) = get_config().await.expect("Error getting config and DB pool");
+    let options = pool_options(&DatabaseConfig::default());
+    assert_eq!(options.get_max_connections(), 10);
+    assert_eq!(options.get_acquire_timeout(), Duration::from_secs(10));
+    assert_eq!(options.get_idle_timeout(), Some(Duration::from_secs(600)));
+    let options = pool_options(&DatabaseConfig { max_connections: 0, idle_timeout_secs: 0, ..Default::default() });
+    assert_eq!(options.get_max_connections(), 1);
+    assert_eq!(options.get_idle_timeout(), None);
+
+    // With every connection in use, a query fails with a 503 once the acquire timeout passes.
+    let database = DatabaseConfig { max_connections: 1, acquire_timeout_secs: 1, ..Default::default() };
+    let pool = connect(&config.database_url, &database).await.unwrap();
+    let held = pool.acquire().await.unwrap();
+    let err: ServerError = sqlx::query("SELECT 1").execute(&pool).await.unwrap_err().into();
+    assert_eq!(err.error_type, ErrorType::ServiceUnavailable);
+    drop(held);
+    sqlx::query("SELECT 1").execute(&pool).await.unwrap();
+}
+
+#[actix_web::test]
+async fn test_db_missing_default_categories() {
+    use crate::models::{coop::CoopPreview, maps::Maps};
+    use crate::tools::helpers::{calc_coop_aggregate_points, calc_sp_aggregate_points};
+    use std::collections::HashMap;
+    let (config, pool) = get_config().await.expect("Error getting config and DB pool");
+    // Maps without a default category are left out of the points, rather than panicking.
+    let sp_points = calc_sp_aggregate_points(&pool, &config, &HashMap::new()).await.unwrap();
+    let coop_points = calc_coop_aggregate_points(&pool, &config, &HashMap::new()).await.unwrap();
+    assert!(sp_points.is_empty());
+    assert!(coop_points.is_empty());
+    // The previews have to line up with the maps, so a missing default category is an error.
+    let has_coop_maps = !Maps::get_steam_ids(&pool, Some(true), Some(1)).await.unwrap().is_empty();
+    assert_eq!(CoopPreview::get_coop_previews(&pool, &HashMap::new(), 1).await.is_err(), has_coop_maps);
+}
+
+#[actix_web::test]
+async fn test_preview_generation() {
+    use crate::tools::cache::{COOP_PREVIEWS, SP_PREVIEWS};
+    let cache = test_cache_state();
+    // Previews invalidated while they were being rebuilt are not written, or marked as cached.
+    let generation = cache.preview_generation(SP_PREVIEWS).await;
+    cache.invalidate_all_previews().await;
+    assert_ne!(cache.preview_generation(SP_PREVIEWS).await, generation);
+    cache.write_previews(SP_PREVIEWS, generation, &vec![vec!["stale".to_string()]]).await.unwrap();
+    assert!(!cache.get_current_state(SP_PREVIEWS).await);
+    assert!(!cache.get_current_state(COOP_PREVIEWS).await);
+    // Marking an entry as cached does not count as an invalidation.
+    let generation = cache.preview_generation(SP_PREVIEWS).await;
+    cache.update_current_state(SP_PREVIEWS, true).await;
+    assert_eq!(cache.preview_generation(SP_PREVIEWS).await, generation);
+}
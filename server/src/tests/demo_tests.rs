@@ -0,0 +1,405 @@
+use futures::StreamExt;
+use crate::tools::backblaze::{
+    check_bucket_access, content_sha1, is_retryable, large_file_part_size, uses_large_file, with_retries, B2Auth, B2Error,
+    B2_MIN_PART_SIZE, LARGE_FILE_PART_SIZE,
+};
+use crate::tools::config::DemosConfig;
+use crate::tools::demo::{demo_matches_map, has_demo_magic, is_demo_file_name, parse_demo_bytes, sanitize_demo_file_name};
+use crate::tools::error::{ErrorType, ServerError};
+
+/// Builds a demo header for `map_name`, with the rest of the header filled with plausible values.
+pub fn demo_header(map_name: &str) -> Vec<u8> {
+    let mut bytes = b"HL2DEMO\0".to_vec();
+    bytes.extend(4i32.to_le_bytes());
+    bytes.extend(2001i32.to_le_bytes());
+    for s in ["localhost:0", "Player", map_name, "portal2"] {
+        let mut field = [0u8; 260];
+        field[..s.len()].copy_from_slice(s.as_bytes());
+        bytes.extend(field);
+    }
+    bytes.extend(17.5f32.to_le_bytes());
+    bytes.extend(1050i32.to_le_bytes());
+    bytes.extend(1049i32.to_le_bytes());
+    bytes.extend(0i32.to_le_bytes());
+    bytes
+}
+
+#[test]
+fn test_parse_demo_header() {
+    let mut bytes = demo_header("sp_a2_laser_vs_turret");
+    // Stand-in for the demo's messages.
+    bytes.extend([7u8; 32]);
+    let metadata = parse_demo_bytes(&bytes).unwrap();
+    assert_eq!(metadata.demo_protocol, 4);
+    assert_eq!(metadata.network_protocol, 2001);
+    assert_eq!(metadata.map_name, "sp_a2_laser_vs_turret");
+    assert_eq!(metadata.playback_ticks, 1050);
+    assert_eq!(metadata.playback_time, 17.5);
+    assert_eq!(metadata.sar_checksum, None);
+    assert!(demo_matches_map(&metadata, "47763"));
+    assert!(!demo_matches_map(&metadata, "47770"));
+}
+
+#[test]
+fn test_parse_demo_sar_checksum() {
+    let mut bytes = demo_header("sp_a2_triple_laser");
+    bytes.extend(17i32.to_le_bytes());
+    bytes.extend([0u8; 8]);
+    bytes.push(0xFF);
+    bytes.extend(0xDEADBEEFu32.to_le_bytes());
+    bytes.extend(0x1234ABCDu32.to_le_bytes());
+    let checksum = parse_demo_bytes(&bytes).unwrap().sar_checksum.unwrap();
+    assert_eq!(checksum.demo_sum, 0xDEADBEEF);
+    assert_eq!(checksum.sar_sum, 0x1234ABCD);
+}
+
+#[test]
+fn test_parse_demo_corrupt() {
+    let bytes = demo_header("sp_a2_triple_laser");
+    assert!(parse_demo_bytes(&bytes[..500]).is_err());
+    assert!(parse_demo_bytes(&[]).is_err());
+    let mut bad_magic = bytes.clone();
+    bad_magic[0] = b'X';
+    assert!(parse_demo_bytes(&bad_magic).is_err());
+}
+
+#[test]
+fn test_content_sha1() {
+    assert_eq!(content_sha1(b"abc"), "a9993e364706816aba3e25717850c26c9cd0d89d");
+    let header = demo_header("sp_a2_laser_vs_turret");
+    assert_ne!(content_sha1(&header), content_sha1(&demo_header("sp_a2_bts1")));
+}
+
+/// Tracks the bytes currently allocated by each thread, and the peak, so tests can check how much memory a call holds at once.
+mod alloc_tracking {
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::cell::Cell;
+
+    thread_local! {
+        static CURRENT: Cell<isize> = const { Cell::new(0) };
+        static PEAK: Cell<isize> = const { Cell::new(0) };
+    }
+
+    pub struct TrackingAlloc;
+
+    fn track(delta: isize) {
+        let _ = CURRENT.try_with(|current| {
+            current.set(current.get() + delta);
+            let _ = PEAK.try_with(|peak| peak.set(peak.get().max(current.get())));
+        });
+    }
+
+    unsafe impl GlobalAlloc for TrackingAlloc {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            track(layout.size() as isize);
+            System.alloc(layout)
+        }
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            track(-(layout.size() as isize));
+            System.dealloc(ptr, layout)
+        }
+    }
+
+    /// Resets the peak to the current allocation, and returns the current allocation.
+    pub fn reset_peak() -> isize {
+        let current = CURRENT.with(|current| current.get());
+        PEAK.with(|peak| peak.set(current));
+        current
+    }
+
+    pub fn peak() -> isize {
+        PEAK.with(|peak| peak.get())
+    }
+}
+
+#[global_allocator]
+static ALLOC: alloc_tracking::TrackingAlloc = alloc_tracking::TrackingAlloc;
+
+#[test]
+fn test_write_stream_to_file_bounded_memory() {
+    use crate::api::v1::handlers::demos::write_stream_to_file;
+    use actix_web::web::Bytes;
+    const CHUNK_SIZE: usize = 64 * 1024;
+    const CHUNKS: usize = 128;
+    let path = std::env::temp_dir().join("write_stream_to_file_test.dem");
+    let path = path.to_str().unwrap().to_string();
+    // Chunks are only allocated as the stream is polled, like an upload arriving over the network.
+    let mut stream = futures::stream::iter(0..CHUNKS)
+        .map(|i| Ok::<_, std::io::Error>(Bytes::from(vec![(i % 256) as u8; CHUNK_SIZE])));
+    let baseline = alloc_tracking::reset_peak();
+    let written = futures::executor::block_on(write_stream_to_file(&mut stream, &path, u64::MAX)).unwrap();
+    let peak = alloc_tracking::peak() - baseline;
+    assert_eq!(written, (CHUNK_SIZE * CHUNKS) as u64);
+    assert_eq!(std::fs::metadata(&path).unwrap().len(), (CHUNK_SIZE * CHUNKS) as u64);
+    std::fs::remove_file(&path).unwrap();
+    // An 8MB upload should never have more than a few chunks in memory at once.
+    assert!(peak < (CHUNK_SIZE * 4) as isize, "peak allocation was {peak} bytes");
+}
+
+#[test]
+fn test_sanitize_demo_file_name() {
+    assert_eq!(sanitize_demo_file_name("run.dem").unwrap(), "run.dem");
+    assert_eq!(sanitize_demo_file_name("../../etc/passwd").unwrap(), "passwd");
+    assert_eq!(sanitize_demo_file_name("..\\..\\run.dem").unwrap(), "run.dem");
+    assert_eq!(sanitize_demo_file_name("/tmp/run.dem").unwrap(), "run.dem");
+    assert!(sanitize_demo_file_name("").is_err());
+    assert!(sanitize_demo_file_name("..").is_err());
+    assert!(sanitize_demo_file_name("demos/").is_err());
+}
+
+#[actix_web::test]
+async fn test_multipart_traversal_file_name() {
+    use crate::api::v1::handlers::demos::parse_and_write_multipart;
+    use actix_multipart::Multipart;
+    use actix_web::{
+        error::PayloadError,
+        http::header::{HeaderMap, HeaderValue, CONTENT_TYPE},
+        web::Bytes,
+    };
+    let boundary = "demoboundary";
+    let mut body = format!(
+        "--{boundary}\r\nContent-Disposition: form-data; name=\"demo\"; filename=\"../../traversal_test.dem\"\r\n\r\n"
+    )
+    .into_bytes();
+    body.extend(demo_header("sp_a2_laser_vs_turret"));
+    body.extend(format!("\r\n--{boundary}--\r\n").into_bytes());
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_str(&format!("multipart/form-data; boundary={boundary}")).unwrap(),
+    );
+    let stream = futures::stream::iter(vec![Ok::<_, PayloadError>(Bytes::from(body))]);
+    let mut payload = Multipart::new(&headers, stream);
+    let mut file_name = String::new();
+    let metadata = parse_and_write_multipart(&mut payload, &mut file_name, DemosConfig::default().max_bytes).await.unwrap();
+    assert_eq!(metadata.map_name, "sp_a2_laser_vs_turret");
+    assert!(!file_name.contains("traversal_test"));
+    assert!(!std::path::Path::new("../../traversal_test.dem").exists());
+    std::fs::remove_file(format!("./demos/{file_name}")).unwrap();
+}
+
+#[actix_web::test]
+async fn test_multipart_same_file_name() {
+    use crate::api::v1::handlers::demos::parse_and_write_multipart;
+    let max_bytes = DemosConfig::default().max_bytes;
+    let demo = demo_header("sp_a2_laser_vs_turret");
+    // Two uploads with the same name are written to different files.
+    let (mut first, mut second) = (String::new(), String::new());
+    let (mut first_payload, mut second_payload) =
+        (demo_multipart("same_name_test.dem", &demo), demo_multipart("same_name_test.dem", &demo));
+    let (a, b) = futures::join!(
+        parse_and_write_multipart(&mut first_payload, &mut first, max_bytes),
+        parse_and_write_multipart(&mut second_payload, &mut second, max_bytes),
+    );
+    a.unwrap();
+    b.unwrap();
+    assert_ne!(first, second);
+    std::fs::remove_file(format!("./demos/{first}")).unwrap();
+    std::fs::remove_file(format!("./demos/{second}")).unwrap();
+}
+
+#[actix_web::test]
+async fn test_multipart_extra_file() {
+    use crate::api::v1::handlers::demos::parse_and_write_multipart;
+    use actix_web::{
+        error::PayloadError,
+        http::header::{HeaderMap, HeaderValue, CONTENT_TYPE},
+        web::Bytes,
+    };
+    let boundary = "demoboundary";
+    let mut body = Vec::new();
+    for name in ["extra_file_test.dem", "extra_file_test2.dem"] {
+        body.extend(format!("--{boundary}\r\nContent-Disposition: form-data; name=\"demo\"; filename=\"{name}\"\r\n\r\n").into_bytes());
+        body.extend(demo_header("sp_a2_laser_vs_turret"));
+        body.extend(b"\r\n");
+    }
+    body.extend(format!("--{boundary}--\r\n").into_bytes());
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_str(&format!("multipart/form-data; boundary={boundary}")).unwrap(),
+    );
+    let stream = futures::stream::iter(vec![Ok::<_, PayloadError>(Bytes::from(body))]);
+    let mut payload = actix_multipart::Multipart::new(&headers, stream);
+    let mut file_name = String::new();
+    let err = parse_and_write_multipart(&mut payload, &mut file_name, DemosConfig::default().max_bytes).await.unwrap_err();
+    assert_eq!(ServerError::from(err).error_type, ErrorType::BadRequest);
+    // The first file is not left behind.
+    assert!(file_name.is_empty());
+}
+
+/// Multipart body with a single file field, for [parse_and_write_multipart](crate::api::v1::handlers::demos::parse_and_write_multipart).
+fn demo_multipart(file_name: &str, contents: &[u8]) -> actix_multipart::Multipart {
+    use actix_web::{
+        error::PayloadError,
+        http::header::{HeaderMap, HeaderValue, CONTENT_TYPE},
+        web::Bytes,
+    };
+    let boundary = "demoboundary";
+    let mut body =
+        format!("--{boundary}\r\nContent-Disposition: form-data; name=\"demo\"; filename=\"{file_name}\"\r\n\r\n").into_bytes();
+    body.extend(contents);
+    body.extend(format!("\r\n--{boundary}--\r\n").into_bytes());
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_str(&format!("multipart/form-data; boundary={boundary}")).unwrap(),
+    );
+    actix_multipart::Multipart::new(&headers, futures::stream::iter(vec![Ok::<_, PayloadError>(Bytes::from(body))]))
+}
+
+#[test]
+fn test_demo_file_type() {
+    assert!(is_demo_file_name("run.dem"));
+    assert!(is_demo_file_name("RUN.DEM"));
+    assert!(!is_demo_file_name("run.dem.exe"));
+    assert!(!is_demo_file_name("run.txt"));
+    assert!(!is_demo_file_name(".dem"));
+    assert!(!is_demo_file_name("dem"));
+    assert!(has_demo_magic(&demo_header("sp_a2_laser_vs_turret")));
+    assert!(!has_demo_magic(b"HL2DEMO"));
+    assert!(!has_demo_magic(b"PK\x03\x04 not a demo"));
+}
+
+#[actix_web::test]
+async fn test_multipart_wrong_type() {
+    use crate::api::v1::handlers::demos::parse_and_write_multipart;
+    let max_bytes = DemosConfig::default().max_bytes;
+    // Rejected by the extension before anything is written.
+    let mut file_name = String::new();
+    let err = parse_and_write_multipart(&mut demo_multipart("wrong_type_test.txt", b"hello"), &mut file_name, max_bytes)
+        .await
+        .unwrap_err();
+    assert_eq!(ServerError::from(err).error_type, ErrorType::UnsupportedMediaType);
+    assert!(!std::path::Path::new("./demos/wrong_type_test.txt").exists());
+    // Named like a demo, but missing the demo header.
+    let mut contents = b"PK\x03\x04".to_vec();
+    contents.extend(&demo_header("sp_a2_laser_vs_turret")[8..]);
+    let err = parse_and_write_multipart(&mut demo_multipart("wrong_magic_test.dem", &contents), &mut file_name, max_bytes)
+        .await
+        .unwrap_err();
+    assert_eq!(ServerError::from(err).error_type, ErrorType::UnsupportedMediaType);
+    assert!(!std::path::Path::new("./demos/wrong_magic_test.dem").exists());
+    assert!(file_name.is_empty());
+}
+
+#[actix_web::test]
+async fn test_multipart_oversize() {
+    use crate::api::v1::handlers::demos::{parse_and_write_multipart, write_stream_to_file};
+    use actix_web::web::Bytes;
+    let demo = demo_header("sp_a2_laser_vs_turret");
+    let mut file_name = String::new();
+    let err = parse_and_write_multipart(&mut demo_multipart("oversize_test.dem", &demo), &mut file_name, demo.len() as u64 - 1)
+        .await
+        .unwrap_err();
+    assert_eq!(ServerError::from(err).error_type, ErrorType::PayloadTooLarge);
+    assert!(!std::path::Path::new("./demos/oversize_test.dem").exists());
+    // The upload is stopped at the first chunk past the limit, the rest of the stream is never read.
+    let path = std::env::temp_dir().join("write_stream_to_file_limit_test.dem");
+    let path = path.to_str().unwrap().to_string();
+    let mut read = 0;
+    let mut stream = futures::stream::iter(0..10).map(|_| {
+        read += 1;
+        Ok::<_, std::io::Error>(Bytes::from(vec![0u8; 1024]))
+    });
+    let err = write_stream_to_file(&mut stream, &path, 2048).await.unwrap_err();
+    assert_eq!(ServerError::from(err).error_type, ErrorType::PayloadTooLarge);
+    assert_eq!(read, 3);
+    assert!(!std::path::Path::new(&path).exists());
+    // Exactly at the limit is allowed.
+    let mut stream = futures::stream::iter(0..2).map(|_| Ok::<_, std::io::Error>(Bytes::from(vec![0u8; 1024])));
+    assert_eq!(write_stream_to_file(&mut stream, &path, 2048).await.unwrap(), 2048);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[actix_web::test]
+async fn test_upload_retries() {
+    use std::time::{Duration, Instant};
+    let b2_error = |status: u16, code: &str| {
+        anyhow::Error::new(B2Error { status, code: code.to_string(), message: "test".to_string() })
+    };
+    // Each attempt asks for a new upload url, the first two uploads fail with a 503.
+    let mut upload_urls = Vec::new();
+    let start = Instant::now();
+    let res = with_retries(3, Duration::from_millis(10), |attempt| {
+        upload_urls.push(format!("https://upload.example/{attempt}"));
+        async move {
+            if attempt < 3 {
+                return Err(b2_error(503, "service_unavailable"));
+            }
+            Ok(attempt)
+        }
+    })
+    .await;
+    assert_eq!(res.unwrap(), 3);
+    assert_eq!(upload_urls.len(), 3);
+    assert_eq!(upload_urls[0], "https://upload.example/1");
+    assert_eq!(upload_urls[2], "https://upload.example/3");
+    // Waited 10ms, then 20ms.
+    assert!(start.elapsed() >= Duration::from_millis(30));
+
+    let mut calls = 0;
+    let res: anyhow::Result<()> = with_retries(2, Duration::from_millis(1), |attempt| {
+        calls += 1;
+        async move { Err(b2_error(500, &format!("failure_{attempt}"))) }
+    })
+    .await;
+    assert_eq!(res.unwrap_err().to_string(), "BackBlaze returned 500 (failure_2) -> test");
+    assert_eq!(calls, 2);
+
+    // A 4xx fails the same way again, so it is returned after the first attempt.
+    let mut calls = 0;
+    let res: anyhow::Result<()> = with_retries(3, Duration::from_millis(1), |_| {
+        calls += 1;
+        async { Err(b2_error(400, "bad_request")) }
+    })
+    .await;
+    assert_eq!(res.unwrap_err().downcast_ref::<B2Error>().unwrap().status, 400);
+    assert_eq!(calls, 1);
+
+    assert!(is_retryable(&b2_error(503, "service_unavailable")));
+    assert!(!is_retryable(&b2_error(401, "expired_auth_token")));
+    assert!(!is_retryable(&b2_error(404, "not_found")));
+    assert!(!is_retryable(&anyhow::anyhow!("BackBlaze did not return a file id for the large file")));
+}
+
+#[test]
+fn test_check_bucket_access() {
+    let auth = |allowed: serde_json::Value| -> B2Auth {
+        serde_json::from_value(serde_json::json!({
+            "authorizationToken": "token",
+            "apiUrl": "https://api001.backblazeb2.com",
+            "downloadUrl": "https://f001.backblazeb2.com",
+            "allowed": allowed,
+        }))
+        .unwrap()
+    };
+    // Keys for the whole account can access any bucket.
+    let unrestricted = auth(serde_json::json!({ "bucketId": null, "capabilities": ["readFiles", "deleteFiles"] }));
+    assert!(check_bucket_access(&unrestricted, "active_bucket").is_ok());
+    assert!(check_bucket_access(&unrestricted, "retired_bucket").is_ok());
+    // Keys restricted to a bucket cannot reach demos stored in another.
+    let restricted = auth(serde_json::json!({ "bucketId": "active_bucket", "bucketName": "demos" }));
+    assert!(check_bucket_access(&restricted, "active_bucket").is_ok());
+    let err = check_bucket_access(&restricted, "retired_bucket").unwrap_err();
+    assert!(err.to_string().contains("retired_bucket"));
+}
+
+#[test]
+fn test_large_file_upload_path() {
+    const MB: u64 = 1024 * 1024;
+    let threshold = 200 * MB;
+    // Demos at or under the threshold keep using a single upload.
+    assert!(!uses_large_file(30 * MB, threshold));
+    assert!(!uses_large_file(threshold, threshold));
+    assert!(uses_large_file(threshold + 1, threshold));
+    // A low threshold does not send files too small to split into two parts through the large file API.
+    assert!(!uses_large_file(2 * B2_MIN_PART_SIZE - 1, 0));
+    assert!(uses_large_file(2 * B2_MIN_PART_SIZE, 0));
+
+    assert_eq!(large_file_part_size(1024 * MB), LARGE_FILE_PART_SIZE);
+    // Smaller files are still split into at least two parts, of at least the minimum part size.
+    assert_eq!(large_file_part_size(150 * MB), 75 * MB);
+    assert_eq!(large_file_part_size(11 * MB), B2_MIN_PART_SIZE + MB / 2);
+    assert_eq!(large_file_part_size(2 * B2_MIN_PART_SIZE), B2_MIN_PART_SIZE);
+}
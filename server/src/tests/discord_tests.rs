@@ -0,0 +1,44 @@
+use crate::models::changelog::ChangelogInsert;
+use crate::tools::discord::{is_announced_wr, wr_message};
+use crate::tools::helpers::{format_score, DEFAULT_TICK_RATE};
+
+#[test]
+fn test_format_score() {
+    assert_eq!(format_score(1763, DEFAULT_TICK_RATE), "17.63");
+    assert_eq!(format_score(605, DEFAULT_TICK_RATE), "6.05");
+    assert_eq!(format_score(6000, DEFAULT_TICK_RATE), "1:00.00");
+    assert_eq!(format_score(24185, DEFAULT_TICK_RATE), "4:01.85");
+}
+
+#[test]
+fn test_wr_message() {
+    let changelog = ChangelogInsert {
+        profile_number: "76561198040982247".to_string(),
+        score: 1763,
+        map_id: "47763".to_string(),
+        post_rank: Some(1),
+        score_delta: Some(-12),
+        youtube_id: Some("DPgJgmLmzCw".to_string()),
+        ..Default::default()
+    };
+    let message = wr_message(&changelog, "Laser vs. Turret", DEFAULT_TICK_RATE);
+    let embed = &message["embeds"][0];
+    assert_eq!(embed["title"], "New World Record on Laser vs. Turret");
+    let description = embed["description"].as_str().unwrap();
+    assert!(description.contains("**17.63** (-0.12)"));
+    assert!(description.contains("https://youtu.be/DPgJgmLmzCw"));
+    // Games counted in ticks are formatted at their tick rate.
+    let message = wr_message(&changelog, "Laser vs. Turret", 60);
+    assert!(message["embeds"][0]["description"].as_str().unwrap().contains("**29.38** (-0.20)"));
+}
+
+#[test]
+fn test_is_announced_wr() {
+    let wr = ChangelogInsert { post_rank: Some(1), verified: Some(true), ..Default::default() };
+    assert!(is_announced_wr(&wr));
+    // Unverified and unreviewed world records wait for an admin.
+    assert!(!is_announced_wr(&ChangelogInsert { verified: Some(false), ..wr.clone() }));
+    assert!(!is_announced_wr(&ChangelogInsert { verified: None, ..wr.clone() }));
+    assert!(!is_announced_wr(&ChangelogInsert { banned: true, ..wr.clone() }));
+    assert!(!is_announced_wr(&ChangelogInsert { post_rank: Some(2), ..wr }));
+}
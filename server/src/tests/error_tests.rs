@@ -0,0 +1,66 @@
+use crate::tools::error::{ErrResponse, ErrorType, ServerError};
+use actix_web::{body::to_bytes, http::StatusCode, ResponseError};
+
+#[actix_web::test]
+async fn test_not_found_error_body() {
+    let err = ServerError::new(ErrorType::NotFound, "Could not find demo.");
+    let resp = err.error_response();
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    let body = to_bytes(resp.into_body()).await.unwrap();
+    let body: ErrResponse = serde_json::from_slice(&body).unwrap();
+    assert_eq!(body.error_type, ErrorType::NotFound);
+    assert_eq!(body.message, "Could not find demo.");
+    // Clients match on the snake_case name of the error type.
+    assert!(std::str::from_utf8(&to_bytes(err.error_response().into_body()).await.unwrap())
+        .unwrap()
+        .contains("\"error_type\":\"not_found\""));
+}
+
+#[test]
+fn test_auth_error_status() {
+    let err = ServerError::new(ErrorType::Unauthorized, "A valid auth token is required.");
+    assert_eq!(err.status_code(), StatusCode::UNAUTHORIZED);
+    let err = ServerError::new(ErrorType::Forbidden, "Only admins can do this.");
+    assert_eq!(err.status_code(), StatusCode::FORBIDDEN);
+}
+
+#[test]
+fn test_upload_error_status() {
+    let err = ServerError::new(ErrorType::PayloadTooLarge, "Demos can be at most 1024 bytes.");
+    assert_eq!(err.status_code(), StatusCode::PAYLOAD_TOO_LARGE);
+    let err = ServerError::new(ErrorType::UnsupportedMediaType, "Only .dem files can be uploaded.");
+    assert_eq!(err.status_code(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+}
+
+#[test]
+fn test_conflict_error_status() {
+    let err = ServerError::new(ErrorType::Conflict, "Changelog entry 1 was changed since it was fetched.");
+    assert_eq!(err.status_code(), StatusCode::CONFLICT);
+}
+
+#[test]
+fn test_pool_timeout_error() {
+    // Running out of database connections can be retried, other database errors are internal.
+    let err = ServerError::from(sqlx::Error::PoolTimedOut);
+    assert_eq!(err.error_type, ErrorType::ServiceUnavailable);
+    assert_eq!(err.retry_after, Some(1));
+    assert_eq!(ServerError::from(sqlx::Error::RowNotFound).error_type, ErrorType::DbError);
+}
+
+#[test]
+fn test_service_unavailable_error_status() {
+    let err = ServerError::new(ErrorType::ServiceUnavailable, "The boards are in maintenance mode.");
+    assert_eq!(err.status_code(), StatusCode::SERVICE_UNAVAILABLE);
+}
+
+#[test]
+fn test_server_error_through_anyhow() {
+    // Errors returned through helpers using anyhow keep their type.
+    let err: anyhow::Error = ServerError::new(ErrorType::Unprocessable, "Invalid YouTube video.").into();
+    let err = ServerError::from(err);
+    assert_eq!(err.error_type, ErrorType::Unprocessable);
+    assert_eq!(err.status_code(), StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(err.error_message, "Invalid YouTube video.");
+    let err = ServerError::from(anyhow::anyhow!("User is banned"));
+    assert_eq!(err.error_type, ErrorType::Internal);
+}
@@ -0,0 +1,79 @@
+use crate::models::chapters::ExportFormat;
+use crate::models::coop::{CoopMap, CoopRanked};
+use crate::tools::export::{csv_response, escape_formula, wants_csv, CoopCsvRow, SpCsvRow};
+use actix_web::{body::to_bytes, http::header, test};
+
+#[actix_web::test]
+async fn test_wants_csv() {
+    let req = |accept: &str| test::TestRequest::default().insert_header((header::ACCEPT, accept)).to_http_request();
+    assert!(wants_csv(&req("text/csv"), None));
+    assert!(wants_csv(&req("application/json;q=0.5, text/csv"), None));
+    assert!(!wants_csv(&req("text/html,application/xhtml+xml,*/*;q=0.8"), None));
+    assert!(!wants_csv(&test::TestRequest::default().to_http_request(), None));
+    // An explicit format wins over the header.
+    assert!(wants_csv(&req("application/json"), Some(ExportFormat::Csv)));
+    assert!(!wants_csv(&req("text/csv"), Some(ExportFormat::Json)));
+}
+
+#[actix_web::test]
+async fn test_csv_response() {
+    let res = csv_response(Vec::<SpCsvRow>::new());
+    assert_eq!(res.headers().get(header::CONTENT_TYPE).unwrap(), "text/csv; charset=utf-8");
+    let body = to_bytes(res.into_body()).await.unwrap();
+    assert_eq!(body, "rank,profile_number,user_name,score,points\n");
+
+    // Rows past the first chunk are still written, and fields are quoted when needed.
+    let rows = (1..=300).map(|rank| SpCsvRow {
+        rank,
+        profile_number: rank.to_string(),
+        user_name: (rank == 1).then(|| "Zypeh, \"the\" runner".to_string()),
+        score: 1000 + rank,
+        points: 1.5,
+    });
+    let body = to_bytes(csv_response(rows).into_body()).await.unwrap();
+    let body = std::str::from_utf8(&body).unwrap();
+    assert_eq!(body.lines().count(), 301);
+    assert_eq!(body.lines().nth(1).unwrap(), r#"1,1,"Zypeh, ""the"" runner",1001,1.5"#);
+    assert_eq!(body.lines().last().unwrap(), "300,300,,1300,1.5");
+}
+
+#[actix_web::test]
+async fn test_escape_formula() {
+    for name in ["=HYPERLINK(\"http://example.com\")", "+1", "-1", "@SUM(A1)", "\tname"] {
+        assert_eq!(escape_formula(name.to_string()), format!("'{name}"));
+    }
+    assert_eq!(escape_formula("Zypeh".to_string()), "Zypeh");
+    assert_eq!(escape_formula("a=b".to_string()), "a=b");
+    assert_eq!(escape_formula(String::new()), "");
+}
+
+#[actix_web::test]
+async fn test_csv_formula_names() {
+    let coop = CoopRanked {
+        map_data: CoopMap {
+            timestamp: None,
+            profile_number1: "1".to_string(),
+            profile_number2: "2".to_string(),
+            score: 1000,
+            p1_is_host: None,
+            demo_id1: None,
+            demo_id2: None,
+            youtube_id1: None,
+            youtube_id2: None,
+            submission1: 0,
+            submission2: 0,
+            note1: None,
+            note2: None,
+            category_id: 1,
+            user_name1: "=1+1".to_string(),
+            user_name2: Some("@cmd".to_string()),
+            avatar1: None,
+            avatar2: None,
+        },
+        rank: 1,
+        points: 200.0,
+        formatted_score: None,
+    };
+    let body = to_bytes(csv_response(vec![CoopCsvRow::from(coop)]).into_body()).await.unwrap();
+    assert_eq!(std::str::from_utf8(&body).unwrap().lines().nth(1).unwrap(), "1,1,'=1+1,2,'@cmd,1000,200.0");
+}
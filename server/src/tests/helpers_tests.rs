@@ -0,0 +1,348 @@
+use crate::models::changelog::{Changelog, ChangelogSource};
+use crate::models::coop::{CoopMap, CoopPreview};
+use crate::models::sp::SpMap;
+use crate::tools::helpers::{
+    build_pb_timeline, clean_note, dedup_coop_previews, escape_like, filter_coop_entries, format_score, normalize_youtube_id, parse_score, parse_submission_timestamp,
+    points_for_rank, rank_point_totals, rank_sp_entries, results_limit, score, PointTotals, DEFAULT_TICK_RATE,
+};
+use crate::tools::config::{PointsConfig, ProofConfig};
+use chrono::NaiveDate;
+
+fn sp_entry(profile_number: &str, score: i32) -> SpMap {
+    SpMap {
+        timestamp: None,
+        profile_number: profile_number.to_string(),
+        score,
+        demo_id: None,
+        youtube_id: None,
+        submission: 0,
+        note: None,
+        category_id: 49,
+        user_name: None,
+        avatar: None,
+        verified: None,
+        banned: None,
+    }
+}
+
+#[test]
+fn test_sp_tied_ranks() {
+    let entries = vec![
+        sp_entry("1", 1000),
+        sp_entry("2", 1001),
+        sp_entry("3", 1001),
+        sp_entry("4", 1002),
+    ];
+    let ranked = rank_sp_entries(entries, 0, 1);
+    let ranks: Vec<i32> = ranked.iter().map(|x| x.rank).collect();
+    assert_eq!(ranks, vec![1, 2, 2, 4]);
+    assert_eq!(ranked[1].points, ranked[2].points);
+    assert_eq!(ranked[2].points, score(2));
+    assert_eq!(ranked[3].points, score(4));
+}
+
+#[test]
+fn test_sp_paged_ranks() {
+    let entries = vec![sp_entry("101", 2000), sp_entry("102", 2001), sp_entry("103", 2001)];
+    let ranks: Vec<i32> = rank_sp_entries(entries, 100, 101).iter().map(|x| x.rank).collect();
+    assert_eq!(ranks, vec![101, 102, 102]);
+    // The first entry on the page tied with the last two entries of the previous page.
+    let entries = vec![sp_entry("101", 1999), sp_entry("102", 2000)];
+    let ranks: Vec<i32> = rank_sp_entries(entries, 100, 99).iter().map(|x| x.rank).collect();
+    assert_eq!(ranks, vec![99, 102]);
+}
+
+#[test]
+fn test_point_totals_ranks() {
+    let mut totals = PointTotals::new();
+    totals.insert("1".to_string(), (Some("a".to_string()), 150.0));
+    totals.insert("2".to_string(), (None, 400.0));
+    totals.insert("3".to_string(), (None, 150.0));
+    totals.insert("4".to_string(), (None, 10.5));
+    let ranked = rank_point_totals(totals);
+    let order: Vec<(&str, i32)> = ranked.iter().map(|x| (x.profile_number.as_str(), x.rank)).collect();
+    assert_eq!(order, vec![("2", 1), ("1", 2), ("3", 2), ("4", 4)]);
+    assert_eq!(ranked[1].user_name.as_deref(), Some("a"));
+}
+
+fn history_entry(day: u32, score: i32, post_rank: Option<i32>) -> Changelog {
+    Changelog {
+        id: day as i64,
+        timestamp: NaiveDate::from_ymd_opt(2022, 1, day).and_then(|d| d.and_hms_opt(0, 0, 0)),
+        profile_number: "1".to_string(),
+        score,
+        map_id: "47458".to_string(),
+        demo_id: None,
+        banned: false,
+        youtube_id: None,
+        previous_id: None,
+        coop_id: None,
+        post_rank,
+        pre_rank: None,
+        submission: 0,
+        note: None,
+        category_id: 49,
+        score_delta: None,
+        verified: None,
+        admin_note: None,
+        updated: None,
+        source: ChangelogSource::SteamAuto,
+    }
+}
+
+#[test]
+fn test_pb_timeline() {
+    // History comes back newest first.
+    let history = vec![
+        history_entry(4, 2300, Some(1)),
+        history_entry(3, 2310, None),
+        history_entry(2, 2320, Some(5)),
+        history_entry(1, 2330, None),
+    ];
+    let timeline = build_pb_timeline(history);
+    let scores: Vec<i32> = timeline.iter().map(|x| x.score).collect();
+    assert_eq!(scores, vec![2330, 2320, 2310, 2300]);
+    let ranks: Vec<Option<i32>> = timeline.iter().map(|x| x.rank).collect();
+    assert_eq!(ranks, vec![None, Some(5), Some(5), Some(1)]);
+    assert_eq!(timeline[0].points, 0.0);
+    assert_eq!(timeline[2].points, score(5));
+    assert_eq!(timeline[3].points, score(1));
+}
+
+#[test]
+fn test_format_score_boundaries() {
+    assert_eq!(format_score(0, DEFAULT_TICK_RATE), "0.00");
+    assert_eq!(format_score(7, DEFAULT_TICK_RATE), "0.07");
+    assert_eq!(format_score(99, DEFAULT_TICK_RATE), "0.99");
+    assert_eq!(format_score(100, DEFAULT_TICK_RATE), "1.00");
+    assert_eq!(format_score(5999, DEFAULT_TICK_RATE), "59.99");
+    assert_eq!(format_score(6000, DEFAULT_TICK_RATE), "1:00.00");
+    assert_eq!(format_score(359999, DEFAULT_TICK_RATE), "59:59.99");
+    // Over an hour stays in minutes.
+    assert_eq!(format_score(360000, DEFAULT_TICK_RATE), "60:00.00");
+    assert_eq!(format_score(372305, DEFAULT_TICK_RATE), "62:03.05");
+    assert_eq!(format_score(-12, DEFAULT_TICK_RATE), "-0.12");
+}
+
+#[test]
+fn test_parse_score() {
+    for score in [0, 7, 99, 100, 2326, 5999, 6000, 359999, 360000, 372305] {
+        assert_eq!(parse_score(&format_score(score, DEFAULT_TICK_RATE), DEFAULT_TICK_RATE).unwrap(), score);
+    }
+    assert_eq!(parse_score("05.00", DEFAULT_TICK_RATE).unwrap(), 500);
+    for invalid in ["", "23", "23.2", "23.260", "1:5.00", "1:60.00", "a:00.00", "1:00.0a", ":01.00", "-1.00", "99999999:00.00"] {
+        assert!(parse_score(invalid, DEFAULT_TICK_RATE).is_err(), "{invalid:?} should not parse");
+    }
+}
+
+#[test]
+fn test_format_score_tick_rate() {
+    // The same tick count is a different time at different tick rates.
+    assert_eq!(format_score(3600, 60), "1:00.00");
+    assert_eq!(format_score(3600, 66), "54.54");
+    assert_eq!(format_score(59, 60), "0.98");
+    assert_eq!(format_score(65, 66), "0.98");
+    assert_eq!(format_score(-30, 60), "-0.50");
+    assert_eq!(parse_score("1:00.00", 60).unwrap(), 3600);
+    assert_eq!(parse_score("54.54", 66).unwrap(), 3600);
+    for tick_rate in [60, 66, DEFAULT_TICK_RATE] {
+        for score in (0..=200).chain([3599, 3600, 216000, 1000003]) {
+            assert_eq!(parse_score(&format_score(score, tick_rate), tick_rate).unwrap(), score, "{score} at {tick_rate}");
+        }
+    }
+}
+
+fn coop_entry(profile_number1: &str, profile_number2: &str, score: i32) -> CoopMap {
+    CoopMap {
+        timestamp: None,
+        profile_number1: profile_number1.to_string(),
+        profile_number2: profile_number2.to_string(),
+        score,
+        p1_is_host: None,
+        demo_id1: None,
+        demo_id2: None,
+        youtube_id1: None,
+        youtube_id2: None,
+        submission1: 0,
+        submission2: 0,
+        note1: None,
+        note2: None,
+        category_id: 1,
+        user_name1: profile_number1.to_string(),
+        user_name2: Some(profile_number2.to_string()),
+        avatar1: None,
+        avatar2: None,
+    }
+}
+
+#[actix_web::test]
+async fn test_coop_tied_ranks() {
+    let entries = vec![
+        coop_entry("a", "b", 1000),
+        // Not a PB for either player, so it is filtered out.
+        coop_entry("a", "b", 1005),
+        coop_entry("c", "d", 1010),
+        coop_entry("e", "f", 1010),
+        // A PB for g, so it is kept and ties.
+        coop_entry("c", "g", 1010),
+        coop_entry("h", "i", 1020),
+        // Ties with h & i, but is filtered out, so it doesn't affect the next rank.
+        coop_entry("e", "f", 1020),
+        coop_entry("j", "k", 1025),
+    ];
+    let ranked = filter_coop_entries(entries.clone(), 10).await;
+    let ranks: Vec<(i32, i32)> = ranked.iter().map(|x| (x.map_data.score, x.rank)).collect();
+    assert_eq!(ranks, vec![(1000, 1), (1010, 2), (1010, 2), (1010, 2), (1020, 5), (1025, 6)]);
+    assert!(ranked[1..4].iter().all(|x| x.points == score(2)));
+    assert_eq!(ranked[4].points, score(5));
+    assert_eq!(filter_coop_entries(entries, 3).await.len(), 3);
+}
+
+fn coop_preview(profile_number1: &str, profile_number2: Option<&str>, score: i32) -> CoopPreview {
+    CoopPreview {
+        profile_number1: profile_number1.to_string(),
+        profile_number2: profile_number2.map(|p| p.to_string()),
+        score,
+        youtube_id1: None,
+        youtube_id2: None,
+        category_id: 1,
+        user_name1: profile_number1.to_string(),
+        user_name2: profile_number2.map(|p| p.to_string()),
+        map_id: "47741".to_string(),
+    }
+}
+
+#[test]
+fn test_dedup_coop_previews_missing_partner() {
+    let entries = vec![
+        coop_preview("a", None, 100),
+        coop_preview("b", Some("c"), 110),
+        // Player 1 was already seen with no partner, and the placeholder is never new.
+        coop_preview("a", Some("N/A"), 120),
+        coop_preview("b", Some("c"), 130),
+        coop_preview("c", Some("d"), 140),
+        coop_preview("e", None, 150),
+    ];
+    let deduped = dedup_coop_previews(entries, 7);
+    let scores: Vec<i32> = deduped.iter().map(|e| e.score).collect();
+    assert_eq!(scores, vec![100, 110, 140, 150]);
+    let entries = (0..10).map(|i| coop_preview(&format!("p{i}"), None, i)).collect();
+    assert_eq!(dedup_coop_previews(entries, 7).len(), 7);
+}
+
+#[test]
+fn test_normalize_youtube_id() {
+    let normalized = normalize_youtube_id;
+    let expected = Some("DPgJgmLmzCw".to_string());
+    let expected_start = Some("DPgJgmLmzCw?start=90".to_string());
+    // Bare ids, including the form already stored.
+    assert_eq!(normalized("DPgJgmLmzCw"), expected);
+    assert_eq!(normalized("  DPgJgmLmzCw "), expected);
+    assert_eq!(normalized("DPgJgmLmzCw?start=0"), expected);
+    assert_eq!(normalized("DPgJgmLmzCw?start=90"), expected_start);
+    // youtube.com links.
+    assert_eq!(normalized("https://www.youtube.com/watch?v=DPgJgmLmzCw"), expected);
+    assert_eq!(normalized("https://youtube.com/watch?feature=share&v=DPgJgmLmzCw&t=90s"), expected_start);
+    assert_eq!(normalized("http://m.youtube.com/watch?v=DPgJgmLmzCw&t=1m30s"), expected_start);
+    assert_eq!(normalized("www.youtube.com/watch?v=DPgJgmLmzCw&t=90"), expected_start);
+    assert_eq!(normalized("https://www.youtube.com/embed/DPgJgmLmzCw?start=90"), expected_start);
+    assert_eq!(normalized("https://www.youtube.com/shorts/DPgJgmLmzCw"), expected);
+    assert_eq!(normalized("https://www.youtube.com/live/DPgJgmLmzCw?si=abc"), expected);
+    // youtu.be links.
+    assert_eq!(normalized("https://youtu.be/DPgJgmLmzCw"), expected);
+    assert_eq!(normalized("youtu.be/DPgJgmLmzCw?t=90"), expected_start);
+    assert_eq!(normalized("https://youtu.be/DPgJgmLmzCw?si=abc&t=1h"), Some("DPgJgmLmzCw?start=3600".to_string()));
+    // Invalid ids, offsets and links.
+    assert_eq!(normalized(""), None);
+    assert_eq!(normalized("DPgJgmLmzC"), None);
+    assert_eq!(normalized("DPgJgmLmzCw!"), None);
+    assert_eq!(normalized("DPgJgmLmzCw?t=90"), None);
+    assert_eq!(normalized("DPgJgmLmzCw?start=soon"), None);
+    assert_eq!(normalized("https://youtu.be/DPgJgmLmzCw?t=1m30"), None);
+    assert_eq!(normalized("https://www.youtube.com/watch?list=PL123"), None);
+    assert_eq!(normalized("https://www.youtube.com/channel/UCDPgJgmLmzCw"), None);
+    assert_eq!(normalized("https://notyoutube.com/watch?v=DPgJgmLmzCw"), None);
+    assert_eq!(normalized("https://vimeo.com/DPgJgmLmzCw"), None);
+}
+
+#[test]
+fn test_parse_submission_timestamp() {
+    use crate::tools::error::ErrorType;
+    let now = NaiveDate::from_ymd_opt(2022, 1, 1).unwrap().and_hms_opt(12, 0, 0).unwrap();
+    let at = |h, m, s| NaiveDate::from_ymd_opt(2020, 8, 18).unwrap().and_hms_opt(h, m, s).unwrap();
+    assert_eq!(parse_submission_timestamp("2020-08-18 14:32:10", now).unwrap(), at(14, 32, 10));
+    assert_eq!(parse_submission_timestamp(" 2020-08-18 00:00:00 ", now).unwrap(), at(0, 0, 0));
+    // Malformed or impossible values.
+    for invalid in ["", "2020-08-18", "2020-08-18T14:32:10", "2020-08-18 24:60:60", "2020-02-30 10:00:00", "yesterday"] {
+        let err = parse_submission_timestamp(invalid, now).unwrap_err();
+        assert_eq!(err.error_type, ErrorType::Unprocessable, "{invalid:?}");
+        assert!(err.error_message.contains("YYYY-MM-DD HH:MM:SS"));
+    }
+    // Timezones ahead of UTC are allowed, anything further ahead is rejected.
+    assert!(parse_submission_timestamp("2022-01-02 10:00:00", now).is_ok());
+    let err = parse_submission_timestamp("2022-01-03 12:00:00", now).unwrap_err();
+    assert_eq!(err.error_type, ErrorType::Unprocessable);
+    assert!(err.error_message.contains("future"));
+}
+
+#[test]
+fn test_points_curve() {
+    // The default curve gives the same points as the original formula.
+    let legacy = |i: i32| {
+        if i > 200 {
+            0.0
+        } else {
+            let res: f32 = num::pow(200.0 - (i as f32 - 1.0), 2) / 200.0;
+            res.max(1.0)
+        }
+    };
+    for rank in 1..=250 {
+        assert_eq!(score(rank), legacy(rank));
+    }
+    assert_eq!(score(1), 200.0);
+    assert_eq!(score(200), 1.0);
+    assert_eq!(score(201), 0.0);
+    let curve = PointsConfig { max_points: 100.0, max_rank: 10, decay: 1.0, min_points: 0.0 };
+    assert_eq!(points_for_rank(&curve, 1), 100.0);
+    assert_eq!(points_for_rank(&curve, 6), 50.0);
+    assert_eq!(points_for_rank(&curve, 10), 10.0);
+    assert_eq!(points_for_rank(&curve, 11), 0.0);
+    let floored = PointsConfig { min_points: 25.0, ..curve };
+    assert_eq!(points_for_rank(&floored, 9), 25.0);
+}
+
+#[test]
+fn test_clean_note() {
+    assert_eq!(clean_note(None, 10).unwrap(), None);
+    assert_eq!(clean_note(Some("  tas \u{0}route\u{7}  ".to_string()), 10).unwrap(), Some("tas route".to_string()));
+    assert_eq!(clean_note(Some("line 1\r\nline 2".to_string()), 20).unwrap(), Some("line 1\nline 2".to_string()));
+    assert_eq!(clean_note(Some(" \t\u{1b} ".to_string()), 10).unwrap(), None);
+    // The limit is in characters, and applies after cleaning.
+    assert_eq!(clean_note(Some("é".repeat(10)), 10).unwrap(), Some("é".repeat(10)));
+    assert_eq!(clean_note(Some(format!("  {}  ", "a".repeat(10))), 10).unwrap(), Some("a".repeat(10)));
+    let err = clean_note(Some("a".repeat(11)), 10).unwrap_err();
+    assert_eq!(actix_web::ResponseError::status_code(&err), actix_web::http::StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(err.to_string().contains("11 characters"));
+}
+
+#[test]
+fn test_escape_like() {
+    assert_eq!(escape_like("Zypeh"), "Zypeh");
+    assert_eq!(escape_like("100%_done"), "100\\%\\_done");
+    assert_eq!(escape_like("back\\slash"), "back\\\\slash");
+}
+
+#[test]
+fn test_results_limit() {
+    let proof = ProofConfig { results: 500, demo: 200, video: 200, max_results: 1000 };
+    assert_eq!(results_limit(None, &proof), 500);
+    assert_eq!(results_limit(Some(10), &proof), 10);
+    // Floored at a single entry.
+    assert_eq!(results_limit(Some(0), &proof), 1);
+    assert_eq!(results_limit(Some(-5), &proof), 1);
+    // Capped at `max_results`, including the default.
+    assert_eq!(results_limit(Some(1000), &proof), 1000);
+    assert_eq!(results_limit(Some(5000), &proof), 1000);
+    assert_eq!(results_limit(None, &ProofConfig { max_results: 100, ..proof.clone() }), 100);
+    assert_eq!(results_limit(Some(10), &ProofConfig { max_results: 0, ..proof }), 1);
+}
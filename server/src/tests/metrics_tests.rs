@@ -0,0 +1,55 @@
+use crate::api::v1::handlers::health::metrics;
+use crate::tools::metrics::{track_requests, Metrics};
+use actix_web::{get, middleware::from_fn, test, web, App, HttpResponse, Responder};
+use std::time::Duration;
+
+#[get("/scores/{id}")]
+async fn scores() -> impl Responder {
+    HttpResponse::Ok()
+}
+
+#[actix_web::test]
+async fn test_metrics_track_requests() {
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(track_requests))
+            .app_data(web::Data::new(Metrics::new().unwrap()))
+            .service(scores)
+            .service(metrics),
+    )
+    .await;
+    for id in 0..3 {
+        let req = test::TestRequest::get().uri(&format!("/scores/{id}")).to_request();
+        test::call_service(&app, req).await;
+    }
+    let req = test::TestRequest::get().uri("/unknown/path").to_request();
+    test::call_service(&app, req).await;
+
+    let req = test::TestRequest::get().uri("/metrics").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+    let body = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+    // Requests are labelled by the route pattern, not the path.
+    assert!(body.contains(r#"boards_http_requests_total{method="GET",route="/scores/{id}",status="200"} 3"#));
+    assert!(body.contains(r#"boards_http_requests_total{method="GET",route="unmatched",status="404"} 1"#));
+    assert!(body.contains(r#"boards_http_request_duration_seconds_count{route="/scores/{id}"} 3"#));
+}
+
+#[actix_web::test]
+async fn test_metrics_cache_and_uploads() {
+    let registry = Metrics::new().unwrap();
+    registry.record_cache_lookup("sp_previews", true);
+    registry.record_cache_lookup("sp_previews", true);
+    registry.record_cache_lookup("sp_previews", false);
+    registry.observe_backblaze_upload(Duration::from_millis(300));
+    registry.observe_db_acquire(Duration::from_millis(20));
+    let body = registry.render().unwrap();
+    assert!(body.contains(r#"boards_cache_lookups_total{cache="sp_previews",result="hit"} 2"#));
+    assert!(body.contains(r#"boards_cache_lookups_total{cache="sp_previews",result="miss"} 1"#));
+    assert!(body.contains("boards_backblaze_upload_duration_seconds_count 1"));
+    assert!(body.contains(r#"boards_backblaze_upload_duration_seconds_bucket{le="0.25"} 0"#));
+    assert!(body.contains(r#"boards_backblaze_upload_duration_seconds_bucket{le="0.5"} 1"#));
+    assert!(body.contains(r#"boards_db_acquire_duration_seconds_bucket{le="0.01"} 0"#));
+    assert!(body.contains(r#"boards_db_acquire_duration_seconds_bucket{le="0.05"} 1"#));
+}
+
@@ -1,2 +1,30 @@
 #[cfg(test)]
+pub mod auth_tests;
+#[cfg(test)]
+pub mod client_tests;
+#[cfg(test)]
+pub mod cors_tests;
+#[cfg(test)]
 pub mod db_tests;
+#[cfg(test)]
+pub mod demo_tests;
+#[cfg(test)]
+pub mod discord_tests;
+#[cfg(test)]
+pub mod error_tests;
+#[cfg(test)]
+pub mod export_tests;
+#[cfg(test)]
+pub mod helpers_tests;
+#[cfg(test)]
+pub mod metrics_tests;
+#[cfg(test)]
+pub mod moderation_tests;
+#[cfg(test)]
+pub mod openapi_tests;
+#[cfg(test)]
+pub mod ratelimit_tests;
+#[cfg(test)]
+pub mod storage_tests;
+#[cfg(test)]
+pub mod users_tests;
@@ -0,0 +1,40 @@
+use crate::models::users::Users;
+use crate::tools::config::ModerationConfig;
+use crate::tools::moderation::{is_offensive, moderate_new_user};
+
+#[test]
+fn test_is_offensive() {
+    assert!(is_offensive("xX_N4Z1_Xx"));
+    assert!(is_offensive("Hit ler"));
+    assert!(!is_offensive("Zypeh"));
+    assert!(!is_offensive("grapespicy"));
+    assert!(!is_offensive("『 Jonese1234 』"));
+}
+
+#[test]
+fn test_moderate_new_user() {
+    let user = Users {
+        profile_number: "76561198040982247".to_string(),
+        board_name: Some("n4zi".to_string()),
+        steam_name: Some("BigDaniel".to_string()),
+        ..Default::default()
+    };
+    // Nothing is changed when filtering is disabled.
+    let mut unfiltered = user.clone();
+    moderate_new_user(&ModerationConfig::default(), &mut unfiltered);
+    assert_eq!(unfiltered.board_name, user.board_name);
+    assert!(!unfiltered.needs_review);
+    let config = ModerationConfig { filter_names: true };
+    let mut filtered = user.clone();
+    moderate_new_user(&config, &mut filtered);
+    assert_eq!(filtered.board_name, None);
+    assert_eq!(filtered.steam_name, user.steam_name);
+    assert!(filtered.needs_review);
+    let mut clean = Users {
+        board_name: Some("Daniel".to_string()),
+        ..user
+    };
+    moderate_new_user(&config, &mut clean);
+    assert_eq!(clean.board_name, Some("Daniel".to_string()));
+    assert!(!clean.needs_review);
+}
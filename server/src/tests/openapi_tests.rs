@@ -0,0 +1,30 @@
+use crate::api::v1::handlers::openapi::openapi_spec;
+use actix_web::{test, App};
+use serde_json::Value;
+
+#[actix_web::test]
+async fn test_openapi_spec() {
+    let app = test::init_service(App::new().service(openapi_spec)).await;
+    let req = test::TestRequest::get().uri("/openapi.json").to_request();
+    let spec: Value = test::call_and_read_body_json(&app, req).await;
+    assert!(spec["openapi"].as_str().unwrap().starts_with("3."));
+    assert_eq!(spec["servers"][0]["url"], "/api/v1");
+    let paths = spec["paths"].as_object().unwrap();
+    for path in ["/map/sp/{map_id}", "/map/coop/{map_id}", "/demos/changelog", "/points/curve", "/openapi.json"] {
+        assert!(paths.contains_key(path), "{path} should be in the spec");
+    }
+    // Query structs are documented as parameters, and path segments are named from the route.
+    let sp_map = &paths["/map/sp/{map_id}"]["get"];
+    let params: Vec<&str> = sp_map["parameters"].as_array().unwrap().iter().map(|p| p["name"].as_str().unwrap()).collect();
+    assert!(params.contains(&"map_id"));
+    assert!(params.contains(&"cat_id"));
+    assert!(params.contains(&"formatted"));
+    let upload = &paths["/demos/changelog"]["post"]["responses"];
+    assert!(upload.get("413").is_some());
+    assert!(upload.get("415").is_some());
+    let schemas = spec["components"]["schemas"].as_object().unwrap();
+    for schema in ["SubmissionChangelog", "SpRanked", "CoopRanked", "Changelog", "ReadyStatus"] {
+        assert!(schemas.contains_key(schema), "{schema} should be in the spec");
+    }
+    assert!(schemas["SpRanked"]["properties"].get("rank").is_some());
+}
@@ -0,0 +1,58 @@
+use crate::tools::config::RateLimitConfig;
+use crate::tools::error::ErrorType;
+use crate::tools::ratelimit::{RateLimiter, MAX_BUCKETS};
+use actix_web::{http::header::RETRY_AFTER, ResponseError};
+use std::time::{Duration, Instant};
+
+#[actix_web::test]
+async fn test_rate_limiter_refills() {
+    // 2 submissions, refilled at 1 every 30 seconds.
+    let limiter = RateLimiter::new(RateLimitConfig { submissions: 2, per_secs: 60 });
+    let start = Instant::now();
+    assert!(limiter.check_at("player", start).await.is_ok());
+    assert!(limiter.check_at("player", start).await.is_ok());
+    let err = limiter.check_at("player", start).await.unwrap_err();
+    assert_eq!(err.error_type, ErrorType::TooManyRequests);
+    assert_eq!(err.retry_after, Some(30));
+    assert_eq!(err.error_response().headers().get(RETRY_AFTER).unwrap(), "30");
+    // Other players have their own bucket.
+    assert!(limiter.check_at("other", start).await.is_ok());
+    let err = limiter.check_at("player", start + Duration::from_secs(20)).await.unwrap_err();
+    assert_eq!(err.retry_after, Some(10));
+    assert!(limiter.check_at("player", start + Duration::from_secs(30)).await.is_ok());
+    assert!(limiter.check_at("player", start + Duration::from_secs(30)).await.is_err());
+    // Buckets never hold more than the limit.
+    let later = start + Duration::from_secs(3600);
+    assert!(limiter.check_at("player", later).await.is_ok());
+    assert!(limiter.check_at("player", later).await.is_ok());
+    assert!(limiter.check_at("player", later).await.is_err());
+}
+
+#[actix_web::test]
+async fn test_rate_limiter_disabled() {
+    let limiter = RateLimiter::new(RateLimitConfig { submissions: 0, per_secs: 60 });
+    let now = Instant::now();
+    for _ in 0..100 {
+        assert!(limiter.check_at("player", now).await.is_ok());
+    }
+}
+
+#[actix_web::test]
+async fn test_rate_limiter_max_buckets() {
+    let limiter = RateLimiter::new(RateLimitConfig { submissions: 1, per_secs: 60 });
+    let start = Instant::now();
+    assert!(limiter.check_at("player", start).await.is_ok());
+    assert!(limiter.check_at("player", start).await.is_err());
+    for i in 1..MAX_BUCKETS {
+        assert!(limiter.check_at(&format!("other{i}"), start + Duration::from_micros(i as u64)).await.is_ok());
+    }
+    // Still tracked while there is room.
+    assert!(limiter.check_at("player", start + Duration::from_secs(1)).await.is_err());
+    // A new key past the cap drops the least recently used bucket.
+    assert!(limiter.check_at("new", start + Duration::from_secs(1)).await.is_ok());
+    assert!(limiter.check_at("player", start + Duration::from_secs(1)).await.is_ok());
+    // Buckets that have refilled are dropped, and come back full.
+    let later = start + Duration::from_secs(120);
+    assert!(limiter.check_at("other1", later).await.is_ok());
+    assert!(limiter.check_at("other1", later).await.is_err());
+}
@@ -0,0 +1,84 @@
+use crate::tools::backblaze::{content_sha1, B2Error};
+use crate::tools::config::{StorageBackend, StorageConfig};
+use crate::tools::storage::{is_missing_file, DemoStorage, LocalStorage, StoredFile};
+use std::path::PathBuf;
+
+/// A [LocalStorage] in a fresh directory under the system temp directory, with a local file to upload.
+async fn local_storage(name: &str, contents: &[u8]) -> (LocalStorage, PathBuf, String) {
+    let dir = std::env::temp_dir().join(format!("p2boards_storage_{}_{}", name, std::process::id()));
+    let _ = tokio::fs::remove_dir_all(&dir).await;
+    let config = StorageConfig {
+        backend: StorageBackend::Local,
+        local_dir: dir.join("stored").to_string_lossy().to_string(),
+    };
+    tokio::fs::create_dir_all(&dir).await.unwrap();
+    let upload = dir.join("upload.dem").to_string_lossy().to_string();
+    tokio::fs::write(&upload, contents).await.unwrap();
+    (LocalStorage::new(&config), dir, upload)
+}
+
+#[actix_web::test]
+async fn test_storage_config_default() {
+    let config = StorageConfig::default();
+    assert_eq!(config.backend, StorageBackend::Backblaze);
+    assert_eq!(config.local_dir, "./demo_storage");
+    let config: StorageConfig = serde_json::from_value(serde_json::json!({ "backend": "local" })).unwrap();
+    assert_eq!(config.backend, StorageBackend::Local);
+}
+
+#[actix_web::test]
+async fn test_local_storage_round_trip() {
+    let contents = b"HL2DEMO\0local storage".to_vec();
+    let (storage, dir, upload) = local_storage("round_trip", &contents).await;
+    assert_eq!(storage.bucket(), None);
+    let file_id = storage.upload(&upload, "47763_1763_76561198040982247").await.unwrap();
+    assert!(file_id.ends_with("_47763_1763_76561198040982247"));
+    let file = StoredFile { file_id: &file_id, bucket_id: None };
+    assert_eq!(storage.download(file).await.unwrap(), contents);
+    assert_eq!(storage.sha1(file).await.unwrap(), content_sha1(&contents));
+    // Uploading under the same name does not overwrite the earlier file.
+    let second_id = storage.upload(&upload, "47763_1763_76561198040982247").await.unwrap();
+    assert_ne!(second_id, file_id);
+    storage.delete(file, "47763_1763_76561198040982247").await.unwrap();
+    assert!(storage.download(file).await.is_err());
+    assert!(storage.delete(file, "47763_1763_76561198040982247").await.is_err());
+    let second = StoredFile { file_id: &second_id, bucket_id: None };
+    let results = storage.delete_all(vec![(second, "47763_1763_76561198040982247".to_string()), (file, "missing".to_string())]).await;
+    assert!(results[0].is_ok());
+    assert!(results[1].is_err());
+    tokio::fs::remove_dir_all(dir).await.unwrap();
+}
+
+#[actix_web::test]
+async fn test_local_storage_rejects_paths() {
+    let (storage, dir, _) = local_storage("paths", b"HL2DEMO\0").await;
+    tokio::fs::write(dir.join("outside.dem"), b"outside").await.unwrap();
+    for file_id in ["", "../outside.dem", "..", "nested/file.dem", "nested\\file.dem", ".hidden"] {
+        let file = StoredFile { file_id, bucket_id: None };
+        assert!(storage.download(file).await.is_err(), "{file_id} should be rejected");
+        assert!(storage.delete(file, file_id).await.is_err(), "{file_id} should be rejected");
+    }
+    assert!(dir.join("outside.dem").exists());
+    tokio::fs::remove_dir_all(dir).await.unwrap();
+}
+
+#[actix_web::test]
+async fn test_is_missing_file() {
+    let b2_error = |status: u16, code: &str| {
+        anyhow::Error::new(B2Error { status, code: code.to_string(), message: "test".to_string() })
+    };
+    assert!(is_missing_file(&b2_error(400, "file_not_present")));
+    assert!(is_missing_file(&b2_error(404, "not_found")));
+    assert!(!is_missing_file(&b2_error(401, "bad_auth_token")));
+    assert!(!is_missing_file(&b2_error(503, "service_unavailable")));
+    assert!(!is_missing_file(&anyhow::anyhow!("BackBlaze returned 500")));
+    assert_eq!(b2_error(400, "file_not_present").to_string(), "BackBlaze returned 400 (file_not_present) -> test");
+
+    let (storage, dir, _) = local_storage("missing", b"HL2DEMO\0").await;
+    let file = StoredFile { file_id: "missing.dem", bucket_id: None };
+    assert!(is_missing_file(&storage.delete(file, "missing.dem").await.unwrap_err()));
+    // An invalid id is rejected rather than missing.
+    let file = StoredFile { file_id: "../missing.dem", bucket_id: None };
+    assert!(!is_missing_file(&storage.delete(file, "missing.dem").await.unwrap_err()));
+    tokio::fs::remove_dir_all(dir).await.unwrap();
+}
@@ -0,0 +1,42 @@
+use crate::models::users::{SteamIdInput, Users};
+
+#[test]
+fn test_parse_steam_input_steam_id() {
+    assert_eq!(
+        Users::parse_steam_input("76561198040982247"),
+        SteamIdInput::SteamId64("76561198040982247".to_string())
+    );
+    assert_eq!(
+        Users::parse_steam_input(" 76561198040982247\n"),
+        SteamIdInput::SteamId64("76561198040982247".to_string())
+    );
+}
+
+#[test]
+fn test_parse_steam_input_vanity() {
+    assert_eq!(
+        Users::parse_steam_input("bigdaniel"),
+        SteamIdInput::Vanity("bigdaniel".to_string())
+    );
+    // Too short to be a steamID64.
+    assert_eq!(
+        Users::parse_steam_input("1234567"),
+        SteamIdInput::Vanity("1234567".to_string())
+    );
+}
+
+#[test]
+fn test_parse_steam_input_profile_url() {
+    assert_eq!(
+        Users::parse_steam_input("https://steamcommunity.com/profiles/76561198040982247/"),
+        SteamIdInput::SteamId64("76561198040982247".to_string())
+    );
+    assert_eq!(
+        Users::parse_steam_input("steamcommunity.com/profiles/76561198040982247/games"),
+        SteamIdInput::SteamId64("76561198040982247".to_string())
+    );
+    assert_eq!(
+        Users::parse_steam_input("https://steamcommunity.com/id/bigdaniel"),
+        SteamIdInput::Vanity("bigdaniel".to_string())
+    );
+}
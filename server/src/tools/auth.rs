@@ -0,0 +1,120 @@
+//! Authentication for endpoints that change scores or users.
+//!
+//! Callers authenticate with an `Authorization: Bearer {token}` header, the hex encoded SHA-256 of the token is
+//! stored as the user's `auth_hash`. Handlers take an [AuthUser] as an argument to require authentication.
+//!
+//! Admin levels follow [Users::get_all_admins], any level of 1 or higher has admin permissions.
+use crate::{
+    models::{
+        changelog::{Changelog, ChangelogInsert},
+        users::Users,
+    },
+    tools::error::{ErrorType, ServerError},
+};
+use actix_web::{dev::Payload, http::header, web, FromRequest, HttpRequest};
+use futures::future::LocalBoxFuture;
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+
+/// The authenticated caller of an endpoint.
+#[derive(Debug, Clone)]
+pub struct AuthUser {
+    pub profile_number: String,
+    pub admin: i32,
+}
+
+/// Hashes an auth token into the form stored in `users.auth_hash`.
+pub fn hash_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+fn forbidden(message: &str) -> ServerError {
+    ServerError::new(ErrorType::Forbidden, message)
+}
+
+impl AuthUser {
+    /// Standard, shadow and developer admins all have admin permissions.
+    pub fn is_admin(&self) -> bool {
+        self.admin >= 1
+    }
+    /// Returns a 403 unless the caller is an admin.
+    pub fn require_admin(&self) -> Result<(), ServerError> {
+        if self.is_admin() {
+            Ok(())
+        } else {
+            Err(forbidden("Only admins can do this."))
+        }
+    }
+    /// Checks the caller can change `existing` into `updated`.
+    ///
+    /// Admins can change anything, other users can only change the `note` and `youtube_id` of their own scores.
+    pub fn check_changelog_update(&self, existing: &Changelog, updated: &Changelog) -> Result<(), ServerError> {
+        if self.is_admin() {
+            return Ok(());
+        }
+        if existing.profile_number != self.profile_number {
+            return Err(forbidden("You can only update your own scores."));
+        }
+        let mut allowed = existing.clone();
+        allowed.note = updated.note.clone();
+        allowed.youtube_id = updated.youtube_id.clone();
+        // Neither of these are written by an update.
+        allowed.previous_id = updated.previous_id;
+        allowed.updated = updated.updated;
+        if &allowed != updated {
+            return Err(forbidden("Only admins can change fields other than `note` and `youtube_id`."));
+        }
+        Ok(())
+    }
+    /// Checks the caller can submit scores (and demos) for `profile_number`, admins can submit for anyone.
+    pub fn check_submitter(&self, profile_number: &str) -> Result<(), ServerError> {
+        if self.is_admin() || profile_number == self.profile_number {
+            Ok(())
+        } else {
+            Err(forbidden("You can only submit your own scores."))
+        }
+    }
+    /// Checks the caller can submit `changelog`.
+    ///
+    /// Admins can submit anything, other users can only submit their own scores and cannot set `banned`, `verified` or `admin_note`.
+    pub fn check_changelog_insert(&self, changelog: &ChangelogInsert) -> Result<(), ServerError> {
+        if self.is_admin() {
+            return Ok(());
+        }
+        self.check_submitter(&changelog.profile_number)?;
+        if changelog.banned || changelog.verified.is_some() || changelog.admin_note.is_some() {
+            return Err(forbidden("Only admins can set `banned`, `verified` or `admin_note`."));
+        }
+        Ok(())
+    }
+}
+
+impl FromRequest for AuthUser {
+    type Error = ServerError;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let token = req
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map(|token| token.trim().to_string());
+        let pool = req.app_data::<web::Data<PgPool>>().cloned();
+        Box::pin(async move {
+            let unauthorized = || ServerError::new(ErrorType::Unauthorized, "A valid auth token is required.");
+            let token = token.ok_or_else(unauthorized)?;
+            let pool = pool.ok_or_else(|| ServerError::new(ErrorType::Internal, "No database pool available."))?;
+            let profile_number = Users::get_profile_number_by_auth_hash(&pool, &hash_token(&token))
+                .await?
+                .ok_or_else(unauthorized)?;
+            let admin = Users::get_admin_for_user(&pool, profile_number.clone())
+                .await?
+                .unwrap_or(0);
+            Ok(AuthUser {
+                profile_number,
+                admin,
+            })
+        })
+    }
+}
@@ -0,0 +1,453 @@
+//! Minimal client for the parts of BackBlaze's B2 native API (v2) that the boards use.
+//!
+//! Function names mirror the B2 API calls they wrap.
+//! Ref: https://www.backblaze.com/apidocs/introduction-to-the-b2-native-api
+use actix_web::web::Bytes;
+use anyhow::{bail, Result};
+use futures::StreamExt;
+use reqwest::{Client, Response};
+use sha1::{Digest, Sha1};
+use std::future::Future;
+use std::io::SeekFrom;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+/// Base url used to authorize against the B2 API.
+const B2_AUTHORIZE_URL: &str = "https://api.backblazeb2.com/b2api/v2/b2_authorize_account";
+/// Smallest part B2 accepts for a large file, only the last part can be smaller.
+pub const B2_MIN_PART_SIZE: u64 = 5 * 1024 * 1024;
+/// Part size used for large files, B2's recommended part size.
+pub const LARGE_FILE_PART_SIZE: u64 = 100 * 1024 * 1024;
+
+/// Authorization information returned by `b2_authorize_account`.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct B2Auth {
+    pub authorization_token: String,
+    pub api_url: String,
+    pub download_url: String,
+    #[serde(default)]
+    pub allowed: B2Allowed,
+}
+
+/// Restrictions on what the authorized key can access.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct B2Allowed {
+    /// Set if the key is restricted to a single bucket.
+    pub bucket_id: Option<String>,
+}
+
+/// Upload target returned by `b2_get_upload_url`, only valid for a single upload at a time.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct UploadAuth {
+    pub upload_url: String,
+    pub authorization_token: String,
+}
+
+/// File information returned by B2 after a file operation.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct B2FileInfo {
+    pub file_id: Option<String>,
+}
+
+/// Parameters describing a file being uploaded.
+#[derive(Debug, Clone)]
+pub struct FileParameters<'a> {
+    pub file_path: &'a str,
+    pub content_type: Option<&'a str>,
+    pub last_modified_millis: u64,
+}
+
+/// Error body returned by B2 on a failed request.
+#[derive(Deserialize, Debug)]
+pub struct B2Error {
+    pub status: u16,
+    pub code: String,
+    pub message: String,
+}
+
+impl B2Error {
+    /// B2 reports a file that does not exist (or was already deleted) with `file_not_present`, or a 404.
+    pub fn is_file_missing(&self) -> bool {
+        self.status == 404 || self.code == "file_not_present" || self.code == "not_found"
+    }
+}
+
+impl std::fmt::Display for B2Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "BackBlaze returned {} ({}) -> {}", self.status, self.code, self.message)
+    }
+}
+
+impl std::error::Error for B2Error {}
+
+/// Returns true if a failed B2 call is worth retrying, B2 server errors (5xx) and network errors. Any other error,
+/// including a 4xx from B2, fails the same way again.
+pub fn is_retryable(e: &anyhow::Error) -> bool {
+    if let Some(e) = e.downcast_ref::<B2Error>() {
+        return e.status >= 500;
+    }
+    match e.downcast_ref::<reqwest::Error>() {
+        Some(e) => e.is_timeout() || e.is_connect() || e.is_request() || e.is_body(),
+        None => false,
+    }
+}
+
+/// Converts a non-successful B2 response into an error containing the B2 error code and message.
+///
+/// The error can always be downcast to a [B2Error], if B2 did not send one the `code` is left empty.
+async fn b2_error(resp: Response) -> anyhow::Error {
+    let status = resp.status();
+    match resp.json::<B2Error>().await {
+        Ok(e) => anyhow::Error::new(e),
+        Err(_) => anyhow::Error::new(B2Error {
+            status: status.as_u16(),
+            code: String::new(),
+            message: status.canonical_reason().unwrap_or_default().to_string(),
+        }),
+    }
+}
+
+/// B2 expects file names to be percent-encoded, with `/` left intact.
+fn encode_file_name(file_name: &str) -> String {
+    file_name
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => (b as char).to_string(),
+            _ => format!("%{b:02X}"),
+        })
+        .collect()
+}
+
+/// Authorizes the account, `key_string` is expected to be in the form `keyid:key`.
+pub async fn b2_authorize_account(client: &Client, key_string: String) -> Result<B2Auth> {
+    let (keyid, key) = match key_string.split_once(':') {
+        Some(pair) => pair,
+        None => bail!("Invalid BackBlaze key string, expected `keyid:key`"),
+    };
+    let resp = client
+        .get(B2_AUTHORIZE_URL)
+        .basic_auth(keyid, Some(key))
+        .send()
+        .await?;
+    if !resp.status().is_success() {
+        return Err(b2_error(resp).await);
+    }
+    Ok(resp.json::<B2Auth>().await?)
+}
+
+/// Checks that the authorized key can access files in `bucket_id`.
+///
+/// File operations by id do not take a bucket, so a key restricted to another bucket would otherwise only fail with
+/// a generic unauthorized error from B2.
+pub fn check_bucket_access(auth: &B2Auth, bucket_id: &str) -> Result<()> {
+    match &auth.allowed.bucket_id {
+        Some(allowed) if allowed != bucket_id => {
+            bail!("BackBlaze key is restricted to bucket {}, cannot access files in bucket {}", allowed, bucket_id)
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Gets an upload url for the given bucket.
+pub async fn b2_get_upload_url(client: &Client, auth: &B2Auth, bucket_id: String) -> Result<UploadAuth> {
+    let resp = client
+        .post(format!("{}/b2api/v2/b2_get_upload_url", auth.api_url))
+        .header("Authorization", &auth.authorization_token)
+        .json(&serde_json::json!({ "bucketId": bucket_id }))
+        .send()
+        .await?;
+    if !resp.status().is_success() {
+        return Err(b2_error(resp).await);
+    }
+    Ok(resp.json::<UploadAuth>().await?)
+}
+
+/// Hex encoded SHA1 of a file's contents, the same format B2 uses for `contentSha1`.
+pub fn content_sha1(contents: &[u8]) -> String {
+    format!("{:x}", Sha1::digest(contents))
+}
+
+/// Uploads the contents of a file, the SHA1 of the contents is computed and sent for B2 to verify against.
+pub async fn b2_upload_file(
+    client: &Client,
+    upload_auth: &UploadAuth,
+    contents: Bytes,
+    params: FileParameters<'_>,
+) -> Result<B2FileInfo> {
+    let sha1 = content_sha1(&contents);
+    let resp = client
+        .post(&upload_auth.upload_url)
+        .header("Authorization", &upload_auth.authorization_token)
+        .header("X-Bz-File-Name", encode_file_name(params.file_path))
+        .header("Content-Type", params.content_type.unwrap_or("b2/x-auto"))
+        .header("Content-Length", contents.len())
+        .header("X-Bz-Content-Sha1", sha1)
+        .header(
+            "X-Bz-Info-src_last_modified_millis",
+            params.last_modified_millis,
+        )
+        .body(contents)
+        .send()
+        .await?;
+    if !resp.status().is_success() {
+        return Err(b2_error(resp).await);
+    }
+    Ok(resp.json::<B2FileInfo>().await?)
+}
+
+/// Uploads a file, retrying with a fresh upload url from `b2_get_upload_url` on failure, as B2 upload urls should not be reused
+/// after a failed upload. B2 regularly returns 503 while under load.
+///
+/// Makes at most `attempts` attempts, see [with_retries] for the backoff. `contents` is shared by every attempt rather
+/// than copied for each one.
+pub async fn b2_upload_file_with_retries(
+    client: &Client,
+    auth: &B2Auth,
+    bucket_id: String,
+    contents: Bytes,
+    params: FileParameters<'_>,
+    attempts: u32,
+    backoff: Duration,
+) -> Result<B2FileInfo> {
+    with_retries(attempts, backoff, |_| async {
+        let upload_auth = b2_get_upload_url(client, auth, bucket_id.clone()).await?;
+        b2_upload_file(client, &upload_auth, contents.clone(), params.clone()).await
+    })
+    .await
+}
+
+/// Runs `op` until it succeeds, or it has been attempted `attempts` times (at least once), returning the final error.
+/// Errors that are not worth retrying are returned straight away, see [is_retryable].
+///
+/// Waits `backoff` after the first failure, doubling the wait after each failure after that.
+/// `op` is passed the number of the attempt, starting at 1.
+pub async fn with_retries<T, F, Fut>(attempts: u32, backoff: Duration, mut op: F) -> Result<T>
+where
+    F: FnMut(u32) -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let attempts = attempts.max(1);
+    let mut wait = backoff;
+    let mut attempt = 1;
+    loop {
+        match op(attempt).await {
+            Ok(res) => return Ok(res),
+            Err(e) if attempt >= attempts || !is_retryable(&e) => return Err(e),
+            Err(e) => {
+                eprintln!("BackBlaze attempt {} of {} failed, retrying in {:?} -> {}", attempt, attempts, wait, e);
+                tokio::time::sleep(wait).await;
+                wait *= 2;
+                attempt += 1;
+            }
+        }
+    }
+}
+
+/// Returns true if a file of `size` bytes should be uploaded with the large file API rather than [b2_upload_file].
+///
+/// Files over `threshold` bytes use the large file API, as long as they are big enough to be split into the two parts
+/// B2 requires.
+pub fn uses_large_file(size: u64, threshold: u64) -> bool {
+    size > threshold && size >= 2 * B2_MIN_PART_SIZE
+}
+
+/// The part size to split a large file of `size` bytes into, [LARGE_FILE_PART_SIZE] unless the file is too small to be
+/// split into at least two parts with it.
+pub fn large_file_part_size(size: u64) -> u64 {
+    LARGE_FILE_PART_SIZE.min(size.div_ceil(2)).max(B2_MIN_PART_SIZE)
+}
+
+/// Starts a large file upload, the returned `file_id` is used to upload the parts.
+pub async fn b2_start_large_file(
+    client: &Client,
+    auth: &B2Auth,
+    bucket_id: String,
+    params: &FileParameters<'_>,
+) -> Result<B2FileInfo> {
+    let resp = client
+        .post(format!("{}/b2api/v2/b2_start_large_file", auth.api_url))
+        .header("Authorization", &auth.authorization_token)
+        .json(&serde_json::json!({
+            "bucketId": bucket_id,
+            "fileName": params.file_path,
+            "contentType": params.content_type.unwrap_or("b2/x-auto"),
+            "fileInfo": { "src_last_modified_millis": params.last_modified_millis.to_string() },
+        }))
+        .send()
+        .await?;
+    if !resp.status().is_success() {
+        return Err(b2_error(resp).await);
+    }
+    Ok(resp.json::<B2FileInfo>().await?)
+}
+
+/// Gets an upload url for the parts of a large file, only valid for a single part upload at a time.
+pub async fn b2_get_upload_part_url(client: &Client, auth: &B2Auth, file_id: &str) -> Result<UploadAuth> {
+    let resp = client
+        .post(format!("{}/b2api/v2/b2_get_upload_part_url", auth.api_url))
+        .header("Authorization", &auth.authorization_token)
+        .json(&serde_json::json!({ "fileId": file_id }))
+        .send()
+        .await?;
+    if !resp.status().is_success() {
+        return Err(b2_error(resp).await);
+    }
+    Ok(resp.json::<UploadAuth>().await?)
+}
+
+/// Uploads a single part of a large file, parts are numbered from 1. Returns the SHA1 of the part, which is needed to
+/// finish the file.
+pub async fn b2_upload_part(
+    client: &Client,
+    upload_auth: &UploadAuth,
+    part_number: u32,
+    contents: Bytes,
+) -> Result<String> {
+    let sha1 = content_sha1(&contents);
+    let resp = client
+        .post(&upload_auth.upload_url)
+        .header("Authorization", &upload_auth.authorization_token)
+        .header("X-Bz-Part-Number", part_number)
+        .header("Content-Length", contents.len())
+        .header("X-Bz-Content-Sha1", &sha1)
+        .body(contents)
+        .send()
+        .await?;
+    if !resp.status().is_success() {
+        return Err(b2_error(resp).await);
+    }
+    Ok(sha1)
+}
+
+/// Finishes a large file once all of its parts are uploaded, `part_sha1s` are in order of part number.
+pub async fn b2_finish_large_file(
+    client: &Client,
+    auth: &B2Auth,
+    file_id: &str,
+    part_sha1s: Vec<String>,
+) -> Result<B2FileInfo> {
+    let resp = client
+        .post(format!("{}/b2api/v2/b2_finish_large_file", auth.api_url))
+        .header("Authorization", &auth.authorization_token)
+        .json(&serde_json::json!({ "fileId": file_id, "partSha1Array": part_sha1s }))
+        .send()
+        .await?;
+    if !resp.status().is_success() {
+        return Err(b2_error(resp).await);
+    }
+    Ok(resp.json::<B2FileInfo>().await?)
+}
+
+/// Cancels an unfinished large file, removing any parts that were already uploaded.
+pub async fn b2_cancel_large_file(client: &Client, auth: &B2Auth, file_id: &str) -> Result<B2FileInfo> {
+    let resp = client
+        .post(format!("{}/b2api/v2/b2_cancel_large_file", auth.api_url))
+        .header("Authorization", &auth.authorization_token)
+        .json(&serde_json::json!({ "fileId": file_id }))
+        .send()
+        .await?;
+    if !resp.status().is_success() {
+        return Err(b2_error(resp).await);
+    }
+    Ok(resp.json::<B2FileInfo>().await?)
+}
+
+/// Uploads the local file at `path` with the large file API, reading one part of [large_file_part_size] at a time so the
+/// whole file is never held in memory.
+///
+/// Each part is retried with a fresh part upload url, see [with_retries]. If any part fails, or the file cannot be
+/// finished, the large file is cancelled so no unfinished parts are left in the bucket.
+pub async fn b2_upload_large_file_with_retries(
+    client: &Client,
+    auth: &B2Auth,
+    bucket_id: String,
+    path: &str,
+    params: FileParameters<'_>,
+    attempts: u32,
+    backoff: Duration,
+) -> Result<B2FileInfo> {
+    let mut file = tokio::fs::File::open(path).await?;
+    let size = file.metadata().await?.len();
+    let part_size = large_file_part_size(size);
+    let file_id = match b2_start_large_file(client, auth, bucket_id, &params).await?.file_id {
+        Some(file_id) => file_id,
+        None => bail!("BackBlaze did not return a file id for the large file"),
+    };
+    let upload = async {
+        let mut part_sha1s = Vec::new();
+        let mut offset = 0;
+        while offset < size {
+            let len = part_size.min(size - offset);
+            let mut contents = vec![0u8; len as usize];
+            file.seek(SeekFrom::Start(offset)).await?;
+            file.read_exact(&mut contents).await?;
+            let contents = Bytes::from(contents);
+            let part_number = part_sha1s.len() as u32 + 1;
+            let sha1 = with_retries(attempts, backoff, |_| async {
+                let upload_auth = b2_get_upload_part_url(client, auth, &file_id).await?;
+                b2_upload_part(client, &upload_auth, part_number, contents.clone()).await
+            })
+            .await?;
+            part_sha1s.push(sha1);
+            offset += len;
+        }
+        b2_finish_large_file(client, auth, &file_id, part_sha1s).await
+    };
+    match upload.await {
+        Ok(info) => Ok(info),
+        Err(e) => {
+            if let Err(cancel_err) = b2_cancel_large_file(client, auth, &file_id).await {
+                eprintln!("Failed to cancel large file {} after a failed upload -> {}", file_id, cancel_err);
+            }
+            Err(e)
+        }
+    }
+}
+
+/// Deletes a specific version of a file.
+pub async fn b2_delete_file_version(
+    client: &Client,
+    auth: &B2Auth,
+    file_name: String,
+    file_id: String,
+) -> Result<B2FileInfo> {
+    let resp = client
+        .post(format!("{}/b2api/v2/b2_delete_file_version", auth.api_url))
+        .header("Authorization", &auth.authorization_token)
+        .json(&serde_json::json!({ "fileName": file_name, "fileId": file_id }))
+        .send()
+        .await?;
+    if !resp.status().is_success() {
+        return Err(b2_error(resp).await);
+    }
+    Ok(resp.json::<B2FileInfo>().await?)
+}
+
+/// Starts a download of a file by its id, the returned response can be streamed back to the client.
+pub async fn b2_download_file_by_id(client: &Client, auth: &B2Auth, file_id: &str) -> Result<Response> {
+    let resp = client
+        .get(format!("{}/b2api/v2/b2_download_file_by_id", auth.download_url))
+        .header("Authorization", &auth.authorization_token)
+        .query(&[("fileId", file_id)])
+        .send()
+        .await?;
+    if !resp.status().is_success() {
+        return Err(b2_error(resp).await);
+    }
+    Ok(resp)
+}
+
+/// Computes the SHA1 of a stored file, the file is hashed as it is streamed rather than held in memory.
+pub async fn b2_file_sha1(client: &Client, auth: &B2Auth, file_id: &str) -> Result<String> {
+    let resp = b2_download_file_by_id(client, auth, file_id).await?;
+    let mut hasher = Sha1::new();
+    let mut stream = resp.bytes_stream();
+    while let Some(chunk) = stream.next().await {
+        hasher.update(&chunk?);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
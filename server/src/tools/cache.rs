@@ -12,7 +12,7 @@
 //!     let cache = cache.into_inner(); // Extracts the CacheState from the [actix_web::web::Data] wrapper
 //!     // Access the default category ids.
 //!     let map_id = "47458";
-//!     let def_cat_id = cache.default_cat_ids[map_id];
+//!     let def_cat_id = cache.default_cat_ids.get(map_id, 1).unwrap();
 //!
 //!     // Check the current cache state for points.
 //!     let state_data = &mut cache.current_state.lock().await; // We use &mut here so that we can change the value accordingly.
@@ -47,18 +47,32 @@
 //! ```
 //!
 use crate::{
-    models::{coop::CoopMap, maps::Maps, points::Points, sp::SpMap},
-    tools::config::Config,
+    models::{
+        changelog::RecentActivity,
+        coop::{CoopMap, CoopPreview},
+        demos::ReparseProgress,
+        maps::{MapSummary, Maps},
+        points::Points,
+        sp::{SpMap, SpPreview},
+        stats::StatsOverview,
+    },
+    tools::{config::Config, live::LiveUpdates},
 };
-use anyhow::Result;
+use actix_web::{
+    http::header::{self, ETAG},
+    HttpRequest, HttpResponse,
+};
+use anyhow::{bail, Result};
 use serde::Serialize;
+use sha1::{Digest, Sha1};
 use sqlx::PgPool;
 use std::{
     collections::{HashMap, HashSet},
     fs::File,
     io::BufReader,
     path::Path,
-    sync::Arc,
+    sync::{Arc, RwLock, RwLockReadGuard},
+    time::{Duration, Instant},
 };
 use tokio::sync::Mutex;
 
@@ -82,6 +96,17 @@ pub const POINTS_15: &'static str = "points15";
 pub const POINTS_SP: &'static str = "points_sp";
 pub const POINTS_COOP: &'static str = "points_coop";
 pub const POINTS_OVERALL: &'static str = "points_overall";
+pub const SP_AGGREGATE: &str = "sp_aggregate";
+pub const COOP_AGGREGATE: &str = "coop_aggregate";
+/// How long a successful BackBlaze authorization is trusted by the readiness check before authorizing again.
+pub const B2_AUTH_WINDOW: Duration = Duration::from_secs(60);
+/// How long the recent activity feed is cached for, it changes too often to wait for invalidation.
+pub const RECENT_ACTIVITY_TTL: Duration = Duration::from_secs(30);
+/// How long the stats overview is cached for, the counts only need to be roughly current.
+pub const STATS_OVERVIEW_TTL: Duration = Duration::from_secs(60);
+
+/// A cached recent activity feed, and when it was cached.
+type CachedRecentActivity = (Instant, Vec<RecentActivity>);
 
 /// Cache for the current ranks all players have within the top X scores (defined by [crate::tools::config::ProofConfig])
 ///
@@ -92,13 +117,80 @@ pub struct Ranks {
     pub current_ranks: HashMap<String, HashMap<String, i32>>,
 }
 
+/// Default category for every map, keyed by `(map_id, game_id)` so maps from different games can share a steam_id.
+///
+/// Clones share the same map, so a default changed with [DefaultCategories::set] is seen by every worker.
+#[derive(Debug, Clone, Default)]
+pub struct DefaultCategories(Arc<RwLock<HashMap<(String, i32), i32>>>);
+
+impl DefaultCategories {
+    /// The map is only written to while a lock is held for a single insert, so a poisoned lock still holds valid data.
+    fn read(&self) -> RwLockReadGuard<'_, HashMap<(String, i32), i32>> {
+        self.0.read().unwrap_or_else(|e| e.into_inner())
+    }
+    /// Returns the default category for `map_id` in `game_id`, `None` if the map does not exist in that game.
+    pub fn get(&self, map_id: &str, game_id: i32) -> Option<i32> {
+        self.read().get(&(map_id.to_string(), game_id)).copied()
+    }
+    /// Sets the default category for `map_id` in `game_id`, see [crate::api::v1::handlers::maps::set_default_category].
+    pub fn set(&self, map_id: &str, game_id: i32, cat_id: i32) {
+        self.0.write().unwrap_or_else(|e| e.into_inner()).insert((map_id.to_string(), game_id), cat_id);
+    }
+    /// Returns the default categories for every map in `game_id`, as `map_id` -> `cat_id`.
+    pub fn for_game(&self, game_id: i32) -> HashMap<String, i32> {
+        self.read()
+            .iter()
+            .filter(|((_, game), _)| *game == game_id)
+            .map(|((map_id, _), cat_id)| (map_id.clone(), *cat_id))
+            .collect()
+    }
+    /// Returns the default categories for every map in every game, as `map_id` -> `cat_id`.
+    pub fn all(&self) -> HashMap<String, i32> {
+        self.read().iter().map(|((map_id, _), cat_id)| (map_id.clone(), *cat_id)).collect()
+    }
+}
+
+impl From<HashMap<(String, i32), i32>> for DefaultCategories {
+    fn from(hm: HashMap<(String, i32), i32>) -> Self {
+        DefaultCategories(Arc::new(RwLock::new(hm)))
+    }
+}
+
 /// Holds a thread-sharable hashmap that we use to control cache invalidation.
 #[derive(Debug, Clone)]
 pub struct CacheState {
     pub current_state: Arc<Mutex<HashMap<&'static str, bool>>>,
-    pub default_cat_ids: HashMap<String, i32>,
+    /// Default categories by `(map_id, game_id)`, loaded on startup and updated when an admin changes a map's default.
+    pub default_cat_ids: DefaultCategories,
     pub points: Arc<Mutex<HashMap<&'static str, HashMap<String, Points>>>>,
     pub ranks: Arc<Mutex<Ranks>>,
+    /// When each entry in `current_state` was last cached, entries older than `ttl` are treated as stale.
+    pub cached_at: Arc<Mutex<HashMap<&'static str, Instant>>>,
+    /// `None` keeps entries cached until they are invalidated.
+    pub ttl: Option<Duration>,
+    /// When BackBlaze last authorized successfully for the readiness check.
+    pub b2_authorized_at: Arc<Mutex<Option<Instant>>>,
+    /// Map summaries by `(map_id, cat_id)`, cleared whenever the previews are invalidated.
+    pub map_summaries: Arc<Mutex<HashMap<(String, i32), MapSummary>>>,
+    /// Coop previews for games other than the base game by `game_id`, cleared whenever the coop previews are invalidated.
+    ///
+    /// The base game's previews are cached to file under [COOP_PREVIEWS].
+    pub game_coop_previews: Arc<Mutex<HashMap<i32, Vec<Vec<CoopPreview>>>>>,
+    /// ETags of the cached previews, cleared whenever the matching entry in `current_state` changes.
+    pub etags: Arc<Mutex<HashMap<&'static str, String>>>,
+    /// Recent activity feeds by `(limit, game_id)`, and when they were cached. Expire after [RECENT_ACTIVITY_TTL].
+    pub recent_activity: Arc<Mutex<HashMap<(i64, i32), CachedRecentActivity>>>,
+    /// The stats overview, and when it was cached. Expires after [STATS_OVERVIEW_TTL].
+    pub stats_overview: Arc<Mutex<Option<(Instant, StatsOverview)>>>,
+    /// Progress of the demo re-parse job, see [crate::api::v1::handlers::demos::spawn_demo_reparse].
+    pub demo_reparse: Arc<Mutex<ReparseProgress>>,
+    /// Updates for live clients, published when a submission changes a map's leaderboard.
+    pub live: LiveUpdates,
+    /// Bumped each time an entry in `current_state` is invalidated, see [CacheState::preview_generation].
+    pub generations: Arc<Mutex<HashMap<&'static str, u64>>>,
+    /// Held while the cached preview files are read or written, so a single map's preview is never updated in a file
+    /// that is being replaced, see [CacheState::write_previews].
+    pub preview_files: Arc<Mutex<()>>,
 }
 
 impl CacheState {
@@ -113,11 +205,13 @@ impl CacheState {
     ///  "points_sp", "points_coop", "points_overall"
     /// ```
     ///
+    /// The aggregate points leaderboards, "sp_aggregate" and "coop_aggregate", are calculated by the server and cached like the previews.
+    ///
     /// **NOTE**: Portal 2 references coop chapters 1-6 as chapter ID's 1-6, meaning 1-6 are coop, and 7-15 are SP.
     pub async fn new(
         pool: &PgPool,
         config: &Config,
-        default_cat_ids: HashMap<String, i32>,
+        default_cat_ids: DefaultCategories,
     ) -> Self {
         let mut hm = HashMap::new();
         let mut points = HashMap::new();
@@ -157,8 +251,10 @@ impl CacheState {
                 hm.insert(x, false);
             }
         }
+        hm.insert(SP_AGGREGATE, false);
+        hm.insert(COOP_AGGREGATE, false);
 
-        let current_ranks = CacheState::load_all_ranks(&default_cat_ids, pool, config, true)
+        let current_ranks = CacheState::load_all_ranks(&default_cat_ids.for_game(1), pool, config, true)
             .await
             .unwrap();
 
@@ -167,6 +263,21 @@ impl CacheState {
             default_cat_ids,
             points: Arc::new(Mutex::new(points)),
             ranks: Arc::new(Mutex::new(current_ranks)),
+            cached_at: Arc::new(Mutex::new(HashMap::new())),
+            ttl: match config.cache.ttl_secs {
+                0 => None,
+                secs => Some(Duration::from_secs(secs)),
+            },
+            b2_authorized_at: Arc::new(Mutex::new(None)),
+            map_summaries: Arc::new(Mutex::new(HashMap::new())),
+            game_coop_previews: Arc::new(Mutex::new(HashMap::new())),
+            etags: Arc::new(Mutex::new(HashMap::new())),
+            recent_activity: Arc::new(Mutex::new(HashMap::new())),
+            stats_overview: Arc::new(Mutex::new(None)),
+            demo_reparse: Arc::new(Mutex::new(ReparseProgress::default())),
+            live: LiveUpdates::default(),
+            generations: Arc::new(Mutex::new(HashMap::new())),
+            preview_files: Arc::new(Mutex::new(())),
         }
     }
     /// Try to load points data from files rather than expecting that the backend must send over the data fresh every time the web server is run.
@@ -199,13 +310,16 @@ impl CacheState {
             }
         }
 
-        let coop = Maps::get_steam_ids(pool, true).await?;
-        let sp = Maps::get_steam_ids(pool, false).await?;
+        // Ranks are only kept for the base game, `default_cat_ids` has no entries for other games' maps.
+        let coop = Maps::get_steam_ids(pool, Some(true), Some(1)).await?;
+        let sp = Maps::get_steam_ids(pool, Some(false), Some(1)).await?;
         let mut current_ranks = HashMap::with_capacity(1000);
         for map in sp {
-            let res =
-                SpMap::get_sp_map_page(pool, &map, config.proof.results, default_cat_ids[&map], 1)
-                    .await?;
+            let Some(&cat_id) = default_cat_ids.get(&map) else {
+                eprintln!("No default category for map {}, skipping its ranks", map);
+                continue;
+            };
+            let res = SpMap::get_sp_map_page(pool, &map, config.proof.results, cat_id, 1, 0).await?;
             for (i, entry) in res.into_iter().enumerate() {
                 let user = current_ranks
                     .entry(entry.profile_number)
@@ -214,13 +328,11 @@ impl CacheState {
             }
         }
         for map in coop {
-            let res = CoopMap::get_coop_map_page(
-                pool,
-                &map,
-                default_cat_ids[&map],
-                1,
-            )
-            .await?;
+            let Some(&cat_id) = default_cat_ids.get(&map) else {
+                eprintln!("No default category for map {}, skipping its ranks", map);
+                continue;
+            };
+            let res = CoopMap::get_coop_map_page(pool, &map, cat_id, 1).await?;
             for (i, entry) in res.into_iter().enumerate() {
                 let user = current_ranks
                     .entry(entry.profile_number1)
@@ -256,7 +368,7 @@ impl CacheState {
             let res = CoopMap::get_coop_map_page(
                 pool,
                 map_id,
-                self.default_cat_ids[map_id],
+                self.default_cat_ids.get(map_id, 1).unwrap(),
                 1,
             )
             .await
@@ -293,8 +405,9 @@ impl CacheState {
                 pool,
                 map_id,
                 config.proof.results,
-                self.default_cat_ids[map_id],
+                self.default_cat_ids.get(map_id, 1).unwrap(),
                 1,
+                0,
             )
             .await
             .unwrap();
@@ -313,6 +426,18 @@ impl CacheState {
         let state_data = &mut self.current_state.lock().await;
         let is_cached = state_data.get_mut(update).unwrap();
         *is_cached = set_cache;
+        self.etags.lock().await.remove(update);
+        if !set_cache {
+            *self.generations.lock().await.entry(update).or_default() += 1;
+        }
+        if set_cache {
+            self.cached_at.lock().await.insert(update, Instant::now());
+        } else if update == SP_PREVIEWS || update == COOP_PREVIEWS {
+            self.map_summaries.lock().await.clear();
+            if update == COOP_PREVIEWS {
+                self.game_coop_previews.lock().await.clear();
+            }
+        }
     }
     pub async fn update_current_states(&self, update: &[&'static str], set_cache: &[bool]) -> () {
         assert_eq!(update.len(), set_cache.len());
@@ -320,12 +445,270 @@ impl CacheState {
         for (i, x) in update.into_iter().enumerate() {
             let is_cached = state_data.get_mut(x).unwrap();
             *is_cached = set_cache[i];
+            self.etags.lock().await.remove(x);
+            if !set_cache[i] {
+                *self.generations.lock().await.entry(x).or_default() += 1;
+            }
+            if set_cache[i] {
+                self.cached_at.lock().await.insert(x, Instant::now());
+            } else if *x == SP_PREVIEWS || *x == COOP_PREVIEWS {
+                self.map_summaries.lock().await.clear();
+                if *x == COOP_PREVIEWS {
+                    self.game_coop_previews.lock().await.clear();
+                }
+            }
         }
     }
+    /// Returns if the value is cached, and was cached within the `ttl`.
     pub async fn get_current_state(&self, value: &'static str) -> bool {
+        let is_cached = *self.current_state.lock().await.get_mut(value).unwrap();
+        match (is_cached, self.ttl) {
+            (true, Some(ttl)) => match self.cached_at.lock().await.get(value) {
+                Some(cached_at) => cached_at.elapsed() < ttl,
+                // Cached before we started tracking (loaded from a file), treat it as fresh.
+                None => true,
+            },
+            (is_cached, _) => is_cached,
+        }
+    }
+    /// Rebuilds the singleplayer and cooperative previews, and writes them to the cache.
+    pub async fn rebuild_previews(&self, pool: &PgPool) -> Result<()> {
+        self.rebuild_sp_previews(pool).await?;
+        self.rebuild_coop_previews(pool).await
+    }
+    /// Rebuilds the singleplayer previews, and writes them to the cache.
+    pub async fn rebuild_sp_previews(&self, pool: &PgPool) -> Result<()> {
+        self.map_summaries.lock().await.clear();
+        let generation = self.preview_generation(SP_PREVIEWS).await;
+        let sp_previews = SpPreview::get_sp_previews(pool).await?;
+        self.write_previews(SP_PREVIEWS, generation, &sp_previews).await
+    }
+    /// Rebuilds the base game's cooperative previews and writes them to the cache, other games are rebuilt on their next request.
+    pub async fn rebuild_coop_previews(&self, pool: &PgPool) -> Result<()> {
+        self.map_summaries.lock().await.clear();
+        let generation = self.preview_generation(COOP_PREVIEWS).await;
+        let coop_previews = CoopPreview::get_coop_previews(pool, &self.default_cat_ids.for_game(1), 1).await?;
+        self.write_previews(COOP_PREVIEWS, generation, &coop_previews).await?;
+        self.game_coop_previews.lock().await.clear();
+        Ok(())
+    }
+    /// Returns how many times `value` has been invalidated, taken before the previews are read from the database so
+    /// [CacheState::write_previews] can tell if they were invalidated while being read.
+    pub async fn preview_generation(&self, value: &'static str) -> u64 {
+        self.generations.lock().await.get(value).copied().unwrap_or_default()
+    }
+    /// Writes the previews for `value` (`SP_PREVIEWS` or `COOP_PREVIEWS`) to the cache and marks them as cached.
+    ///
+    /// Nothing is written if `value` was invalidated since `generation` was taken, as the previews may be missing the
+    /// change that invalidated them. They are left stale to be rebuilt on the next request.
+    pub async fn write_previews<T: Serialize>(&self, value: &'static str, generation: u64, previews: &T) -> Result<()> {
+        let _files = self.preview_files.lock().await;
+        if self.preview_generation(value).await != generation {
+            return Ok(());
+        }
+        write_to_file(value, previews).await?;
+        // Checked again with the state locked, an invalidation while the file was written leaves the previews stale.
         let state_data = &mut self.current_state.lock().await;
-        *state_data.get_mut(value).unwrap()
+        if self.preview_generation(value).await != generation {
+            return Ok(());
+        }
+        if let Some(is_cached) = state_data.get_mut(value) {
+            *is_cached = true;
+        }
+        self.etags.lock().await.remove(value);
+        self.cached_at.lock().await.insert(value, Instant::now());
+        Ok(())
+    }
+    /// Reads the cached previews for `value`, see [CacheState::write_previews].
+    pub async fn read_previews<T: for<'de> serde::Deserialize<'de>>(&self, value: &'static str) -> Result<T> {
+        let _files = self.preview_files.lock().await;
+        read_from_file(value).await
+    }
+    /// Spawns a task that rebuilds the previews every half `ttl`, so they are refreshed before they expire rather than
+    /// leaving the first request after expiry to rebuild them. Does nothing if there is no `ttl`.
+    pub fn spawn_preview_refresh(&self, pool: PgPool) {
+        let ttl = match self.ttl {
+            Some(ttl) => ttl,
+            None => return,
+        };
+        let cache = self.clone();
+        actix_web::rt::spawn(async move {
+            let mut interval = tokio::time::interval(ttl / 2);
+            loop {
+                interval.tick().await;
+                if let Err(e) = cache.rebuild_previews(&pool).await {
+                    eprintln!("Error refreshing the preview cache -> {}", e);
+                }
+            }
+        });
+    }
+    /// Returns true if BackBlaze authorized successfully within the last [B2_AUTH_WINDOW].
+    pub async fn b2_recently_authorized(&self) -> bool {
+        matches!(*self.b2_authorized_at.lock().await, Some(at) if at.elapsed() < B2_AUTH_WINDOW)
     }
+    /// Returns the cached [MapSummary] for a map and category, if there is one.
+    pub async fn get_map_summary(&self, map_id: &str, cat_id: i32) -> Option<MapSummary> {
+        self.map_summaries
+            .lock()
+            .await
+            .get(&(map_id.to_string(), cat_id))
+            .cloned()
+    }
+    /// Caches a [MapSummary] until the previews are next invalidated or rebuilt.
+    pub async fn set_map_summary(&self, summary: MapSummary) {
+        self.map_summaries
+            .lock()
+            .await
+            .insert((summary.map_id.clone(), summary.category_id), summary);
+    }
+    /// Returns the cached recent activity feed, if it was cached within the last [RECENT_ACTIVITY_TTL].
+    pub async fn get_recent_activity(&self, limit: i64, game_id: i32) -> Option<Vec<RecentActivity>> {
+        match self.recent_activity.lock().await.get(&(limit, game_id)) {
+            Some((cached_at, recent)) if cached_at.elapsed() < RECENT_ACTIVITY_TTL => Some(recent.clone()),
+            _ => None,
+        }
+    }
+    /// Caches the recent activity feed for [RECENT_ACTIVITY_TTL].
+    pub async fn set_recent_activity(&self, limit: i64, game_id: i32, recent: Vec<RecentActivity>) {
+        self.recent_activity
+            .lock()
+            .await
+            .insert((limit, game_id), (Instant::now(), recent));
+    }
+    /// Returns the cached coop previews for a game other than the base game, if there are any.
+    pub async fn get_game_coop_previews(&self, game_id: i32) -> Option<Vec<Vec<CoopPreview>>> {
+        self.game_coop_previews.lock().await.get(&game_id).cloned()
+    }
+    /// Caches the coop previews for a game other than the base game until the coop previews are next invalidated.
+    pub async fn set_game_coop_previews(&self, game_id: i32, previews: Vec<Vec<CoopPreview>>) {
+        self.game_coop_previews.lock().await.insert(game_id, previews);
+    }
+    /// Returns the cached stats overview, if it was cached within the last [STATS_OVERVIEW_TTL].
+    pub async fn get_stats_overview(&self) -> Option<StatsOverview> {
+        match &*self.stats_overview.lock().await {
+            Some((cached_at, overview)) if cached_at.elapsed() < STATS_OVERVIEW_TTL => Some(overview.clone()),
+            _ => None,
+        }
+    }
+    /// Caches the stats overview for [STATS_OVERVIEW_TTL].
+    pub async fn set_stats_overview(&self, overview: StatsOverview) {
+        *self.stats_overview.lock().await = Some((Instant::now(), overview));
+    }
+    /// Returns a 304 if `value` is cached and the request's `If-None-Match` matches its ETag, so the cached file does not need to be read.
+    pub async fn not_modified(&self, req: &HttpRequest, value: &'static str) -> Option<HttpResponse> {
+        if !self.get_current_state(value).await {
+            return None;
+        }
+        let etag = self.etags.lock().await.get(value).cloned()?;
+        etag_matches(req, &etag).then(|| not_modified_response(etag))
+    }
+    /// Serializes the previews for `value` and responds with their ETag, or a 304 if the client already has them.
+    ///
+    /// The ETag is stored until `value` is next updated, see [CacheState::not_modified].
+    pub async fn preview_response<T: Serialize>(
+        &self,
+        req: &HttpRequest,
+        value: &'static str,
+        previews: &T,
+    ) -> Result<HttpResponse> {
+        let body = serde_json::to_vec(previews)?;
+        let etag = compute_etag(&body);
+        self.etags.lock().await.insert(value, etag.clone());
+        if etag_matches(req, &etag) {
+            return Ok(not_modified_response(etag));
+        }
+        Ok(HttpResponse::Ok()
+            .content_type("application/json")
+            .insert_header((ETAG, etag))
+            .body(body))
+    }
+    /// Records a successful BackBlaze authorization.
+    pub async fn set_b2_authorized(&self) {
+        *self.b2_authorized_at.lock().await = Some(Instant::now());
+    }
+    /// Marks both the singleplayer and cooperative previews as stale, used when a change can affect scores on every map (bans, verification).
+    pub async fn invalidate_all_previews(&self) {
+        self.update_current_states(&[SP_PREVIEWS, COOP_PREVIEWS], &[false, false])
+            .await;
+    }
+    /// Marks the aggregate points leaderboards as stale, any change to a score can shift points across the board.
+    pub async fn invalidate_aggregate_points(&self) {
+        self.update_current_states(&[SP_AGGREGATE, COOP_AGGREGATE], &[false, false])
+            .await;
+    }
+    /// Rebuilds the cached preview for a single map in place, rather than invalidating the previews for every map.
+    ///
+    /// Falls back to [CacheState::invalidate_all_previews] if the cached previews cannot be updated.
+    pub async fn invalidate_map_preview(&self, pool: &PgPool, map_id: &str) {
+        self.map_summaries.lock().await.retain(|(id, _), _| id != map_id);
+        if let Err(e) = self.refresh_map_preview(pool, map_id).await {
+            eprintln!("Could not refresh the cached preview for {map_id}, invalidating all previews -> {e}");
+            self.invalidate_all_previews().await;
+        }
+    }
+    /// The previews are cached in the same order as the map ids from [Maps::get_steam_ids], so the map's position is used to find its preview.
+    ///
+    /// The preview files are locked from when they are read until the updated previews are written, so concurrent
+    /// refreshes and rebuilds cannot overwrite each other's changes.
+    async fn refresh_map_preview(&self, pool: &PgPool, map_id: &str) -> Result<()> {
+        let _files = self.preview_files.lock().await;
+        let sp = Maps::get_steam_ids(pool, Some(false), None).await?;
+        if let Some(i) = sp.iter().position(|id| id == map_id) {
+            // Nothing to refresh if the previews will be rebuilt on the next request anyway.
+            if self.get_current_state(SP_PREVIEWS).await {
+                let mut previews = read_from_file::<Vec<Vec<SpPreview>>>(SP_PREVIEWS).await?;
+                match previews.get_mut(i) {
+                    Some(preview) => *preview = SpPreview::get_sp_preview(pool, map_id).await?,
+                    None => bail!("Cached sp previews are missing map {}", map_id),
+                }
+                write_to_file(SP_PREVIEWS, &previews).await?;
+                self.etags.lock().await.remove(SP_PREVIEWS);
+            }
+            return Ok(());
+        }
+        // Only the base game's coop previews are cached to file, other games are rebuilt on their next request.
+        let coop = Maps::get_steam_ids(pool, Some(true), Some(1)).await?;
+        if let Some(i) = coop.iter().position(|id| id == map_id) {
+            if self.get_current_state(COOP_PREVIEWS).await {
+                let cat_id = match self.default_cat_ids.get(map_id, 1) {
+                    Some(cat_id) => cat_id,
+                    None => bail!("No default category for map {}", map_id),
+                };
+                let mut previews = read_from_file::<Vec<Vec<CoopPreview>>>(COOP_PREVIEWS).await?;
+                match previews.get_mut(i) {
+                    Some(preview) => *preview = CoopPreview::get_coop_preview(pool, map_id, cat_id).await?,
+                    None => bail!("Cached coop previews are missing map {}", map_id),
+                }
+                write_to_file(COOP_PREVIEWS, &previews).await?;
+                self.etags.lock().await.remove(COOP_PREVIEWS);
+            }
+            return Ok(());
+        }
+        if Maps::get_steam_ids(pool, Some(true), None).await?.iter().any(|id| id == map_id) {
+            self.game_coop_previews.lock().await.clear();
+            return Ok(());
+        }
+        bail!("Map {} does not exist", map_id)
+    }
+}
+
+/// A strong ETag for a response body, the quoted hex SHA1 of the body.
+pub fn compute_etag(body: &[u8]) -> String {
+    format!("\"{:x}\"", Sha1::digest(body))
+}
+
+/// Returns true if the request's `If-None-Match` header lists `etag` (or is `*`), weak ETags are compared as if they were strong.
+pub fn etag_matches(req: &HttpRequest, etag: &str) -> bool {
+    req.headers()
+        .get_all(header::IF_NONE_MATCH)
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|tag| tag.trim())
+        .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+}
+
+fn not_modified_response(etag: String) -> HttpResponse {
+    HttpResponse::NotModified().insert_header((ETAG, etag)).finish()
 }
 
 /// Writes data to a file if the type implements [serde::Serialize]
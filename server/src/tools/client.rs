@@ -0,0 +1,83 @@
+//! Identifying the client behind a request, for rate limiting and logging.
+//!
+//! `X-Forwarded-For` and `X-Real-IP` can be set by anyone, so they are only read when the request comes from one of the
+//! proxies in `TRUSTED_PROXIES`, see [crate::tools::config::Config]. Otherwise the peer address is the client.
+use actix_web::{http::header::HeaderMap, HttpRequest};
+use std::net::{IpAddr, Ipv6Addr};
+
+/// Used as the identifier when there is no peer address (e.g. requests built in tests).
+pub const UNKNOWN_CLIENT: &str = "unknown";
+
+/// The client's address, trusting the forwarding headers only when the peer is in `trusted_proxies`.
+///
+/// `X-Forwarded-For` is read from the right, skipping trusted proxies, so the first untrusted address is the client.
+/// Anything further left was sent by the client itself and is never read, so it cannot change the result. An entry that
+/// is not an address stops the walk at the last address before it. `X-Real-IP` is used when there is no `X-Forwarded-For`.
+pub fn client_ip(req: &HttpRequest, trusted_proxies: &[IpAddr]) -> Option<IpAddr> {
+    let peer = normalize(req.peer_addr()?.ip());
+    if !trusted_proxies.contains(&peer) {
+        return Some(peer);
+    }
+    let headers = req.headers();
+    let forwarded = forwarded_for(headers);
+    if forwarded.is_empty() {
+        return header_ip(headers, "x-real-ip").or(Some(peer));
+    }
+    // When every hop is trusted, the leftmost one is as close to the client as we can get.
+    let mut client = peer;
+    for entry in forwarded.into_iter().rev() {
+        let Some(ip) = entry else {
+            break;
+        };
+        client = ip;
+        if !trusted_proxies.contains(&ip) {
+            break;
+        }
+    }
+    Some(client)
+}
+
+/// The key a client is rate limited by.
+///
+/// IPv6 addresses are grouped by their /64, a single host is usually given a whole /64 and can pick any address in it.
+pub fn client_id(req: &HttpRequest, trusted_proxies: &[IpAddr]) -> String {
+    match client_ip(req, trusted_proxies) {
+        Some(IpAddr::V6(ip)) => {
+            let [a, b, c, d, ..] = ip.segments();
+            format!("{}/64", Ipv6Addr::new(a, b, c, d, 0, 0, 0, 0))
+        }
+        Some(ip) => ip.to_string(),
+        None => UNKNOWN_CLIENT.to_string(),
+    }
+}
+
+/// Every entry in the `X-Forwarded-For` headers in order, `None` for entries that are not an address.
+fn forwarded_for(headers: &HeaderMap) -> Vec<Option<IpAddr>> {
+    headers
+        .get_all("x-forwarded-for")
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|entry| parse_ip(entry.trim()))
+        .collect()
+}
+
+fn header_ip(headers: &HeaderMap, name: &str) -> Option<IpAddr> {
+    headers.get(name)?.to_str().ok().and_then(|value| parse_ip(value.trim()))
+}
+
+/// Parses an address, allowing the port some proxies include (`1.2.3.4:5678`, `[::1]:5678`).
+fn parse_ip(value: &str) -> Option<IpAddr> {
+    let ip = value
+        .parse::<IpAddr>()
+        .ok()
+        .or_else(|| value.parse::<std::net::SocketAddr>().ok().map(|addr| addr.ip()))?;
+    Some(normalize(ip))
+}
+
+/// IPv4 clients reaching a dual-stack socket show up as IPv4-mapped IPv6 addresses (`::ffff:1.2.3.4`).
+fn normalize(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(ip),
+        ip => ip,
+    }
+}
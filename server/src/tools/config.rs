@@ -1,5 +1,6 @@
 use config::ConfigError;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
 /// Server hosting information for mounting the webserver.
 #[derive(Deserialize, Debug, Clone)]
@@ -8,21 +9,248 @@ pub struct ServerConfig {
     pub port: i32,
 }
 /// The proof standards, update based on the mod tools desired.
+///
+/// `results` is also the number of entries on a map's leaderboard by default, requests can ask for up to `max_results`.
 #[derive(Deserialize, Debug, Clone)]
 pub struct ProofConfig {
     pub results: i32,
     pub demo: i32,
     pub video: i32,
+    #[serde(default = "default_max_results")]
+    pub max_results: i32,
+}
+
+fn default_max_results() -> i32 {
+    1000
 }
 
 /// Authentication information used to interact with BackBlaze's storage API.
+///
+/// Failed uploads are retried up to `upload_attempts` times in total, waiting `upload_backoff_ms` after the first failure
+/// and doubling the wait after each one after that. Files larger than `large_file_threshold_mb` are uploaded in parts
+/// with B2's large file API.
 #[derive(Deserialize, Debug, Clone)]
 pub struct BackBlazeConfig {
     pub keyid: String,
     pub key: String,
     pub bucket: String,
+    #[serde(default = "default_upload_attempts")]
+    pub upload_attempts: u32,
+    #[serde(default = "default_upload_backoff_ms")]
+    pub upload_backoff_ms: u64,
+    #[serde(default = "default_large_file_threshold_mb")]
+    pub large_file_threshold_mb: u64,
+}
+
+fn default_upload_attempts() -> u32 {
+    3
+}
+
+fn default_upload_backoff_ms() -> u64 {
+    500
+}
+
+fn default_large_file_threshold_mb() -> u64 {
+    200
+}
+
+/// The backend demo files are stored in, see [crate::tools::storage].
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageBackend {
+    #[default]
+    Backblaze,
+    Local,
+}
+
+/// Limits on uploaded demos, uploads over `max_bytes` are rejected while they are being received.
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(default)]
+pub struct DemosConfig {
+    pub max_bytes: u64,
+}
+
+impl Default for DemosConfig {
+    fn default() -> Self {
+        DemosConfig {
+            max_bytes: 256 * 1024 * 1024,
+        }
+    }
+}
+
+/// Where demo files are stored, demos are kept in `local_dir` when `backend` is `local`.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct StorageConfig {
+    pub backend: StorageBackend,
+    pub local_dir: String,
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        StorageConfig {
+            backend: StorageBackend::default(),
+            local_dir: "./demo_storage".to_string(),
+        }
+    }
+}
+
+/// Discord integration, the webhook is used to announce new world records. Optional, nothing is posted if unset.
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct DiscordConfig {
+    pub webhook_url: Option<String>,
+}
+
+/// Size and timeouts of the database connection pool, see [crate::tools::db].
+///
+/// Requests waiting longer than `acquire_timeout_secs` for a connection fail with a 503 instead of hanging, idle
+/// connections are closed after `idle_timeout_secs` (`0` keeps them open). Waits longer than `slow_acquire_ms` are logged.
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(default)]
+pub struct DatabaseConfig {
+    pub max_connections: u32,
+    pub acquire_timeout_secs: u64,
+    pub idle_timeout_secs: u64,
+    pub slow_acquire_ms: u64,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        DatabaseConfig {
+            max_connections: 10,
+            acquire_timeout_secs: 10,
+            idle_timeout_secs: 600,
+            slow_acquire_ms: 500,
+        }
+    }
+}
+
+/// How long cached previews are served before they are considered stale, `0` caches until a score invalidates them.
+#[derive(Deserialize, Debug, Clone)]
+pub struct CacheConfig {
+    pub ttl_secs: u64,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        CacheConfig { ttl_secs: 3600 }
+    }
+}
+
+/// Moderation for new users, when `filter_names` is set offensive names are flagged for review.
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct ModerationConfig {
+    pub filter_names: bool,
 }
 
+/// Maintenance mode, while `enabled` the boards are read-only and changes are rejected with a 503.
+///
+/// This is only the value the server starts with, admins can toggle it at runtime, see [crate::tools::maintenance].
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct MaintenanceConfig {
+    pub enabled: bool,
+}
+
+/// The curve used to turn a rank into points, see [crate::tools::helpers::score].
+///
+/// Rank 1 is worth `max_points`, falling off with the power `decay` until ranks past `max_rank` are worth nothing.
+/// Ranks up to `max_rank` are always worth at least `min_points`. The defaults are the original iVerb formula.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, ToSchema)]
+#[serde(default)]
+pub struct PointsConfig {
+    pub max_points: f32,
+    pub max_rank: i32,
+    pub decay: f32,
+    pub min_points: f32,
+}
+
+impl Default for PointsConfig {
+    fn default() -> Self {
+        PointsConfig {
+            max_points: 200.0,
+            max_rank: 200,
+            decay: 2.0,
+            min_points: 1.0,
+        }
+    }
+}
+
+/// Limits on score submissions, each player can make `submissions` submissions at once, refilled evenly over `per_secs`.
+/// Setting `submissions` to `0` turns off the limit, see [crate::tools::ratelimit].
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(default)]
+pub struct RateLimitConfig {
+    pub submissions: u32,
+    pub per_secs: u64,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        RateLimitConfig {
+            submissions: 10,
+            per_secs: 60,
+        }
+    }
+}
+
+/// Rules for new score submissions.
+///
+/// When `require_improvement` is set, a submission that does not beat the player's current personal best is rejected,
+/// otherwise it is accepted but recorded as not being a PB (with a `score_delta` that is zero or negative).
+///
+/// `max_note_length` is the most characters a `note` or `admin_note` can have, see [crate::tools::helpers::clean_note].
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(default)]
+pub struct SubmissionConfig {
+    pub require_improvement: bool,
+    pub max_note_length: usize,
+}
+
+impl Default for SubmissionConfig {
+    fn default() -> Self {
+        SubmissionConfig {
+            require_improvement: true,
+            max_note_length: 500,
+        }
+    }
+}
+
+/// Origins allowed to make cross-origin requests to the API, see [crate::tools::cors].
+///
+/// `allowed_origins` is a comma separated list, no cross-origin requests are allowed if it is empty.
+/// When `allow_credentials` is set, browsers send cookies and `Authorization` headers with cross-origin requests.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct CorsConfig {
+    #[serde(deserialize_with = "comma_separated")]
+    pub allowed_origins: Vec<String>,
+    pub allow_credentials: bool,
+}
+
+/// Splits a comma separated environment variable into a list, ignoring empty entries.
+fn comma_separated<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let list = String::deserialize(deserializer)?;
+    Ok(list
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(String::from)
+        .collect())
+}
+
+/// Parses a comma separated environment variable of IP addresses, ignoring empty entries.
+fn comma_separated_ips<'de, D>(deserializer: D) -> Result<Vec<std::net::IpAddr>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    comma_separated(deserializer)?
+        .iter()
+        .map(|ip| ip.parse().map_err(serde::de::Error::custom))
+        .collect()
+}
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct SteamConfig {
@@ -33,10 +261,35 @@ pub struct SteamConfig {
 #[derive(Deserialize, Debug, Clone)]
 pub struct Config {
     pub database_url: String,
+    #[serde(default)]
+    pub database: DatabaseConfig,
     pub server: ServerConfig,
     pub proof: ProofConfig,
     pub steam: SteamConfig,
     pub backblaze: BackBlazeConfig,
+    #[serde(default)]
+    pub storage: StorageConfig,
+    #[serde(default)]
+    pub demos: DemosConfig,
+    #[serde(default)]
+    pub discord: DiscordConfig,
+    #[serde(default)]
+    pub cache: CacheConfig,
+    #[serde(default)]
+    pub moderation: ModerationConfig,
+    #[serde(default)]
+    pub points: PointsConfig,
+    #[serde(default)]
+    pub ratelimit: RateLimitConfig,
+    #[serde(default)]
+    pub submissions: SubmissionConfig,
+    #[serde(default)]
+    pub cors: CorsConfig,
+    #[serde(default)]
+    pub maintenance: MaintenanceConfig,
+    /// Proxies whose `X-Forwarded-For` and `X-Real-IP` headers are trusted, see [crate::tools::client].
+    #[serde(default, deserialize_with = "comma_separated_ips")]
+    pub trusted_proxies: Vec<std::net::IpAddr>,
 }
 // Extracts the environment variables from the .env file at the src level.
 impl Config {
@@ -0,0 +1,34 @@
+//! Cross-origin resource sharing for the frontend, built from [CorsConfig].
+//!
+//! Only the origins in `CORS.ALLOWED_ORIGINS` can make cross-origin requests, if none are configured every cross-origin request is denied.
+//! Requests without an `Origin` header (the backend, scripts) are not affected.
+use crate::api::v1::handlers::demos::DEMO_SHA1_HEADER;
+use crate::tools::config::CorsConfig;
+use actix_cors::Cors;
+use actix_web::http::header;
+
+/// How long browsers can cache the response to a preflight request, in seconds.
+const PREFLIGHT_MAX_AGE: usize = 3600;
+
+/// Builds the [Cors] middleware for the allowed origins, allowing the methods and headers used by the API.
+pub fn cors(config: &CorsConfig) -> Cors {
+    let mut cors = Cors::default()
+        .allowed_methods(vec!["GET", "POST", "PUT", "PATCH", "DELETE"])
+        .allowed_headers(vec![
+            header::ACCEPT,
+            header::AUTHORIZATION,
+            header::CONTENT_TYPE,
+            header::IF_NONE_MATCH,
+        ])
+        .allowed_header(DEMO_SHA1_HEADER)
+        // Let the frontend read the headers it needs for caching and retrying rate limited submissions.
+        .expose_headers(vec![header::ETAG, header::RETRY_AFTER])
+        .max_age(PREFLIGHT_MAX_AGE);
+    for origin in &config.allowed_origins {
+        cors = cors.allowed_origin(origin);
+    }
+    if config.allow_credentials {
+        cors = cors.supports_credentials();
+    }
+    cors
+}
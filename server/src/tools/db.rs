@@ -0,0 +1,52 @@
+//! The database connection pool, sized and timed out from `DATABASE.*`, see [DatabaseConfig].
+//!
+//! Queries take connections from the pool directly, so the time spent waiting for one is sampled in the background by
+//! [spawn_acquire_monitor] rather than measured on every query.
+use crate::tools::{config::DatabaseConfig, metrics::Metrics};
+use sqlx::{postgres::PgPoolOptions, PgPool};
+use std::time::{Duration, Instant};
+
+/// How often [spawn_acquire_monitor] checks how long it takes to get a connection.
+const MONITOR_INTERVAL: Duration = Duration::from_secs(10);
+
+/// The pool options for `config`, an `idle_timeout_secs` of `0` keeps idle connections open.
+pub fn pool_options(config: &DatabaseConfig) -> PgPoolOptions {
+    PgPoolOptions::new()
+        .max_connections(config.max_connections.max(1))
+        .acquire_timeout(Duration::from_secs(config.acquire_timeout_secs))
+        .idle_timeout((config.idle_timeout_secs > 0).then(|| Duration::from_secs(config.idle_timeout_secs)))
+}
+
+/// Connects to the database at `url` with the pool options from `config`.
+pub async fn connect(url: &str, config: &DatabaseConfig) -> Result<PgPool, sqlx::Error> {
+    pool_options(config).connect(url).await
+}
+
+/// Periodically times getting a connection from `pool`, recorded in [Metrics] and logged when it takes longer than
+/// `slow_acquire_ms`, a sign that the pool is too small for the load.
+pub fn spawn_acquire_monitor(pool: PgPool, config: DatabaseConfig, metrics: Metrics) {
+    let threshold = Duration::from_millis(config.slow_acquire_ms);
+    actix_web::rt::spawn(async move {
+        let mut interval = tokio::time::interval(MONITOR_INTERVAL);
+        loop {
+            interval.tick().await;
+            let start = Instant::now();
+            match pool.acquire().await {
+                Ok(_connection) => {
+                    let elapsed = start.elapsed();
+                    metrics.observe_db_acquire(elapsed);
+                    if elapsed > threshold {
+                        eprintln!(
+                            "Waited {}ms for a database connection ({} open, {} idle, max {})",
+                            elapsed.as_millis(),
+                            pool.size(),
+                            pool.num_idle(),
+                            config.max_connections
+                        );
+                    }
+                }
+                Err(e) => eprintln!("Could not get a database connection -> {e}"),
+            }
+        }
+    });
+}
@@ -0,0 +1,271 @@
+//! Parsing for uploaded Source engine demo files.
+//!
+//! Only the demo header and the trailing SAR checksum message are read, the rest of the demo is left untouched.
+use crate::models::demos::{DemoHeader, DemoMetadata, SarChecksum};
+use anyhow::{bail, Result};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Magic bytes every Source engine demo starts with.
+const DEMO_MAGIC: &[u8; 8] = b"HL2DEMO\0";
+/// Length of the fixed size strings in the demo header.
+const HEADER_STRING_LEN: usize = 260;
+/// Total length of the demo header.
+const HEADER_LEN: usize = 8 + 4 + 4 + HEADER_STRING_LEN * 4 + 4 + 4 + 4 + 4;
+/// SAR checksum data: 8 bytes of padding, the SAR data type and two u32 checksums.
+const SAR_CHECKSUM_LEN: usize = 8 + 1 + 4 + 4;
+/// SAR data type used for the checksum message.
+const SAR_CHECKSUM_TYPE: u8 = 0xFF;
+
+/// Reads a demo file from disk and extracts the header information, and the SAR checksum if one was appended.
+///
+/// Returns an error if the file is too short to contain a header, or is not a demo file.
+pub fn parse_demo(path: &str) -> Result<DemoMetadata> {
+    let bytes = std::fs::read(path)?;
+    parse_demo_bytes(&bytes)
+}
+
+/// Parses the contents of a demo file, see [parse_demo].
+pub fn parse_demo_bytes(bytes: &[u8]) -> Result<DemoMetadata> {
+    if bytes.len() < HEADER_LEN {
+        bail!("Demo is truncated, expected at least {} bytes but found {}", HEADER_LEN, bytes.len());
+    }
+    if &bytes[..8] != DEMO_MAGIC {
+        bail!("File is not a demo, missing the HL2DEMO header");
+    }
+    let mut offset = 8;
+    let demo_protocol = read_i32(bytes, &mut offset);
+    let network_protocol = read_i32(bytes, &mut offset);
+    let server_name = read_string(bytes, &mut offset);
+    let client_name = read_string(bytes, &mut offset);
+    let map_name = read_string(bytes, &mut offset);
+    let game_directory = read_string(bytes, &mut offset);
+    let playback_time = f32::from_le_bytes(bytes[offset..offset + 4].try_into()?);
+    offset += 4;
+    let playback_ticks = read_i32(bytes, &mut offset);
+    let playback_frames = read_i32(bytes, &mut offset);
+    if map_name.is_empty() || playback_ticks < 0 || !playback_time.is_finite() {
+        bail!("Demo header is corrupt");
+    }
+    Ok(DemoMetadata {
+        demo_protocol,
+        network_protocol,
+        server_name,
+        client_name,
+        map_name,
+        game_directory,
+        playback_time,
+        playback_ticks,
+        playback_frames,
+        sar_checksum: parse_sar_checksum(&bytes[HEADER_LEN..]),
+    })
+}
+
+/// SAR appends its checksum as the final custom data message in the demo, preceded by the length of its data.
+fn parse_sar_checksum(body: &[u8]) -> Option<SarChecksum> {
+    if body.len() < SAR_CHECKSUM_LEN + 4 {
+        return None;
+    }
+    let data = &body[body.len() - SAR_CHECKSUM_LEN..];
+    let mut size_offset = body.len() - SAR_CHECKSUM_LEN - 4;
+    if read_i32(body, &mut size_offset) != SAR_CHECKSUM_LEN as i32 || data[8] != SAR_CHECKSUM_TYPE {
+        return None;
+    }
+    Some(SarChecksum {
+        demo_sum: u32::from_le_bytes(data[9..13].try_into().ok()?),
+        sar_sum: u32::from_le_bytes(data[13..17].try_into().ok()?),
+    })
+}
+
+/// Reads a little-endian i32 and advances the offset. Callers are expected to have checked the length.
+fn read_i32(bytes: &[u8], offset: &mut usize) -> i32 {
+    let mut buf = [0u8; 4];
+    buf.copy_from_slice(&bytes[*offset..*offset + 4]);
+    *offset += 4;
+    i32::from_le_bytes(buf)
+}
+
+/// Reads one of the fixed length, null-terminated header strings and advances the offset.
+fn read_string(bytes: &[u8], offset: &mut usize) -> String {
+    let raw = &bytes[*offset..*offset + HEADER_STRING_LEN];
+    *offset += HEADER_STRING_LEN;
+    let end = raw.iter().position(|&b| b == 0).unwrap_or(HEADER_STRING_LEN);
+    String::from_utf8_lossy(&raw[..end]).to_string()
+}
+
+/// Strips any directories from an uploaded file name and removes characters that are not valid in file names,
+/// so the demo cannot be written outside of the demos directory.
+///
+/// Returns an error if nothing usable is left of the name.
+pub fn sanitize_demo_file_name(file_name: &str) -> Result<String> {
+    let base_name = file_name.rsplit(['/', '\\']).next().unwrap_or_default();
+    let sanitized = sanitize_filename::sanitize(base_name);
+    if sanitized.is_empty() || sanitized.chars().all(|c| c == '.') {
+        bail!("Invalid demo file name {:?}", file_name);
+    }
+    Ok(sanitized)
+}
+
+/// Generates the name an upload is written under locally, made from the time, the process and a counter rather than the
+/// client's file name, so concurrent uploads of files with the same name never share a path.
+pub fn upload_file_name() -> String {
+    static UPLOADS: AtomicU64 = AtomicU64::new(0);
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos();
+    format!("upload_{}_{}_{}.dem", now, std::process::id(), UPLOADS.fetch_add(1, Ordering::Relaxed))
+}
+
+/// Checks an uploaded file name has the `.dem` extension.
+pub fn is_demo_file_name(file_name: &str) -> bool {
+    file_name.rsplit_once('.').is_some_and(|(name, ext)| !name.is_empty() && ext.eq_ignore_ascii_case("dem"))
+}
+
+/// Checks a file starts with the magic bytes every demo starts with.
+pub fn has_demo_magic(bytes: &[u8]) -> bool {
+    bytes.starts_with(DEMO_MAGIC)
+}
+
+/// Checks that the map a demo was recorded on matches the map the score is being submitted for.
+///
+/// Maps we do not have a file name for are not checked.
+pub fn demo_matches_map(metadata: &DemoMetadata, map_id: &str) -> bool {
+    match map_file_name(map_id) {
+        Some(file_name) => metadata.map_name.eq_ignore_ascii_case(file_name),
+        None => true,
+    }
+}
+
+/// The version of SAR a demo was recorded with, stored as the hex SAR checksum. `None` if SAR was not used.
+pub fn sar_version(metadata: &DemoMetadata) -> Option<String> {
+    metadata.sar_checksum.map(|checksum| format!("{:08X}", checksum.sar_sum))
+}
+
+/// The parts of a demo's metadata that are stored with the demo.
+pub fn demo_header(metadata: &DemoMetadata) -> DemoHeader {
+    DemoHeader {
+        map_name: metadata.map_name.clone(),
+        tick_count: metadata.playback_ticks,
+        playback_time: metadata.playback_time,
+        sar_version: sar_version(metadata),
+        protocol: metadata.demo_protocol,
+    }
+}
+
+/// Returns the `.bsp` name for a map's steam_id.
+pub fn map_file_name(map_id: &str) -> Option<&'static str> {
+    MAP_FILE_NAMES
+        .iter()
+        .find(|(id, _)| *id == map_id)
+        .map(|(_, name)| *name)
+}
+
+/// Steam IDs for the base game maps, and the file name of the map.
+const MAP_FILE_NAMES: &[(&str, &str)] = &[
+    ("62761", "sp_a1_intro1"),
+    ("62758", "sp_a1_intro2"),
+    ("47458", "sp_a1_intro3"),
+    ("47455", "sp_a1_intro4"),
+    ("47452", "sp_a1_intro5"),
+    ("47106", "sp_a1_intro6"),
+    ("62763", "sp_a1_intro7"),
+    ("62759", "sp_a1_wakeup"),
+    ("47735", "sp_a2_intro"),
+    ("62765", "sp_a2_laser_intro"),
+    ("47736", "sp_a2_laser_stairs"),
+    ("47738", "sp_a2_dual_lasers"),
+    ("47742", "sp_a2_laser_over_goo"),
+    ("62767", "sp_a2_catapult_intro"),
+    ("47744", "sp_a2_trust_fling"),
+    ("47465", "sp_a2_pit_flings"),
+    ("47746", "sp_a2_fizzler_intro"),
+    ("47748", "sp_a2_sphere_peek"),
+    ("47751", "sp_a2_ricochet"),
+    ("47752", "sp_a2_bridge_intro"),
+    ("47755", "sp_a2_bridge_the_gap"),
+    ("47756", "sp_a2_turret_intro"),
+    ("47759", "sp_a2_laser_relays"),
+    ("47760", "sp_a2_turret_blocker"),
+    ("47763", "sp_a2_laser_vs_turret"),
+    ("47764", "sp_a2_pull_the_rug"),
+    ("47766", "sp_a2_column_blocker"),
+    ("47768", "sp_a2_laser_chaining"),
+    ("47770", "sp_a2_triple_laser"),
+    ("47773", "sp_a2_bts1"),
+    ("47774", "sp_a2_bts2"),
+    ("47776", "sp_a2_bts3"),
+    ("47779", "sp_a2_bts4"),
+    ("47780", "sp_a2_bts5"),
+    ("62771", "sp_a2_core"),
+    ("47783", "sp_a3_01"),
+    ("47784", "sp_a3_03"),
+    ("47787", "sp_a3_jump_intro"),
+    ("47468", "sp_a3_bomb_flings"),
+    ("47469", "sp_a3_crazy_box"),
+    ("47472", "sp_a3_transition01"),
+    ("47791", "sp_a3_speed_ramp"),
+    ("47793", "sp_a3_speed_flings"),
+    ("47795", "sp_a3_portal_intro"),
+    ("47798", "sp_a3_end"),
+    ("88350", "sp_a4_intro"),
+    ("47800", "sp_a4_tb_intro"),
+    ("47802", "sp_a4_tb_trust_drop"),
+    ("47804", "sp_a4_tb_wall_button"),
+    ("47806", "sp_a4_tb_polarity"),
+    ("47808", "sp_a4_tb_catch"),
+    ("47811", "sp_a4_stop_the_box"),
+    ("47813", "sp_a4_laser_catapult"),
+    ("47815", "sp_a4_laser_platform"),
+    ("47817", "sp_a4_speed_tb_catch"),
+    ("47819", "sp_a4_jump_polarity"),
+    ("62776", "sp_a4_finale1"),
+    ("47821", "sp_a4_finale2"),
+    ("47824", "sp_a4_finale3"),
+    ("47456", "sp_a4_finale4"),
+    ("47741", "mp_coop_doors"),
+    ("47825", "mp_coop_race_2"),
+    ("47828", "mp_coop_laser_2"),
+    ("47829", "mp_coop_rat_maze"),
+    ("45467", "mp_coop_laser_crusher"),
+    ("46362", "mp_coop_teambts"),
+    ("47831", "mp_coop_fling_3"),
+    ("47833", "mp_coop_infinifling_train"),
+    ("47835", "mp_coop_come_along"),
+    ("47837", "mp_coop_fling_1"),
+    ("47840", "mp_coop_catapult_1"),
+    ("47841", "mp_coop_multifling_1"),
+    ("47844", "mp_coop_fling_crushers"),
+    ("47845", "mp_coop_fan"),
+    ("47848", "mp_coop_wall_intro"),
+    ("47849", "mp_coop_wall_2"),
+    ("47854", "mp_coop_catapult_wall_intro"),
+    ("47856", "mp_coop_wall_block"),
+    ("47858", "mp_coop_catapult_2"),
+    ("47861", "mp_coop_turret_walls"),
+    ("52642", "mp_coop_turret_ball"),
+    ("52660", "mp_coop_wall_5"),
+    ("52662", "mp_coop_tbeam_redirect"),
+    ("52663", "mp_coop_tbeam_drill"),
+    ("52665", "mp_coop_tbeam_catch_grind_1"),
+    ("52667", "mp_coop_tbeam_laser_1"),
+    ("52671", "mp_coop_tbeam_polarity"),
+    ("52687", "mp_coop_tbeam_polarity2"),
+    ("52689", "mp_coop_tbeam_polarity3"),
+    ("52691", "mp_coop_tbeam_maze"),
+    ("52777", "mp_coop_tbeam_end"),
+    ("52694", "mp_coop_paint_come_along"),
+    ("52711", "mp_coop_paint_redirect"),
+    ("52714", "mp_coop_paint_bridge"),
+    ("52715", "mp_coop_paint_walljumps"),
+    ("52717", "mp_coop_paint_speed_fling"),
+    ("52735", "mp_coop_paint_red_racer"),
+    ("52738", "mp_coop_paint_speed_catch"),
+    ("52740", "mp_coop_paint_longjump_intro"),
+    ("49341", "mp_coop_separation_1"),
+    ("49343", "mp_coop_tripleaxis"),
+    ("49345", "mp_coop_catapult_catch"),
+    ("49347", "mp_coop_2paints_1bridge"),
+    ("49349", "mp_coop_paint_conversion"),
+    ("49351", "mp_coop_bridge_catch"),
+    ("52757", "mp_coop_laser_tbeam"),
+    ("52759", "mp_coop_paint_rat_maze"),
+    ("48287", "mp_coop_paint_crazy_box"),
+];
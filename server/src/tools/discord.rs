@@ -0,0 +1,83 @@
+//! Posts announcements to the Discord webhook configured with `DISCORD.WEBHOOK_URL`.
+use crate::models::changelog::ChangelogInsert;
+use crate::models::chapters::Games;
+use crate::models::maps::Maps;
+use crate::tools::config::Config;
+use crate::tools::helpers::{format_score, DEFAULT_TICK_RATE};
+use anyhow::{bail, Result};
+use sqlx::PgPool;
+
+/// Posts a message announcing a new world record to the configured webhook.
+///
+/// Does nothing if there is no webhook configured.
+pub async fn post_wr_webhook(
+    config: &Config,
+    changelog: &ChangelogInsert,
+    map_name: &str,
+    tick_rate: i32,
+) -> Result<()> {
+    let webhook_url = match &config.discord.webhook_url {
+        Some(url) if !url.is_empty() => url,
+        _ => return Ok(()),
+    };
+    let resp = reqwest::Client::new()
+        .post(webhook_url)
+        .json(&wr_message(changelog, map_name, tick_rate))
+        .send()
+        .await?;
+    if !resp.status().is_success() {
+        bail!("Discord returned {}", resp.status());
+    }
+    Ok(())
+}
+
+/// Returns true if the score should be announced, a new world record (`post_rank == 1`) that is verified and not banned.
+pub fn is_announced_wr(changelog: &ChangelogInsert) -> bool {
+    changelog.post_rank == Some(1) && changelog.verified == Some(true) && !changelog.banned
+}
+
+/// Announces the score in the background if it is a new world record, see [is_announced_wr].
+///
+/// Called after the score has been inserted, failures are only logged so they can never fail a submission.
+pub fn notify_if_wr(pool: &PgPool, config: &Config, changelog: &ChangelogInsert) {
+    if !is_announced_wr(changelog) {
+        return;
+    }
+    let (pool, config, changelog) = (pool.clone(), config.clone(), changelog.clone());
+    actix_web::rt::spawn(async move {
+        let map_name = match Maps::get_map_name(&pool, changelog.map_id.clone()).await {
+            Ok(Some(map_name)) => map_name,
+            _ => changelog.map_id.clone(),
+        };
+        let tick_rate = match Maps::get_chapter_from_map_id(&pool, changelog.map_id.clone()).await {
+            Ok(Some(chapter)) => Games::get_tick_rate(&pool, chapter.game_id).await.unwrap_or(DEFAULT_TICK_RATE),
+            _ => DEFAULT_TICK_RATE,
+        };
+        if let Err(e) = post_wr_webhook(&config, &changelog, &map_name, tick_rate).await {
+            eprintln!("Error posting world record to Discord -> {}", e);
+        }
+    });
+}
+
+/// Builds the webhook body for a new world record, times are formatted at the `tick_rate` of the map's game.
+pub fn wr_message(changelog: &ChangelogInsert, map_name: &str, tick_rate: i32) -> serde_json::Value {
+    let mut description = format!(
+        "[{}](https://steamcommunity.com/profiles/{}) set a new world record of **{}**",
+        changelog.profile_number,
+        changelog.profile_number,
+        format_score(changelog.score, tick_rate)
+    );
+    if let Some(delta) = changelog.score_delta {
+        description.push_str(&format!(" (-{})", format_score(delta.abs(), tick_rate)));
+    }
+    if let Some(youtube_id) = &changelog.youtube_id {
+        description.push_str(&format!("\nhttps://youtu.be/{}", youtube_id));
+    }
+    serde_json::json!({
+        "embeds": [{
+            "title": format!("New World Record on {}", map_name),
+            "description": description,
+        }]
+    })
+}
+
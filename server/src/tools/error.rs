@@ -1,41 +1,89 @@
-use actix_web::{http::StatusCode, HttpResponse, ResponseError};
+use actix_web::{
+    http::{header::RETRY_AFTER, StatusCode},
+    HttpResponse, ResponseError,
+};
 use std::{fmt, io};
 
-#[derive(Debug)]
+/// The kind of error, determines the status code and is returned to the client as `error_type`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum ErrorType {
     DbError,
     Reqwest,
     Internal,
     Unknown,
+    NotFound,
+    BadRequest,
+    Unprocessable,
+    BadGateway,
+    Unauthorized,
+    Forbidden,
+    TooManyRequests,
+    PayloadTooLarge,
+    UnsupportedMediaType,
+    Conflict,
+    ServiceUnavailable,
 }
 
 #[derive(Debug)]
 pub struct ServerError {
     pub error_message: String,
     pub error_type: ErrorType,
+    /// Seconds the client should wait before retrying, sent as a `Retry-After` header.
+    pub retry_after: Option<u64>,
 }
 
-#[derive(Serialize)]
+/// The JSON body returned for any [ServerError].
+///
+/// ```json
+/// {
+///     "error_type": "not_found",
+///     "message": "Could not find demo."
+/// }
+/// ```
+#[derive(Serialize, Deserialize, Debug)]
 pub struct ErrResponse {
-    pub error: String,
+    pub error_type: ErrorType,
+    pub message: String,
 }
 
 pub type Result<T, E = ServerError> = std::result::Result<T, E>;
 
+impl ServerError {
+    pub fn new(error_type: ErrorType, error_message: impl Into<String>) -> Self {
+        ServerError {
+            error_message: error_message.into(),
+            error_type,
+            retry_after: None,
+        }
+    }
+    /// Sets the `Retry-After` header sent with the error.
+    pub fn with_retry_after(mut self, secs: u64) -> Self {
+        self.retry_after = Some(secs);
+        self
+    }
+}
+
 impl From<io::Error> for ServerError {
     fn from(error: io::Error) -> Self {
         ServerError {
             error_message: format!("{error}"),
             error_type: ErrorType::Unknown,
+            retry_after: None,
         }
     }
 }
 
+/// A [ServerError] returned through an [anyhow::Error] keeps its type, anything else is an internal error.
 impl From<anyhow::Error> for ServerError {
     fn from(error: anyhow::Error) -> Self {
-        ServerError {
-            error_message: format!("{error}"),
-            error_type: ErrorType::Internal,
+        match error.downcast::<ServerError>() {
+            Ok(error) => error,
+            Err(error) => ServerError {
+                error_message: format!("{error}"),
+                error_type: ErrorType::Internal,
+                retry_after: None,
+            },
         }
     }
 }
@@ -45,19 +93,26 @@ impl From<reqwest::Error> for ServerError {
         ServerError {
             error_message: format!("{error}"),
             error_type: ErrorType::Reqwest,
+            retry_after: None,
         }
     }
 }
 
+/// Timing out while waiting for a database connection is a 503, the pool is busy but the request can be retried.
 impl From<sqlx::Error> for ServerError {
     fn from(error: sqlx::Error) -> Self {
-        ServerError {
-            error_message: format!("{error}"),
-            error_type: ErrorType::DbError,
+        match error {
+            sqlx::Error::PoolTimedOut => {
+                ServerError::new(ErrorType::ServiceUnavailable, "Timed out waiting for a database connection.")
+                    .with_retry_after(1)
+            }
+            error => ServerError::new(ErrorType::DbError, format!("{error}")),
         }
     }
 }
 
+impl std::error::Error for ServerError {}
+
 impl fmt::Display for ServerError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "Error -> {self:?}")
@@ -71,11 +126,27 @@ impl ResponseError for ServerError {
             ErrorType::Internal => StatusCode::INTERNAL_SERVER_ERROR,
             ErrorType::Reqwest => StatusCode::SERVICE_UNAVAILABLE,
             ErrorType::Unknown => StatusCode::UNPROCESSABLE_ENTITY,
+            ErrorType::NotFound => StatusCode::NOT_FOUND,
+            ErrorType::BadRequest => StatusCode::BAD_REQUEST,
+            ErrorType::Unprocessable => StatusCode::UNPROCESSABLE_ENTITY,
+            ErrorType::BadGateway => StatusCode::BAD_GATEWAY,
+            ErrorType::Unauthorized => StatusCode::UNAUTHORIZED,
+            ErrorType::Forbidden => StatusCode::FORBIDDEN,
+            ErrorType::TooManyRequests => StatusCode::TOO_MANY_REQUESTS,
+            ErrorType::PayloadTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            ErrorType::UnsupportedMediaType => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            ErrorType::Conflict => StatusCode::CONFLICT,
+            ErrorType::ServiceUnavailable => StatusCode::SERVICE_UNAVAILABLE,
         }
     }
     fn error_response(&self) -> HttpResponse {
-        HttpResponse::build(self.status_code()).json(ErrResponse {
-            error: self.error_message.clone(),
+        let mut res = HttpResponse::build(self.status_code());
+        if let Some(secs) = self.retry_after {
+            res.insert_header((RETRY_AFTER, secs.to_string()));
+        }
+        res.json(ErrResponse {
+            error_type: self.error_type,
+            message: self.error_message.clone(),
         })
     }
 }
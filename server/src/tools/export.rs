@@ -0,0 +1,131 @@
+//! CSV exports of leaderboard pages, for clients that send `Accept: text/csv` or ask for `?format=csv`.
+//!
+//! The rows are written to the response a chunk at a time, so a large board is never held as one CSV string.
+use crate::{
+    models::{chapters::ExportFormat, coop::CoopRanked, sp::SpRanked},
+    tools::error::{ErrorType, ServerError},
+};
+use actix_web::{
+    http::header::{Accept, Header},
+    web::Bytes,
+    HttpRequest, HttpResponse,
+};
+use futures::stream;
+use serde::Serialize;
+
+/// How many rows are written to each chunk of the response.
+const CHUNK_ROWS: usize = 256;
+
+/// A row of a CSV export, serialized as a CSV record with one column for each of `HEADERS`.
+pub trait CsvRow: Serialize {
+    /// The header line, in the same order as the fields.
+    const HEADERS: &'static [&'static str];
+}
+
+/// A ranked singleplayer entry, see [crate::api::v1::handlers::sp::sp_map].
+#[derive(Debug, Serialize)]
+pub struct SpCsvRow {
+    pub rank: i32,
+    pub profile_number: String,
+    pub user_name: Option<String>,
+    pub score: i32,
+    pub points: f32,
+}
+
+impl CsvRow for SpCsvRow {
+    const HEADERS: &'static [&'static str] = &["rank", "profile_number", "user_name", "score", "points"];
+}
+
+impl From<SpRanked> for SpCsvRow {
+    fn from(entry: SpRanked) -> Self {
+        SpCsvRow {
+            rank: entry.rank,
+            profile_number: entry.map_data.profile_number,
+            user_name: entry.map_data.user_name.map(escape_formula),
+            score: entry.map_data.score,
+            points: entry.points,
+        }
+    }
+}
+
+/// A ranked coop run, with both partners, see [crate::api::v1::handlers::coop::coop_map].
+#[derive(Debug, Serialize)]
+pub struct CoopCsvRow {
+    pub rank: i32,
+    pub profile_number1: String,
+    pub user_name1: String,
+    pub profile_number2: String,
+    pub user_name2: Option<String>,
+    pub score: i32,
+    pub points: f32,
+}
+
+impl CsvRow for CoopCsvRow {
+    const HEADERS: &'static [&'static str] =
+        &["rank", "profile_number1", "user_name1", "profile_number2", "user_name2", "score", "points"];
+}
+
+impl From<CoopRanked> for CoopCsvRow {
+    fn from(entry: CoopRanked) -> Self {
+        CoopCsvRow {
+            rank: entry.rank,
+            profile_number1: entry.map_data.profile_number1,
+            user_name1: escape_formula(entry.map_data.user_name1),
+            profile_number2: entry.map_data.profile_number2,
+            user_name2: entry.map_data.user_name2.map(escape_formula),
+            score: entry.map_data.score,
+            points: entry.points,
+        }
+    }
+}
+
+/// Prefixes a user chosen value with `'` if it starts with a character a spreadsheet would read as the start of a
+/// formula (`=`, `+`, `-`, `@`, tab or carriage return), so opening an export cannot run a player's name as a formula.
+pub fn escape_formula(value: String) -> String {
+    if value.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+        format!("'{value}")
+    } else {
+        value
+    }
+}
+
+/// `true` if the response should be CSV, either because `format` asks for it or the client prefers `text/csv`.
+///
+/// An explicit `format` wins over the `Accept` header, so `?format=json` returns JSON to any client.
+pub fn wants_csv(req: &HttpRequest, format: Option<ExportFormat>) -> bool {
+    match format {
+        Some(format) => format == ExportFormat::Csv,
+        None => Accept::parse(req).is_ok_and(|accept| accept.preference().essence_str() == "text/csv"),
+    }
+}
+
+/// A streaming `text/csv` response with the header line followed by `rows`.
+pub fn csv_response<R, I>(rows: I) -> HttpResponse
+where
+    R: CsvRow,
+    I: IntoIterator<Item = R>,
+    I::IntoIter: 'static,
+{
+    let header = write_records(|writer| writer.write_record(R::HEADERS));
+    let mut rows = rows.into_iter().peekable();
+    let records = std::iter::from_fn(move || {
+        rows.peek()?;
+        let mut chunk = rows.by_ref().take(CHUNK_ROWS);
+        Some(write_records(|writer| chunk.try_for_each(|row| writer.serialize(row))))
+    });
+    HttpResponse::Ok()
+        .content_type("text/csv; charset=utf-8")
+        .streaming(stream::iter(std::iter::once(header).chain(records)))
+}
+
+fn write_records(
+    write: impl FnOnce(&mut csv::Writer<Vec<u8>>) -> csv::Result<()>,
+) -> Result<Bytes, ServerError> {
+    let mut writer = csv::WriterBuilder::new().has_headers(false).from_writer(Vec::new());
+    write(&mut writer)
+        .map_err(|e| ServerError::new(ErrorType::Internal, format!("Could not write CSV: {e}")))?;
+    let bytes = writer
+        .into_inner()
+        .map_err(|e| ServerError::new(ErrorType::Internal, format!("Could not write CSV: {e}")))?;
+    Ok(Bytes::from(bytes))
+}
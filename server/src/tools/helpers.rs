@@ -1,90 +1,492 @@
 use anyhow::{bail, Result};
-use num::pow;
+use chrono::{NaiveDateTime, TimeDelta, Utc};
 use sqlx::PgPool;
 use std::collections::{HashMap, HashSet};
+use std::sync::OnceLock;
 
-use crate::models::changelog::{CalcValues, Changelog, ChangelogInsert, SubmissionChangelog};
-use crate::models::coop::{CoopMap, CoopRanked};
+use crate::models::categories::Categories;
+use crate::models::changelog::{CalcValues, Changelog, ChangelogInsert, PersonalBest, SubmissionChangelog};
+use crate::models::coop::{CoopMap, CoopPreview, CoopRanked};
 use crate::models::maps::Maps;
-use crate::models::sp::SpMap;
+use crate::models::points::{ChapterPoints, PlayerMapRank, PointsAggregate};
+use crate::models::sp::{SpHistoryPoint, SpMap, SpRanked};
 use crate::models::users::Users;
 
-use super::cache::CacheState;
-use super::config::Config;
+use super::cache::{CacheState, DefaultCategories};
+use super::config::{Config, PointsConfig, ProofConfig};
+use super::error::{ErrorType, ServerError};
+use super::moderation::moderate_new_user;
 
 pub type Transaction<'a> = sqlx::Transaction<'a, sqlx::Postgres>;
 
-/// Calcultes the score using the pre-existing iVerb point formula.
+/// Board scores are stored in centiseconds, the precision the Steam leaderboards report times in. Games whose scores are
+/// counted in ticks set their own `tick_rate`, see [crate::models::chapters::Games].
+pub const DEFAULT_TICK_RATE: i32 = 100;
+/// Length of a YouTube video id.
+const YOUTUBE_ID_LEN: usize = 11;
+/// Format of the `timestamp` on submissions.
+pub const SUBMISSION_TIMESTAMP_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+/// The `admin_note` set on submissions that are banned for being below their category's `min_score`.
+pub const MIN_SCORE_FLAG_NOTE: &str = "auto-flagged: below min_score";
+/// How far a submission's timestamp can be ahead of the server's clock (UTC), to allow for players in timezones ahead of UTC.
+const MAX_TIMESTAMP_LEAD_HOURS: i64 = 24;
+
+/// Formats a board score counted at `tick_rate` per second as `m:ss.cc`, or `s.cc` for scores under a minute. Minutes are
+/// not rolled over into hours, and partial seconds are rounded down to the centisecond.
+pub fn format_score(score: i32, tick_rate: i32) -> String {
+    let sign = if score < 0 { "-" } else { "" };
+    let score = score.unsigned_abs() as u64;
+    let per_second = tick_rate.max(1) as u64;
+    let (minutes, seconds) = (score / (per_second * 60), (score / per_second) % 60);
+    let centis = score % per_second * 100 / per_second;
+    if minutes > 0 {
+        format!("{}{}:{:02}.{:02}", sign, minutes, seconds, centis)
+    } else {
+        format!("{}{}.{:02}", sign, seconds, centis)
+    }
+}
+
+/// Parses a time formatted by [format_score] (`m:ss.cc` or `s.cc`) back into a board score counted at `tick_rate`.
+///
+/// Times are parsed to the first tick in the centisecond, so formatting and parsing a score round trips at tick rates up to 100.
+#[allow(dead_code)]
+pub fn parse_score(time: &str, tick_rate: i32) -> Result<i32> {
+    let invalid = || anyhow::anyhow!("Invalid time {:?}, expected m:ss.cc or s.cc", time);
+    let is_digits = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+    let (minutes, rest) = match time.split_once(':') {
+        Some((minutes, rest)) => (Some(minutes), rest),
+        None => (None, time),
+    };
+    let (seconds, centis) = rest.split_once('.').ok_or_else(invalid)?;
+    // Seconds are zero padded after minutes, and can't overflow into the next minute.
+    let seconds_len = if minutes.is_some() { 2..=2 } else { 1..=2 };
+    if !is_digits(seconds) || !seconds_len.contains(&seconds.len()) || !is_digits(centis) || centis.len() != 2 {
+        return Err(invalid());
+    }
+    let seconds: i32 = seconds.parse()?;
+    let minutes: i32 = match minutes {
+        Some(minutes) if is_digits(minutes) && seconds < 60 => minutes.parse().map_err(|_| invalid())?,
+        Some(_) => return Err(invalid()),
+        None => 0,
+    };
+    let tick_rate = tick_rate.max(1);
+    let ticks = (centis.parse::<i32>()? * tick_rate + 99) / 100;
+    minutes
+        .checked_mul(60 * tick_rate)
+        .and_then(|m| m.checked_add(seconds.checked_mul(tick_rate)?.checked_add(ticks)?))
+        .ok_or_else(invalid)
+}
+
+/// Parses a submission's `timestamp` in the [SUBMISSION_TIMESTAMP_FORMAT] format.
+///
+/// Returns an [ErrorType::Unprocessable] error if the timestamp is not a valid date and time (`2020-08-18 24:60:60`), or if
+/// it is more than [MAX_TIMESTAMP_LEAD_HOURS] ahead of `now`.
+pub fn parse_submission_timestamp(timestamp: &str, now: NaiveDateTime) -> Result<NaiveDateTime, ServerError> {
+    let parsed = NaiveDateTime::parse_from_str(timestamp.trim(), SUBMISSION_TIMESTAMP_FORMAT).map_err(|e| {
+        ServerError::new(
+            ErrorType::Unprocessable,
+            format!("Invalid timestamp {timestamp:?}, expected a valid date and time as `YYYY-MM-DD HH:MM:SS` ({e})."),
+        )
+    })?;
+    if parsed > now + TimeDelta::hours(MAX_TIMESTAMP_LEAD_HOURS) {
+        return Err(ServerError::new(
+            ErrorType::Unprocessable,
+            format!("Invalid timestamp {timestamp:?}, the timestamp is in the future."),
+        ));
+    }
+    Ok(parsed)
+}
+
+/// Normalizes a YouTube video to the form stored on changelog entries, the video id followed by `?start={seconds}` if
+/// the video starts at an offset.
+///
+/// Accepts bare ids (including ids already in the stored form), `youtu.be` links and `youtube.com` `/watch`, `/embed`,
+/// `/shorts` and `/live` links. The offset is taken from `t` (`90`, `90s` or `1m30s`) or `start`.
+///
+/// Returns `None` if no valid video id or offset can be found.
+pub fn normalize_youtube_id(input: &str) -> Option<String> {
+    let input = input.trim();
+    let (id, start) = if input.contains("youtube.com") || input.contains("youtu.be") {
+        let url = if input.contains("://") {
+            reqwest::Url::parse(input).ok()?
+        } else {
+            reqwest::Url::parse(&format!("https://{input}")).ok()?
+        };
+        let host = url.host_str()?.trim_start_matches("www.").trim_start_matches("m.");
+        let mut segments = url.path_segments()?.filter(|s| !s.is_empty());
+        let id = match (host, segments.next()) {
+            ("youtu.be", Some(id)) => id.to_string(),
+            ("youtube.com", Some("watch")) => url.query_pairs().find(|(k, _)| k == "v")?.1.into_owned(),
+            ("youtube.com", Some("embed" | "shorts" | "live")) => segments.next()?.to_string(),
+            _ => return None,
+        };
+        let start = url
+            .query_pairs()
+            .find(|(k, _)| k == "t" || k == "start")
+            .map(|(_, v)| v.into_owned());
+        (id, start)
+    } else {
+        match input.split_once('?') {
+            Some((id, query)) => (id.to_string(), Some(query.strip_prefix("start=")?.to_string())),
+            None => (input.to_string(), None),
+        }
+    };
+    let valid_id = id.len() == YOUTUBE_ID_LEN
+        && id.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_');
+    if !valid_id {
+        return None;
+    }
+    match start.map(|start| parse_youtube_offset(&start)) {
+        Some(None) => None,
+        Some(Some(start)) if start > 0 => Some(format!("{id}?start={start}")),
+        _ => Some(id),
+    }
+}
+
+/// Cleans a free text note before it is stored, control characters (other than new lines) are removed and the note is
+/// trimmed. An empty note is stored as `None`.
+///
+/// Returns an [ErrorType::Unprocessable] error if the cleaned note is longer than `max_len` characters.
+pub fn clean_note(note: Option<String>, max_len: usize) -> Result<Option<String>, ServerError> {
+    let Some(note) = note else {
+        return Ok(None);
+    };
+    let note: String = note.chars().filter(|c| *c == '\n' || !c.is_control()).collect();
+    let note = note.trim();
+    let len = note.chars().count();
+    if len > max_len {
+        return Err(ServerError::new(
+            ErrorType::Unprocessable,
+            format!("Note is {len} characters long, the limit is {max_len}."),
+        ));
+    }
+    Ok((!note.is_empty()).then(|| note.to_string()))
+}
+
+/// Escapes the `LIKE` wildcards `%` and `_` (and the escape character `\`) in user input, so they only match themselves.
+///
+/// Queries using the result need `ESCAPE '\'`.
+pub fn escape_like(input: &str) -> String {
+    let mut escaped = String::with_capacity(input.len());
+    for c in input.chars() {
+        if matches!(c, '%' | '_' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Parses a YouTube start offset in seconds, either a plain number of seconds or a duration like `1h2m3s`.
+fn parse_youtube_offset(offset: &str) -> Option<u32> {
+    if !offset.is_empty() && offset.bytes().all(|b| b.is_ascii_digit()) {
+        return offset.parse().ok();
+    }
+    let mut total: u32 = 0;
+    let mut digits = String::new();
+    for c in offset.chars() {
+        let unit = match c {
+            '0'..='9' => {
+                digits.push(c);
+                continue;
+            }
+            'h' => 3600,
+            'm' => 60,
+            's' => 1,
+            _ => return None,
+        };
+        total = total.checked_add(digits.parse::<u32>().ok()?.checked_mul(unit)?)?;
+        digits.clear();
+    }
+    // Trailing digits without a unit are not a valid offset.
+    (digits.is_empty() && !offset.is_empty()).then_some(total)
+}
+
+/// The points curve from the config, set once at startup with [set_points_curve].
+static POINTS_CURVE: OnceLock<PointsConfig> = OnceLock::new();
+
+/// Sets the curve used by [score], later calls are ignored.
+pub fn set_points_curve(curve: PointsConfig) {
+    let _ = POINTS_CURVE.set(curve);
+}
+
+/// The curve used by [score], the default curve is used if none was set.
+pub fn points_curve() -> PointsConfig {
+    POINTS_CURVE.get().copied().unwrap_or_default()
+}
+
+/// Calcultes the score for a rank using the configured points curve, see [points_for_rank].
 #[inline(always)]
 pub fn score(i: i32) -> f32 {
-    if i > 200 {
+    points_for_rank(&points_curve(), i)
+}
+
+/// Calculates the points for a rank on the given curve, by default this is the pre-existing iVerb point formula.
+pub fn points_for_rank(curve: &PointsConfig, i: i32) -> f32 {
+    if i > curve.max_rank || curve.max_rank < 1 {
         0.0
     } else {
-        let i = i as f32;
-        let res: f32 = pow(200.0 - (i - 1.0), 2) / 200.0;
-        if 1.0 > res {
-            1.0
+        let max_rank = curve.max_rank as f32;
+        let res: f32 = (max_rank - (i as f32 - 1.0)).powf(curve.decay) / (max_rank.powf(curve.decay) / curve.max_points);
+        if curve.min_points > res {
+            curve.min_points
         } else {
             res
         }
     }
 }
 
-/// Grabs the default category IDs for all maps as a HashMap.
-pub async fn get_default_cat_ids(pool: &PgPool) -> HashMap<String, i32> {
-    Maps::get_all_default_cats(pool).await.unwrap()
+/// Grabs the default category IDs for all maps, keyed by map and game.
+pub async fn get_default_cat_ids(pool: &PgPool) -> DefaultCategories {
+    Maps::get_default_cats_by_game(pool).await.unwrap().into()
+}
+
+/// Ranks the entries of a singleplayer map page, the entries are expected to already be sorted by score.
+///
+/// Tied scores share the same rank (and points), and the next distinct score skips accordingly (1, 2, 2, 4).
+///
+/// For pages past the first, `offset` is the number of entries skipped so ranks stay absolute, and `first_rank` is the rank
+/// of the first entry, which can be lower than `offset + 1` when it ties with entries on the previous page.
+pub fn rank_sp_entries(sp_entries: Vec<SpMap>, offset: i32, first_rank: i32) -> Vec<SpRanked> {
+    let mut ranked_vec: Vec<SpRanked> = Vec::with_capacity(sp_entries.len());
+    for (i, entry) in sp_entries.into_iter().enumerate() {
+        let rank = match ranked_vec.last() {
+            Some(prev) if prev.map_data.score == entry.score => prev.rank,
+            Some(_) => offset + i as i32 + 1,
+            None => first_rank,
+        };
+        ranked_vec.push(SpRanked { map_data: entry, rank, points: score(rank), formatted_score: None })
+    }
+    ranked_vec
+}
+
+/// Builds an oldest-first timeline from a player's PB history, with the points for each `post_rank`.
+///
+/// Entries without a `post_rank` carry forward the rank of the previous entry, entries before any known rank have no points.
+pub fn build_pb_timeline(mut history: Vec<Changelog>) -> Vec<SpHistoryPoint> {
+    history.sort_by_key(|cl| cl.timestamp);
+    let mut rank = None;
+    history
+        .into_iter()
+        .map(|cl| {
+            rank = cl.post_rank.or(rank);
+            SpHistoryPoint {
+                timestamp: cl.timestamp,
+                score: cl.score,
+                rank,
+                points: rank.map(score).unwrap_or(0.0),
+            }
+        })
+        .collect()
+}
+
+/// Removes preview entries where both players already have a better time in the preview, then truncates to `limit` entries.
+///
+/// A missing partner (`profile_number2` of `None`) is treated as not yet seen, so the entry is kept, player 1 is still marked as seen.
+pub fn dedup_coop_previews(entries: Vec<CoopPreview>, limit: usize) -> Vec<CoopPreview> {
+    let mut remove_dups: HashSet<String> = HashSet::with_capacity(limit * 2);
+    remove_dups.insert("N/A".to_string());
+    let mut deduped = Vec::new();
+    for entry in entries {
+        let new_player1 = remove_dups.insert(entry.profile_number1.clone());
+        let new_player2 = match &entry.profile_number2 {
+            Some(profile_number2) => remove_dups.insert(profile_number2.clone()),
+            None => true,
+        };
+        if new_player1 || new_player2 {
+            deduped.push(entry);
+        }
+    }
+    deduped.truncate(limit);
+    deduped
+}
+
+/// The number of leaderboard entries to return for a requested `limit`, defaults to `PROOF.RESULTS` and is clamped to
+/// between 1 and `PROOF.MAX_RESULTS`.
+pub fn results_limit(limit: Option<i32>, proof: &ProofConfig) -> i32 {
+    limit.unwrap_or(proof.results).clamp(1, proof.max_results.max(1))
 }
 
 /// Filters out all obsolete times from the result, then truncates to x entries.
+///
+/// A time is kept if it is the best time for either player. Ranks are given to the kept times, tied scores share the same
+/// rank (and points), and the next distinct score skips accordingly (1, 2, 2, 4), the same as [rank_sp_entries].
 pub async fn filter_coop_entries(coop_entries: Vec<CoopMap>, limit: usize) -> Vec<CoopRanked> {
-    let mut coop_entries_filtered = Vec::new();
+    let mut coop_entries_filtered: Vec<CoopRanked> = Vec::new();
     let mut remove_dups: HashSet<String> = HashSet::with_capacity(limit);
     remove_dups.insert("N/A".to_string());
-    let mut i = 1;
     for entry in coop_entries.into_iter() {
-        match remove_dups.insert(entry.profile_number1.clone()) {
-            // If player 1 has a better time, check to see if player 2 doesn't.
-            false => match remove_dups.insert(entry.profile_number2.clone()) {
-                false => (),
-                true => {
-                    coop_entries_filtered.push(CoopRanked {
-                        map_data: entry.clone(),
-                        rank: i,
-                        points: score(i),
-                    });
-                    i += 1;
-                }
-            },
-            // This case handles if player 1 doesn't have a better time, and it tries to add player 2 in as well, if two has a better time or not, this is included.
-            true => match remove_dups.insert(entry.profile_number2.clone()) {
-                false => {
-                    coop_entries_filtered.push(CoopRanked {
-                        map_data: entry.clone(),
-                        rank: i,
-                        points: score(i),
-                    });
-                    i += 1;
-                }
-                true => {
-                    coop_entries_filtered.push(CoopRanked {
-                        map_data: entry.clone(),
-                        rank: i,
-                        points: score(i),
-                    });
-                    i += 1;
-                }
-            },
+        if coop_entries_filtered.len() >= limit {
+            break;
+        }
+        // Both players are recorded, even if player 1 already has a better time.
+        let new_player1 = remove_dups.insert(entry.profile_number1.clone());
+        let new_player2 = remove_dups.insert(entry.profile_number2.clone());
+        if !new_player1 && !new_player2 {
+            continue;
         }
+        let rank = match coop_entries_filtered.last() {
+            Some(prev) if prev.map_data.score == entry.score => prev.rank,
+            _ => coop_entries_filtered.len() as i32 + 1,
+        };
+        coop_entries_filtered.push(CoopRanked {
+            map_data: entry,
+            rank,
+            points: score(rank),
+            formatted_score: None,
+        });
     }
-    coop_entries_filtered.truncate(limit);
     coop_entries_filtered
 }
 
+/// Running point totals for the aggregate leaderboards, profile_number -> (user_name, total_points).
+pub type PointTotals = HashMap<String, (Option<String>, f32)>;
+
+/// Sums the points each player has across every singleplayer map of the base game, using their best rank on the map's default category.
+/// Maps without a default category are left out.
+///
+/// Banned players and times are excluded by [SpMap::get_sp_map_page].
+pub async fn calc_sp_aggregate_points(
+    pool: &PgPool,
+    config: &Config,
+    default_cat_ids: &HashMap<String, i32>,
+) -> Result<Vec<PointsAggregate>> {
+    let mut totals = PointTotals::new();
+    for map_id in Maps::get_steam_ids(pool, Some(false), Some(1)).await? {
+        let Some(&cat_id) = default_cat_ids.get(&map_id) else {
+            eprintln!("No default category for map {}, leaving it out of the aggregate points", map_id);
+            continue;
+        };
+        let entries = SpMap::get_sp_map_page(pool, &map_id, config.proof.results, cat_id, 1, 0).await?;
+        for entry in rank_sp_entries(entries, 0, 1) {
+            let total = totals
+                .entry(entry.map_data.profile_number)
+                .or_insert((entry.map_data.user_name, 0.0));
+            total.1 += entry.points;
+        }
+    }
+    Ok(rank_point_totals(totals))
+}
+
+/// Sums the points each player has across every cooperative map of the base game, a player only counts their best rank on each map regardless of partner.
+/// Maps without a default category are left out.
+///
+/// Banned players and times are excluded by [CoopMap::get_coop_map_page].
+pub async fn calc_coop_aggregate_points(
+    pool: &PgPool,
+    config: &Config,
+    default_cat_ids: &HashMap<String, i32>,
+) -> Result<Vec<PointsAggregate>> {
+    let mut totals = PointTotals::new();
+    for map_id in Maps::get_steam_ids(pool, Some(true), Some(1)).await? {
+        let Some(&cat_id) = default_cat_ids.get(&map_id) else {
+            eprintln!("No default category for map {}, leaving it out of the aggregate points", map_id);
+            continue;
+        };
+        let entries = CoopMap::get_coop_map_page(pool, &map_id, cat_id, 1).await?;
+        let mut counted: HashSet<String> = HashSet::new();
+        for entry in filter_coop_entries(entries, config.proof.results as usize).await {
+            let players = [
+                (entry.map_data.profile_number1, Some(entry.map_data.user_name1)),
+                (entry.map_data.profile_number2, entry.map_data.user_name2),
+            ];
+            for (profile_number, user_name) in players {
+                if profile_number == "N/A" || !counted.insert(profile_number.clone()) {
+                    continue;
+                }
+                let total = totals.entry(profile_number).or_insert((user_name, 0.0));
+                total.1 += entry.points;
+            }
+        }
+    }
+    Ok(rank_point_totals(totals))
+}
+
+/// Sums a player's points on each chapter of `game_id`, using their rank on each map's default category.
+///
+/// Singleplayer ranks come from [PlayerMapRank::get_for_player], coop ranks are found the same way as
+/// [calc_coop_aggregate_points], on maps the player has a coop time on. Banned and unverified times are excluded.
+pub async fn get_chapter_points(pool: &PgPool, profile_number: &str, game_id: i32) -> Result<Vec<ChapterPoints>> {
+    let mut chapters: Vec<ChapterPoints> = Vec::new();
+    for map in PlayerMapRank::get_for_player(pool, profile_number, game_id).await? {
+        let rank = if map.is_multiplayer && map.has_coop {
+            get_coop_rank(pool, &map.map_id, map.default_cat_id, game_id, profile_number).await?
+        } else {
+            map.sp_rank.map(|rank| rank as i32)
+        };
+        if chapters.last().map(|c| c.chapter_id) != Some(map.chapter_id) {
+            chapters.push(ChapterPoints {
+                chapter_id: map.chapter_id,
+                chapter_name: map.chapter_name,
+                points: 0.0,
+                max_possible: 0.0,
+            });
+        }
+        let chapter = chapters.last_mut().unwrap();
+        chapter.points += rank.map(score).unwrap_or(0.0);
+        chapter.max_possible += score(1);
+    }
+    Ok(chapters)
+}
+
+/// A player's rank on a coop map, found the same way as [calc_coop_aggregate_points]. `None` if the player is not on the map.
+pub async fn get_coop_rank(pool: &PgPool, map_id: &str, cat_id: i32, game_id: i32, profile_number: &str) -> Result<Option<i32>> {
+    let entries = CoopMap::get_coop_map_page(pool, map_id, cat_id, game_id).await?;
+    let limit = entries.len();
+    Ok(filter_coop_entries(entries, limit)
+        .await
+        .into_iter()
+        .find(|e| e.map_data.profile_number1 == profile_number || e.map_data.profile_number2 == profile_number)
+        .map(|e| e.rank))
+}
+
+/// A player's personal best on every map of `game_id`, from [Changelog::get_all_pbs] with the coop ranks filled in
+/// by [get_coop_rank], and the points for each rank.
+pub async fn get_player_pbs(pool: &PgPool, profile_number: &str, game_id: i32) -> Result<Vec<PersonalBest>> {
+    let mut pbs = Changelog::get_all_pbs(pool, profile_number, game_id).await?;
+    for pb in pbs.iter_mut() {
+        if pb.is_coop {
+            pb.rank = get_coop_rank(pool, &pb.map_id, pb.category_id, game_id, profile_number)
+                .await?
+                .map(i64::from);
+        }
+        pb.points = pb.rank.map(|rank| score(rank as i32)).unwrap_or(0.0);
+    }
+    Ok(pbs)
+}
+
+/// Sorts players by their total points and ranks them, tied totals share a rank (1, 2, 2, 4).
+pub fn rank_point_totals(totals: PointTotals) -> Vec<PointsAggregate> {
+    let mut sorted: Vec<(String, (Option<String>, f32))> = totals.into_iter().collect();
+    sorted.sort_by(|a, b| b.1 .1.total_cmp(&a.1 .1).then_with(|| a.0.cmp(&b.0)));
+    let mut ranked: Vec<PointsAggregate> = Vec::with_capacity(sorted.len());
+    for (i, (profile_number, (user_name, total_points))) in sorted.into_iter().enumerate() {
+        let rank = match ranked.last() {
+            Some(prev) if prev.total_points == total_points => prev.rank,
+            _ => i as i32 + 1,
+        };
+        ranked.push(PointsAggregate {
+            profile_number,
+            user_name,
+            total_points,
+            rank,
+        });
+    }
+    ranked
+}
+
 /// Checks if a score is valid, if it is, returns post_rank, pre_rank, score_delta, previous_id
+///
+/// A score that does not beat the player's current PB returns an [ErrorType::Unprocessable] error naming the PB when
+/// `require_improvement` is set. Otherwise it is valid, but not a PB, `score_delta` is zero or negative and the ranks are left empty.
+/// A PB outside of the top `limit` is given the rank after the last ranked entry.
+///
+/// Returns an [ErrorType::NotFound] error if the user does not exist, other errors are passed on.
 pub async fn check_for_valid_score(
     pool: &PgPool,
     cl: &SubmissionChangelog,
     limit: i32,
+    require_improvement: bool,
 ) -> Result<CalcValues> {
     let mut values = CalcValues::default();
     match Users::check_banned(pool, &cl.profile_number).await {
@@ -94,12 +496,16 @@ pub async fn check_for_valid_score(
                 return Ok(values);
             }
         }
-        Err(e) => {
-            // Assuming someone is manually/automatically submitting a demo, a user account should be created for them on the boards.
-            // TODO: Maybe this changes when AUTH changes?
-            eprintln!("User assumed not found -> {:?}", e);
-            bail!("User does not exist");
+        // Assuming someone is manually/automatically submitting a demo, a user account should be created for them on the boards.
+        // TODO: Maybe this changes when AUTH changes?
+        Err(sqlx::Error::RowNotFound) => {
+            return Err(ServerError::new(
+                ErrorType::NotFound,
+                format!("No user with profile_number {}", cl.profile_number),
+            )
+            .into());
         }
+        Err(e) => return Err(e.into()),
     }
     let cl_res = Changelog::get_sp_pb_history(
         pool,
@@ -110,43 +516,51 @@ pub async fn check_for_valid_score(
     )
     .await;
     let cl_res = match cl_res {
-        Ok(x) => {
-            if x.is_empty() {
-                return Ok(values);
-            } else {
-                x
-            }
-        }
+        Ok(x) => x,
         Err(e) => {
             eprintln!("Error with sp pb history -> {:?}", e);
             eprintln!("Assume there is not sp_pb_histroy for the player.");
-            return Ok(values);
+            Vec::new()
         }
     };
 
-    if cl_res[0].score <= cl.score {
-        bail!("Current score is the same, or better.")
+    // The newest entry is not always the PB when non-improving submissions are allowed.
+    // A first score on the map is always a PB, and is ranked below.
+    if let Some(pb) = cl_res.iter().filter(|entry| !entry.banned).min_by_key(|entry| entry.score) {
+        values.score_delta = Some(pb.score - cl.score);
+        values.previous_id = Some(pb.id);
+        if pb.score <= cl.score {
+            if require_improvement {
+                return Err(ServerError::new(
+                    ErrorType::Unprocessable,
+                    format!("Score {} does not beat the current personal best of {} (changelog {}).", cl.score, pb.score, pb.id),
+                )
+                .into());
+            }
+            return Ok(values);
+        }
     }
-    values.score_delta = Some(cl_res[0].score - cl.score);
-    values.previous_id = Some(cl_res[0].id);
-    // Assuming there is a PB History, there must be other scores, this should return a valid list of ranked maps.
     let cl_ranked = SpMap::get_sp_map_page(
         pool,
         &cl.map_id,
         limit,
         cl.category_id.unwrap(),
         cl.game_id.unwrap_or(1),
+        0,
     )
-    .await
-    .unwrap();
-    for (i, entry) in cl_ranked.iter().enumerate() {
-        if entry.score >= cl.score {
-            values.post_rank = Some(i as i32 + 1);
-        }
-        if entry.profile_number == cl.profile_number {
-            values.pre_rank = Some(i as i32 + 1);
-        }
-    }
+    .await?;
+    // The new score takes the rank of the first entry it does not beat, sharing it on a tie, or last place if it beats none.
+    values.post_rank = Some(
+        cl_ranked
+            .iter()
+            .position(|entry| entry.score >= cl.score)
+            .unwrap_or(cl_ranked.len()) as i32
+            + 1,
+    );
+    values.pre_rank = cl_ranked
+        .iter()
+        .position(|entry| entry.profile_number == cl.profile_number)
+        .map(|i| i as i32 + 1);
     Ok(values)
 }
 
@@ -156,20 +570,53 @@ pub async fn check_for_valid_score(
 ///
 /// Score is invalid if any of the following are true
 /// 1. The user is banned.
-/// 2. The user has a time on the same map, with the same score (time).
+/// 2. The user has a time on the same map, with the same score (time), or a better one when
+///    `SUBMISSIONS.REQUIRE_IMPROVEMENT` is set (an [ErrorType::Unprocessable] error with the existing PB).
 /// 3. The user does not exist (and cannot be added from Steam).
 ///
 /// This function handles the error case where the user is valid on steam, but does not currently exist in our database.
+///
+/// The `youtube_id` is normalized with [normalize_youtube_id], an invalid video returns an [ErrorType::Unprocessable] error.
+/// The `note` is cleaned with [clean_note], a note over `SUBMISSIONS.MAX_NOTE_LENGTH` also returns an
+/// [ErrorType::Unprocessable] error.
+/// The `timestamp` is checked with [parse_submission_timestamp], an invalid or future timestamp also returns an
+/// [ErrorType::Unprocessable] error.
+///
+/// A score below the category's `min_score` is still returned, but banned for review, see [flag_below_min_score].
 pub async fn get_valid_changelog_insert(
     pool: &PgPool,
     config: &Config,
     cache: &CacheState,
     mut cl: SubmissionChangelog,
 ) -> Result<ChangelogInsert> {
+    cl.timestamp = parse_submission_timestamp(&cl.timestamp, Utc::now().naive_utc())?
+        .format(SUBMISSION_TIMESTAMP_FORMAT)
+        .to_string();
+    cl.youtube_id = match cl.youtube_id.as_deref().map(str::trim) {
+        None | Some("") => None,
+        Some(youtube_id) => match normalize_youtube_id(youtube_id) {
+            Some(youtube_id) => Some(youtube_id),
+            None => {
+                return Err(ServerError::new(
+                    ErrorType::Unprocessable,
+                    format!("Invalid YouTube video {youtube_id:?}, expected a YouTube link or video id."),
+                )
+                .into())
+            }
+        },
+    };
+    cl.note = clean_note(cl.note, config.submissions.max_note_length)?;
     if cl.category_id.is_none() {
-        cl.category_id = Some(cache.default_cat_ids[&cl.map_id]);
+        match cache.default_cat_ids.get(&cl.map_id, cl.game_id.unwrap_or(1)) {
+            Some(cat_id) => cl.category_id = Some(cat_id),
+            None => {
+                return Err(
+                    ServerError::new(ErrorType::NotFound, format!("No map with id {}", cl.map_id)).into(),
+                )
+            }
+        }
     } // Steps 1 & 2
-    let values = match check_for_valid_score(pool, &cl, config.proof.results).await {
+    let values = match check_for_valid_score(pool, &cl, config.proof.results, config.submissions.require_improvement).await {
         Ok(details) => {
             if details.banned {
                 bail!("User is banned");
@@ -177,15 +624,19 @@ pub async fn get_valid_changelog_insert(
                 details
             }
         }
+        Err(e) if !matches!(e.downcast_ref::<ServerError>(), Some(e) if e.error_type == ErrorType::NotFound) => return Err(e),
         Err(e) => {
             // Step 3
             eprintln!("Error checking valid score details -> {e}");
             // Try to insert the user into the users table.
             match Users::new_from_steam(&config.steam.api_key, &cl.profile_number).await {
-                Ok(user) => match Users::insert_new_users(pool, user).await {
-                    Ok(_) => CalcValues::default(),
-                    _ => bail!("Could not add new user to database."),
-                },
+                Ok(mut user) => {
+                    moderate_new_user(&config.moderation, &mut user);
+                    match Users::insert_new_users(pool, user).await {
+                        Ok(_) => CalcValues::default(),
+                        _ => bail!("Could not add new user to database."),
+                    }
+                }
                 Err(e) => {
                     eprintln!("Could not get user from steam -> {e}");
                     bail!("Invalid user steam_id provided.");
@@ -194,5 +645,22 @@ pub async fn get_valid_changelog_insert(
         }
     };
     // Step 4
-    Ok(ChangelogInsert::new_from_submission(cl, values, &cache.default_cat_ids).await)
+    let mut insert = ChangelogInsert::new_from_submission(cl, values, &cache.default_cat_ids).await;
+    flag_below_min_score(pool, &mut insert).await?;
+    Ok(insert)
+}
+
+/// Bans a submission that is faster than its category's `min_score` allows, so it is kept for an admin to review
+/// instead of showing up on the boards.
+///
+/// The entry is left unreviewed (`verified` is `NULL`) with [MIN_SCORE_FLAG_NOTE] as its `admin_note`, which puts it in
+/// the verification queue, see [Changelog::get_unverified]. A score equal to the minimum is not flagged.
+pub async fn flag_below_min_score(pool: &PgPool, cl: &mut ChangelogInsert) -> Result<(), sqlx::Error> {
+    let min_score = Categories::get_min_score(pool, cl.category_id).await?;
+    if min_score.is_some_and(|min_score| cl.score < min_score) {
+        cl.banned = true;
+        cl.verified = None;
+        cl.admin_note = Some(MIN_SCORE_FLAG_NOTE.to_string());
+    }
+    Ok(())
 }
@@ -0,0 +1,47 @@
+//! Live leaderboard updates pushed to WebSocket clients, see [crate::api::v1::handlers::live::live].
+//!
+//! Submission handlers publish a [LiveUpdate] after they invalidate the previews for a map, and every connected client is
+//! sent a copy so it can refetch only the affected map instead of polling the previews.
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+
+/// How many updates are kept for a client that is slow to read them, clients that fall further behind are sent a
+/// [LiveUpdate::Resync] instead.
+const CHANNEL_CAPACITY: usize = 256;
+
+/// A message sent to live clients, serialized as `{ "type": "sp_update", "map_id": "47763" }`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LiveUpdate {
+    /// A singleplayer map's leaderboard changed.
+    SpUpdate { map_id: String },
+    /// A coop map's leaderboard changed.
+    CoopUpdate { map_id: String },
+    /// The client missed updates, so it should refetch everything it is showing.
+    Resync,
+}
+
+/// The broadcast channel live updates are published to, cloned handles share the same channel.
+#[derive(Debug, Clone)]
+pub struct LiveUpdates {
+    sender: broadcast::Sender<LiveUpdate>,
+}
+
+impl Default for LiveUpdates {
+    fn default() -> Self {
+        LiveUpdates {
+            sender: broadcast::channel(CHANNEL_CAPACITY).0,
+        }
+    }
+}
+
+impl LiveUpdates {
+    /// Sends `update` to every connected client, it is dropped if nobody is connected.
+    pub fn publish(&self, update: LiveUpdate) {
+        let _ = self.sender.send(update);
+    }
+    /// Receives every update published from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<LiveUpdate> {
+        self.sender.subscribe()
+    }
+}
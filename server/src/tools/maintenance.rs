@@ -0,0 +1,63 @@
+//! Read-only maintenance mode, e.g. while the database is being migrated.
+//!
+//! While it is enabled [block_changes] rejects every request that could change something with a 503, reads keep working.
+//! It starts as `MAINTENANCE.ENABLED`, see [crate::tools::config::MaintenanceConfig], and admins can toggle it at
+//! runtime with [crate::api::v1::handlers::admin::admin_maintenance] without restarting the server.
+use crate::tools::{
+    config::MaintenanceConfig,
+    error::{ErrorType, ServerError},
+};
+use actix_web::{
+    body::{EitherBody, MessageBody},
+    dev::{ServiceRequest, ServiceResponse},
+    http::Method,
+    middleware::Next,
+    web, Error, ResponseError,
+};
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+/// The endpoint that toggles maintenance mode, it is let through so admins can turn it off again.
+const TOGGLE_ROUTE: &str = "/admin/maintenance";
+
+/// Whether maintenance mode is enabled, cloned handles share the same flag.
+#[derive(Debug, Clone, Default)]
+pub struct Maintenance {
+    enabled: Arc<AtomicBool>,
+}
+
+impl Maintenance {
+    pub fn new(config: &MaintenanceConfig) -> Self {
+        Maintenance {
+            enabled: Arc::new(AtomicBool::new(config.enabled)),
+        }
+    }
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+    pub fn set(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+}
+
+/// Middleware that rejects anything but `GET`, `HEAD` and `OPTIONS` requests with a 503 while maintenance mode is
+/// enabled. Does nothing if there is no [Maintenance] in the app data.
+pub async fn block_changes(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let enabled = req
+        .app_data::<web::Data<Maintenance>>()
+        .is_some_and(|maintenance| maintenance.is_enabled());
+    let read_only = matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS);
+    if !enabled || read_only || req.path().ends_with(TOGGLE_ROUTE) {
+        return Ok(next.call(req).await?.map_into_left_body());
+    }
+    let err = ServerError::new(
+        ErrorType::ServiceUnavailable,
+        "The boards are in maintenance mode, changes are disabled until it is over.",
+    );
+    Ok(req.into_response(err.error_response()).map_into_right_body())
+}
@@ -0,0 +1,127 @@
+//! Prometheus metrics for the boards.
+//!
+//! A single [Metrics] registry is kept in app data alongside the pool and cache, every request is recorded by
+//! [track_requests] and the registry is rendered in the Prometheus text format by the `/metrics` endpoint.
+use actix_web::{
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    middleware::Next,
+    web, Error,
+};
+use prometheus::{
+    Encoder, Histogram, HistogramOpts, HistogramVec, IntCounterVec, Opts, Registry, TextEncoder,
+};
+use std::time::{Duration, Instant};
+
+/// Route label used for requests that did not match any endpoint, so unknown paths do not create new series.
+const UNMATCHED_ROUTE: &str = "unmatched";
+
+/// Metrics collected by the server, all of them are registered to `registry`.
+#[derive(Clone)]
+pub struct Metrics {
+    pub registry: Registry,
+    /// Requests handled, by route, method and response status.
+    pub requests: IntCounterVec,
+    /// Time taken to handle requests, by route.
+    pub request_duration: HistogramVec,
+    /// Time taken to upload a demo to BackBlaze, including any retries.
+    pub backblaze_upload_duration: Histogram,
+    /// Lookups of the cached previews, by cache and result (`hit` or `miss`).
+    pub cache_lookups: IntCounterVec,
+    /// Time taken to get a connection from the database pool, see [crate::tools::db::spawn_acquire_monitor].
+    pub db_acquire_duration: Histogram,
+}
+
+impl Metrics {
+    /// Creates the metrics and registers them to a new registry.
+    pub fn new() -> prometheus::Result<Self> {
+        let registry = Registry::new_custom(Some("boards".to_string()), None)?;
+        let requests = IntCounterVec::new(
+            Opts::new("http_requests_total", "Requests handled, by route, method and status."),
+            &["route", "method", "status"],
+        )?;
+        let request_duration = HistogramVec::new(
+            HistogramOpts::new("http_request_duration_seconds", "Time taken to handle requests, by route."),
+            &["route"],
+        )?;
+        let backblaze_upload_duration = Histogram::with_opts(
+            HistogramOpts::new(
+                "backblaze_upload_duration_seconds",
+                "Time taken to upload a demo to BackBlaze, including retries.",
+            )
+            .buckets(vec![0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0]),
+        )?;
+        let cache_lookups = IntCounterVec::new(
+            Opts::new("cache_lookups_total", "Lookups of the cached previews, by cache and result."),
+            &["cache", "result"],
+        )?;
+        let db_acquire_duration = Histogram::with_opts(
+            HistogramOpts::new(
+                "db_acquire_duration_seconds",
+                "Time taken to get a connection from the database pool.",
+            )
+            .buckets(vec![0.001, 0.005, 0.01, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0]),
+        )?;
+        registry.register(Box::new(requests.clone()))?;
+        registry.register(Box::new(request_duration.clone()))?;
+        registry.register(Box::new(backblaze_upload_duration.clone()))?;
+        registry.register(Box::new(cache_lookups.clone()))?;
+        registry.register(Box::new(db_acquire_duration.clone()))?;
+        Ok(Metrics {
+            registry,
+            requests,
+            request_duration,
+            backblaze_upload_duration,
+            cache_lookups,
+            db_acquire_duration,
+        })
+    }
+    /// Records a handled request.
+    pub fn observe_request(&self, route: &str, method: &str, status: u16, elapsed: Duration) {
+        self.requests
+            .with_label_values(&[route, method, &status.to_string()])
+            .inc();
+        self.request_duration
+            .with_label_values(&[route])
+            .observe(elapsed.as_secs_f64());
+    }
+    /// Records the time taken by a BackBlaze upload.
+    pub fn observe_backblaze_upload(&self, elapsed: Duration) {
+        self.backblaze_upload_duration.observe(elapsed.as_secs_f64());
+    }
+    /// Records the time taken to get a database connection.
+    pub fn observe_db_acquire(&self, elapsed: Duration) {
+        self.db_acquire_duration.observe(elapsed.as_secs_f64());
+    }
+    /// Records a lookup of the cache `cache`.
+    pub fn record_cache_lookup(&self, cache: &str, hit: bool) {
+        let result = if hit { "hit" } else { "miss" };
+        self.cache_lookups.with_label_values(&[cache, result]).inc();
+    }
+    /// Renders every metric in the Prometheus text format.
+    pub fn render(&self) -> prometheus::Result<String> {
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+        Ok(String::from_utf8_lossy(&buffer).to_string())
+    }
+}
+
+/// Middleware that records the count and latency of every request in [Metrics], labelled by the matched route pattern.
+///
+/// Requests are not recorded if there is no [Metrics] in app data.
+pub async fn track_requests(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let metrics = req.app_data::<web::Data<Metrics>>().cloned();
+    let route = req
+        .match_pattern()
+        .unwrap_or_else(|| UNMATCHED_ROUTE.to_string());
+    let method = req.method().to_string();
+    let start = Instant::now();
+    let res = next.call(req).await?;
+    if let Some(metrics) = metrics {
+        metrics.observe_request(&route, &method, res.status().as_u16(), start.elapsed());
+    }
+    Ok(res)
+}
@@ -1,8 +1,36 @@
+/// Authentication and permission checks
+pub mod auth;
+/// Minimal client for BackBlaze's B2 storage API
+pub mod backblaze;
 /// Caching for endpoints
 pub mod cache;
+/// Identifying clients behind proxies
+pub mod client;
 /// Configuration module that handles extracting information from the environment for setup.
 pub mod config;
+/// Cross-origin request handling
+pub mod cors;
+/// Database connection pool
+pub mod db;
+/// Demo file parsing
+pub mod demo;
+/// Discord webhook notifications
+pub mod discord;
+/// CSV exports of leaderboards
+pub mod export;
 /// Helper functions used accross different modules
 pub mod helpers;
+/// Live leaderboard updates for WebSocket clients
+pub mod live;
+/// Read-only maintenance mode
+pub mod maintenance;
+/// Prometheus metrics
+pub mod metrics;
+/// Name moderation
+pub mod moderation;
+/// Rate limiting for submissions
+pub mod ratelimit;
+/// Demo file storage backends
+pub mod storage;
 
 pub mod error;
\ No newline at end of file
@@ -0,0 +1,48 @@
+//! Filtering for offensive user names.
+//!
+//! Names are only checked against a short list of really bad words, anything flagged is left for an admin to review.
+use crate::models::users::Users;
+use crate::tools::config::ModerationConfig;
+
+/// Words that flag a name, matched after [normalize] so common substitutions are caught.
+const BLOCKED_WORDS: &[&str] = &[
+    "nigger", "nigga", "faggot", "retard", "kike", "chink", "tranny", "cunt", "hitler", "nazi",
+];
+
+/// Lowercases the name, undoes common character substitutions and strips everything that isn't a letter.
+fn normalize(name: &str) -> String {
+    name.chars()
+        .filter_map(|c| match c.to_ascii_lowercase() {
+            '0' => Some('o'),
+            '1' | '!' | '|' => Some('i'),
+            '3' => Some('e'),
+            '4' | '@' => Some('a'),
+            '5' | '$' => Some('s'),
+            '7' => Some('t'),
+            c if c.is_alphabetic() => Some(c),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Returns true if the name contains a blocked word.
+pub fn is_offensive(name: &str) -> bool {
+    let name = normalize(name);
+    BLOCKED_WORDS.iter().any(|word| name.contains(word))
+}
+
+/// Checks a new user's names when `filter_names` is enabled.
+///
+/// A flagged `board_name` is dropped so the user falls back to their steam name, and the user is marked `needs_review`.
+pub fn moderate_new_user(config: &ModerationConfig, user: &mut Users) {
+    if !config.filter_names {
+        return;
+    }
+    if user.board_name.as_deref().is_some_and(is_offensive) {
+        user.board_name = None;
+        user.needs_review = true;
+    }
+    if user.steam_name.as_deref().is_some_and(is_offensive) {
+        user.needs_review = true;
+    }
+}
@@ -0,0 +1,111 @@
+//! Per-player rate limiting for score submissions.
+//!
+//! Submissions are also limited for each client address, so one client cannot get around the limit by submitting for
+//! several players, see [crate::tools::client::client_id].
+//!
+//! Each player has a token bucket holding up to `submissions` tokens, refilled evenly over `per_secs`, see [RateLimitConfig].
+//! Every submission takes a token, and a submission without a token left is rejected with a 429 and a `Retry-After` header.
+//! A single [RateLimiter] is kept in app data and shared by the submission endpoints, reads are not limited.
+//!
+//! Buckets that have refilled are dropped, and at most [MAX_BUCKETS] are kept, the least recently used going first.
+use crate::tools::config::RateLimitConfig;
+use crate::tools::error::{ErrorType, Result, ServerError};
+use std::collections::{BTreeSet, HashMap};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+/// The most buckets that are kept, the least recently used bucket is dropped to make room for a new one past this.
+pub const MAX_BUCKETS: usize = 10_000;
+
+/// Tokens left for a player, as of `updated`.
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Buckets keyed by player or client, along with the keys ordered by when their bucket was last updated.
+#[derive(Debug, Default)]
+struct Buckets {
+    by_key: HashMap<String, Bucket>,
+    by_age: BTreeSet<(Instant, String)>,
+}
+
+impl Buckets {
+    /// Drops buckets that have not been updated for `full_after`, as they have refilled and are the same as a new one.
+    /// If `key` is new and there are still [MAX_BUCKETS], the least recently updated bucket is dropped to make room.
+    fn evict(&mut self, key: &str, now: Instant, full_after: Duration) {
+        while let Some((updated, _)) = self.by_age.first() {
+            if now.saturating_duration_since(*updated) < full_after {
+                break;
+            }
+            self.pop_oldest();
+        }
+        if self.by_key.len() >= MAX_BUCKETS && !self.by_key.contains_key(key) {
+            self.pop_oldest();
+        }
+    }
+    fn pop_oldest(&mut self) {
+        if let Some((_, key)) = self.by_age.pop_first() {
+            self.by_key.remove(&key);
+        }
+    }
+    fn update(&mut self, key: &str, bucket: Bucket) {
+        if let Some(old) = self.by_key.insert(key.to_string(), bucket) {
+            self.by_age.remove(&(old.updated, key.to_string()));
+        }
+        self.by_age.insert((bucket.updated, key.to_string()));
+    }
+}
+
+/// Token buckets for each player, keyed by `profile_number`, and for each client, see [crate::tools::client::client_id].
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    buckets: Arc<Mutex<Buckets>>,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> Self {
+        RateLimiter {
+            config,
+            buckets: Arc::new(Mutex::new(Buckets::default())),
+        }
+    }
+    /// Takes a token from `key`'s bucket, returns a 429 with the seconds until the next token if there are none left.
+    pub async fn check(&self, key: &str) -> Result<()> {
+        self.check_at(key, Instant::now()).await
+    }
+    /// [RateLimiter::check] as of `now`.
+    pub async fn check_at(&self, key: &str, now: Instant) -> Result<()> {
+        let capacity = self.config.submissions as f64;
+        if capacity == 0.0 {
+            return Ok(());
+        }
+        // Tokens refilled per second.
+        let rate = capacity / self.config.per_secs.max(1) as f64;
+        let refill = |bucket: &Bucket| {
+            let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+            (bucket.tokens + elapsed * rate).min(capacity)
+        };
+        let mut buckets = self.buckets.lock().await;
+        buckets.evict(key, now, Duration::from_secs(self.config.per_secs.max(1)));
+        let tokens = match buckets.by_key.get(key) {
+            Some(bucket) => refill(bucket),
+            None => capacity,
+        };
+        if tokens < 1.0 {
+            // Rounded to the millisecond first, so float error cannot add a second.
+            let wait_ms = ((1.0 - tokens) / rate * 1000.0).round();
+            let retry_after = (wait_ms / 1000.0).ceil().max(1.0) as u64;
+            return Err(ServerError::new(
+                ErrorType::TooManyRequests,
+                format!("Too many submissions, try again in {} seconds.", retry_after),
+            )
+            .with_retry_after(retry_after));
+        }
+        buckets.update(key, Bucket { tokens: tokens - 1.0, updated: now });
+        Ok(())
+    }
+}
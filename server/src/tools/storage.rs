@@ -0,0 +1,253 @@
+//! Where uploaded demo files are stored, chosen with `STORAGE.BACKEND`, see [StorageConfig].
+//!
+//! Handlers use the [DemoStorage] kept in app data rather than a specific backend, demos are stored in BackBlaze by default
+//! ([B2Storage]), or in a local directory ([LocalStorage]) for development and self-hosting.
+use crate::models::demos::Demos;
+use crate::tools::backblaze::*;
+use crate::tools::config::{BackBlazeConfig, Config, StorageBackend, StorageConfig};
+use actix_web::web::{Bytes, BytesMut};
+use anyhow::{bail, Result};
+use futures::{future::BoxFuture, stream, stream::BoxStream, StreamExt, TryStreamExt};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::io::AsyncReadExt;
+
+/// How many bytes are read from a local file for each chunk of a [DemoStream].
+const LOCAL_CHUNK_SIZE: usize = 64 * 1024;
+
+/// The contents of a stored file, a chunk at a time.
+pub type DemoStream = BoxStream<'static, Result<Bytes>>;
+
+/// A stored demo file, `bucket_id` is only used by backends that have buckets.
+#[derive(Debug, Clone, Copy)]
+pub struct StoredFile<'a> {
+    pub file_id: &'a str,
+    pub bucket_id: Option<&'a str>,
+}
+
+impl<'a> From<&'a Demos> for StoredFile<'a> {
+    fn from(demo: &'a Demos) -> Self {
+        StoredFile {
+            file_id: &demo.file_id,
+            bucket_id: demo.bucket_id.as_deref(),
+        }
+    }
+}
+
+/// A backend that demo files are uploaded to, downloaded from and deleted from.
+///
+/// `file_name` is the canonical name of the demo, see [crate::api::v1::handlers::demos::generate_file_name].
+pub trait DemoStorage: Send + Sync {
+    /// Stores the local file at `path` as `file_name`, returns the `file_id` used to find the stored file.
+    fn upload<'a>(&'a self, path: &'a str, file_name: &'a str) -> BoxFuture<'a, Result<String>>;
+    /// Returns the full contents of a stored file.
+    fn download<'a>(&'a self, file: StoredFile<'a>) -> BoxFuture<'a, Result<Vec<u8>>>;
+    /// Returns the contents of a stored file as a stream, backends that can should avoid holding the whole file in memory.
+    fn download_stream<'a>(&'a self, file: StoredFile<'a>) -> BoxFuture<'a, Result<DemoStream>> {
+        Box::pin(async move {
+            let contents = Bytes::from(self.download(file).await?);
+            Ok(stream::once(async { Ok(contents) }).boxed())
+        })
+    }
+    /// Removes a stored file, `file_name` is the name it was uploaded as.
+    fn delete<'a>(&'a self, file: StoredFile<'a>, file_name: &'a str) -> BoxFuture<'a, Result<()>>;
+    /// Removes several stored files, returning the result for each file in order.
+    fn delete_all<'a>(&'a self, files: Vec<(StoredFile<'a>, String)>) -> BoxFuture<'a, Vec<Result<()>>> {
+        Box::pin(async move {
+            let mut results = Vec::with_capacity(files.len());
+            for (file, file_name) in files {
+                results.push(self.delete(file, &file_name).await);
+            }
+            results
+        })
+    }
+    /// Returns the hex SHA1 of a stored file.
+    fn sha1<'a>(&'a self, file: StoredFile<'a>) -> BoxFuture<'a, Result<String>> {
+        Box::pin(async move { Ok(content_sha1(&self.download(file).await?)) })
+    }
+    /// The bucket new files are stored in, `None` if the backend does not have buckets.
+    fn bucket(&self) -> Option<String>;
+}
+
+/// Whether `e` is a backend reporting that the file does not exist, rather than a failure to reach the backend.
+///
+/// Deleting a file that is already gone is treated as done, see [crate::api::v1::handlers::demos::demos_delete].
+pub fn is_missing_file(e: &anyhow::Error) -> bool {
+    if let Some(e) = e.downcast_ref::<B2Error>() {
+        return e.is_file_missing();
+    }
+    matches!(e.downcast_ref::<std::io::Error>(), Some(e) if e.kind() == std::io::ErrorKind::NotFound)
+}
+
+/// Builds the [DemoStorage] selected by `STORAGE.BACKEND`.
+pub fn demo_storage(config: &Config) -> Arc<dyn DemoStorage> {
+    match config.storage.backend {
+        StorageBackend::Backblaze => Arc::new(B2Storage::new(config.backblaze.clone())),
+        StorageBackend::Local => Arc::new(LocalStorage::new(&config.storage)),
+    }
+}
+
+/// Stores demos in BackBlaze, files are kept in the bucket they were uploaded to even if `BACKBLAZE.BUCKET` changes.
+#[derive(Debug, Clone)]
+pub struct B2Storage {
+    config: BackBlazeConfig,
+}
+
+impl B2Storage {
+    pub fn new(config: BackBlazeConfig) -> Self {
+        B2Storage { config }
+    }
+    /// The bucket a file is stored in, files without a recorded bucket are assumed to be in the active bucket.
+    pub fn bucket_for<'a>(&'a self, file: &StoredFile<'a>) -> &'a str {
+        file.bucket_id.unwrap_or(&self.config.bucket)
+    }
+    /// Returns a client, and an authenticated session for use with backblaze.
+    async fn client_and_auth(&self) -> Result<(reqwest::Client, B2Auth)> {
+        let client = reqwest::ClientBuilder::new().build()?;
+        let auth = b2_authorize_account(&client, format!("{}:{}", self.config.keyid, self.config.key)).await?;
+        Ok((client, auth))
+    }
+    /// Returns a client and authenticated session, checking the configured key can access the bucket `file` is in.
+    async fn client_and_auth_for(&self, file: &StoredFile<'_>) -> Result<(reqwest::Client, B2Auth)> {
+        let (client, auth) = self.client_and_auth().await?;
+        check_bucket_access(&auth, self.bucket_for(file))?;
+        Ok((client, auth))
+    }
+    /// Uploads the file at `path`, demos over `BACKBLAZE.LARGE_FILE_THRESHOLD_MB` are uploaded in parts, see [uses_large_file].
+    async fn upload_file(&self, path: &str, file_name: &str) -> Result<String> {
+        let (client, auth) = self.client_and_auth().await?;
+        let file = tokio::fs::File::open(path).await?;
+        let metadata = file.metadata().await?;
+        let modf = metadata.modified()?.duration_since(UNIX_EPOCH)?.as_secs() * 1000;
+        let param = FileParameters {
+            file_path: file_name,
+            content_type: None,
+            last_modified_millis: modf,
+        };
+        let attempts = self.config.upload_attempts;
+        let backoff = Duration::from_millis(self.config.upload_backoff_ms);
+        let bucket_id = self.config.bucket.clone();
+        let resp = if uses_large_file(metadata.len(), self.config.large_file_threshold_mb * 1024 * 1024) {
+            b2_upload_large_file_with_retries(&client, &auth, bucket_id, path, param, attempts, backoff).await?
+        } else {
+            let contents = Bytes::from(tokio::fs::read(path).await?);
+            b2_upload_file_with_retries(&client, &auth, bucket_id, contents, param, attempts, backoff).await?
+        };
+        match resp.file_id {
+            Some(file_id) => Ok(file_id),
+            None => bail!("BackBlaze did not return a file id for {}", file_name),
+        }
+    }
+}
+
+impl DemoStorage for B2Storage {
+    fn upload<'a>(&'a self, path: &'a str, file_name: &'a str) -> BoxFuture<'a, Result<String>> {
+        Box::pin(self.upload_file(path, file_name))
+    }
+    fn download<'a>(&'a self, file: StoredFile<'a>) -> BoxFuture<'a, Result<Vec<u8>>> {
+        Box::pin(async move {
+            let (client, auth) = self.client_and_auth_for(&file).await?;
+            let resp = b2_download_file_by_id(&client, &auth, file.file_id).await?;
+            Ok(resp.bytes().await?.to_vec())
+        })
+    }
+    fn download_stream<'a>(&'a self, file: StoredFile<'a>) -> BoxFuture<'a, Result<DemoStream>> {
+        Box::pin(async move {
+            let (client, auth) = self.client_and_auth_for(&file).await?;
+            let resp = b2_download_file_by_id(&client, &auth, file.file_id).await?;
+            Ok(resp.bytes_stream().map_err(anyhow::Error::from).boxed())
+        })
+    }
+    fn delete<'a>(&'a self, file: StoredFile<'a>, file_name: &'a str) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let (client, auth) = self.client_and_auth_for(&file).await?;
+            b2_delete_file_version(&client, &auth, file_name.to_string(), file.file_id.to_string()).await?;
+            Ok(())
+        })
+    }
+    /// Authorizes once for all the files, every file fails if BackBlaze cannot be authorized.
+    fn delete_all<'a>(&'a self, files: Vec<(StoredFile<'a>, String)>) -> BoxFuture<'a, Vec<Result<()>>> {
+        Box::pin(async move {
+            let (client, auth) = match self.client_and_auth().await {
+                Ok(client_and_auth) => client_and_auth,
+                Err(e) => return files.iter().map(|_| Err(anyhow::anyhow!("Error authorizing with backblaze -> {e}"))).collect(),
+            };
+            let mut results = Vec::with_capacity(files.len());
+            for (file, file_name) in files {
+                let result = match check_bucket_access(&auth, self.bucket_for(&file)) {
+                    Ok(_) => b2_delete_file_version(&client, &auth, file_name, file.file_id.to_string()).await.map(|_| ()),
+                    Err(e) => Err(e),
+                };
+                results.push(result);
+            }
+            results
+        })
+    }
+    /// The file is hashed as it is streamed from BackBlaze rather than held in memory.
+    fn sha1<'a>(&'a self, file: StoredFile<'a>) -> BoxFuture<'a, Result<String>> {
+        Box::pin(async move {
+            let (client, auth) = self.client_and_auth_for(&file).await?;
+            b2_file_sha1(&client, &auth, file.file_id).await
+        })
+    }
+    fn bucket(&self) -> Option<String> {
+        Some(self.config.bucket.clone())
+    }
+}
+
+/// Stores demos as files in `STORAGE.LOCAL_DIR`, the `file_id` is the name of the file in the directory.
+#[derive(Debug, Clone)]
+pub struct LocalStorage {
+    dir: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new(config: &StorageConfig) -> Self {
+        LocalStorage {
+            dir: PathBuf::from(&config.local_dir),
+        }
+    }
+    /// The path of a stored file, `file_id`s that could point outside of the directory are rejected.
+    fn path(&self, file_id: &str) -> Result<PathBuf> {
+        if file_id.is_empty() || file_id.starts_with('.') || file_id.contains(['/', '\\']) {
+            bail!("Invalid local file id {}", file_id);
+        }
+        Ok(self.dir.join(file_id))
+    }
+}
+
+impl DemoStorage for LocalStorage {
+    /// Files are prefixed with the upload time, so demos uploaded under the same name do not overwrite each other.
+    fn upload<'a>(&'a self, path: &'a str, file_name: &'a str) -> BoxFuture<'a, Result<String>> {
+        Box::pin(async move {
+            let uploaded_at = SystemTime::now().duration_since(UNIX_EPOCH)?.as_nanos();
+            let file_id = format!("{}_{}", uploaded_at, sanitize_filename::sanitize(file_name));
+            tokio::fs::create_dir_all(&self.dir).await?;
+            tokio::fs::copy(path, self.path(&file_id)?).await?;
+            Ok(file_id)
+        })
+    }
+    fn download<'a>(&'a self, file: StoredFile<'a>) -> BoxFuture<'a, Result<Vec<u8>>> {
+        Box::pin(async move { Ok(tokio::fs::read(self.path(file.file_id)?).await?) })
+    }
+    fn download_stream<'a>(&'a self, file: StoredFile<'a>) -> BoxFuture<'a, Result<DemoStream>> {
+        Box::pin(async move {
+            let file = tokio::fs::File::open(self.path(file.file_id)?).await?;
+            Ok(stream::try_unfold(file, |mut file| async move {
+                let mut chunk = BytesMut::with_capacity(LOCAL_CHUNK_SIZE);
+                match file.read_buf(&mut chunk).await? {
+                    0 => Ok(None),
+                    _ => Ok(Some((chunk.freeze(), file))),
+                }
+            })
+            .boxed())
+        })
+    }
+    fn delete<'a>(&'a self, file: StoredFile<'a>, _file_name: &'a str) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move { Ok(tokio::fs::remove_file(self.path(file.file_id)?).await?) })
+    }
+    fn bucket(&self) -> Option<String> {
+        None
+    }
+}